@@ -0,0 +1,130 @@
+//! Allocations per request for a 2 MB body
+//!
+//! Compares the body handling before the switch to `Bytes` (UTF-8 copy of
+//! the request body, cloned frame fields, response body copied out of the
+//! JSON value and re-serialized) with the current `request_frame` /
+//! `worker_response` path. Run with `cargo bench --bench body_allocations`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use hyper::body::Bytes;
+use laravel_rust_server::response_validation::ResponseValidation;
+use laravel_rust_server::server::{request_frame, worker_response};
+use laravel_rust_server::HttpRequestPayload;
+use serde_json::{json, Value};
+
+const BODY_SIZE: usize = 2 * 1024 * 1024;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Allocations and bytes allocated while running `f`
+fn measure<T>(f: impl FnOnce() -> T) -> (usize, usize) {
+    let (count, bytes) = (ALLOCATIONS.load(Ordering::Relaxed), ALLOCATED_BYTES.load(Ordering::Relaxed));
+    let result = f();
+    let measured = (
+        ALLOCATIONS.load(Ordering::Relaxed) - count,
+        ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes,
+    );
+    drop(result);
+    measured
+}
+
+fn headers() -> HashMap<String, String> {
+    HashMap::from([("content-type".to_string(), "application/json".to_string())])
+}
+
+/// Request side as it was: body copied into a `String`, every field cloned
+fn request_before(body: &Bytes) -> Vec<u8> {
+    let payload = HttpRequestPayload {
+        method: "POST".to_string(),
+        uri: "/upload".to_string(),
+        headers: headers(),
+        body: Some(body.clone()),
+        query_params: HashMap::new(),
+    };
+    let content = String::from_utf8(body.to_vec()).ok();
+    let frame = json!({
+        "uri": payload.uri.clone(),
+        "method": payload.method.clone(),
+        "headers": payload.headers.clone(),
+        "parameters": payload.query_params.clone(),
+        "content": content.clone(),
+        "server": {
+            "REQUEST_METHOD": payload.method.clone(),
+            "REQUEST_URI": payload.uri.clone(),
+            "CONTENT_TYPE": payload.headers.get("content-type").unwrap_or(&"".to_string()).clone(),
+            "CONTENT_LENGTH": content.as_ref().map(|b| b.len().to_string()).unwrap_or("0".to_string()),
+            "REQUEST_ID": "bench"
+        }
+    });
+    serde_json::to_vec(&frame).unwrap()
+}
+
+fn request_after(body: &Bytes) -> Vec<u8> {
+    let payload = HttpRequestPayload {
+        method: "POST".to_string(),
+        uri: "/upload".to_string(),
+        headers: headers(),
+        body: Some(body.clone()),
+        query_params: HashMap::new(),
+    };
+    serde_json::to_vec(&request_frame(payload, "bench")).unwrap()
+}
+
+/// Response side as it was: body copied out of the value, JSON re-serialized
+fn response_before(data: Value) -> hyper::Body {
+    let body = data["body"].as_str().unwrap_or("").to_string();
+    let parsed: Value = serde_json::from_str(&body).unwrap();
+    hyper::Body::from(serde_json::to_string(&parsed).unwrap())
+}
+
+fn response_after(data: Value) -> hyper::Body {
+    worker_response(data, ResponseValidation::Lenient).unwrap().into_body()
+}
+
+fn main() {
+    let text = format!("{{\"data\":\"{}\"}}", "x".repeat(BODY_SIZE - 11));
+    let body = Bytes::from(text.clone());
+    let response = || json!({"status": 200, "headers": {"content-type": ["application/json"]}, "body": text.clone()});
+
+    println!("body size: {} bytes", body.len());
+    println!("{:<10} {:>8} {:>12} {:>8} {:>12}", "", "before", "", "after", "");
+    println!("{:<10} {:>8} {:>12} {:>8} {:>12}", "stage", "allocs", "bytes", "allocs", "bytes");
+    let report = |stage: &str, before: (usize, usize), after: (usize, usize)| {
+        println!("{:<10} {:>8} {:>12} {:>8} {:>12}", stage, before.0, before.1, after.0, after.1);
+    };
+
+    report("request", measure(|| request_before(&body)), measure(|| request_after(&body)));
+    let (before_data, after_data) = (response(), response());
+    report(
+        "response",
+        measure(|| response_before(before_data)),
+        measure(|| response_after(after_data)),
+    );
+}
@@ -0,0 +1,33 @@
+//! Cost of the weak ETag of a response body
+//!
+//! Hashes JSON bodies of typical API sizes with `weak_etag`, as
+//! `DYNAMIC_ETAG=true` does for every tagged response, and prints the time
+//! per body and the throughput. Run with `cargo bench --bench etag`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use laravel_rust_server::etag::weak_etag;
+
+const SIZES: [usize; 5] = [1024, 16 * 1024, 64 * 1024, 256 * 1024, 1024 * 1024];
+
+/// How long each size is hashed for
+const DURATION: Duration = Duration::from_secs(1);
+
+fn main() {
+    println!("{:>10} {:>12} {:>10}", "body", "per body", "MB/s");
+    for size in SIZES {
+        let body = format!("{{\"items\":[{}]}}", "{\"id\":1,\"name\":\"x\"},".repeat(size / 20 + 1));
+        let body = &body.as_bytes()[..size];
+
+        let started = Instant::now();
+        let mut runs = 0u64;
+        while started.elapsed() < DURATION {
+            black_box(weak_etag(black_box(body)));
+            runs += 1;
+        }
+        let per_body = started.elapsed() / runs as u32;
+        let throughput = body.len() as f64 * runs as f64 / started.elapsed().as_secs_f64() / 1_000_000.0;
+        println!("{:>9}K {:>12?} {:>10.0}", body.len() / 1024, per_body, throughput);
+    }
+}
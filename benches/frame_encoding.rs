@@ -0,0 +1,102 @@
+//! Allocations per request frame written to the worker socket
+//!
+//! Compares the way frames used to be written (the whole frame serialized
+//! to a `String`, then its length and bytes written) with `encode_frame`
+//! into a buffer reused across requests, as each pooled connection does.
+//! Both must produce the same bytes on the wire. Run with
+//! `cargo bench --bench frame_encoding`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use hyper::body::Bytes;
+use laravel_rust_server::bridge::connection_pool::encode_frame;
+use laravel_rust_server::server::request_frame;
+use laravel_rust_server::HttpRequestPayload;
+use serde_json::Value;
+
+/// Frames written per measurement, as on one pooled connection
+const FRAMES: usize = 100;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Allocations and bytes allocated while running `f`
+fn measure(f: impl FnOnce()) -> (usize, usize) {
+    let (count, bytes) = (ALLOCATIONS.load(Ordering::Relaxed), ALLOCATED_BYTES.load(Ordering::Relaxed));
+    f();
+    (
+        ALLOCATIONS.load(Ordering::Relaxed) - count,
+        ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes,
+    )
+}
+
+fn frame(body_size: usize) -> Value {
+    let body = Bytes::from("x".repeat(body_size));
+    request_frame(HttpRequestPayload::synthetic("POST", "/upload", Some(body)), "bench")
+}
+
+/// Frame as it used to be written: a `String`, then its length and bytes
+fn write_before(wire: &mut Vec<u8>, frame: &Value) {
+    let json = serde_json::to_string(frame).unwrap();
+    wire.extend_from_slice(&(json.len() as u32).to_be_bytes());
+    wire.extend_from_slice(json.as_bytes());
+}
+
+/// Frame encoded into the connection's buffer, which is then written
+fn write_after(wire: &mut Vec<u8>, buf: &mut Vec<u8>, frame: &Value) {
+    encode_frame(buf, frame).unwrap();
+    wire.extend_from_slice(buf);
+}
+
+fn main() {
+    println!("{} frames per row", FRAMES);
+    println!("{:<10} {:>8} {:>12} {:>8} {:>12}", "", "before", "", "after", "");
+    println!("{:<10} {:>8} {:>12} {:>8} {:>12}", "body", "allocs", "bytes", "allocs", "bytes");
+    for body_size in [256, 64 * 1024, 2 * 1024 * 1024] {
+        let frame = frame(body_size);
+        // The socket; sized up front so writing to it allocates nothing
+        let wire_size = FRAMES * (serde_json::to_vec(&frame).unwrap().len() + 4);
+        let mut wire_before = Vec::with_capacity(wire_size);
+        let mut wire_after = Vec::with_capacity(wire_size);
+
+        let before = measure(|| {
+            for _ in 0..FRAMES {
+                write_before(&mut wire_before, &frame);
+            }
+        });
+        let mut buf = Vec::new();
+        let after = measure(|| {
+            for _ in 0..FRAMES {
+                write_after(&mut wire_after, &mut buf, &frame);
+            }
+        });
+
+        assert!(wire_before == wire_after, "frames differ on the wire");
+        println!("{:<10} {:>8} {:>12} {:>8} {:>12}", body_size, before.0, before.1, after.0, after.1);
+    }
+    println!("wire bytes identical");
+}
@@ -0,0 +1,56 @@
+//! Cost of inserting a snippet into HTML pages
+//!
+//! Runs the built-in `SnippetInjector` of `HTML_INJECT_SNIPPET` over pages
+//! of typical sizes, once with `</body>` where layouts put it and once
+//! without it, where the whole page is searched and left alone. Prints the
+//! time per page and the throughput. Run with `cargo bench --bench
+//! html_inject`.
+
+use std::hint::black_box;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
+
+use hyper::{Body, Request, StatusCode};
+use laravel_rust_server::html_transform::SnippetInjector;
+use laravel_rust_server::request_context::RequestContext;
+use laravel_rust_server::ResponseTransformer;
+
+const SIZES: [usize; 5] = [4 * 1024, 16 * 1024, 100 * 1024, 256 * 1024, 1024 * 1024];
+
+/// How long each page is transformed for
+const DURATION: Duration = Duration::from_secs(1);
+
+const SNIPPET: &str = "<div class=\"env-badge\">staging</div>";
+
+/// Page of `size` bytes, closed by `</body></html>` when `closed`
+fn page(size: usize, closed: bool) -> Vec<u8> {
+    let tail = if closed { "</body>\n</html>\n" } else { "" };
+    let row = "<div class=\"row\"><span>Item</span><a href=\"/items/1\">Open</a></div>\n";
+    let mut page = format!("<!DOCTYPE html>\n<html><head><title>Items</title></head>\n<body>\n{}", row.repeat(size / row.len() + 1));
+    page.truncate(size - tail.len());
+    page.push_str(tail);
+    page.into_bytes()
+}
+
+fn main() {
+    let injector = SnippetInjector::new(SNIPPET);
+    let request = Request::get("/items").body(Body::empty()).unwrap();
+    let context = RequestContext::new(&request, IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+    println!("{:>10} {:>10} {:>12} {:>10}", "page", "</body>", "per page", "MB/s");
+    for size in SIZES {
+        for closed in [true, false] {
+            let page = page(size, closed);
+            let started = Instant::now();
+            let mut runs = 0u64;
+            while started.elapsed() < DURATION {
+                black_box(injector.transform(&context, StatusCode::OK, black_box(&page)));
+                runs += 1;
+            }
+            let per_page = started.elapsed() / runs as u32;
+            let throughput = page.len() as f64 * runs as f64 / started.elapsed().as_secs_f64() / 1_000_000.0;
+            let found = if closed { "at end" } else { "missing" };
+            println!("{:>9}K {:>10} {:>12?} {:>10.0}", page.len() / 1024, found, per_page, throughput);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Throughput of the worker connection pool under hundreds of concurrent requests
+//!
+//! `CLIENTS` tasks send frames back to back for `DURATION` through one
+//! pool of `MAX_CONNECTIONS` connections to an in-process worker on a Unix
+//! socket that answers at once, so the time spent checking connections in
+//! and out of the pool dominates. The pool with a single shard shows the
+//! cost of every request queueing on one lock; the sharded pools should
+//! answer more requests in the same time on a machine with several cores.
+//! Run with `cargo bench --bench pool_contention`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use laravel_rust_server::bridge::connection_pool::{ConnectionPool, ConnectionPoolConfig, Framing};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+
+const CLIENTS: usize = 500;
+const MAX_CONNECTIONS: usize = 64;
+const DURATION: Duration = Duration::from_secs(3);
+
+/// Answer every frame on `stream` with the same small response
+async fn answer(mut stream: UnixStream) -> std::io::Result<()> {
+    let response = br#"{"id":null,"success":true,"data":{"status":200,"headers":{},"body":"ok"},"error":null}"#;
+    let mut frame = Vec::new();
+    loop {
+        let len = stream.read_u32().await? as usize;
+        frame.resize(len, 0);
+        stream.read_exact(&mut frame).await?;
+        stream.write_u32(response.len() as u32).await?;
+        stream.write_all(response).await?;
+    }
+}
+
+/// Requests answered through a pool of `shards` shards within `DURATION`
+async fn run(socket_path: &str, shards: usize) -> u64 {
+    let pool = Arc::new(ConnectionPool::new(ConnectionPoolConfig {
+        socket_path: socket_path.to_string(),
+        min_connections: MAX_CONNECTIONS,
+        max_connections: MAX_CONNECTIONS,
+        connection_timeout: Duration::from_secs(1),
+        read_timeout: Duration::from_secs(5),
+        health_check_interval: Duration::from_secs(30),
+        shards,
+        max_uses: None,
+        goodbye_frame: false,
+        framing: Framing::LengthPrefixed,
+    }));
+    pool.initialize().await.unwrap();
+
+    let answered = Arc::new(AtomicU64::new(0));
+    let until = Instant::now() + DURATION;
+    let clients: Vec<_> = (0..CLIENTS)
+        .map(|_| {
+            let (pool, answered) = (pool.clone(), answered.clone());
+            tokio::spawn(async move {
+                let frame = json!({"uri": "/", "method": "GET", "headers": {}, "parameters": {}, "content": null});
+                while Instant::now() < until {
+                    pool.send_http_request(frame.clone()).await.unwrap();
+                    answered.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        })
+        .collect();
+    for client in clients {
+        client.await.unwrap();
+    }
+    pool.close_all().await;
+    answered.load(Ordering::Relaxed)
+}
+
+#[tokio::main]
+async fn main() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket_path = dir.path().join("worker.sock");
+    let listener = UnixListener::bind(&socket_path).unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(answer(stream));
+        }
+    });
+
+    let socket_path = socket_path.to_string_lossy();
+    println!("{} clients, {} connections, {:?} per run", CLIENTS, MAX_CONNECTIONS, DURATION);
+    println!("{:>8} {:>12} {:>12}", "shards", "requests", "per second");
+    for shards in [1, 4, 16] {
+        let answered = run(&socket_path, shards).await;
+        println!("{:>8} {:>12} {:>12.0}", shards, answered, answered as f64 / DURATION.as_secs_f64());
+    }
+}
@@ -0,0 +1,130 @@
+//! Build script
+//!
+//! Captures the git commit, build time and compiler for `build_info()` as
+//! the `BUILD_GIT_SHA`, `BUILD_TIMESTAMP` and `BUILD_RUSTC_VERSION`
+//! environment variables of the compilation. `SOURCE_DATE_EPOCH` fixes the timestamp for reproducible
+//! builds; outside a git checkout the commit is `unknown`.
+//!
+//! With `--features header` the C header for the FFI in
+//! `src/laravel_integration.rs` is regenerated into `include/laravel_rust.h`.
+//! The header is committed, so regular builds do not need cbindgen.
+//!
+//! With `--features grpc` the service in `proto/bridge.proto` is compiled
+//! into the `grpc` module, using the vendored `protoc` unless `PROTOC` names
+//! another one.
+
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    capture_build_info();
+
+    #[cfg(feature = "header")]
+    generate_header();
+
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+fn capture_build_info() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is set by cargo");
+
+    // Rerun when HEAD moves; without these the script would rerun on every
+    // source change, or never once `header` adds its own rerun-if-changed
+    for file in [".git/HEAD", ".git/refs", ".git/packed-refs"] {
+        let path = Path::new(&crate_dir).join(file);
+        if path.exists() {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let git_sha = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .current_dir(&crate_dir)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|sha| sha.trim().to_string())
+        .filter(|sha| !sha.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let epoch = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0));
+
+    // The compiler cargo runs for this crate, not whichever is first on PATH
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|version| version.trim().to_string())
+        .filter(|version| !version.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=BUILD_GIT_SHA={}", git_sha);
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}", rfc3339(epoch));
+    println!("cargo:rustc-env=BUILD_RUSTC_VERSION={}", rustc_version);
+}
+
+/// Format seconds since the Unix epoch as `YYYY-MM-DDTHH:MM:SSZ`
+fn rfc3339(epoch: u64) -> String {
+    let days = (epoch / 86_400) as i64;
+    let secs = epoch % 86_400;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(feature = "header")]
+fn generate_header() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is set by cargo");
+
+    println!("cargo:rerun-if-changed=src/laravel_integration.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir)).expect("invalid cbindgen.toml");
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("failed to generate the C header")
+        .write_to_file(format!("{}/include/laravel_rust.h", crate_dir));
+}
+
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    println!("cargo:rerun-if-changed=proto/bridge.proto");
+    println!("cargo:rerun-if-env-changed=PROTOC");
+
+    if std::env::var_os("PROTOC").is_none() {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("no vendored protoc for this platform, set PROTOC");
+        std::env::set_var("PROTOC", protoc);
+    }
+    tonic_build::compile_protos("proto/bridge.proto").expect("failed to compile proto/bridge.proto");
+}
@@ -0,0 +1,38 @@
+//! Round trip through the gRPC listener
+//!
+//! Runs one command and fetches the stats with the generated tonic client:
+//!
+//! ```bash
+//! GRPC_ENABLED=true ./target/release/laravel-rust-server &
+//! cargo run --example grpc_client --features grpc -- http://127.0.0.1:50051 ping
+//! cargo run --example grpc_client --features grpc -- http://127.0.0.1:50051 cache:warm '{"tags": ["pages"]}'
+//! ```
+//!
+//! Exits with 1 when a call fails or returns something other than JSON.
+
+use laravel_rust_server::grpc::proto::bridge_client::BridgeClient;
+use laravel_rust_server::grpc::proto::{CommandRequest, StatsRequest};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let endpoint = args.next().unwrap_or_else(|| "http://127.0.0.1:50051".to_string());
+    let command = args.next().unwrap_or_else(|| "ping".to_string());
+    let json_data = args.next().unwrap_or_default();
+
+    let mut client = BridgeClient::connect(endpoint).await?;
+
+    let response = client.execute(CommandRequest { command, json_data }).await?.into_inner();
+    if !response.json_data.is_empty() {
+        serde_json::from_str::<serde_json::Value>(&response.json_data)?;
+    }
+    println!(
+        "Execute: success={} json_data={} error={:?}",
+        response.success, response.json_data, response.error
+    );
+
+    let stats = client.get_stats(StatsRequest {}).await?.into_inner();
+    let stats: serde_json::Value = serde_json::from_str(&stats.json_data)?;
+    println!("GetStats: build {}", stats["build"]);
+    Ok(())
+}
@@ -0,0 +1,357 @@
+/* Generated by cbindgen from src/laravel_integration.rs, do not edit. */
+
+#ifndef LARAVEL_RUST_H
+#define LARAVEL_RUST_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/*
+ Result of every fallible FFI call
+ */
+typedef enum LaravelRustStatus {
+  /*
+   The call succeeded
+   */
+  LARAVEL_RUST_STATUS_OK = 0,
+  /*
+   A required pointer was null, or a string or JSON argument was invalid
+   */
+  LARAVEL_RUST_STATUS_INVALID_ARGUMENT = -1,
+  /*
+   The call needs a running HTTP server and none was started
+   */
+  LARAVEL_RUST_STATUS_NOT_STARTED = -2,
+  /*
+   The PHP worker could not be reached
+   */
+  LARAVEL_RUST_STATUS_BRIDGE_UNAVAILABLE = -3,
+  /*
+   The PHP worker did not answer in time
+   */
+  LARAVEL_RUST_STATUS_TIMEOUT = -4,
+  /*
+   Unexpected failure inside the library
+   */
+  LARAVEL_RUST_STATUS_INTERNAL = -5,
+  /*
+   `laravel_rust_start` on a handle whose server is already running
+   */
+  LARAVEL_RUST_STATUS_ALREADY_RUNNING = -6,
+  /*
+   The configuration was rejected or the port could not be bound
+   */
+  LARAVEL_RUST_STATUS_START_FAILED = -7,
+} LaravelRustStatus;
+
+/*
+ Handle owned by the PHP side between `laravel_rust_init` and `laravel_rust_destroy`
+ */
+typedef struct LaravelRustServer LaravelRustServer;
+
+/*
+ Called with the request as JSON before it is forwarded to Laravel
+
+ Returns 0 to forward the request, or an HTTP status code (100-599) to
+ answer it with that status and an empty body. Null means no hook.
+ */
+typedef int32_t (*LaravelRustRequestHook)(const char *request_json);
+
+/*
+ Called for every response with its status and the time since the request
+ arrived. Null means no hook.
+ */
+typedef void (*LaravelRustResponseHook)(uint16_t status, double duration_ms);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ Create a handle
+
+ On failure `*out_server` is set to null and the reason is available from
+ `laravel_rust_last_error(NULL)`.
+
+ # Returns
+
+ * `Ok` - `*out_server` holds a handle to pass to the other functions
+ * `InvalidArgument` - `out_server` is null
+ * `Internal` - the async runtime could not be started
+
+ # Safety
+
+ `out_server` must be null or point to writable storage for a pointer.
+ */
+enum LaravelRustStatus laravel_rust_init(struct LaravelRustServer **out_server);
+
+/*
+ Destroy a handle and everything it owns
+
+ A running HTTP server is stopped first, exactly as `laravel_rust_stop`
+ would. A panic while doing so does not reach PHP; it is available from
+ `laravel_rust_last_error(NULL)` instead.
+
+ # Safety
+
+ `server` must be null or a handle returned by `laravel_rust_init` that has not
+ been destroyed yet; it must not be used afterwards.
+ */
+void laravel_rust_destroy(struct LaravelRustServer *server);
+
+/*
+ Send a command to the Laravel worker and wait for its response
+
+ `json_data` is an optional JSON object passed as the command data; null
+ sends no data. A worker that handles the command but reports a failure
+ is still `Ok`: the response then has `success: false`.
+
+ # Returns
+
+ * `Ok` - `*out_response` holds the `PhpResponse` serialized as JSON;
+   release it with `laravel_rust_free_string`
+ * `InvalidArgument` - a pointer is null, the command or data is not valid
+   UTF-8, or the data is not a JSON object
+ * `BridgeUnavailable` - the PHP worker could not be reached
+ * `Timeout` - the PHP worker did not answer in time
+ * `Internal` - unexpected failure
+
+ On failure `*out_response` is set to null.
+
+ # Safety
+
+ `server` must be null or a live handle from `laravel_rust_init`; `command`
+ and `json_data` must be null or point to NUL-terminated strings;
+ `out_response` must be null or point to writable storage for a pointer.
+ */
+enum LaravelRustStatus laravel_rust_send_command(struct LaravelRustServer *server,
+                                                 const char *command,
+                                                 const char *json_data,
+                                                 char **out_response);
+
+/*
+ Set an option for the HTTP server before starting it
+
+ Supported keys are `host`, `port`, `socket_path`, `log_level`,
+ `connection_timeout` (seconds), `read_timeout_ms`, `notify_timeout_ms`
+ and `drain_timeout_ms`. Timeouts also accept a unit, e.g. `"5s"`. The
+ value is validated immediately and kept on the handle; it takes effect on
+ the next `laravel_rust_start`, where it overrides the environment.
+
+ # Returns
+
+ * `Ok` - the option was stored
+ * `InvalidArgument` - a pointer is null, a string is not valid UTF-8, the
+   key is unknown or the value is invalid
+ * `Internal` - unexpected failure
+
+ # Safety
+
+ `server` must be null or a live handle from `laravel_rust_init`; `key`
+ and `value` must be null or point to NUL-terminated strings.
+ */
+enum LaravelRustStatus laravel_rust_set_option(struct LaravelRustServer *server,
+                                               const char *key,
+                                               const char *value);
+
+/*
+ Read the value an option will have when the server starts
+
+ That is the value from `laravel_rust_set_option`, else the environment,
+ else the default. Timeouts are reported as bare integers in their unit.
+
+ # Returns
+
+ * `Ok` - `*out_value` holds the value, or null if the option has none;
+   release it with `laravel_rust_free_string`
+ * `InvalidArgument` - a pointer is null, the key is not valid UTF-8 or is
+   unknown
+ * `Internal` - unexpected failure
+
+ On failure `*out_value` is set to null.
+
+ # Safety
+
+ `server` must be null or a live handle from `laravel_rust_init`; `key`
+ must be null or point to a NUL-terminated string; `out_value` must be
+ null or point to writable storage for a pointer.
+ */
+enum LaravelRustStatus laravel_rust_get_option(struct LaravelRustServer *server,
+                                               const char *key,
+                                               char **out_value);
+
+/*
+ Install a callback that sees every request before it is forwarded
+
+ The callback receives the request as JSON (`method`, `uri`, `headers`,
+ `body`, `query_params`) and returns 0 to forward it, or an HTTP status
+ code to answer it with that status and an empty body. Null removes the
+ callback. Takes effect on the next `laravel_rust_start`.
+
+ The callback is called from a thread of the library's runtime, never the
+ PHP thread that started the server, and must be fast and non-blocking. If
+ it does not return within `REQUEST_HOOK_TIMEOUT_MS` (default 100) the
+ stall is logged and the request is forwarded.
+
+ # Returns
+
+ * `Ok` - the callback was stored
+ * `InvalidArgument` - `server` is null
+
+ # Safety
+
+ `server` must be null or a live handle from `laravel_rust_init`; `hook`
+ must stay callable until the server is stopped.
+ */
+enum LaravelRustStatus laravel_rust_set_request_hook(struct LaravelRustServer *server,
+                                                     LaravelRustRequestHook hook);
+
+/*
+ Install a callback that sees the status and duration of every response
+
+ `duration_ms` is measured from the moment the request arrived. The
+ callback runs after the response is handed to the client, so it never
+ delays it. Null removes the callback. Takes effect on the next
+ `laravel_rust_start`; the same threading and `REQUEST_HOOK_TIMEOUT_MS`
+ rules as for the request hook apply.
+
+ # Returns
+
+ * `Ok` - the callback was stored
+ * `InvalidArgument` - `server` is null
+
+ # Safety
+
+ `server` must be null or a live handle from `laravel_rust_init`; `hook`
+ must stay callable until the server is stopped.
+ */
+enum LaravelRustStatus laravel_rust_set_response_hook(struct LaravelRustServer *server,
+                                                      LaravelRustResponseHook hook);
+
+/*
+ Start the HTTP server in the background
+
+ `config_json` is null or a JSON object of environment variable overrides,
+ e.g. `{"HTTP_PORT": 8081, "SOCKET_PATH": "/run/app.sock"}`; everything
+ not listed keeps its environment value or default. The overrides and the
+ handle's options are set in the process environment while the server is
+ built and restored before this returns, so they never reach another
+ handle; other threads of the process can see them in the meantime. Every
+ setting is read, and the listening socket bound, before this returns, so
+ an invalid setting or a taken port is reported here.
+
+ # Returns
+
+ * `Ok` - the server is accepting connections
+ * `AlreadyRunning` - the running server is left untouched
+ * `InvalidArgument` - `server` is null or `config_json` is not a JSON object
+   of strings, numbers and booleans
+ * `StartFailed` - invalid configuration or the port could not be bound
+ * `Internal` - unexpected failure
+
+ # Safety
+
+ `server` must be null or a live handle from `laravel_rust_init`;
+ `config_json` must be null or point to a NUL-terminated string.
+ */
+enum LaravelRustStatus laravel_rust_start(struct LaravelRustServer *server,
+                                          const char *config_json);
+
+/*
+ Gracefully stop the HTTP server started by `laravel_rust_start`
+
+ Blocks until in-flight requests finish or `SHUTDOWN_DRAIN_TIMEOUT_MS`
+ passes. The handle stays usable and the server can be started again.
+
+ # Returns
+
+ * `Ok` - the server was stopped
+ * `NotStarted` - nothing was running
+ * `InvalidArgument` - `server` is null
+ * `Internal` - unexpected failure
+
+ # Safety
+
+ `server` must be null or a live handle from `laravel_rust_init`.
+ */
+enum LaravelRustStatus laravel_rust_stop(struct LaravelRustServer *server);
+
+/*
+ Snapshot of the running server and the bridge metrics
+
+ The JSON object has a `server` section (`address`, `uptime_secs`,
+ `socket_path`, `worker_ready`), a `build` section (`version`, `git_sha`,
+ `build_timestamp`) and a `metrics` section with every
+ metric as `{type, series: [{labels, value}]}`; summaries carry `sum` and
+ `count` instead of `value`. Cheap enough to call on every dashboard
+ refresh and safe to call while the server handles traffic.
+
+ # Returns
+
+ * `Ok` - `*out_json` holds the stats; release it with `laravel_rust_free_string`
+ * `NotStarted` - the server is not running
+ * `InvalidArgument` - a pointer is null
+ * `Internal` - unexpected failure
+
+ On failure `*out_json` is set to null.
+
+ # Safety
+
+ `server` must be null or a live handle from `laravel_rust_init`;
+ `out_json` must be null or point to writable storage for a pointer.
+ */
+enum LaravelRustStatus laravel_rust_get_stats(struct LaravelRustServer *server, char **out_json);
+
+/*
+ Describe the most recent failed call on a handle
+
+ Failures are kept until the next failure replaces them; successful calls
+ do not clear them. With a null `server` this describes the last failure
+ of a call that had no handle to record it on, such as `laravel_rust_init`.
+
+ # Returns
+
+ * A copy of the message; release it with `laravel_rust_free_string`
+ * Null if no call has failed yet
+
+ # Safety
+
+ `server` must be null or a live handle from `laravel_rust_init`.
+ */
+char *laravel_rust_last_error(struct LaravelRustServer *server);
+
+/*
+ Release a string returned by this library
+
+ Strings from `laravel_rust_send_command`, `laravel_rust_get_option`,
+ `laravel_rust_get_stats` and `laravel_rust_last_error` must be released
+ here and nowhere else: they come from Rust's allocator,
+ not `malloc` or PHP's. With the `string-registry` feature a pointer that
+ was not returned by this library, or was already released, is logged as
+ an error and left alone.
+
+ # Safety
+
+ `ptr` must be null or a string returned by this library that has not been
+ freed yet.
+ */
+void laravel_rust_free_string(char *ptr);
+
+#if defined(LARAVEL_RUST_STRING_REGISTRY)
+/*
+ Number of strings returned by this library that were not released yet
+
+ Only available with the `string-registry` feature; meant for leak checks
+ in tests.
+ */
+size_t laravel_rust_live_strings(void);
+#endif
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* LARAVEL_RUST_H */
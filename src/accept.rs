@@ -0,0 +1,165 @@
+//! Accept loop of the HTTP listener
+//!
+//! Some `accept()` errors belong to a single connection or to a passing
+//! shortage, not to the listener: the client gave up before it was accepted
+//! (`ECONNABORTED`), a signal interrupted the call (`EINTR`), or the
+//! process or system ran out of descriptors or buffers (`EMFILE`, `ENFILE`,
+//! `ENOBUFS`, `ENOMEM`). Those are logged, counted in
+//! `http_accept_errors_total` by errno name and the loop carries on; after
+//! a shortage it first sleeps `ACCEPT_ERROR_BACKOFF_MS`, since accepting
+//! again at once fails the same way until a connection closes. The warning
+//! for a shortage goes through the log throttle. Any other error ends the
+//! server.
+
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use hyper::server::accept::Accept;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Sleep;
+use tracing::{debug, warn, Level};
+
+use crate::log_throttle::log_throttle;
+use crate::metrics::{metrics, MetricKind};
+
+/// Default for `ACCEPT_ERROR_BACKOFF_MS`
+pub const DEFAULT_BACKOFF_MS: u64 = 100;
+
+/// How an `accept()` error is handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AcceptError {
+    /// Only the connection being accepted is affected; accept the next one
+    Connection(&'static str),
+    /// Descriptors or memory ran out; wait before accepting again
+    Exhausted(&'static str),
+    /// The listener itself is broken
+    Fatal,
+}
+
+impl AcceptError {
+    fn classify(error: &io::Error) -> Self {
+        match error.raw_os_error() {
+            Some(libc::ECONNABORTED) => Self::Connection("ECONNABORTED"),
+            Some(libc::ECONNRESET) => Self::Connection("ECONNRESET"),
+            Some(libc::EINTR) => Self::Connection("EINTR"),
+            Some(libc::EPROTO) => Self::Connection("EPROTO"),
+            Some(libc::EMFILE) => Self::Exhausted("EMFILE"),
+            Some(libc::ENFILE) => Self::Exhausted("ENFILE"),
+            Some(libc::ENOBUFS) => Self::Exhausted("ENOBUFS"),
+            Some(libc::ENOMEM) => Self::Exhausted("ENOMEM"),
+            _ => Self::Fatal,
+        }
+    }
+}
+
+/// Accepted client connection with the peer address seen by `accept()`
+pub struct AddrStream {
+    stream: TcpStream,
+    remote_addr: SocketAddr,
+}
+
+impl AddrStream {
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+}
+
+impl AsyncRead for AddrStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for AddrStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[io::IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+/// Incoming connections of a listener, riding out transient accept errors
+pub struct Incoming {
+    listener: TcpListener,
+    backoff: Duration,
+    /// Pending wait after running out of descriptors
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl Incoming {
+    pub fn new(listener: TcpListener) -> Self {
+        metrics().describe(
+            "http_accept_errors_total",
+            MetricKind::Counter,
+            "Failed accept() calls on the HTTP listener that were retried, by errno",
+        );
+        let backoff_ms = std::env::var("ACCEPT_ERROR_BACKOFF_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_BACKOFF_MS);
+        Self {
+            listener,
+            backoff: Duration::from_millis(backoff_ms.max(1)),
+            sleep: None,
+        }
+    }
+}
+
+impl Accept for Incoming {
+    type Conn = AddrStream;
+    type Error = io::Error;
+
+    fn poll_accept(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<AddrStream, io::Error>>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(sleep) = &mut this.sleep {
+                ready!(sleep.as_mut().poll(cx));
+                this.sleep = None;
+            }
+
+            let error = match ready!(this.listener.poll_accept(cx)) {
+                Ok((stream, remote_addr)) => return Poll::Ready(Some(Ok(AddrStream { stream, remote_addr }))),
+                Err(error) => error,
+            };
+            match AcceptError::classify(&error) {
+                AcceptError::Connection(errno) => {
+                    debug!(errno, error = %error, "Accepting a connection failed, accepting the next one");
+                    metrics().inc_counter("http_accept_errors_total", &[("errno", errno)]);
+                }
+                AcceptError::Exhausted(errno) => {
+                    let message = format!("cannot accept connections: {}", error);
+                    if log_throttle().allow(&format!("accept:{}", errno), Level::WARN, &message) {
+                        warn!(
+                            errno,
+                            error = %error,
+                            backoff_ms = this.backoff.as_millis() as u64,
+                            "⚠️ Cannot accept connections: out of file descriptors or memory, retrying"
+                        );
+                    }
+                    metrics().inc_counter("http_accept_errors_total", &[("errno", errno)]);
+                    this.sleep = Some(Box::pin(tokio::time::sleep(this.backoff)));
+                }
+                AcceptError::Fatal => return Poll::Ready(Some(Err(error))),
+            }
+        }
+    }
+}
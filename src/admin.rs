@@ -0,0 +1,407 @@
+//! Admin HTTP listener
+//!
+//! Serves operational endpoints (stats, Prometheus metrics, worker control)
+//! on a separate address so they are never reachable through the public
+//! listener.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use serde_json::json;
+use tracing::{error, info, warn};
+
+use crate::admin_artisan::ArtisanRunner;
+use crate::admin_auth::{AdminAuth, AdminAuthConfig};
+use crate::bridge::socket_bridge::SocketBridge;
+use crate::drain::Drain;
+use crate::file_cache::FileCache;
+use crate::metrics::metrics;
+use crate::runtime_info::{InfoListener, RuntimeInfo};
+use crate::supervisor::{RestartReason, WorkerSupervisor};
+use crate::tenants::Tenants;
+use crate::upgrade::UpgradeTrigger;
+
+/// Default of `ADMIN_LOG_LEVEL_REVERT_MS`: debug logging lasts 15 minutes
+const DEFAULT_LOG_LEVEL_REVERT_MS: u64 = 900_000;
+
+/// Admin listener configuration
+#[derive(Debug, Clone)]
+pub struct AdminConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    /// Default lifetime of a log filter set through `/admin/log-level`; `None` keeps it
+    pub log_level_revert_after: Option<Duration>,
+    /// Credentials and allowed client addresses
+    pub auth: AdminAuthConfig,
+    /// Serve a non-loopback host without credentials or an allow list (`ADMIN_ALLOW_INSECURE`)
+    pub allow_insecure: bool,
+}
+
+impl AdminConfig {
+    pub fn from_env() -> Self {
+        Self {
+            enabled: std::env::var("ADMIN_ENABLED")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            host: std::env::var("ADMIN_HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
+            port: std::env::var("ADMIN_PORT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(9090),
+            log_level_revert_after: std::env::var("ADMIN_LOG_LEVEL_REVERT_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(Some(DEFAULT_LOG_LEVEL_REVERT_MS))
+                .filter(|&ms| ms > 0)
+                .map(Duration::from_millis),
+            auth: AdminAuthConfig::from_env(),
+            allow_insecure: std::env::var("ADMIN_ALLOW_INSECURE")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+        }
+    }
+
+    /// Refuse a listener that anyone able to reach the port could use
+    ///
+    /// A host other than loopback needs `ADMIN_TOKEN`, `ADMIN_USER` and
+    /// `ADMIN_PASSWORD`, or `ADMIN_ALLOW_IPS`, unless `ADMIN_ALLOW_INSECURE`
+    /// is set.
+    pub fn check_exposure(&self) -> Result<()> {
+        let loopback = self.host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback()) || self.host == "localhost";
+        if loopback || self.auth.is_configured() {
+            return Ok(());
+        }
+        if self.allow_insecure {
+            warn!(
+                "Admin listener on {} accepts requests from anyone who can reach it (ADMIN_ALLOW_INSECURE)",
+                self.host
+            );
+            return Ok(());
+        }
+        bail!(
+            "Admin listener on {} would accept requests from anyone who can reach it; \
+             set ADMIN_TOKEN, ADMIN_USER and ADMIN_PASSWORD, or ADMIN_ALLOW_IPS, \
+             bind ADMIN_HOST to a loopback address, or set ADMIN_ALLOW_INSECURE=true",
+            self.host
+        )
+    }
+}
+
+/// State shared with the admin handlers
+pub struct AdminState {
+    pub supervisor: Arc<WorkerSupervisor>,
+    pub socket_bridge: Arc<SocketBridge>,
+    /// Applications under path prefixes, reported in the stats
+    pub tenants: Arc<Tenants>,
+    /// Starts a binary upgrade (`POST /admin/upgrade`), when the server supports one
+    pub upgrade: Option<UpgradeTrigger>,
+    /// Runs allowed artisan commands (`POST /admin/artisan`)
+    pub artisan: ArtisanRunner,
+    /// Drain mode of the HTTP listener (`POST /admin/drain`, `POST /admin/undrain`)
+    pub drain: Arc<Drain>,
+    /// Answers `INFO_PATH` with `INFO_LISTENER=admin`
+    pub info: Arc<RuntimeInfo>,
+    /// Static files in memory (`POST /admin/cache/static/flush`)
+    pub file_cache: Arc<FileCache>,
+    /// Directory the HTTP listener serves static files from
+    pub public_dir: String,
+}
+
+/// Admin HTTP server
+pub struct AdminServer {
+    config: AdminConfig,
+    state: Arc<AdminState>,
+    auth: Arc<AdminAuth>,
+    /// Listener bound ahead of `start` (e.g. before dropping privileges)
+    listener: std::sync::Mutex<Option<std::net::TcpListener>>,
+}
+
+impl AdminServer {
+    pub fn new(config: AdminConfig, state: AdminState) -> Self {
+        Self {
+            auth: Arc::new(AdminAuth::new(config.auth.clone())),
+            config,
+            state: Arc::new(state),
+            listener: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Bind the admin socket now instead of in `start`
+    pub fn bind(&self) -> Result<()> {
+        self.config.check_exposure()?;
+        let addr: std::net::SocketAddr = format!("{}:{}", self.config.host, self.config.port).parse()?;
+        let listener = match crate::upgrade::take_tcp_listener("admin", addr) {
+            Some(listener) => listener,
+            None => std::net::TcpListener::bind(addr).map_err(|e| {
+                error!("Failed to bind admin server to {}: {}", addr, e);
+                e
+            })?,
+        };
+        listener.set_nonblocking(true)?;
+        crate::upgrade::register("admin", &listener);
+        *self.listener.lock().unwrap_or_else(|e| e.into_inner()) = Some(listener);
+        Ok(())
+    }
+
+    /// Start the admin server
+    pub async fn start(&self) -> Result<()> {
+        self.config.check_exposure()?;
+        let addr = format!("{}:{}", self.config.host, self.config.port)
+            .parse()
+            .map_err(|e| {
+                error!("Failed to parse admin address: {}", e);
+                Box::new(e)
+            })?;
+
+        let state = self.state.clone();
+        let config = Arc::new(self.config.clone());
+        let auth = self.auth.clone();
+
+        info!("🛠 Starting admin server on {}:{}", self.config.host, self.config.port);
+
+        let make_svc = make_service_fn(move |conn: &AddrStream| {
+            let state = state.clone();
+            let config = config.clone();
+            let auth = auth.clone();
+            let client_ip = conn.remote_addr().ip();
+
+            async move {
+                Ok::<_, hyper::Error>(service_fn(move |req| {
+                    let state = state.clone();
+                    let config = config.clone();
+                    let rejection = auth.check(&req, client_ip);
+                    async move {
+                        match rejection {
+                            Some(response) => Ok(response),
+                            None => handle_admin_request(req, state, config, client_ip).await,
+                        }
+                    }
+                }))
+            }
+        });
+
+        let prebound = self.listener.lock().unwrap_or_else(|e| e.into_inner()).take();
+        let builder = match prebound {
+            Some(listener) => Server::from_tcp(listener)?,
+            None => Server::try_bind(&addr).map_err(|e| {
+                error!("Failed to bind admin server to {}: {}", addr, e);
+                Box::new(e)
+            })?,
+        };
+
+        let server = builder.serve(make_svc);
+
+        server.await.map_err(anyhow::Error::from)
+    }
+}
+
+/// Build info, PHP worker and tenant state, the static file directory, the open file limit and a snapshot of every metric
+///
+/// `public_path` is the directory the HTTP listener resolved at startup, so
+/// the stats name the one being served even if `PUBLIC_PATH` changed since.
+pub fn stats(supervisor: &WorkerSupervisor, tenants: &Tenants, public_path: &str) -> serde_json::Value {
+    json!({
+        "build": crate::build_info(),
+        "php_worker": supervisor.get_stats(),
+        "static": {
+            "exists": std::path::Path::new(public_path).is_dir(),
+            "public_path": public_path,
+        },
+        "tenants": tenants.report(),
+        "open_files": crate::fd_limit::report(),
+        "metrics": metrics().snapshot(),
+    })
+}
+
+/// Route admin requests
+async fn handle_admin_request(
+    req: Request<Body>,
+    state: Arc<AdminState>,
+    config: Arc<AdminConfig>,
+    client_ip: IpAddr,
+) -> Result<Response<Body>, hyper::Error> {
+    if req.method() == Method::GET && state.info.matches(InfoListener::Admin, req.uri().path()) {
+        return Ok(state.info.response(client_ip));
+    }
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/admin/stats") => {
+            let mut body = stats(&state.supervisor, &state.tenants, &state.public_dir);
+            body["drain"] = state.drain.report();
+            if let Some(backends) = state.socket_bridge.backends() {
+                body["backends"] = backends.report();
+            }
+            json_response(StatusCode::OK, body)
+        }
+        (&Method::GET, "/metrics") => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(metrics().render_prometheus()))
+            .unwrap_or_else(|_| Response::new(Body::empty())),
+        (&Method::POST, "/admin/worker/restart") => match state.supervisor.restart(RestartReason::Operator) {
+            Ok(()) => json_response(StatusCode::OK, json!({ "restarted": true, "pid": state.supervisor.pid() })),
+            Err(e) => json_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json!({ "restarted": false, "error": e.to_string() }),
+            ),
+        },
+        (&Method::POST, "/admin/socket/swap") => handle_socket_swap(req, &state).await?,
+        (&Method::POST, "/admin/upgrade") => match &state.upgrade {
+            Some(trigger) => match trigger.upgrade().await {
+                Ok(pid) => json_response(StatusCode::OK, json!({ "upgraded": true, "pid": pid })),
+                Err(error) => json_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    json!({ "upgraded": false, "error": error }),
+                ),
+            },
+            None => json_response(StatusCode::NOT_FOUND, json!({ "error": "not found" })),
+        },
+        (&Method::GET, "/admin/log-level") => json_response(
+            StatusCode::OK,
+            json!({ "filter": crate::hot_reload::current_log_filter() }),
+        ),
+        (&Method::PUT, "/admin/log-level") => handle_log_level(req, &config).await?,
+        (&Method::POST, "/admin/artisan") => {
+            let body = hyper::body::to_bytes(req.into_body()).await?;
+            let authenticated = config.auth.token.is_some() || config.auth.basic.is_some();
+            let (status, body) = state.artisan.handle(&body, &client_ip.to_string(), authenticated).await;
+            json_response(status, body)
+        }
+        (&Method::POST, "/admin/drain") => {
+            let changed = state.drain.start(&format!("admin API from {}", client_ip));
+            json_response(StatusCode::OK, json!({ "changed": changed, "drain": state.drain.report() }))
+        }
+        (&Method::POST, "/admin/cache/static/flush") => handle_static_flush(req.uri().query(), &state).await,
+        (&Method::POST, "/admin/undrain") => {
+            let changed = state.drain.stop(&format!("admin API from {}", client_ip));
+            json_response(StatusCode::OK, json!({ "changed": changed, "drain": state.drain.report() }))
+        }
+        _ => json_response(StatusCode::NOT_FOUND, json!({ "error": "not found" })),
+    };
+
+    Ok(response)
+}
+
+/// Switch the bridge to a new worker socket (blue/green deploy)
+///
+/// Accepts an optional JSON body `{"socket_path": "..."}`; without it the
+/// current path is re-resolved and the pool flushed.
+async fn handle_socket_swap(req: Request<Body>, state: &AdminState) -> Result<Response<Body>, hyper::Error> {
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+
+    let new_path = if body.is_empty() {
+        None
+    } else {
+        match serde_json::from_slice::<serde_json::Value>(&body) {
+            Ok(value) => value.get("socket_path").and_then(|v| v.as_str()).map(str::to_string),
+            Err(e) => {
+                return Ok(json_response(
+                    StatusCode::BAD_REQUEST,
+                    json!({ "error": format!("invalid JSON body: {}", e) }),
+                ));
+            }
+        }
+    };
+
+    let old_path = state.socket_bridge.socket_path();
+    Ok(match state.socket_bridge.swap_socket(new_path).await {
+        Ok(()) => json_response(
+            StatusCode::OK,
+            json!({ "old_socket_path": old_path, "socket_path": state.socket_bridge.socket_path() }),
+        ),
+        Err(e) => json_response(StatusCode::INTERNAL_SERVER_ERROR, json!({ "error": e.to_string() })),
+    })
+}
+
+/// Evict static files from memory, those under `?prefix=` if given
+///
+/// Re-reads the build manifests, which touches the disk, so it runs off the
+/// async workers.
+async fn handle_static_flush(query: Option<&str>, state: &AdminState) -> Response<Body> {
+    let prefix = query
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .find_map(|pair| pair.strip_prefix("prefix="))
+        .map(|prefix| urlencoding::decode(prefix).map_or_else(|_| prefix.to_string(), |prefix| prefix.into_owned()))
+        .filter(|prefix| !prefix.is_empty());
+    if prefix.as_ref().is_some_and(|prefix| !prefix.starts_with('/')) {
+        return json_response(StatusCode::BAD_REQUEST, json!({ "error": "prefix must start with /" }));
+    }
+
+    let file_cache = state.file_cache.clone();
+    let flushed = tokio::task::spawn_blocking(move || {
+        let evicted = file_cache.flush(prefix.as_deref());
+        (prefix, evicted, file_cache.usage())
+    })
+    .await;
+    match flushed {
+        Ok((prefix, evicted, (files, bytes))) => json_response(
+            StatusCode::OK,
+            json!({ "prefix": prefix, "evicted": evicted, "cached": { "files": files, "bytes": bytes } }),
+        ),
+        Err(e) => json_response(StatusCode::INTERNAL_SERVER_ERROR, json!({ "error": e.to_string() })),
+    }
+}
+
+/// Replace the log filter at runtime
+///
+/// The body is either the filter as plain text (`debug`, or a directive
+/// string like `laravel_rust_server=debug,hyper=trace`) or JSON
+/// `{"filter": "...", "revert_after": "10m"}`. `revert_after` overrides
+/// `ADMIN_LOG_LEVEL_REVERT_MS`; `"0"` keeps the filter until changed again.
+async fn handle_log_level(req: Request<Body>, config: &AdminConfig) -> Result<Response<Body>, hyper::Error> {
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let bad_request = |error: String| json_response(StatusCode::BAD_REQUEST, json!({ "error": error }));
+
+    let body = String::from_utf8_lossy(&body);
+    let (filter, revert_after) = if body.trim_start().starts_with('{') {
+        let value = match serde_json::from_str::<serde_json::Value>(&body) {
+            Ok(value) => value,
+            Err(e) => return Ok(bad_request(format!("invalid JSON body: {}", e))),
+        };
+        let Some(filter) = value.get("filter").and_then(|v| v.as_str()).map(str::to_string) else {
+            return Ok(bad_request("missing \"filter\"".to_string()));
+        };
+        let revert_after = match value.get("revert_after") {
+            None | Some(serde_json::Value::Null) => config.log_level_revert_after,
+            Some(v) => {
+                let raw = v.as_str().map(str::to_string).unwrap_or_else(|| v.to_string());
+                match crate::config_loader::parse_duration("revert_after", &raw) {
+                    Ok(d) if d.is_zero() => None,
+                    Ok(d) => Some(d),
+                    Err(e) => return Ok(bad_request(e.to_string())),
+                }
+            }
+        };
+        (filter, revert_after)
+    } else {
+        (body.trim().to_string(), config.log_level_revert_after)
+    };
+
+    Ok(match crate::hot_reload::override_log_filter(&filter, revert_after) {
+        Ok(change) => json_response(
+            StatusCode::OK,
+            json!({
+                "previous": change.previous,
+                "filter": change.filter,
+                "revert_after_ms": revert_after.map(|d| d.as_millis() as u64),
+                "revert_to": change.revert_to,
+            }),
+        ),
+        Err(e) => bad_request(format!("{:#}", e)),
+    })
+}
+
+/// Build a JSON response with the given status
+pub(crate) fn json_response(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap_or_else(|_| Response::new(Body::empty()))
+}
@@ -0,0 +1,286 @@
+//! Artisan commands run through the admin listener
+//!
+//! `POST /admin/artisan` with `{"command": "cache:clear", "args": []}` runs
+//! `php artisan <command> --no-interaction <args>` in `LARAVEL_PATH`, as a
+//! short-lived child of its own next to the PHP worker, and answers with
+//! its exit code and output. Only commands listed in
+//! `ADMIN_ARTISAN_COMMANDS` are run; others get `403` and the attempt is
+//! logged. The endpoint also answers `403` unless the admin listener
+//! requires credentials (`ADMIN_TOKEN` or `ADMIN_USER`), since an address
+//! allow list alone does not say who is calling.
+//!
+//! Arguments are passed to PHP as they are, without a shell. Options
+//! (arguments starting with `-`) are refused unless listed for the command
+//! in `ADMIN_ARTISAN_COMMANDS`, as in `migrate --force`, and a bare `--` is
+//! always refused, so a caller cannot reach options such as `--env` that
+//! change what an allowed command does. A command
+//! that runs longer than `ADMIN_ARTISAN_TIMEOUT_MS` is killed and answered
+//! with `504`. Standard output and error are each cut after
+//! `ADMIN_ARTISAN_MAX_OUTPUT_BYTES`. Calls of the same command wait for
+//! each other, so two `queue:restart` never overlap; different commands
+//! run side by side.
+
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::StatusCode;
+use serde_json::json;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::metrics::{metrics, MetricKind};
+
+/// Default for `ADMIN_ARTISAN_TIMEOUT_MS`
+const DEFAULT_TIMEOUT_MS: u64 = 60_000;
+
+/// Default for `ADMIN_ARTISAN_MAX_OUTPUT_BYTES`
+const DEFAULT_MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// How long output is still read after the command exited or was killed
+const DRAIN_AFTER_EXIT: Duration = Duration::from_secs(1);
+
+/// Artisan command settings
+#[derive(Debug, Clone)]
+pub struct ArtisanConfig {
+    /// Commands that may be run; empty turns the endpoint off
+    pub allowed: Vec<String>,
+    /// Options each command may be given, by command
+    pub options: HashMap<String, Vec<String>>,
+    pub timeout: Duration,
+    /// Bytes kept of standard output and of standard error
+    pub max_output_bytes: usize,
+    pub php_path: String,
+    pub laravel_path: String,
+}
+
+impl ArtisanConfig {
+    pub fn from_env() -> Self {
+        // Each entry is a command followed by the options it may be given
+        let mut allowed = Vec::new();
+        let mut options = HashMap::new();
+        for entry in std::env::var("ADMIN_ARTISAN_COMMANDS").unwrap_or_default().split(',') {
+            let mut words = entry.split_whitespace();
+            let Some(command) = words.next() else {
+                continue;
+            };
+            allowed.push(command.to_string());
+            options.insert(command.to_string(), words.map(str::to_string).collect());
+        }
+        Self {
+            allowed,
+            options,
+            timeout: Duration::from_millis(
+                std::env::var("ADMIN_ARTISAN_TIMEOUT_MS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(DEFAULT_TIMEOUT_MS),
+            ),
+            max_output_bytes: std::env::var("ADMIN_ARTISAN_MAX_OUTPUT_BYTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_OUTPUT_BYTES),
+            php_path: std::env::var("PHP_PATH").unwrap_or_else(|_| "php".to_string()),
+            // Same default as the PHP worker: the parent of the working directory
+            laravel_path: std::env::var("LARAVEL_PATH").unwrap_or_else(|_| "..".to_string()),
+        }
+    }
+}
+
+/// Output of a command stream, cut at the cap
+struct Captured {
+    text: String,
+    truncated: bool,
+}
+
+/// Runs allowed artisan commands, one call of each at a time
+pub struct ArtisanRunner {
+    config: ArtisanConfig,
+    /// One lock per command that has been run
+    running: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl ArtisanRunner {
+    pub fn new(config: ArtisanConfig) -> Self {
+        metrics().describe(
+            "admin_artisan_commands_total",
+            MetricKind::Counter,
+            "Artisan commands requested through the admin listener, by outcome",
+        );
+        Self {
+            config,
+            running: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Answer to a `POST /admin/artisan` body from `client`
+    ///
+    /// `authenticated` tells whether the admin listener requires a token or
+    /// a password.
+    pub async fn handle(&self, body: &[u8], client: &str, authenticated: bool) -> (StatusCode, serde_json::Value) {
+        let request = match serde_json::from_slice::<serde_json::Value>(body) {
+            Ok(request) => request,
+            Err(e) => return (StatusCode::BAD_REQUEST, json!({ "error": format!("invalid JSON body: {}", e) })),
+        };
+        let Some(command) = request.get("command").and_then(|v| v.as_str()).map(str::to_string) else {
+            return (StatusCode::BAD_REQUEST, json!({ "error": "missing \"command\"" }));
+        };
+        let args: Vec<String> = match request.get("args") {
+            None | Some(serde_json::Value::Null) => Vec::new(),
+            Some(serde_json::Value::Array(args)) if args.iter().all(|arg| arg.is_string()) => {
+                args.iter().filter_map(|arg| arg.as_str()).map(str::to_string).collect()
+            }
+            Some(_) => return (StatusCode::BAD_REQUEST, json!({ "error": "\"args\" must be an array of strings" })),
+        };
+
+        let refusal = if !authenticated {
+            Some("the admin listener has no credentials configured; set ADMIN_TOKEN or ADMIN_USER")
+        } else if !self.config.allowed.contains(&command) {
+            Some("command is not in ADMIN_ARTISAN_COMMANDS")
+        } else if args.iter().any(|arg| arg == "--") {
+            Some("\"--\" is not accepted in args")
+        } else if args.iter().any(|arg| !self.option_allowed(&command, arg)) {
+            Some("option is not listed for this command in ADMIN_ARTISAN_COMMANDS")
+        } else {
+            None
+        };
+        if let Some(reason) = refusal {
+            metrics().inc_counter("admin_artisan_commands_total", &[("outcome", "rejected")]);
+            warn!(client = %client, command = %command, args = ?args, reason, "Refused artisan command from the admin API");
+            return (StatusCode::FORBIDDEN, json!({ "error": reason, "command": command }));
+        }
+
+        let lock = self
+            .running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(command.clone())
+            .or_default()
+            .clone();
+        let _turn = lock.lock().await;
+        self.run(&command, &args, client).await
+    }
+
+    /// Whether `arg` may be passed to `command`: not an option, or one
+    /// listed for it, with or without a `=value`
+    fn option_allowed(&self, command: &str, arg: &str) -> bool {
+        if !arg.starts_with('-') {
+            return true;
+        }
+        let name = arg.split_once('=').map_or(arg, |(name, _)| name);
+        self.config
+            .options
+            .get(command)
+            .is_some_and(|options| options.iter().any(|option| option == name))
+    }
+
+    /// Run `php artisan command args`, holding the command's turn
+    async fn run(&self, command: &str, args: &[String], client: &str) -> (StatusCode, serde_json::Value) {
+        info!(client = %client, command = %command, args = ?args, "Running artisan command from the admin API");
+        let started = Instant::now();
+        let mut child = match Command::new(&self.config.php_path)
+            .arg("artisan")
+            .arg(command)
+            .arg("--no-interaction")
+            .args(args)
+            .current_dir(&self.config.laravel_path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            // A group of its own, so a timeout also kills what the command started
+            .process_group(0)
+            .kill_on_drop(true)
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                metrics().inc_counter("admin_artisan_commands_total", &[("outcome", "failure")]);
+                warn!(command = %command, error = %e, "Could not start artisan command");
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    json!({ "error": format!("cannot start {}: {}", self.config.php_path, e), "command": command }),
+                );
+            }
+        };
+        let cap = self.config.max_output_bytes;
+        let stdout = child.stdout.take().map(|stream| tokio::spawn(capture(stream, cap)));
+        let stderr = child.stderr.take().map(|stream| tokio::spawn(capture(stream, cap)));
+
+        let (status, timed_out) = match tokio::time::timeout(self.config.timeout, child.wait()).await {
+            Ok(status) => (status.ok(), false),
+            Err(_) => {
+                if let Some(pid) = child.id() {
+                    // SAFETY: kill(2) only takes plain integers. The child was
+                    // spawned as the leader of its own process group and has
+                    // not been reaped (`id()` is still `Some`), so the group
+                    // id cannot have been reused by an unrelated process.
+                    unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+                }
+                let _ = child.kill().await;
+                (None, true)
+            }
+        };
+        let (stdout, stderr) = tokio::join!(collect(stdout), collect(stderr));
+        let duration_ms = started.elapsed().as_millis() as u64;
+
+        let exit_code = status.and_then(|status| status.code());
+        let (http_status, outcome) = match exit_code {
+            _ if timed_out => (StatusCode::GATEWAY_TIMEOUT, "timeout"),
+            Some(0) => (StatusCode::OK, "success"),
+            _ => (StatusCode::OK, "failure"),
+        };
+        metrics().inc_counter("admin_artisan_commands_total", &[("outcome", outcome)]);
+        info!(command = %command, exit_code = ?exit_code, duration_ms, outcome, "Artisan command finished");
+
+        let mut body = json!({
+            "command": command,
+            "args": args,
+            "exit_code": exit_code,
+            "success": exit_code == Some(0),
+            "duration_ms": duration_ms,
+            "stdout": stdout.text,
+            "stderr": stderr.text,
+            "truncated": stdout.truncated || stderr.truncated,
+        });
+        if timed_out {
+            body["error"] = format!("killed after {} ms (ADMIN_ARTISAN_TIMEOUT_MS)", self.config.timeout.as_millis()).into();
+        }
+        (http_status, body)
+    }
+}
+
+/// Read `stream` to its end, keeping the first `cap` bytes
+async fn capture(mut stream: impl AsyncRead + Unpin, cap: usize) -> Captured {
+    let mut kept = Vec::new();
+    let mut truncated = false;
+    let mut buf = [0u8; 8192];
+    loop {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                let room = cap.saturating_sub(kept.len());
+                kept.extend_from_slice(&buf[..n.min(room)]);
+                truncated |= n > room;
+            }
+        }
+    }
+    Captured {
+        text: String::from_utf8_lossy(&kept).into_owned(),
+        truncated,
+    }
+}
+
+/// Output of a capture task; empty if it does not end soon after the command
+async fn collect(task: Option<tokio::task::JoinHandle<Captured>>) -> Captured {
+    let empty = || Captured { text: String::new(), truncated: false };
+    let Some(task) = task else {
+        return empty();
+    };
+    // A process the command left running in the background may keep the pipe open
+    match tokio::time::timeout(DRAIN_AFTER_EXIT, task).await {
+        Ok(Ok(captured)) => captured,
+        _ => empty(),
+    }
+}
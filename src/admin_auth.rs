@@ -0,0 +1,284 @@
+//! Authentication of the admin listener
+//!
+//! The admin endpoints restart the PHP worker, swap its socket and replace
+//! the binary, so being able to reach the port must not be enough. Every
+//! request passes these checks before any handler runs:
+//!
+//! 1. With `ADMIN_ALLOW_IPS` set, the client address must be in one of the
+//!    listed networks, otherwise the answer is 403. Combined with
+//!    credentials this is a second factor.
+//! 2. With credentials configured, the request must carry
+//!    `Authorization: Bearer <ADMIN_TOKEN>` or Basic credentials matching
+//!    `ADMIN_USER` and `ADMIN_PASSWORD`, otherwise the answer is 401 with
+//!    `WWW-Authenticate`. `GET /metrics` also accepts `ADMIN_METRICS_TOKEN`,
+//!    so Prometheus can scrape with a token that opens nothing else.
+//!
+//! Secrets are compared in constant time. A client address that fails
+//! `ADMIN_AUTH_MAX_FAILURES` times within `ADMIN_AUTH_FAILURE_WINDOW_MS` is
+//! answered with 429 until the window ends, whatever it sends. Failures are
+//! logged with the client address and the reason, never the credentials
+//! presented, throttled like request errors, and counted in
+//! `admin_auth_failures_total{reason}`.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use serde_json::json;
+use tracing::{warn, Level};
+
+use crate::admin::json_response;
+use crate::cidr::{self, Cidr};
+use crate::log_throttle::log_throttle;
+use crate::metrics::{metrics, MetricKind};
+
+/// Default for `ADMIN_AUTH_MAX_FAILURES`
+pub const DEFAULT_MAX_FAILURES: u32 = 10;
+
+/// Default for `ADMIN_AUTH_FAILURE_WINDOW_MS`
+pub const DEFAULT_FAILURE_WINDOW_MS: u64 = 60_000;
+
+/// Realm named in `WWW-Authenticate`
+const REALM: &str = "laravel-rust-admin";
+
+/// Client addresses tracked before expired entries are dropped
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Credentials and address restrictions of the admin listener
+#[derive(Debug, Clone, Default)]
+pub struct AdminAuthConfig {
+    /// Bearer token accepted on every endpoint
+    pub token: Option<String>,
+    /// Bearer token accepted on `GET /metrics` only
+    pub metrics_token: Option<String>,
+    /// Basic auth user name and password
+    pub basic: Option<(String, String)>,
+    /// Networks clients must connect from; `None` allows any address
+    pub allow_ips: Option<Vec<Cidr>>,
+    pub max_failures: u32,
+    pub failure_window: Duration,
+}
+
+impl AdminAuthConfig {
+    /// Read the `ADMIN_*` auth settings
+    ///
+    /// A malformed `ADMIN_ALLOW_IPS` entry is dropped and logged; if none is
+    /// left, no address is allowed rather than every one. Startup validation
+    /// reports the entry before it gets this far.
+    pub fn from_env() -> Self {
+        let secret = |env: &str| std::env::var(env).ok().filter(|v| !v.is_empty());
+        let allow_ips = secret("ADMIN_ALLOW_IPS").map(|list| match cidr::parse_list(&list) {
+            Ok(networks) => networks,
+            Err(problems) => {
+                tracing::error!(problems = ?problems, "Invalid ADMIN_ALLOW_IPS; rejecting admin requests from every address");
+                Vec::new()
+            }
+        });
+
+        Self {
+            token: secret("ADMIN_TOKEN"),
+            metrics_token: secret("ADMIN_METRICS_TOKEN"),
+            basic: secret("ADMIN_USER").zip(secret("ADMIN_PASSWORD")),
+            allow_ips,
+            max_failures: std::env::var("ADMIN_AUTH_MAX_FAILURES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_FAILURES),
+            failure_window: Duration::from_millis(
+                std::env::var("ADMIN_AUTH_FAILURE_WINDOW_MS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(DEFAULT_FAILURE_WINDOW_MS),
+            ),
+        }
+    }
+
+    fn has_credentials(&self) -> bool {
+        self.token.is_some() || self.metrics_token.is_some() || self.basic.is_some()
+    }
+
+    /// Whether any check is configured at all
+    pub fn is_configured(&self) -> bool {
+        self.has_credentials() || self.allow_ips.is_some()
+    }
+}
+
+/// Recent failures of one client address
+struct Failures {
+    window_started: Instant,
+    count: u32,
+}
+
+/// Checks admin requests against [`AdminAuthConfig`]
+pub struct AdminAuth {
+    config: AdminAuthConfig,
+    failures: Mutex<HashMap<IpAddr, Failures>>,
+}
+
+impl AdminAuth {
+    pub fn new(config: AdminAuthConfig) -> Self {
+        metrics().describe(
+            "admin_auth_failures_total",
+            MetricKind::Counter,
+            "Admin requests rejected before reaching a handler, by reason",
+        );
+        Self {
+            config,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &AdminAuthConfig {
+        &self.config
+    }
+
+    /// Response rejecting the request, or `None` when it may go on
+    pub fn check(&self, req: &Request<Body>, client_ip: IpAddr) -> Option<Response<Body>> {
+        if let Some(retry_after) = self.locked_out(client_ip) {
+            self.log_failure(client_ip, req, "locked_out");
+            let mut response = json_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                json!({ "error": "too many failed attempts" }),
+            );
+            if let Ok(value) = retry_after.as_secs().max(1).to_string().parse() {
+                response.headers_mut().insert(header::RETRY_AFTER, value);
+            }
+            return Some(response);
+        }
+
+        if let Some(networks) = &self.config.allow_ips {
+            if !cidr::any_contains(networks, client_ip) {
+                self.fail(client_ip, req, "address_not_allowed");
+                return Some(json_response(StatusCode::FORBIDDEN, json!({ "error": "forbidden" })));
+            }
+        }
+
+        if !self.config.has_credentials() {
+            return None;
+        }
+        let reason = match req.headers().get(header::AUTHORIZATION) {
+            None => "missing_credentials",
+            Some(value) if self.accepts(value.as_bytes(), req) => {
+                self.failures.lock().unwrap_or_else(|e| e.into_inner()).remove(&client_ip);
+                return None;
+            }
+            Some(_) => "invalid_credentials",
+        };
+        self.fail(client_ip, req, reason);
+        Some(self.unauthorized())
+    }
+
+    /// Whether the `Authorization` header value grants access to `req`
+    fn accepts(&self, authorization: &[u8], req: &Request<Body>) -> bool {
+        let (scheme, credentials) = match authorization.iter().position(|&b| b == b' ') {
+            Some(space) => (&authorization[..space], authorization[space + 1..].trim_ascii()),
+            None => return false,
+        };
+
+        if scheme.eq_ignore_ascii_case(b"bearer") {
+            let admin = self
+                .config
+                .token
+                .as_ref()
+                .is_some_and(|token| constant_time_eq(credentials, token.as_bytes()));
+            let scrape = req.method() == Method::GET
+                && req.uri().path() == "/metrics"
+                && self
+                    .config
+                    .metrics_token
+                    .as_ref()
+                    .is_some_and(|token| constant_time_eq(credentials, token.as_bytes()));
+            return admin || scrape;
+        }
+
+        if scheme.eq_ignore_ascii_case(b"basic") {
+            let Some((user, password)) = &self.config.basic else {
+                return false;
+            };
+            let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(credentials) else {
+                return false;
+            };
+            let Some(colon) = decoded.iter().position(|&b| b == b':') else {
+                return false;
+            };
+            // Compare both halves so the time taken does not tell which one was wrong
+            let user_matches = constant_time_eq(&decoded[..colon], user.as_bytes());
+            let password_matches = constant_time_eq(&decoded[colon + 1..], password.as_bytes());
+            return user_matches & password_matches;
+        }
+
+        false
+    }
+
+    /// 401 offering every configured scheme
+    fn unauthorized(&self) -> Response<Body> {
+        let mut response = json_response(StatusCode::UNAUTHORIZED, json!({ "error": "unauthorized" }));
+        let mut challenges = Vec::new();
+        if self.config.token.is_some() || self.config.metrics_token.is_some() {
+            challenges.push(format!("Bearer realm=\"{}\"", REALM));
+        }
+        if self.config.basic.is_some() {
+            challenges.push(format!("Basic realm=\"{}\", charset=\"UTF-8\"", REALM));
+        }
+        for challenge in challenges {
+            if let Ok(value) = challenge.parse() {
+                response.headers_mut().append(header::WWW_AUTHENTICATE, value);
+            }
+        }
+        response
+    }
+
+    /// Time left in the client's lockout, if it has one
+    fn locked_out(&self, client_ip: IpAddr) -> Option<Duration> {
+        let failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = failures.get(&client_ip)?;
+        let elapsed = entry.window_started.elapsed();
+        (entry.count >= self.config.max_failures && elapsed < self.config.failure_window)
+            .then(|| self.config.failure_window - elapsed)
+    }
+
+    fn fail(&self, client_ip: IpAddr, req: &Request<Body>, reason: &'static str) {
+        {
+            let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+            if failures.len() >= MAX_TRACKED_CLIENTS {
+                let window = self.config.failure_window;
+                failures.retain(|_, entry| entry.window_started.elapsed() < window);
+            }
+            let entry = failures.entry(client_ip).or_insert(Failures {
+                window_started: Instant::now(),
+                count: 0,
+            });
+            if entry.window_started.elapsed() >= self.config.failure_window {
+                entry.window_started = Instant::now();
+                entry.count = 0;
+            }
+            entry.count += 1;
+        }
+        self.log_failure(client_ip, req, reason);
+    }
+
+    fn log_failure(&self, client_ip: IpAddr, req: &Request<Body>, reason: &'static str) {
+        metrics().inc_counter("admin_auth_failures_total", &[("reason", reason)]);
+        let message = format!("admin request from {} rejected: {}", client_ip, reason);
+        if log_throttle().allow(&format!("admin_auth:{}", reason), Level::WARN, &message) {
+            warn!(
+                client_ip = %client_ip,
+                method = %req.method(),
+                path = req.uri().path(),
+                reason,
+                "Admin request rejected"
+            );
+        }
+    }
+}
+
+/// Compare secrets without returning early on the first difference
+///
+/// Only the length is revealed, which a token of fixed length gives away
+/// anyway.
+pub(crate) fn constant_time_eq(given: &[u8], secret: &[u8]) -> bool {
+    given.len() == secret.len() && given.iter().zip(secret).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
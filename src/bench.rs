@@ -0,0 +1,198 @@
+//! Load generator for the bridge path (`bench` subcommand)
+//!
+//! Drives synthetic requests through the same code an HTTP request takes
+//! after routing — payload construction, [`SocketBridge`], response parsing —
+//! from `concurrency` tasks for a fixed duration, against the PHP worker at
+//! `SOCKET_PATH`. The HTTP listener itself is not involved, so the numbers
+//! isolate the bridge and the worker.
+//!
+//! Allocation counts are only available in builds with the `alloc-stats`
+//! feature, which installs [`CountingAllocator`] as the global allocator.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hyper::body::Bytes;
+use hyper::{Body, Request};
+
+use crate::errors::ServerError;
+use crate::request_context::RequestContext;
+use crate::server::{forward, HttpRequestPayload};
+use crate::SocketBridge;
+
+/// What to send and for how long
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// Requests in flight at once
+    pub concurrency: usize,
+    pub duration: Duration,
+    /// Size of the request body; 0 sends GET requests, anything else POST
+    pub payload_size: usize,
+    /// Request path, including the query string
+    pub path: String,
+}
+
+/// Outcome of a run
+#[derive(Debug)]
+pub struct BenchReport {
+    pub options: BenchOptions,
+    pub elapsed: Duration,
+    /// Latency of every successful request, sorted
+    pub latencies: Vec<Duration>,
+    /// Failed requests by error kind
+    pub errors: BTreeMap<&'static str, u64>,
+    /// Heap allocations during the run (`alloc-stats` builds only)
+    pub allocations: Option<u64>,
+}
+
+/// Run the benchmark against `bridge`
+pub async fn run(bridge: Arc<SocketBridge>, options: BenchOptions) -> BenchReport {
+    let body = Bytes::from(vec![b'x'; options.payload_size]);
+    let allocations_before = allocations();
+    let started = Instant::now();
+    let deadline = started + options.duration;
+
+    let tasks: Vec<_> = (0..options.concurrency.max(1))
+        .map(|_| {
+            let bridge = bridge.clone();
+            let path = options.path.clone();
+            let body = body.clone();
+            tokio::spawn(async move {
+                let mut latencies = Vec::new();
+                let mut errors: BTreeMap<&'static str, u64> = BTreeMap::new();
+                while Instant::now() < deadline {
+                    let request_started = Instant::now();
+                    match send_one(&bridge, &path, &body).await {
+                        Ok(()) => latencies.push(request_started.elapsed()),
+                        Err(e) => *errors.entry(ServerError::classify(e).kind()).or_default() += 1,
+                    }
+                }
+                (latencies, errors)
+            })
+        })
+        .collect();
+
+    let mut latencies = Vec::new();
+    let mut errors = BTreeMap::new();
+    for task in tasks {
+        if let Ok((task_latencies, task_errors)) = task.await {
+            latencies.extend(task_latencies);
+            for (kind, count) in task_errors {
+                *errors.entry(kind).or_default() += count;
+            }
+        }
+    }
+    let elapsed = started.elapsed();
+    latencies.sort_unstable();
+
+    BenchReport {
+        options,
+        elapsed,
+        latencies,
+        errors,
+        allocations: allocations().zip(allocations_before).map(|(after, before)| after - before),
+    }
+}
+
+/// One request through the bridge, with its response body read
+async fn send_one(bridge: &Arc<SocketBridge>, path: &str, body: &Bytes) -> anyhow::Result<()> {
+    let method = if body.is_empty() { "GET" } else { "POST" };
+    let request = Request::builder().method(method).uri(path).body(Body::empty())?;
+    let context = RequestContext::new(&request, IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+    let payload = HttpRequestPayload::synthetic(method, path, (!body.is_empty()).then(|| body.clone()));
+
+    let response = forward(bridge, payload, &context).await?;
+    hyper::body::to_bytes(response.into_body()).await?;
+    Ok(())
+}
+
+impl BenchReport {
+    pub fn requests(&self) -> u64 {
+        self.latencies.len() as u64 + self.errors.values().sum::<u64>()
+    }
+
+    /// Latency below which `quantile` of the successful requests completed
+    pub fn percentile(&self, quantile: f64) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let index = ((self.latencies.len() as f64 * quantile).ceil() as usize).clamp(1, self.latencies.len());
+        Some(self.latencies[index - 1])
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let requests = self.requests();
+        let seconds = self.elapsed.as_secs_f64();
+        let method = if self.options.payload_size == 0 { "GET" } else { "POST" };
+        writeln!(
+            f,
+            "{} {} with a {} byte body, {} concurrent, {:.1}s",
+            method, self.options.path, self.options.payload_size, self.options.concurrency, seconds
+        )?;
+        writeln!(f, "  requests:    {} ({:.1}/s)", requests, requests as f64 / seconds)?;
+
+        let failed: u64 = self.errors.values().sum();
+        let by_kind: Vec<String> = self.errors.iter().map(|(kind, count)| format!("{}: {}", kind, count)).collect();
+        if by_kind.is_empty() {
+            writeln!(f, "  errors:      0")?;
+        } else {
+            writeln!(f, "  errors:      {} ({})", failed, by_kind.join(", "))?;
+        }
+
+        let ms = |quantile| self.percentile(quantile).map_or("-".to_string(), |d| format!("{:.2}ms", d.as_secs_f64() * 1000.0));
+        writeln!(
+            f,
+            "  latency:     p50 {}  p90 {}  p99 {}  max {}",
+            ms(0.5),
+            ms(0.9),
+            ms(0.99),
+            ms(1.0)
+        )?;
+
+        match self.allocations {
+            Some(allocations) if requests > 0 => {
+                write!(f, "  allocations: {:.1} per request", allocations as f64 / requests as f64)
+            }
+            Some(_) => write!(f, "  allocations: -"),
+            None => write!(f, "  allocations: n/a (build with --features alloc-stats)"),
+        }
+    }
+}
+
+#[cfg(feature = "alloc-stats")]
+static ALLOCATIONS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+/// Global allocator that counts allocations for the benchmark report
+#[cfg(feature = "alloc-stats")]
+pub struct CountingAllocator;
+
+#[cfg(feature = "alloc-stats")]
+unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        std::alloc::System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        std::alloc::System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: std::alloc::Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        std::alloc::System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Allocations so far, when counted
+fn allocations() -> Option<u64> {
+    #[cfg(feature = "alloc-stats")]
+    return Some(ALLOCATIONS.load(std::sync::atomic::Ordering::Relaxed));
+    #[cfg(not(feature = "alloc-stats"))]
+    None
+}
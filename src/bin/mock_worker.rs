@@ -0,0 +1,38 @@
+//! Stand-in PHP worker for testing the server without Laravel
+//!
+//! ```text
+//! mock-worker /tmp/rust_php_bridge.sock [script.json]
+//! ```
+//!
+//! Listens on the socket until interrupted and answers frames as the
+//! script says (see `laravel_rust_server::mock_worker`); without a script
+//! it echoes every request back.
+
+use anyhow::{Context, Result};
+use laravel_rust_server::mock_worker::{MockWorker, Script};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let socket_path = args.next().context("usage: mock-worker SOCKET_PATH [SCRIPT.json]")?;
+    let script = match args.next() {
+        Some(path) => {
+            let json = std::fs::read_to_string(&path).with_context(|| format!("cannot read {}", path))?;
+            serde_json::from_str::<Script>(&json).with_context(|| format!("invalid script {}", path))?
+        }
+        None => Script::default(),
+    };
+
+    let worker = MockWorker::start(&socket_path, script).with_context(|| format!("cannot listen on {}", socket_path))?;
+    eprintln!("mock worker listening on {}", socket_path);
+    tokio::signal::ctrl_c().await?;
+    let stats = worker.stats();
+    eprintln!(
+        "{} connections, {} frames, {} clean closes, {} abrupt closes",
+        stats.accepted(),
+        stats.frames(),
+        stats.clean_closes(),
+        stats.abrupt_closes()
+    );
+    Ok(())
+}
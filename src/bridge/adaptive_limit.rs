@@ -0,0 +1,265 @@
+//! Adaptive concurrency limit in front of the PHP worker
+//!
+//! How many requests the worker can take at once depends on what they do,
+//! so any fixed cap is either too low or lets requests queue until they
+//! time out. The limiter instead watches the latency and outcome of bridge
+//! requests in short windows (AIMD):
+//!
+//! * while the window's average latency stays within `latency_tolerance` ×
+//!   the baseline and the limit is actually used, it grows by one;
+//! * when latency inflates, a request times out or more than a tenth of
+//!   requests fail, it is multiplied by `backoff`;
+//! * requests arriving while the limit is reached are shed with a 503.
+//!
+//! The baseline is the lowest window latency seen, so the limit should start
+//! below the worker's capacity for it to be measured uncongested. If
+//! latency is still inflated at `min_limit`, the workload itself has become
+//! slower and the baseline is reset to it.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+use crate::metrics::{metrics, MetricKind};
+
+/// Limiter settings
+#[derive(Debug, Clone)]
+pub struct AdaptiveLimitConfig {
+    pub initial_limit: usize,
+    pub min_limit: usize,
+    pub max_limit: usize,
+    /// Window latency above `baseline × latency_tolerance` shrinks the limit
+    pub latency_tolerance: f64,
+    /// Factor applied to the limit when it shrinks
+    pub backoff: f64,
+    /// Length of one measurement window
+    pub window: Duration,
+}
+
+/// How a request admitted by the limiter ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The worker answered; its latency is sampled
+    Success,
+    /// No answer in time
+    Timeout,
+    /// The worker could not be reached
+    Failure,
+    /// Not informative about worker capacity (e.g. a frame rejected up front)
+    Ignore,
+}
+
+/// Samples of the current window
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    samples: u32,
+    latency_sum: Duration,
+    timeouts: u32,
+    failures: u32,
+    /// Highest number of requests in flight, to tell whether the limit is used
+    max_in_flight: usize,
+}
+
+impl Window {
+    fn new(now: Instant) -> Self {
+        Self {
+            started: now,
+            samples: 0,
+            latency_sum: Duration::ZERO,
+            timeouts: 0,
+            failures: 0,
+            max_in_flight: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    limit: f64,
+    /// Lowest window latency, in seconds
+    baseline: Option<f64>,
+    window: Window,
+}
+
+/// AIMD concurrency limiter for bridge requests
+#[derive(Debug)]
+pub struct AdaptiveLimiter {
+    config: AdaptiveLimitConfig,
+    in_flight: AtomicUsize,
+    /// Current limit, readable without the state lock
+    limit: AtomicUsize,
+    state: Mutex<State>,
+}
+
+impl AdaptiveLimiter {
+    pub fn new(config: AdaptiveLimitConfig) -> Arc<Self> {
+        metrics().describe(
+            "bridge_concurrency_limit",
+            MetricKind::Gauge,
+            "Current adaptive limit of requests in flight to the PHP worker",
+        );
+        metrics().describe(
+            "bridge_concurrency_baseline_seconds",
+            MetricKind::Gauge,
+            "Bridge latency the adaptive limit treats as uncongested",
+        );
+        metrics().describe(
+            "bridge_requests_shed_total",
+            MetricKind::Counter,
+            "Requests answered with 503 because the adaptive concurrency limit was reached",
+        );
+
+        let min_limit = config.min_limit.max(1);
+        let max_limit = config.max_limit.max(min_limit);
+        let config = AdaptiveLimitConfig {
+            initial_limit: config.initial_limit.clamp(min_limit, max_limit),
+            min_limit,
+            max_limit,
+            latency_tolerance: config.latency_tolerance.max(1.0),
+            backoff: config.backoff.clamp(0.1, 0.99),
+            ..config
+        };
+        metrics().set_gauge("bridge_concurrency_limit", &[], config.initial_limit as f64);
+
+        Arc::new(Self {
+            in_flight: AtomicUsize::new(0),
+            limit: AtomicUsize::new(config.initial_limit),
+            state: Mutex::new(State {
+                limit: config.initial_limit as f64,
+                baseline: None,
+                window: Window::new(Instant::now()),
+            }),
+            config,
+        })
+    }
+
+    /// Current limit
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    /// Requests currently admitted
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Admit a request, or `None` when it should be shed
+    pub fn try_acquire(self: &Arc<Self>) -> Option<LimitPermit> {
+        let admitted = self
+            .in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |in_flight| {
+                (in_flight < self.limit()).then_some(in_flight + 1)
+            });
+        match admitted {
+            Ok(previous) => Some(LimitPermit {
+                limiter: self.clone(),
+                started: Instant::now(),
+                in_flight: previous + 1,
+                outcome: Outcome::Ignore,
+            }),
+            Err(_) => {
+                metrics().inc_counter("bridge_requests_shed_total", &[]);
+                None
+            }
+        }
+    }
+
+    fn complete(&self, outcome: Outcome, latency: Duration, in_flight: usize) {
+        self.in_flight.fetch_sub(1, Ordering::AcqRel);
+        if outcome == Outcome::Ignore {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let window = &mut state.window;
+        window.max_in_flight = window.max_in_flight.max(in_flight);
+        match outcome {
+            Outcome::Success => {
+                window.samples += 1;
+                window.latency_sum += latency;
+            }
+            Outcome::Timeout => window.timeouts += 1,
+            Outcome::Failure => window.failures += 1,
+            Outcome::Ignore => {}
+        }
+
+        if now.duration_since(window.started) >= self.config.window {
+            let finished = std::mem::replace(&mut state.window, Window::new(now));
+            self.adjust(&mut state, finished);
+        }
+    }
+
+    /// Move the limit according to the finished window
+    fn adjust(&self, state: &mut State, window: Window) {
+        let total = window.samples + window.timeouts + window.failures;
+        if total == 0 {
+            return;
+        }
+
+        let latency = (window.samples > 0).then(|| window.latency_sum.as_secs_f64() / window.samples as f64);
+        let inflated = match (latency, state.baseline) {
+            (Some(latency), Some(baseline)) => latency > baseline * self.config.latency_tolerance,
+            _ => false,
+        };
+        let overloaded = window.timeouts > 0 || window.failures * 10 > total || inflated;
+        let min_limit = self.config.min_limit as f64;
+
+        if overloaded {
+            if inflated && state.limit <= min_limit {
+                // Slow even without concurrency: the workload changed
+                state.baseline = latency;
+            }
+            state.limit = (state.limit * self.config.backoff).max(min_limit);
+        } else {
+            if let Some(latency) = latency {
+                state.baseline = Some(state.baseline.map_or(latency, |baseline| baseline.min(latency)));
+            }
+            // Only grow a limit the traffic actually reaches
+            if window.max_in_flight * 2 >= state.limit as usize {
+                state.limit = (state.limit + 1.0).min(self.config.max_limit as f64);
+            }
+        }
+
+        let limit = state.limit as usize;
+        self.limit.store(limit, Ordering::Relaxed);
+        metrics().set_gauge("bridge_concurrency_limit", &[], limit as f64);
+        if let Some(baseline) = state.baseline {
+            metrics().set_gauge("bridge_concurrency_baseline_seconds", &[], baseline);
+        }
+        debug!(
+            limit,
+            latency_ms = latency.map(|l| l * 1000.0),
+            baseline_ms = state.baseline.map(|b| b * 1000.0),
+            timeouts = window.timeouts,
+            failures = window.failures,
+            "Adjusted adaptive concurrency limit"
+        );
+    }
+}
+
+/// Slot of an admitted request; report how it ended with [`LimitPermit::finish`]
+///
+/// A permit dropped without an outcome frees its slot without being sampled.
+#[derive(Debug)]
+pub struct LimitPermit {
+    limiter: Arc<AdaptiveLimiter>,
+    started: Instant,
+    in_flight: usize,
+    outcome: Outcome,
+}
+
+impl LimitPermit {
+    pub fn finish(mut self, outcome: Outcome) {
+        self.outcome = outcome;
+    }
+}
+
+impl Drop for LimitPermit {
+    fn drop(&mut self) {
+        self.limiter.complete(self.outcome, self.started.elapsed(), self.in_flight);
+    }
+}
@@ -0,0 +1,519 @@
+//! Pooled connections to the PHP worker socket
+//!
+//! Every frame exchanged with the worker, in both directions, is a 4-byte
+//! big-endian length followed by that many bytes of JSON: an HTTP request
+//! frame or a serialized `PhpRequest` command going out, a serialized
+//! [`PhpResponse`] coming back. A connection carries one frame at a time and
+//! goes back to the pool once its response has been read; a connection that
+//! failed, or whose exchange was cancelled half way, is closed instead.
+//!
+//! Response frames are read with every read bounded by `read_timeout`.
+//! A frame of length 0, a connection that ends before the whole frame has
+//! arrived and a frame that is not a response are each a [`FrameError`],
+//! and the connection is closed rather than returned to the pool, since
+//! whatever is left on it can no longer be told apart from the next frame.
+//!
+//! Frames are serialized straight into a buffer kept with each connection,
+//! length prefix included, and the response is read into the same buffer.
+//! A request therefore costs no intermediate `String`, and the buffer only
+//! grows until it fits the largest frame its connection has carried.
+//!
+//! Connections are retired in an orderly way: when they have been idle for
+//! too long, have carried `max_uses` frames, were found closed or holding
+//! unsolicited bytes, failed an exchange, or the pool is closed. Whatever
+//! the worker sent that was not read is drained first, since closing a Unix
+//! socket with unread data resets the worker's end instead of ending it.
+//! Connections retired between frames may then be sent a `goodbye`
+//! command, which the worker does not answer. Finally the stream is shut
+//! down, so the worker reads an end of file where it expects the next
+//! frame. Each retirement is logged at debug level and counted in
+//! `bridge_connections_closed_total{reason}`.
+//!
+//! Connections are opened by a [`BridgeTransport`]: the worker's Unix
+//! socket unless the pool is built [`with_transport`](ConnectionPool::with_transport).
+//!
+//! With [`Framing::Goridge`] frames are RoadRunner's instead (see
+//! [`goridge`](crate::bridge::goridge)), and the workers connect to the
+//! socket rather than listen on it. Such connections cannot be reopened at
+//! will, so they are not closed for being idle.
+//!
+//! Idle connections are spread over several shards, each behind a lock of
+//! its own, so hundreds of requests checking connections in and out do not
+//! all queue on one lock. A request starts at the next shard in turn and
+//! takes from the others when its own is empty. How many connections may be
+//! open is counted once for the whole pool, so `max_connections` holds
+//! whatever the shards contain.
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::task::{Context as TaskContext, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::sync::Semaphore;
+use thiserror::Error;
+use tracing::debug;
+
+use crate::bridge::goridge;
+use crate::bridge::socket_bridge::PhpRequest;
+use crate::bridge::transport::{BridgeTransport, UnixTransport};
+use crate::bridge::PhpResponse;
+use crate::bridge_config::BridgeConfig;
+use crate::config::AppConfig;
+use crate::errors::ServerError;
+use crate::metrics::{metrics, MetricKind};
+
+/// Bytes of the length prefix in front of every frame
+pub const FRAME_PREFIX_LEN: usize = 4;
+
+/// Capacity a connection's frame buffer is cut back to after an unusually large frame
+const RETAINED_BUFFER_CAPACITY: usize = 1024 * 1024;
+
+/// Unread bytes drained from a retiring connection before it is closed regardless
+const MAX_DRAINED: usize = 1024 * 1024;
+
+/// Connection pool settings
+#[derive(Debug, Clone)]
+pub struct ConnectionPoolConfig {
+    /// Socket of [`UnixTransport`]; unused by other transports
+    pub socket_path: String,
+    /// Connections opened by [`ConnectionPool::initialize`]
+    pub min_connections: usize,
+    /// Connections open at once, in use or idle
+    pub max_connections: usize,
+    pub connection_timeout: Duration,
+    /// Longest wait for each read of a response frame
+    pub read_timeout: Duration,
+    /// Idle connections unused for longer are closed rather than reused
+    pub health_check_interval: Duration,
+    /// Locks the idle connections are spread over
+    pub shards: usize,
+    /// Frames a connection carries before it is closed (None: no limit)
+    pub max_uses: Option<u64>,
+    /// Send a `goodbye` command before closing a connection between frames
+    pub goodbye_frame: bool,
+    /// How frames are laid out, and which side connects
+    pub framing: Framing,
+}
+
+/// How frames are laid out on a connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// A 4-byte big-endian length and JSON, both ways; the server connects to the worker
+    #[default]
+    LengthPrefixed,
+    /// RoadRunner's goridge frames; the workers connect to the server
+    Goridge,
+}
+
+impl ConnectionPoolConfig {
+    /// Pool settings of [`BridgeConfig::from_env`]
+    pub fn from_env() -> Self {
+        Self::from(&BridgeConfig::from_env())
+    }
+}
+
+impl From<&BridgeConfig> for ConnectionPoolConfig {
+    fn from(config: &BridgeConfig) -> Self {
+        Self {
+            socket_path: config.socket_path.clone(),
+            min_connections: config.pool_min,
+            max_connections: config.pool_max.max(1),
+            connection_timeout: config.connect_timeout,
+            read_timeout: config.read_timeout,
+            health_check_interval: config.health_check_interval,
+            shards: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            max_uses: config.pool_max_uses,
+            goodbye_frame: config.goodbye_frame,
+            framing: config.protocol.codec().framing(),
+        }
+    }
+}
+
+/// Why the worker's response frame could not be read
+///
+/// The connection it was read from is always closed.
+#[derive(Debug, Error)]
+pub enum FrameError {
+    /// The length prefix was 0, which no response can be
+    #[error("PHP worker sent an empty response frame")]
+    Empty,
+    /// The connection ended before the whole frame had arrived
+    #[error("PHP worker closed the connection mid-response, after {received} bytes of the frame")]
+    ClosedMidResponse { received: usize },
+    /// The frame arrived whole but is not a response
+    #[error("invalid response frame from PHP worker: {0}")]
+    Invalid(#[source] serde_json::Error),
+    /// The frame's header does not describe a frame
+    #[error("corrupt response frame from PHP worker: {0}")]
+    Corrupt(String),
+    /// Nothing arrived for `read_timeout`
+    #[error("PHP worker sent nothing for {0:?}")]
+    TimedOut(Duration),
+}
+
+impl From<FrameError> for ServerError {
+    fn from(error: FrameError) -> Self {
+        match error {
+            FrameError::TimedOut(_) => ServerError::BridgeTimeout(error.to_string()),
+            _ => ServerError::UpstreamMalformed(error.to_string()),
+        }
+    }
+}
+
+/// Why a connection was closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetireReason {
+    /// Idle for longer than `health_check_interval`
+    Idle,
+    /// Carried `max_uses` frames
+    Recycled,
+    /// Found closed by the worker, or with bytes nobody asked for
+    Unhealthy,
+    /// Its exchange failed
+    Error,
+    /// The pool was closed
+    Shutdown,
+}
+
+impl RetireReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RetireReason::Idle => "idle",
+            RetireReason::Recycled => "recycled",
+            RetireReason::Unhealthy => "unhealthy",
+            RetireReason::Error => "error",
+            RetireReason::Shutdown => "shutdown",
+        }
+    }
+
+    /// Whether the connection is known to be between frames, so the worker can be told goodbye
+    fn between_frames(&self) -> bool {
+        matches!(self, RetireReason::Idle | RetireReason::Recycled | RetireReason::Shutdown)
+    }
+}
+
+/// Write `value` into `buf` as one frame, replacing what `buf` held
+///
+/// The JSON is serialized in place behind a placeholder prefix, which is
+/// then filled in with its length.
+pub fn encode_frame(buf: &mut Vec<u8>, value: &impl Serialize) -> Result<()> {
+    buf.clear();
+    buf.extend_from_slice(&[0; FRAME_PREFIX_LEN]);
+    serde_json::to_writer(&mut *buf, value).context("cannot serialize frame")?;
+    let len = u32::try_from(buf.len() - FRAME_PREFIX_LEN).context("frame longer than 4 GiB")?;
+    buf[..FRAME_PREFIX_LEN].copy_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+/// One connection to the worker and its frame buffer
+struct Connection<S> {
+    stream: S,
+    /// Holds the frame being sent, then the response being read
+    buf: Vec<u8>,
+    idle_since: Instant,
+    /// Frames exchanged so far
+    uses: u64,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    async fn open<T: BridgeTransport<Stream = S>>(transport: &T, config: &ConnectionPoolConfig) -> Result<Self> {
+        let stream = tokio::time::timeout(config.connection_timeout, transport.connect())
+            .await
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "connecting to the PHP worker timed out"))
+            .and_then(|connected| connected)
+            .with_context(|| format!("cannot connect to PHP worker at {}", transport))?;
+        Ok(Self {
+            stream,
+            buf: Vec::new(),
+            idle_since: Instant::now(),
+            uses: 0,
+        })
+    }
+
+    /// Send `frame` and read the worker's response to it
+    async fn exchange(&mut self, frame: &serde_json::Value, framing: Framing, read_timeout: Duration) -> Result<PhpResponse> {
+        if framing == Framing::Goridge {
+            return self.exchange_goridge(frame, read_timeout).await;
+        }
+        encode_frame(&mut self.buf, frame)?;
+        self.stream.write_all(&self.buf).await?;
+
+        let mut prefix = [0; FRAME_PREFIX_LEN];
+        read_part(&mut self.stream, &mut prefix, 0, read_timeout).await?;
+        let len = u32::from_be_bytes(prefix) as usize;
+        if len == 0 {
+            return Err(FrameError::Empty.into());
+        }
+        self.buf.clear();
+        self.buf.resize(len, 0);
+        read_part(&mut self.stream, &mut self.buf, FRAME_PREFIX_LEN, read_timeout).await?;
+        let response = serde_json::from_slice(&self.buf).map_err(|e| FrameError::Invalid(e).into());
+
+        if self.buf.capacity() > RETAINED_BUFFER_CAPACITY {
+            self.buf = Vec::new();
+        }
+        response
+    }
+
+    /// [`exchange`](Self::exchange) in goridge frames
+    ///
+    /// Frames flagged [`goridge::STREAM`] are followed by more of the same
+    /// response; their bodies are joined, and only the first one's context is kept.
+    async fn exchange_goridge(&mut self, frame: &serde_json::Value, read_timeout: Duration) -> Result<PhpResponse> {
+        goridge::encode_request(&mut self.buf, frame)?;
+        self.stream.write_all(&self.buf).await?;
+
+        let mut first = None;
+        let mut payload = Vec::new();
+        let mut received = 0;
+        loop {
+            let mut header = [0; goridge::HEADER_LEN];
+            read_part(&mut self.stream, &mut header, received, read_timeout).await?;
+            let header = goridge::parse_header(&header)?;
+            received += goridge::HEADER_LEN;
+            self.buf.clear();
+            self.buf.resize(header.options_len, 0);
+            read_part(&mut self.stream, &mut self.buf, received, read_timeout).await?;
+            received += header.options_len;
+            let options = goridge::parse_options(&self.buf);
+
+            let start = payload.len();
+            payload.resize(start + header.payload_len, 0);
+            read_part(&mut self.stream, &mut payload[start..], received, read_timeout).await?;
+            received += header.payload_len;
+            match &first {
+                None => first = Some((header.flags, options)),
+                Some(_) => {
+                    let context_len = options.first().map_or(0, |&len| len as usize).min(header.payload_len);
+                    payload.drain(start..start + context_len);
+                }
+            }
+            if header.stream & goridge::STREAM == 0 {
+                break;
+            }
+        }
+        let (flags, options) = first.unwrap_or_default();
+        Ok(goridge::decode_response(flags, &options, payload)?)
+    }
+
+    /// Read what the worker has already sent, without waiting
+    ///
+    /// Returns the bytes read, and whether the worker has closed its end.
+    fn drain(&mut self) -> (usize, bool) {
+        let mut cx = TaskContext::from_waker(Waker::noop());
+        let mut scratch = [0; 8192];
+        let mut drained = 0;
+        while drained < MAX_DRAINED {
+            let mut buf = ReadBuf::new(&mut scratch);
+            match Pin::new(&mut self.stream).poll_read(&mut cx, &mut buf) {
+                Poll::Ready(Ok(())) if buf.filled().is_empty() => return (drained, true),
+                Poll::Ready(Ok(())) => drained += buf.filled().len(),
+                Poll::Ready(Err(_)) => return (drained, true),
+                Poll::Pending => break,
+            }
+        }
+        (drained, false)
+    }
+}
+
+/// Idle connections behind one lock
+type Shard<S> = Mutex<Vec<Connection<S>>>;
+
+/// Fill `buf` from `stream`, `received` bytes into the frame, waiting at most `timeout` for each read
+async fn read_part(stream: &mut (impl AsyncRead + Unpin), buf: &mut [u8], received: usize, timeout: Duration) -> Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let read = tokio::time::timeout(timeout, stream.read(&mut buf[filled..]))
+            .await
+            .map_err(|_| FrameError::TimedOut(timeout))?;
+        match read {
+            Ok(0) => return Err(FrameError::ClosedMidResponse { received: received + filled }.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => {
+                return Err(FrameError::ClosedMidResponse { received: received + filled }.into())
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Connections to one PHP worker, over its Unix socket by default
+pub struct ConnectionPool<T: BridgeTransport = UnixTransport> {
+    config: ConnectionPoolConfig,
+    transport: T,
+    /// Idle connections; locked only to push or pop, never across an await
+    shards: Box<[Shard<T::Stream>]>,
+    /// Shard the next request starts at
+    next_shard: AtomicUsize,
+    /// One permit per connection that may be in use; idle ones hold none
+    slots: Semaphore,
+}
+
+impl ConnectionPool {
+    /// Pool of connections to the Unix socket at `config.socket_path`
+    ///
+    /// With [`Framing::Goridge`] the pool listens on the socket, and its
+    /// connections are the workers that connect to it.
+    pub fn new(config: ConnectionPoolConfig) -> Self {
+        let transport = match config.framing {
+            Framing::LengthPrefixed => UnixTransport::new(config.socket_path.clone()),
+            Framing::Goridge => UnixTransport::listening(config.socket_path.clone()),
+        };
+        Self::with_transport(config, transport)
+    }
+
+    /// Pool settings for the bridge of `app_config`
+    pub fn create_config_from_app_config(app_config: &AppConfig) -> ConnectionPoolConfig {
+        ConnectionPoolConfig::from(&BridgeConfig::from_app_config(app_config))
+    }
+}
+
+impl<T: BridgeTransport> ConnectionPool<T> {
+    /// Pool of connections opened by `transport`
+    pub fn with_transport(config: ConnectionPoolConfig, transport: T) -> Self {
+        metrics().describe(
+            "bridge_connections_closed_total",
+            MetricKind::Counter,
+            "Connections to the PHP worker closed by the pool, by reason",
+        );
+        let slots = Semaphore::new(config.max_connections);
+        let shards = (0..config.shards.clamp(1, config.max_connections.max(1)))
+            .map(|_| Mutex::new(Vec::new()))
+            .collect();
+        Self {
+            config,
+            transport,
+            shards,
+            next_shard: AtomicUsize::new(0),
+            slots,
+        }
+    }
+
+    /// Open connections until `min_connections` are idle
+    pub async fn initialize(&self) -> Result<()> {
+        let wanted = self.config.min_connections.min(self.config.max_connections);
+        let Ok(_slots) = self.slots.acquire_many(wanted as u32).await else {
+            return Ok(());
+        };
+        let mut idle = self.idle_connections();
+        while idle < wanted {
+            let connection = Connection::open(&self.transport, &self.config).await?;
+            self.shard(idle).push(connection);
+            idle += 1;
+        }
+        debug!(worker = %self.transport, connections = idle, "Connection pool filled");
+        Ok(())
+    }
+
+    /// Connections open and not in use
+    pub fn idle_connections(&self) -> usize {
+        (0..self.shards.len()).map(|at| self.shard(at).len()).sum()
+    }
+
+    /// Idle connections of shard `at`, counted round the shards
+    fn shard(&self, at: usize) -> std::sync::MutexGuard<'_, Vec<Connection<T::Stream>>> {
+        self.shards[at % self.shards.len()].lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Send one frame on a pooled connection and wait for the response
+    ///
+    /// Waits for a connection while `max_connections` are in use. A
+    /// response that cannot be read fails with a [`FrameError`]; other
+    /// errors keep the underlying `std::io::Error` in their chain. The
+    /// connection is closed after any error.
+    pub async fn send_http_request(&self, frame: serde_json::Value) -> Result<PhpResponse> {
+        let _slot = self.slots.acquire().await?;
+        let home = self.next_shard.fetch_add(1, Ordering::Relaxed);
+        let mut connection = match self.take_idle(home).await {
+            Some(connection) => connection,
+            None => Connection::open(&self.transport, &self.config).await?,
+        };
+        let response = match connection.exchange(&frame, self.config.framing, self.config.read_timeout).await {
+            Ok(response) => response,
+            Err(e) => {
+                debug!(worker = %self.transport, error = %e, "Worker exchange failed");
+                self.retire(connection, RetireReason::Error).await;
+                return Err(e);
+            }
+        };
+        connection.uses += 1;
+        if self.config.max_uses.is_some_and(|max_uses| connection.uses >= max_uses) {
+            self.retire(connection, RetireReason::Recycled).await;
+        } else {
+            connection.idle_since = Instant::now();
+            self.shard(home).push(connection);
+        }
+        Ok(response)
+    }
+
+    /// A good idle connection, looking in shard `home` first, closing stale ones on the way
+    async fn take_idle(&self, home: usize) -> Option<Connection<T::Stream>> {
+        for at in home..home + self.shards.len() {
+            loop {
+                let Some(mut connection) = self.shard(at).pop() else {
+                    break;
+                };
+                let reason = if self.transport.opens_on_demand()
+                    && connection.idle_since.elapsed() > self.config.health_check_interval
+                {
+                    RetireReason::Idle
+                } else if !self.transport.is_healthy(&mut connection.stream) {
+                    RetireReason::Unhealthy
+                } else {
+                    return Some(connection);
+                };
+                self.retire(connection, reason).await;
+            }
+        }
+        None
+    }
+
+    /// Close `connection` in an orderly way: drain it, say goodbye if enabled, shut it down
+    async fn retire(&self, mut connection: Connection<T::Stream>, reason: RetireReason) {
+        let (unread, closed_by_worker) = connection.drain();
+        let goodbye = self.config.goodbye_frame && reason.between_frames() && unread == 0 && !closed_by_worker;
+        let closing = async {
+            if goodbye {
+                let request = PhpRequest {
+                    id: None,
+                    command: "goodbye".to_string(),
+                    data: None,
+                };
+                match self.config.framing {
+                    Framing::LengthPrefixed => encode_frame(&mut connection.buf, &request)?,
+                    Framing::Goridge => goridge::encode_request(&mut connection.buf, &serde_json::to_value(&request)?)?,
+                }
+                connection.stream.write_all(&connection.buf).await?;
+            }
+            connection.stream.shutdown().await?;
+            anyhow::Ok(())
+        };
+        let closed = tokio::time::timeout(self.config.connection_timeout, closing).await;
+        debug!(
+            worker = %self.transport,
+            reason = reason.as_str(),
+            uses = connection.uses,
+            unread,
+            closed_by_worker,
+            goodbye,
+            clean = matches!(closed, Ok(Ok(()))),
+            "Closed worker connection"
+        );
+        metrics().inc_counter("bridge_connections_closed_total", &[("reason", reason.as_str())]);
+    }
+
+    /// Close the idle connections; those in use are closed when their exchange ends
+    pub async fn close_all(&self) {
+        for at in 0..self.shards.len() {
+            let idle = std::mem::take(&mut *self.shard(at));
+            for connection in idle {
+                self.retire(connection, RetireReason::Shutdown).await;
+            }
+        }
+    }
+}
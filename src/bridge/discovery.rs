@@ -0,0 +1,242 @@
+//! PHP workers discovered as sockets in a directory
+//!
+//! An autoscaler that starts and stops worker processes lets each one
+//! listen on a socket of its own in one directory. With discovery enabled
+//! the bridge sends requests to those workers in turn instead of to the
+//! single `SOCKET_PATH`. The directory is rescanned every `interval`: a new
+//! `*.sock` file becomes a backend once its worker answers a `ping`, and a
+//! backend is removed when its socket file disappears or its worker stops
+//! answering. Removal takes the backend out of routing at once; requests
+//! already on it finish, and its idle connections are closed.
+//!
+//! Every change is logged and counted in
+//! `bridge_backend_changes_total{change}`, the current number of backends
+//! is the `bridge_backends` gauge, and [`Backends::report`] lists them with
+//! the requests each has been given for `/admin/stats`.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use serde_json::json;
+use tracing::{debug, info, warn};
+
+use crate::bridge::connection_pool::{ConnectionPool, ConnectionPoolConfig};
+use crate::bridge::socket_bridge::ping_socket;
+use crate::metrics::{metrics, MetricKind};
+
+/// Discovery settings
+#[derive(Debug, Clone)]
+pub struct DiscoveryConfig {
+    /// Directory the workers' `*.sock` files appear in
+    pub dir: PathBuf,
+    /// How often the directory is rescanned and the backends pinged
+    pub interval: Duration,
+    /// Longest wait for a backend's answer to `ping`
+    pub ping_timeout: Duration,
+}
+
+/// One discovered worker
+pub struct Backend {
+    socket_path: String,
+    pool: Arc<ConnectionPool>,
+    /// Requests routed to this backend
+    requests: AtomicU64,
+}
+
+impl Backend {
+    pub fn socket_path(&self) -> &str {
+        &self.socket_path
+    }
+
+    pub fn pool(&self) -> &Arc<ConnectionPool> {
+        &self.pool
+    }
+}
+
+/// The workers currently found in the discovery directory
+pub struct Backends {
+    config: DiscoveryConfig,
+    /// Settings of each backend's pool; `socket_path` is set per backend
+    pool_config: ConnectionPoolConfig,
+    /// Replaced whole on every change, so routing never waits for a rescan
+    members: RwLock<Arc<Vec<Arc<Backend>>>>,
+    /// Backend the next request goes to, counted round the members
+    next: AtomicUsize,
+}
+
+impl Backends {
+    pub fn new(config: DiscoveryConfig, pool_config: ConnectionPoolConfig) -> Self {
+        metrics().describe("bridge_backends", MetricKind::Gauge, "PHP worker backends found in SOCKET_DISCOVERY_DIR");
+        metrics().describe(
+            "bridge_backend_changes_total",
+            MetricKind::Counter,
+            "PHP worker backends added to or removed from routing",
+        );
+        metrics().set_gauge("bridge_backends", &[], 0.0);
+        Self {
+            config,
+            pool_config,
+            members: RwLock::new(Arc::new(Vec::new())),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.config.dir
+    }
+
+    fn members(&self) -> Arc<Vec<Arc<Backend>>> {
+        self.members.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Backends in routing right now
+    pub fn len(&self) -> usize {
+        self.members().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The backend for the next request, taking them in turn; `None` while there are none
+    pub fn pick(&self) -> Option<Arc<Backend>> {
+        let members = self.members();
+        if members.is_empty() {
+            return None;
+        }
+        let backend = members[self.next.fetch_add(1, Ordering::Relaxed) % members.len()].clone();
+        backend.requests.fetch_add(1, Ordering::Relaxed);
+        Some(backend)
+    }
+
+    /// Each backend's socket and requests, for `/admin/stats`
+    pub fn report(&self) -> serde_json::Value {
+        let members = self.members();
+        json!({
+            "dir": self.config.dir.display().to_string(),
+            "backends": members
+                .iter()
+                .map(|backend| json!({
+                    "socket_path": backend.socket_path,
+                    "requests": backend.requests.load(Ordering::Relaxed),
+                }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Bring the backends in line with the directory
+    ///
+    /// Sockets that appeared are added once their worker answers a ping;
+    /// backends whose socket is gone or whose worker no longer answers are
+    /// removed. A directory that cannot be read leaves the backends as they are.
+    pub async fn rescan(&self) {
+        let found = match socket_files(&self.config.dir) {
+            Ok(found) => found,
+            Err(e) => {
+                warn!(dir = %self.config.dir.display(), error = %e, "Cannot read the socket discovery directory");
+                return;
+            }
+        };
+
+        let members = self.members();
+        let known: HashSet<&str> = members.iter().map(|backend| backend.socket_path.as_str()).collect();
+        let mut removed = Vec::new();
+        for backend in members.iter() {
+            if !found.contains(&backend.socket_path) {
+                removed.push((backend.clone(), "socket_removed"));
+            } else if self.ping(&backend.socket_path).await.is_err() {
+                removed.push((backend.clone(), "unhealthy"));
+            }
+        }
+        let mut added = Vec::new();
+        for socket_path in found.iter().filter(|path| !known.contains(path.as_str())) {
+            match self.ping(socket_path).await {
+                Ok(()) => added.push(socket_path.clone()),
+                Err(e) => debug!(socket_path = %socket_path, error = %e, "Discovered socket does not answer yet"),
+            }
+        }
+        if added.is_empty() && removed.is_empty() {
+            return;
+        }
+
+        self.update(&added, &removed);
+        for socket_path in &added {
+            metrics().inc_counter("bridge_backend_changes_total", &[("change", "added")]);
+            info!(socket_path = %socket_path, backends = self.len(), "➕ PHP worker backend added");
+        }
+        for (backend, reason) in &removed {
+            metrics().inc_counter("bridge_backend_changes_total", &[("change", "removed")]);
+            warn!(socket_path = %backend.socket_path, reason, backends = self.len(), "➖ PHP worker backend removed");
+            backend.pool.close_all().await;
+        }
+    }
+
+    /// Swap in the member list without `removed` and with backends for `added`
+    fn update(&self, added: &[String], removed: &[(Arc<Backend>, &str)]) {
+        let mut members = self.members.write().unwrap_or_else(|e| e.into_inner());
+        let mut kept: Vec<_> = members
+            .iter()
+            .filter(|backend| !removed.iter().any(|(gone, _)| Arc::ptr_eq(gone, backend)))
+            .cloned()
+            .collect();
+        kept.extend(added.iter().map(|socket_path| {
+            Arc::new(Backend {
+                socket_path: socket_path.clone(),
+                pool: Arc::new(ConnectionPool::new(self.pool_config_for(socket_path))),
+                requests: AtomicU64::new(0),
+            })
+        }));
+        metrics().set_gauge("bridge_backends", &[], kept.len() as f64);
+        *members = Arc::new(kept);
+    }
+
+    fn pool_config_for(&self, socket_path: &str) -> ConnectionPoolConfig {
+        ConnectionPoolConfig {
+            socket_path: socket_path.to_string(),
+            ..self.pool_config.clone()
+        }
+    }
+
+    async fn ping(&self, socket_path: &str) -> anyhow::Result<()> {
+        ping_socket(self.pool_config_for(socket_path), self.config.ping_timeout).await
+    }
+
+    /// Rescan every `interval` for as long as the backends are in use
+    pub fn spawn_watcher(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let backends = Arc::downgrade(self);
+        let interval = self.config.interval;
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let Some(backends) = backends.upgrade() else {
+                    break;
+                };
+                backends.rescan().await;
+            }
+        })
+    }
+
+    /// Close the idle connections of every backend
+    pub async fn close_all(&self) {
+        for backend in self.members().iter() {
+            backend.pool.close_all().await;
+        }
+    }
+}
+
+/// Paths of the `*.sock` files in `dir`, sorted
+fn socket_files(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut found: Vec<String> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "sock"))
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    found.sort();
+    Ok(found)
+}
@@ -0,0 +1,177 @@
+//! RoadRunner's goridge frames, for `WORKER_PROTOCOL=psr7`
+//!
+//! Every frame starts with a 12-byte header:
+//!
+//! | bytes | content |
+//! |-------|---------|
+//! | 0     | protocol version (1) in the high nibble, header length in 32-bit words in the low one |
+//! | 1     | flags: the payload's codec, [`CONTROL`], [`ERROR`] |
+//! | 2..6  | payload length, little-endian |
+//! | 6..10 | CRC32 (IEEE) of bytes 0..6, little-endian |
+//! | 10    | stream flags: [`STREAM`] while more frames of the response follow |
+//! | 11    | reserved |
+//!
+//! The header's options follow it, one little-endian `u32` each, and then
+//! the payload. An HTTP request or response frame has one option, the
+//! length of the JSON context at the start of the payload; the rest of the
+//! payload is the body. Control frames carry JSON: the worker answers
+//! `{"pid": true}` with its process id, and leaves on `{"stop": true}`.
+//! A frame flagged [`ERROR`] carries the worker's error message.
+//!
+//! Frames are written from, and read into, the values the psr7 codec
+//! exchanges with the pool: `{"context": {...}, "body": "..."}`, with
+//! `"body_encoding": "base64"` for a body that is not UTF-8.
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::bridge::connection_pool::FrameError;
+use crate::bridge::PhpResponse;
+
+/// Bytes of a frame header without options
+pub const HEADER_LEN: usize = 12;
+/// Header length, in 32-bit words, of a frame without options
+const HEADER_WORDS: u8 = 3;
+const VERSION: u8 = 1;
+
+/// Flag: a control frame, not a request or response
+pub const CONTROL: u8 = 0x01;
+/// Flag: the payload is raw bytes
+pub const CODEC_RAW: u8 = 0x04;
+/// Flag: the payload's context is JSON
+pub const CODEC_JSON: u8 = 0x08;
+/// Flag: the payload is an error message
+pub const ERROR: u8 = 0x40;
+/// Stream flag: more frames of the same response follow
+pub const STREAM: u8 = 0x01;
+
+/// What a frame header says about the rest of the frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub flags: u8,
+    pub stream: u8,
+    /// Bytes of options between the header and the payload
+    pub options_len: usize,
+    pub payload_len: usize,
+}
+
+/// Append a frame with `flags`, `options` and the concatenated `payload` to `buf`
+pub fn write_frame(buf: &mut Vec<u8>, flags: u8, options: &[u32], payload: &[&[u8]]) -> Result<()> {
+    let words = HEADER_WORDS as usize + options.len();
+    if words > 0x0f {
+        bail!("{} goridge options do not fit a header", options.len());
+    }
+    let len: usize = payload.iter().map(|part| part.len()).sum();
+    let len = u32::try_from(len).context("frame longer than 4 GiB")?;
+
+    let start = buf.len();
+    buf.push(VERSION << 4 | words as u8);
+    buf.push(flags);
+    buf.extend_from_slice(&len.to_le_bytes());
+    let crc = crc32fast::hash(&buf[start..start + 6]);
+    buf.extend_from_slice(&crc.to_le_bytes());
+    buf.extend_from_slice(&[0, 0]);
+    for option in options {
+        buf.extend_from_slice(&option.to_le_bytes());
+    }
+    for part in payload {
+        buf.extend_from_slice(part);
+    }
+    Ok(())
+}
+
+/// Check a frame header and read its lengths
+pub fn parse_header(header: &[u8; HEADER_LEN]) -> Result<Header, FrameError> {
+    if header[0] >> 4 != VERSION {
+        return Err(FrameError::Corrupt(format!("goridge version {} instead of {}", header[0] >> 4, VERSION)));
+    }
+    let words = header[0] & 0x0f;
+    if words < HEADER_WORDS {
+        return Err(FrameError::Corrupt(format!("goridge header of {} words", words)));
+    }
+    let crc = u32::from_le_bytes([header[6], header[7], header[8], header[9]]);
+    if crc != crc32fast::hash(&header[..6]) {
+        return Err(FrameError::Corrupt("goridge header checksum does not match".to_string()));
+    }
+    Ok(Header {
+        flags: header[1],
+        stream: header[10],
+        options_len: (words - HEADER_WORDS) as usize * 4,
+        payload_len: u32::from_le_bytes([header[2], header[3], header[4], header[5]]) as usize,
+    })
+}
+
+/// Options of a frame, from the bytes after its header
+pub fn parse_options(bytes: &[u8]) -> Vec<u32> {
+    bytes.chunks_exact(4).map(|option| u32::from_le_bytes([option[0], option[1], option[2], option[3]])).collect()
+}
+
+/// Write the frame for an HTTP request value of the psr7 codec, or a command frame
+///
+/// `ping` becomes the `{"pid": true}` control frame and `goodbye` the
+/// `{"stop": true}` one; other commands have no goridge equivalent.
+pub fn encode_request(buf: &mut Vec<u8>, frame: &Value) -> Result<()> {
+    buf.clear();
+    if let Some(command) = frame.get("command").and_then(Value::as_str) {
+        let control = match command {
+            "ping" => json!({"pid": true}),
+            "goodbye" => json!({"stop": true}),
+            other => bail!("the {} command has no RoadRunner control frame", other),
+        };
+        return write_frame(buf, CONTROL | CODEC_JSON, &[], &[&serde_json::to_vec(&control)?]);
+    }
+
+    let context = serde_json::to_vec(&frame["context"]).context("cannot serialize the request context")?;
+    let body = body_bytes(frame)?;
+    let context_len = u32::try_from(context.len()).context("request context longer than 4 GiB")?;
+    write_frame(buf, CODEC_JSON, &[context_len], &[&context, &body])
+}
+
+/// The response for the frames of one answer
+///
+/// `flags` and `options` are those of the first frame; `payload` is the
+/// payload of every frame of a streamed answer, one after the other, with
+/// the context only at the start.
+pub fn decode_response(flags: u8, options: &[u32], mut payload: Vec<u8>) -> Result<PhpResponse, FrameError> {
+    if flags & ERROR != 0 {
+        return Ok(PhpResponse::new_error(None, String::from_utf8_lossy(&payload).into_owned()));
+    }
+    if flags & CONTROL != 0 {
+        let data = serde_json::from_slice(&payload).map_err(FrameError::Invalid)?;
+        return Ok(PhpResponse::new_success(None, Some(data)));
+    }
+
+    let context_len = options.first().map_or(0, |&len| len as usize);
+    if context_len > payload.len() {
+        return Err(FrameError::Corrupt(format!(
+            "context of {} bytes in a payload of {}",
+            context_len,
+            payload.len()
+        )));
+    }
+    let context: Value = match context_len {
+        0 => Value::Null,
+        _ => serde_json::from_slice(&payload[..context_len]).map_err(FrameError::Invalid)?,
+    };
+    let mut data = json!({ "context": context });
+    let body = payload.split_off(context_len);
+    match String::from_utf8(body) {
+        Ok(text) => data["body"] = text.into(),
+        Err(e) => {
+            data["body"] = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, e.as_bytes()).into();
+            data["body_encoding"] = "base64".into();
+        }
+    }
+    Ok(PhpResponse::new_success(None, Some(data)))
+}
+
+/// Bytes of the `body` of a psr7 request value
+fn body_bytes(frame: &Value) -> Result<Vec<u8>> {
+    let body = frame["body"].as_str().unwrap_or_default();
+    match frame["body_encoding"].as_str() {
+        None => Ok(body.as_bytes().to_vec()),
+        Some("base64") => base64::Engine::decode(&base64::engine::general_purpose::STANDARD, body)
+            .context("request body is not valid base64"),
+        Some(other) => bail!("unknown body_encoding {:?}", other),
+    }
+}
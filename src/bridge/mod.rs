@@ -1,8 +1,13 @@
 use serde::{Deserialize, Serialize};
 
+pub mod adaptive_limit;
+pub mod discovery;
+pub mod goridge;
 pub mod socket_bridge;
 pub mod connection_pool;
+pub mod restart_park;
 pub mod retry;
+pub mod transport;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PhpResponse {
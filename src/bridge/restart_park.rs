@@ -0,0 +1,215 @@
+//! Parking of requests while the PHP worker restarts
+//!
+//! A planned worker restart leaves the socket down for a second or two,
+//! and every request arriving in that gap used to fail with a 503. When the
+//! bridge is told the backend is restarting (a supervisor restart or a
+//! blue/green socket swap), requests are instead held in a queue until the
+//! socket accepts connections again, and then let through in the order they
+//! arrived. If the backend is not back within the park timeout, the parked
+//! requests fail with the usual 503.
+//!
+//! The queue is bounded by the number of requests and by the total size of
+//! their frames; requests beyond either bound are not parked and fail as
+//! before. So are requests whose deadline leaves less than `min_remaining`,
+//! and a parked request is released with at least that much left.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+use crate::errors::{ServerError, UnavailableReason};
+use crate::metrics::{metrics, MetricKind};
+
+/// Parking settings
+#[derive(Debug, Clone)]
+pub struct RestartParkConfig {
+    /// Longest a request is held, and a restart waited for
+    pub timeout: Duration,
+    /// Requests held at once
+    pub max_requests: usize,
+    /// Total frame bytes held at once
+    pub max_body_bytes: usize,
+    /// Requests with less of their deadline left are not parked
+    pub min_remaining: Duration,
+}
+
+/// A restart in progress
+#[derive(Debug)]
+struct Restart {
+    /// Tells a watcher of an earlier restart that it is no longer current
+    generation: u64,
+    cause: &'static str,
+    since: Instant,
+}
+
+/// A parked request, woken when the backend is back
+#[derive(Debug)]
+struct Waiter {
+    id: u64,
+    bytes: usize,
+    wake: oneshot::Sender<()>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    restart: Option<Restart>,
+    queue: VecDeque<Waiter>,
+    /// Frame bytes of the requests in `queue`
+    bytes: usize,
+    next_id: u64,
+}
+
+/// Queue of requests waiting for a restarting backend
+#[derive(Debug)]
+pub struct RestartPark {
+    config: RestartParkConfig,
+    state: Mutex<State>,
+}
+
+/// Removes a request from the queue when it stops waiting for any reason
+struct Parked<'a> {
+    park: &'a RestartPark,
+    id: u64,
+}
+
+impl Drop for Parked<'_> {
+    fn drop(&mut self) {
+        let mut state = self.park.lock();
+        if let Some(at) = state.queue.iter().position(|waiter| waiter.id == self.id) {
+            let waiter = state.queue.remove(at).expect("position is in the queue");
+            state.bytes -= waiter.bytes;
+            report_depth(&state);
+        }
+    }
+}
+
+impl RestartPark {
+    pub fn new(config: RestartParkConfig) -> Self {
+        metrics().describe("bridge_parked_requests", MetricKind::Gauge, "Requests parked while the PHP worker restarts");
+        metrics().describe(
+            "bridge_parked_requests_total",
+            MetricKind::Counter,
+            "Requests that arrived while the PHP worker was restarting, by outcome",
+        );
+        metrics().describe(
+            "bridge_park_duration_seconds",
+            MetricKind::Summary,
+            "Time requests spent parked while the PHP worker restarted, by outcome",
+        );
+        Self {
+            config,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub fn config(&self) -> &RestartParkConfig {
+        &self.config
+    }
+
+    /// Start holding requests; returns the restart's generation for [`end`](Self::end)
+    ///
+    /// A restart that is already in progress is taken over by the new one.
+    pub fn begin(&self, cause: &'static str) -> u64 {
+        let mut state = self.lock();
+        state.next_id += 1;
+        let generation = state.next_id;
+        state.restart = Some(Restart {
+            generation,
+            cause,
+            since: Instant::now(),
+        });
+        info!(cause, timeout_ms = self.config.timeout.as_millis() as u64, "PHP worker restarting, parking new requests");
+        generation
+    }
+
+    /// End restart `generation`, releasing the parked requests in order if
+    /// the backend is `back`, or failing them otherwise
+    pub fn end(&self, generation: u64, back: bool) {
+        let mut state = self.lock();
+        let Some(restart) = state.restart.take_if(|restart| restart.generation == generation) else {
+            return;
+        };
+        let parked = state.queue.len();
+        state.bytes = 0;
+        let queue = std::mem::take(&mut state.queue);
+        report_depth(&state);
+        drop(state);
+
+        let waited_ms = restart.since.elapsed().as_millis() as u64;
+        if back {
+            info!(cause = restart.cause, waited_ms, parked, "PHP worker back, releasing parked requests");
+            for waiter in queue {
+                let _ = waiter.wake.send(());
+            }
+        } else {
+            // Dropping the senders fails the waiters
+            warn!(cause = restart.cause, waited_ms, parked, "PHP worker not back in time, failing parked requests");
+        }
+    }
+
+    /// Wait for a restarting backend, if there is one
+    ///
+    /// `bytes` gives the size of the request's frame and is only called
+    /// while a restart is in progress. `remaining` is the time left of its
+    /// deadline. Returns how long the request was parked; requests that
+    /// cannot be parked return at once and go on to fail as they would
+    /// have. A request the backend did not come back for in time gets a 503.
+    pub async fn wait(&self, bytes: impl FnOnce() -> usize, remaining: Duration) -> Result<Duration, ServerError> {
+        let started = Instant::now();
+        let (parked, woken) = {
+            let mut state = self.lock();
+            if state.restart.is_none() {
+                return Ok(Duration::ZERO);
+            }
+            let bytes = bytes();
+            let skipped = if remaining <= self.config.min_remaining {
+                Some("deadline")
+            } else if state.queue.len() >= self.config.max_requests || state.bytes + bytes > self.config.max_body_bytes {
+                Some("overflow")
+            } else {
+                None
+            };
+            if let Some(outcome) = skipped {
+                metrics().inc_counter("bridge_parked_requests_total", &[("outcome", outcome)]);
+                return Ok(Duration::ZERO);
+            }
+
+            state.next_id += 1;
+            let id = state.next_id;
+            let (wake, woken) = oneshot::channel();
+            state.queue.push_back(Waiter { id, bytes, wake });
+            state.bytes += bytes;
+            report_depth(&state);
+            (Parked { park: self, id }, woken)
+        };
+
+        let limit = self.config.timeout.min(remaining.saturating_sub(self.config.min_remaining));
+        let released = matches!(tokio::time::timeout(limit, woken).await, Ok(Ok(())));
+        drop(parked);
+
+        let waited = started.elapsed();
+        let outcome = if released { "released" } else { "failed" };
+        metrics().inc_counter("bridge_parked_requests_total", &[("outcome", outcome)]);
+        metrics().observe("bridge_park_duration_seconds", &[("outcome", outcome)], waited.as_secs_f64());
+        if released {
+            Ok(waited)
+        } else {
+            Err(ServerError::Unavailable {
+                reason: UnavailableReason::BridgeDown,
+                retry_after: None,
+                message: format!("PHP worker still restarting after {} ms", waited.as_millis()),
+            })
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn report_depth(state: &State) {
+    metrics().set_gauge("bridge_parked_requests", &[], state.queue.len() as f64);
+}
@@ -0,0 +1,67 @@
+//! Retries with exponential backoff
+//!
+//! Used for work that may fail while the PHP worker is still starting, such
+//! as filling the connection pool. The delay doubles after every failed
+//! attempt, from `base_delay` up to `max_delay`.
+
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::{debug, warn};
+
+/// How often and how patiently an operation is retried
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// Attempts in total, the first one included
+    pub max_attempts: u32,
+    /// Delay after the first failure
+    pub base_delay: Duration,
+    /// Longest delay between two attempts
+    pub max_delay: Duration,
+}
+
+impl RetryConfig {
+    /// Delay before attempt `attempt + 1`, counting attempts from 1
+    pub fn delay_after(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+/// Run `operation` until it succeeds or `config.max_attempts` attempts failed
+///
+/// Returns the error of the last attempt. `name` identifies the operation in
+/// the logs.
+pub async fn retry_with_backoff<T, F, Fut>(config: &RetryConfig, name: &str, mut operation: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let max_attempts = config.max_attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Ok(value) => {
+                if attempt > 1 {
+                    debug!(operation = name, attempt, "Succeeded after retrying");
+                }
+                return Ok(value);
+            }
+            Err(e) if attempt >= max_attempts => return Err(e),
+            Err(e) => {
+                let delay = config.delay_after(attempt);
+                warn!(
+                    operation = name,
+                    attempt,
+                    max_attempts,
+                    delay_ms = delay.as_millis() as u64,
+                    error = %e,
+                    "Attempt failed, retrying"
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        }
+    }
+}
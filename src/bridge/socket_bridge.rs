@@ -1,20 +1,99 @@
 use anyhow::Result;
-use crate::bridge::connection_pool::{ConnectionPool, ConnectionPoolConfig};
+use crate::bridge::adaptive_limit::{AdaptiveLimitConfig, AdaptiveLimiter, Outcome};
+use crate::bridge::connection_pool::{ConnectionPool, ConnectionPoolConfig, FrameError, Framing};
+use crate::bridge::discovery::{Backends, DiscoveryConfig};
+use crate::worker_protocol::{self, WorkerCodec, WorkerProtocol};
+use crate::bridge::restart_park::{RestartPark, RestartParkConfig};
 use crate::bridge::retry::{RetryConfig, retry_with_backoff};
 use crate::bridge::PhpResponse;
+use crate::bridge_config::BridgeConfig;
+use crate::deadline::{Deadline, DEADLINE_SERVER_VAR};
+use crate::errors::{ServerError, UnavailableReason};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::Path;
-use std::sync::Arc;
-use tokio::sync::Mutex as AsyncMutex;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, Semaphore};
+use tracing::{error, info, warn};
+
+use crate::metrics::{metrics, MetricKind};
 
 #[derive(Debug)]
 pub struct SocketBridgeConfig {
     pub socket_path: String,
+    /// How often the socket path is re-resolved to detect a flipped symlink (None disables)
+    pub swap_watch_interval: Option<Duration>,
+    /// Maximum time to wait for the worker's response to one frame
+    pub read_timeout: Duration,
+    /// Frames in flight to the worker at once
+    pub max_concurrent_frames: usize,
+    /// Largest request frame sent to the worker, in bytes
+    pub max_frame_size: usize,
+    /// Frames reserved for health checks, outside `max_concurrent_frames` and the adaptive limit
+    pub priority_frames: usize,
+    /// Latency-driven limit of HTTP requests in flight
+    pub adaptive_limit: Option<AdaptiveLimitConfig>,
+    /// Holding of requests while the worker restarts
+    pub restart_park: Option<RestartParkConfig>,
+    /// Workers found in a socket directory, used instead of `socket_path`
+    pub discovery: Option<DiscoveryConfig>,
+    /// Shape of the request and response frames the worker speaks
+    pub protocol: WorkerProtocol,
+}
+
+impl From<&BridgeConfig> for SocketBridgeConfig {
+    fn from(config: &BridgeConfig) -> Self {
+        Self {
+            socket_path: config.socket_path.clone(),
+            swap_watch_interval: config.swap_watch_interval,
+            read_timeout: config.read_timeout,
+            max_concurrent_frames: config.max_concurrent_frames.max(1),
+            max_frame_size: config.max_frame_size,
+            priority_frames: config.priority_frames.max(1),
+            adaptive_limit: config.adaptive_limit.clone(),
+            restart_park: config.restart_park.clone(),
+            discovery: config.discovery.clone(),
+            protocol: config.protocol,
+        }
+    }
+}
+
+fn log_bridge_config(config: &BridgeConfig) {
+    info!(
+        socket_path = %config.socket_path,
+        pool_min = config.pool_min,
+        pool_max = config.pool_max,
+        connect_timeout = ?config.connect_timeout,
+        read_timeout = ?config.read_timeout,
+        max_concurrent_frames = config.max_concurrent_frames,
+        max_frame_size = config.max_frame_size,
+        priority_frames = config.priority_frames,
+        adaptive_limit = config.adaptive_limit.is_some(),
+        restart_park = config.restart_park.is_some(),
+        protocol = config.protocol.codec().name(),
+        discovery_dir = ?config.discovery.as_ref().map(|discovery| &discovery.dir),
+        "Bridge configured"
+    );
+}
+
+fn retry_config(config: &BridgeConfig) -> RetryConfig {
+    RetryConfig {
+        max_attempts: config.retry_max_attempts,
+        base_delay: config.retry_base_delay,
+        max_delay: config.retry_max_delay,
+    }
 }
 
 // SocketBridgeConfig теперь используется только как структура для хранения пути к сокету
 
+/// Счетчик для идентификаторов команд
+static COMMAND_ID: AtomicU64 = AtomicU64::new(1);
+
+/// How often a restarting worker's socket is tried while requests are parked
+const RESTART_PROBE_INTERVAL: Duration = Duration::from_millis(50);
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PhpRequest {
     pub id: Option<String>,
@@ -22,10 +101,65 @@ pub struct PhpRequest {
     pub data: Option<HashMap<String, serde_json::Value>>,
 }
 
+/// Serialized [`PhpRequest`] for a command, with a fresh id
+fn command_frame(command: &str, data: Option<HashMap<String, serde_json::Value>>) -> Result<serde_json::Value> {
+    let request = PhpRequest {
+        id: Some(format!("cmd-{}", COMMAND_ID.fetch_add(1, Ordering::Relaxed))),
+        command: command.to_string(),
+        data,
+    };
+    Ok(serde_json::to_value(&request)?)
+}
+
 pub struct SocketBridge {
     config: SocketBridgeConfig,
-    connection_pool: Arc<ConnectionPool>,
-    cleanup_on_drop: Arc<AsyncMutex<()>>,
+    /// Socket path new connections are opened against; changes on a blue/green swap
+    current_socket_path: RwLock<String>,
+    pool_config: Mutex<ConnectionPoolConfig>,
+    /// Active pool; requests clone the Arc so a swap never interrupts them
+    connection_pool: RwLock<Arc<ConnectionPool>>,
+    /// Last resolved target of the socket path, used to detect symlink flips
+    resolved_target: Mutex<Option<PathBuf>>,
+    /// Limits frames in flight to `max_concurrent_frames`
+    frame_permits: Semaphore,
+    /// Limits health check frames in flight to `priority_frames`
+    priority_permits: Semaphore,
+    /// Sheds HTTP requests above the adaptive limit, when enabled
+    limiter: Option<Arc<AdaptiveLimiter>>,
+    /// Holds HTTP requests while the worker restarts, when enabled
+    park: Option<RestartPark>,
+    /// Workers requests are spread over, when `SOCKET_DISCOVERY_DIR` is set
+    backends: Option<Arc<Backends>>,
+    /// Whether dropping the bridge removes the socket file
+    owns_socket_file: AtomicBool,
+    /// Frames sent and not yet answered
+    in_flight: AtomicUsize,
+    /// Reference point of `last_progress_ms`
+    created: Instant,
+    /// When the worker last answered a frame, or frames started after an idle spell
+    last_progress_ms: AtomicU64,
+    /// Wakes frames in flight to fail them, when the worker is found stalled
+    stalled: Notify,
+}
+
+/// Files a codec wrote for a request frame, removed when the exchange ends however it ends
+struct ScratchDir(PathBuf);
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.0) {
+            warn!(path = %self.0.display(), error = %e, "Cannot remove the files written for a request");
+        }
+    }
+}
+
+/// Counts a frame as in flight until dropped
+struct InFlight<'a>(&'a SocketBridge);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
 }
 
 impl SocketBridge {
@@ -34,35 +168,30 @@ impl SocketBridge {
         // Load environment variables
         dotenvy::dotenv().ok();
 
-        // Get socket path from environment variables, using default path as fallback
-        let socket_path = std::env::var("SOCKET_PATH").unwrap_or_else(|_| "/tmp/rust_php_bridge.sock".to_string());
-
-        let config = SocketBridgeConfig { socket_path };
+        let bridge_config = BridgeConfig::from_env();
+        let config = SocketBridgeConfig::from(&bridge_config);
+        log_bridge_config(&bridge_config);
 
         // Create connection pool with configuration from environment
-        let pool_config = ConnectionPoolConfig::from_env();
-        let connection_pool = Arc::new(ConnectionPool::new(pool_config));
+        let mut pool_config = ConnectionPoolConfig::from_env();
+        pool_config.socket_path = bridge_config.socket_path.clone();
 
         // Initialize the pool with minimum connections
-        let bridge = Arc::new(Self {
-            config,
-            connection_pool,
-            cleanup_on_drop: Arc::new(AsyncMutex::new(())),
-        });
+        let bridge = Self::from_parts(config, pool_config);
 
         // Initialize the pool with minimum connections in a background task
         // This ensures connections are pre-established but doesn't block the creation
         let bridge_clone = bridge.clone();
+        let retry_config = retry_config(&bridge_config);
         tokio::spawn(async move {
-            let retry_config = crate::bridge::retry::RetryConfig::from_env();
             if let Err(e) = retry_with_backoff(
                 &retry_config,
                 "initialize_connection_pool",
                 || async {
-                    bridge_clone.connection_pool.initialize().await
+                    bridge_clone.pool().initialize().await
                 }
             ).await {
-                eprintln!("Failed to initialize connection pool after all retry attempts: {}", e);
+                error!(error = %e, "Failed to initialize connection pool after all retry attempts");
                 // Still continue even if initialization failed, as connections can be created on-demand
             }
         });
@@ -72,46 +201,123 @@ impl SocketBridge {
 
     #[allow(dead_code)]
     pub fn new_with_config(app_config: &crate::config::AppConfig) -> Result<Arc<Self>> {
-        let config = SocketBridgeConfig {
-            socket_path: app_config.connection.socket_path.clone()
-        };
+        Self::for_socket(app_config, &app_config.connection.socket_path, WorkerProtocol::from_env())
+    }
+
+    /// Bridge to the worker at `socket_path` speaking `protocol`, otherwise configured from `app_config`
+    pub fn for_socket(
+        app_config: &crate::config::AppConfig,
+        socket_path: &str,
+        protocol: WorkerProtocol,
+    ) -> Result<Arc<Self>> {
+        let mut bridge_config = BridgeConfig::from_app_config(app_config);
+        bridge_config.socket_path = socket_path.to_string();
+        bridge_config.protocol = protocol;
+        let config = SocketBridgeConfig::from(&bridge_config);
+        log_bridge_config(&bridge_config);
 
         // Create connection pool with configuration from app config
-        let pool_config = ConnectionPool::create_config_from_app_config(app_config);
-        let connection_pool = Arc::new(ConnectionPool::new(pool_config));
+        let mut pool_config = ConnectionPool::create_config_from_app_config(app_config);
+        pool_config.socket_path = socket_path.to_string();
+        pool_config.framing = protocol.codec().framing();
 
         // Initialize the pool with minimum connections
-        let bridge = Arc::new(Self {
-            config,
-            connection_pool,
-            cleanup_on_drop: Arc::new(AsyncMutex::new(())),
-        });
+        let bridge = Self::from_parts(config, pool_config);
 
         // Initialize the pool with minimum connections in a background task
         // This ensures connections are pre-established but doesn't block the creation
         let bridge_clone = bridge.clone();
-        let retry_config = crate::bridge::retry::RetryConfig {
-            max_attempts: app_config.retry.max_attempts,
-            base_delay: app_config.retry.base_delay,
-            max_delay: app_config.retry.max_delay,
-        };
+        let retry_config = retry_config(&bridge_config);
         
         // Spawn initialization task to ensure connections are ready before the server starts handling requests
+        // (discovered backends fill their pools on demand)
+        if bridge.backends.is_some() {
+            return Ok(bridge);
+        }
         tokio::spawn(async move {
             if let Err(e) = retry_with_backoff(
                 &retry_config,
                 "initialize_connection_pool",
                 || async {
-                    bridge_clone.connection_pool.initialize().await
+                    bridge_clone.pool().initialize().await
                 }
             ).await {
-                eprintln!("Failed to initialize connection pool after all retry attempts: {}", e);
+                error!(error = %e, "Failed to initialize connection pool after all retry attempts");
                 // Still continue even if initialization failed, as connections can be created on-demand
             }
         });
 
         Ok(bridge)
     }
+
+    fn from_parts(config: SocketBridgeConfig, pool_config: ConnectionPoolConfig) -> Arc<Self> {
+        metrics().describe(
+            "bridge_socket_swaps_total",
+            MetricKind::Counter,
+            "Blue/green switches of the PHP worker socket",
+        );
+
+        let connection_pool = Arc::new(ConnectionPool::new(pool_config.clone()));
+        Arc::new(Self {
+            current_socket_path: RwLock::new(config.socket_path.clone()),
+            frame_permits: Semaphore::new(config.max_concurrent_frames),
+            priority_permits: Semaphore::new(config.priority_frames),
+            limiter: config.adaptive_limit.clone().map(AdaptiveLimiter::new),
+            park: config.restart_park.clone().map(RestartPark::new),
+            backends: config.discovery.clone().map(|discovery| Arc::new(Backends::new(discovery, pool_config.clone()))),
+            owns_socket_file: AtomicBool::new(true),
+            config,
+            pool_config: Mutex::new(pool_config),
+            connection_pool: RwLock::new(connection_pool),
+            resolved_target: Mutex::new(None),
+            in_flight: AtomicUsize::new(0),
+            created: Instant::now(),
+            last_progress_ms: AtomicU64::new(0),
+            stalled: Notify::new(),
+        })
+    }
+
+    /// Currently active connection pool
+    fn pool(&self) -> Arc<ConnectionPool> {
+        self.connection_pool.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Pool the next frame goes to: the next discovered backend's, or the active pool
+    fn route(&self) -> Result<Arc<ConnectionPool>> {
+        let Some(backends) = &self.backends else {
+            return Ok(self.pool());
+        };
+        match backends.pick() {
+            Some(backend) => Ok(backend.pool().clone()),
+            None => Err(ServerError::bridge_down(format!(
+                "no PHP worker answering in {}",
+                backends.dir().display()
+            ))
+            .into()),
+        }
+    }
+
+    /// Discovered backends, when `SOCKET_DISCOVERY_DIR` is set
+    pub fn backends(&self) -> Option<&Arc<Backends>> {
+        self.backends.as_ref()
+    }
+
+    /// Wait until a backend has been discovered; returns at once without discovery
+    pub async fn wait_for_backend(&self, poll_interval: Duration) {
+        while self.backends.as_ref().is_some_and(|backends| backends.is_empty()) {
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+
+    /// Rescan the discovery directory every `SOCKET_DISCOVERY_INTERVAL_MS`
+    pub fn spawn_discovery(&self) -> Option<tokio::task::JoinHandle<()>> {
+        self.backends.as_ref().map(|backends| backends.spawn_watcher())
+    }
+
+    /// Socket path new connections are currently opened against
+    pub fn socket_path(&self) -> String {
+        self.current_socket_path.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
     
     
     #[allow(dead_code)]
@@ -119,23 +325,444 @@ impl SocketBridge {
         &self,
         http_request_data: serde_json::Value,
     ) -> Result<PhpResponse> {
-        self.connection_pool.send_http_request(http_request_data).await
+        self.send_request_frame(http_request_data, self.config.read_timeout).await
+    }
+
+    /// Send an HTTP request frame, giving up after `timeout` instead of `read_timeout`
+    pub async fn send_http_request_within(
+        &self,
+        http_request_data: serde_json::Value,
+        timeout: Duration,
+    ) -> Result<PhpResponse> {
+        self.send_request_frame(http_request_data, timeout).await
+    }
+
+    /// Codec of `WORKER_PROTOCOL`, for the worker's HTTP request and response frames
+    pub fn codec(&self) -> &'static dyn WorkerCodec {
+        self.config.protocol.codec()
+    }
+
+    /// Maximum time to wait for the worker's response to one frame
+    pub fn read_timeout(&self) -> Duration {
+        self.config.read_timeout
+    }
+
+    /// Largest request frame sent to the worker, in bytes
+    pub fn max_frame_size(&self) -> usize {
+        self.config.max_frame_size
+    }
+
+    /// Adaptive concurrency limiter, when `ADAPTIVE_CONCURRENCY` is enabled
+    pub fn limiter(&self) -> Option<&Arc<AdaptiveLimiter>> {
+        self.limiter.as_ref()
+    }
+
+    /// Send an HTTP request or client command frame through the adaptive limiter
+    ///
+    /// Above the current limit the request is shed with a 503 instead of
+    /// queueing; otherwise its latency and outcome feed the limit. The
+    /// server's own commands bypass the limiter so shutdown notifications are
+    /// never shed.
+    async fn send_request_frame(&self, mut frame: serde_json::Value, timeout: Duration) -> Result<PhpResponse> {
+        // Requests arriving while the worker restarts wait for it instead of failing
+        let mut timeout = timeout;
+        if let Some(park) = &self.park {
+            let parked = park.wait(|| serialized_len(&frame), timeout).await?;
+            if !parked.is_zero() {
+                timeout = timeout.checked_sub(parked).filter(|left| !left.is_zero()).ok_or_else(|| ServerError::Unavailable {
+                    reason: UnavailableReason::BridgeDown,
+                    retry_after: None,
+                    message: format!("no time left after {} ms waiting for the PHP worker to restart", parked.as_millis()),
+                })?;
+                if frame["server"].get(DEADLINE_SERVER_VAR).is_some() {
+                    Deadline::new(Instant::now(), timeout, None).stamp(&mut frame);
+                }
+            }
+        }
+
+        let Some(limiter) = &self.limiter else {
+            return self.send_frame(&self.frame_permits, frame, timeout).await;
+        };
+        let Some(permit) = limiter.try_acquire() else {
+            return Err(ServerError::Unavailable {
+                reason: UnavailableReason::Overloaded,
+                retry_after: None,
+                message: format!("adaptive concurrency limit of {} reached", limiter.limit()),
+            }
+            .into());
+        };
+
+        let result = self.send_frame(&self.frame_permits, frame, timeout).await;
+        permit.finish(match &result {
+            Ok(_) => Outcome::Success,
+            Err(e) => match e.downcast_ref::<ServerError>() {
+                Some(ServerError::BridgeTimeout(_)) => Outcome::Timeout,
+                Some(ServerError::Unavailable { .. }) => Outcome::Failure,
+                _ => Outcome::Ignore,
+            },
+        });
+        result
+    }
+
+    /// Send one frame to the worker within the configured frame limits
+    ///
+    /// Puts HTTP request frames in the shape of `WORKER_PROTOCOL`, rejects
+    /// frames larger than `max_frame_size`, waits for one of the slots of
+    /// `permits`, and gives up after `timeout`.
+    async fn send_frame(&self, permits: &Semaphore, frame: serde_json::Value, timeout: Duration) -> Result<PhpResponse> {
+        #[cfg(feature = "chaos")]
+        let fault = crate::chaos::BridgeFault::pick(&frame);
+        let frame = match worker_protocol::is_command(&frame) {
+            true => frame,
+            false => self.codec().encode_request(frame),
+        };
+        let _scratch = self.codec().scratch_dir(&frame).map(ScratchDir);
+        let size = serialized_len(&frame);
+        if size > self.config.max_frame_size {
+            return Err(ServerError::PayloadTooLarge(format!(
+                "request frame of {} bytes exceeds SOCKET_MAX_FRAME_SIZE ({} bytes)",
+                size, self.config.max_frame_size
+            ))
+            .into());
+        }
+
+        let _permit = permits.acquire().await?;
+        let pool = self.route()?;
+        let _in_flight = self.start_frame();
+        let stalled = self.stalled.notified();
+        let exchange = async {
+            #[cfg(feature = "chaos")]
+            if let Some(fault) = &fault {
+                fault.before_send().await?;
+            }
+            pool.send_http_request(frame).await
+        };
+        let response = tokio::select! {
+            response = tokio::time::timeout(timeout, exchange) => response,
+            _ = stalled => {
+                return Err(ServerError::Unavailable {
+                    reason: UnavailableReason::BridgeDown,
+                    retry_after: None,
+                    message: "PHP worker stalled and is being restarted".to_string(),
+                }
+                .into());
+            }
+        };
+        let response = response
+            .map_err(|_| ServerError::BridgeTimeout(format!("no response within {:?}", timeout)))?
+            .map_err(|e| match e.downcast::<FrameError>() {
+                Ok(frame_error) => ServerError::from(frame_error).into(),
+                Err(e) if e.is::<ServerError>() => e,
+                // Keep the underlying error in the chain for `is_connection_failure`
+                Err(e) => e.context(ServerError::bridge_down("request to PHP worker failed")),
+            })?;
+        self.mark_progress();
+        #[cfg(feature = "chaos")]
+        if let Some(fault) = &fault {
+            return fault.after_receive(response);
+        }
+        Ok(response)
+    }
+
+    /// Send a command frame to the PHP worker and wait for its response
+    ///
+    /// Commands travel over the same pooled connections and framing as HTTP
+    /// requests, as a serialized [`PhpRequest`].
+    pub async fn send_command(
+        &self,
+        command: &str,
+        data: Option<HashMap<String, serde_json::Value>>,
+    ) -> Result<PhpResponse> {
+        self.send_frame(&self.frame_permits, command_frame(command, data)?, self.config.read_timeout).await
+    }
+
+    /// Send a health check's HTTP request frame past the load limits
+    ///
+    /// Skips the adaptive limiter and `max_concurrent_frames`, and waits
+    /// for one of the few `priority_frames` slots instead, so a load
+    /// balancer's health check is answered while other traffic is shed.
+    pub async fn send_priority_request(
+        &self,
+        http_request_data: serde_json::Value,
+        timeout: Duration,
+    ) -> Result<PhpResponse> {
+        self.send_frame(&self.priority_permits, http_request_data, timeout).await
+    }
+
+    /// Send a command on behalf of a client, within the limits of HTTP requests
+    ///
+    /// Unlike [`send_command`](Self::send_command), which the server uses for
+    /// its own commands, this goes through the adaptive limiter, so commands
+    /// from other services are shed together with HTTP traffic.
+    pub async fn execute_command(
+        &self,
+        command: &str,
+        data: Option<HashMap<String, serde_json::Value>>,
+    ) -> Result<PhpResponse> {
+        self.send_request_frame(command_frame(command, data)?, self.config.read_timeout).await
+    }
+
+    /// Check that the PHP worker answers a `ping` command
+    ///
+    /// Any response counts, whatever its `success`: the worker is reading
+    /// frames and replying.
+    pub async fn ping(&self) -> Result<()> {
+        self.send_command("ping", None).await.map(|_| ())
+    }
+
+    /// Ping the worker over a connection of its own, outside the pool and the frame limits
+    ///
+    /// Tells a busy worker from a stalled one: the pooled connections and
+    /// frame slots may all be taken by requests the worker is stuck on.
+    /// An answer counts as progress.
+    ///
+    /// Goridge workers connect to the server, so there is no connection of
+    /// their own to open; they are pinged over the pool instead.
+    pub async fn ping_direct(&self, timeout: Duration) -> Result<()> {
+        let pool_config = self.pool_config.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        match pool_config.framing {
+            Framing::LengthPrefixed => ping_socket(pool_config, timeout).await?,
+            Framing::Goridge => {
+                let pool = self.pool();
+                tokio::time::timeout(timeout, pool.send_http_request(command_frame("ping", None)?))
+                    .await
+                    .map_err(|_| ServerError::BridgeTimeout(format!("no answer to ping within {:?}", timeout)))??;
+            }
+        }
+        self.mark_progress();
+        Ok(())
+    }
+
+    /// Count a frame as in flight; the first after an idle spell restarts the progress clock
+    fn start_frame(&self) -> InFlight<'_> {
+        if self.in_flight.fetch_add(1, Ordering::AcqRel) == 0 {
+            self.mark_progress();
+        }
+        InFlight(self)
+    }
+
+    /// Record that the worker is making progress
+    pub fn mark_progress(&self) {
+        self.last_progress_ms.store(self.created.elapsed().as_millis() as u64, Ordering::Release);
+    }
+
+    /// Frames sent to the worker and not yet answered
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Time since the worker last answered a frame while frames were in flight
+    pub fn since_progress(&self) -> Duration {
+        let last = Duration::from_millis(self.last_progress_ms.load(Ordering::Acquire));
+        self.created.elapsed().saturating_sub(last)
+    }
+
+    /// Fail every frame in flight with 503, for a worker found stalled
+    pub fn fail_in_flight(&self) {
+        self.stalled.notify_waiters();
+    }
+
+    /// Drop the pooled connections and open new ones on demand, e.g. after the worker was replaced
+    pub async fn reset_connections(&self) {
+        let socket_path = self.socket_path();
+        let old_pool = self.install_pool(&socket_path, self.new_pool(&socket_path));
+        old_pool.close_all().await;
+    }
+
+    /// Pool for `socket_path`, with the current pool settings
+    fn new_pool(&self, socket_path: &str) -> Arc<ConnectionPool> {
+        let pool_config = {
+            let mut pool_config = self.pool_config.lock().unwrap_or_else(|e| e.into_inner());
+            pool_config.socket_path = socket_path.to_string();
+            pool_config.clone()
+        };
+        Arc::new(ConnectionPool::new(pool_config))
+    }
+
+    /// Send new requests through `pool`, returning the pool it replaces
+    fn install_pool(&self, socket_path: &str, pool: Arc<ConnectionPool>) -> Arc<ConnectionPool> {
+        let old_pool = {
+            let mut current = self.connection_pool.write().unwrap_or_else(|e| e.into_inner());
+            std::mem::replace(&mut *current, pool)
+        };
+        *self.current_socket_path.write().unwrap_or_else(|e| e.into_inner()) = socket_path.to_string();
+        *self.resolved_target.lock().unwrap_or_else(|e| e.into_inner()) = std::fs::canonicalize(socket_path).ok();
+        old_pool
+    }
+
+    /// Hold new HTTP requests until the worker socket accepts connections again
+    ///
+    /// Called when the worker is gone and a new one is starting. Does
+    /// nothing unless `RESTART_PARK_TIMEOUT_MS` is set.
+    pub fn restarting(self: &Arc<Self>, cause: &'static str) {
+        let Some(park) = &self.park else {
+            return;
+        };
+        let generation = park.begin(cause);
+        let bridge = self.clone();
+        tokio::spawn(async move { bridge.await_backend(generation).await });
+    }
+
+    /// Release the requests parked by restart `generation` once the socket accepts connections
+    async fn await_backend(&self, generation: u64) {
+        let Some(park) = &self.park else {
+            return;
+        };
+        let give_up = Instant::now() + park.config().timeout;
+        loop {
+            if tokio::net::UnixStream::connect(self.socket_path()).await.is_ok() {
+                park.end(generation, true);
+                return;
+            }
+            if Instant::now() >= give_up {
+                park.end(generation, false);
+                return;
+            }
+            tokio::time::sleep(RESTART_PROBE_INTERVAL).await;
+        }
+    }
+
+    /// Switch new requests to a fresh pool, optionally pointed at a different socket path
+    ///
+    /// In-flight requests keep their reference to the old pool and complete on the
+    /// old backend; its idle connections are closed once the new pool is in place.
+    /// New requests are parked during the switch, when parking is enabled.
+    pub async fn swap_socket(&self, new_socket_path: Option<String>) -> Result<()> {
+        let old_path = self.socket_path();
+        let new_path = new_socket_path.unwrap_or_else(|| old_path.clone());
+        let parking = self.park.as_ref().map(|park| park.begin("swap"));
+
+        let new_pool = self.new_pool(&new_path);
+        if let Err(e) = new_pool.initialize().await {
+            // Connections will be created on demand once the new worker is up
+            warn!(new_path = %new_path, "Failed to pre-fill pool for new socket: {}", e);
+        }
+
+        let old_pool = self.install_pool(&new_path, new_pool);
+
+        metrics().inc_counter("bridge_socket_swaps_total", &[]);
+        info!(old_path = %old_path, new_path = %new_path, "🔀 Swapped PHP worker socket");
+        if let Some(generation) = parking {
+            self.await_backend(generation).await;
+        }
+
+        old_pool.close_all().await;
+        Ok(())
+    }
+
+    /// Watch the socket path and flush the pool whenever its symlink target changes
+    pub fn spawn_swap_watcher(self: &Arc<Self>) -> Option<tokio::task::JoinHandle<()>> {
+        let interval = self.config.swap_watch_interval?;
+        let bridge = Arc::downgrade(self);
+
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let Some(bridge) = bridge.upgrade() else {
+                    break;
+                };
+
+                let path = bridge.socket_path();
+                let Ok(target) = std::fs::canonicalize(&path) else {
+                    continue;
+                };
+
+                let previous = bridge
+                    .resolved_target
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .replace(target.clone());
+
+                if let Some(previous) = previous {
+                    if previous != target {
+                        info!(
+                            socket_path = %path,
+                            old_target = %previous.display(),
+                            new_target = %target.display(),
+                            "Socket symlink target changed"
+                        );
+                        if let Err(e) = bridge.swap_socket(None).await {
+                            warn!("Failed to swap PHP worker socket: {}", e);
+                        }
+                    }
+                }
+            }
+        }))
     }
 }
 
+/// Ping the worker at `pool_config.socket_path` over a connection of its own
+pub(crate) async fn ping_socket(pool_config: ConnectionPoolConfig, timeout: Duration) -> Result<()> {
+    let pool = ConnectionPool::new(pool_config);
+    let result = tokio::time::timeout(timeout, pool.send_http_request(command_frame("ping", None)?)).await;
+    pool.close_all().await;
+    result.map_err(|_| ServerError::BridgeTimeout(format!("no answer to ping within {:?}", timeout)))??;
+    Ok(())
+}
+
+/// Whether a failed frame never reached the PHP worker
+///
+/// True only for failures to connect (`ConnectionRefused`, a missing socket)
+/// or to write to a connection the worker had already closed (`BrokenPipe`).
+/// A timeout or a reset while waiting for the response means the worker may
+/// have processed the request, so those are never considered safe to resend.
+pub fn is_connection_failure(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .any(|io_error| {
+            matches!(
+                io_error.kind(),
+                std::io::ErrorKind::ConnectionRefused | std::io::ErrorKind::NotFound | std::io::ErrorKind::BrokenPipe
+            )
+        })
+}
+
+/// Length of a value's JSON encoding, without allocating it
+fn serialized_len(value: &serde_json::Value) -> usize {
+    struct Counter(usize);
+
+    impl std::io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    let _ = serde_json::to_writer(&mut counter, value);
+    counter.0
+}
+
 impl SocketBridge {
     #[allow(dead_code)]
     pub async fn cleanup(&self) {
-        self.connection_pool.close_all().await;
+        self.pool().close_all().await;
+        if let Some(backends) = &self.backends {
+            backends.close_all().await;
+        }
+    }
+
+    /// Leave the socket file in place on drop, for a worker this process does not manage
+    pub fn keep_socket_file(&self) {
+        self.owns_socket_file.store(false, Ordering::Relaxed);
     }
 }
 
 impl Drop for SocketBridge {
     fn drop(&mut self) {
         // Remove socket file when dropping
-        if Path::new(&self.config.socket_path).exists() {
-            let _ = std::fs::remove_file(&self.config.socket_path);
+        let socket_path = self.socket_path();
+        if self.owns_socket_file.load(Ordering::Relaxed) && Path::new(&socket_path).exists() {
+            match std::fs::remove_file(&socket_path) {
+                Ok(()) => info!(socket_path = %socket_path, "SocketBridge dropped, socket file removed"),
+                Err(e) => warn!(socket_path = %socket_path, error = %e, "SocketBridge dropped, failed to remove socket file"),
+            }
         }
-        println!("⚠️ SocketBridge уничтожается, файл сокета удален");
     }
 }
@@ -0,0 +1,159 @@
+//! How connections to the PHP worker are opened
+//!
+//! The [`ConnectionPool`](crate::bridge::connection_pool::ConnectionPool)
+//! and its framing work on any [`BridgeTransport`]: something that opens
+//! byte streams to the worker and can tell whether an idle one is still
+//! usable. [`UnixTransport`], the worker's Unix socket, is what the server
+//! uses; RoadRunner workers connect to a socket the server listens on
+//! instead ([`UnixTransport::listening`]). [`DuplexTransport`] connects to a [`DuplexListener`] in the same
+//! process over `tokio::io::duplex` pipes, so the pool can be tested without
+//! a socket file or a worker process.
+
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, OnceCell};
+
+/// Opens connections to the PHP worker
+///
+/// Displayed in log lines and errors as the worker's address.
+pub trait BridgeTransport: fmt::Display + Send + Sync + 'static {
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    /// Open a new connection
+    fn connect(&self) -> impl Future<Output = io::Result<Self::Stream>> + Send;
+
+    /// Whether an idle connection is still open, with nothing unread on it
+    ///
+    /// Must not wait. The default polls one read: a connection with nothing
+    /// to read is healthy, one that has ended or has data waiting is not.
+    fn is_healthy(&self, stream: &mut Self::Stream) -> bool {
+        let mut byte = [0; 1];
+        let mut buf = ReadBuf::new(&mut byte);
+        let mut cx = Context::from_waker(Waker::noop());
+        matches!(Pin::new(stream).poll_read(&mut cx, &mut buf), Poll::Pending)
+    }
+
+    /// Whether [`connect`](Self::connect) opens a connection whenever asked
+    ///
+    /// False when it waits for a worker to connect instead; the pool then
+    /// keeps idle connections however long they are idle.
+    fn opens_on_demand(&self) -> bool {
+        true
+    }
+}
+
+/// The worker's Unix socket
+#[derive(Debug)]
+pub struct UnixTransport {
+    socket_path: String,
+    /// Bound on the first connect, when the workers connect to the server
+    listener: Option<OnceCell<UnixListener>>,
+}
+
+impl UnixTransport {
+    /// Connections to the worker listening on `socket_path`
+    pub fn new(socket_path: impl Into<String>) -> Self {
+        Self {
+            socket_path: socket_path.into(),
+            listener: None,
+        }
+    }
+
+    /// Connections from workers, accepted on `socket_path`
+    ///
+    /// The socket is bound on the first connect, replacing a file left at
+    /// `socket_path`; each connect then waits for the next worker.
+    pub fn listening(socket_path: impl Into<String>) -> Self {
+        Self {
+            socket_path: socket_path.into(),
+            listener: Some(OnceCell::new()),
+        }
+    }
+}
+
+impl fmt::Display for UnixTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.socket_path)
+    }
+}
+
+impl BridgeTransport for UnixTransport {
+    type Stream = UnixStream;
+
+    async fn connect(&self) -> io::Result<UnixStream> {
+        let Some(listener) = &self.listener else {
+            return UnixStream::connect(&self.socket_path).await;
+        };
+        let listener = listener
+            .get_or_try_init(|| async {
+                let _ = std::fs::remove_file(&self.socket_path);
+                UnixListener::bind(&self.socket_path)
+            })
+            .await?;
+        Ok(listener.accept().await?.0)
+    }
+
+    fn is_healthy(&self, stream: &mut UnixStream) -> bool {
+        matches!(stream.try_read(&mut [0; 1]), Err(e) if e.kind() == io::ErrorKind::WouldBlock)
+    }
+
+    fn opens_on_demand(&self) -> bool {
+        self.listener.is_none()
+    }
+}
+
+/// In-memory connections to a [`DuplexListener`], for tests
+#[derive(Debug, Clone)]
+pub struct DuplexTransport {
+    incoming: mpsc::UnboundedSender<DuplexStream>,
+    /// Bytes each direction of a connection buffers before writes wait
+    buffer: usize,
+}
+
+/// The worker's end of a [`DuplexTransport`]
+#[derive(Debug)]
+pub struct DuplexListener {
+    incoming: mpsc::UnboundedReceiver<DuplexStream>,
+}
+
+impl DuplexTransport {
+    /// A transport and the listener its connections arrive at
+    ///
+    /// Connecting fails with `ConnectionRefused` once the listener is dropped.
+    pub fn new(buffer: usize) -> (Self, DuplexListener) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (Self { incoming: sender, buffer }, DuplexListener { incoming: receiver })
+    }
+}
+
+impl fmt::Display for DuplexTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("in-memory worker")
+    }
+}
+
+impl BridgeTransport for DuplexTransport {
+    type Stream = DuplexStream;
+
+    fn connect(&self) -> impl Future<Output = io::Result<DuplexStream>> + Send {
+        let (ours, theirs) = tokio::io::duplex(self.buffer);
+        let sent = self.incoming.send(theirs);
+        async move {
+            sent.map_err(|_| io::Error::new(io::ErrorKind::ConnectionRefused, "in-memory listener is gone"))?;
+            Ok(ours)
+        }
+    }
+}
+
+impl DuplexListener {
+    /// The next connection; `None` once every transport is dropped
+    pub async fn accept(&mut self) -> Option<DuplexStream> {
+        self.incoming.recv().await
+    }
+}
@@ -0,0 +1,129 @@
+//! Bridge tuning parameters
+//!
+//! Everything the socket bridge needs under load — pool sizes, timeouts,
+//! retry policy, concurrency and frame limits — is read here, once, so the
+//! bridge module itself never touches the environment.
+
+use std::time::Duration;
+
+use crate::bridge::adaptive_limit::AdaptiveLimitConfig;
+use crate::bridge::discovery::DiscoveryConfig;
+use crate::worker_protocol::WorkerProtocol;
+use crate::bridge::restart_park::RestartParkConfig;
+use crate::config::AppConfig;
+
+/// Typed configuration of the bridge to the PHP worker
+#[derive(Debug, Clone)]
+pub struct BridgeConfig {
+    pub socket_path: String,
+    pub pool_min: usize,
+    pub pool_max: usize,
+    /// Timeout for opening a connection to the worker socket
+    pub connect_timeout: Duration,
+    /// Pooled connections idle for longer are closed instead of reused
+    pub health_check_interval: Duration,
+    /// Maximum time to wait for the worker's response to one frame
+    pub read_timeout: Duration,
+    /// Frames a pooled connection carries before it is closed (None: no limit)
+    pub pool_max_uses: Option<u64>,
+    /// Send the worker a `goodbye` command before closing an idle connection
+    pub goodbye_frame: bool,
+    /// Attempts when pre-filling the connection pool
+    pub retry_max_attempts: u32,
+    pub retry_base_delay: Duration,
+    pub retry_max_delay: Duration,
+    /// Frames in flight to the worker at once
+    pub max_concurrent_frames: usize,
+    /// Largest request frame sent to the worker, in bytes
+    pub max_frame_size: usize,
+    /// Frames reserved for `PRIORITY_PATHS`, outside the other limits
+    pub priority_frames: usize,
+    /// How often the socket path is re-resolved to detect a flipped symlink (None disables)
+    pub swap_watch_interval: Option<Duration>,
+    /// Latency-driven limit of requests in flight (None keeps only the static cap)
+    pub adaptive_limit: Option<AdaptiveLimitConfig>,
+    /// Holding of requests while the worker restarts (None fails them at once)
+    pub restart_park: Option<RestartParkConfig>,
+    /// Workers found in a socket directory instead of `socket_path` (None uses `socket_path`)
+    pub discovery: Option<DiscoveryConfig>,
+    /// Shape of the request and response frames the worker speaks
+    pub protocol: WorkerProtocol,
+}
+
+impl BridgeConfig {
+    /// Load every bridge setting from the environment
+    pub fn from_env() -> Self {
+        Self {
+            socket_path: std::env::var("SOCKET_PATH").unwrap_or_else(|_| "/tmp/rust_php_bridge.sock".to_string()),
+            pool_min: env_or("SOCKET_POOL_MIN", 2),
+            pool_max: env_or("SOCKET_POOL_MAX", 10),
+            connect_timeout: Duration::from_secs(env_or("SOCKET_CONNECTION_TIMEOUT", 5)),
+            health_check_interval: Duration::from_secs(env_or("SOCKET_HEALTH_CHECK_INTERVAL", 30)),
+            read_timeout: Duration::from_millis(env_or("SOCKET_READ_TIMEOUT_MS", 30_000)),
+            pool_max_uses: match env_or("SOCKET_POOL_MAX_USES", 0) {
+                0 => None,
+                uses => Some(uses),
+            },
+            goodbye_frame: env_flag("SOCKET_GOODBYE_FRAME"),
+            retry_max_attempts: env_or("RETRY_MAX_ATTEMPTS", 5),
+            retry_base_delay: Duration::from_millis(env_or("RETRY_BASE_DELAY_MS", 500)),
+            retry_max_delay: Duration::from_secs(env_or("RETRY_MAX_DELAY_SECS", 30)),
+            max_concurrent_frames: env_or("SOCKET_MAX_CONCURRENT_FRAMES", 256),
+            max_frame_size: env_or("SOCKET_MAX_FRAME_SIZE", 16 * 1024 * 1024),
+            priority_frames: env_or("SOCKET_PRIORITY_FRAMES", 2),
+            swap_watch_interval: match env_or("SOCKET_SWAP_WATCH_INTERVAL_MS", 1000) {
+                0 => None,
+                millis => Some(Duration::from_millis(millis)),
+            },
+            adaptive_limit: env_flag("ADAPTIVE_CONCURRENCY").then(|| AdaptiveLimitConfig {
+                initial_limit: env_or("ADAPTIVE_CONCURRENCY_INITIAL", 4),
+                min_limit: env_or("ADAPTIVE_CONCURRENCY_MIN", 1),
+                max_limit: env_or("ADAPTIVE_CONCURRENCY_MAX", 256),
+                latency_tolerance: env_or("ADAPTIVE_CONCURRENCY_LATENCY_TOLERANCE", 2.0),
+                backoff: env_or("ADAPTIVE_CONCURRENCY_BACKOFF", 0.9),
+                window: Duration::from_millis(env_or("ADAPTIVE_CONCURRENCY_WINDOW_MS", 1000)),
+            }),
+            restart_park: match env_or("RESTART_PARK_TIMEOUT_MS", 0) {
+                0 => None,
+                millis => Some(RestartParkConfig {
+                    timeout: Duration::from_millis(millis),
+                    max_requests: env_or("RESTART_PARK_MAX_REQUESTS", 256),
+                    max_body_bytes: env_or("RESTART_PARK_MAX_BODY_BYTES", 16 * 1024 * 1024),
+                    min_remaining: Duration::from_millis(env_or("RESTART_PARK_MIN_REMAINING_MS", 1000)),
+                }),
+            },
+            discovery: std::env::var("SOCKET_DISCOVERY_DIR")
+                .ok()
+                .filter(|dir| !dir.is_empty())
+                .map(|dir| DiscoveryConfig {
+                    dir: dir.into(),
+                    interval: Duration::from_millis(env_or("SOCKET_DISCOVERY_INTERVAL_MS", 1000)),
+                    ping_timeout: Duration::from_millis(env_or("SOCKET_DISCOVERY_PING_TIMEOUT_MS", 1000)),
+                }),
+            protocol: WorkerProtocol::from_env(),
+        }
+    }
+
+    /// Bridge settings with the values `AppConfig` already owns taken from it
+    pub fn from_app_config(app_config: &AppConfig) -> Self {
+        Self {
+            socket_path: app_config.connection.socket_path.clone(),
+            pool_min: app_config.connection_pool.min_connections,
+            pool_max: app_config.connection_pool.max_connections,
+            connect_timeout: app_config.connection_pool.connection_timeout,
+            health_check_interval: app_config.connection_pool.health_check_interval,
+            retry_max_attempts: app_config.retry.max_attempts,
+            retry_base_delay: app_config.retry.base_delay,
+            retry_max_delay: app_config.retry.max_delay,
+            ..Self::from_env()
+        }
+    }
+}
+
+fn env_flag(name: &str) -> bool {
+    matches!(std::env::var(name).as_deref(), Ok("true") | Ok("1"))
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
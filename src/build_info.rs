@@ -0,0 +1,36 @@
+//! Version and build information of the running binary
+
+use serde::Serialize;
+
+/// Version line printed by `--version`
+pub const VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("BUILD_GIT_SHA"),
+    ", built ",
+    env!("BUILD_TIMESTAMP"),
+    ")"
+);
+
+/// What was built, from which commit and when
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BuildInfo {
+    /// Crate version
+    pub version: &'static str,
+    /// Abbreviated git commit, or `unknown` outside a git checkout
+    pub git_sha: &'static str,
+    /// Build time (UTC, RFC 3339), or `SOURCE_DATE_EPOCH` when set
+    pub build_timestamp: &'static str,
+    /// `rustc --version` of the compiler, or `unknown`
+    pub rustc_version: &'static str,
+}
+
+/// Build information of this crate
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("BUILD_GIT_SHA"),
+        build_timestamp: env!("BUILD_TIMESTAMP"),
+        rustc_version: env!("BUILD_RUSTC_VERSION"),
+    }
+}
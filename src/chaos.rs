@@ -0,0 +1,287 @@
+//! Fault injection for resilience testing (`chaos` feature)
+//!
+//! Builds with the `chaos` feature can be told to misbehave on purpose, to
+//! check retries, timeouts and load shedding without breaking the PHP
+//! worker. Nothing is injected unless `CHAOS_ENABLED=true`, and each fault
+//! has its own probability (`0` to `1`, default `0`):
+//!
+//! * `CHAOS_CONNECT_FAILURE_RATE` - the frame fails as if the worker socket
+//!   refused the connection, so `SOCKET_RETRY_IDEMPOTENT` kicks in
+//! * `CHAOS_LATENCY_RATE` - the frame is held back `CHAOS_LATENCY_MS` before
+//!   it is sent, counting against `SOCKET_READ_TIMEOUT_MS`
+//! * `CHAOS_TRUNCATED_FRAME_RATE` - the worker's response is cut in half
+//! * `CHAOS_MALFORMED_RESPONSE_RATE` - the worker's response is not JSON
+//! * `CHAOS_HTTP_ERROR_RATE` - the request is answered with
+//!   `CHAOS_HTTP_ERROR_STATUS` before it reaches the backend
+//!
+//! The bridge faults apply to HTTP request frames only; the server's own
+//! commands (readiness pings, shutdown notifications) and gRPC commands are
+//! never touched. With `CHAOS_HEADER` set (`name` or `name: value`), only
+//! requests carrying that header are eligible.
+//!
+//! Every injected fault is logged at warn level with `chaos = true` and
+//! counted in `chaos_faults_injected_total{fault}`. Requests that had one are
+//! counted in `http_chaos_requests_total{fault,status}` instead of
+//! `http_requests_total`, so a test run does not look like an incident.
+
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::Result;
+use hyper::HeaderMap;
+use once_cell::sync::OnceCell;
+use tracing::warn;
+
+use crate::bridge::PhpResponse;
+use crate::errors::ServerError;
+use crate::metrics::{metrics, MetricKind};
+
+/// Default for `CHAOS_LATENCY_MS`
+pub const DEFAULT_LATENCY_MS: u64 = 1000;
+
+/// Default for `CHAOS_HTTP_ERROR_STATUS`
+pub const DEFAULT_HTTP_ERROR_STATUS: u16 = 500;
+
+/// Set by [`install`] when fault injection is enabled
+static CHAOS: OnceCell<ChaosConfig> = OnceCell::new();
+
+tokio::task_local! {
+    /// Fault injected into the request being handled on this task
+    static INJECTED: Cell<Option<&'static str>>;
+}
+
+/// Fault injection configuration
+#[derive(Debug, Clone, Default)]
+pub struct ChaosConfig {
+    pub enabled: bool,
+    /// Only requests with this header (lowercase name, optional value) are eligible
+    pub header: Option<(String, Option<String>)>,
+    pub connect_failure_rate: f64,
+    pub latency_rate: f64,
+    pub latency: Duration,
+    pub truncated_frame_rate: f64,
+    pub malformed_response_rate: f64,
+    pub http_error_rate: f64,
+    pub http_error_status: u16,
+}
+
+impl ChaosConfig {
+    pub fn from_env() -> Self {
+        let rate = |env: &str| {
+            std::env::var(env)
+                .ok()
+                .and_then(|v| v.trim().parse::<f64>().ok())
+                .map(|v| v.clamp(0.0, 1.0))
+                .unwrap_or(0.0)
+        };
+        Self {
+            enabled: std::env::var("CHAOS_ENABLED")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            header: std::env::var("CHAOS_HEADER").ok().and_then(|v| parse_header(&v)),
+            connect_failure_rate: rate("CHAOS_CONNECT_FAILURE_RATE"),
+            latency_rate: rate("CHAOS_LATENCY_RATE"),
+            latency: Duration::from_millis(
+                std::env::var("CHAOS_LATENCY_MS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(DEFAULT_LATENCY_MS),
+            ),
+            truncated_frame_rate: rate("CHAOS_TRUNCATED_FRAME_RATE"),
+            malformed_response_rate: rate("CHAOS_MALFORMED_RESPONSE_RATE"),
+            http_error_rate: rate("CHAOS_HTTP_ERROR_RATE"),
+            http_error_status: std::env::var("CHAOS_HTTP_ERROR_STATUS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_HTTP_ERROR_STATUS),
+        }
+    }
+}
+
+/// `name` or `name: value`
+fn parse_header(value: &str) -> Option<(String, Option<String>)> {
+    let (name, value) = match value.split_once(':') {
+        Some((name, value)) => (name, Some(value.trim().to_string())),
+        None => (value, None),
+    };
+    let name = name.trim().to_ascii_lowercase();
+    (!name.is_empty()).then_some((name, value))
+}
+
+/// Enable fault injection for this process, when `config` asks for it
+///
+/// Only `serve` calls this, so `send`, `bench` and `replay` always talk to
+/// the worker undisturbed.
+pub fn install(config: ChaosConfig) {
+    if !config.enabled {
+        return;
+    }
+    metrics().describe(
+        "chaos_faults_injected_total",
+        MetricKind::Counter,
+        "Faults injected by the chaos feature, by kind",
+    );
+    metrics().describe(
+        "http_chaos_requests_total",
+        MetricKind::Counter,
+        "Requests that had a fault injected, by fault and status code; not counted in http_requests_total",
+    );
+    warn!(
+        chaos = true,
+        connect_failure_rate = config.connect_failure_rate,
+        latency_rate = config.latency_rate,
+        latency_ms = config.latency.as_millis() as u64,
+        truncated_frame_rate = config.truncated_frame_rate,
+        malformed_response_rate = config.malformed_response_rate,
+        http_error_rate = config.http_error_rate,
+        header = config.header.as_ref().map(|(name, _)| name.as_str()),
+        "💥 Fault injection is enabled; do not run this build in production"
+    );
+    let _ = CHAOS.set(config);
+}
+
+/// Run `request` and report the fault injected while handling it, if any
+pub async fn track<F: Future>(request: F) -> (F::Output, Option<&'static str>) {
+    INJECTED
+        .scope(Cell::new(None), async {
+            let output = request.await;
+            (output, INJECTED.with(Cell::get))
+        })
+        .await
+}
+
+/// Error to answer the request with instead of forwarding it
+pub fn http_fault(headers: &HeaderMap) -> Option<anyhow::Error> {
+    let config = CHAOS.get()?;
+    let eligible = match &config.header {
+        None => true,
+        Some((name, value)) => headers
+            .get(name.as_str())
+            .is_some_and(|v| value.as_deref().is_none_or(|value| v.as_bytes() == value.as_bytes())),
+    };
+    if !eligible || !roll(config.http_error_rate) {
+        return None;
+    }
+
+    let status = config.http_error_status;
+    injected("http_error", format!("answering with {}", status));
+    let message = format!("chaos: injected {} response", status);
+    Some(
+        match status {
+            502 => ServerError::UpstreamMalformed(message),
+            503 => ServerError::bridge_down(message),
+            504 => ServerError::BridgeTimeout(message),
+            _ => ServerError::Internal(message),
+        }
+        .into(),
+    )
+}
+
+/// Fault to inject into one request frame to the worker
+#[derive(Debug, Clone, Copy)]
+pub enum BridgeFault {
+    ConnectFailure,
+    Latency(Duration),
+    TruncatedFrame,
+    MalformedResponse,
+}
+
+impl BridgeFault {
+    fn name(&self) -> &'static str {
+        match self {
+            BridgeFault::ConnectFailure => "connect_failure",
+            BridgeFault::Latency(_) => "latency",
+            BridgeFault::TruncatedFrame => "truncated_frame",
+            BridgeFault::MalformedResponse => "malformed_response",
+        }
+    }
+
+    /// Fault for `frame`, if one is to be injected; logs and counts it
+    pub fn pick(frame: &serde_json::Value) -> Option<Self> {
+        let config = CHAOS.get()?;
+        // Only HTTP request frames carry `server`; commands are left alone
+        frame.get("server")?;
+        if let Some((name, value)) = &config.header {
+            let sent = frame["headers"].get(name.as_str())?.as_str()?;
+            if value.as_deref().is_some_and(|value| value != sent) {
+                return None;
+            }
+        }
+
+        let fault = [
+            (config.connect_failure_rate, BridgeFault::ConnectFailure),
+            (config.latency_rate, BridgeFault::Latency(config.latency)),
+            (config.truncated_frame_rate, BridgeFault::TruncatedFrame),
+            (config.malformed_response_rate, BridgeFault::MalformedResponse),
+        ]
+        .into_iter()
+        .find_map(|(rate, fault)| roll(rate).then_some(fault))?;
+
+        let uri = frame["uri"].as_str().unwrap_or_default();
+        let detail = match fault {
+            BridgeFault::Latency(delay) => format!("frame to {} held back {:?}", uri, delay),
+            _ => format!("frame to {}", uri),
+        };
+        injected(fault.name(), detail);
+        Some(fault)
+    }
+
+    /// Part of the fault that happens before the frame is sent
+    pub async fn before_send(&self) -> Result<()> {
+        match self {
+            BridgeFault::ConnectFailure => Err(std::io::Error::new(
+                std::io::ErrorKind::ConnectionRefused,
+                "chaos: injected connect failure",
+            )
+            .into()),
+            BridgeFault::Latency(delay) => {
+                tokio::time::sleep(*delay).await;
+                Ok(())
+            }
+            BridgeFault::TruncatedFrame | BridgeFault::MalformedResponse => Ok(()),
+        }
+    }
+
+    /// Part of the fault that happens to the worker's response
+    ///
+    /// The frame was delivered, so the worker did handle the request; only
+    /// its answer is lost, as when a worker dies mid-response.
+    pub fn after_receive(&self, response: PhpResponse) -> Result<PhpResponse> {
+        let raw = serde_json::to_string(&response)?;
+        let (corrupted, what) = match self {
+            BridgeFault::TruncatedFrame => {
+                let cut = (0..=raw.len() / 2).rev().find(|&i| raw.is_char_boundary(i)).unwrap_or(0);
+                (raw[..cut].to_string(), format!("response frame truncated after {} of {} bytes", cut, raw.len()))
+            }
+            BridgeFault::MalformedResponse => (format!("<br />\n<b>Warning</b>: {}", raw), "malformed response frame".to_string()),
+            BridgeFault::ConnectFailure | BridgeFault::Latency(_) => return Ok(response),
+        };
+        match serde_json::from_str::<PhpResponse>(&corrupted) {
+            Ok(response) => Ok(response),
+            Err(e) => Err(ServerError::UpstreamMalformed(format!("chaos: {}: {}", what, e)).into()),
+        }
+    }
+}
+
+/// Log and count an injected fault, and mark the request it belongs to
+fn injected(fault: &'static str, detail: String) {
+    warn!(chaos = true, fault, "💥 Injected {}: {}", fault, detail);
+    metrics().inc_counter("chaos_faults_injected_total", &[("fault", fault)]);
+    let _ = INJECTED.try_with(|injected| injected.set(Some(fault)));
+}
+
+/// `true` with probability `rate`
+fn roll(rate: f64) -> bool {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    if rate <= 0.0 {
+        return false;
+    }
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    // 53 random bits make a uniform f64 in [0, 1)
+    ((hasher.finish() >> 11) as f64 / (1u64 << 53) as f64) < rate
+}
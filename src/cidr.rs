@@ -0,0 +1,180 @@
+//! IP address ranges in CIDR notation
+//!
+//! Used for lists of client addresses such as `ADMIN_ALLOW_IPS`, `IP_ALLOW`
+//! and `TRUSTED_PROXIES`. An entry is a network (`10.0.0.0/8`, `fd00::/8`)
+//! or a single address, which stands for the `/32` or `/128` containing
+//! only it. IPv4 clients reaching a dual stack listener appear as
+//! IPv4-mapped IPv6 addresses (`::ffff:10.0.0.1`) and are matched as the
+//! IPv4 address they carry.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// One network: an address and the number of leading bits that must match
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Whether `ip` is inside this network
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix)).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix)).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+
+    /// Number of leading bits that must match; a larger one is a narrower network
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match value.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value, None),
+        };
+        let network: IpAddr = address
+            .parse()
+            .map_err(|_| format!("{:?} is not an IP address or network", value))?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            None => max,
+            Some(prefix) => match prefix.parse::<u8>() {
+                Ok(prefix) if prefix <= max => prefix,
+                _ => return Err(format!("{:?} has a prefix length outside 0-{}", value, max)),
+            },
+        };
+        Ok(Self {
+            network: network.to_canonical(),
+            prefix: if network.is_ipv6() && network.to_canonical().is_ipv4() {
+                prefix.saturating_sub(96)
+            } else {
+                prefix
+            },
+        })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// Comma-separated list of networks, e.g. `10.0.0.0/8, 127.0.0.1`
+///
+/// Returns every malformed entry at once, for config validation.
+pub fn parse_list(list: &str) -> Result<Vec<Cidr>, Vec<String>> {
+    let mut networks = Vec::new();
+    let mut problems = Vec::new();
+    for entry in list.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        match entry.parse() {
+            Ok(network) => networks.push(network),
+            Err(problem) => problems.push(problem),
+        }
+    }
+    if problems.is_empty() {
+        Ok(networks)
+    } else {
+        Err(problems)
+    }
+}
+
+/// Whether any of `networks` contains `ip`
+pub fn any_contains(networks: &[Cidr], ip: IpAddr) -> bool {
+    networks.iter().any(|network| network.contains(ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidr(value: &str) -> Cidr {
+        value.parse().unwrap()
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn networks_and_single_addresses_parse() {
+        assert_eq!(cidr("10.0.0.0/8").to_string(), "10.0.0.0/8");
+        assert_eq!(cidr("127.0.0.1").to_string(), "127.0.0.1/32");
+        assert_eq!(cidr("fd00::/8").to_string(), "fd00::/8");
+        assert_eq!(cidr("::1").to_string(), "::1/128");
+        assert_eq!(cidr("0.0.0.0/0").prefix_len(), 0);
+        assert_eq!(cidr("::/0").prefix_len(), 0);
+        assert_eq!(cidr("2001:db8::1/128").prefix_len(), 128);
+    }
+
+    #[test]
+    fn malformed_entries_are_refused() {
+        for value in ["", "10.0.0", "10.0.0.0/33", "::/129", "10.0.0.0/-1", "10.0.0.0/", "example.com", "10.0.0.0/8/8"] {
+            assert!(value.parse::<Cidr>().is_err(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn addresses_match_by_their_leading_bits() {
+        let network = cidr("192.168.16.0/20");
+        assert!(network.contains(ip("192.168.16.1")));
+        assert!(network.contains(ip("192.168.31.255")));
+        assert!(!network.contains(ip("192.168.32.0")));
+        assert!(!network.contains(ip("192.168.15.255")));
+
+        let network = cidr("2001:db8:abcd::/48");
+        assert!(network.contains(ip("2001:db8:abcd:12::1")));
+        assert!(!network.contains(ip("2001:db8:abce::1")));
+    }
+
+    #[test]
+    fn zero_and_full_prefixes() {
+        assert!(cidr("0.0.0.0/0").contains(ip("203.0.113.9")));
+        assert!(cidr("::/0").contains(ip("2001:db8::9")));
+        assert!(!cidr("0.0.0.0/0").contains(ip("2001:db8::9")), "an IPv4 network never holds an IPv6 client");
+        assert!(!cidr("::/0").contains(ip("203.0.113.9")), "an IPv6 network never holds an IPv4 client");
+
+        let host = cidr("2001:db8::1/128");
+        assert!(host.contains(ip("2001:db8::1")));
+        assert!(!host.contains(ip("2001:db8::2")));
+        assert!(cidr("10.1.2.3").contains(ip("10.1.2.3")));
+        assert!(!cidr("10.1.2.3").contains(ip("10.1.2.4")));
+    }
+
+    #[test]
+    fn v4_mapped_addresses_match_as_ipv4() {
+        // Clients of a dual stack listener
+        assert!(cidr("10.0.0.0/8").contains(ip("::ffff:10.1.2.3")));
+        assert!(!cidr("10.0.0.0/8").contains(ip("::ffff:11.1.2.3")));
+        // Networks written in the mapped form
+        assert_eq!(cidr("::ffff:10.0.0.0/104"), cidr("10.0.0.0/8"));
+        assert_eq!(cidr("::ffff:10.1.2.3"), cidr("10.1.2.3/32"));
+        assert!(cidr("::ffff:10.0.0.0/104").contains(ip("10.200.0.1")));
+    }
+
+    #[test]
+    fn lists_report_every_bad_entry() {
+        let networks = parse_list(" 10.0.0.0/8, ,::1 ").unwrap();
+        assert_eq!(networks, vec![cidr("10.0.0.0/8"), cidr("::1")]);
+        assert!(any_contains(&networks, ip("10.9.9.9")));
+        assert!(!any_contains(&networks, ip("192.0.2.1")));
+        assert_eq!(parse_list("").unwrap(), Vec::new());
+        assert_eq!(parse_list("bogus, 10.0.0.0/8, 1.2.3.4/40").unwrap_err().len(), 2);
+    }
+}
@@ -0,0 +1,276 @@
+//! Command-line interface
+//!
+//! Flags are a thin layer over the environment: every override is exported
+//! to the variable `AppConfig::from_env()` already reads, so the CLI wins over
+//! `.env` and the config file without a second configuration path.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+/// Rust HTTP front-end for Laravel applications
+#[derive(Debug, Parser)]
+#[command(name = "laravel-rust-server")]
+#[command(version = laravel_rust_server::build_info::VERSION)]
+#[command(about = "Rust HTTP front-end that forwards requests to a Laravel PHP worker over a Unix socket")]
+#[command(after_help = "Every option can also be set through the environment variable shown in brackets, \
+or in the file passed with --config. Precedence: CLI flag > environment / .env > config file > default.")]
+pub struct Cli {
+    /// Print an annotated TOML config with every setting and its default, then exit
+    #[arg(long)]
+    pub print_default_config: bool,
+
+    #[command(flatten)]
+    pub overrides: ConfigOverrides,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Settings that can be overridden from the command line
+#[derive(Debug, Clone, Default, Args)]
+pub struct ConfigOverrides {
+    /// Host for the HTTP server [env: HTTP_HOST]
+    #[arg(long, global = true, value_name = "HOST", value_parser = parse_non_empty)]
+    pub host: Option<String>,
+
+    /// Port for the HTTP server [env: HTTP_PORT]
+    #[arg(long, global = true, value_name = "PORT", value_parser = clap::value_parser!(u16).range(1..))]
+    pub port: Option<u16>,
+
+    /// Path to the PHP worker Unix socket [env: SOCKET_PATH]
+    #[arg(long, alias = "socket", global = true, value_name = "PATH", value_parser = parse_non_empty)]
+    pub socket_path: Option<String>,
+
+    /// Path to the Laravel application [env: LARAVEL_PATH]
+    #[arg(long, global = true, value_name = "DIR", value_parser = parse_existing_dir)]
+    pub laravel_path: Option<PathBuf>,
+
+    /// Log level [env: LOG_LEVEL]
+    #[arg(long, global = true, value_name = "LEVEL")]
+    pub log_level: Option<LogLevel>,
+
+    /// TOML or YAML configuration file [env: CONFIG_PATH]
+    #[arg(long, global = true, value_name = "FILE", value_parser = parse_existing_file)]
+    pub config: Option<PathBuf>,
+}
+
+/// Subcommands; `serve` runs when none is given
+#[derive(Debug, Clone, Default, Subcommand)]
+pub enum Command {
+    /// Run the HTTP server and the PHP worker (default)
+    #[default]
+    Serve,
+    /// Inspect the effective configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Load-test the bridge path against the PHP worker at SOCKET_PATH
+    Bench(BenchArgs),
+    /// Send one command to the PHP worker at SOCKET_PATH and print its response
+    ///
+    /// Exits 0 when the worker reports success, 1 when it reports failure and
+    /// 2 when no response was received.
+    Send(SendArgs),
+    /// Check whether the local instance is healthy, for container health checks
+    ///
+    /// Asks the HTTP listener's `/readyz`, or pings the PHP worker directly
+    /// with `--bridge-only`. Prints one line and exits 0 when healthy, 1
+    /// otherwise. Starts no PHP worker and writes nothing to the log.
+    Check(CheckArgs),
+    /// Send a request recorded in RECORD_DIR to the PHP worker again
+    ///
+    /// Prints the new response and how it differs from the recorded one.
+    /// Exits 0 when the worker reports success, 1 when it reports failure and
+    /// 2 when no response was received.
+    Replay(ReplayArgs),
+}
+
+/// Parameters of the `check` subcommand
+#[derive(Debug, Clone, Args)]
+pub struct CheckArgs {
+    /// Ping the PHP worker at SOCKET_PATH instead of the HTTP listener
+    #[arg(long)]
+    pub bridge_only: bool,
+
+    /// Give up and report unhealthy after this long (e.g. 500ms, 2s; bare numbers are milliseconds)
+    #[arg(long, default_value = "2s", value_parser = parse_timeout)]
+    pub timeout: Duration,
+}
+
+/// Parameters of the `send` subcommand
+#[derive(Debug, Clone, Args)]
+pub struct SendArgs {
+    /// Command name, e.g. `ping`
+    #[arg(required_unless_present = "http", conflicts_with = "http")]
+    pub command: Option<String>,
+
+    /// JSON object sent as the command's data, e.g. '{"key":"value"}'
+    #[arg(long, value_name = "JSON", value_parser = parse_json_object)]
+    pub data: Option<serde_json::Map<String, serde_json::Value>>,
+
+    /// Send an HTTP request frame instead of a command
+    #[arg(long, num_args = 2, value_names = ["METHOD", "PATH"], conflicts_with = "data")]
+    pub http: Option<Vec<String>>,
+
+    /// How long to wait for the response (e.g. 500ms, 5s; bare numbers are milliseconds)
+    #[arg(long, default_value = "5s", value_parser = parse_timeout)]
+    pub timeout: Duration,
+}
+
+/// Parameters of the `replay` subcommand
+#[derive(Debug, Clone, Args)]
+pub struct ReplayArgs {
+    /// Recording to send, e.g. logs/recordings/1760000000000-abc-1.json
+    #[arg(value_parser = parse_existing_file)]
+    pub file: PathBuf,
+
+    /// Send this header instead of the recorded one, e.g. to restore a redacted cookie (repeatable)
+    #[arg(long = "header", short = 'H', value_name = "NAME: VALUE", value_parser = parse_header)]
+    pub headers: Vec<(String, String)>,
+
+    /// How long to wait for the response (e.g. 500ms, 5s; bare numbers are milliseconds)
+    #[arg(long, default_value = "5s", value_parser = parse_timeout)]
+    pub timeout: Duration,
+}
+
+/// Parameters of the `bench` subcommand
+#[derive(Debug, Clone, Args)]
+pub struct BenchArgs {
+    /// Requests in flight at once
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u64).range(1..))]
+    pub concurrency: u64,
+
+    /// How long to send requests, in seconds
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub duration: u64,
+
+    /// Request body size in bytes; 0 sends GET requests, anything else POST
+    #[arg(long, default_value_t = 0)]
+    pub payload_size: usize,
+
+    /// Request path, including the query string
+    #[arg(long, default_value = "/")]
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum ConfigAction {
+    /// Print every setting with its effective value and where it came from
+    Show {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ConfigFormat::Toml)]
+        format: ConfigFormat,
+    },
+    /// Load and validate the configuration; exits non-zero on problems
+    Validate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+impl ConfigOverrides {
+    /// Environment variables set by the given flags
+    pub fn env_pairs(&self) -> Vec<(&'static str, String)> {
+        let mut pairs = Vec::new();
+        if let Some(host) = &self.host {
+            pairs.push(("HTTP_HOST", host.clone()));
+        }
+        if let Some(port) = self.port {
+            pairs.push(("HTTP_PORT", port.to_string()));
+        }
+        if let Some(socket_path) = &self.socket_path {
+            pairs.push(("SOCKET_PATH", socket_path.clone()));
+        }
+        if let Some(laravel_path) = &self.laravel_path {
+            pairs.push(("LARAVEL_PATH", laravel_path.display().to_string()));
+        }
+        if let Some(log_level) = self.log_level {
+            pairs.push(("LOG_LEVEL", log_level.as_str().to_string()));
+        }
+        if let Some(config) = &self.config {
+            pairs.push(("CONFIG_PATH", config.display().to_string()));
+        }
+        pairs
+    }
+
+    /// Export the flags to the environment, overriding `.env` and process values
+    ///
+    /// Must run before the config file is applied, which only fills variables
+    /// that are still unset.
+    pub fn apply_to_env(&self) {
+        for (name, value) in self.env_pairs() {
+            std::env::set_var(name, value);
+        }
+    }
+}
+
+fn parse_non_empty(value: &str) -> Result<String, String> {
+    if value.trim().is_empty() {
+        return Err("value must not be empty".to_string());
+    }
+    Ok(value.to_string())
+}
+
+fn parse_json_object(value: &str) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    match serde_json::from_str(value) {
+        Ok(serde_json::Value::Object(object)) => Ok(object),
+        Ok(_) => Err("must be a JSON object".to_string()),
+        Err(e) => Err(format!("invalid JSON: {}", e)),
+    }
+}
+
+fn parse_header(value: &str) -> Result<(String, String), String> {
+    match value.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        }
+        _ => Err("must look like 'Name: value'".to_string()),
+    }
+}
+
+fn parse_timeout(value: &str) -> Result<Duration, String> {
+    laravel_rust_server::config_loader::parse_duration("--timeout", value).map_err(|e| e.to_string())
+}
+
+fn parse_existing_dir(value: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(value);
+    if !path.is_dir() {
+        return Err(format!("directory '{}' does not exist", value));
+    }
+    Ok(path)
+}
+
+fn parse_existing_file(value: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(value);
+    if !path.is_file() {
+        return Err(format!("file '{}' does not exist", value));
+    }
+    Ok(path)
+}
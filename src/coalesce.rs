@@ -0,0 +1,175 @@
+//! Coalescing of identical concurrent GET/HEAD requests (singleflight)
+//!
+//! When a hot page's cache entry expires, every client asking for it at that
+//! moment would reach the PHP worker. With `COALESCE_REQUESTS=true` only the
+//! first request for a key (method, host, path and query) is forwarded; the
+//! ones arriving while it is in flight wait for its response and get a copy.
+//!
+//! Only anonymous requests are coalesced: a request carrying `Cookie` or
+//! `Authorization` is always forwarded on its own. A response that sets a
+//! cookie, is marked `private` / `no-store` / `no-cache` or carries `Vary`
+//! goes to the initiating request only, and so does a failure; the waiting
+//! requests then make their own bridge calls. The key leaves out the request
+//! headers a `Vary` would name, so such a response may not suit them.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use hyper::body::Bytes;
+use hyper::{header, HeaderMap, Response, StatusCode};
+use tokio::sync::watch;
+
+use crate::metrics::{metrics, MetricKind};
+
+/// Response handed to the requests that waited for it
+#[derive(Debug)]
+pub struct SharedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    /// Request id of the request that was forwarded
+    pub request_id: String,
+}
+
+impl SharedResponse {
+    /// Whether the response may be delivered to requests other than its own
+    pub fn is_shareable(status: StatusCode, headers: &HeaderMap) -> bool {
+        if headers.contains_key(header::SET_COOKIE) || headers.contains_key(header::VARY) || status.is_server_error() {
+            return false;
+        }
+        !headers.get_all(header::CACHE_CONTROL).iter().any(|value| {
+            value.to_str().map_or(true, |value| {
+                let value = value.to_ascii_lowercase();
+                value.contains("private") || value.contains("no-store") || value.contains("no-cache")
+            })
+        })
+    }
+
+    /// Copy of the response for one waiting request
+    pub fn to_response(&self) -> Response<hyper::Body> {
+        let mut response = Response::new(hyper::Body::from(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+/// Result of a flight as seen by the waiting requests
+#[derive(Debug, Clone)]
+pub enum FlightResult {
+    Shared(Arc<SharedResponse>),
+    /// Not shareable; every waiting request goes to the bridge itself
+    NotShared,
+}
+
+/// How a request takes part in coalescing
+pub enum Role {
+    /// First request for the key; forward it and [`Leader::complete`] the flight
+    Leader(Leader),
+    /// Wait for the leader with [`Follower::wait`]
+    Follower(Follower),
+}
+
+/// Requests in flight by key
+#[derive(Default)]
+pub struct Coalescer {
+    flights: Mutex<HashMap<String, watch::Receiver<Option<FlightResult>>>>,
+}
+
+impl Coalescer {
+    pub fn new() -> Arc<Self> {
+        metrics().describe(
+            "http_coalesced_requests_total",
+            MetricKind::Counter,
+            "Requests that waited for an identical request in flight, by outcome (hit, fallthrough, timeout)",
+        );
+        Arc::new(Self::default())
+    }
+
+    /// Key for a request, or `None` when it must not be coalesced
+    pub fn key(method: &str, uri: &str, headers: &HashMap<String, String>) -> Option<String> {
+        if !matches!(method, "GET" | "HEAD") {
+            return None;
+        }
+        if headers.keys().any(|name| name.eq_ignore_ascii_case("cookie") || name.eq_ignore_ascii_case("authorization")) {
+            return None;
+        }
+        let host = headers.get("host").map(String::as_str).unwrap_or("");
+        Some(format!("{} {}{}", method, host, uri))
+    }
+
+    /// Join the flight for `key`, starting it when there is none
+    pub fn join(self: &Arc<Self>, key: String) -> Role {
+        let mut flights = self.lock();
+        if let Some(receiver) = flights.get(&key) {
+            return Role::Follower(Follower {
+                receiver: receiver.clone(),
+            });
+        }
+
+        let (sender, receiver) = watch::channel(None);
+        flights.insert(key.clone(), receiver);
+        Role::Leader(Leader {
+            coalescer: self.clone(),
+            key,
+            sender,
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, watch::Receiver<Option<FlightResult>>>> {
+        self.flights.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The request that is forwarded on behalf of the flight
+///
+/// Dropping it without completing (e.g. when the client goes away) releases
+/// the waiting requests to make their own bridge calls.
+pub struct Leader {
+    coalescer: Arc<Coalescer>,
+    key: String,
+    sender: watch::Sender<Option<FlightResult>>,
+}
+
+impl Leader {
+    pub fn complete(self, result: FlightResult) {
+        // Later requests start a new flight rather than reusing this result
+        self.coalescer.lock().remove(&self.key);
+        let _ = self.sender.send(Some(result));
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        let mut flights = self.coalescer.lock();
+        if flights.get(&self.key).is_some_and(|receiver| receiver.same_channel(&self.sender.subscribe())) {
+            flights.remove(&self.key);
+        }
+    }
+}
+
+/// A request waiting for the leader of its flight
+pub struct Follower {
+    receiver: watch::Receiver<Option<FlightResult>>,
+}
+
+impl Follower {
+    /// Leader's result; `NotShared` as well when the leader went away
+    ///
+    /// `None` when nothing arrived within `timeout`.
+    pub async fn wait(mut self, timeout: std::time::Duration) -> Option<FlightResult> {
+        let result = match tokio::time::timeout(timeout, self.receiver.wait_for(Option::is_some)).await {
+            Err(_) => None,
+            Ok(Ok(result)) => result.clone(),
+            Ok(Err(_)) => Some(FlightResult::NotShared),
+        };
+
+        let outcome = match &result {
+            Some(FlightResult::Shared(_)) => "hit",
+            Some(FlightResult::NotShared) => "fallthrough",
+            None => "timeout",
+        };
+        metrics().inc_counter("http_coalesced_requests_total", &[("outcome", outcome)]);
+        result
+    }
+}
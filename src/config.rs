@@ -0,0 +1,204 @@
+//! Application configuration
+//!
+//! `AppConfig::from_env()` reads the listener, PHP worker, connection, pool
+//! and retry settings from the environment, after the config file layers
+//! and CLI flags have been applied to it (see [`config_loader`]). A set
+//! variable that does not parse is an error naming it, never a silent
+//! default; checks that span several settings are in
+//! [`config_validation`](crate::config_validation).
+//!
+//! [`config_loader`]: crate::config_loader
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+
+/// Default path of the PHP worker's Unix socket
+pub const DEFAULT_SOCKET_PATH: &str = "/tmp/rust_php_bridge.sock";
+
+/// HTTP listener settings
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// Socket of the PHP worker, for the startup log
+    pub socket_path: String,
+}
+
+/// Log output settings
+#[derive(Debug, Clone)]
+pub struct LoggingConfig {
+    pub level: String,
+    pub dir: String,
+}
+
+/// How the PHP worker is started
+#[derive(Debug, Clone)]
+pub struct PhpWorkerConfig {
+    pub php_path: String,
+    pub laravel_path: String,
+    /// Artisan command that runs the worker
+    pub startup_command: String,
+}
+
+/// Connection to the PHP worker
+#[derive(Debug, Clone)]
+pub struct ConnectionConfig {
+    pub socket_path: String,
+    /// How often shutdown waits check whether the worker is gone
+    pub shutdown_check_interval: Duration,
+    pub connection_timeout: Duration,
+}
+
+/// Pool of connections to the PHP worker
+#[derive(Debug, Clone)]
+pub struct ConnectionPoolConfig {
+    pub min_connections: usize,
+    pub max_connections: usize,
+    pub connection_timeout: Duration,
+    /// Pooled connections idle for longer are closed instead of reused
+    pub health_check_interval: Duration,
+}
+
+/// Backoff for filling the connection pool at startup
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+/// Everything the binary and embedders configure through the environment
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    pub server: ServerConfig,
+    pub logging: LoggingConfig,
+    pub php_worker: PhpWorkerConfig,
+    pub connection: ConnectionConfig,
+    pub connection_pool: ConnectionPoolConfig,
+    pub retry: RetryConfig,
+}
+
+impl AppConfig {
+    /// Read the configuration from the environment
+    ///
+    /// Fails with every variable that is set to a value that does not parse.
+    pub fn from_env() -> Result<Self> {
+        let mut env = EnvReader::default();
+        let config = Self::read(&mut env);
+        env.finish("configuration")?;
+        Ok(config)
+    }
+
+    fn read(env: &mut EnvReader) -> Self {
+        let socket_path = env.string("SOCKET_PATH", DEFAULT_SOCKET_PATH);
+        let connection_timeout = Duration::from_secs(env.parse("SOCKET_CONNECTION_TIMEOUT", 5));
+        Self {
+            server: ServerConfig::read(env),
+            logging: LoggingConfig {
+                level: env.string("LOG_LEVEL", "info"),
+                dir: env.string("LOG_DIR", "./logs"),
+            },
+            php_worker: PhpWorkerConfig {
+                php_path: env.string("PHP_PATH", "php"),
+                laravel_path: env.string("LARAVEL_PATH", ".."),
+                startup_command: env.string("STARTUP_COMMAND", "laravel-rust:serve"),
+            },
+            connection: ConnectionConfig {
+                socket_path,
+                shutdown_check_interval: Duration::from_millis(100),
+                connection_timeout,
+            },
+            connection_pool: ConnectionPoolConfig {
+                min_connections: env.parse("SOCKET_POOL_MIN", 2),
+                max_connections: env.parse("SOCKET_POOL_MAX", 10),
+                connection_timeout,
+                health_check_interval: Duration::from_secs(env.parse("SOCKET_HEALTH_CHECK_INTERVAL", 30)),
+            },
+            retry: RetryConfig {
+                max_attempts: env.parse("RETRY_MAX_ATTEMPTS", 5),
+                base_delay: Duration::from_millis(env.parse("RETRY_BASE_DELAY_MS", 500)),
+                max_delay: Duration::from_secs(env.parse("RETRY_MAX_DELAY_SECS", 30)),
+            },
+        }
+    }
+
+    /// Check the values against each other
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        if self.server.port == 0 {
+            problems.push("HTTP_PORT: must be a port number, not 0".to_string());
+        }
+        if self.connection_pool.max_connections == 0 {
+            problems.push("SOCKET_POOL_MAX: must be at least 1".to_string());
+        }
+        if self.connection_pool.min_connections > self.connection_pool.max_connections {
+            problems.push(format!(
+                "SOCKET_POOL_MIN: {} is more than SOCKET_POOL_MAX ({})",
+                self.connection_pool.min_connections, self.connection_pool.max_connections
+            ));
+        }
+        if self.retry.max_attempts == 0 {
+            problems.push("RETRY_MAX_ATTEMPTS: must be at least 1".to_string());
+        }
+        if !problems.is_empty() {
+            bail!("Invalid configuration: {}", problems.join("; "));
+        }
+        Ok(())
+    }
+}
+
+impl ServerConfig {
+    /// Read the listener settings from the environment
+    pub fn from_env() -> Result<Self> {
+        let mut env = EnvReader::default();
+        let config = Self::read(&mut env);
+        env.finish("server settings")?;
+        Ok(config)
+    }
+
+    fn read(env: &mut EnvReader) -> Self {
+        Self {
+            host: env.string("HTTP_HOST", "127.0.0.1"),
+            port: env.parse("HTTP_PORT", 8080),
+            socket_path: env.string("SOCKET_PATH", DEFAULT_SOCKET_PATH),
+        }
+    }
+}
+
+/// Reads variables, collecting one problem per value that does not parse
+#[derive(Default)]
+pub(crate) struct EnvReader {
+    problems: Vec<(&'static str, String)>,
+}
+
+impl EnvReader {
+    /// Value of `env`, or `default` when it is unset or empty
+    pub(crate) fn string(&mut self, env: &'static str, default: &str) -> String {
+        std::env::var(env).ok().filter(|value| !value.is_empty()).unwrap_or_else(|| default.to_string())
+    }
+
+    /// Parsed value of `env`, or `default` when it is unset or empty
+    pub(crate) fn parse<T: FromStr>(&mut self, env: &'static str, default: T) -> T
+    where
+        T::Err: std::fmt::Display,
+    {
+        match std::env::var(env).ok().filter(|value| !value.trim().is_empty()) {
+            None => default,
+            Some(value) => value.trim().parse().unwrap_or_else(|e| {
+                self.problems.push((env, format!("cannot parse {:?}: {}", value, e)));
+                default
+            }),
+        }
+    }
+
+    /// Fail with every problem found while reading `what`
+    fn finish(self, what: &str) -> Result<()> {
+        if self.problems.is_empty() {
+            return Ok(());
+        }
+        let problems: Vec<String> = self.problems.into_iter().map(|(env, p)| format!("{}: {}", env, p)).collect();
+        bail!("Invalid {}: {}", what, problems.join("; "))
+    }
+}
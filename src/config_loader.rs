@@ -0,0 +1,999 @@
+//! Layered configuration sources
+//!
+//! `AppConfig::from_env()` reads everything from environment variables. This
+//! module adds a TOML/YAML configuration file underneath them: every known
+//! key in the file is exported to its environment variable unless that
+//! variable is already set, so the precedence is
+//! process env > `.env` > config file > compiled-in defaults, and
+//! `AppConfig` keeps a single loading path.
+//!
+//! [`Provenance`] records which layer supplied each value, for `config show`,
+//! and [`ConfigLayers`] re-resolves the layers from disk on SIGHUP.
+//!
+//! Duration settings accept human-readable values ("250ms", "5s", "2m").
+//! [`normalize_durations`] rewrites them to the bare integers the readers
+//! expect, so every duration goes through [`parse_duration`].
+//!
+//! `APP_PROFILE` selects a [`Profile`] whose defaults sit between the config
+//! file and the compiled-in defaults.
+//!
+//! Secret settings can instead be given as `<NAME>_FILE` pointing at a file
+//! (Docker/Kubernetes secrets); see [`resolve_secret_files`].
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use tracing::{info, warn};
+
+use crate::{favicon, header_scrub, static_cache};
+
+/// A configuration key known to the binary
+#[derive(Debug, Clone, Copy)]
+pub struct Setting {
+    /// Dotted key in the config file (`section.name`)
+    pub key: &'static str,
+    /// Environment variable read by the config structs
+    pub env: &'static str,
+    /// Compiled-in default, if any
+    pub default: Option<&'static str>,
+    /// What the setting does
+    pub description: &'static str,
+}
+
+impl Setting {
+    /// Whether the setting is a whole `[section]` of free-form keys, stored as JSON
+    pub fn is_table(&self) -> bool {
+        !self.key.contains('.')
+    }
+}
+
+const fn setting(
+    key: &'static str,
+    env: &'static str,
+    default: Option<&'static str>,
+    description: &'static str,
+) -> Setting {
+    Setting {
+        key,
+        env,
+        default,
+        description,
+    }
+}
+
+/// Every setting understood by the binary, grouped by config file section
+pub static SETTINGS: &[Setting] = &[
+    // [app]
+    setting("app.profile", "APP_PROFILE", Some("prod"), "Defaults profile: dev (verbose, no caching, short timeouts) or prod"),
+    setting("app.debug", "APP_DEBUG", Some("false"), "Show the full error chain, failing component and timing in error responses"),
+    setting("app.error_format", "ERROR_FORMAT", Some("json"), "Format of error responses: json or problem (RFC 9457 application/problem+json)"),
+    // [server]
+    setting("server.host", "HTTP_HOST", Some("127.0.0.1"), "Host for the Rust HTTP server"),
+    setting("server.port", "HTTP_PORT", Some("8080"), "Port for the Rust HTTP server"),
+    setting("server.tls_enabled", "TLS_ENABLED", Some("false"), "Serve HTTPS on the HTTP listener with TLS_CERT_PATH and TLS_KEY_PATH"),
+    setting("server.tls_cert_path", "TLS_CERT_PATH", None, "PEM file with the certificate chain, leaf first"),
+    setting("server.tls_key_path", "TLS_KEY_PATH", None, "PEM file with the private key of the certificate"),
+    setting("server.tls_client_ca", "TLS_CLIENT_CA", None, "PEM bundle of the CAs client certificates are verified against; clients are asked for a certificate when set"),
+    setting("server.tls_client_auth", "TLS_CLIENT_AUTH", Some("require"), "With TLS_CLIENT_CA: require refuses clients without a valid certificate, optional lets clients without one in"),
+    setting("server.unavailable_retry_after_secs", "UNAVAILABLE_RETRY_AFTER_SECS", Some("5"), "Retry-After sent with 503 responses when no better estimate is known"),
+    setting("server.coalesce_requests", "COALESCE_REQUESTS", Some("false"), "Let identical concurrent GET/HEAD requests without cookies or Authorization share one PHP worker response"),
+    setting("server.backend", "BACKEND", Some("worker"), "worker to use the long-lived PHP worker at SOCKET_PATH, fastcgi to send requests to php-fpm"),
+    setting("server.request_hook_timeout_ms", "REQUEST_HOOK_TIMEOUT_MS", Some("100"), "Watchdog for embedder request/response hooks; a slower hook is logged and skipped"),
+    setting("server.trusted_proxies", "TRUSTED_PROXIES", None, "Comma-separated addresses or CIDR networks of proxies whose X-Forwarded-For names the client"),
+    setting("server.untrusted_headers", "UNTRUSTED_HEADERS", Some(header_scrub::DEFAULT_UNTRUSTED_HEADERS), "Comma-separated request headers removed unless the peer is in TRUSTED_PROXIES; X-Forwarded-For is replaced by the peer address"),
+    setting("server.internal_headers", "INTERNAL_HEADERS", Some(header_scrub::DEFAULT_INTERNAL_HEADERS), "Comma-separated request headers removed from every client request"),
+    setting("server.raise_nofile", "RAISE_NOFILE", Some("false"), "Raise the soft open file limit toward the hard limit at startup when it is below the estimate for this config"),
+    setting("server.accept_error_backoff_ms", "ACCEPT_ERROR_BACKOFF_MS", Some("100"), "Pause before accepting connections again after running out of file descriptors (EMFILE/ENFILE)"),
+    setting("server.priority_paths", "PRIORITY_PATHS", None, "Comma-separated exact paths of health checks served by Laravel that bypass the bridge concurrency limits"),
+    setting("server.dynamic_etag", "DYNAMIC_ETAG", Some("false"), "Add weak ETags to cacheable GET responses of the PHP worker and answer matching If-None-Match with 304"),
+    setting("server.dynamic_etag_max_body_bytes", "DYNAMIC_ETAG_MAX_BODY_BYTES", Some("1048576"), "Larger response bodies get no ETag, to bound the hashing cost"),
+    setting("server.html_inject_snippet", "HTML_INJECT_SNIPPET", None, "Snippet inserted before </body> of HTML pages served by the PHP worker"),
+    setting("server.html_inject_snippet_file", "HTML_INJECT_SNIPPET_FILE", None, "File whose contents are inserted before </body> of HTML pages, read at startup"),
+    setting("server.html_transform_max_body_bytes", "HTML_TRANSFORM_MAX_BODY_BYTES", Some("1048576"), "Larger HTML pages are served without transformation"),
+    setting("server.html_transform_errors", "HTML_TRANSFORM_ERRORS", Some("false"), "Also transform HTML pages with a 4xx or 5xx status"),
+    setting("server.trailing_slash", "TRAILING_SLASH", Some("off"), "Redirect paths to one spelling: strip (/pricing/ to /pricing), add (/pricing to /pricing/) or off"),
+    setting("server.tenants", "TENANTS", None, "Other Laravel applications under path prefixes, longest prefix first: { prefix, socket, public_dir, strip_prefix, protocol }"),
+    // [ip_filter]
+    setting("ip_filter.allow", "IP_ALLOW", None, "Comma-separated addresses or CIDR networks allowed on every path; others are denied"),
+    setting("ip_filter.deny", "IP_DENY", None, "Comma-separated addresses or CIDR networks denied on every path"),
+    setting("ip_filter.rules", "IP_RULES", None, "Allow and deny lists for path prefixes, longest prefix first: { prefix, allow, deny }"),
+    setting("ip_filter.precedence", "IP_PRECEDENCE", Some("deny"), "Which list wins when an allow and a deny network of the same size contain the client: deny or allow"),
+    setting("ip_filter.denied_body", "IP_DENIED_BODY", Some("Forbidden"), "Body of the 403 sent to denied clients; JSON or HTML is sent with a matching Content-Type"),
+    // [static]
+    setting("static.public_path", "PUBLIC_PATH", None, "Directory static files are served from (defaults to LARAVEL_PATH/public)"),
+    setting("static.cache_enabled", "STATIC_CACHE_ENABLED", Some("true"), "Send long-lived Cache-Control headers for static files"),
+    setting("static.cache_rules", "STATIC_CACHE_RULES", Some(static_cache::DEFAULT_RULES), "Cache-Control rules for static files, first match wins: { pattern, cache_control, immutable }"),
+    setting("static.stream_threshold", "STATIC_STREAM_THRESHOLD", Some("1048576"), "Static files larger than this many bytes are streamed from disk instead of read into memory"),
+    setting("static.cache_default", "STATIC_CACHE_DEFAULT", Some(static_cache::DEFAULT_CACHE_CONTROL), "Cache-Control for static files matching no rule and not in a build manifest"),
+    setting("static.cache_immutable", "STATIC_CACHE_IMMUTABLE", Some(static_cache::DEFAULT_IMMUTABLE_CACHE_CONTROL), "Cache-Control for assets named in the Vite or Mix manifest and matching no rule"),
+    setting("static.symlink_roots", "STATIC_SYMLINK_ROOTS", Some("storage/app/public"), "Comma-separated directories outside public/ that symlinks in it may point into, relative to the Laravel root unless absolute; other links are not followed"),
+    setting("static.sniff_content_type", "STATIC_SNIFF_CONTENT_TYPE", Some("true"), "Detect the content type of static files without a known extension from their first bytes"),
+    setting("static.text_charset", "STATIC_TEXT_CHARSET", None, "Charset appended to text content types of static files that have none, e.g. utf-8"),
+    setting("static.favicon_fallback", "FAVICON_FALLBACK", Some("not_found"), "Answer for /favicon.ico and /apple-touch-icon*.png missing from public/: not_found, no_content or default (a built-in icon)"),
+    setting("static.favicon_cache_control", "FAVICON_CACHE_CONTROL", Some(favicon::DEFAULT_CACHE_CONTROL), "Cache-Control sent with the answer for a missing favicon or touch icon"),
+    setting("static.dir_listing", "STATIC_DIR_LISTING", Some("false"), "HTML listings of directories under public/ (development only: requires the dir-listing feature and APP_PROFILE=dev)"),
+    setting("static.dir_listing_show_hidden", "STATIC_DIR_LISTING_SHOW_HIDDEN", Some("false"), "Include names starting with . in directory listings"),
+    setting("static.manifest_reload_ms", "STATIC_MANIFEST_RELOAD_MS", Some("2000"), "How often the Vite/Mix manifests are checked for changes; 0 reads them only at startup"),
+    setting("static.memory_cache_bytes", "STATIC_MEMORY_CACHE_BYTES", Some("0"), "Memory for the contents of static files up to STATIC_STREAM_THRESHOLD, served with an ETag; 0 reads them from disk on every request"),
+    setting("static.memory_cache_rescan_ms", "STATIC_MEMORY_CACHE_RESCAN_MS", Some("2000"), "How often static files in memory are checked on disk and evicted if changed; 0 leaves it to POST /admin/cache/static/flush"),
+    // [connection]
+    setting("connection.socket_path", "SOCKET_PATH", Some("/tmp/rust_php_bridge.sock"), "Path to the PHP worker Unix socket"),
+    setting("connection.pool_min", "SOCKET_POOL_MIN", Some("2"), "Minimum number of pooled bridge connections"),
+    setting("connection.pool_max", "SOCKET_POOL_MAX", Some("10"), "Maximum number of pooled bridge connections"),
+    setting("connection.pool_max_uses", "SOCKET_POOL_MAX_USES", Some("0"), "Requests a pooled bridge connection carries before it is closed and replaced (0: no limit)"),
+    setting("connection.goodbye_frame", "SOCKET_GOODBYE_FRAME", Some("false"), "Send the PHP worker a goodbye command before closing an idle connection; only for workers that understand it"),
+    setting("connection.connection_timeout", "SOCKET_CONNECTION_TIMEOUT", Some("5"), "Bridge connection timeout in seconds"),
+    setting("connection.health_check_interval", "SOCKET_HEALTH_CHECK_INTERVAL", Some("30"), "Pooled worker connections idle for longer than this many seconds are closed instead of reused"),
+    setting("connection.read_timeout_ms", "SOCKET_READ_TIMEOUT_MS", Some("30000"), "Maximum time to wait for the PHP worker's response to one request"),
+    setting("connection.max_concurrent_frames", "SOCKET_MAX_CONCURRENT_FRAMES", Some("256"), "Requests in flight to the PHP worker at once"),
+    setting("connection.max_frame_size", "SOCKET_MAX_FRAME_SIZE", Some("16777216"), "Largest request frame sent to the PHP worker, in bytes"),
+    setting("connection.priority_frames", "SOCKET_PRIORITY_FRAMES", Some("2"), "Requests to PRIORITY_PATHS in flight to the PHP worker at once, outside the other limits"),
+    setting("connection.retry_idempotent", "SOCKET_RETRY_IDEMPOTENT", Some("false"), "Resend GET/HEAD/OPTIONS requests once when connecting to the PHP worker fails"),
+    setting("connection.response_validation", "SOCKET_RESPONSE_VALIDATION", Some("lenient"), "Malformed PHP worker responses: lenient serves them through the parser fallbacks and counts them, strict answers 502"),
+    setting("connection.swap_watch_interval_ms", "SOCKET_SWAP_WATCH_INTERVAL_MS", Some("1000"), "How often the socket symlink is re-resolved (0 disables)"),
+    setting("connection.worker_protocol", "WORKER_PROTOCOL", Some("laravel-rust"), "Frame shape the PHP worker speaks: laravel-rust, octane for workers written against Laravel Octane's Swoole request and response, or psr7 for RoadRunner PSR-7 workers, which connect to SOCKET_PATH"),
+    setting("connection.discovery_dir", "SOCKET_DISCOVERY_DIR", None, "Directory of PHP worker sockets (*.sock) to spread requests over instead of SOCKET_PATH"),
+    setting("connection.discovery_interval_ms", "SOCKET_DISCOVERY_INTERVAL_MS", Some("1000"), "How often SOCKET_DISCOVERY_DIR is rescanned and its workers pinged"),
+    setting("connection.discovery_ping_timeout_ms", "SOCKET_DISCOVERY_PING_TIMEOUT_MS", Some("1000"), "Longest wait for a discovered worker's answer to ping before it is left out of routing"),
+    // [fastcgi]
+    setting("fastcgi.address", "FASTCGI_ADDRESS", Some("127.0.0.1:9000"), "php-fpm address: host:port, or a Unix socket path (optionally prefixed with unix:)"),
+    setting("fastcgi.script_filename", "FASTCGI_SCRIPT_FILENAME", None, "Script php-fpm runs for every request, as seen by php-fpm (defaults to LARAVEL_PATH/public/index.php)"),
+    setting("fastcgi.connect_timeout_ms", "FASTCGI_CONNECT_TIMEOUT_MS", Some("1000"), "Timeout for connecting to php-fpm"),
+    setting("fastcgi.read_timeout_ms", "FASTCGI_READ_TIMEOUT_MS", Some("30000"), "Maximum time for one php-fpm request, including the wait for a free connection"),
+    setting("fastcgi.max_connections", "FASTCGI_MAX_CONNECTIONS", Some("32"), "Requests sent to php-fpm at once; connections are kept open and reused"),
+
+    // [adaptive_concurrency]
+    setting("adaptive_concurrency.enabled", "ADAPTIVE_CONCURRENCY", Some("false"), "Adjust the limit of requests in flight to the PHP worker from its latency, shedding the rest with 503"),
+    setting("adaptive_concurrency.initial", "ADAPTIVE_CONCURRENCY_INITIAL", Some("4"), "Adaptive limit at startup; keep it below the worker's capacity"),
+    setting("adaptive_concurrency.min", "ADAPTIVE_CONCURRENCY_MIN", Some("1"), "Lowest adaptive limit"),
+    setting("adaptive_concurrency.max", "ADAPTIVE_CONCURRENCY_MAX", Some("256"), "Highest adaptive limit"),
+    setting("adaptive_concurrency.latency_tolerance", "ADAPTIVE_CONCURRENCY_LATENCY_TOLERANCE", Some("2.0"), "Latency above this multiple of the baseline shrinks the limit"),
+    setting("adaptive_concurrency.backoff", "ADAPTIVE_CONCURRENCY_BACKOFF", Some("0.9"), "Factor the limit is multiplied by when it shrinks"),
+    setting("adaptive_concurrency.window_ms", "ADAPTIVE_CONCURRENCY_WINDOW_MS", Some("1000"), "Measurement window after which the limit is adjusted"),
+    // [restart_park]
+    setting("restart_park.timeout_ms", "RESTART_PARK_TIMEOUT_MS", Some("0"), "How long requests are held while the PHP worker restarts or its socket is swapped, instead of failing with 503 (0 disables)"),
+    setting("restart_park.max_requests", "RESTART_PARK_MAX_REQUESTS", Some("256"), "Requests held at once while the PHP worker restarts; further ones fail as before"),
+    setting("restart_park.max_body_bytes", "RESTART_PARK_MAX_BODY_BYTES", Some("16777216"), "Total request frame bytes held at once while the PHP worker restarts"),
+    setting("restart_park.min_remaining_ms", "RESTART_PARK_MIN_REMAINING_MS", Some("1000"), "Requests with less of their deadline left are not held, and held ones are released with at least this much left"),
+    // [retry]
+    setting("retry.max_attempts", "RETRY_MAX_ATTEMPTS", Some("5"), "Attempts when initializing the connection pool"),
+    setting("retry.base_delay_ms", "RETRY_BASE_DELAY_MS", Some("500"), "Initial retry backoff in milliseconds"),
+    setting("retry.max_delay_secs", "RETRY_MAX_DELAY_SECS", Some("30"), "Maximum retry backoff in seconds"),
+    // [worker]
+    setting("worker.php_path", "PHP_PATH", Some("php"), "Path to the PHP executable"),
+    setting("worker.laravel_path", "LARAVEL_PATH", None, "Path to the Laravel application (defaults to the parent of the working directory)"),
+    setting("worker.startup_command", "STARTUP_COMMAND", Some("laravel-rust:serve"), "Artisan command that starts the PHP worker"),
+    setting("worker.auto_restart", "PHP_WORKER_AUTO_RESTART", Some("true"), "Restart the PHP worker automatically when it exits"),
+    setting("worker.poll_interval_ms", "PHP_WORKER_POLL_INTERVAL_MS", Some("500"), "How often the supervisor checks whether the PHP worker is alive"),
+    setting("worker.restart_delay_ms", "PHP_WORKER_RESTART_DELAY_MS", Some("1000"), "Delay before restarting an exited PHP worker"),
+    setting("worker.restart_history", "PHP_WORKER_RESTART_HISTORY", Some("10"), "Number of recent restarts kept in stats"),
+    setting("worker.restart_alert_per_minute", "PHP_WORKER_RESTART_ALERT_PER_MINUTE", Some("5"), "Restarts per minute that trigger an error-level event"),
+    setting("worker.stall_timeout", "PHP_WORKER_STALL_TIMEOUT", Some("60"), "Seconds without an answer from the PHP worker, with requests in flight, before it is pinged and restarted if the ping fails (0 disables)"),
+    setting("worker.stall_ping_timeout_ms", "PHP_WORKER_STALL_PING_TIMEOUT_MS", Some("1000"), "How long a possibly stalled PHP worker has to answer the ping"),
+    setting("worker.stall_kill_grace_ms", "PHP_WORKER_STALL_KILL_GRACE_MS", Some("2000"), "Time between SIGTERM and SIGKILL for a stalled PHP worker"),
+    setting("worker.nice", "PHP_WORKER_NICE", None, "Niceness applied to the PHP worker"),
+    setting("worker.rlimit_as", "PHP_WORKER_RLIMIT_AS", None, "Address space limit for the PHP worker (K/M/G suffixes allowed)"),
+    setting("worker.rlimit_nofile", "PHP_WORKER_RLIMIT_NOFILE", None, "Open file limit for the PHP worker"),
+    setting("worker.cgroup", "PHP_WORKER_CGROUP", None, "cgroup v2 directory the PHP worker is moved into"),
+    setting("worker.cpu_max", "PHP_WORKER_CPU_MAX", None, "Value written to cpu.max of the worker cgroup"),
+    setting("worker.memory_max", "PHP_WORKER_MEMORY_MAX", None, "Value written to memory.max of the worker cgroup"),
+    setting("worker.limits_on_failure", "PHP_WORKER_LIMITS_ON_FAILURE", Some("warn"), "warn or fail when a worker limit cannot be applied"),
+    // [logging]
+    setting("logging.level", "LOG_LEVEL", Some("info"), "Log level (trace, debug, info, warn, error)"),
+    setting("logging.dir", "LOG_DIR", Some("./logs"), "Directory for log files"),
+    setting("logging.format", "LOG_FORMAT", Some("text"), "Log output format: text, or json for one JSON object per line"),
+    setting("logging.rotation", "LOG_ROTATION", Some("daily"), "When server.log is rotated: daily, hourly, never or size:<bytes> (e.g. size:100MB)"),
+    setting("logging.max_files", "LOG_MAX_FILES", Some("7"), "Rotated log files kept; older ones are deleted"),
+    setting("logging.throttle_window_ms", "LOG_THROTTLE_WINDOW_MS", Some("10000"), "Window in which repeated errors of one kind are logged once and then summarized; 0 disables"),
+    setting("logging.throttle_burst", "LOG_THROTTLE_BURST", Some("1"), "Errors of one kind logged per window before the rest are suppressed"),
+    setting("logging.quiet_paths", "QUIET_PATHS", Some("/healthz,/readyz"), "Comma-separated paths (or prefixes ending in *) logged only at trace level and counted apart from other requests"),
+    // [recording]
+    setting("recording.enabled", "RECORD_ENABLED", Some("false"), "Record every request that reaches the backend to RECORD_DIR, for debugging"),
+    setting("recording.header_secret", "RECORD_HEADER_SECRET", None, "Record single requests that carry X-Bridge-Record with this value (at least 16 characters)"),
+    setting("recording.dir", "RECORD_DIR", Some("./logs/recordings"), "Directory recorded requests are written to, one JSON file each"),
+    setting("recording.max_body_bytes", "RECORD_MAX_BODY_BYTES", Some("65536"), "Request and response bodies are cut at this size in recordings"),
+    setting("recording.max_files", "RECORD_MAX_FILES", Some("1000"), "Recordings kept; older ones are deleted"),
+    // [startup]
+    setting("startup.block_until_ready", "STARTUP_BLOCK_UNTIL_READY", Some("false"), "Wait for the PHP worker before binding the HTTP listener"),
+    setting("startup.wait_max_attempts", "SOCKET_WAIT_MAX_ATTEMPTS", Some("10"), "Readiness probe attempts per round"),
+    setting("startup.wait_interval_ms", "SOCKET_WAIT_INTERVAL_MS", Some("250"), "Delay between readiness probe attempts"),
+    setting("startup.self_test", "SELF_TEST", Some("false"), "Before reporting ready, send a GET through the full request pipeline and require a 2xx/3xx response"),
+    setting("startup.self_test_path", "SELF_TEST_PATH", Some("/up"), "Path of the self-test GET request"),
+    setting("startup.self_test_timeout_ms", "SELF_TEST_TIMEOUT_MS", Some("5000"), "Time the self-test request may take"),
+    setting("startup.self_test_on_failure", "SELF_TEST_ON_FAILURE", Some("abort"), "abort to shut down when the self-test fails, degraded to keep running with /readyz failing and retry"),
+    // [shutdown]
+    setting("shutdown.notify_timeout_ms", "SHUTDOWN_NOTIFY_TIMEOUT_MS", Some("2000"), "Timeout for the terminating notification sent to Laravel"),
+    setting("shutdown.drain_timeout_ms", "SHUTDOWN_DRAIN_TIMEOUT_MS", Some("10000"), "How long SIGINT/SIGTERM wait for in-flight requests"),
+    setting("shutdown.fast_drain_timeout_ms", "SHUTDOWN_FAST_DRAIN_TIMEOUT_MS", Some("1000"), "How long SIGQUIT waits for in-flight requests"),
+    setting("shutdown.drain_grace_ms", "DRAIN_GRACE_MS", Some("5000"), "In drain mode (POST /admin/drain, SIGUSR2), how long connections opened before the drain are still served"),
+    // [upgrade]
+    setting("upgrade.worker", "UPGRADE_WORKER", Some("handover"), "PHP worker on a binary upgrade: handover to keep it running under the new process, restart to let the new process start its own"),
+    setting("upgrade.timeout_ms", "UPGRADE_TIMEOUT_MS", Some("30000"), "How long the new process may take to become ready before the upgrade is abandoned"),
+    setting("upgrade.binary", "UPGRADE_BINARY", None, "Binary started on upgrade (defaults to the path this process was started from)"),
+    // [admin]
+    setting("admin.enabled", "ADMIN_ENABLED", Some("false"), "Enable the admin listener"),
+    setting("admin.host", "ADMIN_HOST", Some("127.0.0.1"), "Host for the admin listener"),
+    setting("admin.port", "ADMIN_PORT", Some("9090"), "Port for the admin listener"),
+    setting("admin.token", "ADMIN_TOKEN", None, "Bearer token required on every admin endpoint (at least 16 characters)"),
+    setting("admin.metrics_token", "ADMIN_METRICS_TOKEN", None, "Bearer token accepted on GET /metrics only, for Prometheus (at least 16 characters)"),
+    setting("admin.user", "ADMIN_USER", None, "User name for HTTP basic auth on the admin listener"),
+    setting("admin.password", "ADMIN_PASSWORD", None, "Password for HTTP basic auth on the admin listener"),
+    setting("admin.allow_ips", "ADMIN_ALLOW_IPS", None, "Comma-separated addresses or CIDR networks admin clients must connect from"),
+    setting("admin.allow_insecure", "ADMIN_ALLOW_INSECURE", Some("false"), "Serve a non-loopback ADMIN_HOST without credentials or ADMIN_ALLOW_IPS"),
+    setting("admin.auth_max_failures", "ADMIN_AUTH_MAX_FAILURES", Some("10"), "Failed admin requests from one address before it is answered with 429"),
+    setting("admin.auth_failure_window_ms", "ADMIN_AUTH_FAILURE_WINDOW_MS", Some("60000"), "Window in which failed admin requests are counted, and how long a locked out address waits"),
+    setting("admin.log_level_revert_ms", "ADMIN_LOG_LEVEL_REVERT_MS", Some("900000"), "Restore the log filter this long after it is changed through /admin/log-level (0 keeps the change)"),
+    setting("admin.artisan_commands", "ADMIN_ARTISAN_COMMANDS", None, "Comma-separated artisan commands POST /admin/artisan may run, each optionally followed by the options it may take (e.g. cache:clear,migrate --force)"),
+    setting("admin.artisan_timeout_ms", "ADMIN_ARTISAN_TIMEOUT_MS", Some("60000"), "Artisan commands run through the admin API are killed after this long"),
+    setting("admin.artisan_max_output_bytes", "ADMIN_ARTISAN_MAX_OUTPUT_BYTES", Some("65536"), "Standard output and error of an artisan command are each cut at this size"),
+    // [info]
+    setting("info.path", "INFO_PATH", Some("/__info"), "Path answered with build and runtime info as JSON, for deploy tooling (empty turns it off)"),
+    setting("info.listener", "INFO_LISTENER", Some("http"), "Listener that answers INFO_PATH: http or admin"),
+    setting("info.allow_ips", "INFO_ALLOW_IPS", None, "Comma-separated addresses or CIDR networks allowed to read INFO_PATH; others get 403"),
+    // [websocket]
+    setting("websocket.enabled", "WS_ENABLED", Some("false"), "Accept WebSocket clients and fan out events pushed by Laravel to them"),
+    setting("websocket.path", "WS_PATH", Some("/ws"), "Request path that accepts WebSocket upgrades"),
+    setting("websocket.push_socket", "WS_PUSH_SOCKET", Some("/tmp/rust_php_push.sock"), "Unix socket Laravel pushes broadcast events to, one JSON object per line"),
+    setting("websocket.max_connections", "WS_MAX_CONNECTIONS", Some("10000"), "Open WebSocket connections; further upgrades get 503"),
+    setting("websocket.max_subscriptions", "WS_MAX_SUBSCRIPTIONS", Some("100"), "Channels one connection may subscribe to"),
+    setting("websocket.ping_interval_ms", "WS_PING_INTERVAL_MS", Some("30000"), "Ping interval; clients that do not answer by the next ping are disconnected"),
+    setting("websocket.queue_size", "WS_QUEUE_SIZE", Some("256"), "Events queued per connection before further ones are dropped"),
+    // [grpc]
+    setting("grpc.enabled", "GRPC_ENABLED", Some("false"), "Enable the gRPC listener (requires a build with the grpc feature)"),
+    setting("grpc.host", "GRPC_HOST", Some("127.0.0.1"), "Host for the gRPC listener"),
+    setting("grpc.port", "GRPC_PORT", Some("50051"), "Port for the gRPC listener"),
+    // [chaos]
+    setting("chaos.enabled", "CHAOS_ENABLED", Some("false"), "Inject faults for resilience testing (requires a build with the chaos feature)"),
+    setting("chaos.header", "CHAOS_HEADER", None, "Only inject faults into requests carrying this header (name or name: value)"),
+    setting("chaos.connect_failure_rate", "CHAOS_CONNECT_FAILURE_RATE", Some("0"), "Probability that a frame to the PHP worker fails as if the connection was refused"),
+    setting("chaos.latency_rate", "CHAOS_LATENCY_RATE", Some("0"), "Probability that a frame to the PHP worker is delayed by latency_ms"),
+    setting("chaos.latency_ms", "CHAOS_LATENCY_MS", Some("1000"), "Delay added to frames picked by latency_rate"),
+    setting("chaos.truncated_frame_rate", "CHAOS_TRUNCATED_FRAME_RATE", Some("0"), "Probability that the PHP worker's response frame is cut short"),
+    setting("chaos.malformed_response_rate", "CHAOS_MALFORMED_RESPONSE_RATE", Some("0"), "Probability that the PHP worker's response frame is not valid JSON"),
+    setting("chaos.http_error_rate", "CHAOS_HTTP_ERROR_RATE", Some("0"), "Probability that a request is answered with http_error_status without reaching the backend"),
+    setting("chaos.http_error_status", "CHAOS_HTTP_ERROR_STATUS", Some("500"), "Status of injected HTTP errors: 500, 502, 503 or 504"),
+    // [statsd]
+    setting("statsd.addr", "STATSD_ADDR", None, "DogStatsD agent (host:port) to push metrics to over UDP; unset disables"),
+    setting("statsd.prefix", "STATSD_PREFIX", Some("laravel_rust."), "Prefix of every StatsD metric name"),
+    setting("statsd.tags", "STATSD_TAGS", None, "Comma-separated constant tags (key:value) added to every StatsD metric"),
+    setting("statsd.flush_interval_ms", "STATSD_FLUSH_INTERVAL_MS", Some("1000"), "How often aggregated metrics are sent to StatsD"),
+    setting("statsd.queue_size", "STATSD_QUEUE_SIZE", Some("10000"), "Metric samples buffered between flushes before new ones are dropped"),
+    // [privileges]
+    setting("privileges.run_as_user", "RUN_AS_USER", None, "User to switch to after binding the listeners when started as root"),
+    setting("privileges.run_as_group", "RUN_AS_GROUP", None, "Group to switch to together with run_as_user"),
+    // Tables of free-form keys; keep them last so they render after the plain sections
+    setting("response_headers", "RESPONSE_HEADERS", None, "Extra headers added to every response: name = \"value\" or name = { value = \"...\", mode = \"append\" | \"replace\" }"),
+    setting("mime", "MIME_TYPES", None, "Content types of static files by extension, added to or replacing the built-in table: wasm = \"application/wasm\""),
+];
+
+/// Look up a setting by its dotted config file key
+pub fn find_setting(key: &str) -> Option<&'static Setting> {
+    SETTINGS.iter().find(|s| s.key == key)
+}
+
+/// Look up a setting by the environment variable it is read from
+pub fn find_setting_by_env(env: &str) -> Option<&'static Setting> {
+    SETTINGS.iter().find(|s| s.env == env)
+}
+
+/// Unit a duration setting is read in once normalized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DurationUnit {
+    Millis,
+    Secs,
+}
+
+/// Duration settings stored in whole seconds; all `*_MS` settings are milliseconds
+const SECONDS_SETTINGS: &[&str] = &[
+    "SOCKET_CONNECTION_TIMEOUT",
+    "SOCKET_HEALTH_CHECK_INTERVAL",
+    "RETRY_MAX_DELAY_SECS",
+    "UNAVAILABLE_RETRY_AFTER_SECS",
+];
+
+const DURATION_FORMATS: &str = "a number with a unit (\"250ms\", \"5s\", \"2m\", \"1h\")";
+
+/// Unit of a duration setting, or `None` if the variable is not a duration
+pub fn duration_unit(env: &str) -> Option<DurationUnit> {
+    if SECONDS_SETTINGS.contains(&env) {
+        Some(DurationUnit::Secs)
+    } else if env.ends_with("_MS") {
+        Some(DurationUnit::Millis)
+    } else {
+        None
+    }
+}
+
+/// Parse a duration setting
+///
+/// Accepts `<n>ms`, `<n>s`, `<n>m` and `<n>h`. A bare integer is read in the
+/// setting's own unit (milliseconds for `*_MS`), as before.
+///
+/// # Arguments
+///
+/// * `env` - variable name, used for the unit of bare integers and in errors
+/// * `value` - raw value
+pub fn parse_duration(env: &str, value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let invalid = || {
+        anyhow!(
+            "Invalid duration for {}: {:?}; expected {} or a bare integer in {}",
+            env,
+            value,
+            DURATION_FORMATS,
+            match duration_unit(env) {
+                Some(DurationUnit::Secs) => "seconds",
+                _ => "milliseconds",
+            }
+        )
+    };
+
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (digits, unit) = value.split_at(split);
+    let n: u64 = digits.parse().map_err(|_| invalid())?;
+
+    let duration = match unit.trim() {
+        "" => match duration_unit(env) {
+            Some(DurationUnit::Secs) => Duration::from_secs(n),
+            _ => Duration::from_millis(n),
+        },
+        "ms" => Duration::from_millis(n),
+        "s" => Duration::from_secs(n),
+        "m" => Duration::from_secs(n.checked_mul(60).ok_or_else(invalid)?),
+        "h" => Duration::from_secs(n.checked_mul(3600).ok_or_else(invalid)?),
+        _ => return Err(invalid()),
+    };
+    Ok(duration)
+}
+
+/// Rewrite a duration value as a bare integer in the setting's unit
+///
+/// Values of other settings are returned unchanged.
+pub fn normalize_duration(env: &str, value: &str) -> Result<String> {
+    let Some(unit) = duration_unit(env) else {
+        return Ok(value.to_string());
+    };
+    if value.trim().is_empty() {
+        return Ok(value.to_string());
+    }
+
+    let duration = parse_duration(env, value)?;
+    match unit {
+        DurationUnit::Millis => Ok(duration.as_millis().to_string()),
+        DurationUnit::Secs if duration.subsec_nanos() == 0 => Ok(duration.as_secs().to_string()),
+        DurationUnit::Secs => bail!(
+            "Invalid duration for {}: {:?}; this setting has a resolution of whole seconds",
+            env,
+            value
+        ),
+    }
+}
+
+/// Normalize every duration setting in the environment
+///
+/// # Returns
+///
+/// * `Err` - one line per invalid duration, naming the variable
+pub fn normalize_durations() -> Result<()> {
+    let mut errors = Vec::new();
+    for setting in SETTINGS {
+        let Ok(value) = std::env::var(setting.env) else {
+            continue;
+        };
+        match normalize_duration(setting.env, &value) {
+            Ok(normalized) if normalized != value => std::env::set_var(setting.env, normalized),
+            Ok(_) => {}
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        bail!("{}", errors.join("\n"))
+    }
+}
+
+/// Named set of defaults selected with `APP_PROFILE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Local development: verbose logging, no caching, short timeouts
+    Dev,
+    /// Production: the compiled-in defaults
+    Prod,
+}
+
+impl Profile {
+    /// Parse `APP_PROFILE`; unset means `prod`
+    pub fn parse(value: Option<&str>) -> Result<Self> {
+        match value.map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            None | Some("") | Some("prod") | Some("production") => Ok(Profile::Prod),
+            Some("dev") | Some("development") => Ok(Profile::Dev),
+            Some(other) => bail!("Invalid APP_PROFILE {:?}; expected dev or prod", other),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Profile::Dev => "dev",
+            Profile::Prod => "prod",
+        }
+    }
+
+    /// Values this profile sets for variables that no other layer sets
+    pub fn defaults(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Profile::Dev => &[
+                ("LOG_LEVEL", "debug"),
+                ("APP_DEBUG", "true"),
+                ("STATIC_CACHE_ENABLED", "false"),
+                ("STARTUP_BLOCK_UNTIL_READY", "false"),
+                ("SOCKET_WAIT_INTERVAL_MS", "100"),
+                ("SHUTDOWN_NOTIFY_TIMEOUT_MS", "500"),
+                ("SHUTDOWN_DRAIN_TIMEOUT_MS", "1000"),
+                ("PHP_WORKER_RESTART_DELAY_MS", "200"),
+            ],
+            Profile::Prod => &[
+                ("LOG_LEVEL", "info"),
+                ("APP_DEBUG", "false"),
+                ("STATIC_CACHE_ENABLED", "true"),
+                ("STARTUP_BLOCK_UNTIL_READY", "false"),
+                ("SHUTDOWN_DRAIN_TIMEOUT_MS", "10000"),
+            ],
+        }
+    }
+
+    /// Export the profile defaults for variables that are still unset
+    ///
+    /// # Returns
+    ///
+    /// * `(applied, overridden)` - setting keys taken from the profile, and
+    ///   profile keys already set by a higher layer
+    pub fn apply_to_env(&self) -> (Vec<&'static str>, Vec<&'static str>) {
+        let mut applied = Vec::new();
+        let mut overridden = Vec::new();
+        for (env, value) in self.defaults() {
+            let Some(setting) = find_setting_by_env(env) else {
+                continue;
+            };
+            if std::env::var_os(env).is_some() {
+                overridden.push(setting.key);
+            } else {
+                std::env::set_var(env, value);
+                applied.push(setting.key);
+            }
+        }
+        (applied, overridden)
+    }
+}
+
+/// Name fragments that mark a variable as holding a secret
+const SECRET_MARKERS: &[&str] = &["TOKEN", "SECRET", "PASSWORD", "CREDENTIALS", "DSN"];
+
+/// Whether a variable holds a secret: masked in output, accepted as `<NAME>_FILE`
+pub fn is_secret(env: &str) -> bool {
+    SECRET_MARKERS.iter().any(|marker| env.contains(marker))
+}
+
+/// Hash of the effective value of every setting, to spot drift between instances
+///
+/// Values are read as the process sees them, after all config layers were
+/// applied, falling back to the defaults. Secrets only count as set or
+/// unset, so the hash says nothing about them. Hashes are comparable
+/// between instances of the same build.
+pub fn config_fingerprint() -> String {
+    let mut hasher = DefaultHasher::new();
+    for setting in SETTINGS {
+        let value = std::env::var(setting.env).ok().or_else(|| setting.default.map(str::to_string));
+        let value = match value {
+            Some(value) if is_secret(setting.env) => (!value.is_empty()).then(|| "set".to_string()),
+            value => value,
+        };
+        (setting.env, value).hash(&mut hasher);
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+/// Resolve a secret from its value and its `<NAME>_FILE` variant
+///
+/// # Arguments
+///
+/// * `name` - variable name, used in errors
+/// * `value` - value of `<NAME>`, if set
+/// * `file` - value of `<NAME>_FILE`, if set
+///
+/// # Returns
+///
+/// * `Ok(Some(secret))` - trimmed contents of the file
+/// * `Ok(None)` - `<NAME>_FILE` is not set; use `value` as is
+/// * `Err` - both variants are set, or the file is missing, unreadable or empty
+pub fn secret_from_file(name: &str, value: Option<&str>, file: Option<&str>) -> Result<Option<String>> {
+    let Some(path) = file.filter(|p| !p.is_empty()) else {
+        return Ok(None);
+    };
+    if value.is_some() {
+        bail!("{} and {}_FILE are both set; use only one of them", name, name);
+    }
+
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Cannot read {}_FILE {}: {}", name, path, e))?;
+    let secret = contents.trim();
+    if secret.is_empty() {
+        bail!("{}_FILE {} is empty", name, path);
+    }
+    Ok(Some(secret.to_string()))
+}
+
+/// Load every secret setting given as `<NAME>_FILE` into `<NAME>`
+///
+/// # Returns
+///
+/// * `Ok(keys)` - settings whose value was read from a file
+/// * `Err` - one line per secret that could not be resolved
+pub fn resolve_secret_files() -> Result<Vec<&'static str>> {
+    let mut loaded = Vec::new();
+    let mut errors = Vec::new();
+    for setting in SETTINGS.iter().filter(|s| is_secret(s.env)) {
+        let value = std::env::var(setting.env).ok();
+        let file = std::env::var(format!("{}_FILE", setting.env)).ok();
+        match secret_from_file(setting.env, value.as_deref(), file.as_deref()) {
+            Ok(Some(secret)) => {
+                std::env::set_var(setting.env, secret);
+                loaded.push(setting.key);
+            }
+            Ok(None) => {}
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+    if errors.is_empty() {
+        Ok(loaded)
+    } else {
+        bail!("{}", errors.join("\n"))
+    }
+}
+
+/// Path of the config file from `CONFIG_PATH`, if set
+pub fn config_path_from_env() -> Option<PathBuf> {
+    std::env::var("CONFIG_PATH").ok().filter(|v| !v.is_empty()).map(PathBuf::from)
+}
+
+/// A parsed configuration file
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    /// Known keys and their values, as strings ready for the environment
+    pub values: BTreeMap<&'static str, String>,
+    /// Keys present in the file that the binary does not understand
+    pub unknown_keys: Vec<String>,
+}
+
+impl ConfigFile {
+    /// Parse a TOML (`.toml`) or YAML (`.yaml`/`.yml`) config file
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Cannot read config file {}: {}", path.display(), e))?;
+
+        let tree: serde_json::Value = match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&contents)
+                .map_err(|e| anyhow!("Invalid YAML in {}: {}", path.display(), e))?,
+            _ => toml::from_str(&contents).map_err(|e| anyhow!("Invalid TOML in {}: {}", path.display(), e))?,
+        };
+
+        Self::from_tree(path, &tree)
+    }
+
+    fn from_tree(path: &Path, tree: &serde_json::Value) -> Result<Self> {
+        let Some(sections) = tree.as_object() else {
+            bail!("Config file {} must contain a table of sections", path.display());
+        };
+
+        let mut values = BTreeMap::new();
+        let mut unknown_keys = Vec::new();
+
+        for (section, entries) in sections {
+            let Some(entries) = entries.as_object() else {
+                unknown_keys.push(section.clone());
+                continue;
+            };
+
+            if let Some(setting) = find_setting(section).filter(|s| s.is_table()) {
+                values.insert(setting.key, serde_json::Value::Object(entries.clone()).to_string());
+                continue;
+            }
+
+            for (name, value) in entries {
+                let key = format!("{}.{}", section, name);
+                let Some(setting) = find_setting(&key) else {
+                    unknown_keys.push(key);
+                    continue;
+                };
+
+                let value = match value {
+                    serde_json::Value::String(s) => s.clone(),
+                    serde_json::Value::Bool(_) | serde_json::Value::Number(_) => value.to_string(),
+                    // Lists are passed on as JSON, the format their variables take
+                    serde_json::Value::Array(_) => value.to_string(),
+                    _ => bail!(
+                        "{}: `{}` must be a string, number, boolean or list",
+                        path.display(),
+                        key
+                    ),
+                };
+                values.insert(setting.key, value);
+            }
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            values,
+            unknown_keys,
+        })
+    }
+
+    /// Export file values to the environment where the variable is not already set
+    ///
+    /// # Returns
+    ///
+    /// Keys whose value was taken from the file
+    pub fn apply_to_env(&self) -> Vec<&'static str> {
+        let mut applied = Vec::new();
+        for (key, value) in &self.values {
+            let Some(setting) = find_setting(key) else {
+                continue;
+            };
+            if std::env::var_os(setting.env).is_none() {
+                std::env::set_var(setting.env, value);
+                applied.push(setting.key);
+            }
+        }
+        applied
+    }
+
+    /// Log what was loaded and warn about keys the binary does not understand
+    pub fn log_summary(&self, applied: &[&'static str]) {
+        info!(
+            path = %self.path.display(),
+            keys = self.values.len(),
+            applied = applied.len(),
+            "Loaded config file"
+        );
+        for key in &self.unknown_keys {
+            warn!("Unknown config key `{}` in {}", key, self.path.display());
+        }
+    }
+}
+
+/// Layer that supplied the effective value of a setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    Env,
+    DotEnv,
+    File,
+    Cli,
+    /// Read from the file named by `<NAME>_FILE`
+    SecretFile,
+    /// Default of the selected `APP_PROFILE`
+    Profile,
+}
+
+impl Source {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Source::Default => "default",
+            Source::Env => "env",
+            Source::DotEnv => ".env",
+            Source::File => "file",
+            Source::Cli => "cli",
+            Source::SecretFile => "secret file",
+            Source::Profile => "profile",
+        }
+    }
+}
+
+/// Which layer each setting came from, recorded while the layers are applied
+#[derive(Debug, Clone, Default)]
+pub struct Provenance {
+    sources: HashMap<&'static str, Source>,
+}
+
+/// A setting with its effective value and where that value came from
+#[derive(Debug, Clone)]
+pub struct ResolvedSetting {
+    pub setting: &'static Setting,
+    pub value: Option<String>,
+    pub source: Source,
+}
+
+impl ResolvedSetting {
+    /// Whether the value must not be printed
+    pub fn is_secret(&self) -> bool {
+        is_secret(self.setting.env)
+    }
+
+    /// Value safe for display, with secrets masked
+    pub fn display_value(&self) -> Option<String> {
+        match &self.value {
+            Some(value) if self.is_secret() && !value.is_empty() => Some("********".to_string()),
+            other => other.clone(),
+        }
+    }
+}
+
+impl Provenance {
+    /// Attribute every setting whose variable is set and not yet attributed
+    pub fn record_present(&mut self, source: Source) {
+        for setting in SETTINGS {
+            if std::env::var_os(setting.env).is_some() {
+                self.sources.entry(setting.key).or_insert(source);
+            }
+        }
+    }
+
+    /// Attribute the given setting keys to `source`, replacing earlier layers
+    pub fn record(&mut self, keys: &[&'static str], source: Source) {
+        for key in keys {
+            self.sources.insert(key, source);
+        }
+    }
+
+    /// Attribute the settings read from the given environment variables
+    pub fn record_env(&mut self, envs: &[&str], source: Source) {
+        for env in envs {
+            if let Some(setting) = find_setting_by_env(env) {
+                self.sources.insert(setting.key, source);
+            }
+        }
+    }
+
+    /// Effective value and source of every known setting
+    pub fn resolve(&self) -> Vec<ResolvedSetting> {
+        SETTINGS
+            .iter()
+            .map(|setting| match std::env::var(setting.env) {
+                Ok(value) => ResolvedSetting {
+                    setting,
+                    value: Some(value),
+                    source: self.sources.get(setting.key).copied().unwrap_or(Source::Env),
+                },
+                Err(_) => ResolvedSetting {
+                    setting,
+                    value: setting.default.map(str::to_string),
+                    source: Source::Default,
+                },
+            })
+            .collect()
+    }
+}
+
+/// Render resolved settings as JSON grouped by section
+pub fn render_json(settings: &[ResolvedSetting]) -> String {
+    let mut sections = serde_json::Map::new();
+    for resolved in settings {
+        if resolved.setting.is_table() {
+            let value = resolved
+                .display_value()
+                .map(|v| serde_json::from_str(&v).unwrap_or(serde_json::Value::String(v)));
+            sections.insert(
+                resolved.setting.key.to_string(),
+                serde_json::json!({
+                    "value": value,
+                    "source": resolved.source.as_str(),
+                    "env": resolved.setting.env,
+                }),
+            );
+            continue;
+        }
+
+        let (section, name) = resolved.setting.key.split_once('.').unwrap_or(("", resolved.setting.key));
+        let entry = sections
+            .entry(section.to_string())
+            .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+        if let serde_json::Value::Object(entries) = entry {
+            let value = resolved.display_value().map(|v| match v.starts_with('[') {
+                true => serde_json::from_str(&v).unwrap_or(serde_json::Value::String(v)),
+                false => serde_json::Value::String(v),
+            });
+            entries.insert(
+                name.to_string(),
+                serde_json::json!({
+                    "value": value,
+                    "source": resolved.source.as_str(),
+                    "env": resolved.setting.env,
+                }),
+            );
+        }
+    }
+    serde_json::to_string_pretty(&serde_json::Value::Object(sections)).unwrap_or_default()
+}
+
+/// Render resolved settings as TOML, annotating each value with its source
+pub fn render_toml(settings: &[ResolvedSetting]) -> String {
+    let mut out = String::new();
+    let mut current_section = "";
+    for resolved in settings {
+        if resolved.setting.is_table() {
+            let key = resolved.setting.key;
+            out.push_str(&format!("\n# {} ({})\n", resolved.source.as_str(), resolved.setting.env));
+            let table = resolved
+                .display_value()
+                .and_then(|v| serde_json::from_str::<serde_json::Value>(&v).ok())
+                .and_then(|v| toml::to_string(&serde_json::json!({ key: v })).ok());
+            match table {
+                Some(table) => out.push_str(&table),
+                None => out.push_str(&format!("[{}]\n", key)),
+            }
+            current_section = key;
+            continue;
+        }
+
+        let (section, name) = resolved.setting.key.split_once('.').unwrap_or(("", resolved.setting.key));
+        if section != current_section {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!("[{}]\n", section));
+            current_section = section;
+        }
+        let note = format!("{} ({})", resolved.source.as_str(), resolved.setting.env);
+        match resolved.display_value() {
+            Some(value) => out.push_str(&format!("{} = {}  # {}\n", name, toml_literal(&value), note)),
+            None => out.push_str(&format!("# {} is not set  # {}\n", name, note)),
+        }
+    }
+    out
+}
+
+/// Annotated TOML template with every setting and its compiled-in default
+///
+/// Generated from [`SETTINGS`], so it lists exactly the keys the loader
+/// understands. Settings without a default, and whole-section settings such
+/// as `[mime]`, are emitted commented out.
+pub fn render_default_template() -> String {
+    let mut out = String::from(
+        "# Laravel Rust Bridge configuration\n\
+         #\n\
+         # Generated by `laravel-rust-server --print-default-config`. Point CONFIG_PATH\n\
+         # (or --config) at this file. Environment variables override values set here.\n",
+    );
+    let mut current_section = "";
+    for setting in SETTINGS {
+        if setting.is_table() {
+            // Commented out: an empty section would load as an empty object
+            out.push_str(&format!(
+                "\n# [{}]\n# {} (env: {}, as a JSON object)\n",
+                setting.key, setting.description, setting.env
+            ));
+            current_section = setting.key;
+            continue;
+        }
+
+        let (section, name) = setting.key.split_once('.').unwrap_or(("", setting.key));
+        if section != current_section {
+            out.push_str(&format!("\n[{}]\n", section));
+            current_section = section;
+        }
+        out.push_str(&format!("# {} (env: {})\n", setting.description, setting.env));
+        match setting.default {
+            Some(default) => out.push_str(&format!("{} = {}\n", name, toml_literal(default))),
+            None => out.push_str(&format!("# {} =\n", name)),
+        }
+    }
+    out
+}
+
+/// Format a string value as a TOML literal, keeping numbers and booleans bare
+/// and turning JSON lists back into TOML arrays
+fn toml_literal(value: &str) -> String {
+    if value == "true" || value == "false" || value.parse::<i64>().is_ok() {
+        value.to_string()
+    } else if let Some(array) = value
+        .starts_with('[')
+        .then(|| serde_json::from_str::<serde_json::Value>(value).ok())
+        .flatten()
+        .and_then(|v| toml::Value::try_from(v).ok())
+    {
+        array.to_string()
+    } else {
+        serde_json::to_string(value).unwrap_or_default()
+    }
+}
+
+/// Layers that cannot be re-read from disk, captured at startup
+///
+/// On reload the process environment has already been overwritten with
+/// `.env`, file and CLI values, so the original process values and the CLI
+/// flags are kept here to resolve the layers again in the same order.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigLayers {
+    /// Setting variables (and `<NAME>_FILE` variants) present before `.env`
+    process_env: HashMap<String, String>,
+    /// Setting variables set by command-line flags
+    cli: HashMap<&'static str, String>,
+}
+
+impl ConfigLayers {
+    /// Capture the process environment; call before `.env` is loaded
+    pub fn capture_process_env() -> Self {
+        let process_env = SETTINGS
+            .iter()
+            .chain(std::iter::once(&CONFIG_PATH_SETTING))
+            .flat_map(|s| {
+                let file_var = is_secret(s.env).then(|| format!("{}_FILE", s.env));
+                std::iter::once(s.env.to_string()).chain(file_var)
+            })
+            .filter_map(|env| std::env::var(&env).ok().map(|v| (env, v)))
+            .collect();
+        Self {
+            process_env,
+            cli: HashMap::new(),
+        }
+    }
+
+    /// Remember the variables set by command-line flags
+    pub fn set_cli(&mut self, pairs: &[(&'static str, String)]) {
+        self.cli = pairs.iter().cloned().collect();
+    }
+
+    /// Resolve every setting again from the layers, re-reading `.env` and the config file
+    ///
+    /// Does not touch the environment.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(map)` - effective value per environment variable (`None` when unset)
+    /// * `Err` - `.env` or the config file could not be read or parsed
+    pub fn resolve_fresh(&self) -> Result<BTreeMap<&'static str, Option<String>>> {
+        let dotenv: HashMap<String, String> = match dotenvy::dotenv_iter() {
+            Ok(iter) => iter
+                .collect::<std::result::Result<_, _>>()
+                .map_err(|e| anyhow!("Invalid .env file: {}", e))?,
+            Err(e) if e.not_found() => HashMap::new(),
+            Err(e) => bail!("Cannot read .env file: {}", e),
+        };
+
+        let lookup = |env: &str, file: Option<&String>| -> Option<String> {
+            self.cli
+                .get(env)
+                .or_else(|| self.process_env.get(env))
+                .or_else(|| dotenv.get(env))
+                .or(file)
+                .cloned()
+        };
+
+        let file = match lookup(CONFIG_PATH_SETTING.env, None).filter(|v| !v.is_empty()) {
+            Some(path) => Some(ConfigFile::load(Path::new(&path))?),
+            None => None,
+        };
+
+        let from_file = |s: &Setting| file.as_ref().and_then(|f| f.values.get(s.key)).cloned();
+        let profile_setting = find_setting_by_env("APP_PROFILE").expect("APP_PROFILE is registered");
+        let profile = Profile::parse(lookup(profile_setting.env, from_file(profile_setting).as_ref()).as_deref())?;
+
+        SETTINGS
+            .iter()
+            .map(|s| {
+                let profile_default = profile
+                    .defaults()
+                    .iter()
+                    .find(|(env, _)| *env == s.env)
+                    .map(|(_, value)| value.to_string());
+                let mut value = lookup(s.env, from_file(s).or(profile_default).as_ref());
+                if is_secret(s.env) {
+                    let secret_file = lookup(&format!("{}_FILE", s.env), None);
+                    if let Some(secret) = secret_from_file(s.env, value.as_deref(), secret_file.as_deref())? {
+                        value = Some(secret);
+                    }
+                }
+                let value = value.map(|v| normalize_duration(s.env, &v)).transpose()?;
+                Ok((s.env, value))
+            })
+            .collect()
+    }
+}
+
+/// `CONFIG_PATH` itself, resolved like any other variable but not part of the file
+const CONFIG_PATH_SETTING: Setting = setting("", "CONFIG_PATH", None, "Path to a TOML/YAML configuration file");
@@ -0,0 +1,641 @@
+//! Validation of the resolved configuration
+//!
+//! Runs over the environment after all layers are applied, i.e. exactly the
+//! values `AppConfig::from_env()` reads, and collects every problem instead
+//! of stopping at the first one. Each problem names the variable and config
+//! file key to fix.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config_loader::{find_setting_by_env, Profile};
+use crate::favicon::FaviconPolicy;
+use crate::header_scrub::HeaderScrub;
+use crate::html_transform::HtmlTransform;
+use crate::ip_filter::IpFilter;
+use crate::log_rotation::RotationPolicy;
+use crate::mime::MimeTypes;
+use crate::request_context::{PriorityPaths, QuietPaths};
+use crate::response_headers::ResponseHeaders;
+use crate::self_test::SelfTestConfig;
+use crate::static_cache::CachePolicy;
+use crate::tenants::TenantSpec;
+use crate::trailing_slash::TrailingSlash;
+use crate::trusted_proxies::TrustedProxies;
+use crate::worker_protocol::WorkerProtocol;
+
+/// Longest Unix socket path accepted by `sun_path`, excluding the trailing NUL
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))]
+const MAX_SOCKET_PATH_LEN: usize = 103;
+#[cfg(not(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd")))]
+const MAX_SOCKET_PATH_LEN: usize = 107;
+
+/// One error listing the problems a component found in its settings
+///
+/// `what` names the settings, e.g. "TLS settings"; each problem is shown as
+/// `VARIABLE: message`.
+pub fn settings_error(what: &str, problems: Vec<(&str, String)>) -> anyhow::Error {
+    let problems: Vec<String> = problems.into_iter().map(|(env, p)| format!("{}: {}", env, p)).collect();
+    anyhow::anyhow!("Invalid {}: {}", what, problems.join("; "))
+}
+
+/// Default for `SOCKET_WAIT_INTERVAL_MS`
+pub const DEFAULT_SOCKET_WAIT_INTERVAL_MS: u64 = 250;
+
+/// `SOCKET_WAIT_INTERVAL_MS`: delay between attempts to reach a worker that is not ready yet
+pub fn socket_wait_interval() -> Duration {
+    Duration::from_millis(
+        std::env::var("SOCKET_WAIT_INTERVAL_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_SOCKET_WAIT_INTERVAL_MS),
+    )
+}
+
+/// One invalid setting
+#[derive(Debug, Clone)]
+pub struct ConfigProblem {
+    pub env: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match find_setting_by_env(self.env) {
+            Some(setting) => write!(f, "{} (config key `{}`): {}", self.env, setting.key, self.message),
+            None => write!(f, "{}: {}", self.env, self.message),
+        }
+    }
+}
+
+/// Every problem found in the configuration
+#[derive(Debug, Clone, Default)]
+pub struct ConfigErrors(pub Vec<ConfigProblem>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} configuration problem(s)", self.0.len())?;
+        for problem in &self.0 {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// Check the configuration in the environment
+///
+/// # Returns
+///
+/// * `Ok(())` - no problems found
+/// * `Err(ConfigErrors)` - all problems found, in a stable order
+pub fn validate_environment() -> Result<(), ConfigErrors> {
+    check(Checker::default())
+}
+
+/// Check values about to be applied on top of the environment
+///
+/// Only problems with the given variables are reported, so an unrelated
+/// invalid setting already in the environment does not block them.
+///
+/// # Arguments
+///
+/// * `overrides` - variable name to raw value, already normalized
+pub fn validate_overrides(overrides: HashMap<&'static str, String>) -> Result<(), ConfigErrors> {
+    let envs: Vec<&'static str> = overrides.keys().copied().collect();
+    match check(Checker {
+        problems: Vec::new(),
+        overrides,
+    }) {
+        Ok(()) => Ok(()),
+        Err(ConfigErrors(problems)) => {
+            let problems: Vec<_> = problems.into_iter().filter(|p| envs.contains(&p.env)).collect();
+            if problems.is_empty() {
+                Ok(())
+            } else {
+                Err(ConfigErrors(problems))
+            }
+        }
+    }
+}
+
+fn check(mut checker: Checker) -> Result<(), ConfigErrors> {
+
+    checker.boolean("APP_DEBUG");
+    checker.one_of("ERROR_FORMAT", &["json", "problem"]);
+
+    checker.ip_addr("HTTP_HOST");
+    checker.port("HTTP_PORT");
+    checker.boolean("TLS_ENABLED");
+    if checker.flag("TLS_ENABLED") {
+        // The files are parsed when the server is built
+        for env in ["TLS_CERT_PATH", "TLS_KEY_PATH"] {
+            match checker.value(env) {
+                None => checker.problem(env, "is required when TLS_ENABLED=true"),
+                Some(path) if !Path::new(&path).is_file() => checker.problem(env, format!("file {:?} does not exist", path)),
+                Some(_) => {}
+            }
+        }
+        if let Some(path) = checker.value("TLS_CLIENT_CA").filter(|path| !Path::new(path).is_file()) {
+            checker.problem("TLS_CLIENT_CA", format!("file {:?} does not exist", path));
+        }
+        checker.one_of("TLS_CLIENT_AUTH", crate::tls::ClientAuth::NAMES);
+    }
+    checker.positive("UNAVAILABLE_RETRY_AFTER_SECS");
+    checker.positive("REQUEST_HOOK_TIMEOUT_MS");
+    checker.boolean("COALESCE_REQUESTS");
+    checker.socket_path("SOCKET_PATH");
+
+    checker.positive("SOCKET_CONNECTION_TIMEOUT");
+    checker.positive("SOCKET_HEALTH_CHECK_INTERVAL");
+    checker.non_negative("SOCKET_SWAP_WATCH_INTERVAL_MS");
+    checker.positive("SOCKET_READ_TIMEOUT_MS");
+    checker.non_negative("SOCKET_POOL_MAX_USES");
+    checker.boolean("SOCKET_GOODBYE_FRAME");
+    checker.one_of("WORKER_PROTOCOL", WorkerProtocol::NAMES);
+    checker.existing_dir("SOCKET_DISCOVERY_DIR");
+    if checker.value("WORKER_PROTOCOL").as_deref() == Some("psr7") && checker.value("SOCKET_DISCOVERY_DIR").is_some() {
+        // RoadRunner workers connect to SOCKET_PATH; there are no sockets of theirs to discover
+        checker.problem("SOCKET_DISCOVERY_DIR", "cannot be used with WORKER_PROTOCOL=psr7");
+    }
+    checker.positive("SOCKET_DISCOVERY_INTERVAL_MS");
+    checker.positive("SOCKET_DISCOVERY_PING_TIMEOUT_MS");
+    checker.positive("SOCKET_MAX_CONCURRENT_FRAMES");
+    checker.positive("SOCKET_MAX_FRAME_SIZE");
+    checker.positive("SOCKET_PRIORITY_FRAMES");
+    checker.boolean("RAISE_NOFILE");
+    checker.positive("ACCEPT_ERROR_BACKOFF_MS");
+    checker.boolean("SOCKET_RETRY_IDEMPOTENT");
+    checker.one_of("SOCKET_RESPONSE_VALIDATION", &["lenient", "strict"]);
+    let pool_min = checker.non_negative("SOCKET_POOL_MIN");
+    let pool_max = checker.positive("SOCKET_POOL_MAX");
+    if let (Some(min), Some(max)) = (pool_min, pool_max) {
+        if min > max {
+            checker.problem(
+                "SOCKET_POOL_MIN",
+                format!("must not be greater than SOCKET_POOL_MAX ({} > {})", min, max),
+            );
+        }
+    }
+
+    checker.one_of("BACKEND", &["worker", "fastcgi"]);
+    if checker.value("BACKEND").as_deref() == Some("fastcgi") {
+        match checker.value("FASTCGI_ADDRESS").map(|v| crate::fastcgi::FastCgiAddress::parse(&v)) {
+            Some(crate::fastcgi::FastCgiAddress::Unix(_)) => checker.socket_path("FASTCGI_ADDRESS"),
+            Some(crate::fastcgi::FastCgiAddress::Tcp(_)) => checker.host_port("FASTCGI_ADDRESS"),
+            None => {}
+        }
+        checker.positive("FASTCGI_CONNECT_TIMEOUT_MS");
+        checker.positive("FASTCGI_READ_TIMEOUT_MS");
+        checker.positive("FASTCGI_MAX_CONNECTIONS");
+    }
+
+    checker.non_negative("RESTART_PARK_TIMEOUT_MS");
+    checker.positive("RESTART_PARK_MAX_REQUESTS");
+    checker.positive("RESTART_PARK_MAX_BODY_BYTES");
+    checker.non_negative("RESTART_PARK_MIN_REMAINING_MS");
+
+    checker.boolean("ADAPTIVE_CONCURRENCY");
+    checker.positive("ADAPTIVE_CONCURRENCY_INITIAL");
+    checker.positive("ADAPTIVE_CONCURRENCY_WINDOW_MS");
+    checker.number_in("ADAPTIVE_CONCURRENCY_LATENCY_TOLERANCE", 1.0, f64::MAX);
+    checker.number_in("ADAPTIVE_CONCURRENCY_BACKOFF", 0.1, 0.99);
+    let adaptive_min = checker.positive("ADAPTIVE_CONCURRENCY_MIN");
+    let adaptive_max = checker.positive("ADAPTIVE_CONCURRENCY_MAX");
+    if let (Some(min), Some(max)) = (adaptive_min, adaptive_max) {
+        if min > max {
+            checker.problem(
+                "ADAPTIVE_CONCURRENCY_MIN",
+                format!("must not be greater than ADAPTIVE_CONCURRENCY_MAX ({} > {})", min, max),
+            );
+        }
+    }
+
+    checker.positive("RETRY_MAX_ATTEMPTS");
+    checker.positive("RETRY_BASE_DELAY_MS");
+    checker.positive("RETRY_MAX_DELAY_SECS");
+
+    checker.positive("SOCKET_WAIT_MAX_ATTEMPTS");
+    checker.positive("SOCKET_WAIT_INTERVAL_MS");
+    checker.boolean("STARTUP_BLOCK_UNTIL_READY");
+    checker.boolean("SELF_TEST");
+    checker.positive("SELF_TEST_TIMEOUT_MS");
+    if let Err(problems) = SelfTestConfig::from_env() {
+        for (env, problem) in problems {
+            checker.problem(env, problem);
+        }
+    }
+
+    checker.positive("SHUTDOWN_NOTIFY_TIMEOUT_MS");
+    checker.non_negative("SHUTDOWN_DRAIN_TIMEOUT_MS");
+    checker.non_negative("SHUTDOWN_FAST_DRAIN_TIMEOUT_MS");
+    checker.non_negative("DRAIN_GRACE_MS");
+
+    checker.one_of("UPGRADE_WORKER", &["handover", "restart"]);
+    checker.positive("UPGRADE_TIMEOUT_MS");
+
+    checker.boolean("PHP_WORKER_AUTO_RESTART");
+    checker.non_negative("PHP_WORKER_RESTART_DELAY_MS");
+    checker.non_negative("PHP_WORKER_STALL_TIMEOUT");
+    checker.positive("PHP_WORKER_STALL_PING_TIMEOUT_MS");
+    checker.non_negative("PHP_WORKER_STALL_KILL_GRACE_MS");
+    checker.existing_dir("LARAVEL_PATH");
+    checker.one_of("PHP_WORKER_LIMITS_ON_FAILURE", &["warn", "fail"]);
+
+    checker.one_of("LOG_LEVEL", &["trace", "debug", "info", "warn", "error"]);
+    checker.writable_dir("LOG_DIR");
+    checker.one_of("LOG_FORMAT", &["text", "json"]);
+    checker.log_rotation("LOG_ROTATION");
+    checker.non_negative("LOG_MAX_FILES");
+    checker.non_negative("LOG_THROTTLE_WINDOW_MS");
+    checker.positive("LOG_THROTTLE_BURST");
+
+    checker.boolean("RECORD_ENABLED");
+    let record_secret = checker.value("RECORD_HEADER_SECRET").filter(|secret| !secret.is_empty());
+    if record_secret.as_ref().is_some_and(|secret| secret.len() < 16) {
+        checker.problem("RECORD_HEADER_SECRET", "must be at least 16 characters");
+    }
+    if checker.flag("RECORD_ENABLED") || record_secret.is_some() {
+        checker.writable_dir("RECORD_DIR");
+        checker.non_negative("RECORD_MAX_BODY_BYTES");
+        checker.positive("RECORD_MAX_FILES");
+    }
+
+    checker.boolean("STATIC_CACHE_ENABLED");
+    checker.non_negative("STATIC_STREAM_THRESHOLD");
+    checker.non_negative("STATIC_MANIFEST_RELOAD_MS");
+    checker.non_negative("STATIC_MEMORY_CACHE_BYTES");
+    checker.non_negative("STATIC_MEMORY_CACHE_RESCAN_MS");
+    checker.boolean("STATIC_SNIFF_CONTENT_TYPE");
+    if let Err(problems) = CachePolicy::from_env() {
+        for (env, problem) in problems {
+            checker.problem(env, problem);
+        }
+    }
+    if let Err(problems) = MimeTypes::from_env() {
+        for (env, problem) in problems {
+            checker.problem(env, problem);
+        }
+    }
+    if let Err(problems) = FaviconPolicy::from_env() {
+        for (env, problem) in problems {
+            checker.problem(env, problem);
+        }
+    }
+    checker.boolean("DYNAMIC_ETAG");
+    checker.non_negative("DYNAMIC_ETAG_MAX_BODY_BYTES");
+    if let Err(problems) = HtmlTransform::from_env() {
+        for (env, problem) in problems {
+            checker.problem(env, problem);
+        }
+    }
+    checker.non_negative("HTML_TRANSFORM_MAX_BODY_BYTES");
+    checker.boolean("HTML_TRANSFORM_ERRORS");
+    if let Err(problems) = TrailingSlash::from_env() {
+        for (env, problem) in problems {
+            checker.problem(env, problem);
+        }
+    }
+    checker.boolean("STATIC_DIR_LISTING");
+    checker.boolean("STATIC_DIR_LISTING_SHOW_HIDDEN");
+    if checker.flag("STATIC_DIR_LISTING") {
+        if !cfg!(feature = "dir-listing") {
+            checker.problem("STATIC_DIR_LISTING", "requires a build with the dir-listing feature (cargo build --features dir-listing)");
+        } else if Profile::parse(checker.value("APP_PROFILE").as_deref()).is_ok_and(|profile| profile == Profile::Prod) {
+            checker.problem("STATIC_DIR_LISTING", "is for development and refused with APP_PROFILE=prod");
+        }
+    }
+
+    if let Err(problems) = ResponseHeaders::from_env() {
+        for problem in problems {
+            checker.problem("RESPONSE_HEADERS", problem);
+        }
+    }
+
+    if let Err(problems) = TrustedProxies::from_env() {
+        for problem in problems {
+            checker.problem("TRUSTED_PROXIES", problem);
+        }
+    }
+    if let Err(problems) = HeaderScrub::from_env() {
+        for (env, problem) in problems {
+            checker.problem(env, problem);
+        }
+    }
+
+    if let Err(problems) = IpFilter::from_env() {
+        for (env, problem) in problems {
+            checker.problem(env, problem);
+        }
+    }
+
+    if let Err(problems) = TenantSpec::from_env() {
+        for (env, problem) in problems {
+            checker.problem(env, problem);
+        }
+    }
+
+    if let Err(problems) = QuietPaths::from_env() {
+        for problem in problems {
+            checker.problem("QUIET_PATHS", problem);
+        }
+    }
+
+    if let Err(problems) = PriorityPaths::from_env() {
+        for problem in problems {
+            checker.problem("PRIORITY_PATHS", problem);
+        }
+    }
+
+    checker.boolean("ADMIN_ENABLED");
+    if checker.flag("ADMIN_ENABLED") {
+        checker.ip_addr("ADMIN_HOST");
+        checker.port("ADMIN_PORT");
+        checker.non_negative("ADMIN_LOG_LEVEL_REVERT_MS");
+        for env in ["ADMIN_TOKEN", "ADMIN_METRICS_TOKEN"] {
+            if checker.value(env).is_some_and(|token| !token.is_empty() && token.len() < 16) {
+                checker.problem(env, "must be at least 16 characters");
+            }
+        }
+        match (checker.value("ADMIN_USER"), checker.value("ADMIN_PASSWORD")) {
+            (Some(user), Some(_)) if user.contains(':') => checker.problem("ADMIN_USER", "must not contain ':'"),
+            (Some(_), None) => checker.problem("ADMIN_PASSWORD", "must be set together with ADMIN_USER"),
+            (None, Some(_)) => checker.problem("ADMIN_USER", "must be set together with ADMIN_PASSWORD"),
+            _ => {}
+        }
+        if let Some(Err(problems)) = checker.value("ADMIN_ALLOW_IPS").map(|list| crate::cidr::parse_list(&list)) {
+            for problem in problems {
+                checker.problem("ADMIN_ALLOW_IPS", problem);
+            }
+        }
+        checker.boolean("ADMIN_ALLOW_INSECURE");
+        checker.positive("ADMIN_AUTH_MAX_FAILURES");
+        checker.positive("ADMIN_AUTH_FAILURE_WINDOW_MS");
+        checker.positive("ADMIN_ARTISAN_TIMEOUT_MS");
+        checker.non_negative("ADMIN_ARTISAN_MAX_OUTPUT_BYTES");
+        if checker.value("ADMIN_ARTISAN_COMMANDS").is_some()
+            && checker.value("ADMIN_TOKEN").is_none()
+            && checker.value("ADMIN_USER").is_none()
+        {
+            checker.problem("ADMIN_ARTISAN_COMMANDS", "requires ADMIN_TOKEN or ADMIN_USER");
+        }
+    }
+
+    if checker.value("INFO_PATH").is_some_and(|path| !path.starts_with('/')) {
+        checker.problem("INFO_PATH", "must start with /");
+    }
+    checker.one_of("INFO_LISTENER", &["http", "admin"]);
+    if let Some(Err(problems)) = checker.value("INFO_ALLOW_IPS").map(|list| crate::cidr::parse_list(&list)) {
+        for problem in problems {
+            checker.problem("INFO_ALLOW_IPS", problem);
+        }
+    }
+    if checker.value("INFO_LISTENER").is_some_and(|listener| listener.eq_ignore_ascii_case("admin"))
+        && !checker.flag("ADMIN_ENABLED")
+    {
+        checker.problem("INFO_LISTENER", "admin requires ADMIN_ENABLED=true");
+    }
+
+    checker.boolean("WS_ENABLED");
+    if checker.flag("WS_ENABLED") {
+        if checker.value("WS_PATH").is_some_and(|path| !path.starts_with('/')) {
+            checker.problem("WS_PATH", "must start with /");
+        }
+        checker.socket_path("WS_PUSH_SOCKET");
+        checker.positive("WS_MAX_CONNECTIONS");
+        checker.positive("WS_MAX_SUBSCRIPTIONS");
+        checker.positive("WS_PING_INTERVAL_MS");
+        checker.positive("WS_QUEUE_SIZE");
+    }
+
+    checker.boolean("GRPC_ENABLED");
+    if checker.flag("GRPC_ENABLED") {
+        if !cfg!(feature = "grpc") {
+            checker.problem("GRPC_ENABLED", "requires a build with the grpc feature (cargo build --features grpc)");
+        }
+        checker.ip_addr("GRPC_HOST");
+        checker.port("GRPC_PORT");
+    }
+
+    checker.boolean("CHAOS_ENABLED");
+    if checker.flag("CHAOS_ENABLED") {
+        if !cfg!(feature = "chaos") {
+            checker.problem("CHAOS_ENABLED", "requires a build with the chaos feature (cargo build --features chaos)");
+        }
+        for env in [
+            "CHAOS_CONNECT_FAILURE_RATE",
+            "CHAOS_LATENCY_RATE",
+            "CHAOS_TRUNCATED_FRAME_RATE",
+            "CHAOS_MALFORMED_RESPONSE_RATE",
+            "CHAOS_HTTP_ERROR_RATE",
+        ] {
+            checker.number_in(env, 0.0, 1.0);
+        }
+        checker.non_negative("CHAOS_LATENCY_MS");
+        checker.one_of("CHAOS_HTTP_ERROR_STATUS", &["500", "502", "503", "504"]);
+        if checker.value("CHAOS_HEADER").is_some_and(|header| header.split(':').next().unwrap_or_default().trim().is_empty()) {
+            checker.problem("CHAOS_HEADER", "must be a header name, optionally followed by : and a value");
+        }
+    }
+
+    checker.host_port("STATSD_ADDR");
+    checker.positive("STATSD_FLUSH_INTERVAL_MS");
+    checker.positive("STATSD_QUEUE_SIZE");
+
+    if checker.problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigErrors(checker.problems))
+    }
+}
+
+#[derive(Default)]
+struct Checker {
+    problems: Vec<ConfigProblem>,
+    /// Values that take precedence over the environment
+    overrides: HashMap<&'static str, String>,
+}
+
+impl Checker {
+    fn problem(&mut self, env: &'static str, message: impl Into<String>) {
+        self.problems.push(ConfigProblem {
+            env,
+            message: message.into(),
+        });
+    }
+
+    /// Effective value: the override, the variable, or the registry default when unset
+    fn value(&self, env: &str) -> Option<String> {
+        self.overrides
+            .get(env)
+            .cloned()
+            .or_else(|| std::env::var(env).ok())
+            .or_else(|| find_setting_by_env(env).and_then(|s| s.default).map(str::to_string))
+            .filter(|v| !v.trim().is_empty())
+    }
+
+    fn flag(&self, env: &str) -> bool {
+        matches!(self.value(env).as_deref(), Some("true") | Some("1"))
+    }
+
+    fn integer(&mut self, env: &'static str) -> Option<u64> {
+        let value = self.value(env)?;
+        match value.trim().parse::<u64>() {
+            Ok(n) => Some(n),
+            Err(_) => {
+                self.problem(env, format!("must be a non-negative integer, got {:?}", value));
+                None
+            }
+        }
+    }
+
+    fn non_negative(&mut self, env: &'static str) -> Option<u64> {
+        self.integer(env)
+    }
+
+    fn positive(&mut self, env: &'static str) -> Option<u64> {
+        let n = self.integer(env)?;
+        if n == 0 {
+            self.problem(env, "must be greater than zero");
+            return None;
+        }
+        Some(n)
+    }
+
+    fn number_in(&mut self, env: &'static str, min: f64, max: f64) -> Option<f64> {
+        let value = self.value(env)?;
+        match value.trim().parse::<f64>() {
+            Ok(n) if (min..=max).contains(&n) => Some(n),
+            Ok(_) if max == f64::MAX => {
+                self.problem(env, format!("must be at least {}, got {:?}", min, value));
+                None
+            }
+            _ => {
+                self.problem(env, format!("must be a number between {} and {}, got {:?}", min, max, value));
+                None
+            }
+        }
+    }
+
+    fn boolean(&mut self, env: &'static str) {
+        if let Some(value) = self.value(env) {
+            if !matches!(value.as_str(), "true" | "false" | "1" | "0") {
+                self.problem(env, format!("must be true or false, got {:?}", value));
+            }
+        }
+    }
+
+    fn one_of(&mut self, env: &'static str, allowed: &[&str]) {
+        if let Some(value) = self.value(env) {
+            if !allowed.contains(&value.to_ascii_lowercase().as_str()) {
+                self.problem(env, format!("must be one of {}, got {:?}", allowed.join(", "), value));
+            }
+        }
+    }
+
+    fn ip_addr(&mut self, env: &'static str) {
+        if let Some(value) = self.value(env) {
+            if value.parse::<IpAddr>().is_err() {
+                self.problem(env, format!("must be an IP address such as 127.0.0.1 or 0.0.0.0, got {:?}", value));
+            }
+        }
+    }
+
+    fn port(&mut self, env: &'static str) {
+        if let Some(value) = self.value(env) {
+            match value.parse::<u32>() {
+                Ok(port) if (1..=65535).contains(&port) => {}
+                _ => self.problem(env, format!("must be a port between 1 and 65535, got {:?}", value)),
+            }
+        }
+    }
+
+    fn host_port(&mut self, env: &'static str) {
+        if let Some(value) = self.value(env) {
+            let valid = value
+                .rsplit_once(':')
+                .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok_and(|p| p > 0));
+            if !valid {
+                self.problem(env, format!("must be host:port such as 127.0.0.1:8125, got {:?}", value));
+            }
+        }
+    }
+
+    fn socket_path(&mut self, env: &'static str) {
+        if let Some(value) = self.value(env) {
+            if value.len() > MAX_SOCKET_PATH_LEN {
+                self.problem(
+                    env,
+                    format!(
+                        "is {} bytes long, Unix socket paths are limited to {} bytes; use a shorter path",
+                        value.len(),
+                        MAX_SOCKET_PATH_LEN
+                    ),
+                );
+            }
+            if let Some(parent) = Path::new(&value).parent().filter(|p| !p.as_os_str().is_empty()) {
+                if !parent.is_dir() {
+                    self.problem(env, format!("directory {} does not exist", parent.display()));
+                }
+            }
+        }
+    }
+
+    fn log_rotation(&mut self, env: &'static str) {
+        if let Some(value) = self.value(env) {
+            if RotationPolicy::parse(&value).is_none() {
+                self.problem(
+                    env,
+                    format!("must be daily, hourly, never or size:<bytes> (e.g. size:100MB), got {:?}", value),
+                );
+            }
+        }
+    }
+
+    fn existing_dir(&mut self, env: &'static str) {
+        if let Some(value) = self.value(env) {
+            if !Path::new(&value).is_dir() {
+                self.problem(env, format!("directory {:?} does not exist", value));
+            }
+        }
+    }
+
+    /// Directory that exists and is writable, or can be created
+    fn writable_dir(&mut self, env: &'static str) {
+        let Some(value) = self.value(env) else {
+            return;
+        };
+        let path = Path::new(&value);
+
+        if path.exists() && !path.is_dir() {
+            self.problem(env, format!("{:?} is not a directory", value));
+            return;
+        }
+
+        // The directory is created at startup if missing, so check the
+        // nearest existing ancestor instead
+        let existing = path.ancestors().find(|p| p.as_os_str().is_empty() || p.exists());
+        let existing = match existing {
+            Some(p) if p.as_os_str().is_empty() => Path::new("."),
+            Some(p) => p,
+            None => return,
+        };
+        if !is_writable(existing) {
+            self.problem(env, format!("{} is not writable by the current user", existing.display()));
+        }
+    }
+}
+
+fn is_writable(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: access only reads the NUL-terminated path
+    unsafe { libc::access(c_path.as_ptr(), libc::W_OK) == 0 }
+}
@@ -0,0 +1,62 @@
+//! Time budget of a request, passed on to PHP
+//!
+//! A request may take as long as the backend read timeout, counted from
+//! the moment it arrived. What is left of that budget when the request is
+//! handed to PHP is sent along as the `X-Request-Deadline-Ms` header and
+//! the `REQUEST_DEADLINE_MS` server variable, so the application can stop
+//! working on a request whose client has already been answered with 504.
+//! The same remaining time is used as the read timeout for that exchange,
+//! and a retried request carries the smaller budget left for the retry.
+//!
+//! Callers connecting from `TRUSTED_PROXIES` can send a budget of their
+//! own in `X-Request-Deadline-Ms`. It is honored when it is shorter than
+//! the read timeout; from anyone else the header is replaced.
+
+use std::time::{Duration, Instant};
+
+use hyper::HeaderMap;
+
+/// Header carrying the remaining budget in milliseconds, both ways
+pub const DEADLINE_HEADER: &str = "x-request-deadline-ms";
+
+/// Server variable carrying the remaining budget in milliseconds
+pub const DEADLINE_SERVER_VAR: &str = "REQUEST_DEADLINE_MS";
+
+/// Budget a trusted caller set in `X-Request-Deadline-Ms`
+pub fn caller_budget(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(DEADLINE_HEADER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_millis)
+}
+
+/// Point in time by which the backend must have answered
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    /// `timeout` after `started`, or `budget` after it when that is sooner
+    pub fn new(started: Instant, timeout: Duration, budget: Option<Duration>) -> Self {
+        Self(started + budget.map_or(timeout, |budget| budget.min(timeout)))
+    }
+
+    /// Time left, zero once the deadline has passed
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+
+    /// Write the time left into a worker request frame and return it
+    pub fn stamp(&self, frame: &mut serde_json::Value) -> Duration {
+        let remaining = self.remaining();
+        let millis = remaining.as_millis().to_string();
+        frame["server"][DEADLINE_SERVER_VAR] = millis.clone().into();
+        if frame["headers"].is_object() {
+            frame["headers"][DEADLINE_HEADER] = millis.into();
+        }
+        remaining
+    }
+}
@@ -0,0 +1,154 @@
+//! Directory listings of the static roots, for development
+//!
+//! With `STATIC_DIR_LISTING=true` a request naming a directory below
+//! `public/` (or a tenant's `public_dir`) gets a plain HTML index of it:
+//! name, size and modification time, directories first. It is meant for
+//! checking what the bundler wrote to `public/build/`, so it is refused
+//! with `APP_PROFILE=prod` and only exists in builds with the `dir-listing`
+//! feature. The root path is always left to Laravel.
+//!
+//! Names starting with `.` are hidden, and so are directories below one,
+//! unless `STATIC_DIR_LISTING_SHOW_HIDDEN=true`. Listings are sent with
+//! `Cache-Control: no-store`. The path comes from the traversal-safe
+//! decoding of the static file handler.
+
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyper::header::{self, HeaderValue};
+use hyper::{Body, Response, StatusCode};
+
+/// Directory listing settings
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DirListing {
+    pub enabled: bool,
+    /// List names starting with `.`
+    pub show_hidden: bool,
+}
+
+/// One entry of a listing
+struct Entry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl DirListing {
+    pub fn from_env() -> Self {
+        let flag = |name: &str| matches!(std::env::var(name).as_deref(), Ok("true") | Ok("1"));
+        Self {
+            enabled: flag("STATIC_DIR_LISTING"),
+            show_hidden: flag("STATIC_DIR_LISTING_SHOW_HIDDEN"),
+        }
+    }
+
+    /// Listing of `file_path` below `public_dir`, if it names a directory
+    ///
+    /// `file_path` is the decoded path within the application and
+    /// `request_path` the path as requested, which the links are built on.
+    pub async fn respond(&self, public_dir: &str, file_path: &str, request_path: &str) -> Option<Response<Body>> {
+        let relative = file_path.trim_matches('/');
+        if !self.enabled || relative.is_empty() {
+            return None;
+        }
+        if !self.show_hidden && relative.split('/').any(|segment| segment.starts_with('.')) {
+            return None;
+        }
+        let dir = Path::new(public_dir).join(relative);
+        if !tokio::fs::metadata(&dir).await.ok()?.is_dir() {
+            return None;
+        }
+
+        let mut entries = Vec::new();
+        let mut read_dir = tokio::fs::read_dir(&dir).await.ok()?;
+        while let Ok(Some(entry)) = read_dir.next_entry().await {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !self.show_hidden && name.starts_with('.') {
+                continue;
+            }
+            // Symlinks such as public/storage are listed as what they point to
+            let Ok(metadata) = tokio::fs::metadata(entry.path()).await else {
+                continue;
+            };
+            entries.push(Entry {
+                name,
+                is_dir: metadata.is_dir(),
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+            });
+        }
+        entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+
+        let base = request_path.trim_end_matches('/');
+        let mut html = String::new();
+        let title = escape_html(&format!("/{}/", relative));
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Index of {0}</title></head>\n<body>\n<h1>Index of {0}</h1>\n<table>\n<tr><th>Name</th><th>Size</th><th>Modified (UTC)</th></tr>\n",
+            title
+        );
+        let _ = writeln!(html, "<tr><td><a href=\"{}/\">../</a></td><td></td><td></td></tr>", escape_html(parent(base)));
+        for entry in &entries {
+            let slash = if entry.is_dir { "/" } else { "" };
+            let href = format!("{}/{}{}", base, urlencoding::encode(&entry.name), slash);
+            let _ = writeln!(
+                html,
+                "<tr><td><a href=\"{}\">{}{}</a></td><td>{}</td><td>{}</td></tr>",
+                escape_html(&href),
+                escape_html(&entry.name),
+                slash,
+                if entry.is_dir { "-".to_string() } else { entry.size.to_string() },
+                entry.modified.map(format_time).unwrap_or_default()
+            );
+        }
+        html.push_str("</table>\n</body></html>\n");
+
+        let mut response = Response::new(Body::from(html));
+        *response.status_mut() = StatusCode::OK;
+        let headers = response.headers_mut();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/html; charset=utf-8"));
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+        Some(response)
+    }
+}
+
+/// `path` without its last segment
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+/// `text` safe inside HTML text and quoted attributes
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `time` as `YYYY-MM-DD HH:MM` in UTC
+fn format_time(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rest) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, rest / 3600, rest % 3600 / 60)
+}
@@ -0,0 +1,170 @@
+//! Drain mode: out of the load balancer without stopping
+//!
+//! `POST /admin/drain` on the admin listener, or `SIGUSR2`, puts the server
+//! in drain mode. `/readyz` then fails, so load balancers stop sending
+//! traffic, and requests other than health checks and the info endpoint
+//! get `503 maintenance` with `Retry-After`. Connections that were open
+//! when the drain started are still served for `DRAIN_GRACE_MS`, so a
+//! client in the middle of a page load is not cut off. Refused requests
+//! get `Connection: close`.
+//! Requests already in flight complete normally, and the PHP worker keeps
+//! running with its caches warm.
+//!
+//! `POST /admin/undrain`, or `SIGUSR2` again, serves requests again at
+//! once. Each change is logged with what triggered it, shown under `drain`
+//! in `/admin/stats` and reported in the `http_draining` gauge.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde_json::json;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::metrics::{metrics, MetricKind};
+
+/// Default for `DRAIN_GRACE_MS`
+const DEFAULT_GRACE_MS: u64 = 5_000;
+
+/// A drain in progress
+#[derive(Debug, Clone)]
+struct Draining {
+    since: Instant,
+    /// Seconds since the Unix epoch, for the stats
+    since_unix: u64,
+    /// Who or what started it
+    trigger: String,
+}
+
+/// Drain state shared by the HTTP listener, the admin listener and the signal handler
+#[derive(Debug)]
+pub struct Drain {
+    state: Mutex<Option<Draining>>,
+    /// How long connections opened before the drain are still served
+    grace: Duration,
+}
+
+impl Drain {
+    pub fn new(grace: Duration) -> Self {
+        metrics().describe("http_draining", MetricKind::Gauge, "1 while the server is in drain mode");
+        metrics().describe(
+            "drain_transitions_total",
+            MetricKind::Counter,
+            "Changes into and out of drain mode, by new state and trigger",
+        );
+        metrics().set_gauge("http_draining", &[], 0.0);
+        Self {
+            state: Mutex::new(None),
+            grace,
+        }
+    }
+
+    /// Grace period from `DRAIN_GRACE_MS`
+    pub fn from_env() -> Self {
+        Self::new(Duration::from_millis(
+            std::env::var("DRAIN_GRACE_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_GRACE_MS),
+        ))
+    }
+
+    /// Enter drain mode; `false` if the server was draining already
+    ///
+    /// `trigger` names who or what asked for it, for the log and the stats.
+    pub fn start(&self, trigger: &str) -> bool {
+        let mut state = self.lock();
+        if let Some(draining) = state.as_ref() {
+            info!(trigger, since_trigger = %draining.trigger, "Drain requested while already draining");
+            return false;
+        }
+        *state = Some(Draining {
+            since: Instant::now(),
+            since_unix: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+            trigger: trigger.to_string(),
+        });
+        metrics().set_gauge("http_draining", &[], 1.0);
+        metrics().inc_counter("drain_transitions_total", &[("to", "draining"), ("trigger", kind(trigger))]);
+        warn!(
+            trigger,
+            grace_ms = self.grace.as_millis() as u64,
+            "🚧 Draining: readiness fails and new requests get 503 until undrained"
+        );
+        true
+    }
+
+    /// Leave drain mode; `false` if the server was not draining
+    pub fn stop(&self, trigger: &str) -> bool {
+        let Some(draining) = self.lock().take() else {
+            info!(trigger, "Undrain requested while not draining");
+            return false;
+        };
+        metrics().set_gauge("http_draining", &[], 0.0);
+        metrics().inc_counter("drain_transitions_total", &[("to", "serving"), ("trigger", kind(trigger))]);
+        info!(
+            trigger,
+            drained_by = %draining.trigger,
+            drained_ms = draining.since.elapsed().as_millis() as u64,
+            "✅ Undrained: serving requests again"
+        );
+        true
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.lock().is_some()
+    }
+
+    /// Whether a request on a connection opened at `connected` gets a 503
+    ///
+    /// Connections opened before the drain are served until the grace period ends.
+    pub fn refuses(&self, connected: Instant) -> bool {
+        match self.lock().as_ref() {
+            Some(draining) => connected >= draining.since || draining.since.elapsed() >= self.grace,
+            None => false,
+        }
+    }
+
+    /// State for the admin stats
+    pub fn report(&self) -> serde_json::Value {
+        match self.lock().as_ref() {
+            Some(draining) => json!({
+                "draining": true,
+                "since": draining.since_unix,
+                "duration_ms": draining.since.elapsed().as_millis() as u64,
+                "trigger": draining.trigger,
+                "grace_ms": self.grace.as_millis() as u64,
+            }),
+            None => json!({ "draining": false, "grace_ms": self.grace.as_millis() as u64 }),
+        }
+    }
+
+    /// Toggle drain mode on every `SIGUSR2`
+    pub fn spawn_signal_handler(self: &std::sync::Arc<Self>) -> Result<JoinHandle<()>> {
+        let mut user2 = signal(SignalKind::user_defined2())?;
+        let drain = self.clone();
+        Ok(tokio::spawn(async move {
+            while user2.recv().await.is_some() {
+                if drain.is_draining() {
+                    drain.stop("SIGUSR2");
+                } else {
+                    drain.start("SIGUSR2");
+                }
+            }
+        }))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Draining>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Metric label for a trigger: the signal, or `admin` for any admin client
+fn kind(trigger: &str) -> &'static str {
+    if trigger == "SIGUSR2" {
+        "signal"
+    } else {
+        "admin"
+    }
+}
@@ -0,0 +1,459 @@
+//! Server error taxonomy
+//!
+//! Failures on the request path are classified into [`ServerError`] where
+//! they happen, so that every error response carries a status code that
+//! reflects what actually went wrong: the PHP worker being unreachable, slow,
+//! returning something unusable, or the request itself being too large.
+//!
+//! Turning an error into a response is delegated to an [`ErrorRenderer`], so
+//! embedders can brand error pages or switch formats without touching the
+//! server. [`JsonErrorRenderer`] is the default; [`ProblemJsonRenderer`]
+//! produces RFC 9457 `application/problem+json`.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::{header, Body, Response, StatusCode};
+use thiserror::Error;
+use tracing::{debug, error, warn, Level};
+
+use crate::log_throttle::log_throttle;
+use crate::metrics::{metrics, MetricKind};
+use crate::request_context::{RequestContext, REQUEST_ID_HEADER};
+
+/// How much of an error is shown to the client
+///
+/// The log always gets the full detail; this only affects response bodies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorDetail {
+    /// Generic message, status and request id only
+    Production,
+    /// Full error chain, failing component and timing
+    Debug,
+}
+
+impl ErrorDetail {
+    /// Read `APP_DEBUG`; anything but true/1 means production
+    pub fn from_env() -> Self {
+        match std::env::var("APP_DEBUG").as_deref() {
+            Ok("true") | Ok("1") => ErrorDetail::Debug,
+            _ => ErrorDetail::Production,
+        }
+    }
+}
+
+/// Why a request is answered with 503 Service Unavailable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum UnavailableReason {
+    /// The PHP worker is not reachable (down, restarting or still starting)
+    BridgeDown,
+    /// The server is shedding load
+    Overloaded,
+    /// The service is deliberately taken offline
+    Maintenance,
+}
+
+impl UnavailableReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnavailableReason::BridgeDown => "bridge_down",
+            UnavailableReason::Overloaded => "overloaded",
+            UnavailableReason::Maintenance => "maintenance",
+        }
+    }
+}
+
+/// Retry-After sent with 503 responses when the producer has no better estimate
+pub fn default_retry_after() -> Duration {
+    Duration::from_secs(
+        std::env::var("UNAVAILABLE_RETRY_AFTER_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(5),
+    )
+}
+
+/// Classified failure while serving a request
+#[derive(Debug, Error)]
+pub enum ServerError {
+    /// The service cannot handle the request right now; always a 503
+    #[error("service unavailable ({}): {message}", reason.as_str())]
+    Unavailable {
+        reason: UnavailableReason,
+        /// When the client should retry; `None` uses the configured default
+        retry_after: Option<Duration>,
+        message: String,
+    },
+    /// The PHP worker did not answer in time
+    #[error("PHP worker timed out: {0}")]
+    BridgeTimeout(String),
+    /// The PHP worker answered with something that is not a valid response
+    #[error("malformed response from PHP worker: {0}")]
+    UpstreamMalformed(String),
+    /// The Laravel application reported an error while handling the request
+    #[error("application error: {0}")]
+    Application(String),
+    /// The request is larger than the bridge accepts
+    #[error("payload too large: {0}")]
+    PayloadTooLarge(String),
+    /// A static file that does not exist
+    #[error("not found: {0}")]
+    NotFound(String),
+    /// A bug or unexpected condition in the server itself
+    #[error("internal error: {0}")]
+    Internal(String),
+}
+
+impl ServerError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ServerError::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            ServerError::BridgeTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ServerError::UpstreamMalformed(_) => StatusCode::BAD_GATEWAY,
+            ServerError::Application(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ServerError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ServerError::NotFound(_) => StatusCode::NOT_FOUND,
+            ServerError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Stable identifier used in response bodies, logs and metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ServerError::Unavailable { reason, .. } => match reason {
+                UnavailableReason::BridgeDown => "bridge_unavailable",
+                UnavailableReason::Overloaded => "overloaded",
+                UnavailableReason::Maintenance => "maintenance",
+            },
+            ServerError::BridgeTimeout(_) => "bridge_timeout",
+            ServerError::UpstreamMalformed(_) => "upstream_malformed",
+            ServerError::Application(_) => "application_error",
+            ServerError::PayloadTooLarge(_) => "payload_too_large",
+            ServerError::NotFound(_) => "not_found",
+            ServerError::Internal(_) => "internal_error",
+        }
+    }
+
+    /// Part of the request path that failed
+    pub fn component(&self) -> &'static str {
+        match self {
+            ServerError::Unavailable {
+                reason: UnavailableReason::BridgeDown,
+                ..
+            }
+            | ServerError::BridgeTimeout(_)
+            | ServerError::PayloadTooLarge(_) => "bridge",
+            ServerError::Unavailable { .. } => "server",
+            ServerError::UpstreamMalformed(_) => "response_parser",
+            ServerError::Application(_) => "application",
+            ServerError::NotFound(_) => "static",
+            ServerError::Internal(_) => "server",
+        }
+    }
+
+    /// Message shown to clients in production, free of internal details
+    pub fn public_message(&self) -> &'static str {
+        match self {
+            ServerError::Unavailable { reason, .. } => match reason {
+                UnavailableReason::BridgeDown => "The service is temporarily unavailable",
+                UnavailableReason::Overloaded => "The server is overloaded",
+                UnavailableReason::Maintenance => "The service is down for maintenance",
+            },
+            ServerError::BridgeTimeout(_) => "The request timed out",
+            ServerError::UpstreamMalformed(_) => "The application returned an invalid response",
+            ServerError::Application(_) => "The application encountered an error",
+            ServerError::PayloadTooLarge(_) => "The request is too large",
+            ServerError::NotFound(_) => "Not found",
+            ServerError::Internal(_) => "Internal server error",
+        }
+    }
+
+    /// The PHP worker could not be reached
+    pub fn bridge_down(message: impl Into<String>) -> Self {
+        ServerError::Unavailable {
+            reason: UnavailableReason::BridgeDown,
+            retry_after: None,
+            message: message.into(),
+        }
+    }
+
+    /// Reason and Retry-After of a 503 error
+    pub fn unavailable(&self) -> Option<(UnavailableReason, Duration)> {
+        match self {
+            ServerError::Unavailable {
+                reason, retry_after, ..
+            } => Some((*reason, retry_after.unwrap_or_else(default_retry_after))),
+            _ => None,
+        }
+    }
+
+    /// Classify an error from the request path
+    ///
+    /// Errors that are already a `ServerError` keep their class; anything
+    /// else is an internal error.
+    pub fn classify(error: anyhow::Error) -> Self {
+        match error.downcast::<ServerError>() {
+            Ok(server_error) => server_error,
+            Err(other) => ServerError::Internal(format!("{:#}", other)),
+        }
+    }
+}
+
+/// A classified error, ready to be rendered
+#[derive(Debug)]
+pub struct ErrorReport {
+    pub error: ServerError,
+    /// Messages of the original error and its causes, outermost first
+    pub chain: Vec<String>,
+    pub detail: ErrorDetail,
+}
+
+/// Turns an error into the response sent to the client
+///
+/// `content_type` is the media type negotiated from the request's `Accept`
+/// header (see [`negotiate_content_type`]); renderers are free to ignore it.
+/// `X-Request-Id` and, for 503s, `Retry-After` are added to the rendered
+/// response when the renderer does not set them.
+pub trait ErrorRenderer: Send + Sync {
+    fn render(&self, report: &ErrorReport, context: &RequestContext, content_type: &str) -> Response<Body>;
+}
+
+/// Shared handle to the renderer used by a server
+pub type SharedErrorRenderer = Arc<dyn ErrorRenderer>;
+
+/// Media types offered for error bodies, in order of preference
+const ERROR_CONTENT_TYPES: &[&str] = &["application/json", "application/problem+json", "text/html", "text/plain"];
+
+/// First offered media type listed in `Accept`, or `application/json`
+pub fn negotiate_content_type(accept: Option<&str>) -> &'static str {
+    accept
+        .into_iter()
+        .flat_map(|accept| accept.split(','))
+        .filter_map(|range| range.split(';').next())
+        .find_map(|media| {
+            let media = media.trim();
+            ERROR_CONTENT_TYPES.iter().copied().find(|offered| offered.eq_ignore_ascii_case(media))
+        })
+        .unwrap_or(ERROR_CONTENT_TYPES[0])
+}
+
+/// Error format selected by `ERROR_FORMAT`
+pub fn renderer_from_env() -> SharedErrorRenderer {
+    match std::env::var("ERROR_FORMAT").as_deref() {
+        Ok("problem") => Arc::new(ProblemJsonRenderer),
+        _ => Arc::new(JsonErrorRenderer),
+    }
+}
+
+/// Error chain captured before classification, logged as an error field
+/// so log formatters see each cause instead of one joined string
+#[derive(Debug)]
+struct LoggedError {
+    message: String,
+    source: Option<Box<LoggedError>>,
+}
+
+impl LoggedError {
+    fn new(chain: &[String]) -> Self {
+        let (message, causes) = chain.split_first().map(|(m, rest)| (m.clone(), rest)).unwrap_or_default();
+        Self {
+            message,
+            source: (!causes.is_empty()).then(|| Box::new(Self::new(causes))),
+        }
+    }
+}
+
+impl fmt::Display for LoggedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for LoggedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.as_deref().map(|source| source as &(dyn std::error::Error + 'static))
+    }
+}
+
+/// Build the error response for a failed request
+///
+/// The full detail is always logged, inside the request span, and the class
+/// is counted in `http_error_responses_total`; the body is left to
+/// `renderer`, with `detail` deciding how much of the error it may show.
+/// Every 503 is produced here and carries a `Retry-After` header.
+pub fn handle_error_response(
+    error: anyhow::Error,
+    context: &RequestContext,
+    detail: ErrorDetail,
+    renderer: &dyn ErrorRenderer,
+) -> Response<Body> {
+    let chain: Vec<String> = error.chain().map(|cause| cause.to_string()).collect();
+    let error = ServerError::classify(error);
+    let status = error.status();
+    let elapsed_ms = context.elapsed().as_millis() as u64;
+    let unavailable = error.unavailable();
+
+    // Missing static files are routine; 503s are an expected operational
+    // state, not a failure of the server. Repeats of the same class are
+    // throttled so an outage does not log every single request.
+    let cause = LoggedError::new(&chain);
+    let cause: &(dyn std::error::Error + 'static) = &cause;
+    if status == StatusCode::NOT_FOUND {
+        debug!(kind = error.kind(), component = error.component(), elapsed_ms, error = cause, "Request failed");
+    } else if status.is_server_error() && unavailable.is_none() {
+        if log_throttle().allow(error.kind(), Level::ERROR, &chain.join(": ")) {
+            error!(
+                kind = error.kind(),
+                component = error.component(),
+                status = status.as_u16(),
+                elapsed_ms,
+                error = cause,
+                "Request failed"
+            );
+        }
+    } else if log_throttle().allow(error.kind(), Level::WARN, &chain.join(": ")) {
+        warn!(
+            kind = error.kind(),
+            component = error.component(),
+            status = status.as_u16(),
+            elapsed_ms,
+            error = cause,
+            "Request failed"
+        );
+    }
+
+    metrics().describe(
+        "http_error_responses_total",
+        MetricKind::Counter,
+        "Error responses by error class and status code",
+    );
+    metrics().inc_counter(
+        "http_error_responses_total",
+        &[("kind", error.kind()), ("status", status.as_str())],
+    );
+    if let Some((reason, _)) = unavailable {
+        metrics().describe(
+            "http_unavailable_responses_total",
+            MetricKind::Counter,
+            "503 responses by reason",
+        );
+        metrics().inc_counter("http_unavailable_responses_total", &[("reason", reason.as_str())]);
+    }
+
+    let content_type = negotiate_content_type(context.accept.as_deref());
+    let report = ErrorReport { error, chain, detail };
+    let mut response = renderer.render(&report, context, content_type);
+
+    let headers = response.headers_mut();
+    if let Ok(request_id) = header::HeaderValue::from_str(&context.id) {
+        headers.entry(REQUEST_ID_HEADER).or_insert(request_id);
+    }
+    if let Some((_, retry_after)) = unavailable {
+        headers
+            .entry(header::RETRY_AFTER)
+            .or_insert_with(|| retry_after_secs(retry_after).into());
+    }
+    response
+}
+
+/// The default renderer: a small JSON document
+///
+/// `{"error", "status", "message", "request_id"}`, plus `reason` and
+/// `retry_after` for 503s; in debug mode also `component`, `chain` and
+/// `elapsed_ms`, with the full message instead of the generic one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonErrorRenderer;
+
+impl ErrorRenderer for JsonErrorRenderer {
+    fn render(&self, report: &ErrorReport, context: &RequestContext, _content_type: &str) -> Response<Body> {
+        let error = &report.error;
+        let mut body = match report.detail {
+            ErrorDetail::Production => serde_json::json!({
+                "error": error.kind(),
+                "status": error.status().as_u16(),
+                "message": error.public_message(),
+                "request_id": context.id,
+            }),
+            ErrorDetail::Debug => serde_json::json!({
+                "error": error.kind(),
+                "status": error.status().as_u16(),
+                "message": error.to_string(),
+                "request_id": context.id,
+                "component": error.component(),
+                "chain": report.chain,
+                "elapsed_ms": context.elapsed().as_millis() as u64,
+            }),
+        };
+        if let Some((reason, retry_after)) = error.unavailable() {
+            body["reason"] = reason.as_str().into();
+            body["retry_after"] = retry_after_secs(retry_after).into();
+        }
+        json_response(error.status(), "application/json", &body)
+    }
+}
+
+/// RFC 9457 problem details (`application/problem+json`)
+///
+/// The error kind becomes the `type` URI (`urn:laravel-rust:error:<kind>`)
+/// and the request path the `instance`; request id, reason and debug
+/// details are extension members.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProblemJsonRenderer;
+
+impl ErrorRenderer for ProblemJsonRenderer {
+    fn render(&self, report: &ErrorReport, context: &RequestContext, _content_type: &str) -> Response<Body> {
+        let error = &report.error;
+        let status = error.status();
+        let mut body = serde_json::json!({
+            "type": format!("urn:laravel-rust:error:{}", error.kind()),
+            "title": status.canonical_reason().unwrap_or("Error"),
+            "status": status.as_u16(),
+            "detail": error.public_message(),
+            "instance": context.path,
+            "request_id": context.id,
+        });
+        if let Some((reason, retry_after)) = error.unavailable() {
+            body["reason"] = reason.as_str().into();
+            body["retry_after"] = retry_after_secs(retry_after).into();
+        }
+        if report.detail == ErrorDetail::Debug {
+            body["detail"] = error.to_string().into();
+            body["component"] = error.component().into();
+            body["chain"] = report.chain.clone().into();
+            body["elapsed_ms"] = (context.elapsed().as_millis() as u64).into();
+        }
+        json_response(status, "application/problem+json", &body)
+    }
+}
+
+fn json_response(status: StatusCode, content_type: &'static str, body: &serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Body::from(body.to_string()))
+        .unwrap_or_else(|_| {
+            let mut response = Response::new(Body::from("Internal Server Error"));
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            response
+        })
+}
+
+/// Retry-After in whole seconds, rounded up and at least 1
+fn retry_after_secs(retry_after: Duration) -> u64 {
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    secs.max(1)
+}
+
+/// Text of a caught panic payload
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "non-string panic payload".to_string()
+    }
+}
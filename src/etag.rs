@@ -0,0 +1,144 @@
+//! ETags, and `304 Not Modified` answers to requests that name them
+//!
+//! Static files served from memory get a strong ETag computed from their
+//! contents (see [`file_cache`](crate::file_cache)); responses of the PHP
+//! worker may get a weak one.
+//!
+//! Clients polling an endpoint download the same body again and again when
+//! Laravel sets no validator. With `DYNAMIC_ETAG=true` a `200` answer to a
+//! GET gets a weak ETag computed from its body, unless it has an ETag
+//! already, sets a cookie, is marked `no-store` or is larger than
+//! `DYNAMIC_ETAG_MAX_BODY_BYTES`. A GET or HEAD whose `If-None-Match`
+//! matches the ETag of its response, ours or Laravel's, then gets `304 Not
+//! Modified` without the body.
+//!
+//! The tag is the body length and a 64-bit SipHash of the body. It is weak
+//! because it says nothing about content encoding, and it may change when
+//! the server is built with another Rust version, which only costs clients
+//! one full download. `cargo bench --bench etag` measures the hashing.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+
+use anyhow::Result;
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::{Body, Method, Response, StatusCode};
+
+/// Default for `DYNAMIC_ETAG_MAX_BODY_BYTES`
+pub const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Response headers a `304` keeps (RFC 9110, section 15.4.5)
+const NOT_MODIFIED_HEADERS: [header::HeaderName; 6] = [
+    header::CACHE_CONTROL,
+    header::CONTENT_LOCATION,
+    header::DATE,
+    header::ETAG,
+    header::EXPIRES,
+    header::VARY,
+];
+
+/// ETag settings for responses of the PHP worker
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DynamicEtags {
+    enabled: bool,
+    /// Larger bodies are not hashed
+    max_body_bytes: usize,
+}
+
+impl DynamicEtags {
+    pub fn from_env() -> Self {
+        Self {
+            enabled: matches!(std::env::var("DYNAMIC_ETAG").as_deref(), Ok("true") | Ok("1")),
+            max_body_bytes: std::env::var("DYNAMIC_ETAG_MAX_BODY_BYTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_BODY_BYTES),
+        }
+    }
+
+    /// Add a weak ETag to the response to a `method` request, if it qualifies
+    pub async fn tag(&self, method: &str, response: Response<Body>) -> Result<Response<Body>> {
+        if !self.enabled || method != "GET" || !is_taggable(response.status(), response.headers()) {
+            return Ok(response);
+        }
+        let size = hyper::body::HttpBody::size_hint(response.body()).exact();
+        if size.is_none_or(|size| size > self.max_body_bytes as u64) {
+            return Ok(response);
+        }
+
+        // Worker responses are complete in memory, so this copies nothing
+        let (mut parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        if let Ok(etag) = HeaderValue::from_str(&weak_etag(&body)) {
+            parts.headers.insert(header::ETAG, etag);
+        }
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+
+    /// `304 Not Modified` in place of `response` when `request_headers` ask for it
+    pub fn not_modified(&self, method: &Method, request_headers: &HeaderMap, response: Response<Body>) -> Response<Body> {
+        if !self.enabled || (method != Method::GET && method != Method::HEAD) || response.status() != StatusCode::OK {
+            return response;
+        }
+        let matches = match (request_headers.get(header::IF_NONE_MATCH), response.headers().get(header::ETAG)) {
+            (Some(if_none_match), Some(etag)) => if_none_match_matches(if_none_match, etag),
+            _ => false,
+        };
+        if !matches {
+            return response;
+        }
+        not_modified(&response)
+    }
+}
+
+/// `304 Not Modified` with the headers of `response` it keeps
+pub(crate) fn not_modified(response: &Response<Body>) -> Response<Body> {
+    let mut not_modified = Response::new(Body::empty());
+    *not_modified.status_mut() = StatusCode::NOT_MODIFIED;
+    for name in NOT_MODIFIED_HEADERS {
+        for value in response.headers().get_all(&name) {
+            not_modified.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+    not_modified
+}
+
+/// Weak ETag of `body`: its length and hash
+pub fn weak_etag(body: &[u8]) -> String {
+    format!("W/{}", strong_etag(body))
+}
+
+/// Strong ETag of `body`, for static files whose bytes are sent as they are
+pub fn strong_etag(body: &[u8]) -> String {
+    // Fixed keys, so every instance of one build tags a body alike
+    let mut hasher = DefaultHasher::new();
+    hasher.write(body);
+    format!("\"{:x}-{:016x}\"", body.len(), hasher.finish())
+}
+
+/// Whether a response with `status` and `headers` may be given an ETag
+fn is_taggable(status: StatusCode, headers: &HeaderMap) -> bool {
+    status == StatusCode::OK
+        && !headers.contains_key(header::ETAG)
+        && !headers.contains_key(header::SET_COOKIE)
+        && !headers.get_all(header::CACHE_CONTROL).iter().any(|value| {
+            value
+                .to_str()
+                .is_ok_and(|value| value.split(',').any(|directive| directive.trim().eq_ignore_ascii_case("no-store")))
+        })
+}
+
+/// Weak comparison of `etag` with the list in an `If-None-Match` header
+pub(crate) fn if_none_match_matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let (Ok(list), Ok(etag)) = (if_none_match.to_str(), etag.to_str()) else {
+        return false;
+    };
+    let etag = opaque_tag(etag);
+    list.split(',').any(|candidate| candidate.trim() == "*" || opaque_tag(candidate) == etag)
+}
+
+/// `tag` without surrounding spaces and the weak prefix
+fn opaque_tag(tag: &str) -> &str {
+    let tag = tag.trim();
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
@@ -0,0 +1,413 @@
+//! FastCGI backend: requests go to php-fpm instead of the PHP worker
+//!
+//! With `BACKEND=fastcgi` there is no long-lived artisan worker. Every
+//! request that would go over the bridge is sent to php-fpm as a FastCGI
+//! Responder request for `FASTCGI_SCRIPT_FILENAME` (by default Laravel's
+//! `public/index.php`), the way nginx does it. Static files, request hooks,
+//! coalescing, error responses and logging are handled before this point
+//! and are the same for both backends.
+//!
+//! Connections are opened with `FCGI_KEEP_CONN` and reused; at most
+//! `FASTCGI_MAX_CONNECTIONS` requests are sent to php-fpm at once, further
+//! ones wait for a free connection within the read timeout.
+//!
+//! A request whose time budget (see [`crate::deadline`]) is shorter than
+//! the read timeout is given up on when the budget runs out.
+
+pub mod protocol;
+
+use std::net::IpAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use hyper::body::Bytes;
+use hyper::{Body, Response, StatusCode};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UnixStream};
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+
+use crate::deadline::{Deadline, DEADLINE_HEADER, DEADLINE_SERVER_VAR};
+use crate::errors::{ServerError, UnavailableReason};
+use crate::server::HttpRequestPayload;
+use crate::tls::PeerCertificate;
+
+/// Request id used on every connection; requests are never multiplexed
+const REQUEST_ID: u16 = 1;
+
+/// Longest php-fpm stderr excerpt put in the log
+const MAX_STDERR_LOG_LEN: usize = 2048;
+
+/// Where php-fpm listens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FastCgiAddress {
+    Unix(String),
+    Tcp(String),
+}
+
+impl FastCgiAddress {
+    /// `unix:/path`, an absolute path, or `host:port`
+    pub fn parse(value: &str) -> Self {
+        match value.strip_prefix("unix:") {
+            Some(path) => FastCgiAddress::Unix(path.to_string()),
+            None if value.starts_with('/') || value.starts_with('.') => FastCgiAddress::Unix(value.to_string()),
+            None => FastCgiAddress::Tcp(value.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for FastCgiAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FastCgiAddress::Unix(path) => write!(f, "unix:{}", path),
+            FastCgiAddress::Tcp(addr) => f.write_str(addr),
+        }
+    }
+}
+
+/// FastCGI backend settings
+#[derive(Debug, Clone)]
+pub struct FastCgiConfig {
+    pub address: FastCgiAddress,
+    /// Front controller php-fpm runs, as a path on the php-fpm side
+    pub script_filename: String,
+    pub connect_timeout: Duration,
+    /// Maximum time for one request, including the wait for a connection
+    pub read_timeout: Duration,
+    /// Requests sent to php-fpm at once
+    pub max_connections: usize,
+    /// `SERVER_PORT` reported to PHP
+    pub server_port: u16,
+}
+
+impl FastCgiConfig {
+    /// Settings for `BACKEND=fastcgi`, or `None` for the PHP worker backend
+    pub fn from_env() -> Option<Self> {
+        if std::env::var("BACKEND").ok().as_deref() != Some("fastcgi") {
+            return None;
+        }
+
+        let env_ms = |name: &str, default: u64| {
+            Duration::from_millis(std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default))
+        };
+        let script_filename = std::env::var("FASTCGI_SCRIPT_FILENAME").unwrap_or_else(|_| {
+            let laravel_path = std::env::var("LARAVEL_PATH").unwrap_or_else(|_| "..".to_string());
+            Path::new(&laravel_path).join("public/index.php").to_string_lossy().into_owned()
+        });
+
+        Some(Self {
+            address: FastCgiAddress::parse(
+                &std::env::var("FASTCGI_ADDRESS").unwrap_or_else(|_| "127.0.0.1:9000".to_string()),
+            ),
+            script_filename,
+            connect_timeout: env_ms("FASTCGI_CONNECT_TIMEOUT_MS", 1000),
+            read_timeout: env_ms("FASTCGI_READ_TIMEOUT_MS", 30000),
+            max_connections: std::env::var("FASTCGI_MAX_CONNECTIONS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(32)
+                .max(1),
+            server_port: std::env::var("HTTP_PORT").ok().and_then(|v| v.parse().ok()).unwrap_or(8080),
+        })
+    }
+}
+
+trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
+
+type Connection = Pin<Box<dyn Stream>>;
+
+/// Client for one php-fpm pool, with its own connection pool
+pub struct FastCgiClient {
+    config: FastCgiConfig,
+    /// Kept-alive connections between requests
+    idle: Mutex<Vec<Connection>>,
+    permits: Semaphore,
+}
+
+/// What the caller knows about the request beyond the payload
+#[derive(Debug, Clone, Copy)]
+pub struct RequestInfo<'a> {
+    pub request_id: &'a str,
+    pub client_ip: IpAddr,
+    /// When php-fpm must have answered by
+    pub deadline: Deadline,
+    /// Arrived over TLS; php-fpm gets `HTTPS=on`
+    pub secure: bool,
+    /// Client certificate of the connection, passed on as `SSL_CLIENT_*` parameters
+    pub client_cert: Option<&'a PeerCertificate>,
+}
+
+impl FastCgiClient {
+    pub fn new(config: FastCgiConfig) -> Self {
+        Self {
+            permits: Semaphore::new(config.max_connections),
+            idle: Mutex::new(Vec::new()),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &FastCgiConfig {
+        &self.config
+    }
+
+    /// Check that php-fpm accepts connections
+    pub async fn probe(&self) -> Result<()> {
+        self.connect().await.map(|_| ())
+    }
+
+    /// Send the request to php-fpm and convert its CGI response
+    pub async fn forward(&self, payload: HttpRequestPayload, info: RequestInfo<'_>) -> Result<Response<Body>> {
+        let timeout = info.deadline.remaining();
+        if timeout.is_zero() {
+            return Err(ServerError::BridgeTimeout("request deadline passed before it was sent to php-fpm".to_string()).into());
+        }
+        let params = cgi_params(&payload, info, timeout, &self.config);
+        let params = protocol::encode_params(params.iter().map(|(name, value)| (name.as_str(), value.as_str())));
+        let stdin = payload.body.unwrap_or_default();
+        let request = protocol::encode_request(REQUEST_ID, true, &params, &stdin);
+
+        let stdout = tokio::time::timeout(timeout, self.exchange(&request))
+            .await
+            .map_err(|_| ServerError::BridgeTimeout(format!("no response from php-fpm within {:?}", timeout)))??;
+
+        let cgi = protocol::parse_cgi_response(stdout)
+            .map_err(|e| ServerError::UpstreamMalformed(format!("php-fpm: {:#}", e)))?;
+        let mut response = Response::builder().status(
+            StatusCode::from_u16(cgi.status)
+                .map_err(|_| ServerError::UpstreamMalformed(format!("invalid status code {}", cgi.status)))?,
+        );
+        for (name, value) in cgi.headers {
+            match (
+                hyper::header::HeaderName::from_bytes(name.as_bytes()),
+                hyper::header::HeaderValue::from_str(&value),
+            ) {
+                (Ok(name), Ok(value)) => response = response.header(name, value),
+                _ => warn!("Invalid header from php-fpm: {}", name),
+            }
+        }
+        Ok(response.body(Body::from(cgi.body))?)
+    }
+
+    /// Write the request and collect `STDOUT`, on a pooled connection when there is one
+    ///
+    /// A kept-alive connection php-fpm closed in the meantime fails before
+    /// any response arrives; the request is then sent once more on a fresh
+    /// connection.
+    async fn exchange(&self, request: &[u8]) -> Result<Bytes> {
+        let _permit = self.permits.acquire().await?;
+
+        if let Some(mut connection) = self.take_idle() {
+            match exchange_on(&mut connection, request).await {
+                Ok(stdout) => {
+                    self.put_idle(connection);
+                    return Ok(stdout);
+                }
+                Err(Exchange::Stale(e)) => debug!("Pooled php-fpm connection was closed, reconnecting: {:#}", e),
+                Err(Exchange::Failed(e)) => return Err(e),
+            }
+        }
+
+        let mut connection = self.connect().await?;
+        match exchange_on(&mut connection, request).await {
+            Ok(stdout) => {
+                self.put_idle(connection);
+                Ok(stdout)
+            }
+            Err(Exchange::Stale(e)) => Err(e.context(ServerError::bridge_down("php-fpm closed the connection"))),
+            Err(Exchange::Failed(e)) => Err(e),
+        }
+    }
+
+    async fn connect(&self) -> Result<Connection> {
+        let connecting = async {
+            Ok::<Connection, std::io::Error>(match &self.config.address {
+                FastCgiAddress::Unix(path) => Box::pin(UnixStream::connect(path).await?),
+                FastCgiAddress::Tcp(addr) => {
+                    let stream = TcpStream::connect(addr).await?;
+                    stream.set_nodelay(true)?;
+                    Box::pin(stream)
+                }
+            })
+        };
+        match tokio::time::timeout(self.config.connect_timeout, connecting).await {
+            Ok(Ok(connection)) => Ok(connection),
+            Ok(Err(e)) => Err(anyhow::Error::from(e).context(ServerError::bridge_down(format!(
+                "cannot connect to php-fpm at {}",
+                self.config.address
+            )))),
+            Err(_) => Err(ServerError::Unavailable {
+                reason: UnavailableReason::BridgeDown,
+                retry_after: None,
+                message: format!(
+                    "no connection to php-fpm at {} within {:?}",
+                    self.config.address, self.config.connect_timeout
+                ),
+            }
+            .into()),
+        }
+    }
+
+    fn take_idle(&self) -> Option<Connection> {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop()
+    }
+
+    fn put_idle(&self, connection: Connection) {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        if idle.len() < self.config.max_connections {
+            idle.push(connection);
+        }
+    }
+}
+
+/// Failure of one exchange
+enum Exchange {
+    /// Nothing came back; on a reused connection the request may be resent
+    Stale(anyhow::Error),
+    Failed(anyhow::Error),
+}
+
+/// Run one request on `connection` and return its `STDOUT`
+///
+/// The connection can be reused only when this succeeds.
+async fn exchange_on(connection: &mut Connection, request: &[u8]) -> Result<Bytes, Exchange> {
+    connection
+        .write_all(request)
+        .await
+        .map_err(|e| Exchange::Stale(e.into()))?;
+
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let mut received = false;
+    loop {
+        let record = match protocol::read_record(connection).await {
+            Ok(Some(record)) => record,
+            Ok(None) if !received => return Err(Exchange::Stale(anyhow!("connection closed before a response"))),
+            Ok(None) => {
+                return Err(Exchange::Failed(
+                    ServerError::UpstreamMalformed("php-fpm closed the connection mid-response".to_string()).into(),
+                ))
+            }
+            Err(e) if !received => return Err(Exchange::Stale(e)),
+            Err(e) => {
+                return Err(Exchange::Failed(
+                    e.context(ServerError::UpstreamMalformed("unreadable response from php-fpm".to_string())),
+                ))
+            }
+        };
+        received = true;
+        if record.request_id != REQUEST_ID {
+            continue;
+        }
+
+        match record.kind {
+            protocol::STDOUT => stdout.extend_from_slice(&record.content),
+            protocol::STDERR => stderr.extend_from_slice(&record.content),
+            protocol::END_REQUEST => {
+                log_stderr(&stderr);
+                let (app_status, protocol_status) = protocol::parse_end_request(&record.content)
+                    .map_err(|e| Exchange::Failed(e.context(ServerError::UpstreamMalformed("php-fpm".to_string()))))?;
+                return match protocol_status {
+                    protocol::REQUEST_COMPLETE => {
+                        if app_status != 0 {
+                            debug!(app_status, "php-fpm script exited with a non-zero status");
+                        }
+                        Ok(Bytes::from(stdout))
+                    }
+                    protocol::OVERLOADED => Err(Exchange::Failed(
+                        ServerError::Unavailable {
+                            reason: UnavailableReason::Overloaded,
+                            retry_after: None,
+                            message: "php-fpm is overloaded".to_string(),
+                        }
+                        .into(),
+                    )),
+                    status => Err(Exchange::Failed(
+                        ServerError::UpstreamMalformed(format!("php-fpm rejected the request (protocol status {})", status))
+                            .into(),
+                    )),
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+/// PHP warnings and errors php-fpm forwards on `STDERR`, like nginx logs them
+fn log_stderr(stderr: &[u8]) {
+    if stderr.is_empty() {
+        return;
+    }
+    let text = String::from_utf8_lossy(&stderr[..stderr.len().min(MAX_STDERR_LOG_LEN)]);
+    warn!(truncated = stderr.len() > MAX_STDERR_LOG_LEN, "php-fpm stderr: {}", text.trim_end());
+}
+
+/// CGI/1.1 parameters for `payload`, as nginx's `fastcgi_params` would set them
+///
+/// `remaining` is the request's time budget, passed on like to the worker.
+pub fn cgi_params(
+    payload: &HttpRequestPayload,
+    info: RequestInfo<'_>,
+    remaining: Duration,
+    config: &FastCgiConfig,
+) -> Vec<(String, String)> {
+    let (path, query) = payload.uri.split_once('?').unwrap_or((&payload.uri, ""));
+    let document_root = Path::new(&config.script_filename)
+        .parent()
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let script_name = Path::new(&config.script_filename)
+        .file_name()
+        .map(|name| format!("/{}", name.to_string_lossy()))
+        .unwrap_or_default();
+    let host = payload.headers.get("host").map(String::as_str).unwrap_or("localhost");
+    let server_name = host.rsplit_once(':').filter(|(_, port)| port.parse::<u16>().is_ok()).map_or(host, |(name, _)| name);
+    let content_length = payload.body.as_ref().map_or(0, |body| body.len());
+
+    let mut params: Vec<(String, String)> = [
+        ("GATEWAY_INTERFACE", "CGI/1.1".to_string()),
+        ("SERVER_SOFTWARE", format!("laravel-rust-server/{}", crate::build_info().version)),
+        ("SERVER_PROTOCOL", "HTTP/1.1".to_string()),
+        ("SERVER_NAME", server_name.to_string()),
+        ("SERVER_PORT", config.server_port.to_string()),
+        ("REMOTE_ADDR", info.client_ip.to_string()),
+        ("REQUEST_METHOD", payload.method.clone()),
+        ("REQUEST_URI", payload.uri.clone()),
+        ("DOCUMENT_URI", path.to_string()),
+        ("QUERY_STRING", query.to_string()),
+        ("SCRIPT_FILENAME", config.script_filename.clone()),
+        ("SCRIPT_NAME", script_name),
+        ("DOCUMENT_ROOT", document_root),
+        ("CONTENT_TYPE", payload.headers.get("content-type").cloned().unwrap_or_default()),
+        ("CONTENT_LENGTH", content_length.to_string()),
+        // Same id as in our logs, as in the worker frame
+        ("REQUEST_ID", info.request_id.to_string()),
+        (DEADLINE_SERVER_VAR, remaining.as_millis().to_string()),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value))
+    .collect();
+    if info.secure {
+        params.push(("HTTPS".to_string(), "on".to_string()));
+    }
+    if let Some(client_cert) = info.client_cert {
+        params.extend(client_cert.server_vars().into_iter().map(|(name, value)| (name.to_string(), value)));
+    }
+
+    for (name, value) in &payload.headers {
+        // Content headers have their own parameters; `Proxy` would become
+        // HTTP_PROXY and redirect PHP's outgoing requests (httpoxy)
+        if matches!(name.to_ascii_lowercase().as_str(), "content-type" | "content-length" | "proxy") {
+            continue;
+        }
+        params.push((format!("HTTP_{}", name.to_ascii_uppercase().replace('-', "_")), value.clone()));
+    }
+    // The caller's budget is replaced by what is left of it
+    let deadline_param = format!("HTTP_{}", DEADLINE_HEADER.to_ascii_uppercase().replace('-', "_"));
+    params.retain(|(name, _)| *name != deadline_param);
+    params.push((deadline_param, remaining.as_millis().to_string()));
+    params
+}
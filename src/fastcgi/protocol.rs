@@ -0,0 +1,186 @@
+//! FastCGI record layer (FastCGI 1.0 specification)
+//!
+//! Only what a web server needs for the Responder role: writing
+//! `BEGIN_REQUEST`, `PARAMS` and `STDIN` streams, and reading `STDOUT`,
+//! `STDERR` and `END_REQUEST` records back.
+
+use anyhow::{bail, Result};
+use hyper::body::Bytes;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+pub const VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 8;
+/// Largest content of a single record
+pub const MAX_CONTENT_LEN: usize = u16::MAX as usize;
+
+pub const BEGIN_REQUEST: u8 = 1;
+pub const ABORT_REQUEST: u8 = 2;
+pub const END_REQUEST: u8 = 3;
+pub const PARAMS: u8 = 4;
+pub const STDIN: u8 = 5;
+pub const STDOUT: u8 = 6;
+pub const STDERR: u8 = 7;
+
+pub const ROLE_RESPONDER: u16 = 1;
+/// `BEGIN_REQUEST` flag: keep the connection open after the request
+pub const FLAG_KEEP_CONN: u8 = 1;
+
+/// `protocolStatus` values of `END_REQUEST`
+pub const REQUEST_COMPLETE: u8 = 0;
+pub const CANT_MPX_CONN: u8 = 1;
+pub const OVERLOADED: u8 = 2;
+pub const UNKNOWN_ROLE: u8 = 3;
+
+/// One record as read from the connection
+#[derive(Debug)]
+pub struct Record {
+    pub kind: u8,
+    pub request_id: u16,
+    pub content: Bytes,
+}
+
+/// Append one record; `content` must fit in [`MAX_CONTENT_LEN`]
+fn push_record(out: &mut Vec<u8>, kind: u8, request_id: u16, content: &[u8]) {
+    debug_assert!(content.len() <= MAX_CONTENT_LEN);
+    // Pad content to a multiple of 8 bytes, as the specification recommends
+    let padding = (8 - content.len() % 8) % 8;
+    out.extend_from_slice(&[VERSION, kind]);
+    out.extend_from_slice(&request_id.to_be_bytes());
+    out.extend_from_slice(&(content.len() as u16).to_be_bytes());
+    out.extend_from_slice(&[padding as u8, 0]);
+    out.extend_from_slice(content);
+    out.extend_from_slice(&[0; 8][..padding]);
+}
+
+/// Append `data` as a stream of `kind` records, including the empty record that ends it
+fn push_stream(out: &mut Vec<u8>, kind: u8, request_id: u16, data: &[u8]) {
+    for chunk in data.chunks(MAX_CONTENT_LEN - MAX_CONTENT_LEN % 8) {
+        push_record(out, kind, request_id, chunk);
+    }
+    push_record(out, kind, request_id, &[]);
+}
+
+/// Append the length of a name or value in the name-value pair encoding
+fn push_length(out: &mut Vec<u8>, len: usize) {
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        out.extend_from_slice(&(len as u32 | 0x8000_0000).to_be_bytes());
+    }
+}
+
+/// Encode CGI parameters as name-value pairs
+pub fn encode_params<'a>(params: impl IntoIterator<Item = (&'a str, &'a str)>) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (name, value) in params {
+        push_length(&mut out, name.len());
+        push_length(&mut out, value.len());
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(value.as_bytes());
+    }
+    out
+}
+
+/// Every record of one Responder request: begin, parameters and body
+pub fn encode_request(request_id: u16, keep_conn: bool, params: &[u8], stdin: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(3 * HEADER_LEN + params.len() + stdin.len() + 64);
+    let flags = if keep_conn { FLAG_KEEP_CONN } else { 0 };
+    let [role_hi, role_lo] = ROLE_RESPONDER.to_be_bytes();
+    push_record(&mut out, BEGIN_REQUEST, request_id, &[role_hi, role_lo, flags, 0, 0, 0, 0, 0]);
+    push_stream(&mut out, PARAMS, request_id, params);
+    push_stream(&mut out, STDIN, request_id, stdin);
+    out
+}
+
+/// Read the next record; `Ok(None)` on a clean end of stream between records
+pub async fn read_record<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Record>> {
+    let mut header = [0u8; HEADER_LEN];
+    match reader.read(&mut header[..1]).await? {
+        0 => return Ok(None),
+        _ => reader.read_exact(&mut header[1..]).await?,
+    };
+    if header[0] != VERSION {
+        bail!("unsupported FastCGI version {}", header[0]);
+    }
+
+    let content_len = u16::from_be_bytes([header[4], header[5]]) as usize;
+    let padding = header[6] as usize;
+    let mut content = vec![0; content_len + padding];
+    reader.read_exact(&mut content).await?;
+    content.truncate(content_len);
+
+    Ok(Some(Record {
+        kind: header[1],
+        request_id: u16::from_be_bytes([header[2], header[3]]),
+        content: Bytes::from(content),
+    }))
+}
+
+/// `appStatus` and `protocolStatus` of an `END_REQUEST` body
+pub fn parse_end_request(content: &[u8]) -> Result<(u32, u8)> {
+    if content.len() < 8 {
+        bail!("END_REQUEST record is {} bytes, expected 8", content.len());
+    }
+    Ok((u32::from_be_bytes([content[0], content[1], content[2], content[3]]), content[4]))
+}
+
+/// Status line, headers and body of a CGI response (RFC 3875, section 6)
+#[derive(Debug)]
+pub struct CgiResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Bytes,
+}
+
+/// Split the `STDOUT` stream into status, headers and body
+///
+/// Without a `Status` header the status is 302 when there is a `Location`
+/// and 200 otherwise. Both CRLF and bare LF line endings are accepted.
+pub fn parse_cgi_response(stdout: Bytes) -> Result<CgiResponse> {
+    let (head_len, body_start) = match find_header_end(&stdout) {
+        Some(end) => end,
+        None => bail!("response has no end of headers ({} bytes)", stdout.len()),
+    };
+    let head = std::str::from_utf8(&stdout[..head_len]).map_err(|_| anyhow::anyhow!("response headers are not UTF-8"))?;
+
+    let mut status = None;
+    let mut headers = Vec::new();
+    for line in head.lines().filter(|line| !line.is_empty()) {
+        let Some((name, value)) = line.split_once(':') else {
+            bail!("malformed response header line {:?}", line);
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("status") {
+            let code = value.split_whitespace().next().and_then(|code| code.parse::<u16>().ok());
+            match code {
+                Some(code) => status = Some(code),
+                None => bail!("malformed Status header {:?}", value),
+            }
+        } else {
+            headers.push((name.trim().to_string(), value.to_string()));
+        }
+    }
+
+    let has_location = headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("location"));
+    Ok(CgiResponse {
+        status: status.unwrap_or(if has_location { 302 } else { 200 }),
+        headers,
+        body: stdout.slice(body_start..),
+    })
+}
+
+/// Length of the header block and start of the body
+fn find_header_end(data: &[u8]) -> Option<(usize, usize)> {
+    let mut line_start = 0;
+    for (i, byte) in data.iter().enumerate() {
+        if *byte != b'\n' {
+            continue;
+        }
+        let line = &data[line_start..i];
+        if line.is_empty() || line == b"\r" {
+            return Some((line_start, i + 1));
+        }
+        line_start = i + 1;
+    }
+    None
+}
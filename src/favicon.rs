@@ -0,0 +1,104 @@
+//! Answers for missing favicons
+//!
+//! Browsers ask for `/favicon.ico` on a visitor's first page view, and iOS
+//! for `/apple-touch-icon*.png`, whether or not the page links an icon.
+//! When `public/` has no such file, `FAVICON_FALLBACK` decides the answer:
+//!
+//! * `not_found` (default) - the usual 404;
+//! * `no_content` - an empty 204;
+//! * `default` - a built-in icon, a plain rounded square.
+//!
+//! Each carries `FAVICON_CACHE_CONTROL`, so clients stop asking on every
+//! page view. Icons that exist in `public/` are served like any static file.
+
+use hyper::header::{self, HeaderValue};
+use hyper::{Body, Response, StatusCode};
+
+/// Default for `FAVICON_CACHE_CONTROL`
+pub const DEFAULT_CACHE_CONTROL: &str = "public, max-age=86400";
+
+/// Built-in `/favicon.ico`: a 32×32 PNG in an ICO container
+const DEFAULT_FAVICON: &[u8] = include_bytes!("../assets/favicon.ico");
+
+/// Built-in `/apple-touch-icon*.png`: 180×180, the size iOS asks for
+const DEFAULT_TOUCH_ICON: &[u8] = include_bytes!("../assets/apple-touch-icon.png");
+
+/// Answer for an icon missing from `public/` (`FAVICON_FALLBACK`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FaviconFallback {
+    #[default]
+    NotFound,
+    NoContent,
+    Default,
+}
+
+/// Missing icon handling of the static file handler
+#[derive(Debug, Clone)]
+pub struct FaviconPolicy {
+    fallback: FaviconFallback,
+    cache_control: HeaderValue,
+}
+
+impl FaviconPolicy {
+    pub fn from_env() -> Result<Self, Vec<(&'static str, String)>> {
+        let mut problems = Vec::new();
+        let fallback = match std::env::var("FAVICON_FALLBACK").unwrap_or_default().to_ascii_lowercase().as_str() {
+            "" | "not_found" | "404" => FaviconFallback::NotFound,
+            "no_content" | "204" => FaviconFallback::NoContent,
+            "default" => FaviconFallback::Default,
+            other => {
+                problems.push((
+                    "FAVICON_FALLBACK",
+                    format!("must be one of not_found, no_content, default, got {:?}", other),
+                ));
+                FaviconFallback::NotFound
+            }
+        };
+        let cache_control = std::env::var("FAVICON_CACHE_CONTROL").unwrap_or_else(|_| DEFAULT_CACHE_CONTROL.to_string());
+        let cache_control = HeaderValue::from_str(&cache_control).unwrap_or_else(|_| {
+            problems.push(("FAVICON_CACHE_CONTROL", format!("{:?} is not a valid header value", cache_control)));
+            HeaderValue::from_static(DEFAULT_CACHE_CONTROL)
+        });
+
+        if problems.is_empty() {
+            Ok(Self { fallback, cache_control })
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Whether `path` is one of the icons browsers request on their own
+    pub fn is_icon_path(path: &str) -> bool {
+        path == "/favicon.ico"
+            || path
+                .strip_prefix("/apple-touch-icon")
+                .and_then(|rest| rest.strip_suffix(".png"))
+                .is_some_and(|variant| !variant.contains('/'))
+    }
+
+    /// Response for the icon at `path` missing from `public/`
+    ///
+    /// With `not_found` this is the caller's usual 404, built by
+    /// `not_found`, with the icon `Cache-Control` added.
+    pub fn missing_response(&self, path: &str, not_found: impl FnOnce() -> Response<Body>) -> Response<Body> {
+        let response = match self.fallback {
+            FaviconFallback::NotFound => Ok(not_found()),
+            FaviconFallback::NoContent => Response::builder().status(StatusCode::NO_CONTENT).body(Body::empty()),
+            FaviconFallback::Default => {
+                let (content_type, icon) = if path == "/favicon.ico" {
+                    ("image/vnd.microsoft.icon", DEFAULT_FAVICON)
+                } else {
+                    ("image/png", DEFAULT_TOUCH_ICON)
+                };
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .header(header::CONTENT_TYPE, content_type)
+                    .header(header::CONTENT_LENGTH, icon.len())
+                    .body(Body::from(icon))
+            }
+        };
+        let mut response = response.unwrap_or_else(|_| Response::new(Body::empty()));
+        response.headers_mut().insert(header::CACHE_CONTROL, self.cache_control.clone());
+        response
+    }
+}
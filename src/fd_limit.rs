@@ -0,0 +1,160 @@
+//! Open file limit of the server process
+//!
+//! Every client connection, pooled bridge connection, listener and log
+//! file is a file descriptor, and the common default soft limit of 1024 is
+//! reached long before the configured concurrency is. At startup the soft
+//! `RLIMIT_NOFILE` is compared with an estimate derived from the config; a
+//! limit below it is logged as a warning, and with `RAISE_NOFILE=true` the
+//! soft limit is first raised toward the hard limit. The estimate and the
+//! final limits are kept for `/admin/stats` and `config validate`.
+
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use serde_json::json;
+use tracing::{info, warn};
+
+/// Descriptors for stdio, log files, epoll, timers and static files being served
+const BASE_DESCRIPTORS: u64 = 64;
+
+/// Estimate and limits seen at startup
+static REPORT: Lazy<Mutex<Option<serde_json::Value>>> = Lazy::new(|| Mutex::new(None));
+
+/// Descriptors the configuration may need at once
+#[derive(Debug, Clone, Copy)]
+pub struct FdEstimate {
+    /// Client connections: every request in flight to the backend, and as
+    /// many again waiting for a slot or idle on keep-alive
+    pub clients: u64,
+    /// Connections to the PHP worker or php-fpm
+    pub backend: u64,
+    /// Open WebSocket connections, when enabled
+    pub websockets: u64,
+    /// HTTP, admin and gRPC listeners
+    pub listeners: u64,
+}
+
+impl FdEstimate {
+    pub fn from_env() -> Self {
+        let fastcgi = std::env::var("BACKEND").is_ok_and(|v| v.eq_ignore_ascii_case("fastcgi"));
+        let in_flight = if fastcgi {
+            env_or("FASTCGI_MAX_CONNECTIONS", 32)
+        } else if env_flag("ADAPTIVE_CONCURRENCY") {
+            env_or("ADAPTIVE_CONCURRENCY_MAX", 256)
+        } else {
+            env_or("SOCKET_MAX_CONCURRENT_FRAMES", 256)
+        };
+        let backend = if fastcgi {
+            env_or("FASTCGI_MAX_CONNECTIONS", 32)
+        } else {
+            env_or("SOCKET_POOL_MAX", 10)
+        };
+        // Every TENANTS application has a pool of its own
+        let tenants = crate::tenants::TenantSpec::from_env().map_or(0, |specs| specs.len() as u64);
+        Self {
+            clients: in_flight * 2,
+            backend: backend + tenants * env_or("SOCKET_POOL_MAX", 10),
+            websockets: if env_flag("WS_ENABLED") { env_or("WS_MAX_CONNECTIONS", 10000) } else { 0 },
+            listeners: 1 + u64::from(env_flag("ADMIN_ENABLED")) + u64::from(env_flag("GRPC_ENABLED")),
+        }
+    }
+
+    /// Total, including `BASE_DESCRIPTORS`
+    pub fn total(&self) -> u64 {
+        self.clients + self.backend + self.websockets + self.listeners + BASE_DESCRIPTORS
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "clients": self.clients,
+            "backend": self.backend,
+            "websockets": self.websockets,
+            "listeners": self.listeners,
+            "base": BASE_DESCRIPTORS,
+            "total": self.total(),
+        })
+    }
+}
+
+/// Current soft and hard `RLIMIT_NOFILE`
+pub fn current_limits() -> std::io::Result<(u64, u64)> {
+    let mut rlim = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    // SAFETY: plain syscall with a valid pointer to a stack value
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlim) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok((rlim.rlim_cur, rlim.rlim_max))
+}
+
+/// Set the soft limit, keeping the hard one
+fn set_soft_limit(soft: u64, hard: u64) -> std::io::Result<()> {
+    let rlim = libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+    // SAFETY: plain syscall with a valid pointer to a stack value
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &rlim) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Compare the limit with the estimate, raise it if asked to, and log the outcome
+pub fn check_at_startup() {
+    let estimate = FdEstimate::from_env();
+    let needed = estimate.total();
+    let (soft, hard) = match current_limits() {
+        Ok(limits) => limits,
+        Err(e) => {
+            warn!(error = %e, "Could not read RLIMIT_NOFILE");
+            return;
+        }
+    };
+
+    let mut raised_from = None;
+    if soft < needed && env_flag("RAISE_NOFILE") && soft < hard {
+        // Go all the way to the hard limit; an unlimited hard limit is above
+        // the kernel's nr_open and refused, so fall back to the estimate
+        match set_soft_limit(hard, hard).or_else(|_| set_soft_limit(needed, hard)) {
+            Ok(()) => raised_from = Some(soft),
+            Err(e) => warn!(error = %e, soft, hard, "Failed to raise RLIMIT_NOFILE"),
+        }
+    }
+    let (soft, hard) = current_limits().unwrap_or((soft, hard));
+
+    if soft < needed {
+        warn!(
+            soft,
+            hard,
+            needed,
+            estimate = %estimate.to_json(),
+            "⚠️ Open file limit is below what the configuration may need; expect EMFILE under load. \
+             Raise it (ulimit -n, LimitNOFILE=) or set RAISE_NOFILE=true"
+        );
+    } else if let Some(previous) = raised_from {
+        info!(soft, hard, previous, needed, "Raised the open file limit");
+    } else {
+        info!(soft, hard, needed, "Open file limit");
+    }
+
+    *REPORT.lock().unwrap_or_else(|e| e.into_inner()) = Some(json!({
+        "soft": soft,
+        "hard": hard,
+        "raised_from": raised_from,
+        "estimate": estimate.to_json(),
+        "sufficient": soft >= needed,
+    }));
+}
+
+/// Limits and estimate seen at startup, for stats
+pub fn report() -> Option<serde_json::Value> {
+    REPORT.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+fn env_flag(name: &str) -> bool {
+    matches!(std::env::var(name).as_deref(), Ok("true") | Ok("1"))
+}
+
+fn env_or(name: &str, default: u64) -> u64 {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
@@ -0,0 +1,245 @@
+//! Contents of small static files kept in memory
+//!
+//! With `STATIC_MEMORY_CACHE_BYTES` set, a static file no larger than
+//! `STATIC_STREAM_THRESHOLD` is read from disk once and then served from
+//! memory, with a strong ETag computed from its contents, and a GET or HEAD
+//! whose `If-None-Match` matches that ETag gets `304 Not Modified`. Files
+//! are kept until the budget is used up; those that no longer fit are read
+//! from disk on every request as before.
+//!
+//! A deploy that overwrites files in place (`rsync` into `public/`) would
+//! leave their old contents in memory, so entries are evicted two ways:
+//!
+//! * `POST /admin/cache/static/flush` on the admin listener evicts every
+//!   entry, or with `?prefix=/build/` those whose URL path starts with it
+//! * every `STATIC_MEMORY_CACHE_RESCAN_MS` the cached files are looked up
+//!   on disk, and those whose modification time or size changed, or that
+//!   are gone, are evicted
+//!
+//! Both log how many entries they evicted. The flush also re-reads the
+//! Vite/Mix manifests even when they look unchanged; otherwise their own
+//! watcher picks up changes (`STATIC_MANIFEST_RELOAD_MS`). Responses already
+//! being sent keep the contents they started with, and a file read while a
+//! flush runs is served but not kept, so it cannot bring back what the
+//! flush evicted.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime};
+
+use hyper::body::Bytes;
+use hyper::header::HeaderValue;
+use tracing::info;
+
+use crate::metrics::{metrics, MetricKind};
+use crate::sniff::{self, SNIFF_LEN};
+use crate::static_cache::AssetManifest;
+
+/// Default for `STATIC_MEMORY_CACHE_RESCAN_MS`
+pub const DEFAULT_RESCAN_MS: u64 = 2000;
+
+/// A static file read into memory
+#[derive(Debug)]
+pub struct CachedFile {
+    /// URL path it was first requested under, matched by flush prefixes
+    pub url_path: String,
+    pub contents: Bytes,
+    /// Content type detected from the first bytes, for files without a known extension
+    pub sniffed_type: Option<&'static str>,
+    /// Strong ETag of the contents
+    pub etag: HeaderValue,
+    /// Modification time and size when read
+    signature: Option<(SystemTime, u64)>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    files: HashMap<PathBuf, Arc<CachedFile>>,
+    /// Total size of the cached contents
+    bytes: u64,
+}
+
+impl Entries {
+    /// Remove the entries `evict` selects; returns how many there were
+    fn evict(&mut self, mut evict: impl FnMut(&Path, &Arc<CachedFile>) -> bool) -> usize {
+        let before = self.files.len();
+        self.files.retain(|path, file| !evict(path, file));
+        self.bytes = self.files.values().map(|file| file.contents.len() as u64).sum();
+        before - self.files.len()
+    }
+}
+
+/// Static files held in memory, shared by every static root
+#[derive(Debug, Default)]
+pub struct FileCache {
+    /// Most bytes of contents kept; 0 keeps nothing
+    max_bytes: u64,
+    /// Cached files by their resolved path
+    entries: Mutex<Entries>,
+    /// Bumped by every flush; a file read across one is not kept
+    generation: AtomicU64,
+    /// Build manifests of the static roots, re-read on invalidation
+    manifests: Mutex<Vec<Weak<AssetManifest>>>,
+}
+
+impl FileCache {
+    pub fn new(max_bytes: u64) -> Self {
+        metrics().describe(
+            "static_memory_cache_evictions_total",
+            MetricKind::Counter,
+            "Static files evicted from memory, by what evicted them",
+        );
+        Self {
+            max_bytes,
+            ..Self::default()
+        }
+    }
+
+    /// Budget from `STATIC_MEMORY_CACHE_BYTES`
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("STATIC_MEMORY_CACHE_BYTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0),
+        )
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_bytes > 0
+    }
+
+    /// Number of cached files and their total size
+    pub fn usage(&self) -> (usize, u64) {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        (entries.files.len(), entries.bytes)
+    }
+
+    /// Re-read `manifest` whenever the cache is invalidated
+    pub fn track_manifest(&self, manifest: &Arc<AssetManifest>) {
+        self.manifests.lock().unwrap_or_else(|e| e.into_inner()).push(Arc::downgrade(manifest));
+    }
+
+    /// The file at the resolved `path`, read from memory or else from disk
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(None)` - the cache is off or the file is larger than `max_len`;
+    ///   it is left to the caller to read
+    pub async fn get(&self, url_path: &str, path: &Path, max_len: u64) -> std::io::Result<Option<Arc<CachedFile>>> {
+        use tokio::io::AsyncReadExt;
+
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+        if let Some(file) = self.entries.lock().unwrap_or_else(|e| e.into_inner()).files.get(path) {
+            return Ok(Some(file.clone()));
+        }
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let mut file = tokio::fs::File::open(path).await?;
+        let metadata = file.metadata().await?;
+        if metadata.is_dir() {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "is a directory"));
+        }
+        if metadata.len() > max_len {
+            return Ok(None);
+        }
+        let mut contents = Vec::with_capacity(metadata.len() as usize);
+        file.read_to_end(&mut contents).await?;
+        let cached = Arc::new(CachedFile {
+            url_path: url_path.to_string(),
+            sniffed_type: sniff::content_type(&contents[..contents.len().min(SNIFF_LEN)]),
+            etag: HeaderValue::from_str(&crate::etag::strong_etag(&contents)).expect("ETags are ASCII"),
+            signature: signature(&metadata),
+            contents: Bytes::from(contents),
+        });
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let size = cached.contents.len() as u64;
+        if self.generation.load(Ordering::Acquire) == generation && entries.bytes + size <= self.max_bytes {
+            entries.bytes += size;
+            entries.files.insert(path.to_path_buf(), cached.clone());
+        }
+        Ok(Some(cached))
+    }
+
+    /// Evict every file, or those whose URL path starts with `prefix`, and re-read the manifests
+    ///
+    /// Returns the number of files evicted.
+    pub fn flush(&self, prefix: Option<&str>) -> usize {
+        let evicted = {
+            let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            // Under the lock, so a read that started before cannot be kept after it
+            self.generation.fetch_add(1, Ordering::AcqRel);
+            entries.evict(|_, file| prefix.is_none_or(|prefix| file.url_path.starts_with(prefix)))
+        };
+        let manifests = self.reload_manifests();
+        metrics().add_counter("static_memory_cache_evictions_total", &[("trigger", "flush")], evicted as u64);
+        info!(prefix = prefix.unwrap_or("/"), evicted, manifests, "Flushed static files from memory");
+        evicted
+    }
+
+    /// Evict the files that changed on disk
+    ///
+    /// Returns the number of files evicted. Blocks on the filesystem.
+    pub fn rescan(&self) -> usize {
+        let cached: Vec<(PathBuf, Arc<CachedFile>)> = {
+            let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            entries.files.iter().map(|(path, file)| (path.clone(), file.clone())).collect()
+        };
+        let changed: Vec<(PathBuf, Arc<CachedFile>)> = cached
+            .into_iter()
+            .filter(|(path, file)| {
+                let current = std::fs::metadata(path).ok().and_then(|metadata| signature(&metadata));
+                current.is_none() || current != file.signature
+            })
+            .collect();
+
+        let evicted = if changed.is_empty() {
+            0
+        } else {
+            let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            // Only the entries looked at; one read again meanwhile is already current
+            entries.evict(|path, file| changed.iter().any(|(p, f)| p == path && Arc::ptr_eq(f, file)))
+        };
+        if evicted > 0 {
+            metrics().add_counter("static_memory_cache_evictions_total", &[("trigger", "rescan")], evicted as u64);
+            info!(evicted, "Static files changed on disk, evicted from memory");
+        }
+        evicted
+    }
+
+    /// Re-read the tracked manifests, changed or not; returns how many there are
+    fn reload_manifests(&self) -> usize {
+        let mut manifests = self.manifests.lock().unwrap_or_else(|e| e.into_inner());
+        manifests.retain(|manifest| manifest.strong_count() > 0);
+        for manifest in manifests.iter().filter_map(Weak::upgrade) {
+            manifest.invalidate();
+        }
+        manifests.len()
+    }
+
+    /// Rescan the cached files every `interval` while the cache is in use
+    pub fn spawn_watcher(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let cache = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(cache) = cache.upgrade() else {
+                    break;
+                };
+                let _ = tokio::task::spawn_blocking(move || cache.rescan()).await;
+            }
+        })
+    }
+}
+
+/// Modification time and size of a file
+fn signature(metadata: &std::fs::Metadata) -> Option<(SystemTime, u64)> {
+    Some((metadata.modified().ok()?, metadata.len()))
+}
@@ -0,0 +1,158 @@
+//! Parameters of `application/x-www-form-urlencoded` request bodies
+//!
+//! The PHP worker builds `$request->all()` from the `parameters` of the
+//! frame, so a classic HTML form post would reach Laravel empty if only the
+//! query string were sent there. Form bodies are decoded the way PHP
+//! decodes them: `+` is a space, `%XX` escapes are undone, and bracketed
+//! names build arrays (`tags[]=a&tags[]=b`, `user[name]=x`). Body values
+//! replace query values of the same name, as in PHP, and the raw body is
+//! still sent in `content` for applications that parse it themselves.
+//!
+//! The body has passed the request body limit before it gets here. Beyond
+//! that, PHP's own defaults apply: at most `MAX_INPUT_VARS` pairs are read
+//! and names nest at most `MAX_NESTING` levels deep. Pairs that cannot be
+//! decoded, or exceed those limits, are skipped with a debug log.
+
+use serde_json::{Map, Value};
+use tracing::debug;
+
+/// Media type of the bodies that are parsed
+pub const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+
+/// Pairs read from one body, as PHP's `max_input_vars`
+const MAX_INPUT_VARS: usize = 1000;
+
+/// Bracket levels of one name, as PHP's `max_input_nesting_level`
+const MAX_NESTING: usize = 64;
+
+/// Whether a `Content-Type` value announces a form body
+pub fn is_form(content_type: &str) -> bool {
+    content_type
+        .split(';')
+        .next()
+        .is_some_and(|media| media.trim().eq_ignore_ascii_case(FORM_CONTENT_TYPE))
+}
+
+/// Decode a form body into `parameters`, replacing values of the same name
+pub fn merge_into(parameters: &mut Map<String, Value>, body: &[u8]) {
+    let mut form = Map::new();
+    let pairs = body.split(|&byte| byte == b'&').filter(|pair| !pair.is_empty());
+    for (index, pair) in pairs.enumerate() {
+        if index == MAX_INPUT_VARS {
+            debug!(limit = MAX_INPUT_VARS, "Form body has more pairs than are read, skipping the rest");
+            break;
+        }
+        let (name, value) = match pair.iter().position(|&byte| byte == b'=') {
+            Some(at) => (&pair[..at], &pair[at + 1..]),
+            None => (pair, &b""[..]),
+        };
+        let (Some(name), Some(value)) = (decode(name), decode(value)) else {
+            debug!(pair = %String::from_utf8_lossy(pair), "Skipping form pair that is not valid UTF-8 once decoded");
+            continue;
+        };
+        insert(&mut form, &name, Value::String(value));
+    }
+    // Whole values are replaced, as `$request->all()` does with the query
+    parameters.extend(form);
+}
+
+/// Store `value` under the bracketed field `name` in `parameters`, as PHP does
+///
+/// Names that are malformed or nested too deep are skipped with a debug log.
+pub(crate) fn insert(parameters: &mut Map<String, Value>, name: &str, value: Value) {
+    let Some((base, path)) = split_name(name) else {
+        debug!(name = %name, "Skipping form pair with a malformed name");
+        return;
+    };
+    if path.len() > MAX_NESTING {
+        debug!(name = %name, limit = MAX_NESTING, "Skipping form pair nested too deep");
+        return;
+    }
+    let slot = parameters.entry(base).or_insert(Value::Null);
+    set(slot, &path, value);
+}
+
+/// `+` as a space and `%XX` escapes undone; `None` if the result is not UTF-8
+pub(crate) fn decode(raw: &[u8]) -> Option<String> {
+    let mut bytes = Vec::with_capacity(raw.len());
+    let mut at = 0;
+    while at < raw.len() {
+        match raw[at] {
+            b'+' => bytes.push(b' '),
+            b'%' => match raw.get(at + 1..at + 3).and_then(|hex| std::str::from_utf8(hex).ok()) {
+                Some(hex) if hex.bytes().all(|digit| digit.is_ascii_hexdigit()) => {
+                    bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                    at += 2;
+                }
+                // A stray `%` is kept, as PHP does
+                _ => bytes.push(b'%'),
+            },
+            byte => bytes.push(byte),
+        }
+        at += 1;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// `user[address][]` split into `user` and the keys `address` and append (`None`)
+///
+/// Text after the last `]` is ignored, as PHP does. `None` for an empty
+/// name or an unclosed bracket.
+fn split_name(name: &str) -> Option<(String, Vec<Option<String>>)> {
+    let (base, mut rest) = match name.find('[') {
+        Some(at) => (&name[..at], &name[at..]),
+        None => (name, ""),
+    };
+    if base.is_empty() {
+        return None;
+    }
+    let mut path = Vec::new();
+    while let Some(inner) = rest.strip_prefix('[') {
+        let close = inner.find(']')?;
+        let key = &inner[..close];
+        path.push((!key.is_empty()).then(|| key.to_string()));
+        rest = &inner[close + 1..];
+    }
+    Some((base.to_string(), path))
+}
+
+/// Store `value` under `path` below `slot`, creating arrays on the way
+///
+/// Keys that are all appends build a JSON array; any named key turns the
+/// level into an object, with appended values under the next free index,
+/// as PHP numbers them.
+fn set(slot: &mut Value, path: &[Option<String>], value: Value) {
+    let Some((key, rest)) = path.split_first() else {
+        *slot = value;
+        return;
+    };
+    if !slot.is_array() && !slot.is_object() {
+        *slot = if key.is_none() { Value::Array(Vec::new()) } else { Value::Object(Map::new()) };
+    }
+    if let (Value::Array(items), Some(_)) = (&*slot, key) {
+        let object = items.iter().enumerate().map(|(index, item)| (index.to_string(), item.clone())).collect();
+        *slot = Value::Object(object);
+    }
+
+    match (slot, key) {
+        (Value::Array(items), None) => {
+            items.push(Value::Null);
+            let last = items.len() - 1;
+            set(&mut items[last], rest, value);
+        }
+        (Value::Object(entries), key) => {
+            let key = key.clone().unwrap_or_else(|| next_index(entries).to_string());
+            set(entries.entry(key).or_insert(Value::Null), rest, value);
+        }
+        _ => unreachable!("the slot was made an array or an object above"),
+    }
+}
+
+/// Index PHP gives a value appended to `entries`: one past the largest integer key
+fn next_index(entries: &Map<String, Value>) -> u64 {
+    entries
+        .keys()
+        .filter_map(|key| key.parse::<u64>().ok())
+        .max()
+        .map_or(0, |largest| largest + 1)
+}
@@ -0,0 +1,211 @@
+//! gRPC listener for other services (`grpc` feature)
+//!
+//! Serves the `Bridge` service from `proto/bridge.proto` on a separate
+//! address, so services that do not speak the worker's socket protocol can
+//! run Laravel commands. `Execute` goes through
+//! [`SocketBridge::execute_command`], which shares the adaptive concurrency
+//! limit and frame slots with HTTP requests; `GetStats` returns the same
+//! document as `/admin/stats`.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use tonic::transport::server::TcpIncoming;
+use tonic::{Code, Request, Response, Status};
+use tracing::{debug, error, info, info_span, Instrument};
+
+use crate::bridge::socket_bridge::SocketBridge;
+use crate::errors::ServerError;
+use crate::metrics::{metrics, MetricKind};
+use crate::supervisor::WorkerSupervisor;
+use crate::tenants::Tenants;
+
+/// Generated messages, server and client
+pub mod proto {
+    tonic::include_proto!("laravel_rust.bridge.v1");
+}
+
+use proto::bridge_server::{Bridge, BridgeServer};
+use proto::{CommandRequest, CommandResponse, StatsRequest, StatsResponse};
+
+/// gRPC listener configuration
+#[derive(Debug, Clone)]
+pub struct GrpcConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+}
+
+impl GrpcConfig {
+    pub fn from_env() -> Self {
+        Self {
+            enabled: std::env::var("GRPC_ENABLED")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            host: std::env::var("GRPC_HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
+            port: std::env::var("GRPC_PORT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(50051),
+        }
+    }
+}
+
+/// Implementation of the `Bridge` service
+pub struct BridgeService {
+    socket_bridge: Arc<SocketBridge>,
+    supervisor: Arc<WorkerSupervisor>,
+    tenants: Arc<Tenants>,
+    /// Directory the HTTP listener serves static files from
+    public_dir: String,
+}
+
+#[tonic::async_trait]
+impl Bridge for BridgeService {
+    async fn execute(&self, request: Request<CommandRequest>) -> Result<Response<CommandResponse>, Status> {
+        let CommandRequest { command, json_data } = request.into_inner();
+        let span = info_span!("grpc", method = "Execute", command = %command);
+        observe("Execute", self.execute(command, json_data).instrument(span)).await
+    }
+
+    async fn get_stats(&self, _request: Request<StatsRequest>) -> Result<Response<StatsResponse>, Status> {
+        observe("GetStats", async {
+            Ok(Response::new(StatsResponse {
+                json_data: crate::admin::stats(&self.supervisor, &self.tenants, &self.public_dir).to_string(),
+            }))
+        })
+        .await
+    }
+}
+
+impl BridgeService {
+    async fn execute(&self, command: String, json_data: String) -> Result<Response<CommandResponse>, Status> {
+        if command.is_empty() {
+            return Err(Status::invalid_argument("command is empty"));
+        }
+        let data = if json_data.is_empty() {
+            None
+        } else {
+            let data: HashMap<String, serde_json::Value> = serde_json::from_str(&json_data)
+                .map_err(|e| Status::invalid_argument(format!("json_data is not a JSON object: {}", e)))?;
+            Some(data)
+        };
+
+        let response = self.socket_bridge.execute_command(&command, data).await.map_err(status)?;
+        debug!(success = response.success, "Command completed");
+        Ok(Response::new(CommandResponse {
+            success: response.success,
+            json_data: response.data.map(|data| data.to_string()).unwrap_or_default(),
+            error: response.error.unwrap_or_default(),
+        }))
+    }
+}
+
+/// gRPC status for a failed bridge call
+fn status(error: anyhow::Error) -> Status {
+    let error = ServerError::classify(error);
+    let code = match &error {
+        ServerError::Unavailable { .. } => Code::Unavailable,
+        ServerError::BridgeTimeout(_) => Code::DeadlineExceeded,
+        ServerError::PayloadTooLarge(_) => Code::ResourceExhausted,
+        ServerError::NotFound(_) => Code::NotFound,
+        ServerError::UpstreamMalformed(_) | ServerError::Application(_) | ServerError::Internal(_) => Code::Internal,
+    };
+    Status::new(code, error.to_string())
+}
+
+/// Count a call and its duration, by method and status code
+async fn observe<T>(
+    method: &'static str,
+    call: impl Future<Output = Result<Response<T>, Status>>,
+) -> Result<Response<T>, Status> {
+    let started = Instant::now();
+    let result = call.await;
+    let code = match &result {
+        Ok(_) => Code::Ok,
+        Err(status) => status.code(),
+    };
+    metrics().inc_counter("grpc_requests_total", &[("method", method), ("code", &format!("{:?}", code))]);
+    metrics().observe("grpc_request_duration_seconds", &[("method", method)], started.elapsed().as_secs_f64());
+    result
+}
+
+/// gRPC server
+pub struct GrpcServer {
+    config: GrpcConfig,
+    service: Arc<BridgeService>,
+    /// Listener bound ahead of `start_with_shutdown` (e.g. before dropping privileges)
+    listener: std::sync::Mutex<Option<std::net::TcpListener>>,
+}
+
+impl GrpcServer {
+    pub fn new(
+        config: GrpcConfig,
+        socket_bridge: Arc<SocketBridge>,
+        supervisor: Arc<WorkerSupervisor>,
+        tenants: Arc<Tenants>,
+        public_dir: String,
+    ) -> Self {
+        metrics().describe(
+            "grpc_requests_total",
+            MetricKind::Counter,
+            "gRPC calls by method and status code",
+        );
+        metrics().describe(
+            "grpc_request_duration_seconds",
+            MetricKind::Summary,
+            "Time to answer a gRPC call, by method",
+        );
+        Self {
+            config,
+            service: Arc::new(BridgeService {
+                socket_bridge,
+                supervisor,
+                tenants,
+                public_dir,
+            }),
+            listener: std::sync::Mutex::new(None),
+        }
+    }
+
+    fn addr(&self) -> Result<SocketAddr> {
+        Ok(format!("{}:{}", self.config.host, self.config.port).parse()?)
+    }
+
+    /// Bind the gRPC socket now instead of in `start_with_shutdown`
+    pub fn bind(&self) -> Result<()> {
+        let addr = self.addr()?;
+        let listener = match crate::upgrade::take_tcp_listener("grpc", addr) {
+            Some(listener) => listener,
+            None => std::net::TcpListener::bind(addr).map_err(|e| {
+                error!("Failed to bind gRPC server to {}: {}", addr, e);
+                e
+            })?,
+        };
+        listener.set_nonblocking(true)?;
+        crate::upgrade::register("grpc", &listener);
+        *self.listener.lock().unwrap_or_else(|e| e.into_inner()) = Some(listener);
+        Ok(())
+    }
+
+    /// Serve until `shutdown` resolves, then wait for the calls in progress
+    pub async fn start_with_shutdown(&self, shutdown: impl Future<Output = ()>) -> Result<()> {
+        let prebound = self.listener.lock().unwrap_or_else(|e| e.into_inner()).take();
+        let listener = match prebound {
+            Some(listener) => tokio::net::TcpListener::from_std(listener)?,
+            None => tokio::net::TcpListener::bind(self.addr()?).await?,
+        };
+        let incoming = TcpIncoming::from_listener(listener, true, None).map_err(|e| anyhow!(e))?;
+
+        info!("📡 Starting gRPC server on {}:{}", self.config.host, self.config.port);
+        tonic::transport::Server::builder()
+            .add_service(BridgeServer::from_arc(self.service.clone()))
+            .serve_with_incoming_shutdown(incoming, shutdown)
+            .await?;
+        Ok(())
+    }
+}
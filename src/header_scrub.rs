@@ -0,0 +1,104 @@
+//! Removal of request headers clients may not set
+//!
+//! Headers such as `X-Forwarded-For` or `X-Request-Id` are believed by
+//! Laravel and by our own logs, yet anyone can send them. Before anything
+//! reads the request, two lists are applied:
+//!
+//! * `UNTRUSTED_HEADERS` (forwarding and identity headers by default) are
+//!   removed unless the connecting peer is in `TRUSTED_PROXIES`. From such
+//!   a peer, `X-Forwarded-For` is replaced by the peer address instead, so
+//!   the application still learns who connected.
+//! * `INTERNAL_HEADERS` are removed from every request, trusted proxy or
+//!   not; they are for traffic between our own components only.
+//!
+//! Both are comma-separated header names; setting one to an empty value
+//! turns it off.
+
+use std::net::IpAddr;
+
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::HeaderMap;
+use tracing::debug;
+
+use crate::trusted_proxies::FORWARDED_FOR_HEADER;
+
+/// Default for `UNTRUSTED_HEADERS`
+pub const DEFAULT_UNTRUSTED_HEADERS: &str = "x-forwarded-for,x-forwarded-host,x-forwarded-proto,x-forwarded-port,x-forwarded-prefix,forwarded,x-real-ip,x-request-id";
+
+/// Default for `INTERNAL_HEADERS`
+pub const DEFAULT_INTERNAL_HEADERS: &str = "x-internal-auth";
+
+/// Request headers removed depending on who sent them
+#[derive(Debug, Clone, Default)]
+pub struct HeaderScrub {
+    /// Removed unless the peer is a trusted proxy
+    untrusted: Vec<HeaderName>,
+    /// Always removed
+    internal: Vec<HeaderName>,
+}
+
+impl HeaderScrub {
+    pub fn from_env() -> Result<Self, Vec<(&'static str, String)>> {
+        let mut problems = Vec::new();
+        let mut list = |env: &'static str, default: &str| {
+            let value = std::env::var(env).unwrap_or_else(|_| default.to_string());
+            parse_names(&value).unwrap_or_else(|invalid| {
+                problems.extend(invalid.into_iter().map(|name| (env, format!("{:?} is not a valid header name", name))));
+                Vec::new()
+            })
+        };
+        let scrub = Self {
+            untrusted: list("UNTRUSTED_HEADERS", DEFAULT_UNTRUSTED_HEADERS),
+            internal: list("INTERNAL_HEADERS", DEFAULT_INTERNAL_HEADERS),
+        };
+        if problems.is_empty() {
+            Ok(scrub)
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Remove the headers a request from `peer` may not carry
+    ///
+    /// `trusted` tells whether `peer` is in `TRUSTED_PROXIES`.
+    pub fn apply(&self, headers: &mut HeaderMap, peer: IpAddr, trusted: bool) {
+        let mut removed = Vec::new();
+        for name in &self.internal {
+            if headers.remove(name).is_some() {
+                removed.push(name.as_str());
+            }
+        }
+        if !trusted {
+            for name in &self.untrusted {
+                if headers.remove(name).is_some() {
+                    removed.push(name.as_str());
+                }
+            }
+            if self.untrusted.iter().any(|name| name == FORWARDED_FOR_HEADER) {
+                if let Ok(value) = HeaderValue::from_str(&peer.to_canonical().to_string()) {
+                    headers.insert(FORWARDED_FOR_HEADER, value);
+                }
+            }
+        }
+        if !removed.is_empty() {
+            debug!(peer = %peer, trusted, headers = ?removed, "Removed request headers the peer may not set");
+        }
+    }
+}
+
+/// Header names in a comma-separated list, or the entries that are not valid
+fn parse_names(list: &str) -> Result<Vec<HeaderName>, Vec<String>> {
+    let mut names = Vec::new();
+    let mut invalid = Vec::new();
+    for entry in list.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        match HeaderName::from_bytes(entry.to_ascii_lowercase().as_bytes()) {
+            Ok(name) => names.push(name),
+            Err(_) => invalid.push(entry.to_string()),
+        }
+    }
+    if invalid.is_empty() {
+        Ok(names)
+    } else {
+        Err(invalid)
+    }
+}
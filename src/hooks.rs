@@ -0,0 +1,121 @@
+//! Request lifecycle hooks
+//!
+//! An embedder can observe traffic, and answer a request before it reaches
+//! Laravel, by installing [`RequestHooks`] with
+//! [`HttpServer::with_request_hooks`](crate::server::HttpServer::with_request_hooks).
+//!
+//! Hooks run on the blocking thread pool under a watchdog: a hook that does
+//! not return within `REQUEST_HOOK_TIMEOUT_MS` is logged and the request
+//! carries on as if it had not been called. The stalled call still holds a
+//! blocking thread until it returns, so hooks must be fast and must not wait
+//! on I/O.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::StatusCode;
+use tracing::{error, warn, Instrument};
+
+use crate::metrics::{metrics, MetricKind};
+use crate::server::HttpRequestPayload;
+
+/// Default watchdog timeout for a single hook call
+const DEFAULT_HOOK_TIMEOUT_MS: u64 = 100;
+
+/// Callbacks around every request
+pub trait RequestHooks: Send + Sync {
+    /// Called before a request is forwarded to Laravel
+    ///
+    /// # Returns
+    ///
+    /// * `None` - forward the request
+    /// * `Some(status)` - answer with `status` and an empty body instead
+    fn on_request(&self, request: &HttpRequestPayload) -> Option<StatusCode> {
+        let _ = request;
+        None
+    }
+
+    /// Called for every response, with the time since the request arrived
+    ///
+    /// Runs after the response is sent on its way, so it never delays it.
+    fn on_response(&self, status: StatusCode, duration: Duration) {
+        let _ = (status, duration);
+    }
+}
+
+/// Shared handle to the hooks installed on a server
+pub type SharedRequestHooks = Arc<dyn RequestHooks>;
+
+/// Installed hooks together with their watchdog
+#[derive(Clone)]
+pub(crate) struct HookRunner {
+    hooks: SharedRequestHooks,
+    timeout: Duration,
+}
+
+impl HookRunner {
+    pub(crate) fn new(hooks: SharedRequestHooks) -> Self {
+        let timeout_ms = std::env::var("REQUEST_HOOK_TIMEOUT_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_HOOK_TIMEOUT_MS);
+
+        Self {
+            hooks,
+            timeout: Duration::from_millis(timeout_ms),
+        }
+    }
+
+    /// Ask the request hook whether to answer the request locally
+    pub(crate) async fn before_request(&self, request: &HttpRequestPayload) -> Option<StatusCode> {
+        let hooks = self.hooks.clone();
+        let request = request.clone();
+        let call = tokio::task::spawn_blocking(move || hooks.on_request(&request));
+
+        match tokio::time::timeout(self.timeout, call).await {
+            Ok(Ok(verdict)) => verdict,
+            Ok(Err(e)) => {
+                error!("Request hook panicked, forwarding the request: {}", e);
+                None
+            }
+            Err(_) => {
+                warn!(
+                    timeout_ms = self.timeout.as_millis() as u64,
+                    "Request hook did not return in time, forwarding the request"
+                );
+                count_timeout("request");
+                None
+            }
+        }
+    }
+
+    /// Report a response to the response hook in the background
+    pub(crate) fn after_response(&self, status: StatusCode, duration: Duration) {
+        let hooks = self.hooks.clone();
+        let timeout = self.timeout;
+
+        tokio::spawn(
+            async move {
+                let call = tokio::task::spawn_blocking(move || hooks.on_response(status, duration));
+                match tokio::time::timeout(timeout, call).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => error!("Response hook panicked: {}", e),
+                    Err(_) => {
+                        warn!(timeout_ms = timeout.as_millis() as u64, "Response hook did not return in time");
+                        count_timeout("response");
+                    }
+                }
+            }
+            .in_current_span(),
+        );
+    }
+}
+
+fn count_timeout(hook: &str) {
+    metrics().describe(
+        "request_hook_timeouts_total",
+        MetricKind::Counter,
+        "Hook calls that did not return within REQUEST_HOOK_TIMEOUT_MS, by hook",
+    );
+    metrics().inc_counter("request_hook_timeouts_total", &[("hook", hook)]);
+}
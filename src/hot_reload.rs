@@ -0,0 +1,277 @@
+//! Reloading selected settings on SIGHUP
+//!
+//! Most components read their settings once at startup; a few read them from
+//! the environment every time they are used. On SIGHUP the configuration
+//! layers are resolved again, and only changes to the latter are applied.
+//! Everything else is reported as requiring a restart.
+//!
+//! The log filter can also be replaced at runtime through the admin listener
+//! ([`override_log_filter`]), optionally only for a limited time.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use once_cell::sync::OnceCell;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::config_loader::ConfigLayers;
+
+/// Handle for swapping the global log filter
+static LOG_FILTER: OnceCell<reload::Handle<EnvFilter, Registry>> = OnceCell::new();
+
+/// Runtime log filter override, see [`override_log_filter`]
+static LOG_FILTER_OVERRIDE: Mutex<FilterOverride> = Mutex::new(FilterOverride {
+    generation: 0,
+    revert_to: None,
+});
+
+#[derive(Debug)]
+struct FilterOverride {
+    /// Bumped on every filter change, so a pending revert never undoes a later one
+    generation: u64,
+    /// Filter to restore when the pending revert fires
+    revert_to: Option<String>,
+}
+
+/// Settings that take effect immediately once the variable changes
+const HOT: &[&str] = &[
+    "LOG_LEVEL",
+    "UNAVAILABLE_RETRY_AFTER_SECS",
+    "SOCKET_WAIT_MAX_ATTEMPTS",
+    "SOCKET_WAIT_INTERVAL_MS",
+    "SHUTDOWN_NOTIFY_TIMEOUT_MS",
+    "SHUTDOWN_DRAIN_TIMEOUT_MS",
+    "SHUTDOWN_FAST_DRAIN_TIMEOUT_MS",
+    "UPGRADE_WORKER",
+    "UPGRADE_TIMEOUT_MS",
+    "UPGRADE_BINARY",
+];
+
+/// Settings read each time the PHP worker is spawned
+const NEXT_WORKER_START: &[&str] = &[
+    "PHP_PATH",
+    "LARAVEL_PATH",
+    "STARTUP_COMMAND",
+    "PHP_WORKER_NICE",
+    "PHP_WORKER_RLIMIT_AS",
+    "PHP_WORKER_RLIMIT_NOFILE",
+    "PHP_WORKER_CGROUP",
+    "PHP_WORKER_CPU_MAX",
+    "PHP_WORKER_MEMORY_MAX",
+    "PHP_WORKER_LIMITS_ON_FAILURE",
+];
+
+/// Filter directive used for a given `LOG_LEVEL`
+///
+/// Tracing targets use the crate name with underscores, so a
+/// `laravel-rust-server=...` directive would match nothing.
+pub fn log_filter_directive(level: &str) -> String {
+    format!("laravel_rust_server={},hyper=info", level)
+}
+
+/// Build the log filter: `RUST_LOG` wins over `LOG_LEVEL`
+pub fn build_log_filter(level: &str) -> Result<EnvFilter> {
+    if let Ok(filter) = EnvFilter::try_from_default_env() {
+        return Ok(filter);
+    }
+    Ok(EnvFilter::try_new(log_filter_directive(level))?)
+}
+
+/// Register the reload handle created by `init_logging`
+pub fn set_log_filter_handle(handle: reload::Handle<EnvFilter, Registry>) {
+    let _ = LOG_FILTER.set(handle);
+}
+
+/// Directives of the current log filter, once logging is initialized
+pub fn current_log_filter() -> Option<String> {
+    LOG_FILTER.get()?.with_current(|filter| filter.to_string()).ok()
+}
+
+/// Build a filter from an operator-supplied value
+///
+/// A bare level (`debug`, `warn`, ...) is applied the way `LOG_LEVEL` is;
+/// anything else is taken as a full directive string such as
+/// `laravel_rust_server=debug,hyper=trace`.
+pub fn parse_log_filter(value: &str) -> Result<EnvFilter> {
+    let value = value.trim();
+    if value.is_empty() {
+        bail!("log filter must not be empty");
+    }
+    let directive = if value.parse::<tracing::level_filters::LevelFilter>().is_ok() {
+        log_filter_directive(value)
+    } else {
+        value.to_string()
+    };
+    EnvFilter::try_new(&directive).map_err(|e| anyhow!("invalid log filter {:?}: {}", directive, e))
+}
+
+/// Result of [`override_log_filter`]
+#[derive(Debug, Clone)]
+pub struct LogFilterChange {
+    pub previous: String,
+    pub filter: String,
+    /// Filter restored when `revert_after` elapses
+    pub revert_to: Option<String>,
+}
+
+/// Replace the log filter now, and restore the previous one after `revert_after`
+///
+/// An invalid `value` leaves the current filter untouched. Overrides stack:
+/// a second temporary override reverts to the filter in place before the
+/// first, so a forgotten `debug` always expires. A SIGHUP that changes
+/// `LOG_LEVEL` cancels any pending revert.
+pub fn override_log_filter(value: &str, revert_after: Option<Duration>) -> Result<LogFilterChange> {
+    let handle = LOG_FILTER.get().context("logging is not initialized")?;
+    let filter = parse_log_filter(value)?;
+    let new = filter.to_string();
+
+    let mut state = LOG_FILTER_OVERRIDE.lock().unwrap_or_else(|e| e.into_inner());
+    let previous = handle.with_current(|filter| filter.to_string())?;
+    handle.reload(filter)?;
+    state.generation += 1;
+
+    let revert_to = revert_after.map(|_| state.revert_to.clone().unwrap_or_else(|| previous.clone()));
+    state.revert_to = revert_to.clone();
+    if let (Some(after), Some(revert_to)) = (revert_after, revert_to.clone()) {
+        let generation = state.generation;
+        tokio::spawn(async move {
+            tokio::time::sleep(after).await;
+            revert_log_filter(generation, &revert_to);
+        });
+    }
+    drop(state);
+
+    info!(previous = %previous, filter = %new, revert_after = ?revert_after, "Log filter changed at runtime");
+    Ok(LogFilterChange {
+        previous,
+        filter: new,
+        revert_to,
+    })
+}
+
+/// Restore `filter` unless the filter changed again since override `generation`
+fn revert_log_filter(generation: u64, filter: &str) {
+    let mut state = LOG_FILTER_OVERRIDE.lock().unwrap_or_else(|e| e.into_inner());
+    if state.generation != generation {
+        return;
+    }
+    let reverted = EnvFilter::try_new(filter)
+        .map_err(anyhow::Error::from)
+        .and_then(|parsed| Ok(LOG_FILTER.get().context("logging is not initialized")?.reload(parsed)?));
+    state.generation += 1;
+    state.revert_to = None;
+    drop(state);
+
+    match reverted {
+        Ok(()) => info!(filter, "Log filter override expired, reverted"),
+        Err(e) => error!(filter, "Failed to revert the log filter override: {:#}", e),
+    }
+}
+
+/// Outcome of one reload
+#[derive(Debug, Default)]
+pub struct ReloadReport {
+    pub applied: Vec<&'static str>,
+    pub next_worker_start: Vec<&'static str>,
+    pub restart_required: Vec<&'static str>,
+}
+
+/// Re-resolve the configuration and apply the hot-reloadable changes
+///
+/// Either every reloadable change is applied or none is: the new values are
+/// validated before the environment or the log filter is touched.
+pub fn reload(layers: &ConfigLayers) -> Result<ReloadReport> {
+    let fresh = layers.resolve_fresh()?;
+
+    let changed: BTreeMap<&'static str, Option<String>> = fresh
+        .into_iter()
+        .filter(|(env, value)| std::env::var(env).ok() != *value)
+        .collect();
+
+    let mut report = ReloadReport::default();
+    let mut to_apply = Vec::new();
+    for (env, value) in &changed {
+        if HOT.contains(env) {
+            validate(env, value.as_deref())?;
+            report.applied.push(env);
+            to_apply.push((*env, value.clone()));
+        } else if NEXT_WORKER_START.contains(env) {
+            report.next_worker_start.push(env);
+            to_apply.push((*env, value.clone()));
+        } else {
+            report.restart_required.push(env);
+        }
+    }
+
+    let new_filter = match changed.get("LOG_LEVEL") {
+        Some(level) => Some(build_log_filter(level.as_deref().unwrap_or("info"))?),
+        None => None,
+    };
+
+    for (env, value) in to_apply {
+        match value {
+            Some(value) => std::env::set_var(env, value),
+            None => std::env::remove_var(env),
+        }
+    }
+
+    if let (Some(filter), Some(handle)) = (new_filter, LOG_FILTER.get()) {
+        let mut state = LOG_FILTER_OVERRIDE.lock().unwrap_or_else(|e| e.into_inner());
+        handle.reload(filter)?;
+        // The reloaded LOG_LEVEL replaces any runtime override for good
+        state.generation += 1;
+        state.revert_to = None;
+    }
+
+    Ok(report)
+}
+
+fn validate(env: &str, value: Option<&str>) -> Result<()> {
+    let Some(value) = value else {
+        return Ok(());
+    };
+    if env == "LOG_LEVEL" {
+        EnvFilter::try_new(log_filter_directive(value))?;
+    } else if env == "UPGRADE_WORKER" {
+        if value != "handover" && value != "restart" {
+            bail!("{} must be handover or restart, got {:?}", env, value);
+        }
+    } else if env == "UPGRADE_BINARY" {
+        // Any path; checked when an upgrade starts it
+    } else if value.parse::<u64>().is_err() {
+        bail!("{} must be a non-negative integer, got {:?}", env, value);
+    }
+    Ok(())
+}
+
+/// Reload the configuration whenever the process receives SIGHUP
+pub fn spawn_sighup_handler(layers: ConfigLayers) -> Result<JoinHandle<()>> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    Ok(tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("SIGHUP received, reloading configuration");
+            match reload(&layers) {
+                Ok(report) => {
+                    if report.applied.is_empty() && report.next_worker_start.is_empty() && report.restart_required.is_empty() {
+                        info!("Configuration unchanged");
+                    }
+                    if !report.applied.is_empty() {
+                        info!(keys = ?report.applied, "Applied configuration changes");
+                    }
+                    if !report.next_worker_start.is_empty() {
+                        info!(keys = ?report.next_worker_start, "Configuration changes will apply when the PHP worker restarts");
+                    }
+                    if !report.restart_required.is_empty() {
+                        warn!(keys = ?report.restart_required, "Configuration changes require a restart and were not applied");
+                    }
+                }
+                Err(e) => error!("Configuration reload failed, keeping the current configuration: {:#}", e),
+            }
+        }
+    }))
+}
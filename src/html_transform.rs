@@ -0,0 +1,187 @@
+//! Rewriting of HTML pages served by the PHP worker
+//!
+//! An environment badge or an A/B test snippet can be added to every page
+//! without touching the Blade layouts. After the worker's response is
+//! parsed, each [`ResponseTransformer`] gets the chance to rewrite the body
+//! of an HTML page:
+//!
+//! * `HTML_INJECT_SNIPPET` (or the contents of `HTML_INJECT_SNIPPET_FILE`)
+//!   is inserted before the last `</body>` by the built-in
+//!   [`SnippetInjector`];
+//! * embedders add their own with
+//!   [`HttpServer::with_response_transformer`](crate::server::HttpServer::with_response_transformer).
+//!
+//! Only `text/html` responses with a `2xx` status are rewritten, and with
+//! `HTML_TRANSFORM_ERRORS=true` also those with a `4xx` or `5xx`. Bodies
+//! that are compressed (`Content-Encoding`), streamed or larger than
+//! `HTML_TRANSFORM_MAX_BODY_BYTES` pass unchanged. A rewritten response
+//! gets a fresh `Content-Length`, and an ETag set by Laravel is made weak,
+//! since the bytes it was computed from changed. Transformers run inline on
+//! the request's task, so they must be fast; `cargo bench --bench
+//! html_inject` measures the built-in one.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use hyper::body::Bytes;
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::{Body, Response, StatusCode};
+
+use crate::metrics::{metrics, MetricKind};
+use crate::request_context::RequestContext;
+
+/// Default for `HTML_TRANSFORM_MAX_BODY_BYTES`
+pub const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Closing tag the built-in snippet is inserted before
+const CLOSING_BODY: &[u8] = b"</body";
+
+/// Rewrites the body of HTML pages
+pub trait ResponseTransformer: Send + Sync {
+    /// New body for the page answering `request`, or `None` to leave it as it is
+    ///
+    /// Only called for uncompressed `text/html` bodies within the size cap.
+    fn transform(&self, request: &RequestContext, status: StatusCode, body: &[u8]) -> Option<Vec<u8>>;
+}
+
+/// Shared handle to a transformer installed on a server
+pub type SharedResponseTransformer = Arc<dyn ResponseTransformer>;
+
+/// Inserts a fixed snippet before the last `</body>` of a page
+///
+/// Pages without `</body>` are left alone, matching the tag in any case.
+#[derive(Debug, Clone)]
+pub struct SnippetInjector {
+    snippet: Bytes,
+}
+
+impl SnippetInjector {
+    pub fn new(snippet: impl Into<Bytes>) -> Self {
+        Self { snippet: snippet.into() }
+    }
+}
+
+impl ResponseTransformer for SnippetInjector {
+    fn transform(&self, _request: &RequestContext, _status: StatusCode, body: &[u8]) -> Option<Vec<u8>> {
+        let at = closing_body(body)?;
+        let mut injected = Vec::with_capacity(body.len() + self.snippet.len());
+        injected.extend_from_slice(&body[..at]);
+        injected.extend_from_slice(&self.snippet);
+        injected.extend_from_slice(&body[at..]);
+        Some(injected)
+    }
+}
+
+/// Offset of the last `</body` in `html`, in any case
+pub fn closing_body(html: &[u8]) -> Option<usize> {
+    // Scanning from the end finds the tag of a regular page within its last bytes
+    html.windows(CLOSING_BODY.len()).rposition(|window| window.eq_ignore_ascii_case(CLOSING_BODY))
+}
+
+/// Transformers of a server with the limits they run under
+#[derive(Clone, Default)]
+pub struct HtmlTransform {
+    transformers: Vec<SharedResponseTransformer>,
+    /// Larger bodies are passed unchanged
+    max_body_bytes: usize,
+    /// Also rewrite pages with a 4xx or 5xx status
+    errors: bool,
+}
+
+impl HtmlTransform {
+    /// Settings and the snippet injector from the environment
+    pub fn from_env() -> Result<Self, Vec<(&'static str, String)>> {
+        let snippet = match (std::env::var("HTML_INJECT_SNIPPET"), std::env::var("HTML_INJECT_SNIPPET_FILE")) {
+            (Ok(_), Ok(_)) => {
+                return Err(vec![(
+                    "HTML_INJECT_SNIPPET_FILE",
+                    "cannot be combined with HTML_INJECT_SNIPPET".to_string(),
+                )])
+            }
+            (Ok(snippet), Err(_)) => Some(Bytes::from(snippet)),
+            (Err(_), Ok(path)) => match std::fs::read(&path) {
+                Ok(snippet) => Some(Bytes::from(snippet)),
+                Err(e) => return Err(vec![("HTML_INJECT_SNIPPET_FILE", format!("cannot read {}: {}", path, e))]),
+            },
+            (Err(_), Err(_)) => None,
+        };
+
+        Ok(Self {
+            transformers: snippet
+                .filter(|snippet| !snippet.is_empty())
+                .map(|snippet| Arc::new(SnippetInjector::new(snippet)) as SharedResponseTransformer)
+                .into_iter()
+                .collect(),
+            max_body_bytes: std::env::var("HTML_TRANSFORM_MAX_BODY_BYTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_BODY_BYTES),
+            errors: matches!(std::env::var("HTML_TRANSFORM_ERRORS").as_deref(), Ok("true") | Ok("1")),
+        })
+    }
+
+    /// Run `transformer` after the ones already installed
+    pub fn push(&mut self, transformer: SharedResponseTransformer) {
+        self.transformers.push(transformer);
+    }
+
+    /// `response` with its HTML body rewritten, if it qualifies
+    pub async fn apply(&self, request: &RequestContext, response: Response<Body>) -> Result<Response<Body>> {
+        if self.transformers.is_empty() || !self.applies_to(response.status(), response.headers()) {
+            return Ok(response);
+        }
+        let size = hyper::body::HttpBody::size_hint(response.body()).exact();
+        if size.is_none_or(|size| size == 0 || size > self.max_body_bytes as u64) {
+            return Ok(response);
+        }
+
+        // Worker responses are complete in memory, so this copies nothing
+        let (mut parts, body) = response.into_parts();
+        let mut body = hyper::body::to_bytes(body).await?;
+        let mut changed = false;
+        for transformer in &self.transformers {
+            if let Some(transformed) = transformer.transform(request, parts.status, &body) {
+                body = Bytes::from(transformed);
+                changed = true;
+            }
+        }
+
+        metrics().describe(
+            "http_html_transforms_total",
+            MetricKind::Counter,
+            "HTML pages of the PHP worker passed to the response transformers, by outcome",
+        );
+        let outcome = if changed { "rewritten" } else { "unchanged" };
+        metrics().inc_counter("http_html_transforms_total", &[("outcome", outcome)]);
+        if changed {
+            parts.headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+            if let Some(weak) = parts.headers.get(header::ETAG).and_then(weakened) {
+                parts.headers.insert(header::ETAG, weak);
+            }
+        }
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+
+    /// Whether a response with `status` and `headers` is an HTML page to rewrite
+    fn applies_to(&self, status: StatusCode, headers: &HeaderMap) -> bool {
+        let status_ok = status.is_success() || (self.errors && status.as_u16() >= 400);
+        let is_html = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .is_some_and(|media| media.trim().eq_ignore_ascii_case("text/html"));
+        let encoded = headers
+            .get(header::CONTENT_ENCODING)
+            .is_some_and(|value| !value.as_bytes().eq_ignore_ascii_case(b"identity"));
+        status_ok && status != StatusCode::NO_CONTENT && is_html && !encoded
+    }
+}
+
+/// Weak form of a strong ETag; `None` when it is weak already
+fn weakened(etag: &HeaderValue) -> Option<HeaderValue> {
+    let etag = etag.to_str().ok()?.trim();
+    if etag.starts_with("W/") {
+        return None;
+    }
+    HeaderValue::from_str(&format!("W/{}", etag)).ok()
+}
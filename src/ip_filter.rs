@@ -0,0 +1,375 @@
+//! Client address allow and deny lists
+//!
+//! Requests are checked against these lists before anything else but the
+//! health probes, so a denied client never reaches static files or PHP.
+//! The client address is the one resolved through `TRUSTED_PROXIES`.
+//!
+//! `IP_ALLOW` and `IP_DENY` apply to every path. `IP_RULES` adds lists for
+//! path prefixes; a request is checked against the rule with the longest
+//! prefix matching its path first, and against the global lists only when
+//! that rule says nothing about the address. Within one pair of lists:
+//!
+//! * the narrowest network containing the address decides, so
+//!   `IP_ALLOW=10.0.0.0/8` with `IP_DENY=10.0.5.0/24` denies `10.0.5.7`
+//!   and allows `10.0.6.7`;
+//! * when an allow and a deny network of the same size both contain it,
+//!   `IP_PRECEDENCE` (`deny` by default) decides;
+//! * an address in no network is denied when there is an allow list, and
+//!   left to the next pair of lists when there is only a deny list.
+//!
+//! Denied requests get 403 with `IP_DENIED_BODY` and are counted in
+//! `http_ip_denied_total{scope}`, where `scope` is the rule's prefix or
+//! `global`. Without any list every request passes untouched.
+
+use std::net::IpAddr;
+
+use hyper::{header, Body, Response, StatusCode};
+use serde::Deserialize;
+
+use crate::cidr::{self, Cidr};
+use crate::metrics::{metrics, MetricKind};
+
+/// Default for `IP_DENIED_BODY`
+pub const DEFAULT_DENIED_BODY: &str = "Forbidden";
+
+/// Scope label of the global lists
+const GLOBAL_SCOPE: &str = "global";
+
+/// Which list wins when networks of the same size in both contain the address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Precedence {
+    #[default]
+    Deny,
+    Allow,
+}
+
+/// One pair of allow and deny lists
+#[derive(Debug, Clone, Default)]
+struct Lists {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+impl Lists {
+    fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// `Some(true)` to allow, `Some(false)` to deny, `None` when the lists say nothing
+    fn verdict(&self, ip: IpAddr, precedence: Precedence) -> Option<bool> {
+        let narrowest = |networks: &[Cidr]| {
+            networks
+                .iter()
+                .filter(|network| network.contains(ip))
+                .map(Cidr::prefix_len)
+                .max()
+        };
+        match (narrowest(&self.allow), narrowest(&self.deny)) {
+            (Some(allow), Some(deny)) if allow == deny => Some(precedence == Precedence::Allow),
+            (Some(allow), Some(deny)) => Some(allow > deny),
+            (Some(_), None) => Some(true),
+            (None, Some(_)) => Some(false),
+            (None, None) => (!self.allow.is_empty()).then_some(false),
+        }
+    }
+}
+
+/// Lists for the paths under `prefix`
+#[derive(Debug, Clone)]
+struct PathRule {
+    prefix: String,
+    lists: Lists,
+}
+
+impl PathRule {
+    /// Whether `path` is `prefix` or below it
+    fn matches(&self, path: &str) -> bool {
+        match path.strip_prefix(self.prefix.as_str()) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/') || self.prefix.ends_with('/'),
+            None => false,
+        }
+    }
+}
+
+/// `IP_RULES` entry as written in the config
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    prefix: String,
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+}
+
+/// Allow and deny lists of the HTTP listener
+#[derive(Debug, Clone, Default)]
+pub struct IpFilter {
+    global: Lists,
+    rules: Vec<PathRule>,
+    precedence: Precedence,
+    denied_body: String,
+}
+
+impl IpFilter {
+    pub fn from_env() -> Result<Self, Vec<(&'static str, String)>> {
+        let mut problems = Vec::new();
+        let mut list = |env: &'static str| {
+            let value = std::env::var(env).unwrap_or_default();
+            cidr::parse_list(&value).unwrap_or_else(|errors| {
+                problems.extend(errors.into_iter().map(|e| (env, e)));
+                Vec::new()
+            })
+        };
+        let global = Lists {
+            allow: list("IP_ALLOW"),
+            deny: list("IP_DENY"),
+        };
+        let rules = match std::env::var("IP_RULES").ok().filter(|v| !v.trim().is_empty()) {
+            Some(json) => Self::parse_rules(&json).unwrap_or_else(|errors| {
+                problems.extend(errors.into_iter().map(|e| ("IP_RULES", e)));
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        let precedence = match std::env::var("IP_PRECEDENCE").unwrap_or_default().to_ascii_lowercase().as_str() {
+            "" | "deny" => Precedence::Deny,
+            "allow" => Precedence::Allow,
+            other => {
+                problems.push(("IP_PRECEDENCE", format!("must be one of deny, allow, got {:?}", other)));
+                Precedence::Deny
+            }
+        };
+
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        Ok(Self {
+            global,
+            rules,
+            precedence,
+            denied_body: std::env::var("IP_DENIED_BODY").unwrap_or_else(|_| DEFAULT_DENIED_BODY.to_string()),
+        })
+    }
+
+    /// Parse a JSON array of `{prefix, allow, deny}` rules
+    fn parse_rules(json: &str) -> Result<Vec<PathRule>, Vec<String>> {
+        let specs: Vec<RuleSpec> = serde_json::from_str(json)
+            .map_err(|e| vec![format!("must be a list of {{ prefix, allow, deny }} rules: {}", e)])?;
+
+        let mut rules: Vec<PathRule> = Vec::new();
+        let mut problems = Vec::new();
+        for spec in specs {
+            if !spec.prefix.starts_with('/') {
+                problems.push(format!("prefix {:?} must start with /", spec.prefix));
+                continue;
+            }
+            if rules.iter().any(|rule| rule.prefix == spec.prefix) {
+                problems.push(format!("prefix {:?} appears more than once", spec.prefix));
+                continue;
+            }
+            let mut networks = |entries: &[String]| {
+                cidr::parse_list(&entries.join(",")).unwrap_or_else(|errors| {
+                    problems.extend(errors.into_iter().map(|e| format!("{}: {}", spec.prefix, e)));
+                    Vec::new()
+                })
+            };
+            let lists = Lists {
+                allow: networks(&spec.allow),
+                deny: networks(&spec.deny),
+            };
+            if spec.allow.is_empty() && spec.deny.is_empty() {
+                problems.push(format!("{}: needs an allow or deny list", spec.prefix));
+                continue;
+            }
+            rules.push(PathRule {
+                prefix: spec.prefix,
+                lists,
+            });
+        }
+
+        if problems.is_empty() {
+            Ok(rules)
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Whether any list is configured
+    pub fn is_active(&self) -> bool {
+        !self.global.is_empty() || !self.rules.is_empty()
+    }
+
+    /// Number of `IP_RULES` entries
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Scope that denies `ip` on `path`, or `None` when the request may go on
+    pub fn denied_by(&self, path: &str, ip: IpAddr) -> Option<&str> {
+        let rule = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(path))
+            .max_by_key(|rule| rule.prefix.len());
+        let scopes = rule
+            .map(|rule| (rule.prefix.as_str(), &rule.lists))
+            .into_iter()
+            .chain([(GLOBAL_SCOPE, &self.global)]);
+        for (scope, lists) in scopes {
+            match lists.verdict(ip, self.precedence) {
+                Some(true) => return None,
+                Some(false) => return Some(scope),
+                None => {}
+            }
+        }
+        None
+    }
+
+    /// 403 for a request denied by `scope`; counts it
+    pub fn denied_response(&self, scope: &str) -> Response<Body> {
+        metrics().describe(
+            "http_ip_denied_total",
+            MetricKind::Counter,
+            "Requests rejected by IP_ALLOW, IP_DENY or IP_RULES, by the prefix of the deciding rule or global",
+        );
+        metrics().inc_counter("http_ip_denied_total", &[("scope", scope)]);
+        Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header(header::CONTENT_TYPE, content_type(&self.denied_body))
+            .header(header::CACHE_CONTROL, "no-store")
+            .body(Body::from(self.denied_body.clone()))
+            .unwrap_or_else(|_| Response::new(Body::empty()))
+    }
+}
+
+/// `Content-Type` guessed from the start of `IP_DENIED_BODY`
+fn content_type(body: &str) -> &'static str {
+    match body.trim_start().chars().next() {
+        Some('{') | Some('[') => "application/json",
+        Some('<') => "text/html; charset=utf-8",
+        _ => "text/plain; charset=utf-8",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(allow: &str, deny: &str, rules: &str) -> IpFilter {
+        IpFilter {
+            global: Lists {
+                allow: cidr::parse_list(allow).unwrap(),
+                deny: cidr::parse_list(deny).unwrap(),
+            },
+            rules: match rules {
+                "" => Vec::new(),
+                json => IpFilter::parse_rules(json).unwrap(),
+            },
+            precedence: Precedence::Deny,
+            denied_body: DEFAULT_DENIED_BODY.to_string(),
+        }
+    }
+
+    fn denied<'a>(filter: &'a IpFilter, path: &str, ip: &str) -> Option<&'a str> {
+        filter.denied_by(path, ip.parse().unwrap())
+    }
+
+    #[test]
+    fn without_lists_every_request_passes() {
+        for filter in [IpFilter::default(), filter("", "", "")] {
+            assert!(!filter.is_active());
+            assert_eq!(denied(&filter, "/", "203.0.113.9"), None);
+            assert_eq!(denied(&filter, "/admin", "2001:db8::9"), None);
+        }
+    }
+
+    #[test]
+    fn the_narrowest_network_decides() {
+        let filter = filter("10.0.0.0/8", "10.0.5.0/24", "");
+        assert!(filter.is_active());
+        assert_eq!(denied(&filter, "/", "10.0.5.7"), Some("global"));
+        assert_eq!(denied(&filter, "/", "10.0.6.7"), None);
+        // An allow list denies everything outside it
+        assert_eq!(denied(&filter, "/", "192.0.2.1"), Some("global"));
+
+        let filter = self::filter("10.0.5.7", "10.0.0.0/8", "");
+        assert_eq!(denied(&filter, "/", "10.0.5.7"), None);
+        assert_eq!(denied(&filter, "/", "10.0.5.8"), Some("global"));
+    }
+
+    #[test]
+    fn a_deny_list_alone_lets_other_addresses_through() {
+        let filter = filter("", "198.51.100.0/24, 2001:db8:bad::/48", "");
+        assert_eq!(denied(&filter, "/", "198.51.100.20"), Some("global"));
+        assert_eq!(denied(&filter, "/", "2001:db8:bad::1"), Some("global"));
+        assert_eq!(denied(&filter, "/", "198.51.101.20"), None);
+        assert_eq!(denied(&filter, "/", "2001:db8:900d::1"), None);
+    }
+
+    #[test]
+    fn precedence_settles_networks_of_the_same_size() {
+        let mut filter = filter("10.0.0.0/16", "10.0.0.0/16", "");
+        assert_eq!(denied(&filter, "/", "10.0.1.1"), Some("global"));
+        filter.precedence = Precedence::Allow;
+        assert_eq!(denied(&filter, "/", "10.0.1.1"), None);
+    }
+
+    #[test]
+    fn ipv6_and_v4_mapped_clients() {
+        let filter = filter("fd00::/8, 10.0.0.0/8", "fd00:bad::/32", "");
+        assert_eq!(denied(&filter, "/", "fd00:1::1"), None);
+        assert_eq!(denied(&filter, "/", "fd00:bad::1"), Some("global"));
+        assert_eq!(denied(&filter, "/", "2001:db8::1"), Some("global"));
+        assert_eq!(denied(&filter, "/", "::ffff:10.1.2.3"), None);
+        assert_eq!(denied(&filter, "/", "::ffff:192.0.2.1"), Some("global"));
+    }
+
+    #[test]
+    fn the_rule_with_the_longest_prefix_goes_first() {
+        let rules = r#"[
+            {"prefix": "/api", "allow": ["0.0.0.0/0"]},
+            {"prefix": "/api/internal", "allow": ["10.0.0.0/8"]},
+            {"prefix": "/admin", "allow": ["10.0.0.5"]},
+            {"prefix": "/uploads/", "deny": ["192.0.2.0/24"]}
+        ]"#;
+        let filter = filter("", "10.0.0.0/8", rules);
+        assert_eq!(filter.rule_count(), 4);
+
+        assert_eq!(denied(&filter, "/api/users", "192.0.2.1"), None);
+        assert_eq!(denied(&filter, "/api/internal/jobs", "192.0.2.1"), Some("/api/internal"));
+        assert_eq!(denied(&filter, "/api/internal", "10.1.1.1"), None, "the rule allows before the global deny");
+        // Prefixes match whole path segments
+        assert_eq!(denied(&filter, "/apix", "10.1.1.1"), Some("global"));
+        assert_eq!(denied(&filter, "/admin", "10.0.0.5"), None);
+        assert_eq!(denied(&filter, "/admin/users", "10.0.0.6"), Some("/admin"));
+        // A rule that says nothing about the address leaves it to the global lists
+        assert_eq!(denied(&filter, "/uploads/a.png", "192.0.2.1"), Some("/uploads/"));
+        assert_eq!(denied(&filter, "/uploads/a.png", "10.1.1.1"), Some("global"));
+        assert_eq!(denied(&filter, "/uploads/a.png", "198.51.100.1"), None);
+    }
+
+    #[test]
+    fn every_bad_rule_is_reported() {
+        let problems = IpFilter::parse_rules(
+            r#"[
+                {"prefix": "admin", "allow": ["10.0.0.0/8"]},
+                {"prefix": "/a", "deny": ["10.0.0.0/8"]},
+                {"prefix": "/a", "deny": ["10.0.0.0/8"]},
+                {"prefix": "/b"},
+                {"prefix": "/c", "allow": ["10.0.0.0/40"]}
+            ]"#,
+        )
+        .unwrap_err();
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(IpFilter::parse_rules(r#"[{"prefix": "/a", "allow": [], "extra": 1}]"#).is_err());
+        assert!(IpFilter::parse_rules("{}").is_err());
+    }
+
+    #[test]
+    fn denied_bodies_get_a_matching_content_type() {
+        assert_eq!(content_type(r#"{"error": "forbidden"}"#), "application/json");
+        assert_eq!(content_type("  <h1>Forbidden</h1>"), "text/html; charset=utf-8");
+        assert_eq!(content_type(DEFAULT_DENIED_BODY), "text/plain; charset=utf-8");
+    }
+}
@@ -0,0 +1,1095 @@
+//! C ABI for using the bridge from a PHP process
+//!
+//! PHP loads the cdylib through `FFI::cdef`, creates a handle with
+//! `laravel_rust_init`, and sends commands to the Laravel worker over the same
+//! pooled socket bridge the HTTP server uses. The handle can also run the
+//! HTTP server in the background (`laravel_rust_start`/`laravel_rust_stop`);
+//! the PHP worker itself is not spawned, the host is expected to run it.
+//! Its settings can be given on the handle with `laravel_rust_set_option`,
+//! for hosts such as php-fpm that scrub the environment, and C callbacks can
+//! observe or answer its requests (`laravel_rust_set_request_hook`,
+//! `laravel_rust_set_response_hook`).
+//!
+//! Every fallible function returns a [`LaravelRustStatus`] and hands payloads
+//! back through out-parameters. A description of the most recent failure is
+//! available from `laravel_rust_last_error`.
+//!
+//! Every string returned to PHP is allocated by this library and must be
+//! released exactly once with `laravel_rust_free_string`, never with `free`
+//! or PHP's allocator. Building with the `string-registry` feature tracks
+//! every string handed out, so freeing a foreign pointer or freeing twice is
+//! reported instead of corrupting the heap.
+
+#[cfg(feature = "string-registry")]
+use std::collections::HashSet;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString, OsString};
+use std::fmt;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::bridge::socket_bridge::{is_connection_failure, SocketBridge};
+use crate::config::AppConfig;
+use crate::config_loader::{find_setting_by_env, normalize_duration};
+use crate::config_validation::{socket_wait_interval, validate_overrides};
+use crate::errors::ServerError;
+use crate::hooks::RequestHooks;
+use crate::metrics::metrics;
+use crate::server::{HttpRequestPayload, HttpServer};
+use crate::shutdown::{notify_laravel_terminating_within, notify_timeout, Shutdown, ShutdownMode};
+
+/// Result of every fallible FFI call
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaravelRustStatus {
+    /// The call succeeded
+    Ok = 0,
+    /// A required pointer was null, or a string or JSON argument was invalid
+    InvalidArgument = -1,
+    /// The call needs a running HTTP server and none was started
+    NotStarted = -2,
+    /// The PHP worker could not be reached
+    BridgeUnavailable = -3,
+    /// The PHP worker did not answer in time
+    Timeout = -4,
+    /// Unexpected failure inside the library
+    Internal = -5,
+    /// `laravel_rust_start` on a handle whose server is already running
+    AlreadyRunning = -6,
+    /// The configuration was rejected or the port could not be bound
+    StartFailed = -7,
+}
+
+/// A failed call: the status returned to C and the message kept for
+/// `laravel_rust_last_error`
+#[derive(Debug)]
+struct Failure {
+    status: LaravelRustStatus,
+    message: String,
+}
+
+impl Failure {
+    fn new(status: LaravelRustStatus, message: impl fmt::Display) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+        }
+    }
+
+    fn invalid(message: impl fmt::Display) -> Self {
+        Self::new(LaravelRustStatus::InvalidArgument, message)
+    }
+
+    fn from_bridge(error: anyhow::Error) -> Self {
+        Self::new(bridge_error_status(&error), format!("{:#}", error))
+    }
+}
+
+/// Classify an error from the bridge by its cause
+pub(crate) fn bridge_error_status(error: &anyhow::Error) -> LaravelRustStatus {
+    match error.downcast_ref::<ServerError>() {
+        Some(ServerError::Unavailable { .. }) => LaravelRustStatus::BridgeUnavailable,
+        Some(ServerError::BridgeTimeout(_)) => LaravelRustStatus::Timeout,
+        Some(ServerError::PayloadTooLarge(_)) => LaravelRustStatus::InvalidArgument,
+        _ if is_connection_failure(error) => LaravelRustStatus::BridgeUnavailable,
+        _ => LaravelRustStatus::Internal,
+    }
+}
+
+/// Failure classes counted per command, by the status returned for them
+const COMMAND_FAILURES: [(LaravelRustStatus, &str); 4] = [
+    (LaravelRustStatus::BridgeUnavailable, "bridge_unavailable"),
+    (LaravelRustStatus::Timeout, "timeout"),
+    (LaravelRustStatus::InvalidArgument, "invalid_argument"),
+    (LaravelRustStatus::Internal, "internal"),
+];
+
+/// Lifetime counters of the commands sent through one handle
+///
+/// Lock-free, so reading them never waits for a command in flight.
+pub(crate) struct CommandStats {
+    created: Instant,
+    in_flight: AtomicUsize,
+    /// Commands started, whether finished or not
+    total: AtomicU64,
+    /// Indexed like `COMMAND_FAILURES`
+    failed: [AtomicU64; COMMAND_FAILURES.len()],
+    /// Command name and JSON data as passed in
+    bytes_sent: AtomicU64,
+    /// Serialized responses handed back
+    bytes_received: AtomicU64,
+}
+
+impl CommandStats {
+    pub(crate) fn new() -> Self {
+        Self {
+            created: Instant::now(),
+            in_flight: AtomicUsize::new(0),
+            total: AtomicU64::new(0),
+            failed: Default::default(),
+            bytes_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+        }
+    }
+
+    /// Count a command of `request_bytes` as in flight until the guard is dropped
+    pub(crate) fn start(&self, request_bytes: usize) -> CommandGuard<'_> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(request_bytes as u64, Ordering::Relaxed);
+        CommandGuard { stats: self, finished: false }
+    }
+
+    fn record_failure(&self, status: LaravelRustStatus) {
+        // Anything unexpected counts as internal, the last class
+        let class = COMMAND_FAILURES
+            .iter()
+            .position(|(failure, _)| *failure == status)
+            .unwrap_or(COMMAND_FAILURES.len() - 1);
+        self.failed[class].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        let failed: serde_json::Map<String, serde_json::Value> = COMMAND_FAILURES
+            .iter()
+            .zip(&self.failed)
+            .map(|((_, name), count)| (name.to_string(), count.load(Ordering::Relaxed).into()))
+            .collect();
+        serde_json::json!({
+            "uptime_secs": self.created.elapsed().as_secs_f64(),
+            "in_flight": self.in_flight.load(Ordering::Relaxed),
+            "total": self.total.load(Ordering::Relaxed),
+            "failed": failed,
+            "bytes_sent": self.bytes_sent.load(Ordering::Relaxed),
+            "bytes_received": self.bytes_received.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// A command in flight; one dropped without an outcome, e.g. by a panic,
+/// counts as an internal failure
+pub(crate) struct CommandGuard<'a> {
+    stats: &'a CommandStats,
+    finished: bool,
+}
+
+impl CommandGuard<'_> {
+    pub(crate) fn succeeded(mut self, response_bytes: usize) {
+        self.stats.bytes_received.fetch_add(response_bytes as u64, Ordering::Relaxed);
+        self.finished = true;
+    }
+
+    pub(crate) fn failed(mut self, status: LaravelRustStatus) {
+        self.stats.record_failure(status);
+        self.finished = true;
+    }
+}
+
+impl Drop for CommandGuard<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.stats.record_failure(LaravelRustStatus::Internal);
+        }
+        self.stats.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Called with the request as JSON before it is forwarded to Laravel
+///
+/// Returns 0 to forward the request, or an HTTP status code (100-599) to
+/// answer it with that status and an empty body. Null means no hook.
+pub type LaravelRustRequestHook = Option<extern "C" fn(request_json: *const c_char) -> i32>;
+
+/// Called for every response with its status and the time since the request
+/// arrived. Null means no hook.
+pub type LaravelRustResponseHook = Option<extern "C" fn(status: u16, duration_ms: f64)>;
+
+/// Options accepted by `laravel_rust_set_option` and the variables they set
+const OPTIONS: &[(&str, &str)] = &[
+    ("host", "HTTP_HOST"),
+    ("port", "HTTP_PORT"),
+    ("socket_path", "SOCKET_PATH"),
+    ("log_level", "LOG_LEVEL"),
+    ("connection_timeout", "SOCKET_CONNECTION_TIMEOUT"),
+    ("read_timeout_ms", "SOCKET_READ_TIMEOUT_MS"),
+    ("notify_timeout_ms", "SHUTDOWN_NOTIFY_TIMEOUT_MS"),
+    ("drain_timeout_ms", "SHUTDOWN_DRAIN_TIMEOUT_MS"),
+];
+
+fn option_env(key: &str) -> Result<&'static str, Failure> {
+    OPTIONS
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, env)| *env)
+        .ok_or_else(|| {
+            let names: Vec<_> = OPTIONS.iter().map(|(name, _)| *name).collect();
+            Failure::invalid(format!("unknown option {:?}; supported options: {}", key, names.join(", ")))
+        })
+}
+
+/// Held while a start has its overrides in the process environment
+static ENV_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// Variables set in the process environment until dropped
+///
+/// Every component reads its settings from the environment, so a start puts
+/// the handle's options and overrides there while it builds the server, and
+/// the previous values are restored afterwards, also when the start fails.
+/// The environment is process-wide: other threads of the host see the
+/// overrides while a start runs, and starts of different handles wait for
+/// each other.
+struct ScopedEnv {
+    previous: Vec<(String, Option<OsString>)>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl ScopedEnv {
+    fn apply(vars: Vec<(String, String)>) -> Self {
+        let lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let previous = vars
+            .into_iter()
+            .map(|(name, value)| {
+                let previous = std::env::var_os(&name);
+                std::env::set_var(&name, value);
+                (name, previous)
+            })
+            .collect();
+        Self { previous, _lock: lock }
+    }
+}
+
+impl Drop for ScopedEnv {
+    fn drop(&mut self) {
+        // In reverse, so a variable set twice gets its original value back
+        for (name, previous) in self.previous.drain(..).rev() {
+            match previous {
+                Some(value) => std::env::set_var(&name, value),
+                None => std::env::remove_var(&name),
+            }
+        }
+    }
+}
+
+/// Last failure of a call that had no live handle to record it on
+static LAST_ERROR: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+/// Strings handed to C and not yet released
+#[cfg(feature = "string-registry")]
+static LIVE_STRINGS: Lazy<Mutex<HashSet<usize>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Handle owned by the PHP side between `laravel_rust_init` and `laravel_rust_destroy`
+pub struct LaravelRustServer {
+    /// Runtime the bridge and the HTTP server run on; FFI calls block on it
+    runtime: Runtime,
+    /// Created on first use, so a handle can exist before the worker socket does
+    bridge: Mutex<Option<Arc<SocketBridge>>>,
+    /// HTTP server started by `laravel_rust_start`
+    running: Mutex<Option<RunningServer>>,
+    /// Description of the most recent failed call on this handle
+    last_error: Mutex<Option<String>>,
+    /// Values from `laravel_rust_set_option` by variable name, applied on start
+    options: Mutex<HashMap<&'static str, String>>,
+    /// Callbacks installed on the server at the next start
+    hooks: Mutex<CallbackHooks>,
+    /// Commands sent with `laravel_rust_send_command`
+    commands: CommandStats,
+}
+
+/// C callbacks around every request of the embedded server
+#[derive(Debug, Clone, Copy, Default)]
+struct CallbackHooks {
+    request: LaravelRustRequestHook,
+    response: LaravelRustResponseHook,
+}
+
+impl RequestHooks for CallbackHooks {
+    fn on_request(&self, request: &HttpRequestPayload) -> Option<hyper::StatusCode> {
+        let hook = self.request?;
+        let json = serde_json::to_string(request).ok().and_then(|json| CString::new(json).ok())?;
+
+        match hook(json.as_ptr()) {
+            0 => None,
+            code => match u16::try_from(code)
+                .ok()
+                .filter(|code| (100..600).contains(code))
+                .and_then(|code| hyper::StatusCode::from_u16(code).ok())
+            {
+                Some(status) => Some(status),
+                None => {
+                    warn!(code, "Request hook returned a value that is not an HTTP status; forwarding the request");
+                    None
+                }
+            },
+        }
+    }
+
+    fn on_response(&self, status: hyper::StatusCode, duration: Duration) {
+        if let Some(hook) = self.response {
+            hook(status.as_u16(), duration.as_secs_f64() * 1000.0);
+        }
+    }
+}
+
+/// An HTTP server serving in the background
+struct RunningServer {
+    addr: SocketAddr,
+    socket_path: String,
+    started: Instant,
+    bridge: Arc<SocketBridge>,
+    ready: Arc<AtomicBool>,
+    /// `SHUTDOWN_NOTIFY_TIMEOUT_MS` and `SHUTDOWN_DRAIN_TIMEOUT_MS` as they were at start
+    notify_timeout: Duration,
+    drain_timeout: Duration,
+    shutdown: Shutdown,
+    server: JoinHandle<()>,
+    readiness: JoinHandle<()>,
+    swap_watcher: Option<JoinHandle<()>>,
+}
+
+impl LaravelRustServer {
+    fn new() -> Result<Self, Failure> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("laravel-rust-ffi")
+            .build()
+            .map_err(|e| Failure::new(LaravelRustStatus::Internal, format!("Failed to start the async runtime: {}", e)))?;
+
+        Ok(Self {
+            runtime,
+            bridge: Mutex::new(None),
+            running: Mutex::new(None),
+            last_error: Mutex::new(None),
+            options: Mutex::new(HashMap::new()),
+            hooks: Mutex::new(CallbackHooks::default()),
+            commands: CommandStats::new(),
+        })
+    }
+
+    /// Validate an option and keep it for the next `start`
+    fn set_option(&self, key: &str, value: &str) -> Result<(), Failure> {
+        let env = option_env(key)?;
+        let value = normalize_duration(env, value).map_err(|e| Failure::invalid(format!("{}: {:#}", key, e)))?;
+        validate_overrides(HashMap::from([(env, value.clone())]))
+            .map_err(|errors| {
+                let problems: Vec<_> = errors.0.iter().map(|p| p.to_string()).collect();
+                Failure::invalid(format!("invalid value for option {:?}: {}", key, problems.join("; ")))
+            })?;
+
+        self.options.lock().unwrap_or_else(|e| e.into_inner()).insert(env, value);
+        Ok(())
+    }
+
+    /// Value `start` would use for an option: the one set on the handle, the
+    /// environment, or the default
+    fn get_option(&self, key: &str) -> Result<Option<String>, Failure> {
+        let env = option_env(key)?;
+        let value = self.options.lock().unwrap_or_else(|e| e.into_inner()).get(env).cloned();
+        Ok(value
+            .or_else(|| std::env::var(env).ok())
+            .or_else(|| find_setting_by_env(env).and_then(|s| s.default).map(str::to_string)))
+    }
+
+    fn bridge(&self) -> Result<Arc<SocketBridge>, Failure> {
+        let mut bridge = self.bridge.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(bridge) = bridge.as_ref() {
+            return Ok(bridge.clone());
+        }
+
+        // The bridge warms up its pool in a background task
+        let _runtime = self.runtime.enter();
+        let created = SocketBridge::new().map_err(|e| {
+            Failure::new(LaravelRustStatus::BridgeUnavailable, format!("Failed to create the bridge: {:#}", e))
+        })?;
+        *bridge = Some(created.clone());
+        Ok(created)
+    }
+
+    /// Send a command and return its response as JSON
+    ///
+    /// `request_bytes` is the size of the command name and data as passed in,
+    /// for the command stats.
+    fn send_command(
+        &self,
+        command: &str,
+        data: Option<CommandData>,
+        request_bytes: usize,
+    ) -> Result<String, Failure> {
+        let in_flight = self.commands.start(request_bytes);
+        let result = self.bridge().and_then(|bridge| {
+            let response = self
+                .runtime
+                .block_on(bridge.send_command(command, data))
+                .map_err(Failure::from_bridge)?;
+            serde_json::to_string(&response)
+                .map_err(|e| Failure::new(LaravelRustStatus::Internal, format!("Failed to serialize the response: {}", e)))
+        });
+        match &result {
+            Ok(json) => in_flight.succeeded(json.len()),
+            Err(failure) => in_flight.failed(failure.status),
+        }
+        result
+    }
+
+    /// Start the HTTP server with the given environment overrides
+    ///
+    /// Options set on the handle are applied first, so `overrides` win over
+    /// them. Both are in the environment only while the server is built (see
+    /// [`ScopedEnv`]): the server reads its settings when it is created, an
+    /// invalid one fails the start with `StartFailed`, and those needed
+    /// later, such as the shutdown timeouts, are read before the environment
+    /// is restored. The bridge built for the server
+    /// replaces the one used by `laravel_rust_send_command`, so both share
+    /// one connection pool.
+    fn start(&self, overrides: &serde_json::Map<String, serde_json::Value>) -> Result<(), Failure> {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        if running.is_some() {
+            return Err(Failure::new(LaravelRustStatus::AlreadyRunning, "server is already running"));
+        }
+
+        let mut vars: Vec<(String, String)> = self
+            .options
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(env, value)| (env.to_string(), value.clone()))
+            .collect();
+        for (name, value) in overrides {
+            let value = match value {
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Bool(_) | serde_json::Value::Number(_) => value.to_string(),
+                _ => {
+                    return Err(Failure::invalid(format!(
+                        "config value for {} must be a string, number or boolean",
+                        name
+                    )))
+                }
+            };
+            vars.push((name.clone(), value));
+        }
+        let _env = ScopedEnv::apply(vars);
+        let start_failed = |e: anyhow::Error| Failure::new(LaravelRustStatus::StartFailed, format!("{:#}", e));
+        let config = AppConfig::from_env()
+            .and_then(|config| config.validate().map(|_| config))
+            .map_err(|e| start_failed(e.context("Invalid configuration")))?;
+
+        let _runtime = self.runtime.enter();
+        let bridge = SocketBridge::new_with_config(&config).map_err(start_failed)?;
+        let mut server = self
+            .runtime
+            .block_on(HttpServer::new_with_config(bridge.clone(), &config))
+            .map_err(start_failed)?;
+        let hooks = *self.hooks.lock().unwrap_or_else(|e| e.into_inner());
+        if hooks.request.is_some() || hooks.response.is_some() {
+            server = server.with_request_hooks(Arc::new(hooks));
+        }
+        let addr = server.bind().map_err(start_failed)?;
+
+        let ready = server.readiness();
+        let interval = socket_wait_interval();
+        let readiness = tokio::spawn(wait_until_reachable(config.connection.socket_path.clone(), ready.clone(), interval));
+        let swap_watcher = bridge.spawn_swap_watcher();
+
+        let shutdown = Shutdown::new();
+        let signal = shutdown.signal();
+        let server = tokio::spawn(async move {
+            if let Err(e) = server.start_with_shutdown(signal).await {
+                error!(error = %format!("{:#}", e), "HTTP server stopped with an error");
+            }
+        });
+
+        info!(%addr, "Embedded HTTP server listening");
+        *self.bridge.lock().unwrap_or_else(|e| e.into_inner()) = Some(bridge.clone());
+        *running = Some(RunningServer {
+            addr,
+            socket_path: config.connection.socket_path.clone(),
+            started: Instant::now(),
+            bridge,
+            ready,
+            notify_timeout: notify_timeout(),
+            drain_timeout: ShutdownMode::Graceful.drain_timeout(),
+            shutdown,
+            server,
+            readiness,
+            swap_watcher,
+        });
+        Ok(())
+    }
+
+    /// Stop the HTTP server the way the binary does on SIGTERM
+    ///
+    /// Laravel is told the process is terminating, in-flight requests get
+    /// `SHUTDOWN_DRAIN_TIMEOUT_MS` to finish and the bridge connections are
+    /// closed.
+    fn stop(&self) -> Result<(), Failure> {
+        let Some(mut running) = self.running.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+            return Err(Failure::new(LaravelRustStatus::NotStarted, "server is not running"));
+        };
+
+        self.runtime.block_on(async {
+            running.readiness.abort();
+            if let Some(watcher) = running.swap_watcher.take() {
+                watcher.abort();
+            }
+
+            if running.ready.load(Ordering::Acquire) {
+                notify_laravel_terminating_within(&running.bridge, running.notify_timeout).await;
+            }
+
+            let drain_timeout = running.drain_timeout;
+            running.shutdown.trigger();
+            if tokio::time::timeout(drain_timeout, &mut running.server).await.is_err() {
+                warn!(drain_timeout_ms = drain_timeout.as_millis() as u64, "Not all requests finished in time, aborting");
+                running.server.abort();
+            }
+
+            running.bridge.cleanup().await;
+        });
+
+        *self.bridge.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        Ok(())
+    }
+
+    /// State of the running server plus the metrics registry, as JSON
+    ///
+    /// Reads only the handle's own state and a copy of the metrics, never
+    /// anything a request in flight holds.
+    fn stats(&self) -> Result<String, Failure> {
+        let server = {
+            let running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+            let Some(running) = running.as_ref() else {
+                return Err(Failure::new(LaravelRustStatus::NotStarted, "server is not running"));
+            };
+            serde_json::json!({
+                "address": running.addr.to_string(),
+                "uptime_secs": running.started.elapsed().as_secs_f64(),
+                "socket_path": running.socket_path,
+                "worker_ready": running.ready.load(Ordering::Acquire),
+            })
+        };
+
+        let stats = serde_json::json!({
+            "server": server,
+            "commands": self.commands.to_json(),
+            "build": crate::build_info(),
+            "metrics": metrics().snapshot(),
+        });
+        serde_json::to_string(&stats)
+            .map_err(|e| Failure::new(LaravelRustStatus::Internal, format!("Failed to serialize stats: {}", e)))
+    }
+
+    fn record_error(&self, message: String) {
+        *self.last_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(message);
+    }
+}
+
+impl Drop for LaravelRustServer {
+    fn drop(&mut self) {
+        // Destroying a handle with a running server shuts it down gracefully
+        let _ = self.stop();
+    }
+}
+
+/// Run the body of an FFI call
+///
+/// Panics are caught so they never unwind into the PHP process; a failure is
+/// recorded on `server`, or globally when there is no handle, and its status
+/// returned.
+fn guard(server: Option<&LaravelRustServer>, f: impl FnOnce() -> Result<(), Failure>) -> LaravelRustStatus {
+    let result = panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err(Failure::new(LaravelRustStatus::Internal, "internal error (panic) in laravel_rust")));
+
+    match result {
+        Ok(()) => LaravelRustStatus::Ok,
+        Err(failure) => {
+            match server {
+                Some(server) => server.record_error(failure.message),
+                None => *LAST_ERROR.lock().unwrap_or_else(|e| e.into_inner()) = Some(failure.message),
+            }
+            failure.status
+        }
+    }
+}
+
+/// Mark the server ready once the worker socket accepts connections
+async fn wait_until_reachable(socket_path: String, ready: Arc<AtomicBool>, interval: Duration) {
+    while tokio::net::UnixStream::connect(&socket_path).await.is_err() {
+        tokio::time::sleep(interval).await;
+    }
+    ready.store(true, Ordering::Release);
+}
+
+/// Create a handle
+///
+/// On failure `*out_server` is set to null and the reason is available from
+/// `laravel_rust_last_error(NULL)`.
+///
+/// # Returns
+///
+/// * `Ok` - `*out_server` holds a handle to pass to the other functions
+/// * `InvalidArgument` - `out_server` is null
+/// * `Internal` - the async runtime could not be started
+///
+/// # Safety
+///
+/// `out_server` must be null or point to writable storage for a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn laravel_rust_init(out_server: *mut *mut LaravelRustServer) -> LaravelRustStatus {
+    guard(None, || {
+        if out_server.is_null() {
+            return Err(Failure::invalid("out_server is null"));
+        }
+        *out_server = ptr::null_mut();
+
+        let server = LaravelRustServer::new()?;
+        *out_server = Box::into_raw(Box::new(server));
+        Ok(())
+    })
+}
+
+/// Destroy a handle and everything it owns
+///
+/// A running HTTP server is stopped first, exactly as `laravel_rust_stop`
+/// would. A panic while doing so does not reach PHP; it is available from
+/// `laravel_rust_last_error(NULL)` instead.
+///
+/// # Safety
+///
+/// `server` must be null or a handle returned by `laravel_rust_init` that has not
+/// been destroyed yet; it must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn laravel_rust_destroy(server: *mut LaravelRustServer) {
+    guard(None, || {
+        if !server.is_null() {
+            drop(Box::from_raw(server));
+        }
+        Ok(())
+    });
+}
+
+/// Send a command to the Laravel worker and wait for its response
+///
+/// `json_data` is an optional JSON object passed as the command data; null
+/// sends no data. A worker that handles the command but reports a failure
+/// is still `Ok`: the response then has `success: false`.
+///
+/// # Returns
+///
+/// * `Ok` - `*out_response` holds the `PhpResponse` serialized as JSON;
+///   release it with `laravel_rust_free_string`
+/// * `InvalidArgument` - a pointer is null, the command or data is not valid
+///   UTF-8, or the data is not a JSON object
+/// * `BridgeUnavailable` - the PHP worker could not be reached
+/// * `Timeout` - the PHP worker did not answer in time
+/// * `Internal` - unexpected failure
+///
+/// On failure `*out_response` is set to null.
+///
+/// # Safety
+///
+/// `server` must be null or a live handle from `laravel_rust_init`; `command`
+/// and `json_data` must be null or point to NUL-terminated strings;
+/// `out_response` must be null or point to writable storage for a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn laravel_rust_send_command(
+    server: *mut LaravelRustServer,
+    command: *const c_char,
+    json_data: *const c_char,
+    out_response: *mut *mut c_char,
+) -> LaravelRustStatus {
+    let handle = server.as_ref();
+    guard(handle, || {
+        if out_response.is_null() {
+            return Err(Failure::invalid("out_response is null"));
+        }
+        *out_response = ptr::null_mut();
+        let server = handle.ok_or_else(|| Failure::invalid("server is null"))?;
+        if command.is_null() {
+            return Err(Failure::invalid("command is null"));
+        }
+
+        let (command, data) = parse_command(command, json_data)?;
+        let request_bytes = command.len() + if json_data.is_null() { 0 } else { CStr::from_ptr(json_data).to_bytes().len() };
+        let json = server.send_command(command, data, request_bytes)?;
+        *out_response = into_c_string(json)?;
+        Ok(())
+    })
+}
+
+/// Set an option for the HTTP server before starting it
+///
+/// Supported keys are `host`, `port`, `socket_path`, `log_level`,
+/// `connection_timeout` (seconds), `read_timeout_ms`, `notify_timeout_ms`
+/// and `drain_timeout_ms`. Timeouts also accept a unit, e.g. `"5s"`. The
+/// value is validated immediately and kept on the handle; it takes effect on
+/// the next `laravel_rust_start`, where it overrides the environment.
+///
+/// # Returns
+///
+/// * `Ok` - the option was stored
+/// * `InvalidArgument` - a pointer is null, a string is not valid UTF-8, the
+///   key is unknown or the value is invalid
+/// * `Internal` - unexpected failure
+///
+/// # Safety
+///
+/// `server` must be null or a live handle from `laravel_rust_init`; `key`
+/// and `value` must be null or point to NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn laravel_rust_set_option(
+    server: *mut LaravelRustServer,
+    key: *const c_char,
+    value: *const c_char,
+) -> LaravelRustStatus {
+    let handle = server.as_ref();
+    guard(handle, || {
+        let server = handle.ok_or_else(|| Failure::invalid("server is null"))?;
+        let key = parse_str(key, "key")?;
+        let value = parse_str(value, "value")?;
+        server.set_option(key, value)
+    })
+}
+
+/// Read the value an option will have when the server starts
+///
+/// That is the value from `laravel_rust_set_option`, else the environment,
+/// else the default. Timeouts are reported as bare integers in their unit.
+///
+/// # Returns
+///
+/// * `Ok` - `*out_value` holds the value, or null if the option has none;
+///   release it with `laravel_rust_free_string`
+/// * `InvalidArgument` - a pointer is null, the key is not valid UTF-8 or is
+///   unknown
+/// * `Internal` - unexpected failure
+///
+/// On failure `*out_value` is set to null.
+///
+/// # Safety
+///
+/// `server` must be null or a live handle from `laravel_rust_init`; `key`
+/// must be null or point to a NUL-terminated string; `out_value` must be
+/// null or point to writable storage for a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn laravel_rust_get_option(
+    server: *mut LaravelRustServer,
+    key: *const c_char,
+    out_value: *mut *mut c_char,
+) -> LaravelRustStatus {
+    let handle = server.as_ref();
+    guard(handle, || {
+        if out_value.is_null() {
+            return Err(Failure::invalid("out_value is null"));
+        }
+        *out_value = ptr::null_mut();
+        let server = handle.ok_or_else(|| Failure::invalid("server is null"))?;
+        let key = parse_str(key, "key")?;
+
+        if let Some(value) = server.get_option(key)? {
+            *out_value = into_c_string(value)?;
+        }
+        Ok(())
+    })
+}
+
+/// Install a callback that sees every request before it is forwarded
+///
+/// The callback receives the request as JSON (`method`, `uri`, `headers`,
+/// `body`, `query_params`) and returns 0 to forward it, or an HTTP status
+/// code to answer it with that status and an empty body. Null removes the
+/// callback. Takes effect on the next `laravel_rust_start`.
+///
+/// The callback is called from a thread of the library's runtime, never the
+/// PHP thread that started the server, and must be fast and non-blocking. If
+/// it does not return within `REQUEST_HOOK_TIMEOUT_MS` (default 100) the
+/// stall is logged and the request is forwarded.
+///
+/// # Returns
+///
+/// * `Ok` - the callback was stored
+/// * `InvalidArgument` - `server` is null
+///
+/// # Safety
+///
+/// `server` must be null or a live handle from `laravel_rust_init`; `hook`
+/// must stay callable until the server is stopped.
+#[no_mangle]
+pub unsafe extern "C" fn laravel_rust_set_request_hook(
+    server: *mut LaravelRustServer,
+    hook: LaravelRustRequestHook,
+) -> LaravelRustStatus {
+    let handle = server.as_ref();
+    guard(handle, || {
+        let server = handle.ok_or_else(|| Failure::invalid("server is null"))?;
+        server.hooks.lock().unwrap_or_else(|e| e.into_inner()).request = hook;
+        Ok(())
+    })
+}
+
+/// Install a callback that sees the status and duration of every response
+///
+/// `duration_ms` is measured from the moment the request arrived. The
+/// callback runs after the response is handed to the client, so it never
+/// delays it. Null removes the callback. Takes effect on the next
+/// `laravel_rust_start`; the same threading and `REQUEST_HOOK_TIMEOUT_MS`
+/// rules as for the request hook apply.
+///
+/// # Returns
+///
+/// * `Ok` - the callback was stored
+/// * `InvalidArgument` - `server` is null
+///
+/// # Safety
+///
+/// `server` must be null or a live handle from `laravel_rust_init`; `hook`
+/// must stay callable until the server is stopped.
+#[no_mangle]
+pub unsafe extern "C" fn laravel_rust_set_response_hook(
+    server: *mut LaravelRustServer,
+    hook: LaravelRustResponseHook,
+) -> LaravelRustStatus {
+    let handle = server.as_ref();
+    guard(handle, || {
+        let server = handle.ok_or_else(|| Failure::invalid("server is null"))?;
+        server.hooks.lock().unwrap_or_else(|e| e.into_inner()).response = hook;
+        Ok(())
+    })
+}
+
+/// Start the HTTP server in the background
+///
+/// `config_json` is null or a JSON object of environment variable overrides,
+/// e.g. `{"HTTP_PORT": 8081, "SOCKET_PATH": "/run/app.sock"}`; everything
+/// not listed keeps its environment value or default. The overrides and the
+/// handle's options are set in the process environment while the server is
+/// built and restored before this returns, so they never reach another
+/// handle; other threads of the process can see them in the meantime. Every
+/// setting is read, and the listening socket bound, before this returns, so
+/// an invalid setting or a taken port is reported here.
+///
+/// # Returns
+///
+/// * `Ok` - the server is accepting connections
+/// * `AlreadyRunning` - the running server is left untouched
+/// * `InvalidArgument` - `server` is null or `config_json` is not a JSON object
+///   of strings, numbers and booleans
+/// * `StartFailed` - invalid configuration or the port could not be bound
+/// * `Internal` - unexpected failure
+///
+/// # Safety
+///
+/// `server` must be null or a live handle from `laravel_rust_init`;
+/// `config_json` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn laravel_rust_start(server: *mut LaravelRustServer, config_json: *const c_char) -> LaravelRustStatus {
+    let handle = server.as_ref();
+    guard(handle, || {
+        let server = handle.ok_or_else(|| Failure::invalid("server is null"))?;
+        let overrides = parse_config(config_json)?;
+        server.start(&overrides)
+    })
+}
+
+/// Gracefully stop the HTTP server started by `laravel_rust_start`
+///
+/// Blocks until in-flight requests finish or `SHUTDOWN_DRAIN_TIMEOUT_MS`
+/// passes. The handle stays usable and the server can be started again.
+///
+/// # Returns
+///
+/// * `Ok` - the server was stopped
+/// * `NotStarted` - nothing was running
+/// * `InvalidArgument` - `server` is null
+/// * `Internal` - unexpected failure
+///
+/// # Safety
+///
+/// `server` must be null or a live handle from `laravel_rust_init`.
+#[no_mangle]
+pub unsafe extern "C" fn laravel_rust_stop(server: *mut LaravelRustServer) -> LaravelRustStatus {
+    let handle = server.as_ref();
+    guard(handle, || handle.ok_or_else(|| Failure::invalid("server is null"))?.stop())
+}
+
+/// Snapshot of the running server and the bridge metrics
+///
+/// The JSON object has a `server` section (`address`, `uptime_secs`,
+/// `socket_path`, `worker_ready`), a `commands` section with the counters of
+/// `laravel_rust_send_command` on this handle (`uptime_secs` since
+/// `laravel_rust_init`, `in_flight`, `total`, `failed` by status,
+/// `bytes_sent`, `bytes_received`), a `build` section (`version`, `git_sha`,
+/// `build_timestamp`) and a `metrics` section with every
+/// metric as `{type, series: [{labels, value}]}`; summaries carry `sum` and
+/// `count` instead of `value`. Cheap enough to call on every dashboard
+/// refresh and safe to call while the server handles traffic.
+///
+/// # Returns
+///
+/// * `Ok` - `*out_json` holds the stats; release it with `laravel_rust_free_string`
+/// * `NotStarted` - the server is not running
+/// * `InvalidArgument` - a pointer is null
+/// * `Internal` - unexpected failure
+///
+/// On failure `*out_json` is set to null.
+///
+/// # Safety
+///
+/// `server` must be null or a live handle from `laravel_rust_init`;
+/// `out_json` must be null or point to writable storage for a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn laravel_rust_get_stats(server: *mut LaravelRustServer, out_json: *mut *mut c_char) -> LaravelRustStatus {
+    let handle = server.as_ref();
+    guard(handle, || {
+        if out_json.is_null() {
+            return Err(Failure::invalid("out_json is null"));
+        }
+        *out_json = ptr::null_mut();
+        let server = handle.ok_or_else(|| Failure::invalid("server is null"))?;
+
+        *out_json = into_c_string(server.stats()?)?;
+        Ok(())
+    })
+}
+
+/// Describe the most recent failed call on a handle
+///
+/// Failures are kept until the next failure replaces them; successful calls
+/// do not clear them. With a null `server` this describes the last failure
+/// of a call that had no handle to record it on, such as `laravel_rust_init`.
+///
+/// # Returns
+///
+/// * A copy of the message; release it with `laravel_rust_free_string`
+/// * Null if no call has failed yet
+///
+/// # Safety
+///
+/// `server` must be null or a live handle from `laravel_rust_init`.
+#[no_mangle]
+pub unsafe extern "C" fn laravel_rust_last_error(server: *mut LaravelRustServer) -> *mut c_char {
+    let message = match server.as_ref() {
+        Some(server) => server.last_error.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+        None => LAST_ERROR.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+    };
+
+    message
+        .and_then(|message| into_c_string(message.replace('\0', " ")).ok())
+        .unwrap_or(ptr::null_mut())
+}
+
+/// Release a string returned by this library
+///
+/// Strings from `laravel_rust_send_command`, `laravel_rust_get_option`,
+/// `laravel_rust_get_stats` and `laravel_rust_last_error` must be released
+/// here and nowhere else: they come from Rust's allocator,
+/// not `malloc` or PHP's. With the `string-registry` feature a pointer that
+/// was not returned by this library, or was already released, is logged as
+/// an error and left alone.
+///
+/// # Safety
+///
+/// `ptr` must be null or a string returned by this library that has not been
+/// freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn laravel_rust_free_string(ptr: *mut c_char) {
+    if ptr.is_null() {
+        return;
+    }
+
+    #[cfg(feature = "string-registry")]
+    if !LIVE_STRINGS.lock().unwrap_or_else(|e| e.into_inner()).remove(&(ptr as usize)) {
+        error!(ptr = ?ptr, "laravel_rust_free_string: pointer was not returned by this library or was already freed");
+        return;
+    }
+
+    drop(CString::from_raw(ptr));
+}
+
+/// Number of strings returned by this library that were not released yet
+///
+/// Only available with the `string-registry` feature; meant for leak checks
+/// in tests.
+#[cfg(feature = "string-registry")]
+#[no_mangle]
+pub extern "C" fn laravel_rust_live_strings() -> usize {
+    LIVE_STRINGS.lock().unwrap_or_else(|e| e.into_inner()).len()
+}
+
+/// Hand a string over to C; the caller owns it until `laravel_rust_free_string`
+fn into_c_string(value: String) -> Result<*mut c_char, Failure> {
+    let ptr = CString::new(value)
+        .map(CString::into_raw)
+        .map_err(|_| Failure::new(LaravelRustStatus::Internal, "response contains a NUL byte"))?;
+
+    #[cfg(feature = "string-registry")]
+    LIVE_STRINGS.lock().unwrap_or_else(|e| e.into_inner()).insert(ptr as usize);
+
+    Ok(ptr)
+}
+
+/// Command data as sent over the bridge
+type CommandData = HashMap<String, serde_json::Value>;
+
+/// Decode the command name and optional JSON data passed from C
+///
+/// # Safety
+///
+/// `command` must point to a NUL-terminated string; `json_data` must be null
+/// or point to one.
+unsafe fn parse_command<'a>(
+    command: *const c_char,
+    json_data: *const c_char,
+) -> Result<(&'a str, Option<CommandData>), Failure> {
+    let command = CStr::from_ptr(command)
+        .to_str()
+        .map_err(|_| Failure::invalid("command is not valid UTF-8"))?;
+
+    let data = if json_data.is_null() {
+        None
+    } else {
+        let json = CStr::from_ptr(json_data)
+            .to_str()
+            .map_err(|_| Failure::invalid("data is not valid UTF-8"))?;
+        Some(serde_json::from_str(json).map_err(|e| Failure::invalid(format!("data is not a JSON object: {}", e)))?)
+    };
+
+    Ok((command, data))
+}
+
+/// Borrow a required string argument
+///
+/// # Safety
+///
+/// `value` must be null or point to a NUL-terminated string.
+unsafe fn parse_str<'a>(value: *const c_char, name: &str) -> Result<&'a str, Failure> {
+    if value.is_null() {
+        return Err(Failure::invalid(format!("{} is null", name)));
+    }
+    CStr::from_ptr(value)
+        .to_str()
+        .map_err(|_| Failure::invalid(format!("{} is not valid UTF-8", name)))
+}
+
+/// Decode the optional JSON object of environment overrides
+///
+/// # Safety
+///
+/// `config_json` must be null or point to a NUL-terminated string.
+unsafe fn parse_config(config_json: *const c_char) -> Result<serde_json::Map<String, serde_json::Value>, Failure> {
+    if config_json.is_null() {
+        return Ok(serde_json::Map::new());
+    }
+    let json = CStr::from_ptr(config_json)
+        .to_str()
+        .map_err(|_| Failure::invalid("config is not valid UTF-8"))?;
+    serde_json::from_str(json).map_err(|e| Failure::invalid(format!("config is not a JSON object: {}", e)))
+}
@@ -1,9 +1,164 @@
-use anyhow::Result;
+//! # Laravel Rust Bridge
+//!
+//! HTTP server that forwards requests to a Laravel application over a Unix
+//! socket. The `laravel-rust-server` binary is a thin wrapper around this
+//! crate; the same pieces can be embedded in another program, e.g. to put
+//! custom routes in front of the bridge.
+//!
+//! The embedding surface is re-exported at the crate root:
+//!
+//! * [`AppConfig`] - configuration, read from the environment
+//! * [`SocketBridge`] - pooled connection to the PHP worker
+//! * [`HttpServer`] - the HTTP front end, with [`HttpRequestPayload`] and
+//!   [`HttpResponsePayload`] as the wire format to the worker
+//! * [`ServerError`], [`ErrorRenderer`] - error classes and their rendering
+//! * [`RequestHooks`] - callbacks around every request
+//! * [`ResponseTransformer`] - rewriting of HTML pages
+//! * [`Shutdown`], [`ShutdownSignals`] - graceful shutdown
+//! * [`build_info()`] - version, git commit and build time
+//!
+//! ```no_run
+//! use laravel_rust_server::{AppConfig, HttpServer, Shutdown, SocketBridge};
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let config = AppConfig::from_env()?;
+//! let bridge = SocketBridge::new_with_config(&config)?;
+//! let server = HttpServer::new_with_config(bridge.clone(), &config).await?;
+//!
+//! let shutdown = Shutdown::new();
+//! let signal = shutdown.signal();
+//! let serving = tokio::spawn(async move { server.start_with_shutdown(signal).await });
+//!
+//! // Later, e.g. from the embedder's own signal handling
+//! shutdown.trigger();
+//! serving.await??;
+//! bridge.cleanup().await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Modules marked hidden in the docs serve the binary (supervision of the
+//! PHP worker, the admin listener, config layering) and may change without
+//! notice.
 
 pub mod bridge;
+pub mod bridge_config;
+pub mod build_info;
 pub mod config;
 pub mod errors;
+pub mod hooks;
+pub mod html_transform;
+pub mod laravel_integration;
+pub mod metrics;
+#[cfg(feature = "php-ext")]
+pub mod php_ext;
+pub mod request_context;
+pub mod response_headers;
+pub mod server;
+pub mod shutdown;
+pub mod static_cache;
+pub mod statsd;
+pub mod telemetry;
+pub mod worker_protocol;
+
+#[doc(hidden)]
+pub mod accept;
+#[doc(hidden)]
+pub mod admin;
+#[doc(hidden)]
+pub mod admin_artisan;
+#[doc(hidden)]
+pub mod admin_auth;
+#[doc(hidden)]
+pub mod bench;
+#[cfg(feature = "chaos")]
+#[doc(hidden)]
+pub mod chaos;
+#[doc(hidden)]
+pub mod cidr;
+#[doc(hidden)]
+pub mod coalesce;
+#[doc(hidden)]
+pub mod config_loader;
+#[doc(hidden)]
+pub mod config_validation;
+#[doc(hidden)]
+pub mod deadline;
+#[cfg(feature = "dir-listing")]
+#[doc(hidden)]
+pub mod dir_listing;
+#[doc(hidden)]
+pub mod drain;
+#[doc(hidden)]
+pub mod etag;
+#[doc(hidden)]
+pub mod fastcgi;
+#[doc(hidden)]
+pub mod favicon;
+#[doc(hidden)]
+pub mod file_cache;
+#[doc(hidden)]
+pub mod fd_limit;
+#[cfg(feature = "grpc")]
+#[doc(hidden)]
+pub mod grpc;
+#[doc(hidden)]
+pub mod form_params;
+#[doc(hidden)]
+pub mod header_scrub;
+#[doc(hidden)]
+pub mod hot_reload;
+#[doc(hidden)]
+pub mod ip_filter;
+#[doc(hidden)]
+pub mod log_format;
+#[doc(hidden)]
+pub mod log_rotation;
+#[doc(hidden)]
+pub mod log_throttle;
+#[doc(hidden)]
+pub mod mime;
+#[cfg(feature = "test-worker")]
+#[doc(hidden)]
+pub mod mock_worker;
+#[doc(hidden)]
+pub mod privileges;
+#[doc(hidden)]
+pub mod recording;
+#[doc(hidden)]
+pub mod response_validation;
+#[doc(hidden)]
+pub mod runtime_info;
+#[doc(hidden)]
+pub mod self_test;
+#[doc(hidden)]
+pub mod sniff;
+#[doc(hidden)]
+pub mod stall;
+#[doc(hidden)]
+pub mod supervisor;
+#[doc(hidden)]
+pub mod tenants;
+#[doc(hidden)]
+pub mod tls;
+#[doc(hidden)]
+pub mod trailing_slash;
+#[doc(hidden)]
+pub mod trusted_proxies;
+#[doc(hidden)]
+pub mod upgrade;
+#[doc(hidden)]
+pub mod websocket;
+#[doc(hidden)]
+pub mod worker_limits;
 
 // Основной модуль для интеграции с Laravel
 
+pub use bridge::socket_bridge::SocketBridge;
+pub use build_info::{build_info, BuildInfo};
 pub use config::{AppConfig, ServerConfig, LoggingConfig, PhpWorkerConfig, ConnectionConfig, ConnectionPoolConfig, RetryConfig};
+pub use errors::{ErrorRenderer, ServerError};
+pub use hooks::RequestHooks;
+pub use html_transform::ResponseTransformer;
+pub use server::{HttpRequestPayload, HttpResponsePayload, HttpServer};
+pub use shutdown::{Shutdown, ShutdownMode, ShutdownSignals};
@@ -0,0 +1,154 @@
+//! Log output format
+//!
+//! `LOG_FORMAT=text` (the default) keeps the human-readable layout;
+//! `LOG_FORMAT=json` writes one JSON object per event for log pipelines
+//! such as Loki or ELK:
+//!
+//! ```json
+//! {"timestamp":"2024-05-01T12:00:00.000000Z","level":"ERROR","target":"laravel_rust_server::errors",
+//!  "message":"Request failed","kind":"bridge_timeout","status":504,
+//!  "error":{"message":"PHP worker timed out","sources":["read timed out"]},
+//!  "span":{"request_id":"...","method":"GET","path":"/api/users","client_ip":"127.0.0.1"}}
+//! ```
+//!
+//! Event fields sit at the top level, fields of the enclosing spans are
+//! merged into `span`, and fields recorded as `&dyn Error` keep their whole
+//! `source()` chain as a list.
+
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::{DefaultFields, JsonFields, Writer};
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields, MakeWriter};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("LOG_FORMAT").unwrap_or_default().trim().to_ascii_lowercase().as_str() {
+            "" | "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(anyhow!("Invalid LOG_FORMAT {:?}; expected text or json", other)),
+        }
+    }
+}
+
+/// Formatting layer that writes JSON lines to `writer`
+pub fn json_layer<S, W>(writer: W) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + 'static,
+{
+    tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .fmt_fields(JsonFields::new())
+        .event_format(JsonFormat)
+}
+
+/// Default text field formatting, as a type of its own
+///
+/// Each fmt layer keeps the formatted span fields in the span's extensions,
+/// keyed by its field formatter type. Two text layers sharing
+/// `DefaultFields` would both append to the same entry whenever a field is
+/// recorded later (e.g. the request `status`) and print it twice; giving the
+/// console layer this type keeps the entries apart.
+#[derive(Default)]
+pub struct ConsoleFields(DefaultFields);
+
+impl<'writer> FormatFields<'writer> for ConsoleFields {
+    fn format_fields<R: RecordFields>(&self, writer: Writer<'writer>, fields: R) -> fmt::Result {
+        self.0.format_fields(writer, fields)
+    }
+}
+
+/// Event formatter producing one JSON object per line
+pub struct JsonFormat;
+
+impl<S> FormatEvent<S, JsonFields> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, JsonFields>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+
+        let metadata = event.metadata();
+        let mut object = Map::new();
+        object.insert("timestamp".into(), timestamp.into());
+        object.insert("level".into(), metadata.level().as_str().into());
+        object.insert("target".into(), metadata.target().into());
+
+        let mut fields = JsonVisitor::default();
+        event.record(&mut fields);
+        object.extend(fields.0);
+
+        // JsonFields stores each span's fields as a JSON object
+        let mut span = Map::new();
+        for parent in ctx.event_scope().into_iter().flat_map(|scope| scope.from_root()) {
+            let extensions = parent.extensions();
+            let stored = extensions
+                .get::<FormattedFields<JsonFields>>()
+                .and_then(|stored| serde_json::from_str::<Map<String, Value>>(&stored.fields).ok());
+            span.extend(stored.unwrap_or_default());
+        }
+        if !span.is_empty() {
+            object.insert("span".into(), span.into());
+        }
+
+        writeln!(writer, "{}", Value::Object(object))
+    }
+}
+
+/// Collects event fields as JSON values
+#[derive(Default)]
+struct JsonVisitor(Map<String, Value>);
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        let mut sources = Vec::new();
+        let mut source = value.source();
+        while let Some(cause) = source {
+            sources.push(Value::from(cause.to_string()));
+            source = cause.source();
+        }
+        self.0.insert(
+            field.name().into(),
+            serde_json::json!({ "message": value.to_string(), "sources": sources }),
+        );
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().into(), format!("{:?}", value).into());
+    }
+}
@@ -0,0 +1,189 @@
+//! Rotating log file
+//!
+//! `LOG_ROTATION` selects when `server.log` is rotated: `daily` (the
+//! default), `hourly`, `size:<n>` with an optional K/M/G suffix (e.g.
+//! `size:100MB`) or `never`. On rotation the current file becomes
+//! `server.log.1`, older files move up by one, and files beyond
+//! `LOG_MAX_FILES` are deleted.
+//!
+//! [`RollingFile`] is a plain `Write`; the binary puts it behind
+//! `tracing_appender::non_blocking`, so rotation happens on the single
+//! writer thread while every event stream keeps flowing into the channel.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+
+use crate::worker_limits::parse_size;
+
+/// Default number of rotated files kept
+const DEFAULT_MAX_FILES: usize = 7;
+
+/// When the log file is rotated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationPolicy {
+    Never,
+    Daily,
+    Hourly,
+    /// Rotate before a write would grow the file past this many bytes
+    Size(u64),
+}
+
+impl RotationPolicy {
+    /// Parse a `LOG_ROTATION` value
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "never" => Some(Self::Never),
+            "daily" => Some(Self::Daily),
+            "hourly" => Some(Self::Hourly),
+            value => value
+                .strip_prefix("size:")
+                .and_then(parse_size)
+                .filter(|&bytes| bytes > 0)
+                .map(Self::Size),
+        }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("LOG_ROTATION") {
+            Ok(value) if !value.trim().is_empty() => Self::parse(&value).ok_or_else(|| {
+                anyhow!(
+                    "Invalid LOG_ROTATION {:?}; expected daily, hourly, never or size:<bytes> (e.g. size:100MB)",
+                    value
+                )
+            }),
+            _ => Ok(Self::Daily),
+        }
+    }
+
+    /// Time period `time` falls into, for the time-based policies
+    fn period(&self, time: SystemTime) -> u64 {
+        let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        match self {
+            Self::Daily => secs / 86_400,
+            Self::Hourly => secs / 3_600,
+            Self::Never | Self::Size(_) => 0,
+        }
+    }
+}
+
+/// `LOG_MAX_FILES`: rotated files kept next to the current one
+pub fn max_files_from_env() -> Result<usize> {
+    match std::env::var("LOG_MAX_FILES") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("Invalid LOG_MAX_FILES {:?}; expected a non-negative integer", value)),
+        _ => Ok(DEFAULT_MAX_FILES),
+    }
+}
+
+/// Log file that rotates itself according to a [`RotationPolicy`]
+pub struct RollingFile {
+    path: PathBuf,
+    policy: RotationPolicy,
+    max_files: usize,
+    file: File,
+    /// Bytes in the current file
+    size: u64,
+    /// Period the current file belongs to (time-based policies)
+    period: u64,
+}
+
+impl RollingFile {
+    /// Open `dir/name` for appending
+    ///
+    /// A file left over from a previous period (e.g. yesterday's log after a
+    /// restart) is rotated right away.
+    pub fn open(dir: &Path, name: &str, policy: RotationPolicy, max_files: usize) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(name);
+        let file = open_append(&path)?;
+        let metadata = file.metadata()?;
+
+        let mut rolling = Self {
+            period: policy.period(metadata.modified().unwrap_or_else(|_| SystemTime::now())),
+            size: metadata.len(),
+            path,
+            policy,
+            max_files,
+            file,
+        };
+        if rolling.size > 0 && rolling.period != policy.period(SystemTime::now()) {
+            rolling.rotate()?;
+        }
+        Ok(rolling)
+    }
+
+    fn should_rotate(&self, incoming: usize) -> bool {
+        match self.policy {
+            RotationPolicy::Never => false,
+            RotationPolicy::Size(limit) => self.size > 0 && self.size + incoming as u64 > limit,
+            RotationPolicy::Daily | RotationPolicy::Hourly => self.policy.period(SystemTime::now()) != self.period,
+        }
+    }
+
+    /// Move the current file aside and start a new one
+    pub fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        // Drop the oldest file, plus any left over from a larger LOG_MAX_FILES
+        let mut n = self.max_files.max(1);
+        while self.rotated(n).exists() {
+            fs::remove_file(self.rotated(n))?;
+            n += 1;
+        }
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.max_files).rev() {
+                let from = self.rotated(n);
+                if from.exists() {
+                    fs::rename(&from, self.rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        self.period = self.policy.period(SystemTime::now());
+        Ok(())
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+}
+
+impl Write for RollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.should_rotate(buf.len()) {
+            if let Err(e) = self.rotate() {
+                // Keep logging into the current file rather than losing lines;
+                // the next period or size limit retries
+                eprintln!("Failed to rotate {}: {}", self.path.display(), e);
+                self.size = 0;
+                self.period = self.policy.period(SystemTime::now());
+            }
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
@@ -0,0 +1,206 @@
+//! Throttling of repeated log events
+//!
+//! During an outage every request fails the same way, and logging each one
+//! buries the first, useful line under thousands of copies. Producers of
+//! high-frequency events ask [`log_throttle()`] before logging, with a key
+//! naming the class of event (e.g. the error kind):
+//!
+//! * the first `LOG_THROTTLE_BURST` events of a key are logged as usual and
+//!   open a window of `LOG_THROTTLE_WINDOW_MS`;
+//! * further events in that window are only counted;
+//! * when the window ends, one `Log message repeated` event reports how many
+//!   were suppressed, and the next event is logged immediately again.
+//!
+//! A key that has been quiet for a whole window therefore always has its
+//! next event logged. `LOG_THROTTLE_WINDOW_MS=0` disables throttling.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use tracing::{error, info, warn, Level};
+
+use crate::metrics::{metrics, MetricKind};
+
+/// Default length of a throttling window
+const DEFAULT_WINDOW_MS: u64 = 10_000;
+
+/// Default number of events logged per key and window
+const DEFAULT_BURST: u32 = 1;
+
+static THROTTLE: Lazy<LogThrottle> = Lazy::new(LogThrottle::from_env);
+
+/// Process-wide throttle shared by all producers
+pub fn log_throttle() -> &'static LogThrottle {
+    &THROTTLE
+}
+
+/// Per-key rate limit for log events
+pub struct LogThrottle {
+    window: Duration,
+    burst: u32,
+    windows: Mutex<HashMap<String, Window>>,
+    /// Set while a task is waiting to report the open windows
+    flusher_running: AtomicBool,
+}
+
+/// Events of one key in the current window
+struct Window {
+    started: Instant,
+    logged: u32,
+    suppressed: u64,
+    /// Level of the suppressed events, reused for the summary
+    level: Level,
+    /// Message of the last suppressed event
+    last_message: String,
+}
+
+impl LogThrottle {
+    fn new(window: Duration, burst: u32) -> Self {
+        Self {
+            window,
+            burst: burst.max(1),
+            windows: Mutex::new(HashMap::new()),
+            flusher_running: AtomicBool::new(false),
+        }
+    }
+
+    /// Read `LOG_THROTTLE_WINDOW_MS` and `LOG_THROTTLE_BURST`
+    fn from_env() -> Self {
+        let window_ms = std::env::var("LOG_THROTTLE_WINDOW_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_WINDOW_MS);
+        let burst = std::env::var("LOG_THROTTLE_BURST")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_BURST);
+
+        Self::new(Duration::from_millis(window_ms), burst)
+    }
+
+    /// Whether an event of class `key` should be logged now
+    ///
+    /// A `false` answer counts the event towards the summary, which is
+    /// logged at `level` with `message` once the window ends.
+    pub fn allow(&'static self, key: &str, level: Level, message: &str) -> bool {
+        if self.window.is_zero() {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut windows = self.lock();
+        let expired = match windows.get(key) {
+            Some(window) if now.duration_since(window.started) >= self.window => windows.remove_entry(key),
+            _ => None,
+        };
+
+        let window = windows.entry(key.to_string()).or_insert_with(|| Window {
+            started: now,
+            logged: 0,
+            suppressed: 0,
+            level,
+            last_message: String::new(),
+        });
+        let allowed = window.logged < self.burst;
+        if allowed {
+            window.logged += 1;
+        } else {
+            window.suppressed += 1;
+            window.level = level;
+            window.last_message.clear();
+            window.last_message.push_str(message);
+        }
+        drop(windows);
+
+        // Report the previous window before the event that follows it
+        if let Some((key, window)) = expired {
+            self.report(&key, &window);
+        }
+        if !allowed {
+            count_suppressed(key);
+            self.ensure_flusher();
+        }
+        allowed
+    }
+
+    /// Report and close every window that has ended
+    pub fn flush_expired(&self) {
+        let now = Instant::now();
+        let expired: Vec<(String, Window)> = {
+            let mut windows = self.lock();
+            let keys: Vec<String> = windows
+                .iter()
+                .filter(|(_, window)| now.duration_since(window.started) >= self.window)
+                .map(|(key, _)| key.clone())
+                .collect();
+            keys.into_iter().filter_map(|key| windows.remove_entry(&key)).collect()
+        };
+
+        for (key, window) in expired {
+            self.report(&key, &window);
+        }
+    }
+
+    /// Whether any open window has suppressed events to report
+    fn has_pending(&self) -> bool {
+        self.lock().values().any(|window| window.suppressed > 0)
+    }
+
+    /// Report suppressed events in the background once their window ends
+    ///
+    /// Without a Tokio runtime the summary is logged by the next event of
+    /// the same key instead.
+    fn ensure_flusher(&'static self) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        if self.flusher_running.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        runtime.spawn(async move {
+            loop {
+                tokio::time::sleep(self.window / 4).await;
+                self.flush_expired();
+                if !self.has_pending() {
+                    self.flusher_running.store(false, Ordering::Release);
+                    // A suppression may have slipped in before the flag was reset
+                    if !self.has_pending() || self.flusher_running.swap(true, Ordering::AcqRel) {
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    fn report(&self, key: &str, window: &Window) {
+        if window.suppressed == 0 {
+            return;
+        }
+
+        let window_secs = self.window.as_secs_f64();
+        let repeated = window.suppressed;
+        let last = window.last_message.as_str();
+        match window.level {
+            Level::ERROR => error!(key, repeated, window_secs, last, "Log message repeated {} times in the last {}s", repeated, window_secs),
+            Level::WARN => warn!(key, repeated, window_secs, last, "Log message repeated {} times in the last {}s", repeated, window_secs),
+            _ => info!(key, repeated, window_secs, last, "Log message repeated {} times in the last {}s", repeated, window_secs),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Window>> {
+        self.windows.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn count_suppressed(key: &str) {
+    metrics().describe(
+        "log_events_suppressed_total",
+        MetricKind::Counter,
+        "Log events suppressed by LOG_THROTTLE_WINDOW_MS, by event class",
+    );
+    metrics().inc_counter("log_events_suppressed_total", &[("key", key)]);
+}
@@ -7,114 +7,913 @@
 use anyhow::Result;
 use std::path::Path;
 use std::process::Command;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
-mod bridge;
-mod server;
-mod errors;
-mod config;
-use server::HttpServer;
-use config::AppConfig;
+mod cli;
+use clap::Parser;
+use tracing::{debug, error, info, warn};
+use tracing_appender::non_blocking::WorkerGuard;
+use cli::{BenchArgs, CheckArgs, Cli, Command as CliCommand, ConfigAction, ConfigFormat, ReplayArgs, SendArgs};
+use laravel_rust_server::admin::{AdminConfig, AdminServer, AdminState};
+use laravel_rust_server::admin_artisan::{ArtisanConfig, ArtisanRunner};
+use laravel_rust_server::bench::{self, BenchOptions};
+use laravel_rust_server::bridge::connection_pool::Framing;
+#[cfg(feature = "chaos")]
+use laravel_rust_server::chaos::{self, ChaosConfig};
+use laravel_rust_server::server::{request_frame, HttpRequestPayload};
+use laravel_rust_server::fastcgi::{FastCgiClient, FastCgiConfig};
+#[cfg(feature = "grpc")]
+use laravel_rust_server::grpc::{GrpcConfig, GrpcServer};
+use laravel_rust_server::config_loader::{self, ConfigFile, ConfigLayers, Profile, Provenance, Source};
+use laravel_rust_server::log_format::{json_layer, ConsoleFields, LogFormat};
+use laravel_rust_server::log_rotation::{self, RollingFile, RotationPolicy};
+use laravel_rust_server::recording::{self, RecordedRequest};
+use laravel_rust_server::privileges::{drop_privileges, PrivilegeConfig};
+use laravel_rust_server::self_test::{self, Outcome as SelfTestOutcome, SelfTestConfig};
+use laravel_rust_server::shutdown::{notify_laravel_terminating, Shutdown, ShutdownMode, ShutdownSignals};
+use laravel_rust_server::stall::{self, StallConfig};
+use laravel_rust_server::statsd::{self, StatsdConfig};
+use laravel_rust_server::supervisor::{SupervisorConfig, WorkerSupervisor};
+use laravel_rust_server::telemetry::{self, TelemetryGuard};
+use laravel_rust_server::tenants::{TenantSpec, Tenants};
+use laravel_rust_server::tls::{self, TlsConfig};
+use laravel_rust_server::upgrade::{self, UpgradeConfig, UpgradeRequests, WorkerHandoff};
+use laravel_rust_server::websocket::{BroadcastConfig, BroadcastHub};
+use laravel_rust_server::worker_limits::WorkerLimits;
+use laravel_rust_server::worker_protocol::WorkerProtocol;
+use laravel_rust_server::{build_info, config_validation, fd_limit, hot_reload, AppConfig, HttpServer, SocketBridge};
 
-// Константы для конфигурации (для обратной совместимости)
-const DEFAULT_SOCKET_PATH: &str = "/tmp/rust_php_bridge.sock";
-const SOCKET_WAIT_MAX_ATTEMPTS: usize = 20;
-const SOCKET_WAIT_INTERVAL_MS: u64 = 500;
-const SHUTDOWN_CHECK_INTERVAL_MS: u64 = 100;
+// Счетчик аллокаций для отчета `bench`
+#[cfg(feature = "alloc-stats")]
+#[global_allocator]
+static GLOBAL: bench::CountingAllocator = bench::CountingAllocator;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Инициализируем систему логирования
-    init_logging()?;
+    // Разбираем аргументы командной строки до любых побочных эффектов:
+    // --help и ошибки в аргументах завершают процесс сразу
+    let cli = Cli::parse();
+    if cli.print_default_config {
+        print!("{}", config_loader::render_default_template());
+        return Ok(());
+    }
 
-    // Устанавливаем обработчик сигналов для корректного завершения
-    let running = Arc::new(AtomicBool::new(true));
-    let r = running.clone();
+    // Запоминаем исходное окружение для нового процесса при обновлении бинарника
+    // и забираем сокеты, переданные предыдущим процессом (LISTEN_FDS)
+    upgrade::init();
 
-    ctrlc::set_handler(move || {
-        r.store(false, Ordering::SeqCst);
-        println!("Получен сигнал завершения, останавливаем сервисы...");
-    })
-    .expect("Ошибка при установке обработчика сигналов");
+    // Загружаем .env, затем флаги CLI и файл конфигурации (CONFIG_PATH) под
+    // переменные окружения: CLI > окружение > файл > значения по умолчанию
+    // Для `config show` запоминаем, из какого слоя пришло каждое значение
+    // и сохраняем исходные слои, чтобы заново собрать их по SIGHUP
+    let mut layers = ConfigLayers::capture_process_env();
+    let mut provenance = Provenance::default();
+    provenance.record_present(Source::Env);
+    dotenvy::dotenv().ok();
+    provenance.record_present(Source::DotEnv);
+    let cli_pairs = cli.overrides.env_pairs();
+    cli.overrides.apply_to_env();
+    layers.set_cli(&cli_pairs);
+    let cli_envs: Vec<&str> = cli_pairs.iter().map(|(name, _)| *name).collect();
+    provenance.record_env(&cli_envs, Source::Cli);
+    let config_file = match config_loader::config_path_from_env() {
+        Some(path) => Some(ConfigFile::load(&path)?),
+        None => None,
+    };
+    let applied_from_file = config_file.as_ref().map(|f| f.apply_to_env()).unwrap_or_default();
+    provenance.record(&applied_from_file, Source::File);
 
-    println!("🚀 Запускаем Laravel Rust Bridge...");
+    // Профиль, секреты и длительности меняют окружение, от которого зависит
+    // само логирование; ошибку откладываем и сообщаем о ней уже через tracing
+    let prepared = prepare_environment(&mut provenance);
 
-    // Запускаем PHP worker в отдельном процессе
-    let php_process_result = start_php_worker();
-    match &php_process_result {
-        Ok(_) => println!("✅ PHP worker запущен"),
-        Err(e) => eprintln!("❌ Ошибка запуска PHP worker: {}", e),
+    // `check` запускается каждые несколько секунд: логирование не
+    // инициализируем, чтобы не засорять файл лога
+    if let Some(CliCommand::Check(args)) = &cli.command {
+        let code = match prepared {
+            Ok(_) => run_check(args).await,
+            Err(e) => {
+                eprintln!("unhealthy: invalid configuration: {:#}", e);
+                1
+            }
+        };
+        std::process::exit(code);
     }
 
-    // Загружаем конфигурацию приложения
-    let config = match AppConfig::from_env() {
-        Ok(config) => {
-            if let Err(validation_err) = config.validate() {
-                eprintln!("❌ Ошибка валидации конфигурации: {}", validation_err);
-                return Err(validation_err);
+    // Инициализируем систему логирования; guard держим до выхода из main,
+    // чтобы последние строки лога успели записаться в файл
+    let _log_guards = init_logging()?;
+    let (profile, overridden_in_profile) = match prepared {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            error!(error = %e, "Invalid configuration");
+            return Err(e);
+        }
+    };
+    if let Some(config_file) = &config_file {
+        config_file.log_summary(&applied_from_file);
+    }
+    info!(
+        profile = profile.as_str(),
+        overridden = ?overridden_in_profile,
+        "Using configuration profile"
+    );
+
+    match cli.command.unwrap_or_default() {
+        CliCommand::Serve => serve(layers, profile).await,
+        CliCommand::Config { action } => run_config_command(action, &provenance),
+        CliCommand::Bench(args) => run_bench(args).await,
+        CliCommand::Send(args) => {
+            let code = run_send(args).await;
+            // process::exit не вызывает деструкторы: дописываем лог сами
+            drop(_log_guards);
+            std::process::exit(code)
+        }
+        CliCommand::Replay(args) => {
+            let code = run_replay(args).await;
+            drop(_log_guards);
+            std::process::exit(code)
+        }
+        CliCommand::Check(_) => unreachable!("handled before logging is initialized"),
+    }
+}
+
+/// Проверка состояния экземпляра для health check контейнера (подкоманда `check`)
+///
+/// Запрашивает `/readyz` у локального HTTP-слушателя или, с `--bridge-only`,
+/// отправляет `ping` PHP worker по SOCKET_PATH. Печатает одну строку и
+/// возвращает код выхода: 0 - экземпляр здоров, 1 - нет или не ответил
+/// за `--timeout`.
+async fn run_check(args: &CheckArgs) -> i32 {
+    let check = async {
+        let config = load_config()?;
+        if args.bridge_only {
+            // Worker RoadRunner подключаются к сокету сервера, отдельно к ним не подключиться
+            if WorkerProtocol::from_env().codec().framing() == Framing::Goridge {
+                anyhow::bail!("--bridge-only cannot reach RoadRunner workers, which connect to the server; check /readyz instead");
+            }
+            let socket_bridge = SocketBridge::new_with_config(&config)?;
+            // Сокет принадлежит работающему worker, удалять его нельзя
+            socket_bridge.keep_socket_file();
+            socket_bridge.ping().await?;
+            return Ok(format!("PHP worker at {} answered ping", config.connection.socket_path));
+        }
+
+        // Слушатель на 0.0.0.0/:: проверяем через loopback
+        let host = match config.server.host.parse::<std::net::IpAddr>() {
+            Ok(ip) if ip.is_unspecified() && ip.is_ipv4() => "127.0.0.1".to_string(),
+            Ok(ip) if ip.is_unspecified() => "[::1]".to_string(),
+            Ok(std::net::IpAddr::V6(ip)) => format!("[{}]", ip),
+            _ => config.server.host.clone(),
+        };
+        // С TLS_ENABLED слушатель принимает только HTTPS; сертификат выписан
+        // на публичное имя, поэтому по loopback он не проверяется
+        let tls = TlsConfig::from_env()
+            .map_err(|problems| config_validation::settings_error("TLS settings", problems))?;
+        let scheme = if tls.is_some() { "https" } else { "http" };
+        let url = format!("{}://{}:{}/readyz", scheme, host, config.server.port);
+        let response = if tls.is_some() {
+            tls::get_unverified(&url.parse()?).await
+        } else {
+            hyper::Client::new().get(url.parse()?).await.map_err(Into::into)
+        }
+        .map_err(|e| anyhow::anyhow!("{}: {:#}", url, e))?;
+        anyhow::ensure!(response.status().is_success(), "{} returned {}", url, response.status());
+        Ok(format!("{} returned {}", url, response.status()))
+    };
+
+    match tokio::time::timeout(args.timeout, check).await {
+        Ok(Ok(message)) => {
+            println!("healthy: {}", message);
+            0
+        }
+        Ok(Err(e)) => {
+            eprintln!("unhealthy: {:#}", e);
+            1
+        }
+        Err(_) => {
+            eprintln!("unhealthy: no answer within {:?}", args.timeout);
+            1
+        }
+    }
+}
+
+/// Отправка одной команды или HTTP-фрейма worker (подкоманда `send`)
+///
+/// Печатает `PhpResponse` в stdout. Код выхода: 0 при `success: true`,
+/// 1 при `success: false`, 2 если ответа нет (конфигурация, сокет, таймаут).
+async fn run_send(args: SendArgs) -> i32 {
+    let response = async {
+        let config = load_config()?;
+        let socket_bridge = SocketBridge::new_with_config(&config)?;
+        // Сокет принадлежит чужому worker, удалять его нельзя
+        socket_bridge.keep_socket_file();
+
+        let request = async {
+            match (&args.http, &args.command) {
+                (Some(http), _) => {
+                    let payload = HttpRequestPayload::synthetic(&http[0], &http[1], None);
+                    let frame = request_frame(payload, &format!("cli-{}", std::process::id()));
+                    socket_bridge.send_http_request_within(frame, args.timeout).await
+                }
+                (None, Some(command)) => {
+                    let data = args.data.clone().map(|data| data.into_iter().collect());
+                    socket_bridge.send_command(command, data).await
+                }
+                (None, None) => unreachable!("clap requires a command or --http"),
+            }
+        };
+        tokio::time::timeout(args.timeout, request)
+            .await
+            .map_err(|_| anyhow::anyhow!("no response within {:?}", args.timeout))?
+    }
+    .await;
+
+    match response {
+        Ok(response) => {
+            match serde_json::to_string_pretty(&response) {
+                Ok(json) => println!("{}", json),
+                Err(_) => println!("{:?}", response),
+            }
+            if response.success { 0 } else { 1 }
+        }
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            2
+        }
+    }
+}
+
+/// Повторная отправка записанного запроса worker (подкоманда `replay`)
+///
+/// Печатает новый `PhpResponse` в stdout, а в stderr - отличия от ответа,
+/// сохраненного в записи. Коды выхода те же, что у `send`.
+async fn run_replay(args: ReplayArgs) -> i32 {
+    let response = async {
+        let mut recorded = RecordedRequest::load(&args.file)?;
+        for (name, value) in &args.headers {
+            recorded.payload.headers.retain(|existing, _| !existing.eq_ignore_ascii_case(name));
+            recorded.payload.headers.insert(name.clone(), value.clone());
+            recorded.redacted_headers.retain(|redacted| !redacted.eq_ignore_ascii_case(name));
+        }
+        if !recorded.redacted_headers.is_empty() {
+            recorded.redacted_headers.sort();
+            eprintln!(
+                "warning: sending redacted header(s) as {}: {} (override with --header)",
+                recording::REDACTED,
+                recorded.redacted_headers.join(", ")
+            );
+        }
+        if recorded.body_truncated {
+            eprintln!("warning: the recorded body was cut at RECORD_MAX_BODY_BYTES, the replayed one is incomplete");
+        }
+
+        let config = load_config()?;
+        let socket_bridge = SocketBridge::new_with_config(&config)?;
+        // Сокет принадлежит чужому worker, удалять его нельзя
+        socket_bridge.keep_socket_file();
+
+        let frame = request_frame(recorded.payload, &format!("replay-{}", recorded.request_id));
+        let response = tokio::time::timeout(args.timeout, socket_bridge.send_http_request_within(frame, args.timeout))
+            .await
+            .map_err(|_| anyhow::anyhow!("no response within {:?}", args.timeout))??;
+        Ok::<_, anyhow::Error>((response, recorded.bridge_response))
+    }
+    .await;
+
+    match response {
+        Ok((response, recorded)) => {
+            match serde_json::to_string_pretty(&response) {
+                Ok(json) => println!("{}", json),
+                Err(_) => println!("{:?}", response),
+            }
+            match recorded {
+                Some(recorded) => {
+                    let replayed = serde_json::to_value(&response).unwrap_or_default();
+                    let differences = recording::compare_frames(&recorded, &replayed);
+                    if differences.is_empty() {
+                        eprintln!("same response as recorded");
+                    } else {
+                        eprintln!("differs from the recording:");
+                        for difference in differences {
+                            eprintln!("  {}", difference);
+                        }
+                    }
+                }
+                None => eprintln!("the recording has no worker response to compare with"),
             }
-            config
+            if response.success { 0 } else { 1 }
+        }
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            2
+        }
+    }
+}
+
+/// Нагрузочный прогон пути запроса через мост (подкоманда `bench`)
+///
+/// PHP worker не запускается: нагрузка идет на уже работающий worker по
+/// SOCKET_PATH, отчет печатается в stdout.
+async fn run_bench(args: BenchArgs) -> Result<()> {
+    let config = load_config()?;
+    let socket_bridge = SocketBridge::new_with_config(&config)?;
+    // Сокет принадлежит чужому worker, удалять его нельзя
+    socket_bridge.keep_socket_file();
+    let options = BenchOptions {
+        concurrency: args.concurrency as usize,
+        duration: Duration::from_secs(args.duration),
+        payload_size: args.payload_size,
+        path: args.path,
+    };
+
+    info!(socket_path = %config.connection.socket_path, ?options, "Starting bridge benchmark");
+    let report = bench::run(socket_bridge.clone(), options).await;
+    socket_bridge.cleanup().await;
+    println!("{}", report);
+    Ok(())
+}
+
+/// Применение профиля, секретов из файлов и нормализация длительностей
+///
+/// Выполняется до инициализации логирования, поэтому сама ничего не пишет:
+/// ошибка возвращается и логируется вызывающим кодом.
+///
+/// # Arguments
+///
+/// * `provenance` - источники значений, дополняемые профилем и секретами
+///
+/// # Returns
+///
+/// * `Ok((Profile, overridden))` - активный профиль и переопределенные в нем ключи
+/// * `Err` - если профиль, файл секрета или длительность некорректны
+fn prepare_environment(provenance: &mut Provenance) -> Result<(Profile, Vec<&'static str>)> {
+    // Профиль APP_PROFILE задает согласованные значения по умолчанию
+    // для всего, что не задано CLI, окружением или файлом
+    let profile = Profile::parse(std::env::var("APP_PROFILE").ok().as_deref())?;
+    let (applied_from_profile, overridden_in_profile) = profile.apply_to_env();
+    provenance.record(&applied_from_profile, Source::Profile);
+
+    // Секреты могут быть переданы файлом через <NAME>_FILE (Docker/Kubernetes secrets)
+    let loaded = config_loader::resolve_secret_files()?;
+    provenance.record(&loaded, Source::SecretFile);
+
+    // Длительности вида "250ms", "5s", "2m" приводим к целым числам в единицах настройки
+    config_loader::normalize_durations()?;
+
+    Ok((profile, overridden_in_profile))
+}
+
+/// Подкоманды `config show` и `config validate`
+///
+/// Загружают `AppConfig` так же, как `serve`, но не запускают сервисы.
+///
+/// # Arguments
+///
+/// * `action` - выбранная подкоманда
+/// * `provenance` - источники значений, собранные при загрузке слоев
+///
+/// # Returns
+///
+/// * `Ok(())` - если конфигурация загружена (и валидна для `validate`)
+/// * `Err` - если конфигурацию не удалось загрузить
+fn run_config_command(action: ConfigAction, provenance: &Provenance) -> Result<()> {
+    match action {
+        ConfigAction::Show { format } => {
+            AppConfig::from_env()?;
+            let resolved = provenance.resolve();
+            let rendered = match format {
+                ConfigFormat::Toml => config_loader::render_toml(&resolved),
+                ConfigFormat::Json => config_loader::render_json(&resolved),
+            };
+            println!("{}", rendered);
         }
+        ConfigAction::Validate => match load_config() {
+            Ok(_) => {
+                println!("✅ Конфигурация корректна");
+                print_fd_estimate();
+            }
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                std::process::exit(1);
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Оценка числа файловых дескрипторов для конфигурации и текущий лимит
+fn print_fd_estimate() {
+    let estimate = fd_limit::FdEstimate::from_env();
+    let needed = estimate.total();
+    match fd_limit::current_limits() {
+        Ok((soft, hard)) if soft < needed => println!(
+            "⚠️ Лимит открытых файлов {} (жесткий {}) ниже оценки {}: {}",
+            soft, hard, needed, estimate.to_json()
+        ),
+        Ok((soft, hard)) => println!(
+            "✅ Лимит открытых файлов {} (жесткий {}), оценка {}: {}",
+            soft, hard, needed, estimate.to_json()
+        ),
+        Err(e) => println!("⚠️ Не удалось прочитать RLIMIT_NOFILE ({}), оценка {}", e, needed),
+    }
+}
+
+/// Загрузка и проверка конфигурации приложения
+///
+/// Сначала проверяет значения в окружении и собирает все ошибки сразу,
+/// затем загружает `AppConfig` и выполняет его собственную валидацию.
+///
+/// # Returns
+///
+/// * `Ok(AppConfig)` - если конфигурация корректна
+/// * `Err` - список всех найденных проблем или ошибка загрузки
+fn load_config() -> Result<AppConfig> {
+    config_validation::validate_environment()?;
+    let config = AppConfig::from_env()?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Запуск HTTP сервера и PHP worker (подкоманда `serve`)
+///
+/// Работает до получения SIGINT/SIGTERM/SIGQUIT, после чего корректно
+/// останавливает сервер, PHP worker и соединения моста. По SIGHUP
+/// перечитывает конфигурацию и применяет изменения, не требующие перезапуска.
+///
+/// # Arguments
+///
+/// * `layers` - исходные слои конфигурации для перезагрузки по SIGHUP
+/// * `profile` - активный профиль конфигурации, для стартового сообщения
+///
+/// # Returns
+///
+/// * `Ok(())` - если сервер штатно завершил работу
+/// * `Err` - если конфигурация некорректна или сервер не удалось запустить
+async fn serve(layers: ConfigLayers, profile: Profile) -> Result<()> {
+    // Подписываемся на сигналы завершения до запуска сервисов,
+    // чтобы SIGTERM во время старта не убил процесс без очистки
+    let shutdown_signals = ShutdownSignals::new()?;
+    // SIGUSR1 и POST /admin/upgrade запускают обновление бинарника
+    let mut upgrades = UpgradeRequests::new()?;
+    upgrade::enable_handoff();
+    let _sighup_handler = hot_reload::spawn_sighup_handler(layers)?;
+
+    // Загружаем конфигурацию приложения
+    let config = match load_config() {
+        Ok(config) => config,
         Err(e) => {
-            eprintln!("❌ Ошибка загрузки конфигурации: {}", e);
+            error!(error = %e, "Configuration validation failed");
             return Err(e);
         }
     };
 
-    // Проверяем, что сокет создан и готов к использованию
-    let _ = wait_for_php_worker(&config.connection.socket_path);
+    // По умолчанию слушаем порт сразу, а готовность PHP worker проверяем параллельно:
+    // до ее подтверждения запросы к Laravel получают быстрый 503.
+    // STARTUP_BLOCK_UNTIL_READY=true возвращает прежнее блокирующее ожидание.
+    let block_until_ready = std::env::var("STARTUP_BLOCK_UNTIL_READY")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false);
+    // SELF_TEST=true: готовность только после успешного запроса через весь конвейер
+    let self_test = SelfTestConfig::from_env()
+        .map_err(|problems| config_validation::settings_error("self-test settings", problems))?;
+    let self_test_failed = Arc::new(tokio::sync::Notify::new());
+
+    // Супервизор PHP worker; сам процесс запускается после сброса привилегий
+    let supervisor_config = SupervisorConfig::from_env();
+
+    // BACKEND=fastcgi: запросы идут в php-fpm, PHP worker не запускается
+    let fastcgi = FastCgiConfig::from_env().map(|config| Arc::new(FastCgiClient::new(config)));
+
+    // Одно сводное событие вместо россыпи стартовых строк
+    let build = build_info();
+    info!(
+        version = build.version,
+        git_sha = build.git_sha,
+        built = build.build_timestamp,
+        bind = %format!("{}:{}", config.server.host, config.server.port),
+        socket_path = %config.connection.socket_path,
+        backend = fastcgi.as_ref().map_or("worker".to_string(), |f| format!("fastcgi {}", f.config().address)),
+        worker_auto_restart = supervisor_config.auto_restart,
+        worker_startup = if block_until_ready { "blocking" } else { "background" },
+        profile = profile.as_str(),
+        "🚀 Starting Laravel Rust Bridge"
+    );
+    let inherited = upgrade::inherited_names();
+    if !inherited.is_empty() {
+        info!(descriptors = ?inherited, "🔄 Started by a binary upgrade, adopting the previous process's sockets");
+    }
+
+    // Лимит открытых файлов сверяем с оценкой из конфигурации до открытия сокетов
+    // (RAISE_NOFILE=true поднимает мягкий лимит до жесткого)
+    fd_limit::check_at_startup();
+
+    // Экспорт метрик в DogStatsD (STATSD_ADDR); без агента сервер работает как обычно
+    if let Some(statsd_config) = StatsdConfig::from_env() {
+        if let Err(e) = statsd::install(statsd_config) {
+            warn!(error = %e, "StatsD export disabled");
+        }
+    }
+
+    // Внесение отказов для проверки устойчивости (feature `chaos`, CHAOS_ENABLED)
+    #[cfg(feature = "chaos")]
+    chaos::install(ChaosConfig::from_env());
+
+    let supervisor = WorkerSupervisor::new(supervisor_config, Box::new(start_php_worker));
 
     // Создаем и запускаем Rust HTTP сервер
-    let socket_bridge = match crate::bridge::socket_bridge::SocketBridge::new_with_config(&config) {
+    let socket_bridge = match SocketBridge::new_with_config(&config) {
         Ok(bridge) => bridge,
         Err(e) => {
-            eprintln!("Ошибка инициализации SocketBridge: {}", e);
-            return Err(e.into());
+            error!(error = %e, "Failed to initialize SocketBridge");
+            return Err(e);
+        }
+    };
+    // Следим за symlink сокета для blue/green деплоя PHP worker
+    let _swap_watcher = socket_bridge.spawn_swap_watcher();
+    // SOCKET_DISCOVERY_DIR: worker запускаются снаружи, каждый со своим сокетом в каталоге
+    let _discovery = socket_bridge.spawn_discovery();
+    let discovery = socket_bridge.backends().is_some();
+    // WORKER_PROTOCOL=psr7: worker RoadRunner запускаются снаружи и сами подключаются к SOCKET_PATH
+    let relay = socket_bridge.codec().framing() == Framing::Goridge;
+    // Пока PHP worker перезапускается, запросы ждут его (RESTART_PARK_TIMEOUT_MS), а не получают 503
+    let restarting_bridge = socket_bridge.clone();
+    supervisor.on_restart(Box::new(move |reason| restarting_bridge.restarting(reason.as_str())));
+
+    // Другие Laravel-приложения под префиксами путей (TENANTS); их worker запускаются снаружи
+    let tenants = match TenantSpec::from_env()
+        .map_err(|problems| config_validation::settings_error("tenants", problems))
+        .and_then(|specs| Tenants::new(specs, &config))
+    {
+        Ok(tenants) => Arc::new(tenants),
+        Err(e) => {
+            error!(error = %e, "Failed to initialize tenants");
+            return Err(e);
         }
     };
-    println!("✅ Rust HTTP сервер готов к работе");
+
+    // Рассылка broadcast-событий Laravel по WebSocket (WS_ENABLED)
+    let broadcast = BroadcastConfig::from_env().map(|config| BroadcastHub::new(config, socket_bridge.clone()));
 
     let server = match HttpServer::new_with_config(socket_bridge.clone(), &config).await {
-        Ok(server) => server,
+        Ok(server) => match &fastcgi {
+            Some(fastcgi) => server.with_fastcgi(fastcgi.clone()),
+            None => server,
+        },
         Err(e) => {
-            eprintln!("Ошибка инициализации HTTP сервера: {}", e);
-            return Err(e.into());
+            error!(error = %e, "Failed to initialize HTTP server");
+            return Err(e);
         }
     };
-    println!("✅ Rust HTTP сервер готов к работе");
+    let server = match &broadcast {
+        Some(hub) => server.with_broadcast(hub.clone()),
+        None => server,
+    };
+    let server = server.with_tenants(tenants.clone());
+    // SIGUSR2 включает и выключает режим drain, не останавливая PHP worker
+    let _sigusr2_handler = server.drain().spawn_signal_handler()?;
+    // Занимаем порты, пока у процесса еще есть права root (для :80/:443)
+    if let Err(e) = server.bind() {
+        error!(error = %e, "Failed to bind HTTP server");
+        return Err(e);
+    }
+    let admin_config = AdminConfig::from_env();
+    let admin_server = if admin_config.enabled {
+        let admin_server = AdminServer::new(admin_config, AdminState {
+            supervisor: supervisor.clone(),
+            socket_bridge: socket_bridge.clone(),
+            tenants: tenants.clone(),
+            upgrade: Some(upgrades.trigger()),
+            artisan: ArtisanRunner::new(ArtisanConfig::from_env()),
+            drain: server.drain(),
+            info: server.runtime_info(),
+            file_cache: server.file_cache(),
+            public_dir: server.public_dir().to_string(),
+        });
+        admin_server.bind()?;
+        Some(admin_server)
+    } else {
+        None
+    };
+    // gRPC-сервис Bridge для других сервисов (feature `grpc`, GRPC_ENABLED)
+    #[cfg(feature = "grpc")]
+    let grpc_server = {
+        let grpc_config = GrpcConfig::from_env();
+        if grpc_config.enabled {
+            let grpc_server = GrpcServer::new(
+                grpc_config,
+                socket_bridge.clone(),
+                supervisor.clone(),
+                tenants.clone(),
+                server.public_dir().to_string(),
+            );
+            grpc_server.bind()?;
+            Some(grpc_server)
+        } else {
+            None
+        }
+    };
+
+    // Сбрасываем права до RUN_AS_USER/RUN_AS_GROUP до приема соединений и запуска PHP
+    if let Err(e) = drop_privileges(&PrivilegeConfig::from_env()) {
+        error!(error = %e, "Failed to drop privileges");
+        return Err(e);
+    }
+
+    // Сокет для событий от Laravel создаем уже без прав root, чтобы PHP мог в него писать
+    let push_listener = match broadcast.as_ref().map(|hub| hub.spawn_push_listener()) {
+        Some(Ok(listener)) => Some(listener),
+        Some(Err(e)) => {
+            error!(error = %e, "Failed to listen for broadcast events");
+            return Err(e);
+        }
+        None => None,
+    };
+
+    // Запускаем PHP worker в отдельном процессе под наблюдением супервизора;
+    // он наследует уже непривилегированного пользователя
+    let supervisor_handle = if fastcgi.is_none() && !discovery && !relay {
+        // После обновления бинарника продолжаем работать с worker предыдущего процесса
+        match upgrade::take_inherited_worker() {
+            Some(pid) => {
+                supervisor.adopt(pid);
+                info!(pid, "✅ PHP worker taken over from the previous process");
+            }
+            None => match supervisor.start() {
+                Ok(_) => info!(pid = supervisor.pid(), "✅ PHP worker started"),
+                Err(e) => error!(error = %e, "Failed to start PHP worker"),
+            },
+        }
+        Some(supervisor.spawn_monitor())
+    } else {
+        None
+    };
+    // Worker, который не отвечает при запросах в работе и не проходит ping, перезапускаем
+    let stall_watchdog = (fastcgi.is_none() && !discovery && !relay)
+        .then(|| stall::spawn_watchdog(StallConfig::from_env(), socket_bridge.clone(), supervisor.clone()))
+        .flatten();
+
+    // Готовность каждого приложения из TENANTS проверяем отдельно
+    let wait_interval = config_validation::socket_wait_interval();
+    tenants.spawn_readiness_probes(wait_interval);
+
+    let readiness = server.readiness();
+    let bridge_ready = readiness.clone();
+    let upgrade_ready = readiness.clone();
+
+    if let Some(fastcgi) = fastcgi.clone() {
+        // php-fpm управляется снаружи: ждем, пока он начнет принимать соединения
+        let wait = async move {
+            while let Err(e) = fastcgi.probe().await {
+                debug!(error = %e, "php-fpm not reachable yet");
+                tokio::time::sleep(wait_interval).await;
+            }
+            readiness.store(true, Ordering::Release);
+            info!("✅ php-fpm reachable, proxying requests");
+        };
+        if block_until_ready {
+            wait.await;
+        } else {
+            tokio::spawn(wait);
+        }
+    } else if discovery {
+        // Ждем, пока в SOCKET_DISCOVERY_DIR появится отвечающий worker
+        let bridge = socket_bridge.clone();
+        let wait = async move {
+            bridge.wait_for_backend(wait_interval).await;
+            readiness.store(true, Ordering::Release);
+            info!("✅ PHP worker discovered, proxying requests");
+        };
+        if block_until_ready {
+            wait.await;
+        } else {
+            tokio::spawn(wait);
+        }
+    } else if relay {
+        // К своему же сокету не подключаемся: ждем, пока подключившийся worker ответит на ping
+        let bridge = socket_bridge.clone();
+        let wait = async move {
+            while let Err(e) = bridge.ping_direct(bridge.read_timeout()).await {
+                debug!(error = %e, "No RoadRunner worker connected yet");
+                tokio::time::sleep(wait_interval).await;
+            }
+            readiness.store(true, Ordering::Release);
+            info!("✅ RoadRunner worker connected, proxying requests");
+        };
+        if block_until_ready {
+            wait.await;
+        } else {
+            tokio::spawn(wait);
+        }
+    } else if block_until_ready {
+        // Проверяем, что сокет создан и готов к использованию
+        let _ = wait_for_php_worker(&config.connection.socket_path);
+        match self_test::run(&self_test, &socket_bridge).await {
+            SelfTestOutcome::Passed => readiness.store(true, Ordering::Release),
+            SelfTestOutcome::Abort => {
+                supervisor.shutdown();
+                return Err(anyhow::anyhow!("Startup self-test of {} failed", self_test.path));
+            }
+            SelfTestOutcome::Degraded => {
+                tokio::spawn(self_test::retry_until_passed(self_test.clone(), socket_bridge.clone(), readiness));
+            }
+        }
+    } else {
+        let socket_path = config.connection.socket_path.clone();
+        let self_test = self_test.clone();
+        let self_test_bridge = socket_bridge.clone();
+        let self_test_failed = self_test_failed.clone();
+        tokio::spawn(async move {
+            loop {
+                let path = socket_path.clone();
+                let result = tokio::task::spawn_blocking(move || wait_for_php_worker(&path)).await;
+                if matches!(result, Ok(Ok(()))) {
+                    match self_test::run(&self_test, &self_test_bridge).await {
+                        SelfTestOutcome::Passed => {
+                            readiness.store(true, Ordering::Release);
+                            info!("✅ PHP worker ready, proxying requests");
+                        }
+                        SelfTestOutcome::Abort => self_test_failed.notify_one(),
+                        SelfTestOutcome::Degraded => {
+                            self_test::retry_until_passed(self_test, self_test_bridge, readiness).await
+                        }
+                    }
+                    break;
+                }
+            }
+        });
+    }
 
     // Запускаем HTTP сервер
-    let server_handle = tokio::spawn(async move {
-        if let Err(e) = server.start().await {
-            eprintln!("Ошибка в HTTP сервере: {}", e);
+    let shutdown = Shutdown::new();
+    let server_shutdown = shutdown.signal();
+    let mut server_handle = tokio::spawn(async move {
+        // Сюда доходят только фатальные ошибки слушателя: нехватку
+        // дескрипторов и оборванные соединения переживает цикл accept
+        if let Err(e) = server.start_with_shutdown(server_shutdown).await {
+            error!(error = format!("{:#}", e), "HTTP server failed");
             std::process::exit(1);
         }
     });
 
-    // Ждем сигнал завершения
-    while running.load(Ordering::SeqCst) {
-        thread::sleep(config.connection.shutdown_check_interval);
+    // gRPC-сервер останавливается вместе с HTTP сервером
+    #[cfg(feature = "grpc")]
+    let mut grpc_handle = grpc_server.map(|grpc_server| {
+        let grpc_shutdown = shutdown.signal();
+        tokio::spawn(async move {
+            if let Err(e) = grpc_server.start_with_shutdown(grpc_shutdown).await {
+                error!(error = %e, "gRPC server failed");
+            }
+        })
+    });
+
+    // Запускаем admin-сервер (статистика, метрики), если он включен
+    if let Some(admin_server) = admin_server {
+        tokio::spawn(async move {
+            if let Err(e) = admin_server.start().await {
+                error!(error = %e, "Admin server failed");
+            }
+        });
+    }
+
+    // Запущены процессом, который обновляется: сообщаем ему о готовности,
+    // как только запросы к Laravel начнут проходить
+    if upgrade::is_successor() {
+        tokio::spawn(async move {
+            while !upgrade_ready.load(Ordering::Acquire) {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            upgrade::notify_ready();
+            info!("🔄 Reported readiness to the previous process");
+        });
+    }
+
+    // Ждем сигнал завершения или успешное обновление бинарника
+    let shutdown_requested = shutdown_signals.recv();
+    tokio::pin!(shutdown_requested);
+    let (mode, signal_name, upgrade) = loop {
+        tokio::select! {
+            (mode, signal_name) = &mut shutdown_requested => break (mode, signal_name, None),
+            // Самопроверка не прошла при SELF_TEST_ON_FAILURE=abort
+            _ = self_test_failed.notified() => break (ShutdownMode::Fast, "self-test", None),
+            request = upgrades.recv() => {
+                info!(source = request.source(), "🔄 Binary upgrade requested");
+                let outcome = match UpgradeConfig::from_env() {
+                    Ok(config) => {
+                        let worker_pid = supervisor.pid().filter(|_| config.worker == WorkerHandoff::Handover);
+                        upgrade::spawn_successor(&config, worker_pid).await.map(|pid| (pid, config.worker))
+                    }
+                    Err(e) => Err(e),
+                };
+                request.reply(outcome.as_ref().map(|(pid, _)| *pid).map_err(|e| format!("{:#}", e)));
+                match outcome {
+                    Ok((pid, worker)) => break (ShutdownMode::Graceful, "upgrade", Some((pid, worker))),
+                    Err(e) => error!(error = %format!("{:#}", e), "❌ Binary upgrade failed, continuing to serve"),
+                }
+            }
+        }
+    };
+    // Новые запросы на обновление после этого получают отказ
+    drop(upgrades);
+    // Во время остановки worker дорабатывает запросы и не перезапускается
+    if let Some(stall_watchdog) = &stall_watchdog {
+        stall_watchdog.abort();
+    }
+    let drain_timeout = mode.drain_timeout();
+    match upgrade {
+        Some((pid, worker)) => {
+            info!(
+                successor_pid = pid,
+                worker = worker.as_str(),
+                drain_timeout_ms = drain_timeout.as_millis() as u64,
+                "🔄 New process is serving, handing over"
+            );
+            // Новый процесс уже следит за worker: перестаем перезапускать его сами
+            if worker == WorkerHandoff::Handover {
+                info!(pid = supervisor.release(), "🔄 Leaving the PHP worker to the new process");
+            }
+        }
+        None => info!(
+            signal = signal_name,
+            mode = mode.as_str(),
+            drain_timeout_ms = drain_timeout.as_millis() as u64,
+            "Shutdown signal received"
+        ),
+    }
+
+    // Даем Laravel выполнить terminating-хуки, пока мост еще жив; при обновлении
+    // Laravel продолжает работать, а сокет может уже вести к worker нового процесса
+    if upgrade.is_some() {
+        info!("⏭️ Binary upgrade, skipping the terminating notification");
+    } else if bridge_ready.load(Ordering::Acquire) && supervisor.pid().is_some() {
+        notify_laravel_terminating(&socket_bridge).await;
+    } else {
+        info!("⏭️ PHP worker unavailable, skipping the terminating notification");
     }
 
-    // Завершаем PHP процесс
-    if let Ok(mut proc) = php_process_result {
-        println!("🛑 Останавливаем PHP worker...");
-        let _ = proc.kill();
-        let _ = proc.wait();
+    // Завершаем сервер: перестаем принимать соединения и ждем текущие запросы
+    info!("🛑 Stopping Rust HTTP server");
+    shutdown.trigger();
+    // WebSocket-соединения не относятся к HTTP серверу после upgrade, закрываем их отдельно
+    if let Some(hub) = &broadcast {
+        hub.close_all();
+    }
+    let drain_deadline = tokio::time::Instant::now() + drain_timeout;
+    if tokio::time::timeout_at(drain_deadline, &mut server_handle).await.is_err() {
+        warn!(
+            drain_timeout_ms = drain_timeout.as_millis() as u64,
+            "⚠️ Requests still running after the drain timeout, aborting them"
+        );
+        server_handle.abort();
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_handle) = &mut grpc_handle {
+        if tokio::time::timeout_at(drain_deadline, &mut *grpc_handle).await.is_err() {
+            warn!(
+                drain_timeout_ms = drain_timeout.as_millis() as u64,
+                "⚠️ gRPC calls still running after the drain timeout, aborting them"
+            );
+            grpc_handle.abort();
+        }
     }
 
-    // Завершаем сервер
-    println!("🛑 Останавливаем Rust HTTP сервер...");
+    if let Some(hub) = &broadcast {
+        if tokio::time::timeout_at(drain_deadline, hub.closed()).await.is_err() {
+            warn!(connections = hub.connections(), "⚠️ WebSocket clients still connected after the drain timeout");
+        }
+        if let Some(push_listener) = push_listener {
+            push_listener.abort();
+        }
+        // Файл сокета уже принадлежит новому процессу
+        if upgrade.is_none() {
+            hub.cleanup();
+        }
+    }
 
-    // Ждем завершения сервера
-    let _ = server_handle.await;
+    // Завершаем PHP процесс, если он не передан новому процессу
+    if !matches!(upgrade, Some((_, WorkerHandoff::Handover))) {
+        info!(pid = supervisor.pid(), "🛑 Stopping PHP worker");
+        supervisor.shutdown();
+    }
+    if let Some(supervisor_handle) = supervisor_handle {
+        supervisor_handle.abort();
+    }
+    // Сокет worker теперь использует новый процесс, не удаляем его
+    if upgrade.is_some() {
+        socket_bridge.keep_socket_file();
+    }
 
-    // Очищаем соединения в SocketBridge
+    // Очищаем соединения в SocketBridge, в том числе к worker из TENANTS
     socket_bridge.cleanup().await;
+    tenants.cleanup().await;
 
+    if signal_name == "self-test" {
+        return Err(anyhow::anyhow!("Startup self-test of {} failed", self_test.path));
+    }
     Ok(())
 }
 
@@ -127,11 +926,12 @@ async fn main() -> Result<()> {
 ///
 /// * `Ok(())` - если логирование успешно инициализировано
 /// * `Err` - если произошла ошибка при настройке логирования
-fn init_logging() -> Result<()> {
+fn init_logging() -> Result<(WorkerGuard, TelemetryGuard)> {
     use std::fs;
     use tracing_subscriber::fmt;
     use tracing_subscriber::EnvFilter;
     use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
+    use tracing_subscriber::reload;
     use tracing_subscriber::Layer;
     use tracing_subscriber::util::SubscriberInitExt;
 
@@ -147,40 +947,60 @@ fn init_logging() -> Result<()> {
     // Создаем директорию для логов, если она не существует
     fs::create_dir_all(&log_dir)?;
 
-    // Создаем файл для логов
-    let log_file = std::fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .append(true)
-        .open(Path::new(&log_dir).join("server.log"))?;
+    // Файл логов с ротацией по времени или размеру; запись идет в отдельном
+    // потоке, поэтому ротация не останавливает обработку запросов
+    let log_file = RollingFile::open(
+        Path::new(&log_dir),
+        "server.log",
+        RotationPolicy::from_env()?,
+        log_rotation::max_files_from_env()?,
+    )?;
+    let (log_writer, log_guard) = tracing_appender::non_blocking(log_file);
 
-    // Настройка фильтрации по уровню логирования
-    let env_filter = EnvFilter::try_from_default_env()
-        .unwrap_or(EnvFilter::new(&format!("laravel-rust-server={},hyper=info", log_level)));
+    // Настройка фильтрации по уровню логирования; фильтр общий для обоих
+    // выводов и может быть заменен по SIGHUP
+    let env_filter = hot_reload::build_log_filter(&log_level)
+        .unwrap_or_else(|_| EnvFilter::new(hot_reload::log_filter_directive("info")));
+    let (env_filter, filter_handle) = reload::Layer::new(env_filter);
+
+    // LOG_FORMAT=json: одна JSON-строка на событие в обоих выводах
+    let text = LogFormat::from_env()? == LogFormat::Text;
 
     // Настройка форматирования логов в файл
-    let file_layer = fmt::layer()
-        .with_writer(log_file)
-        .with_ansi(false) // Отключаем цвета в файле
-        .with_target(true)
-        .with_line_number(true)
-        .with_filter(env_filter.clone()); // Клонируем фильтр для использования в нескольких слоях
+    let file_layer = text.then(|| {
+        fmt::layer()
+            .with_writer(log_writer.clone())
+            .with_ansi(false) // Отключаем цвета в файле
+            .with_target(true)
+            .with_line_number(true)
+    });
 
     // Настройка консольного вывода
-    let stdout_layer = fmt::layer()
-        .with_writer(std::io::stderr)
-        .with_ansi(true)
-        .with_target(true)
-        .with_line_number(true)
-        .with_filter(env_filter); // Используем оригинальный фильтр
+    let stdout_layer = text.then(|| {
+        fmt::layer()
+            .with_writer(std::io::stderr)
+            .fmt_fields(ConsoleFields::default())
+            .with_ansi(true)
+            .with_target(true)
+            .with_line_number(true)
+    });
+
+    let json_layers = (!text).then(|| json_layer(log_writer).and_then(json_layer(std::io::stderr)));
+
+    // Экспорт трейсов по OTLP (feature `otel` и OTEL_EXPORTER_OTLP_ENDPOINT)
+    let (otel_layer, telemetry_guard) = telemetry::layer()?;
 
     // Инициализируем глобальный subscriber с обеими записями
     tracing_subscriber::registry()
+        .with(env_filter)
         .with(file_layer)
         .with(stdout_layer)
+        .with(json_layers)
+        .with(otel_layer)
         .init();
+    hot_reload::set_log_filter_handle(filter_handle);
 
-    Ok(())
+    Ok((log_guard, telemetry_guard))
 }
 
 /// Ожидание готовности PHP worker
@@ -209,13 +1029,13 @@ fn wait_for_php_worker(socket_path: &str) -> Result<()> {
         .parse()
         .unwrap_or(250);
 
-    println!("⏳ Ожидаем готовности PHP worker и сокета...");
+    info!(socket_path, "⏳ Waiting for the PHP worker socket");
     while attempts < max_attempts {
         if std::path::Path::new(socket_path).exists() {
             // Проверяем, можно ли подключиться к сокету
             match std::os::unix::net::UnixStream::connect(socket_path) {
                 Ok(_) => {
-                    println!("✅ Сокет PHP worker готов к использованию");
+                    info!(socket_path, "✅ PHP worker socket accepts connections");
                     return Ok(());
                 }
                 Err(_) => {
@@ -230,7 +1050,11 @@ fn wait_for_php_worker(socket_path: &str) -> Result<()> {
         }
     }
 
-    eprintln!("⚠️ PHP worker не готов к подключению в течение {} секунд", (max_attempts * interval) / 1000);
+    warn!(
+        socket_path,
+        waited_ms = max_attempts * interval,
+        "⚠️ PHP worker socket not ready in time"
+    );
     Err(anyhow::anyhow!("PHP worker не готов к подключению"))
 }
 
@@ -271,9 +1095,25 @@ fn start_php_worker() -> Result<std::process::Child> {
     let mut cmd = Command::new(&php_path);
     cmd.arg(&artisan_path).arg(&startup_command).current_dir(&laravel_path); // Устанавливаем директорию в корень Laravel проекта
 
-    let child = cmd
+    // Ограничения ресурсов (nice, rlimit, cgroup) применяются к дочернему процессу
+    let limits = WorkerLimits::from_env();
+    limits.apply_to_command(&mut cmd);
+
+    debug!(
+        php = %php_path,
+        artisan = %artisan_path.display(),
+        command = %startup_command,
+        "Spawning PHP worker"
+    );
+    let mut child = cmd
         .spawn()
         .map_err(|e| anyhow::anyhow!("Ошибка при запуске PHP worker: {}", e))?;
 
+    if let Err(e) = limits.after_spawn(&child) {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(e);
+    }
+
     Ok(child)
 }
@@ -0,0 +1,242 @@
+//! Process-wide metrics registry
+//!
+//! Collects counters, gauges and summaries from every part of the bridge and
+//! renders them in the Prometheus text exposition format for the admin
+//! `/metrics` endpoint, or as JSON for the FFI stats. Push-based exporters
+//! (e.g. [`statsd`](crate::statsd)) attach a [`MetricsSink`] and receive
+//! every update as it is recorded.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, RwLock};
+
+use once_cell::sync::Lazy;
+use serde_json::{json, Map, Value};
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+/// Access the global metrics registry
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// Receives every update recorded in the registry
+///
+/// Called on the request path with the registry unlocked, so
+/// implementations must return quickly and never block.
+pub trait MetricsSink: Send + Sync {
+    /// `value` is the increment for counters, the new value for gauges and
+    /// the observation for summaries
+    fn record(&self, name: &'static str, kind: MetricKind, labels: &[(&str, &str)], value: f64);
+}
+
+/// Kind of a metric family, used for the `# TYPE` line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    Summary,
+}
+
+impl MetricKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::Summary => "summary",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Family {
+    kind: Option<MetricKind>,
+    help: Option<&'static str>,
+    /// Rendered label set (`{a="b"}` or empty) -> series
+    series: BTreeMap<String, Series>,
+}
+
+#[derive(Debug, Clone)]
+struct Series {
+    labels: Vec<(String, String)>,
+    value: SeriesValue,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+enum SeriesValue {
+    Value(f64),
+    Summary { sum: f64, count: u64 },
+}
+
+/// Registry holding all metric families
+#[derive(Default)]
+pub struct Metrics {
+    families: Mutex<BTreeMap<&'static str, Family>>,
+    sinks: RwLock<Vec<Arc<dyn MetricsSink>>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forward every later update to `sink` as well
+    pub fn add_sink(&self, sink: Arc<dyn MetricsSink>) {
+        self.sinks.write().unwrap_or_else(|e| e.into_inner()).push(sink);
+    }
+
+    /// Attach HELP text and a type to a metric family
+    pub fn describe(&self, name: &'static str, kind: MetricKind, help: &'static str) {
+        let mut families = self.families.lock().unwrap_or_else(|e| e.into_inner());
+        let family = families.entry(name).or_default();
+        family.kind = Some(kind);
+        family.help = Some(help);
+    }
+
+    /// Increment a counter by one
+    pub fn inc_counter(&self, name: &'static str, labels: &[(&str, &str)]) {
+        self.add_counter(name, labels, 1);
+    }
+
+    /// Increment a counter by an arbitrary amount
+    pub fn add_counter(&self, name: &'static str, labels: &[(&str, &str)], value: u64) {
+        self.update(name, MetricKind::Counter, labels, |current| match current {
+            Some(SeriesValue::Value(v)) => SeriesValue::Value(v + value as f64),
+            _ => SeriesValue::Value(value as f64),
+        });
+        self.forward(name, MetricKind::Counter, labels, value as f64);
+    }
+
+    /// Set a gauge to an absolute value
+    #[allow(dead_code)]
+    pub fn set_gauge(&self, name: &'static str, labels: &[(&str, &str)], value: f64) {
+        self.update(name, MetricKind::Gauge, labels, |_| SeriesValue::Value(value));
+        self.forward(name, MetricKind::Gauge, labels, value);
+    }
+
+    /// Record one observation of a summary (exported as `_sum` and `_count`)
+    #[allow(dead_code)]
+    pub fn observe(&self, name: &'static str, labels: &[(&str, &str)], value: f64) {
+        self.update(name, MetricKind::Summary, labels, |current| match current {
+            Some(SeriesValue::Summary { sum, count }) => SeriesValue::Summary {
+                sum: sum + value,
+                count: count + 1,
+            },
+            _ => SeriesValue::Summary { sum: value, count: 1 },
+        });
+        self.forward(name, MetricKind::Summary, labels, value);
+    }
+
+    fn forward(&self, name: &'static str, kind: MetricKind, labels: &[(&str, &str)], value: f64) {
+        let sinks = self.sinks.read().unwrap_or_else(|e| e.into_inner());
+        for sink in sinks.iter() {
+            sink.record(name, kind, labels, value);
+        }
+    }
+
+    fn update<F>(&self, name: &'static str, kind: MetricKind, labels: &[(&str, &str)], f: F)
+    where
+        F: FnOnce(Option<SeriesValue>) -> SeriesValue,
+    {
+        let key = render_labels(labels);
+        let mut families = self.families.lock().unwrap_or_else(|e| e.into_inner());
+        let family = families.entry(name).or_default();
+        if family.kind.is_none() {
+            family.kind = Some(kind);
+        }
+        match family.series.get_mut(&key) {
+            Some(series) => series.value = f(Some(series.value)),
+            None => {
+                let labels = labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+                family.series.insert(key, Series { labels, value: f(None) });
+            }
+        }
+    }
+
+    /// Render every family in the Prometheus text exposition format
+    pub fn render_prometheus(&self) -> String {
+        let families = self.families.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+
+        for (name, family) in families.iter() {
+            if family.series.is_empty() {
+                continue;
+            }
+            if let Some(help) = family.help {
+                let _ = writeln!(out, "# HELP {} {}", name, help);
+            }
+            if let Some(kind) = family.kind {
+                let _ = writeln!(out, "# TYPE {} {}", name, kind.as_str());
+            }
+            for (labels, series) in &family.series {
+                match series.value {
+                    SeriesValue::Value(v) => {
+                        let _ = writeln!(out, "{}{} {}", name, labels, v);
+                    }
+                    SeriesValue::Summary { sum, count } => {
+                        let _ = writeln!(out, "{}_sum{} {}", name, labels, sum);
+                        let _ = writeln!(out, "{}_count{} {}", name, labels, count);
+                    }
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Every series as JSON: `{name: {type, series: [{labels, value}]}}`
+    ///
+    /// Summaries carry `sum` and `count` instead of `value`. The registry is
+    /// locked only while it is copied, so callers on other threads are not
+    /// held up by serialization.
+    pub fn snapshot(&self) -> Value {
+        let families: Vec<(&'static str, Option<MetricKind>, Vec<Series>)> = {
+            let families = self.families.lock().unwrap_or_else(|e| e.into_inner());
+            families
+                .iter()
+                .filter(|(_, family)| !family.series.is_empty())
+                .map(|(name, family)| (*name, family.kind, family.series.values().cloned().collect()))
+                .collect()
+        };
+
+        let mut out = Map::new();
+        for (name, kind, series) in families {
+            let series: Vec<Value> = series
+                .into_iter()
+                .map(|series| {
+                    let labels: Map<String, Value> =
+                        series.labels.into_iter().map(|(k, v)| (k, Value::String(v))).collect();
+                    match series.value {
+                        SeriesValue::Value(value) => json!({ "labels": labels, "value": value }),
+                        SeriesValue::Summary { sum, count } => json!({ "labels": labels, "sum": sum, "count": count }),
+                    }
+                })
+                .collect();
+            out.insert(
+                name.to_string(),
+                json!({ "type": kind.map(|k| k.as_str()), "series": series }),
+            );
+        }
+        Value::Object(out)
+    }
+}
+
+/// Render a label set as `{key="value",...}`, escaping values per the text format
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+
+    let mut out = String::from("{");
+    for (i, (key, value)) in labels.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let escaped = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+        let _ = write!(out, "{}=\"{}\"", key, escaped);
+    }
+    out.push('}');
+    out
+}
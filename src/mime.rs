@@ -0,0 +1,253 @@
+//! Content types of static files by extension
+//!
+//! A built-in table covers the usual web assets. `[mime]` in the config
+//! file, or a JSON object in `MIME_TYPES`, adds extensions or replaces
+//! built-in entries; the two are merged once at startup. With
+//! `STATIC_TEXT_CHARSET` set, text types without a `charset` parameter get
+//! one appended.
+//!
+//! ```toml
+//! [mime]
+//! wasm = "application/wasm"
+//! glb = "model/gltf-binary"
+//! ```
+//!
+//! Extensions match case-insensitively. A lookup does not allocate.
+
+use std::collections::HashMap;
+
+use hyper::header::HeaderValue;
+
+/// Built-in extensions and their content types
+const BUILTIN: &[(&str, &str)] = &[
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "application/javascript"),
+    ("mjs", "application/javascript"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("webmanifest", "application/manifest+json"),
+    ("xml", "application/xml"),
+    ("txt", "text/plain"),
+    ("csv", "text/csv"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("svg", "image/svg+xml"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("eot", "application/vnd.ms-fontobject"),
+    ("wasm", "application/wasm"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("apk", "application/vnd.android.package-archive"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("mp3", "audio/mpeg"),
+];
+
+/// Content type of files whose type is neither known nor sniffed
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Extensions longer than this are never in the table; keeps lowercasing on the stack
+const MAX_EXTENSION_LEN: usize = 32;
+
+/// Extension to content type table of the static file handler
+#[derive(Debug, Clone)]
+pub struct MimeTypes(HashMap<String, HeaderValue>);
+
+impl MimeTypes {
+    pub fn from_env() -> Result<Self, Vec<(&'static str, String)>> {
+        let user = std::env::var("MIME_TYPES").ok();
+        let charset = std::env::var("STATIC_TEXT_CHARSET").ok();
+        Self::build(user.as_deref(), charset.as_deref())
+    }
+
+    /// Table from the values of `MIME_TYPES` and `STATIC_TEXT_CHARSET`
+    fn build(user: Option<&str>, charset: Option<&str>) -> Result<Self, Vec<(&'static str, String)>> {
+        let mut problems = Vec::new();
+        let user = match user {
+            Some(json) if !json.trim().is_empty() => Self::parse_user(json).unwrap_or_else(|errors| {
+                problems.extend(errors.into_iter().map(|e| ("MIME_TYPES", e)));
+                Vec::new()
+            }),
+            _ => Vec::new(),
+        };
+        let charset = charset.filter(|v| !v.trim().is_empty());
+        if let Some(charset) = charset {
+            if !charset.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':')) {
+                problems.push(("STATIC_TEXT_CHARSET", format!("{:?} is not a charset name", charset)));
+            }
+        }
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
+        let builtin = BUILTIN.iter().map(|(extension, content_type)| (extension.to_string(), content_type.to_string()));
+        let mut types = HashMap::new();
+        // User entries come last and replace built-in ones
+        for (extension, content_type) in builtin.chain(user) {
+            let content_type = match charset {
+                Some(charset) if is_text(&content_type) && !content_type.contains(';') => {
+                    format!("{}; charset={}", content_type, charset)
+                }
+                _ => content_type,
+            };
+            let Ok(value) = HeaderValue::from_str(&content_type) else {
+                return Err(vec![("MIME_TYPES", format!("{:?} is not a valid header value", content_type))]);
+            };
+            types.insert(extension, value);
+        }
+        Ok(Self(types))
+    }
+
+    /// Parse a JSON object of `extension: content type` entries
+    fn parse_user(json: &str) -> Result<Vec<(String, String)>, Vec<String>> {
+        let entries: serde_json::Map<String, serde_json::Value> = serde_json::from_str(json)
+            .map_err(|e| vec![format!("must be an object of extensions to content types: {}", e)])?;
+
+        let mut types = Vec::new();
+        let mut problems = Vec::new();
+        for (extension, content_type) in entries {
+            let extension = extension.strip_prefix('.').unwrap_or(&extension).to_ascii_lowercase();
+            if extension.is_empty() || extension.len() > MAX_EXTENSION_LEN || extension.contains(['.', '/']) {
+                problems.push(format!(
+                    "extension {:?} must be a single extension of at most {} characters",
+                    extension, MAX_EXTENSION_LEN
+                ));
+                continue;
+            }
+            let Some(content_type) = content_type.as_str() else {
+                problems.push(format!("{}: content type must be a string", extension));
+                continue;
+            };
+            let essence = content_type.split(';').next().unwrap_or_default().trim();
+            let valid = essence
+                .split_once('/')
+                .is_some_and(|(kind, subtype)| !kind.is_empty() && !subtype.is_empty() && !subtype.contains('/'));
+            if !valid || HeaderValue::from_str(content_type).is_err() {
+                problems.push(format!("{}: {:?} is not a type/subtype content type", extension, content_type));
+                continue;
+            }
+            types.push((extension, content_type.to_string()));
+        }
+
+        if problems.is_empty() {
+            Ok(types)
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Content type of `file_path` from its extension; `None` for an unknown one
+    pub fn lookup(&self, file_path: &str) -> Option<&HeaderValue> {
+        let extension = std::path::Path::new(file_path).extension()?.to_str()?;
+        if let Some(content_type) = self.0.get(extension) {
+            return Some(content_type);
+        }
+        if extension.len() > MAX_EXTENSION_LEN || !extension.bytes().any(|b| b.is_ascii_uppercase()) {
+            return None;
+        }
+        let mut lowercase = [0u8; MAX_EXTENSION_LEN];
+        let lowercase = &mut lowercase[..extension.len()];
+        lowercase.copy_from_slice(extension.as_bytes());
+        lowercase.make_ascii_lowercase();
+        self.0.get(std::str::from_utf8(lowercase).ok()?)
+    }
+}
+
+/// Content type to send: the `known` one, else the `sniffed` one, else `application/octet-stream`
+pub fn resolve(known: Option<&HeaderValue>, sniffed: Option<&'static str>) -> HeaderValue {
+    match known {
+        Some(known) => known.clone(),
+        None => HeaderValue::from_static(sniffed.unwrap_or(DEFAULT_CONTENT_TYPE)),
+    }
+}
+
+/// Whether a charset parameter applies to `content_type`
+fn is_text(content_type: &str) -> bool {
+    content_type.starts_with("text/")
+        || matches!(
+            content_type,
+            "application/javascript" | "application/json" | "application/manifest+json" | "application/xml" | "image/svg+xml"
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn types(user: Option<&str>, charset: Option<&str>) -> MimeTypes {
+        MimeTypes::build(user, charset).unwrap()
+    }
+
+    fn content_type(types: &MimeTypes, file_path: &str) -> Option<String> {
+        types.lookup(file_path).map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn built_in_extensions_are_known() {
+        let types = types(None, None);
+        assert_eq!(content_type(&types, "public/app.wasm").as_deref(), Some("application/wasm"));
+        assert_eq!(content_type(&types, "/build/assets/app-4f2a.css").as_deref(), Some("text/css"));
+        assert_eq!(content_type(&types, "fonts/inter.woff2").as_deref(), Some("font/woff2"));
+    }
+
+    #[test]
+    fn user_entries_add_and_replace_built_in_ones() {
+        let types = types(Some(r#"{"js": "text/javascript", ".glb": "model/gltf-binary"}"#), None);
+        assert_eq!(content_type(&types, "app.js").as_deref(), Some("text/javascript"));
+        assert_eq!(content_type(&types, "scene.glb").as_deref(), Some("model/gltf-binary"));
+        assert_eq!(content_type(&types, "app.css").as_deref(), Some("text/css"));
+    }
+
+    #[test]
+    fn invalid_user_entries_are_all_reported() {
+        let problems = MimeTypes::build(Some(r#"{"a/b": "text/plain", "x": "plain", "y": 1}"#), Some("utf 8")).unwrap_err();
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems.iter().any(|(env, _)| *env == "STATIC_TEXT_CHARSET"));
+        assert!(MimeTypes::build(Some("[]"), None).is_err());
+    }
+
+    #[test]
+    fn unknown_extensions_fall_back_to_octet_stream() {
+        let types = types(None, None);
+        for file_path in ["data.unknownext", "Makefile", "archive.tar.", "/"] {
+            assert_eq!(types.lookup(file_path), None, "{}", file_path);
+        }
+        assert_eq!(resolve(types.lookup("data.bin"), None), DEFAULT_CONTENT_TYPE);
+        assert_eq!(resolve(types.lookup("data.bin"), Some("image/png")), "image/png");
+        assert_eq!(resolve(types.lookup("logo.svg"), Some("text/plain")), "image/svg+xml");
+    }
+
+    #[test]
+    fn the_charset_is_added_to_text_types_only() {
+        let types = types(Some(r#"{"ics": "text/calendar", "tsv": "text/tab-separated-values; charset=latin1"}"#), Some("utf-8"));
+        assert_eq!(content_type(&types, "index.html").as_deref(), Some("text/html; charset=utf-8"));
+        assert_eq!(content_type(&types, "app.js").as_deref(), Some("application/javascript; charset=utf-8"));
+        assert_eq!(content_type(&types, "logo.svg").as_deref(), Some("image/svg+xml; charset=utf-8"));
+        assert_eq!(content_type(&types, "event.ics").as_deref(), Some("text/calendar; charset=utf-8"));
+        assert_eq!(content_type(&types, "sheet.tsv").as_deref(), Some("text/tab-separated-values; charset=latin1"));
+        assert_eq!(content_type(&types, "photo.png").as_deref(), Some("image/png"));
+        assert_eq!(content_type(&types, "app.wasm").as_deref(), Some("application/wasm"));
+    }
+
+    #[test]
+    fn extensions_match_in_any_case() {
+        let types = types(Some(r#"{"GLB": "model/gltf-binary"}"#), None);
+        assert_eq!(content_type(&types, "PHOTO.JPG").as_deref(), Some("image/jpeg"));
+        assert_eq!(content_type(&types, "Index.Html").as_deref(), Some("text/html"));
+        assert_eq!(content_type(&types, "scene.glb").as_deref(), Some("model/gltf-binary"));
+        assert_eq!(content_type(&types, "scene.GlB").as_deref(), Some("model/gltf-binary"));
+        let long = format!("file.{}", "X".repeat(MAX_EXTENSION_LEN + 1));
+        assert_eq!(types.lookup(&long), None);
+    }
+}
@@ -0,0 +1,391 @@
+//! Stand-in for the PHP worker in tests (`test-worker` feature)
+//!
+//! Listens on a Unix socket and speaks the worker side of the frame format
+//! of [`connection_pool`](crate::bridge::connection_pool), so the server can
+//! be tested end to end without a Laravel installation. What it answers is
+//! scripted: each frame is matched against a list of [`Rule`]s by path,
+//! method or command, and the first match decides the reply, after an
+//! optional delay. Frames no rule matches get the script's default, which
+//! echoes HTTP frames back and acknowledges commands.
+//!
+//! Besides well-formed responses a rule can misbehave the ways a real
+//! worker does: answer with garbage or an empty frame, answer twice, close
+//! the connection before answering or half way through the response, or
+//! never answer. A `goodbye` command is never answered, as the pool expects.
+//! How each connection ended is counted, so tests can tell a server that
+//! closes its connections cleanly from one that resets them.
+//!
+//! The `mock-worker` binary runs one from the command line, with the script
+//! read from a JSON file:
+//!
+//! ```json
+//! {
+//!   "rules": [
+//!     {"path": "/slow", "delay_ms": 2000, "action": "respond", "body": "finally"},
+//!     {"prefix": "/api/", "action": "respond", "status": 201,
+//!      "headers": {"content-type": ["application/json"]}, "body": "{}"},
+//!     {"path": "/crash", "action": "disconnect"}
+//!   ]
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::task::{JoinHandle, JoinSet};
+
+use crate::bridge::connection_pool::{encode_frame, FRAME_PREFIX_LEN};
+
+/// What the mock worker answers
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Script {
+    /// Tried in order; the first that matches a frame answers it
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// Reply to frames no rule matches
+    #[serde(default)]
+    pub default: Reply,
+}
+
+/// A reply for the frames it matches
+///
+/// A rule without `path`, `prefix`, `method` or `command` matches every
+/// frame. `path` and `prefix` are compared with the request path, without
+/// the query string, of native and `octane` request frames alike; `command`
+/// only matches command frames.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rule {
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub command: Option<String>,
+    /// Pause before replying
+    #[serde(default)]
+    pub delay_ms: u64,
+    #[serde(flatten)]
+    pub reply: Reply,
+}
+
+impl Rule {
+    /// Rule answering every frame with `reply`
+    pub fn any(reply: Reply) -> Self {
+        Self {
+            path: None,
+            prefix: None,
+            method: None,
+            command: None,
+            delay_ms: 0,
+            reply,
+        }
+    }
+
+    /// Rule answering requests for `path` with `reply`
+    pub fn path(path: &str, reply: Reply) -> Self {
+        Self {
+            path: Some(path.to_string()),
+            ..Self::any(reply)
+        }
+    }
+
+    /// The same rule, replying after `delay`
+    pub fn delayed(mut self, delay: Duration) -> Self {
+        self.delay_ms = delay.as_millis() as u64;
+        self
+    }
+
+    fn matches(&self, frame: &Value) -> bool {
+        let command = frame.get("command").and_then(Value::as_str);
+        let uri = frame["uri"].as_str().or(frame["server"]["request_uri"].as_str());
+        let path = uri.map(|uri| uri.split('?').next().unwrap_or(uri));
+        let method = frame["method"].as_str().or(frame["server"]["request_method"].as_str());
+        self.path.as_deref().is_none_or(|want| path == Some(want))
+            && self.prefix.as_deref().is_none_or(|want| path.is_some_and(|path| path.starts_with(want)))
+            && self.method.as_deref().is_none_or(|want| method.is_some_and(|method| method.eq_ignore_ascii_case(want)))
+            && self.command.as_deref().is_none_or(|want| command == Some(want))
+    }
+}
+
+/// How a frame is answered
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Reply {
+    /// A successful response with this status, headers and body
+    Respond {
+        #[serde(default = "default_status")]
+        status: u16,
+        #[serde(default)]
+        headers: HashMap<String, Vec<String>>,
+        #[serde(default)]
+        body: String,
+    },
+    /// A successful answer with this `data` as it is, e.g. in the shape of another `WORKER_PROTOCOL`
+    Data { data: Value },
+    /// HTTP frames: a 200 whose JSON body is the frame received;
+    /// commands: success with the command's name
+    #[default]
+    Echo,
+    /// `success: false` with this error, as Laravel reports an exception
+    Fail { message: String },
+    /// A frame that is not JSON
+    Garbage,
+    /// A frame of length 0
+    Empty,
+    /// The echoed response, sent twice
+    Duplicate,
+    /// Half of a response frame, then the connection is closed
+    Truncate,
+    /// The connection is closed without an answer
+    Disconnect,
+    /// No answer, ever
+    Hang,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+impl Reply {
+    /// A successful response with `status` and a plain text `body`
+    pub fn respond(status: u16, body: &str) -> Self {
+        Reply::Respond {
+            status,
+            headers: HashMap::from([("content-type".to_string(), vec!["text/plain".to_string()])]),
+            body: body.to_string(),
+        }
+    }
+}
+
+/// What the mock worker has seen
+#[derive(Debug, Default)]
+pub struct WorkerStats {
+    /// Connections accepted
+    accepted: AtomicUsize,
+    /// Connections open right now
+    open: AtomicUsize,
+    /// Most connections open at once
+    most_open: AtomicUsize,
+    /// Frames read
+    frames: AtomicUsize,
+    /// `goodbye` commands read
+    goodbyes: AtomicUsize,
+    /// Connections the server closed between frames
+    clean_closes: AtomicUsize,
+    /// Connections the server reset, or closed in the middle of a frame
+    abrupt_closes: AtomicUsize,
+}
+
+impl WorkerStats {
+    fn get(counter: &AtomicUsize) -> usize {
+        counter.load(Ordering::SeqCst)
+    }
+
+    pub fn accepted(&self) -> usize {
+        Self::get(&self.accepted)
+    }
+
+    pub fn open(&self) -> usize {
+        Self::get(&self.open)
+    }
+
+    pub fn most_open(&self) -> usize {
+        Self::get(&self.most_open)
+    }
+
+    pub fn frames(&self) -> usize {
+        Self::get(&self.frames)
+    }
+
+    pub fn goodbyes(&self) -> usize {
+        Self::get(&self.goodbyes)
+    }
+
+    pub fn clean_closes(&self) -> usize {
+        Self::get(&self.clean_closes)
+    }
+
+    pub fn abrupt_closes(&self) -> usize {
+        Self::get(&self.abrupt_closes)
+    }
+}
+
+/// A running mock worker; closes its connections and removes its socket when dropped
+pub struct MockWorker {
+    socket_path: PathBuf,
+    stats: Arc<WorkerStats>,
+    task: JoinHandle<()>,
+}
+
+impl MockWorker {
+    /// Listen on `socket_path` and answer according to `script`
+    ///
+    /// Must be called within a Tokio runtime. A file already at
+    /// `socket_path` is replaced.
+    pub fn start(socket_path: impl AsRef<Path>, script: Script) -> std::io::Result<Self> {
+        let socket_path = socket_path.as_ref().to_path_buf();
+        let _ = std::fs::remove_file(&socket_path);
+        let listener = UnixListener::bind(&socket_path)?;
+        let stats = Arc::new(WorkerStats::default());
+        let script = Arc::new(script);
+
+        let counts = stats.clone();
+        let task = tokio::spawn(async move {
+            // Owned by this task, so stopping it closes every connection too
+            let mut connections = JoinSet::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                counts.accepted.fetch_add(1, Ordering::SeqCst);
+                let open = counts.open.fetch_add(1, Ordering::SeqCst) + 1;
+                counts.most_open.fetch_max(open, Ordering::SeqCst);
+                connections.spawn(serve(stream, script.clone(), counts.clone()));
+                while connections.try_join_next().is_some() {}
+            }
+        });
+        Ok(Self { socket_path, stats, task })
+    }
+
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    pub fn stats(&self) -> &WorkerStats {
+        &self.stats
+    }
+}
+
+impl Drop for MockWorker {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = std::fs::remove_file(&self.socket_path);
+    }
+}
+
+/// How a connection ended
+enum Closed {
+    /// By the server, between frames
+    Clean,
+    /// By the server, with a reset or in the middle of a frame
+    Abrupt,
+    /// By the mock itself, as a rule said
+    ByRule,
+}
+
+/// Answer the frames of one connection until it is closed
+async fn serve(mut stream: UnixStream, script: Arc<Script>, stats: Arc<WorkerStats>) {
+    let closed = answer_frames(&mut stream, &script, &stats).await;
+    stats.open.fetch_sub(1, Ordering::SeqCst);
+    let counter = match closed {
+        Closed::Clean => &stats.clean_closes,
+        Closed::Abrupt => &stats.abrupt_closes,
+        Closed::ByRule => return,
+    };
+    counter.fetch_add(1, Ordering::SeqCst);
+}
+
+async fn answer_frames(stream: &mut UnixStream, script: &Script, stats: &WorkerStats) -> Closed {
+    let mut buf = Vec::new();
+    loop {
+        let mut prefix = [0; FRAME_PREFIX_LEN];
+        match read_prefix(stream, &mut prefix).await {
+            Ok(true) => {}
+            Ok(false) => return Closed::Clean,
+            Err(_) => return Closed::Abrupt,
+        }
+        buf.resize(u32::from_be_bytes(prefix) as usize, 0);
+        if stream.read_exact(&mut buf).await.is_err() {
+            return Closed::Abrupt;
+        }
+        stats.frames.fetch_add(1, Ordering::SeqCst);
+        let frame: Value = serde_json::from_slice(&buf).unwrap_or(Value::Null);
+        if frame["command"] == "goodbye" {
+            stats.goodbyes.fetch_add(1, Ordering::SeqCst);
+            continue;
+        }
+
+        let rule = script.rules.iter().find(|rule| rule.matches(&frame));
+        if let Some(rule) = rule.filter(|rule| rule.delay_ms > 0) {
+            tokio::time::sleep(Duration::from_millis(rule.delay_ms)).await;
+        }
+        let reply = rule.map_or(&script.default, |rule| &rule.reply);
+        if reply_to(stream, &frame, reply, &mut buf).await.is_err() {
+            return Closed::ByRule;
+        }
+    }
+}
+
+/// Read a frame's length prefix; `false` when the connection ended before it
+async fn read_prefix(stream: &mut UnixStream, prefix: &mut [u8; FRAME_PREFIX_LEN]) -> std::io::Result<bool> {
+    let mut read = 0;
+    while read < prefix.len() {
+        match stream.read(&mut prefix[read..]).await? {
+            0 if read == 0 => return Ok(false),
+            0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            n => read += n,
+        }
+    }
+    Ok(true)
+}
+
+/// Write `reply` to `frame`; an error ends the connection
+async fn reply_to(stream: &mut UnixStream, frame: &Value, reply: &Reply, buf: &mut Vec<u8>) -> std::io::Result<()> {
+    let id = frame.get("id").cloned().unwrap_or(Value::Null);
+    let response = match reply {
+        Reply::Respond { status, headers, body } => json!({
+            "id": id,
+            "success": true,
+            "data": { "status": status, "headers": headers, "body": body },
+            "error": null,
+        }),
+        Reply::Data { data } => json!({ "id": id, "success": true, "data": data, "error": null }),
+        Reply::Echo => echo(frame),
+        Reply::Fail { message } => json!({ "id": id, "success": false, "data": null, "error": message }),
+        Reply::Garbage => {
+            let garbage = b"<html>Fatal error: Allowed memory size exhausted</html>";
+            stream.write_all(&(garbage.len() as u32).to_be_bytes()).await?;
+            return stream.write_all(garbage).await;
+        }
+        Reply::Empty => return stream.write_all(&[0; FRAME_PREFIX_LEN]).await,
+        Reply::Duplicate => {
+            encode_frame(buf, &echo(frame)).map_err(std::io::Error::other)?;
+            stream.write_all(buf).await?;
+            return stream.write_all(buf).await;
+        }
+        Reply::Truncate => {
+            encode_frame(buf, &json!({ "id": id, "success": true, "data": { "status": 200, "body": "cut short" } }))
+                .map_err(std::io::Error::other)?;
+            stream.write_all(&buf[..buf.len() / 2]).await?;
+            return Err(std::io::ErrorKind::ConnectionAborted.into());
+        }
+        Reply::Disconnect => return Err(std::io::ErrorKind::ConnectionAborted.into()),
+        Reply::Hang => std::future::pending().await,
+    };
+    encode_frame(buf, &response).map_err(std::io::Error::other)?;
+    stream.write_all(buf).await
+}
+
+/// Reply of [`Reply::Echo`] to `frame`
+fn echo(frame: &Value) -> Value {
+    let id = frame.get("id").cloned().unwrap_or(Value::Null);
+    match frame.get("command").and_then(Value::as_str) {
+        Some(command) => json!({ "id": id, "success": true, "data": { "command": command }, "error": null }),
+        None => json!({
+            "id": id,
+            "success": true,
+            "data": {
+                "status": 200,
+                "headers": { "content-type": ["application/json"] },
+                "body": frame.to_string(),
+            },
+            "error": null,
+        }),
+    }
+}
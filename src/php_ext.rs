@@ -0,0 +1,159 @@
+//! Native PHP extension (`php-ext` feature)
+//!
+//! Exposes the socket bridge to PHP as `LaravelRust\Bridge`, without the
+//! FFI layer:
+//!
+//! ```php
+//! $bridge = new LaravelRust\Bridge('/tmp/rust_php_bridge.sock');
+//! $response = $bridge->sendCommand('ping', ['from' => 'php']);
+//! $stats = $bridge->stats();
+//! ```
+//!
+//! Every failure throws `LaravelRust\BridgeException`; its code is the
+//! matching [`LaravelRustStatus`] of the C API, so PHP code can tell an
+//! unreachable worker from a timeout.
+
+use std::sync::Arc;
+
+use ext_php_rs::class::RegisteredClass;
+use ext_php_rs::convert::IntoZvalDyn;
+use ext_php_rs::exception::PhpException;
+use ext_php_rs::prelude::*;
+use ext_php_rs::types::{ZendCallable, Zval};
+use ext_php_rs::zend::ce;
+use tokio::runtime::Runtime;
+
+use crate::bridge::socket_bridge::SocketBridge;
+use crate::config::AppConfig;
+use crate::laravel_integration::{bridge_error_status, CommandStats, LaravelRustStatus};
+use crate::metrics::metrics;
+
+/// Thrown by `LaravelRust\Bridge`; `getCode()` is a `LaravelRustStatus` value
+#[php_class(name = "LaravelRust\\BridgeException")]
+#[extends(ce::exception)]
+#[derive(Default)]
+pub struct BridgeException;
+
+fn exception(status: LaravelRustStatus, message: impl Into<String>) -> PhpException {
+    PhpException::new(message.into(), status as i32, BridgeException::get_metadata().ce())
+}
+
+/// Connection to the Laravel worker over its Unix socket
+#[php_class(name = "LaravelRust\\Bridge")]
+pub struct Bridge {
+    /// Runtime the bridge runs on; method calls block on it
+    runtime: Runtime,
+    bridge: Arc<SocketBridge>,
+    commands: CommandStats,
+}
+
+#[php_impl]
+impl Bridge {
+    /// Use the worker listening on `socket_path`; every other setting
+    /// (pool size, timeouts) comes from the environment as for the server
+    pub fn __construct(socket_path: String) -> PhpResult<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("laravel-rust-php")
+            .build()
+            .map_err(|e| {
+                exception(
+                    LaravelRustStatus::Internal,
+                    format!("Failed to start the async runtime: {}", e),
+                )
+            })?;
+
+        let mut config = AppConfig::from_env().map_err(|e| {
+            exception(
+                LaravelRustStatus::InvalidArgument,
+                format!("Invalid configuration: {:#}", e),
+            )
+        })?;
+        config.connection.socket_path = socket_path;
+
+        // The bridge warms up its pool in a background task
+        let bridge = {
+            let _runtime = runtime.enter();
+            SocketBridge::new_with_config(&config)
+        }
+        .map_err(|e| {
+            exception(
+                LaravelRustStatus::BridgeUnavailable,
+                format!("Failed to create the bridge: {:#}", e),
+            )
+        })?;
+
+        Ok(Self {
+            runtime,
+            bridge,
+            commands: CommandStats::new(),
+        })
+    }
+
+    /// Send a command to the worker and return its response
+    ///
+    /// The response is an array with `id`, `success`, `data` and `error`; a
+    /// command the worker rejects comes back with `success` false rather
+    /// than as an exception.
+    #[optional(data)]
+    pub fn send_command(&self, command: String, data: Option<&Zval>) -> PhpResult<Zval> {
+        let (data, data_bytes) = match data.filter(|data| !data.is_null()) {
+            Some(data) if data.is_array() => {
+                let json = call_php("json_encode", vec![&data.shallow_clone()])?;
+                let json = json.string().unwrap_or_default();
+                let data = serde_json::from_str(&json).map_err(|_| {
+                    exception(
+                        LaravelRustStatus::InvalidArgument,
+                        "data must be an array with string keys",
+                    )
+                })?;
+                (Some(data), json.len())
+            }
+            Some(_) => {
+                return Err(exception(LaravelRustStatus::InvalidArgument, "data must be an array"));
+            }
+            None => (None, 0),
+        };
+
+        let in_flight = self.commands.start(command.len() + data_bytes);
+        let response = match self.runtime.block_on(self.bridge.send_command(&command, data)) {
+            Ok(response) => response,
+            Err(e) => {
+                let status = bridge_error_status(&e);
+                in_flight.failed(status);
+                return Err(exception(status, format!("{:#}", e)));
+            }
+        };
+        let json = serde_json::to_string(&response).unwrap_or_default();
+        in_flight.succeeded(json.len());
+
+        call_php("json_decode", vec![&json, &true])
+    }
+
+    /// Socket path, command counters and bridge metrics, in the shape of
+    /// `laravel_rust_get_stats`
+    pub fn stats(&self) -> PhpResult<Zval> {
+        to_php_array(&serde_json::json!({
+            "socket_path": self.bridge.socket_path(),
+            "commands": self.commands.to_json(),
+            "metrics": metrics().snapshot(),
+        }))
+    }
+}
+
+/// Call a global PHP function
+fn call_php(name: &str, args: Vec<&dyn IntoZvalDyn>) -> PhpResult<Zval> {
+    ZendCallable::try_from_name(name)
+        .and_then(|function| function.try_call(args))
+        .map_err(|e| exception(LaravelRustStatus::Internal, format!("{}() failed: {:?}", name, e)))
+}
+
+/// Convert JSON to a PHP array through `json_decode`
+fn to_php_array(value: &serde_json::Value) -> PhpResult<Zval> {
+    call_php("json_decode", vec![&value.to_string(), &true])
+}
+
+#[php_module]
+pub fn get_module(module: ModuleBuilder) -> ModuleBuilder {
+    module
+}
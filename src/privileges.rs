@@ -0,0 +1,142 @@
+//! Dropping root privileges after privileged resources are acquired
+//!
+//! When started as root (to bind :80/:443), the bridge switches to the
+//! account configured via `RUN_AS_USER` / `RUN_AS_GROUP` before it accepts
+//! connections or spawns the PHP worker, so neither runs as root.
+
+use std::ffi::CString;
+
+use anyhow::{anyhow, bail, Result};
+use tracing::{info, warn};
+
+/// Target identity for the privilege drop
+#[derive(Debug, Clone, Default)]
+pub struct PrivilegeConfig {
+    pub user: Option<String>,
+    pub group: Option<String>,
+}
+
+impl PrivilegeConfig {
+    pub fn from_env() -> Self {
+        Self {
+            user: std::env::var("RUN_AS_USER").ok().filter(|v| !v.is_empty()),
+            group: std::env::var("RUN_AS_GROUP").ok().filter(|v| !v.is_empty()),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.user.is_some() || self.group.is_some()
+    }
+}
+
+/// Switch the process to the configured user/group
+///
+/// Does nothing when no account is configured. When not running as root the
+/// drop is skipped with a warning, since there is nothing to give up.
+///
+/// # Returns
+///
+/// * `Ok(())` - privileges were dropped (or there was nothing to do)
+/// * `Err` - the account does not exist or a syscall failed; startup must abort
+pub fn drop_privileges(config: &PrivilegeConfig) -> Result<()> {
+    if !config.is_configured() {
+        return Ok(());
+    }
+
+    // SAFETY: geteuid has no preconditions
+    if unsafe { libc::geteuid() } != 0 {
+        warn!(
+            user = ?config.user,
+            group = ?config.group,
+            "Not running as root, RUN_AS_USER/RUN_AS_GROUP ignored"
+        );
+        return Ok(());
+    }
+
+    let (uid, mut gid, user_name) = match &config.user {
+        Some(user) => {
+            let (uid, gid) = lookup_user(user)?;
+            (Some(uid), gid, Some(user.clone()))
+        }
+        None => (None, 0, None),
+    };
+
+    if let Some(group) = &config.group {
+        gid = lookup_group(group)?;
+    }
+
+    // SAFETY: plain syscalls with valid arguments; order matters — groups and
+    // gid must be changed while we still have the privilege to do so.
+    unsafe {
+        if config.group.is_some() || uid.is_some() {
+            let groups = [gid];
+            if libc::setgroups(1, groups.as_ptr()) != 0 {
+                bail!("setgroups({}) failed: {}", gid, std::io::Error::last_os_error());
+            }
+            if libc::setgid(gid) != 0 {
+                bail!("setgid({}) failed: {}", gid, std::io::Error::last_os_error());
+            }
+        }
+        if let Some(uid) = uid {
+            if libc::setuid(uid) != 0 {
+                bail!("setuid({}) failed: {}", uid, std::io::Error::last_os_error());
+            }
+            // Regaining root must be impossible now
+            if libc::setuid(0) == 0 {
+                bail!("privileges could be regained after dropping to uid {}", uid);
+            }
+        }
+    }
+
+    info!(
+        user = ?user_name,
+        uid = unsafe { libc::getuid() },
+        gid = unsafe { libc::getgid() },
+        "Dropped root privileges"
+    );
+    Ok(())
+}
+
+/// Resolve a user name (or numeric uid) to its uid and primary gid
+fn lookup_user(user: &str) -> Result<(libc::uid_t, libc::gid_t)> {
+    let name = CString::new(user).map_err(|_| anyhow!("Invalid RUN_AS_USER value: {:?}", user))?;
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+
+    // SAFETY: all pointers refer to live, correctly sized buffers
+    let rc = unsafe { libc::getpwnam_r(name.as_ptr(), &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if rc == 0 && !result.is_null() {
+        return Ok((pwd.pw_uid, pwd.pw_gid));
+    }
+
+    if let Ok(uid) = user.parse::<libc::uid_t>() {
+        // SAFETY: same as above
+        let rc = unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+        if rc == 0 && !result.is_null() {
+            return Ok((pwd.pw_uid, pwd.pw_gid));
+        }
+    }
+
+    bail!("User '{}' does not exist (RUN_AS_USER)", user)
+}
+
+/// Resolve a group name (or numeric gid) to its gid
+fn lookup_group(group: &str) -> Result<libc::gid_t> {
+    let name = CString::new(group).map_err(|_| anyhow!("Invalid RUN_AS_GROUP value: {:?}", group))?;
+    let mut grp: libc::group = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::group = std::ptr::null_mut();
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+
+    // SAFETY: all pointers refer to live, correctly sized buffers
+    let rc = unsafe { libc::getgrnam_r(name.as_ptr(), &mut grp, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if rc == 0 && !result.is_null() {
+        return Ok(grp.gr_gid);
+    }
+
+    if let Ok(gid) = group.parse::<libc::gid_t>() {
+        return Ok(gid);
+    }
+
+    bail!("Group '{}' does not exist (RUN_AS_GROUP)", group)
+}
@@ -0,0 +1,454 @@
+//! Request/response recording for debugging (`RECORD_ENABLED`)
+//!
+//! A recorded request leaves one JSON file in `RECORD_DIR` holding the
+//! `HttpRequestPayload` sent to the PHP worker, the response frame the
+//! worker sent back and the status and headers the client got. That is
+//! usually enough to tell whether a request broke in the bridge or in the
+//! application, and `laravel-rust-server replay <file>` sends the recorded
+//! payload to the worker again to compare the answers.
+//!
+//! * `RECORD_ENABLED=true` records every request that reaches the backend;
+//!   static files and health probes are never recorded.
+//! * With `RECORD_HEADER_SECRET` set, a single request is recorded when it
+//!   carries `X-Bridge-Record: <secret>`. The header is never forwarded to
+//!   Laravel, recorded or not.
+//! * `Authorization`, `Proxy-Authorization`, `Cookie` and `Set-Cookie`
+//!   values are replaced by `[redacted]`.
+//! * Bodies are cut at `RECORD_MAX_BODY_BYTES`; the file notes the original
+//!   size. Bodies that are not UTF-8 are stored base64-encoded.
+//! * Only the newest `RECORD_MAX_FILES` files are kept, including files
+//!   left by earlier runs.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use hyper::body::Bytes;
+use hyper::{Body, HeaderMap, Response};
+use serde_json::{json, Map, Value};
+use tracing::{info, warn, Level};
+
+use crate::admin_auth::constant_time_eq;
+use crate::bridge::PhpResponse;
+use crate::log_throttle::log_throttle;
+use crate::metrics::{metrics, MetricKind};
+use crate::request_context::RequestContext;
+use crate::server::HttpRequestPayload;
+
+/// Header that asks for one request to be recorded
+pub const RECORD_HEADER: &str = "x-bridge-record";
+
+/// Default for `RECORD_DIR`
+pub const DEFAULT_DIR: &str = "./logs/recordings";
+
+/// Default for `RECORD_MAX_BODY_BYTES`
+pub const DEFAULT_MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Default for `RECORD_MAX_FILES`
+pub const DEFAULT_MAX_FILES: usize = 1000;
+
+/// Headers whose values never reach a recording
+const REDACTED_HEADERS: &[&str] = &["authorization", "proxy-authorization", "cookie", "set-cookie"];
+
+/// Stands in for a redacted header value
+pub const REDACTED: &str = "[redacted]";
+
+/// Longest request id kept in a file name
+const MAX_ID_IN_FILE_NAME: usize = 64;
+
+/// Recording configuration
+#[derive(Debug, Clone)]
+pub struct RecordingConfig {
+    /// Record every request that reaches the backend
+    pub enabled: bool,
+    /// Value of `X-Bridge-Record` that records a single request
+    pub header_secret: Option<String>,
+    pub dir: PathBuf,
+    pub max_body_bytes: usize,
+    pub max_files: usize,
+}
+
+impl RecordingConfig {
+    pub fn from_env() -> Self {
+        Self {
+            enabled: std::env::var("RECORD_ENABLED")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            header_secret: std::env::var("RECORD_HEADER_SECRET").ok().filter(|v| !v.is_empty()),
+            dir: PathBuf::from(std::env::var("RECORD_DIR").unwrap_or_else(|_| DEFAULT_DIR.to_string())),
+            max_body_bytes: std::env::var("RECORD_MAX_BODY_BYTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_BODY_BYTES),
+            max_files: std::env::var("RECORD_MAX_FILES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_FILES),
+        }
+    }
+
+    /// Whether any request can be recorded at all
+    pub fn is_active(&self) -> bool {
+        self.enabled || self.header_secret.is_some()
+    }
+}
+
+/// Decides which requests are recorded and keeps the directory within `RECORD_MAX_FILES`
+#[derive(Debug)]
+pub struct Recorder {
+    config: RecordingConfig,
+    /// Recordings on disk, oldest first
+    files: Mutex<VecDeque<PathBuf>>,
+}
+
+impl Recorder {
+    /// Recorder for `config`, or `None` when recording is off
+    ///
+    /// Creates the directory and picks up the recordings already in it, so
+    /// the retention limit covers earlier runs too.
+    pub fn new(config: RecordingConfig) -> Result<Option<Arc<Self>>> {
+        if !config.is_active() {
+            return Ok(None);
+        }
+        std::fs::create_dir_all(&config.dir)
+            .with_context(|| format!("cannot create RECORD_DIR {}", config.dir.display()))?;
+
+        let mut files: Vec<PathBuf> = std::fs::read_dir(&config.dir)
+            .with_context(|| format!("cannot read RECORD_DIR {}", config.dir.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        // File names start with the recording time
+        files.sort();
+
+        metrics().describe(
+            "recorded_requests_total",
+            MetricKind::Counter,
+            "Requests written to RECORD_DIR, by outcome",
+        );
+        if config.enabled {
+            info!("📼 Recording every request to {}", config.dir.display());
+        } else {
+            info!("📼 Recording requests with the {} header to {}", RECORD_HEADER, config.dir.display());
+        }
+
+        let recorder = Arc::new(Self {
+            config,
+            files: Mutex::new(files.into()),
+        });
+        recorder.trim();
+        Ok(Some(recorder))
+    }
+
+    /// Start recording the request, if it is to be recorded
+    pub fn start(self: &Arc<Self>, headers: &HeaderMap, context: &RequestContext) -> Option<Arc<Recording>> {
+        let requested = match (&self.config.header_secret, headers.get(RECORD_HEADER)) {
+            (Some(secret), Some(value)) => constant_time_eq(value.as_bytes(), secret.as_bytes()),
+            _ => false,
+        };
+        if !self.config.enabled && !requested {
+            return None;
+        }
+
+        let mut document = Map::new();
+        document.insert("request_id".to_string(), context.id.clone().into());
+        document.insert("client_ip".to_string(), context.client_ip.to_string().into());
+        Some(Arc::new(Recording {
+            recorder: self.clone(),
+            request_id: context.id.clone(),
+            recorded_at: SystemTime::now(),
+            document: Mutex::new(document),
+        }))
+    }
+
+    /// Write `document` and drop the oldest recordings over the limit
+    fn write(&self, file_name: String, document: &Value) {
+        let path = self.config.dir.join(file_name);
+        let written = serde_json::to_vec_pretty(document)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| std::fs::write(&path, contents).map_err(anyhow::Error::from));
+
+        match written {
+            Ok(()) => {
+                metrics().inc_counter("recorded_requests_total", &[("outcome", "written")]);
+                self.files.lock().unwrap_or_else(|e| e.into_inner()).push_back(path);
+                self.trim();
+            }
+            Err(e) => {
+                metrics().inc_counter("recorded_requests_total", &[("outcome", "failed")]);
+                let message = format!("cannot write {}: {:#}", path.display(), e);
+                if log_throttle().allow("recording", Level::WARN, &message) {
+                    warn!("Request recording failed: {}", message);
+                }
+            }
+        }
+    }
+
+    fn trim(&self) {
+        let expired: Vec<PathBuf> = {
+            let mut files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+            let excess = files.len().saturating_sub(self.config.max_files);
+            files.drain(..excess).collect()
+        };
+        for path in expired {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// One request being recorded, shared through its [`RequestContext`]
+#[derive(Debug)]
+pub struct Recording {
+    recorder: Arc<Recorder>,
+    request_id: String,
+    recorded_at: SystemTime,
+    document: Mutex<Map<String, Value>>,
+}
+
+impl Recording {
+    /// Record the payload sent to the backend
+    pub fn request(&self, payload: &HttpRequestPayload) {
+        let max_body = self.recorder.config.max_body_bytes;
+        let request = json!({
+            "method": payload.method,
+            "uri": payload.uri,
+            "headers": redact_headers(&payload.headers),
+            "query_params": payload.query_params,
+            "body": payload.body.as_deref().map(|body| capped_body(body, max_body)),
+        });
+        self.set("request", request);
+    }
+
+    /// Record the frame the PHP worker answered with, or why there was none
+    pub fn bridge_response(&self, result: &Result<PhpResponse>) {
+        match result {
+            Ok(response) => {
+                let mut frame = serde_json::to_value(response).unwrap_or(Value::Null);
+                if let Some(data) = frame.get_mut("data") {
+                    cap_response_data(data, self.recorder.config.max_body_bytes);
+                }
+                self.set("bridge_response", frame);
+            }
+            Err(e) => self.set("bridge_error", format!("{:#}", e).into()),
+        }
+    }
+
+    /// Record what the client got and write the file
+    ///
+    /// Nothing is written for requests answered without the backend.
+    pub fn finish(&self, response: &Response<Body>, context: &RequestContext) {
+        let mut document = std::mem::take(&mut *self.document.lock().unwrap_or_else(|e| e.into_inner()));
+        if !document.contains_key("request") {
+            return;
+        }
+
+        let headers: HashMap<String, String> = response
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.as_str().to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned()))
+            .collect();
+        document.insert(
+            "response".to_string(),
+            json!({
+                "status": response.status().as_u16(),
+                "headers": redact_headers(&headers),
+            }),
+        );
+        document.insert("duration_ms".to_string(), (context.elapsed().as_millis() as u64).into());
+
+        let recorded_ms = self
+            .recorded_at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        document.insert("recorded_at_ms".to_string(), (recorded_ms as u64).into());
+
+        let file_name = format!("{:013}-{}.json", recorded_ms, file_name_part(&self.request_id));
+        let recorder = self.recorder.clone();
+        tokio::task::spawn_blocking(move || recorder.write(file_name, &Value::Object(document)));
+    }
+
+    fn set(&self, key: &str, value: Value) {
+        self.document
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key.to_string(), value);
+    }
+}
+
+/// Request read back from a recording, for `replay`
+#[derive(Debug)]
+pub struct RecordedRequest {
+    pub request_id: String,
+    pub payload: HttpRequestPayload,
+    /// The body was cut at `RECORD_MAX_BODY_BYTES`, so the replayed one is incomplete
+    pub body_truncated: bool,
+    /// Headers whose values were redacted and are sent as `[redacted]`
+    pub redacted_headers: Vec<String>,
+    /// Worker frame recorded with the request, if it got one
+    pub bridge_response: Option<Value>,
+}
+
+impl RecordedRequest {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read(path).with_context(|| format!("cannot read {}", path.display()))?;
+        let document: Value =
+            serde_json::from_slice(&contents).with_context(|| format!("{} is not JSON", path.display()))?;
+        let request = document
+            .get("request")
+            .ok_or_else(|| anyhow!("{} has no recorded request", path.display()))?;
+
+        let string_map = |key: &str| -> Result<HashMap<String, String>> {
+            match request.get(key) {
+                None | Some(Value::Null) => Ok(HashMap::new()),
+                Some(value) => serde_json::from_value(value.clone()).with_context(|| format!("request.{} is malformed", key)),
+            }
+        };
+        let headers = string_map("headers")?;
+        let redacted_headers = headers
+            .iter()
+            .filter(|(_, value)| value.as_str() == REDACTED)
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        let (body, body_truncated) = match request.get("body") {
+            None | Some(Value::Null) => (None, false),
+            Some(body) => {
+                let content = body["content"].as_str().unwrap_or_default();
+                let bytes = match body["encoding"].as_str() {
+                    Some("base64") => base64::engine::general_purpose::STANDARD
+                        .decode(content)
+                        .context("request.body is not valid base64")?,
+                    _ => content.as_bytes().to_vec(),
+                };
+                (Some(Bytes::from(bytes)), body["truncated"].as_bool().unwrap_or(false))
+            }
+        };
+
+        let field = |key: &str| -> Result<String> {
+            request[key]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| anyhow!("request.{} is missing", key))
+        };
+        Ok(Self {
+            request_id: document["request_id"].as_str().unwrap_or_default().to_string(),
+            payload: HttpRequestPayload {
+                method: field("method")?,
+                uri: field("uri")?,
+                headers,
+                body,
+                query_params: string_map("query_params")?,
+            },
+            body_truncated,
+            redacted_headers,
+            bridge_response: document.get("bridge_response").cloned(),
+        })
+    }
+}
+
+/// Differences between a recorded worker frame and the one a replay got
+///
+/// Compares the outcome, status and body; a recorded body that was cut
+/// short only has to be a prefix of the new one.
+pub fn compare_frames(recorded: &Value, replayed: &Value) -> Vec<String> {
+    let mut differences = Vec::new();
+    if recorded["success"] != replayed["success"] {
+        differences.push(format!("success: {} -> {}", recorded["success"], replayed["success"]));
+    }
+    if recorded["error"] != replayed["error"] {
+        differences.push(format!("error: {} -> {}", recorded["error"], replayed["error"]));
+    }
+
+    let (recorded, replayed) = (&recorded["data"], &replayed["data"]);
+    if recorded["status"] != replayed["status"] {
+        differences.push(format!("status: {} -> {}", recorded["status"], replayed["status"]));
+    }
+    let recorded_body = recorded["body"].as_str().unwrap_or_default();
+    let replayed_body = replayed["body"].as_str().unwrap_or_default();
+    let same_body = if recorded["body_truncated"].as_bool().unwrap_or(false) {
+        replayed_body.starts_with(recorded_body)
+    } else {
+        recorded_body == replayed_body
+    };
+    if !same_body {
+        let recorded_len = recorded["body_bytes"].as_u64().unwrap_or(recorded_body.len() as u64);
+        differences.push(format!("body: {} bytes -> {} bytes, contents differ", recorded_len, replayed_body.len()));
+    }
+    differences
+}
+
+fn redact_headers(headers: &HashMap<String, String>) -> HashMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| match REDACTED_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+            true => (name.clone(), REDACTED.to_string()),
+            false => (name.clone(), value.clone()),
+        })
+        .collect()
+}
+
+/// Request body as stored in a recording, cut at `max` bytes
+fn capped_body(body: &[u8], max: usize) -> Value {
+    let (content, encoding) = match std::str::from_utf8(body) {
+        Ok(text) => (text[..floor_char_boundary(text, max)].to_string(), "utf8"),
+        Err(_) => (
+            base64::engine::general_purpose::STANDARD.encode(&body[..body.len().min(max)]),
+            "base64",
+        ),
+    };
+    json!({
+        "content": content,
+        "encoding": encoding,
+        "bytes": body.len(),
+        "truncated": body.len() > max,
+    })
+}
+
+/// Cut the body of a worker response and redact its cookies
+fn cap_response_data(data: &mut Value, max: usize) {
+    if let Some(headers) = data.get_mut("headers").and_then(Value::as_object_mut) {
+        for (name, value) in headers.iter_mut() {
+            if REDACTED_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+                *value = REDACTED.into();
+            }
+        }
+    }
+
+    let Some(object) = data.as_object_mut() else {
+        return;
+    };
+    let Some(body) = object.get("body").and_then(Value::as_str) else {
+        return;
+    };
+    if body.len() > max {
+        let bytes = body.len();
+        let cut = body[..floor_char_boundary(body, max)].to_string();
+        object.insert("body".to_string(), cut.into());
+        object.insert("body_bytes".to_string(), bytes.into());
+        object.insert("body_truncated".to_string(), true.into());
+    }
+}
+
+/// Largest index `<= max` that falls on a character boundary of `text`
+fn floor_char_boundary(text: &str, max: usize) -> usize {
+    if max >= text.len() {
+        return text.len();
+    }
+    (0..=max).rev().find(|&i| text.is_char_boundary(i)).unwrap_or(0)
+}
+
+/// Request id reduced to characters that are safe in a file name
+fn file_name_part(request_id: &str) -> String {
+    request_id
+        .chars()
+        .take(MAX_ID_IN_FILE_NAME)
+        .map(|c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+            true => c,
+            false => '_',
+        })
+        .collect()
+}
@@ -0,0 +1,268 @@
+//! Per-request context used to correlate logs and error responses
+//!
+//! Requests to a `QUIET_PATHS` path (by default the `/healthz` and `/readyz`
+//! probes) are traced at trace level and counted apart from other requests,
+//! so frequent load balancer checks stay out of the logs and the latency
+//! summary.
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hyper::{Body, Method, Request, StatusCode};
+use once_cell::sync::Lazy;
+use tracing::Span;
+
+use crate::metrics::{metrics, MetricKind};
+use crate::recording::Recording;
+use crate::tls::PeerCertificate;
+
+/// Header carrying the request id, accepted from clients and proxies
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest client-supplied request id that is kept as is
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Paths treated as quiet when `QUIET_PATHS` is not set
+const DEFAULT_QUIET_PATHS: &str = "/healthz,/readyz";
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Distinguishes ids from different process runs
+static ID_PREFIX: Lazy<String> = Lazy::new(|| {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    format!("{:x}{:x}", secs, std::process::id())
+});
+
+/// Identity and timing of one request
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    pub id: String,
+    pub method: Method,
+    pub path: String,
+    pub client_ip: IpAddr,
+    /// `Accept` header, used to pick the format of error responses
+    pub accept: Option<String>,
+    pub started: Instant,
+    /// `QUIET_PATHS` entry the path matched, if any
+    pub quiet: Option<String>,
+    /// Set when the request is being recorded (`RECORD_ENABLED`, `X-Bridge-Record`)
+    pub recording: Option<Arc<Recording>>,
+    /// Fault injected into the request by the `chaos` feature, if any
+    pub chaos: Option<&'static str>,
+    /// Time budget a trusted caller sent in `X-Request-Deadline-Ms`
+    pub caller_budget: Option<Duration>,
+    /// How the request is treated when the bridge is saturated
+    pub class: RequestClass,
+    /// Arrived over TLS on the HTTP listener (`TLS_ENABLED`)
+    pub secure: bool,
+    /// The connection's client certificate, when the listener asks for one (`TLS_CLIENT_CA`)
+    pub client_cert: Option<PeerCertificate>,
+}
+
+impl RequestContext {
+    /// Start a request, reusing a well-formed `X-Request-Id` from the client
+    pub fn new(req: &Request<Body>, client_ip: IpAddr) -> Self {
+        let id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty() && v.len() <= MAX_REQUEST_ID_LEN)
+            .filter(|v| v.bytes().all(|b| b.is_ascii_graphic()))
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{}-{}", *ID_PREFIX, NEXT_ID.fetch_add(1, Ordering::Relaxed)));
+
+        Self {
+            id,
+            method: req.method().clone(),
+            path: req.uri().path().to_string(),
+            client_ip,
+            accept: req
+                .headers()
+                .get(hyper::header::ACCEPT)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+            started: Instant::now(),
+            quiet: None,
+            recording: None,
+            chaos: None,
+            caller_budget: None,
+            class: RequestClass::Normal,
+            secure: false,
+            client_cert: None,
+        }
+    }
+
+    /// Span carrying the request fields; events inside it inherit them
+    ///
+    /// `status` and `duration_ms` are filled in by [`finish`](Self::finish).
+    /// Quiet requests get a trace-level span.
+    pub fn span(&self) -> Span {
+        if self.quiet.is_some() {
+            return tracing::trace_span!(
+                "request",
+                request_id = %self.id,
+                method = %self.method,
+                path = %self.path,
+                client_ip = %self.client_ip,
+                status = tracing::field::Empty,
+                duration_ms = tracing::field::Empty,
+            );
+        }
+        tracing::info_span!(
+            "request",
+            request_id = %self.id,
+            method = %self.method,
+            path = %self.path,
+            client_ip = %self.client_ip,
+            status = tracing::field::Empty,
+            duration_ms = tracing::field::Empty,
+        )
+    }
+
+    /// Record the outcome on the current request span, log and count it
+    ///
+    /// Quiet requests are logged at trace level and only counted in
+    /// `http_quiet_requests_total`, never in the request latency summary.
+    pub fn finish(&self, status: StatusCode) {
+        let elapsed = self.elapsed();
+        let span = Span::current();
+        span.record("status", status.as_u16());
+        span.record("duration_ms", elapsed.as_millis() as u64);
+
+        if let Some(pattern) = &self.quiet {
+            tracing::trace!("Request completed");
+            metrics().describe(
+                "http_quiet_requests_total",
+                MetricKind::Counter,
+                "Requests to QUIET_PATHS (health checks), by matching entry and status code",
+            );
+            metrics().inc_counter("http_quiet_requests_total", &[("path", pattern), ("status", status.as_str())]);
+            return;
+        }
+
+        // Injected faults must not show up as real failures
+        if let Some(fault) = self.chaos {
+            tracing::debug!(chaos = true, fault, "Request completed");
+            metrics().inc_counter("http_chaos_requests_total", &[("fault", fault), ("status", status.as_str())]);
+            return;
+        }
+
+        tracing::debug!("Request completed");
+        metrics().describe("http_requests_total", MetricKind::Counter, "Requests by status code, excluding QUIET_PATHS");
+        metrics().inc_counter("http_requests_total", &[("status", status.as_str())]);
+        metrics().describe(
+            "http_request_duration_seconds",
+            MetricKind::Summary,
+            "Time from request arrival to response, excluding QUIET_PATHS",
+        );
+        metrics().observe("http_request_duration_seconds", &[], elapsed.as_secs_f64());
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// How a request is treated when the bridge is saturated
+///
+/// Assigned from the listener and the exact request path before any limit
+/// applies, never from headers a client could set. The admin listener is
+/// not classified: none of its endpoints sends frames to the worker, so it
+/// never waits behind HTTP traffic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestClass {
+    /// Health checks of the HTTP listener: the built-in probes, answered
+    /// without the bridge, and `PRIORITY_PATHS`, which bypass the adaptive
+    /// limit and `SOCKET_MAX_CONCURRENT_FRAMES` within their own
+    /// `SOCKET_PRIORITY_FRAMES`
+    Health,
+    /// Everything else, shed as configured
+    Normal,
+}
+
+/// Exact paths of health checks served by the application (`PRIORITY_PATHS`)
+#[derive(Debug, Clone, Default)]
+pub struct PriorityPaths(Vec<String>);
+
+impl PriorityPaths {
+    pub fn from_env() -> Result<Self, Vec<String>> {
+        Self::parse(&std::env::var("PRIORITY_PATHS").unwrap_or_default())
+    }
+
+    pub fn parse(list: &str) -> Result<Self, Vec<String>> {
+        let entries: Vec<String> = list
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        let problems: Vec<String> = entries
+            .iter()
+            .filter_map(|entry| {
+                if !entry.starts_with('/') {
+                    Some(format!("path {:?} must start with /", entry))
+                } else if entry.contains(['*', '?']) {
+                    Some(format!("path {:?} must be an exact path, without wildcards or a query", entry))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        if problems.is_empty() {
+            Ok(Self(entries))
+        } else {
+            Err(problems)
+        }
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.0.iter().any(|entry| entry == path)
+    }
+}
+
+/// Request paths kept out of the regular logs and metrics (`QUIET_PATHS`)
+///
+/// A comma-separated list of exact paths, or prefixes ending in `*`
+/// (e.g. `/healthz,/internal/probe/*`). An empty value disables it.
+#[derive(Debug, Clone, Default)]
+pub struct QuietPaths(Vec<String>);
+
+impl QuietPaths {
+    pub fn from_env() -> Result<Self, Vec<String>> {
+        Self::parse(&std::env::var("QUIET_PATHS").unwrap_or_else(|_| DEFAULT_QUIET_PATHS.to_string()))
+    }
+
+    pub fn parse(list: &str) -> Result<Self, Vec<String>> {
+        let entries: Vec<String> = list
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        let problems: Vec<String> = entries
+            .iter()
+            .filter(|entry| !entry.starts_with('/'))
+            .map(|entry| format!("path {:?} must start with /", entry))
+            .collect();
+        if problems.is_empty() {
+            Ok(Self(entries))
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Entry matching `path`, if any
+    pub fn matching(&self, path: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|entry| match entry.strip_suffix('*') {
+                Some(prefix) => path.starts_with(prefix),
+                None => path == entry.as_str(),
+            })
+            .map(String::as_str)
+    }
+}
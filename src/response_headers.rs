@@ -0,0 +1,125 @@
+//! Extra headers added to every response
+//!
+//! Configured as `[response_headers]` in the config file or as a JSON object
+//! in `RESPONSE_HEADERS`, and applied once to every response the server
+//! produces: proxied, static, probe and error responses alike.
+//!
+//! ```toml
+//! [response_headers]
+//! X-Routing-Hint = "edge-1"
+//! X-Compliance = { value = "internal", mode = "append" }
+//! ```
+
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Body, Response};
+
+/// What to do when the response already carries the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderMode {
+    /// Drop the existing values and set ours (default)
+    Replace,
+    /// Keep the existing values and add ours
+    Append,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtraHeader {
+    pub name: HeaderName,
+    pub value: HeaderValue,
+    pub mode: HeaderMode,
+}
+
+/// The configured extra headers
+#[derive(Debug, Clone, Default)]
+pub struct ResponseHeaders {
+    headers: Vec<ExtraHeader>,
+}
+
+impl ResponseHeaders {
+    /// Load from `RESPONSE_HEADERS`
+    ///
+    /// # Returns
+    ///
+    /// * `Err(problems)` - one message per invalid entry
+    pub fn from_env() -> Result<Self, Vec<String>> {
+        match std::env::var("RESPONSE_HEADERS") {
+            Ok(json) if !json.trim().is_empty() => Self::parse(&json),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Parse a JSON object of `name: value` or `name: {value, mode}` entries
+    pub fn parse(json: &str) -> Result<Self, Vec<String>> {
+        let entries: serde_json::Map<String, serde_json::Value> = serde_json::from_str(json)
+            .map_err(|e| vec![format!("must be an object of header names to values: {}", e)])?;
+
+        let mut headers = Vec::new();
+        let mut problems = Vec::new();
+        for (name, entry) in &entries {
+            let (value, mode) = match entry {
+                serde_json::Value::String(value) => (value.as_str(), Some("replace")),
+                serde_json::Value::Object(spec) => (
+                    spec.get("value").and_then(|v| v.as_str()).unwrap_or_default(),
+                    Some(spec.get("mode").and_then(|v| v.as_str()).unwrap_or("replace")),
+                ),
+                _ => ("", None),
+            };
+
+            let Some(mode) = mode else {
+                problems.push(format!("header {:?} must be a string or {{ value, mode }}", name));
+                continue;
+            };
+            let mode = match mode {
+                "replace" => HeaderMode::Replace,
+                "append" => HeaderMode::Append,
+                other => {
+                    problems.push(format!("header {:?} has mode {:?}; expected replace or append", name, other));
+                    continue;
+                }
+            };
+            let Ok(header_name) = HeaderName::from_bytes(name.as_bytes()) else {
+                problems.push(format!("{:?} is not a valid header name", name));
+                continue;
+            };
+            let Ok(header_value) = HeaderValue::from_str(value) else {
+                problems.push(format!("header {:?} has an invalid value {:?}", name, value));
+                continue;
+            };
+
+            headers.push(ExtraHeader {
+                name: header_name,
+                value: header_value,
+                mode,
+            });
+        }
+
+        if problems.is_empty() {
+            Ok(Self { headers })
+        } else {
+            Err(problems)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    /// Add the configured headers to a response
+    pub fn apply(&self, response: &mut Response<Body>) {
+        let headers = response.headers_mut();
+        for extra in &self.headers {
+            match extra.mode {
+                HeaderMode::Replace => {
+                    headers.insert(extra.name.clone(), extra.value.clone());
+                }
+                HeaderMode::Append => {
+                    headers.append(extra.name.clone(), extra.value.clone());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Checking the shape of the PHP worker's responses
+//!
+//! The response parser makes something out of anything: a bare string is
+//! served as a `200`, an object without the expected fields is serialized
+//! as the body, a status it cannot read becomes `200`. That keeps odd
+//! handlers working, and it also hides broken ones.
+//! `SOCKET_RESPONSE_VALIDATION` picks what happens to such responses:
+//!
+//! * `lenient` (default) - the fallbacks apply as before, and each one is
+//!   counted in `bridge_response_fallbacks_total{fallback}` and logged as
+//!   a warning the first time it is seen for a response shape;
+//! * `strict` - the response is rejected with `502 upstream_malformed` and
+//!   logged with the first bytes of its JSON.
+//!
+//! The fallbacks are: a response that is not an object; an object without
+//! `status`; a `status` that is not a number from 100 to 599; `headers`
+//! that are not an object; a header value that is neither a string nor an
+//! array of strings; a `body` that is not a string; and a missing `body`
+//! next to `status`, which makes the whole object the body.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use tracing::{error, warn};
+
+use crate::errors::ServerError;
+use crate::metrics::{metrics, MetricKind};
+
+/// Bytes of the offending JSON logged when a response is rejected
+const DUMP_BYTES: usize = 512;
+
+/// Response shapes warned about; later new shapes are only counted
+const MAX_SHAPES: usize = 256;
+
+/// Shapes of responses a fallback was already logged for
+static SEEN_SHAPES: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// What happens to a response the parser would have to guess about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResponseValidation {
+    /// Apply the fallback, counting and logging it
+    #[default]
+    Lenient,
+    /// Reject the response with a 502
+    Strict,
+}
+
+/// A guess the response parser makes about a malformed response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    /// Not an object: served as a `200` with the value as body
+    NotAnObject,
+    /// An object without `status`: served as a `200` with the object as body
+    MissingStatus,
+    /// `status` is not a number from 100 to 599
+    InvalidStatus,
+    /// `headers` is not an object: the response has no headers
+    HeadersNotObject,
+    /// A header value is neither a string nor an array of strings: it is serialized
+    InvalidHeaderValue,
+    /// `body` is not a string: it is serialized, or dropped next to `headers`
+    BodyNotString,
+    /// No `body` next to `status`: the whole object is the body
+    MissingBody,
+}
+
+impl Fallback {
+    /// Label in `bridge_response_fallbacks_total`
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::NotAnObject => "not_an_object",
+            Self::MissingStatus => "missing_status",
+            Self::InvalidStatus => "invalid_status",
+            Self::HeadersNotObject => "headers_not_object",
+            Self::InvalidHeaderValue => "invalid_header_value",
+            Self::BodyNotString => "body_not_string",
+            Self::MissingBody => "missing_body",
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Self::NotAnObject => "response is not an object",
+            Self::MissingStatus => "response has no status",
+            Self::InvalidStatus => "status is not a number from 100 to 599",
+            Self::HeadersNotObject => "headers is not an object",
+            Self::InvalidHeaderValue => "a header value is neither a string nor an array of strings",
+            Self::BodyNotString => "body is not a string",
+            Self::MissingBody => "response has a status but no body",
+        }
+    }
+}
+
+impl ResponseValidation {
+    /// Mode from `SOCKET_RESPONSE_VALIDATION`; other values are refused by config validation
+    pub fn from_env() -> Self {
+        match std::env::var("SOCKET_RESPONSE_VALIDATION").as_deref() {
+            Ok("strict") => Self::Strict,
+            _ => Self::Lenient,
+        }
+    }
+
+    /// Check the `data` of a successful worker response before it is parsed
+    ///
+    /// In lenient mode every fallback is counted and the response passes;
+    /// in strict mode the first one is an error.
+    pub fn check(self, response: &Value) -> Result<(), ServerError> {
+        let fallbacks = fallbacks(response);
+        let Some(&first) = fallbacks.first() else {
+            return Ok(());
+        };
+        match self {
+            Self::Strict => {
+                let problem = first.describe();
+                metrics().describe(
+                    "bridge_responses_rejected_total",
+                    MetricKind::Counter,
+                    "Malformed PHP worker responses rejected by SOCKET_RESPONSE_VALIDATION=strict, by fallback avoided",
+                );
+                metrics().inc_counter("bridge_responses_rejected_total", &[("fallback", first.label())]);
+                error!(fallback = first.label(), response = %dump(response), "Rejected malformed PHP worker response: {}", problem);
+                Err(ServerError::UpstreamMalformed(problem.to_string()))
+            }
+            Self::Lenient => {
+                metrics().describe(
+                    "bridge_response_fallbacks_total",
+                    MetricKind::Counter,
+                    "Malformed PHP worker responses served through a parser fallback, by fallback",
+                );
+                for fallback in &fallbacks {
+                    metrics().inc_counter("bridge_response_fallbacks_total", &[("fallback", fallback.label())]);
+                }
+                if first_time(shape(response, &fallbacks)) {
+                    let labels: Vec<&str> = fallbacks.iter().map(|fallback| fallback.label()).collect();
+                    warn!(
+                        fallbacks = ?labels,
+                        response = %dump(response),
+                        "PHP worker response needed parser fallbacks: {} (SOCKET_RESPONSE_VALIDATION=strict would reject it)",
+                        first.describe()
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Fallbacks the response parser takes for `response`, in the order it meets them
+pub fn fallbacks(response: &Value) -> Vec<Fallback> {
+    let Value::Object(obj) = response else {
+        return vec![Fallback::NotAnObject];
+    };
+    let Some(status) = obj.get("status") else {
+        return vec![Fallback::MissingStatus];
+    };
+
+    let mut found = Vec::new();
+    if !status.as_u64().is_some_and(|status| (100..=599).contains(&status)) {
+        found.push(Fallback::InvalidStatus);
+    }
+    match obj.get("headers") {
+        None => {}
+        Some(Value::Object(headers)) => {
+            let valid = |value: &Value| match value {
+                Value::String(_) => true,
+                Value::Array(values) => values.iter().all(Value::is_string),
+                _ => false,
+            };
+            if !headers.values().all(valid) {
+                found.push(Fallback::InvalidHeaderValue);
+            }
+        }
+        Some(_) => found.push(Fallback::HeadersNotObject),
+    }
+    match obj.get("body") {
+        Some(Value::String(_)) => {}
+        Some(_) => found.push(Fallback::BodyNotString),
+        None => found.push(Fallback::MissingBody),
+    }
+    found
+}
+
+/// The fallbacks with the JSON type or top-level keys of `response`
+fn shape(response: &Value, fallbacks: &[Fallback]) -> String {
+    let mut shape: Vec<&str> = fallbacks.iter().map(|fallback| fallback.label()).collect();
+    shape.push("|");
+    match response {
+        Value::Object(obj) => {
+            let mut keys: Vec<&str> = obj.keys().map(String::as_str).collect();
+            keys.sort_unstable();
+            shape.extend(keys);
+        }
+        Value::Null => shape.push("null"),
+        Value::Bool(_) => shape.push("bool"),
+        Value::Number(_) => shape.push("number"),
+        Value::String(_) => shape.push("string"),
+        Value::Array(_) => shape.push("array"),
+    }
+    shape.join(",")
+}
+
+/// Whether `shape` was not logged before, remembering it
+fn first_time(shape: String) -> bool {
+    let mut seen = SEEN_SHAPES.lock().unwrap_or_else(|e| e.into_inner());
+    if seen.len() >= MAX_SHAPES || seen.contains(&shape) {
+        return false;
+    }
+    seen.insert(shape)
+}
+
+/// Serialized `response`, cut after [`DUMP_BYTES`]
+fn dump(response: &Value) -> String {
+    let mut raw = response.to_string();
+    if raw.len() > DUMP_BYTES {
+        let cut = (0..=DUMP_BYTES).rev().find(|&i| raw.is_char_boundary(i)).unwrap_or(0);
+        let total = raw.len();
+        raw.truncate(cut);
+        raw.push_str(&format!("... ({} bytes)", total));
+    }
+    raw
+}
@@ -0,0 +1,128 @@
+//! Build and runtime information for deploy tooling
+//!
+//! `GET /__info` (`INFO_PATH`) answers with what the instance is running,
+//! so a rollout can be verified without logging in to each machine:
+//!
+//! ```json
+//! {"version": "0.1.0", "git_sha": "3f2a9c1b7d4e", "build_timestamp": "2026-10-01T12:00:00Z",
+//!  "rustc_version": "rustc 1.95.0 (...)", "started_at": 1791892800, "uptime_secs": 3600,
+//!  "profile": "prod", "worker_protocol_version": 1, "config_hash": "9c1e0b7a55d2f3e8"}
+//! ```
+//!
+//! The build fields are those of `--version`. `config_hash` is
+//! [`config_fingerprint`]: two instances with the same hash run the same
+//! configuration, and the hash reveals no values. Everything but the uptime
+//! is collected once at startup, and the bridge is never asked, so the
+//! endpoint answers while PHP is down.
+//!
+//! `INFO_LISTENER=admin` moves the endpoint from the HTTP listener to the
+//! admin listener, behind its credentials. `INFO_ALLOW_IPS` limits it to
+//! some client addresses on either listener; others get `403`. An empty
+//! `INFO_PATH` turns it off.
+
+use std::net::IpAddr;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use hyper::{header, Body, Response, StatusCode};
+use serde_json::json;
+
+use crate::build_info;
+use crate::cidr::{self, Cidr};
+use crate::config_loader::{config_fingerprint, Profile};
+use crate::server::WORKER_PROTOCOL_VERSION;
+use crate::worker_protocol::WorkerProtocol;
+
+/// Default for `INFO_PATH`
+pub const DEFAULT_PATH: &str = "/__info";
+
+/// Listener that answers `INFO_PATH`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InfoListener {
+    Http,
+    Admin,
+}
+
+/// The info endpoint with the answer it gives
+#[derive(Debug)]
+pub struct RuntimeInfo {
+    /// `None` when the endpoint is turned off
+    path: Option<String>,
+    listener: InfoListener,
+    /// Clients allowed to read it; empty allows everyone
+    allow: Vec<Cidr>,
+    /// Everything but the uptime, collected at startup
+    body: serde_json::Value,
+    started: Instant,
+}
+
+impl RuntimeInfo {
+    /// Settings from the environment, with the answer collected now
+    pub fn from_env() -> Result<Self, Vec<(&'static str, String)>> {
+        let mut problems = Vec::new();
+        let path = std::env::var("INFO_PATH").unwrap_or_else(|_| DEFAULT_PATH.to_string());
+        let path = path.trim();
+        if !path.is_empty() && !path.starts_with('/') {
+            problems.push(("INFO_PATH", "must start with /".to_string()));
+        }
+        let listener = match std::env::var("INFO_LISTENER").unwrap_or_default().trim().to_ascii_lowercase().as_str() {
+            "" | "http" => InfoListener::Http,
+            "admin" => InfoListener::Admin,
+            other => {
+                problems.push(("INFO_LISTENER", format!("must be http or admin, got {:?}", other)));
+                InfoListener::Http
+            }
+        };
+        let allow = match cidr::parse_list(&std::env::var("INFO_ALLOW_IPS").unwrap_or_default()) {
+            Ok(allow) => allow,
+            Err(errors) => {
+                problems.extend(errors.into_iter().map(|problem| ("INFO_ALLOW_IPS", problem)));
+                Vec::new()
+            }
+        };
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
+        let build = build_info();
+        let profile = Profile::parse(std::env::var("APP_PROFILE").ok().as_deref()).map_or("unknown", |p| p.as_str());
+        Ok(Self {
+            path: (!path.is_empty()).then(|| path.to_string()),
+            listener,
+            allow,
+            body: json!({
+                "version": build.version,
+                "git_sha": build.git_sha,
+                "build_timestamp": build.build_timestamp,
+                "rustc_version": build.rustc_version,
+                "started_at": SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+                "profile": profile,
+                "worker_protocol": WorkerProtocol::from_env().codec().name(),
+                "worker_protocol_version": WORKER_PROTOCOL_VERSION,
+                "config_hash": config_fingerprint(),
+            }),
+            started: Instant::now(),
+        })
+    }
+
+    /// Whether a request for `path` on `listener` is for the endpoint
+    pub fn matches(&self, listener: InfoListener, path: &str) -> bool {
+        self.listener == listener && self.path.as_deref() == Some(path)
+    }
+
+    /// Answer to a client at `client_ip`
+    pub fn response(&self, client_ip: IpAddr) -> Response<Body> {
+        let (status, body) = if self.allow.is_empty() || cidr::any_contains(&self.allow, client_ip) {
+            let mut body = self.body.clone();
+            body["uptime_secs"] = self.started.elapsed().as_secs().into();
+            (StatusCode::OK, body)
+        } else {
+            (StatusCode::FORBIDDEN, json!({ "error": "forbidden" }))
+        };
+        Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CACHE_CONTROL, "no-store")
+            .body(Body::from(body.to_string()))
+            .unwrap_or_else(|_| Response::new(Body::empty()))
+    }
+}
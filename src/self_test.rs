@@ -0,0 +1,180 @@
+//! Startup self-test through the full request pipeline
+//!
+//! A worker socket that accepts connections says nothing about whether the
+//! worker understands our frames. With `SELF_TEST=true`, once the socket is
+//! reachable, a GET to `SELF_TEST_PATH` is built and sent the way a
+//! client's request is: same payload, frame, bridge call and response
+//! parsing, including `SOCKET_RESPONSE_VALIDATION`. It must come back 2xx or 3xx within `SELF_TEST_TIMEOUT_MS`, and
+//! until it does the server is not ready. A failure is logged with the
+//! frame sent and the response received, then:
+//!
+//! * `SELF_TEST_ON_FAILURE=abort` shuts the server down with a non-zero
+//!   exit status;
+//! * `SELF_TEST_ON_FAILURE=degraded` keeps it running with `/readyz`
+//!   failing and repeats the test every `RETRY_INTERVAL` until it passes.
+//!
+//! Only the PHP worker backend is tested; php-fpm has no frames to disagree on.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tracing::{error, info, warn, Level};
+
+use crate::bridge::socket_bridge::SocketBridge;
+use crate::deadline::Deadline;
+use crate::log_throttle::log_throttle;
+use crate::response_validation::ResponseValidation;
+use crate::server::{php_response, request_frame, HttpRequestPayload};
+
+/// Request id of the self-test request, in our logs and Laravel's
+const REQUEST_ID: &str = "startup-self-test";
+
+/// Time between attempts of a degraded server
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What a failed self-test does to the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnFailure {
+    Abort,
+    Degraded,
+}
+
+impl OnFailure {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "abort" => Some(Self::Abort),
+            "degraded" => Some(Self::Degraded),
+            _ => None,
+        }
+    }
+}
+
+/// Self-test settings
+#[derive(Debug, Clone)]
+pub struct SelfTestConfig {
+    pub enabled: bool,
+    /// Path, with an optional query string, of the GET request sent
+    pub path: String,
+    pub timeout: Duration,
+    pub on_failure: OnFailure,
+    /// Checks applied to the response, as for client requests
+    pub validation: ResponseValidation,
+}
+
+impl SelfTestConfig {
+    pub fn from_env() -> Result<Self, Vec<(&'static str, String)>> {
+        let mut problems = Vec::new();
+        let path = std::env::var("SELF_TEST_PATH").unwrap_or_else(|_| "/up".to_string());
+        if !path.starts_with('/') {
+            problems.push(("SELF_TEST_PATH", format!("{:?} must start with /", path)));
+        }
+        let on_failure = std::env::var("SELF_TEST_ON_FAILURE").unwrap_or_else(|_| "abort".to_string());
+        let on_failure = OnFailure::parse(&on_failure).unwrap_or_else(|| {
+            problems.push(("SELF_TEST_ON_FAILURE", format!("{:?} must be abort or degraded", on_failure)));
+            OnFailure::Abort
+        });
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
+        Ok(Self {
+            enabled: matches!(std::env::var("SELF_TEST").as_deref(), Ok("true") | Ok("1")),
+            path,
+            timeout: Duration::from_millis(
+                std::env::var("SELF_TEST_TIMEOUT_MS").ok().and_then(|v| v.parse().ok()).unwrap_or(5000),
+            ),
+            on_failure,
+            validation: ResponseValidation::from_env(),
+        })
+    }
+}
+
+/// Result of the first self-test
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Passed, or disabled: the server is ready
+    Passed,
+    /// Failed with `SELF_TEST_ON_FAILURE=abort`: shut down
+    Abort,
+    /// Failed with `SELF_TEST_ON_FAILURE=degraded`: run [`retry_until_passed`]
+    Degraded,
+}
+
+/// Run the self-test once, logging the diagnostic when it fails
+pub async fn run(config: &SelfTestConfig, socket_bridge: &SocketBridge) -> Outcome {
+    if !config.enabled {
+        return Outcome::Passed;
+    }
+    if attempt(config, socket_bridge, true).await {
+        return Outcome::Passed;
+    }
+    match config.on_failure {
+        OnFailure::Abort => {
+            error!(path = %config.path, "❌ Startup self-test failed, shutting down (SELF_TEST_ON_FAILURE=abort)");
+            Outcome::Abort
+        }
+        OnFailure::Degraded => {
+            warn!(
+                path = %config.path,
+                retry_in_secs = RETRY_INTERVAL.as_secs(),
+                "⚠️ Startup self-test failed, serving with /readyz failing until it passes (SELF_TEST_ON_FAILURE=degraded)"
+            );
+            Outcome::Degraded
+        }
+    }
+}
+
+/// Repeat the self-test of a degraded server, marking it ready once it passes
+pub async fn retry_until_passed(config: SelfTestConfig, socket_bridge: Arc<SocketBridge>, ready: Arc<AtomicBool>) {
+    loop {
+        tokio::time::sleep(RETRY_INTERVAL).await;
+        // The diagnostic was logged by the first attempt
+        if attempt(&config, &socket_bridge, false).await {
+            ready.store(true, Ordering::Release);
+            info!("✅ PHP worker ready, proxying requests");
+            return;
+        }
+    }
+}
+
+/// Send the self-test request; true for a 2xx or 3xx response
+async fn attempt(config: &SelfTestConfig, socket_bridge: &SocketBridge, diagnose: bool) -> bool {
+    let started = Instant::now();
+    let mut frame = request_frame(HttpRequestPayload::synthetic("GET", &config.path, None), REQUEST_ID);
+    let remaining = Deadline::new(started, config.timeout, None).stamp(&mut frame);
+    let sent = diagnose.then(|| frame.clone());
+
+    let answer = socket_bridge.send_http_request_within(frame, remaining).await;
+    let received = answer.as_ref().ok().filter(|_| diagnose).and_then(|answer| serde_json::to_value(answer).ok());
+    let problem = match answer.and_then(|answer| php_response(answer, socket_bridge.codec(), config.validation)) {
+        Ok(response) if response.status().is_success() || response.status().is_redirection() => {
+            info!(
+                path = %config.path,
+                status = response.status().as_u16(),
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "✅ Startup self-test passed"
+            );
+            return true;
+        }
+        Ok(response) => format!("expected a 2xx or 3xx status, got {}", response.status().as_u16()),
+        Err(e) => format!("{:#}", e),
+    };
+
+    match sent {
+        Some(sent) => error!(
+            path = %config.path,
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            problem = %problem,
+            frame_sent = %sent,
+            response_received = %received.map_or_else(|| "none".to_string(), |received| received.to_string()),
+            "Startup self-test request failed"
+        ),
+        None => {
+            if log_throttle().allow("self_test", Level::WARN, &problem) {
+                warn!(path = %config.path, problem = %problem, "Startup self-test still failing");
+            }
+        }
+    }
+    false
+}
@@ -1,14 +1,54 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use base64;
+use futures::{FutureExt, StreamExt};
 use hyper::service::{make_service_fn, service_fn};
+use hyper::body::Bytes;
+use hyper::header::HeaderValue;
 use hyper::{header, Body, Request, Response, Server, StatusCode};
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
-use tracing::{debug, error, info};
+use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
+use tracing::{debug, error, info, trace, warn, Instrument, Level};
 
-use crate::bridge::socket_bridge::SocketBridge;
+use crate::accept::Incoming;
+use crate::bridge::socket_bridge::{is_connection_failure, SocketBridge};
+use crate::bridge::PhpResponse;
+use crate::coalesce::{Coalescer, FlightResult, Leader, Role, SharedResponse};
+use crate::deadline::Deadline;
+use crate::drain::Drain;
+use crate::runtime_info::{InfoListener, RuntimeInfo};
+use crate::fastcgi::{FastCgiClient, RequestInfo};
+use crate::websocket::BroadcastHub;
+use crate::metrics::{metrics, MetricKind};
+use crate::etag::DynamicEtags;
+use crate::errors::{ErrorDetail, ServerError, SharedErrorRenderer, UnavailableReason};
+use crate::header_scrub::HeaderScrub;
+use crate::html_transform::{HtmlTransform, SharedResponseTransformer};
+use crate::hooks::{HookRunner, SharedRequestHooks};
+use crate::log_throttle::log_throttle;
+use crate::ip_filter::IpFilter;
+use crate::recording::{Recorder, RecordingConfig, RECORD_HEADER};
+use crate::request_context::{PriorityPaths, QuietPaths, RequestClass, RequestContext};
+use crate::response_headers::ResponseHeaders;
+use crate::response_validation::ResponseValidation;
+use crate::favicon::FaviconPolicy;
+use crate::mime::MimeTypes;
+use crate::sniff::{self, SNIFF_LEN};
+use crate::file_cache::FileCache;
+use crate::static_cache::{AssetManifest, CachePolicy};
+use crate::tenants::{Tenant, Tenants, FORWARDED_PREFIX_HEADER};
+use crate::tls::{Connection, Connections, TlsConfig, TlsListener};
+use crate::trailing_slash::TrailingSlash;
+use crate::trusted_proxies::TrustedProxies;
+use crate::worker_protocol::WorkerCodec;
 
 use crate::config::AppConfig;
+use crate::config_validation::settings_error;
 
 /// Represents an HTTP request that will be forwarded to Laravel
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -16,22 +56,199 @@ pub struct HttpRequestPayload {
     pub method: String,
     pub uri: String,
     pub headers: std::collections::HashMap<String, String>,
-    pub body: Option<String>,
+    /// Body as read from the client; serialized as a string when it is
+    /// UTF-8 and as an array of bytes otherwise
+    #[serde(with = "body_serde::option")]
+    pub body: Option<Bytes>,
     pub query_params: std::collections::HashMap<String, String>,
 }
 
+impl HttpRequestPayload {
+    /// Payload for a request made up outside the HTTP listener (`bench`, `send --http`)
+    ///
+    /// `uri` is a path with an optional query string; a body is sent as
+    /// `application/octet-stream`.
+    #[doc(hidden)]
+    pub fn synthetic(method: &str, uri: &str, body: Option<Bytes>) -> Self {
+        let mut headers = std::collections::HashMap::from([("host".to_string(), "localhost".to_string())]);
+        if body.is_some() {
+            headers.insert("content-type".to_string(), "application/octet-stream".to_string());
+        }
+        Self {
+            method: method.to_ascii_uppercase(),
+            uri: uri.to_string(),
+            headers,
+            body,
+            query_params: extract_query_params(uri.split_once('?').map(|(_, query)| query)),
+        }
+    }
+}
+
 /// Represents the response from Laravel
 #[derive(Deserialize, Debug)]
 pub struct HttpResponsePayload {
     pub status: u16,
     pub headers: std::collections::HashMap<String, String>,
-    pub body: String,
+    #[serde(with = "body_serde")]
+    pub body: Bytes,
 }
 
+/// Liveness probe path, answered without touching the bridge
+const HEALTH_PATH: &str = "/healthz";
+/// Readiness probe path, reports whether the PHP worker is reachable
+const READY_PATH: &str = "/readyz";
+/// Path whose handler panics, for the tests of the panic guard (`test-worker` feature)
+#[cfg(feature = "test-worker")]
+const PANIC_PATH: &str = "/__test/panic";
+/// Retry-After value (seconds) sent while the worker is still starting
+const STARTUP_RETRY_AFTER_SECS: u64 = 1;
+
+/// Bytes a request frame adds around the body, for a request without headers
+///
+/// Every frame has at least these, so a body longer than
+/// `SOCKET_MAX_FRAME_SIZE` minus this can never be sent.
+static FRAME_ENVELOPE: Lazy<usize> = Lazy::new(|| {
+    let empty = HttpRequestPayload {
+        method: String::new(),
+        uri: String::new(),
+        headers: std::collections::HashMap::new(),
+        body: Some(Bytes::new()),
+        query_params: std::collections::HashMap::new(),
+    };
+    serde_json::to_vec(&request_frame(empty, "")).map_or(0, |frame| frame.len())
+});
+
 /// Main HTTP server struct
 pub struct HttpServer {
     config: crate::config::ServerConfig,
+    ready: Arc<AtomicBool>,
+    /// Drain mode, toggled from the admin listener or SIGUSR2
+    drain: Arc<Drain>,
+    /// Build and runtime info for deploy tooling (`INFO_PATH`)
+    info: Arc<RuntimeInfo>,
+    /// Terminates TLS on the listener (`TLS_ENABLED`); `None` serves plain HTTP
+    tls: Option<TlsListener>,
+    /// Listener bound ahead of `start` (e.g. before dropping privileges)
+    listener: std::sync::Mutex<Option<std::net::TcpListener>>,
+    /// Small static files kept in memory (`STATIC_MEMORY_CACHE_BYTES`)
+    file_cache: Arc<FileCache>,
+    /// Laravel's public directory (`PUBLIC_PATH`), resolved once so the stats name the one served
+    public_dir: String,
+    /// Request handler state, read from the environment when the server is created
+    ///
+    /// Only shared once `start` runs; the `with_*` builders change it before that.
+    state: Arc<ServerState>,
+    /// How often the Vite/Mix manifests are reloaded (`STATIC_MANIFEST_RELOAD_MS`), 0 for never
+    manifest_reload_ms: u64,
+    /// How often the memory cache looks for changed files (`STATIC_MEMORY_CACHE_RESCAN_MS`), 0 for never
+    memory_cache_rescan_ms: u64,
+}
+
+/// State shared by all request handlers
+struct ServerState {
     socket_bridge: Arc<SocketBridge>,
+    /// Set once the PHP worker has been confirmed reachable
+    ready: Arc<AtomicBool>,
+    /// Readiness fails and new requests get 503 while draining
+    drain: Arc<Drain>,
+    /// Answers `INFO_PATH` when it is on this listener
+    info: Arc<RuntimeInfo>,
+    /// Send long-lived Cache-Control headers for static files
+    static_cache: bool,
+    /// Cache-Control rules for static files
+    cache_policy: CachePolicy,
+    /// Laravel's public directory, where static files are served from
+    public_dir: String,
+    /// Versioned assets from the Vite/Mix manifests
+    assets: Arc<AssetManifest>,
+    /// Static files larger than this are streamed from disk instead of read into memory
+    static_stream_threshold: u64,
+    /// Contents of small static files, shared with the admin listener
+    file_cache: Arc<FileCache>,
+    /// Directories outside the public directory that symlinks in it may point into
+    static_symlink_roots: Vec<PathBuf>,
+    /// Answer for a missing favicon or touch icon
+    favicon: FaviconPolicy,
+    /// Canonical spelling of paths with or without a trailing slash
+    trailing_slash: TrailingSlash,
+    /// Weak ETags and `304` answers for responses of the PHP worker
+    etags: DynamicEtags,
+    /// Rewrites of HTML pages of the PHP worker
+    html_transform: HtmlTransform,
+    /// HTML index of directories under the static roots
+    #[cfg(feature = "dir-listing")]
+    dir_listing: crate::dir_listing::DirListing,
+    /// Content types of static files by extension
+    mime_types: MimeTypes,
+    /// Detect the content type of files without a known extension from their first bytes
+    sniff_content_type: bool,
+    /// Extra headers added to every response
+    response_headers: ResponseHeaders,
+    /// How much of an error is shown in error responses
+    error_detail: ErrorDetail,
+    /// Resend idempotent requests once when the connection to the worker fails
+    retry_idempotent: bool,
+    /// Whether malformed worker responses are served through fallbacks or rejected
+    response_validation: ResponseValidation,
+    error_renderer: SharedErrorRenderer,
+    hooks: Option<HookRunner>,
+    /// Health-check paths kept out of the regular logs and metrics
+    quiet_paths: QuietPaths,
+    /// Health-check paths served by Laravel that bypass the bridge limits
+    priority_paths: PriorityPaths,
+    /// Shares one worker response between identical concurrent GETs, when enabled
+    coalescer: Option<Arc<Coalescer>>,
+    /// php-fpm backend used instead of the socket bridge, when configured
+    fastcgi: Option<Arc<FastCgiClient>>,
+    /// Accepts WebSocket clients on its path, when enabled
+    broadcast: Option<Arc<BroadcastHub>>,
+    /// Writes requests to RECORD_DIR, when recording is on
+    recorder: Option<Arc<Recorder>>,
+    /// Request headers removed unless set by a trusted proxy, or always
+    header_scrub: HeaderScrub,
+    /// Proxies whose `X-Forwarded-For` names the client
+    trusted_proxies: TrustedProxies,
+    /// Client address allow and deny lists
+    ip_filter: IpFilter,
+    /// Other applications served under path prefixes
+    tenants: Arc<Tenants>,
+}
+
+impl ServerState {
+    /// Error response for `error`, rendered by the configured renderer
+    fn error_response(&self, error: anyhow::Error, context: &RequestContext) -> Response<Body> {
+        crate::errors::handle_error_response(error, context, self.error_detail, self.error_renderer.as_ref())
+    }
+
+    /// Class of a request to `path` on the HTTP listener
+    fn classify(&self, path: &str) -> RequestClass {
+        if path == HEALTH_PATH || path == READY_PATH || self.priority_paths.contains(path) {
+            RequestClass::Health
+        } else {
+            RequestClass::Normal
+        }
+    }
+
+    /// Largest request body the backend of `tenant` can be sent, if limited
+    ///
+    /// A frame carries the body along with the rest of the request, so the
+    /// limit leaves room for the [`FRAME_ENVELOPE`].
+    fn body_limit(&self, tenant: Option<&Arc<Tenant>>) -> Option<usize> {
+        let max_frame_size = match (tenant, &self.fastcgi) {
+            (Some(tenant), _) => tenant.socket_bridge().max_frame_size(),
+            (None, Some(_)) => return None,
+            (None, None) => self.socket_bridge.max_frame_size(),
+        };
+        Some(max_frame_size.saturating_sub(*FRAME_ENVELOPE))
+    }
+
+    /// Longest a request to the backend may take
+    fn backend_timeout(&self) -> Duration {
+        match &self.fastcgi {
+            Some(fastcgi) => fastcgi.config().read_timeout,
+            None => self.socket_bridge.read_timeout(),
+        }
+    }
 }
 
 impl HttpServer {
@@ -41,8 +258,7 @@ impl HttpServer {
     ) -> Result<Self> {
         dotenvy::dotenv().ok();
         let config = crate::config::ServerConfig::from_env()?;
-
-        Ok(HttpServer { config, socket_bridge })
+        Self::from_env(config, socket_bridge)
     }
 
     /// Create a new HTTP server instance with configuration
@@ -50,67 +266,524 @@ impl HttpServer {
         socket_bridge: Arc<SocketBridge>,
         app_config: &AppConfig,
     ) -> Result<Self> {
+        Self::from_env(app_config.server.clone(), socket_bridge)
+    }
+
+    /// Server for `config`, with every other setting read from the environment now
+    ///
+    /// Nothing is read from the environment later, so an embedder that sets
+    /// it only around the construction (the C API) gets exactly its values,
+    /// and an invalid setting fails here rather than once the server runs.
+    fn from_env(config: crate::config::ServerConfig, socket_bridge: Arc<SocketBridge>) -> Result<Self> {
+        let ready = Arc::new(AtomicBool::new(false));
+        let drain = Arc::new(Drain::from_env());
+        let info = Arc::new(runtime_info_from_env()?);
+        let file_cache = Arc::new(FileCache::from_env());
+        let public_dir = public_dir_from_env();
+
+        let state = ServerState {
+            socket_bridge,
+            ready: ready.clone(),
+            drain: drain.clone(),
+            info: info.clone(),
+            static_cache: std::env::var("STATIC_CACHE_ENABLED")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            cache_policy: CachePolicy::from_env().map_err(|problems| settings_error("static cache rules", problems))?,
+            assets: AssetManifest::load(&public_dir),
+            public_dir: public_dir.clone(),
+            static_stream_threshold: std::env::var("STATIC_STREAM_THRESHOLD")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_STATIC_STREAM_THRESHOLD),
+            file_cache: file_cache.clone(),
+            static_symlink_roots: std::env::var("STATIC_SYMLINK_ROOTS")
+                .unwrap_or_else(|_| DEFAULT_STATIC_SYMLINK_ROOTS.to_string())
+                .split(',')
+                .map(str::trim)
+                .filter(|root| !root.is_empty())
+                .map(PathBuf::from)
+                .collect(),
+            favicon: FaviconPolicy::from_env().map_err(|problems| settings_error("favicon settings", problems))?,
+            trailing_slash: TrailingSlash::from_env()
+                .map_err(|problems| settings_error("trailing slash policy", problems))?,
+            #[cfg(feature = "dir-listing")]
+            dir_listing: crate::dir_listing::DirListing::from_env(),
+            etags: DynamicEtags::from_env(),
+            html_transform: HtmlTransform::from_env()
+                .map_err(|problems| settings_error("HTML transform settings", problems))?,
+            mime_types: MimeTypes::from_env().map_err(|problems| settings_error("content types", problems))?,
+            sniff_content_type: std::env::var("STATIC_SNIFF_CONTENT_TYPE")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            response_headers: ResponseHeaders::from_env()
+                .map_err(|problems| anyhow::anyhow!("Invalid RESPONSE_HEADERS: {}", problems.join("; ")))?,
+            error_detail: ErrorDetail::from_env(),
+            retry_idempotent: std::env::var("SOCKET_RETRY_IDEMPOTENT")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            response_validation: ResponseValidation::from_env(),
+            error_renderer: crate::errors::renderer_from_env(),
+            hooks: None,
+            quiet_paths: QuietPaths::from_env()
+                .map_err(|problems| anyhow::anyhow!("Invalid QUIET_PATHS: {}", problems.join("; ")))?,
+            priority_paths: PriorityPaths::from_env()
+                .map_err(|problems| anyhow::anyhow!("Invalid PRIORITY_PATHS: {}", problems.join("; ")))?,
+            coalescer: std::env::var("COALESCE_REQUESTS")
+                .is_ok_and(|v| v == "true" || v == "1")
+                .then(Coalescer::new),
+            fastcgi: None,
+            broadcast: None,
+            recorder: Recorder::new(RecordingConfig::from_env())?,
+            header_scrub: HeaderScrub::from_env().map_err(|problems| settings_error("header scrub lists", problems))?,
+            trusted_proxies: TrustedProxies::from_env()
+                .map_err(|problems| anyhow::anyhow!("Invalid TRUSTED_PROXIES: {}", problems.join("; ")))?,
+            ip_filter: IpFilter::from_env().map_err(|problems| settings_error("IP filter", problems))?,
+            tenants: Arc::default(),
+        };
+
         Ok(HttpServer {
-            config: app_config.server.clone(),
-            socket_bridge
+            config,
+            ready,
+            drain,
+            info,
+            tls: tls_from_env()?,
+            listener: std::sync::Mutex::new(None),
+            file_cache,
+            public_dir,
+            state: Arc::new(state),
+            manifest_reload_ms: std::env::var("STATIC_MANIFEST_RELOAD_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MANIFEST_RELOAD_MS),
+            memory_cache_rescan_ms: std::env::var("STATIC_MEMORY_CACHE_RESCAN_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(crate::file_cache::DEFAULT_RESCAN_MS),
         })
     }
 
+    /// Request handler state, for the `with_*` builders
+    fn state_mut(&mut self) -> &mut ServerState {
+        Arc::get_mut(&mut self.state).expect("the server state is only shared once the server starts")
+    }
+
+    /// Render error responses with `renderer` instead of the configured format
+    pub fn with_error_renderer(mut self, renderer: SharedErrorRenderer) -> Self {
+        self.state_mut().error_renderer = renderer;
+        self
+    }
+
+    /// Call `hooks` around every request
+    pub fn with_request_hooks(mut self, hooks: SharedRequestHooks) -> Self {
+        self.state_mut().hooks = Some(HookRunner::new(hooks));
+        self
+    }
+
+    /// Rewrite HTML pages of the PHP worker with `transformer`
+    ///
+    /// Transformers run in the order they were added, after the snippet of
+    /// `HTML_INJECT_SNIPPET`.
+    pub fn with_response_transformer(mut self, transformer: SharedResponseTransformer) -> Self {
+        self.state_mut().html_transform.push(transformer);
+        self
+    }
+
+    /// Send requests to php-fpm through `client` instead of the socket bridge
+    pub fn with_fastcgi(mut self, client: Arc<FastCgiClient>) -> Self {
+        self.state_mut().fastcgi = Some(client);
+        self
+    }
+
+    /// Accept WebSocket clients of `hub` on its path
+    pub fn with_broadcast(mut self, hub: Arc<BroadcastHub>) -> Self {
+        self.state_mut().broadcast = Some(hub);
+        self
+    }
+
+    /// Send requests under the prefixes of `tenants` to their applications
+    pub fn with_tenants(mut self, tenants: Arc<Tenants>) -> Self {
+        self.state_mut().tenants = tenants;
+        self
+    }
+
+    /// Readiness flag; non-static requests get 503 until it is set
+    pub fn readiness(&self) -> Arc<AtomicBool> {
+        self.ready.clone()
+    }
+
+    /// Drain mode of the listener, shared with the admin listener
+    pub fn drain(&self) -> Arc<Drain> {
+        self.drain.clone()
+    }
+
+    /// Build and runtime info, shared with the admin listener
+    pub fn runtime_info(&self) -> Arc<RuntimeInfo> {
+        self.info.clone()
+    }
+
+    /// Static files in memory, flushed from the admin listener
+    pub fn file_cache(&self) -> Arc<FileCache> {
+        self.file_cache.clone()
+    }
+
+    /// Directory static files are served from, as resolved when the server was created
+    pub fn public_dir(&self) -> &str {
+        &self.public_dir
+    }
+
+    /// Bind the listening socket now instead of in `start`
+    ///
+    /// Used to grab privileged ports while still running as root. A listener
+    /// on the same address inherited through `LISTEN_FDS` (binary upgrade,
+    /// socket activation) is adopted instead of binding a new one.
+    pub fn bind(&self) -> Result<std::net::SocketAddr> {
+        let addr: std::net::SocketAddr = format!("{}:{}", self.config.host, self.config.port).parse()?;
+        // After a binary upgrade the previous process passes its listener in
+        let listener = match crate::upgrade::take_tcp_listener("http", addr) {
+            Some(listener) => listener,
+            None => std::net::TcpListener::bind(addr).map_err(|e| {
+                error!("Failed to bind to {}: {}", addr, e);
+                e
+            })?,
+        };
+        listener.set_nonblocking(true)?;
+        crate::upgrade::register("http", &listener);
+        let local_addr = listener.local_addr()?;
+        *self.listener.lock().unwrap_or_else(|e| e.into_inner()) = Some(listener);
+        Ok(local_addr)
+    }
+
     /// Start the HTTP server
+    #[allow(dead_code)]
     pub async fn start(&self) -> Result<()> {
-        let addr = format!("{}:{}", self.config.host, self.config.port)
+        self.start_with_shutdown(std::future::pending()).await
+    }
+
+    /// Start the HTTP server and stop accepting connections once `shutdown` resolves
+    ///
+    /// In-flight requests are allowed to complete before the returned future resolves.
+    pub async fn start_with_shutdown<F>(&self, shutdown: F) -> Result<()>
+    where
+        F: std::future::Future<Output = ()>,
+    {
+        let addr: std::net::SocketAddr = format!("{}:{}", self.config.host, self.config.port)
             .parse()
             .map_err(|e| {
                 error!("Failed to parse server address: {}", e);
                 Box::new(e)
             })?;
 
-        let socket_bridge = self.socket_bridge.clone();
+        // A missing public directory is not fatal: static file requests then get 404
+        if !Path::new(&self.public_dir).is_dir() {
+            warn!(
+                public_dir = %self.public_dir,
+                "Public directory does not exist, static files will not be served; set PUBLIC_PATH or LARAVEL_PATH"
+            );
+        }
+
+        let state = self.state.clone();
+        if self.manifest_reload_ms > 0 {
+            // Exits once the server state is dropped
+            state.assets.spawn_watcher(Duration::from_millis(self.manifest_reload_ms));
+            state.tenants.spawn_manifest_watchers(Duration::from_millis(self.manifest_reload_ms));
+        }
+
+        if state.file_cache.is_enabled() {
+            state.file_cache.track_manifest(&state.assets);
+            state.tenants.track_manifests(&state.file_cache);
+            if self.memory_cache_rescan_ms > 0 {
+                state.file_cache.spawn_watcher(Duration::from_millis(self.memory_cache_rescan_ms));
+            }
+        }
+
+        if state.ip_filter.is_active() {
+            info!("🧱 Checking client addresses against IP_ALLOW/IP_DENY and {} IP_RULES entry(ies)", state.ip_filter.rule_count());
+        }
+
+        if !state.response_headers.is_empty() {
+            info!("🏷️  Adding {} configured header(s) to every response", state.response_headers.len());
+        }
 
         info!("🚀 Starting HTTP server on {}:{}", self.config.host, self.config.port);
-        info!("🔌 Connecting to Laravel via Unix socket: {}", self.config.socket_path);
+        if self.tls.is_some() {
+            info!("🔒 Terminating TLS on the HTTP listener");
+        }
+        match &state.fastcgi {
+            Some(fastcgi) => info!(
+                "🔌 Connecting to Laravel via FastCGI: {} ({})",
+                fastcgi.config().address,
+                fastcgi.config().script_filename
+            ),
+            None => info!("🔌 Connecting to Laravel via Unix socket: {}", self.config.socket_path),
+        }
+        if !state.tenants.is_empty() {
+            info!("🏢 Routing {} path prefix(es) to other Laravel applications", state.tenants.len());
+        }
 
-        let make_svc = make_service_fn(move |_conn| {
-            let socket_bridge = socket_bridge.clone();
+        let make_svc = make_service_fn(move |conn: &Connection| {
+            let state = state.clone();
+            let peer_ip = conn.remote_addr().ip();
+            let secure = conn.is_tls();
+            let client_cert = conn.peer_certificate().cloned();
+            // Connections opened before a drain get its grace period
+            let connected = Instant::now();
 
             async move {
-                Ok::<_, hyper::Error>(service_fn(move |req| {
-                    let socket_bridge = socket_bridge.clone();
-                    handle_request(req, socket_bridge)
+                Ok::<_, hyper::Error>(service_fn(move |mut req: Request<Body>| {
+                    let state = state.clone();
+                    // Headers the peer may not set are gone before anything reads them
+                    let trusted_peer = state.trusted_proxies.contains(peer_ip.to_canonical());
+                    state.header_scrub.apply(req.headers_mut(), peer_ip, trusted_peer);
+                    let client_ip = state.trusted_proxies.resolve(peer_ip, req.headers());
+                    let mut context = RequestContext::new(&req, client_ip);
+                    context.secure = secure;
+                    context.client_cert = client_cert.clone();
+                    if trusted_peer {
+                        context.caller_budget = crate::deadline::caller_budget(req.headers());
+                    }
+                    context.quiet = state.quiet_paths.matching(&context.path).map(str::to_string);
+                    context.class = state.classify(&context.path);
+                    context.recording = state.recorder.as_ref().and_then(|recorder| recorder.start(req.headers(), &context));
+                    let span = context.span();
+                    crate::telemetry::set_remote_parent(&span, req.headers());
+                    async move {
+                        // A panic must not tear down the connection without a response
+                        let handled = AssertUnwindSafe(handle_request(req, state.clone(), context.clone(), connected)).catch_unwind();
+                        #[cfg(feature = "chaos")]
+                        let handled = {
+                            let (handled, fault) = crate::chaos::track(handled).await;
+                            context.chaos = fault;
+                            handled
+                        };
+                        #[cfg(not(feature = "chaos"))]
+                        let handled = handled.await;
+                        let mut response = match handled {
+                            Ok(result) => result?,
+                            Err(panic) => {
+                                let message = crate::errors::panic_message(panic.as_ref());
+                                error!(panic = %message, "Request handler panicked");
+                                state.error_response(
+                                    ServerError::Internal(format!("request handler panicked: {}", message)).into(),
+                                    &context,
+                                )
+                            }
+                        };
+                        state.response_headers.apply(&mut response);
+                        context.finish(response.status());
+                        state.tenants.record(&context.path, response.status());
+                        if let Some(recording) = &context.recording {
+                            recording.finish(&response, &context);
+                        }
+                        if let Some(hooks) = &state.hooks {
+                            hooks.after_response(response.status(), context.elapsed());
+                        }
+                        Ok::<_, hyper::Error>(response)
+                    }
+                    .instrument(span)
                 }))
             }
         });
 
-        let server = Server::try_bind(&addr)
-            .map_err(|e| {
+        let prebound = self.listener.lock().unwrap_or_else(|e| e.into_inner()).take();
+        let listener = match prebound {
+            Some(listener) => tokio::net::TcpListener::from_std(listener)?,
+            None => tokio::net::TcpListener::bind(addr).await.map_err(|e| {
                 error!("Failed to bind to {}: {}", addr, e);
-                Box::new(e)
-            })?
-            .serve(make_svc);
+                e
+            })?,
+        };
+
+        // Our own accept loop: EMFILE and aborted connections must not end the server
+        let connections = Connections::new(Incoming::new(listener), self.tls.clone());
+        let server = Server::builder(connections).serve(make_svc).with_graceful_shutdown(shutdown);
 
-        server.await.map_err(|e| anyhow::Error::from(e))
+        server
+            .await
+            .with_context(|| format!("HTTP server on {} stopped accepting connections", addr))
     }
 }
 
 /// Handle incoming HTTP requests and forward them to Laravel
-async fn handle_request(req: Request<Body>, socket_bridge: Arc<SocketBridge>) -> Result<Response<Body>, hyper::Error> {
-    debug!("Received request: {} {}", req.method(), req.uri());
+///
+/// `connected` is when the request's connection was accepted.
+async fn handle_request(
+    req: Request<Body>,
+    state: Arc<ServerState>,
+    context: RequestContext,
+    connected: Instant,
+) -> Result<Response<Body>, hyper::Error> {
+    if context.quiet.is_some() {
+        trace!("Received request: {} {}", req.method(), req.uri());
+    } else {
+        debug!("Received request: {} {}", req.method(), req.uri());
+    }
 
-    // Check if this is a static file request (favicon.ico, assets, etc.)
     let uri_path = req.uri().path();
-    if is_static_file_request(uri_path) {
-        return handle_static_file_request(uri_path).await;
+    let query = req.uri().query();
+    let is_ready = state.ready.load(Ordering::Acquire);
+
+    #[cfg(feature = "test-worker")]
+    if uri_path == PANIC_PATH {
+        panic!("deliberate panic for {}", PANIC_PATH);
+    }
+
+    // Health probes never touch the bridge
+    if uri_path == HEALTH_PATH {
+        return Ok(probe_response(StatusCode::OK, "ok"));
+    }
+    if uri_path == READY_PATH {
+        // Ready only once every application behind the listener is
+        let waiting: Vec<&str> = state.tenants.not_ready().collect();
+        return Ok(if !is_ready {
+            probe_response(StatusCode::SERVICE_UNAVAILABLE, "not ready")
+        } else if state.drain.is_draining() {
+            probe_response(StatusCode::SERVICE_UNAVAILABLE, "draining")
+        } else if !waiting.is_empty() {
+            probe_response(StatusCode::SERVICE_UNAVAILABLE, format!("not ready: {}", waiting.join(", ")))
+        } else {
+            probe_response(StatusCode::OK, "ready")
+        });
+    }
+
+    let is_info = req.method() == hyper::Method::GET && state.info.matches(InfoListener::Http, uri_path);
+
+    // Out of the load balancer: only health checks and the info endpoint are answered
+    if context.class != RequestClass::Health && !is_info && state.drain.refuses(connected) {
+        let error = ServerError::Unavailable {
+            reason: UnavailableReason::Maintenance,
+            retry_after: None,
+            message: "server is draining".to_string(),
+        };
+        // The client retries elsewhere instead of on this connection
+        let mut response = state.error_response(error.into(), &context);
+        response.headers_mut().insert(header::CONNECTION, HeaderValue::from_static("close"));
+        return Ok(response);
+    }
+
+    // Denied clients reach neither static files nor the backend
+    if let Some(scope) = state.ip_filter.denied_by(uri_path, context.client_ip) {
+        let message = format!("request from {} denied by the {} IP rules", context.client_ip, scope);
+        if log_throttle().allow(&format!("ip_filter:{}", scope), Level::INFO, &message) {
+            info!(client_ip = %context.client_ip, scope, path = uri_path, "Request denied by IP rules");
+        }
+        return Ok(state.ip_filter.denied_response(scope));
+    }
+
+    // What is running, for deploy tooling; answered while PHP is down too
+    if is_info {
+        return Ok(state.info.response(context.client_ip));
+    }
+
+    // WebSocket clients of the broadcast fan-out
+    if let Some(hub) = &state.broadcast {
+        if uri_path == hub.config().path {
+            return Ok(hub
+                .accept(req, context.client_ip)
+                .unwrap_or_else(|e| state.error_response(e, &context)));
+        }
+    }
+
+    // Requests under a TENANTS prefix go to that application
+    let tenant = state.tenants.select(uri_path);
+    let app_path = tenant.map_or(uri_path, |tenant| tenant.app_path(uri_path));
+    // Files on disk are named by the decoded path; logs keep the encoded one
+    let file_path = decode_static_path(app_path);
+    let is_static = is_static_file_request(file_path.as_deref().unwrap_or(app_path));
+
+    // Redirect to the TRAILING_SLASH spelling, unless the path is on disk as it is
+    if let Some(canonical) = state.trailing_slash.canonical(uri_path, app_path, is_static) {
+        let public_dir = match tenant {
+            Some(tenant) => tenant.static_root().map(|(public_dir, _)| public_dir),
+            None => Some(state.public_dir.as_str()),
+        };
+        let on_disk = match (public_dir, &file_path) {
+            (Some(public_dir), Ok(file_path)) => TrailingSlash::exists_in(public_dir, file_path).await,
+            _ => false,
+        };
+        if !on_disk {
+            debug!(location = %canonical, "Redirecting to the TRAILING_SLASH spelling of the path");
+            return Ok(TrailingSlash::redirect(req.method(), &canonical, query));
+        }
+    }
+
+    // Development listings of directories under the static roots
+    #[cfg(feature = "dir-listing")]
+    if let Ok(file_path) = &file_path {
+        let public_dir = match tenant {
+            Some(tenant) => tenant.static_root().map(|(public_dir, _)| public_dir),
+            None => Some(state.public_dir.as_str()),
+        };
+        if let Some(public_dir) = public_dir {
+            if let Some(listing) = state.dir_listing.respond(public_dir, file_path, uri_path).await {
+                return Ok(listing);
+            }
+        }
+    }
+
+    // Check if this is a static file request (favicon.ico, assets, etc.)
+    if is_static {
+        let root = match tenant {
+            Some(tenant) => tenant.static_root(),
+            None => Some((state.public_dir.as_str(), &*state.assets)),
+        };
+        if let Some((public_dir, assets)) = root {
+            return match &file_path {
+                Ok(file_path) => {
+                    let if_none_match = req.headers().get(header::IF_NONE_MATCH);
+                    handle_static_file_request(file_path, query, if_none_match, public_dir, assets, &state, &context).await
+                }
+                Err(problem) => {
+                    let error = ServerError::NotFound(format!("{}: {}", app_path, problem));
+                    Ok(state.error_response(error.into(), &context))
+                }
+            };
+        }
+    }
+
+    // Fail fast while the PHP worker is still starting
+    if !tenant.map_or(is_ready, |tenant| tenant.is_ready()) {
+        let error = ServerError::Unavailable {
+            reason: UnavailableReason::BridgeDown,
+            retry_after: Some(Duration::from_secs(STARTUP_RETRY_AFTER_SECS)),
+            message: "PHP worker is not ready yet".to_string(),
+        };
+        return Ok(state.error_response(error.into(), &context));
+    }
+
+    // Resilience tests may answer with an error without touching the backend
+    #[cfg(feature = "chaos")]
+    if let Some(error) = crate::chaos::http_fault(req.headers()) {
+        return Ok(state.error_response(error, &context));
+    }
+
+    // hyper sends `100 Continue` when the body is first read. A body that
+    // cannot fit in a frame is refused before that, so a client waiting on
+    // `Expect: 100-continue` gets the 413 without uploading it.
+    let body_limit = state.body_limit(tenant);
+    if let Some(error) = oversized_body(req.headers(), body_limit) {
+        return Ok(state.error_response(error.into(), &context));
     }
 
     // Extract request data
     let method = req.method().clone();
     let uri = req.uri().clone();
     let headers = req.headers().clone();
-    let body_bytes = hyper::body::to_bytes(req.into_body()).await
-        .map_err(|e| {
+    // A chunked body declares no length, so it is refused once it outgrows the frame
+    let body_bytes = match read_body(req.into_body(), body_limit).await {
+        Ok(Some(body_bytes)) => body_bytes,
+        Ok(None) => {
+            let error = ServerError::PayloadTooLarge(format!(
+                "request body exceeds the {} bytes a frame of SOCKET_MAX_FRAME_SIZE can carry",
+                body_limit.unwrap_or_default()
+            ));
+            return Ok(state.error_response(error.into(), &context));
+        }
+        Err(e) => {
             tracing::error!("Failed to read request body: {}", e);
-            hyper::Error::from(e)
-        })?;
+            return Err(e);
+        }
+    };
 
     // Convert headers to HashMap
     let mut header_map = std::collections::HashMap::new();
@@ -119,6 +792,16 @@ async fn handle_request(req: Request<Body>, socket_bridge: Arc<SocketBridge>) ->
             header_map.insert(name.as_str().to_string(), value_str.to_string());
         }
     }
+    // The recording secret is meant for the bridge only
+    header_map.remove(RECORD_HEADER);
+    // Let the application log the same request id
+    header_map
+        .entry(crate::request_context::REQUEST_ID_HEADER.to_string())
+        .or_insert_with(|| context.id.clone());
+    // An application behind a stripped prefix builds its URLs from this
+    if let Some(tenant) = tenant.filter(|tenant| tenant.strips_prefix()) {
+        header_map.insert(FORWARDED_PREFIX_HEADER.to_string(), tenant.prefix().to_string());
+    }
 
     // Parse query parameters
     let query_params = extract_query_params(uri.query());
@@ -126,28 +809,163 @@ async fn handle_request(req: Request<Body>, socket_bridge: Arc<SocketBridge>) ->
     // Create request payload for Laravel
     let payload = HttpRequestPayload {
         method: method.to_string(),
-        uri: uri.to_string(),
+        uri: tenant.map_or_else(|| uri.to_string(), |tenant| tenant.app_uri(&uri)),
         headers: header_map,
-        body: if body_bytes.is_empty() {
-            None
-        } else {
-            String::from_utf8(body_bytes.to_vec()).ok()
-        },
+        body: (!body_bytes.is_empty()).then_some(body_bytes),
         query_params,
     };
+    if let Some(recording) = &context.recording {
+        recording.request(&payload);
+    }
+
+    // The embedder may answer the request itself
+    if let Some(hooks) = &state.hooks {
+        if let Some(status) = hooks.before_request(&payload).await {
+            debug!(status = status.as_u16(), "Request answered by the request hook");
+            return Ok(Response::builder()
+                .status(status)
+                .body(Body::empty())
+                .unwrap_or_else(|_| internal_server_error()));
+        }
+    }
+
+    // Identical anonymous GETs in flight share one worker response; the key
+    // keeps the full URI, which tells the applications apart
+    let role = state
+        .coalescer
+        .as_ref()
+        .and_then(|coalescer| Some(coalescer.join(Coalescer::key(&payload.method, &uri.to_string(), &payload.headers)?)));
 
     // Send request to Laravel via Unix socket
-    match forward_to_laravel(&socket_bridge, payload).await {
-        Ok(response) => Ok(response),
-        Err(e) => {
-            error!("Error forwarding request to Laravel: {}", e);
-            // Use the centralized error handler
-            Ok(crate::errors::handle_error_response(e))
+    let result = match role {
+        None => forward_to_backend(&state, tenant, payload, &context).await,
+        Some(Role::Leader(leader)) => forward_as_leader(leader, &state, tenant, payload, &context).await,
+        Some(Role::Follower(follower)) => match follower.wait(state.backend_timeout()).await {
+            Some(FlightResult::Shared(shared)) => {
+                debug!(coalesced_with = %shared.request_id, "Response shared from an identical request in flight");
+                Ok(shared.to_response())
+            }
+            Some(FlightResult::NotShared) => forward_to_backend(&state, tenant, payload, &context).await,
+            None => Err(ServerError::BridgeTimeout(format!(
+                "no response from the identical request in flight within {:?}",
+                state.backend_timeout()
+            ))
+            .into()),
+        },
+    };
+
+    match result {
+        Ok(response) => Ok(state.etags.not_modified(&method, &headers, response)),
+        // The centralized error handler logs and classifies the failure
+        Err(e) => Ok(state.error_response(e, &context)),
+    }
+}
+
+/// Error for a declared `Content-Length` above `limit`
+fn oversized_body(headers: &hyper::HeaderMap, limit: Option<usize>) -> Option<ServerError> {
+    let limit = limit?;
+    let length: u64 = headers.get(header::CONTENT_LENGTH)?.to_str().ok()?.parse().ok()?;
+    (length > limit as u64).then(|| {
+        ServerError::PayloadTooLarge(format!(
+            "request body of {} bytes exceeds the {} bytes a frame of SOCKET_MAX_FRAME_SIZE can carry",
+            length, limit
+        ))
+    })
+}
+
+/// Read the request body, or `None` as soon as it grows past `limit`
+///
+/// Stops reading at the limit, so an upload without `Content-Length` is
+/// never buffered beyond what a frame could carry. A body that arrives in
+/// one chunk is returned as it is, without a copy; a longer one is
+/// collected in a buffer sized from its declared length.
+async fn read_body(mut body: Body, limit: Option<usize>) -> Result<Option<Bytes>, hyper::Error> {
+    use hyper::body::HttpBody;
+
+    let Some(limit) = limit else {
+        return hyper::body::to_bytes(body).await.map(Some);
+    };
+    let expected = usize::try_from(body.size_hint().lower()).unwrap_or(usize::MAX).min(limit);
+
+    let first = match body.data().await {
+        Some(chunk) => chunk?,
+        None => return Ok(Some(Bytes::new())),
+    };
+    if first.len() > limit {
+        return Ok(None);
+    }
+    let second = match body.data().await {
+        Some(chunk) => chunk?,
+        None => return Ok(Some(first)),
+    };
+
+    let mut buffer = Vec::with_capacity(expected.max(first.len()));
+    buffer.extend_from_slice(&first);
+    let mut chunk = second;
+    loop {
+        if buffer.len() + chunk.len() > limit {
+            return Ok(None);
         }
+        buffer.extend_from_slice(&chunk);
+        chunk = match body.data().await {
+            Some(chunk) => chunk?,
+            None => return Ok(Some(Bytes::from(buffer))),
+        };
     }
 }
 
-/// Check if the request is for a static file
+/// Build a plain-text response for the health/readiness probes
+fn probe_response(status: StatusCode, body: impl Into<Body>) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(body.into())
+        .unwrap_or_else(|_| internal_server_error())
+}
+
+/// Static files up to this size are read into memory in one go
+pub const DEFAULT_STATIC_STREAM_THRESHOLD: u64 = 1024 * 1024;
+
+/// Laravel's public directory: `PUBLIC_PATH`, or `public` under `LARAVEL_PATH`
+///
+/// Without either it is `../public`, for a binary run from a directory
+/// inside the Laravel application.
+pub fn public_dir_from_env() -> String {
+    std::env::var("PUBLIC_PATH")
+        .ok()
+        .filter(|path| !path.trim().is_empty())
+        .unwrap_or_else(|| {
+            let laravel_path = std::env::var("LARAVEL_PATH").unwrap_or_else(|_| "..".to_string());
+            Path::new(&laravel_path).join("public").to_string_lossy().into_owned()
+        })
+}
+
+/// Default for `STATIC_SYMLINK_ROOTS`: the target of `php artisan storage:link`
+pub const DEFAULT_STATIC_SYMLINK_ROOTS: &str = "storage/app/public";
+
+/// Default for `STATIC_MANIFEST_RELOAD_MS`
+pub const DEFAULT_MANIFEST_RELOAD_MS: u64 = 2000;
+
+/// Size of the chunks a streamed static file is read in
+const STATIC_STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// `uri_path` percent-decoded, as the static file it names is called on disk
+///
+/// Paths that are not valid UTF-8 once decoded, contain NUL or have a `..`
+/// segment, whether encoded (`%2e%2e`) or not, name no static file.
+fn decode_static_path(uri_path: &str) -> Result<Cow<'_, str>, &'static str> {
+    let decoded = urlencoding::decode(uri_path).map_err(|_| "not valid UTF-8 once decoded")?;
+    if decoded.contains('\0') {
+        return Err("contains NUL");
+    }
+    if decoded.split('/').any(|segment| segment == "..") {
+        return Err("leaves the public directory");
+    }
+    Ok(decoded)
+}
+
+/// Check if the request is for a static file, by its decoded path
 fn is_static_file_request(uri_path: &str) -> bool {
     // Check if the URI path contains file extensions typical for static files
     let static_extensions = [
@@ -166,88 +984,260 @@ fn is_static_file_request(uri_path: &str) -> bool {
     uri_path == "/favicon.ico" || uri_path.starts_with("/assets/") || uri_path.starts_with("/build/")
 }
 
-/// Handle static file requests
-async fn handle_static_file_request(uri_path: &str) -> Result<Response<Body>, hyper::Error> {
-    // Determine the file path relative to the public directory
-    // In Laravel, static files are typically served from the public/ directory
-    let file_path = if uri_path == "/favicon.ico" {
-        // Special case for favicon.ico
-        format!("../public{}", uri_path)
-    } else {
-        // For other static files, construct the path relative to public directory
-        format!("../public{}", uri_path)
+/// Handle static file requests for `uri_path` below `public_dir`
+async fn handle_static_file_request(
+    uri_path: &str,
+    query: Option<&str>,
+    if_none_match: Option<&HeaderValue>,
+    public_dir: &str,
+    assets: &AssetManifest,
+    state: &ServerState,
+    context: &RequestContext,
+) -> Result<Response<Body>, hyper::Error> {
+    // Static files, favicon.ico included, are served from the public directory
+    let file_path = format!("{}{}", public_dir, uri_path);
+
+    // Determine the content type based on file extension, or failing that from its first bytes
+    let known_type = state.mime_types.lookup(&file_path);
+    let sniff = known_type.is_none() && state.sniff_content_type;
+
+    // Open the file where its symlinks lead, if that is still under the root;
+    // small files may be in memory, large ones are streamed
+    let opened = match resolve_static_file(public_dir, &file_path, &state.static_symlink_roots).await {
+        Ok(resolved) => match state.file_cache.get(&context.path, &resolved, state.static_stream_threshold).await {
+            Ok(Some(cached)) => {
+                let length = cached.contents.len() as u64;
+                let sniffed = sniff.then_some(cached.sniffed_type).flatten();
+                Ok((Body::from(cached.contents.clone()), length, sniffed, Some(cached.etag.clone())))
+            }
+            Ok(None) => open_static_file(&resolved, state.static_stream_threshold, sniff)
+                .await
+                .map(|(body, length, sniffed)| (body, length, sniffed, None)),
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
     };
+    match opened {
+        Ok((body, length, sniffed_type, etag)) => {
+            let content_type = crate::mime::resolve(known_type, sniffed_type);
 
-    // Read the file
-    match tokio::fs::read(&file_path).await {
-        Ok(contents) => {
-            // Determine the content type based on file extension
-            let content_type = get_content_type(&file_path);
-            
             let mut response = Response::builder()
                 .status(StatusCode::OK)
                 .header(header::CONTENT_TYPE, content_type)
-                .header(header::CONTENT_LENGTH, contents.len());
+                .header(header::CONTENT_LENGTH, length)
+                .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff");
 
             // Add caching headers for static assets
-            if uri_path.starts_with("/build/") || uri_path.contains('.') && !uri_path.ends_with(".html") {
-                // These are likely versioned assets that can be cached long-term
-                response = response.header(header::CACHE_CONTROL, "public, max-age=31536000"); // 1 year
+            if !state.static_cache {
+                response = response.header(header::CACHE_CONTROL, "no-cache");
             } else {
-                // Other assets might change more frequently
-                response = response.header(header::CACHE_CONTROL, "public, max-age=86400"); // 1 day
+                let versioned = assets.is_versioned(uri_path, query);
+                response = response.header(header::CACHE_CONTROL, state.cache_policy.cache_control(uri_path, versioned));
+            }
+
+            // Files in memory have an ETag, and a GET or HEAD naming it gets 304
+            let revalidated = etag.as_ref().is_some_and(|etag| {
+                matches!(context.method, hyper::Method::GET | hyper::Method::HEAD)
+                    && if_none_match.is_some_and(|if_none_match| crate::etag::if_none_match_matches(if_none_match, etag))
+            });
+            if let Some(etag) = etag {
+                response = response.header(header::ETAG, etag);
             }
 
-            Ok(response.body(Body::from(contents)).unwrap_or_else(|_| {
+            let response = response.body(body).unwrap_or_else(|_| {
                 Response::builder()
                     .status(StatusCode::INTERNAL_SERVER_ERROR)
                     .body(Body::from("Failed to create response"))
                     .unwrap()
-            }))
-        }
-        Err(_) => {
-            // File not found - return 404
-            Ok(Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("File not found"))
-                .unwrap_or_else(|_| {
-                    Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .body(Body::from("Failed to create response"))
-                        .unwrap()
-                }))
+            });
+            Ok(if revalidated { crate::etag::not_modified(&response) } else { response })
+        }
+        Err(e) => {
+            // File not found - return 404, or the FAVICON_FALLBACK answer for icons
+            let not_found = || {
+                let error = ServerError::NotFound(format!("{}: {}", file_path, e));
+                state.error_response(error.into(), context)
+            };
+            if FaviconPolicy::is_icon_path(uri_path) {
+                Ok(state.favicon.missing_response(uri_path, not_found))
+            } else {
+                Ok(not_found())
+            }
         }
     }
 }
 
-/// Determine content type based on file extension
-fn get_content_type(file_path: &str) -> &'static str {
-    let extension = std::path::Path::new(file_path)
-        .extension()
-        .and_then(std::ffi::OsStr::to_str)
-        .unwrap_or("")
-        .to_lowercase();
+/// `file_path` with every symlink resolved, if it stays inside the static root
+///
+/// The static root is `public_dir` and the `symlink_roots`, which are
+/// relative to the Laravel root above `public_dir` unless absolute. So
+/// `public/storage` made by `php artisan storage:link` is followed, while a
+/// link to `/etc` or to `.env` is reported as not found.
+async fn resolve_static_file(public_dir: &str, file_path: &str, symlink_roots: &[PathBuf]) -> std::io::Result<PathBuf> {
+    let resolved = tokio::fs::canonicalize(file_path).await?;
+    let public_dir = Path::new(public_dir);
+    let roots = std::iter::once(public_dir.to_path_buf())
+        .chain(symlink_roots.iter().map(|root| public_dir.join("..").join(root)));
+    for root in roots {
+        // A root that does not exist contains nothing
+        if let Ok(root) = tokio::fs::canonicalize(&root).await {
+            if resolved.starts_with(&root) {
+                return Ok(resolved);
+            }
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("resolves to {} outside the public directory", resolved.display()),
+    ))
+}
+
+/// Body, length and, with `sniff`, detected content type of the static file at `file_path`
+///
+/// Files up to `stream_threshold` bytes are read into memory; larger ones
+/// are sent in chunks as they are read, so memory use does not grow with
+/// the file size or the number of concurrent downloads. The body never
+/// exceeds the length from the metadata, even if the file grows meanwhile.
+/// Sniffing looks only at the first `SNIFF_LEN` bytes, which for a streamed
+/// file are read ahead and sent as its first chunk.
+async fn open_static_file(
+    file_path: &Path,
+    stream_threshold: u64,
+    sniff: bool,
+) -> std::io::Result<(Body, u64, Option<&'static str>)> {
+    use tokio::io::AsyncReadExt;
+
+    let mut file = tokio::fs::File::open(file_path).await?;
+    let metadata = file.metadata().await?;
+    if metadata.is_dir() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "is a directory"));
+    }
+    let length = metadata.len();
+
+    if length <= stream_threshold {
+        let mut contents = Vec::with_capacity(length as usize);
+        file.read_to_end(&mut contents).await?;
+        let length = contents.len() as u64;
+        let sniffed = sniff.then(|| sniff::content_type(&contents[..contents.len().min(SNIFF_LEN)])).flatten();
+        return Ok((Body::from(contents), length, sniffed));
+    }
+
+    let mut head = Vec::new();
+    if sniff {
+        (&mut file).take(SNIFF_LEN as u64).read_to_end(&mut head).await?;
+    }
+    let sniffed = sniff.then(|| sniff::content_type(&head)).flatten();
+    let rest = length.saturating_sub(head.len() as u64);
+    let head = futures::stream::iter((!head.is_empty()).then(|| Ok(Bytes::from(head))));
+
+    let chunks = futures::stream::try_unfold(file.take(rest), |mut reader| async move {
+        let mut chunk = vec![0; STATIC_STREAM_CHUNK_SIZE];
+        let read = reader.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok::<_, std::io::Error>(None);
+        }
+        chunk.truncate(read);
+        Ok(Some((Bytes::from(chunk), reader)))
+    });
+    Ok((Body::wrap_stream(head.chain(chunks)), length, sniffed))
+}
+
+/// Forward a request to the PHP worker outside the HTTP listener (used by `bench`)
+#[doc(hidden)]
+pub async fn forward(
+    socket_bridge: &Arc<SocketBridge>,
+    payload: HttpRequestPayload,
+    context: &RequestContext,
+) -> Result<Response<Body>> {
+    forward_to_laravel(
+        socket_bridge,
+        payload,
+        context,
+        false,
+        ResponseValidation::default(),
+        &HtmlTransform::default(),
+        &DynamicEtags::default(),
+    )
+    .await
+}
+
+/// Forward the request on behalf of every identical request waiting for it
+///
+/// A shareable response is buffered (worker responses are complete in
+/// memory already) and handed to the waiting requests; anything else only
+/// answers this request and releases the others to forward their own.
+async fn forward_as_leader(
+    leader: Leader,
+    state: &ServerState,
+    tenant: Option<&Arc<Tenant>>,
+    payload: HttpRequestPayload,
+    context: &RequestContext,
+) -> Result<Response<Body>> {
+    let response = match forward_to_backend(state, tenant, payload, context).await {
+        Ok(response) if SharedResponse::is_shareable(response.status(), response.headers()) => response,
+        other => {
+            leader.complete(FlightResult::NotShared);
+            return other;
+        }
+    };
 
-    match extension.as_str() {
-        "html" | "htm" => "text/html",
-        "css" => "text/css",
-        "js" | "mjs" => "application/javascript",
-        "json" => "application/json",
-        "xml" => "application/xml",
-        "txt" => "text/plain",
-        "ico" => "image/vnd.microsoft.icon",
-        "svg" => "image/svg+xml",
-        "png" => "image/png",
-        "jpg" | "jpeg" => "image/jpeg",
-        "gif" => "image/gif",
-        "webp" => "image/webp",
-        "avif" => "image/avif",
-        "woff" => "font/woff",
-        "woff2" => "font/woff2",
-        "ttf" => "font/ttf",
-        "eot" => "application/vnd.ms-fontobject",
-        "pdf" => "application/pdf",
-        _ => "application/octet-stream", // Default binary type
+    let (parts, body) = response.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+    leader.complete(FlightResult::Shared(Arc::new(SharedResponse {
+        status: parts.status,
+        headers: parts.headers.clone(),
+        body: body.clone(),
+        request_id: context.id.clone(),
+    })));
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+/// Forward the request to the configured backend: php-fpm or the PHP worker
+///
+/// Requests for a tenant go to its PHP worker whatever `BACKEND` says.
+async fn forward_to_backend(
+    state: &ServerState,
+    tenant: Option<&Arc<Tenant>>,
+    payload: HttpRequestPayload,
+    context: &RequestContext,
+) -> Result<Response<Body>> {
+    if let Some(tenant) = tenant {
+        return forward_to_laravel(
+            tenant.socket_bridge(),
+            payload,
+            context,
+            state.retry_idempotent,
+            state.response_validation,
+            &state.html_transform,
+            &state.etags,
+        )
+        .await;
+    }
+    match &state.fastcgi {
+        Some(fastcgi) => {
+            let info = RequestInfo {
+                request_id: &context.id,
+                client_ip: context.client_ip,
+                deadline: Deadline::new(context.started, fastcgi.config().read_timeout, context.caller_budget),
+                secure: context.secure,
+                client_cert: context.client_cert.as_ref(),
+            };
+            let response = fastcgi.forward(payload, info).await?;
+            debug!(elapsed_ms = context.elapsed().as_millis() as u64, "php-fpm responded");
+            Ok(response)
+        }
+        None => {
+            forward_to_laravel(
+                &state.socket_bridge,
+                payload,
+                context,
+                state.retry_idempotent,
+                state.response_validation,
+                &state.html_transform,
+                &state.etags,
+            )
+            .await
+        }
     }
 }
 
@@ -255,286 +1245,433 @@ fn get_content_type(file_path: &str) -> &'static str {
 async fn forward_to_laravel(
     socket_bridge: &Arc<SocketBridge>,
     payload: HttpRequestPayload,
+    context: &RequestContext,
+    retry_idempotent: bool,
+    validation: ResponseValidation,
+    html: &HtmlTransform,
+    etags: &DynamicEtags,
 ) -> Result<Response<Body>> {
+    let retry = retry_idempotent && is_idempotent(&payload.method);
+    let method = payload.method.clone();
+    let mut http_request_data = request_frame(payload, &context.id);
+    if context.secure {
+        // Laravel builds `https://` URLs from it
+        http_request_data["server"]["HTTPS"] = "on".into();
+    }
+    if let Some(client_cert) = &context.client_cert {
+        for (name, value) in client_cert.server_vars() {
+            http_request_data["server"][name] = value.into();
+        }
+    }
+    let deadline = Deadline::new(context.started, socket_bridge.read_timeout(), context.caller_budget);
+    let remaining = deadline.stamp(&mut http_request_data);
+    if remaining.is_zero() {
+        return Err(ServerError::BridgeTimeout("request deadline passed before it was sent to the PHP worker".to_string()).into());
+    }
+
+    // With trace export the worker continues the trace of this call
+    let bridge_span = crate::telemetry::bridge_span();
+    crate::telemetry::inject_trace_context(&bridge_span, &mut http_request_data);
+
+    // Only kept when a failed attempt may be resent
+    let retry_data = retry.then(|| http_request_data.clone());
+
+    // Send HTTP request data directly (not as a command)
+    // Bridge failures are already classified (unavailable, timeout, too large)
+    let response = async {
+        match (send_frame(socket_bridge, context.class, http_request_data, remaining).await, retry_data) {
+            (Err(e), Some(data)) if is_connection_failure(&e) => {
+                match retry_once(socket_bridge, context.class, data, &e, deadline).await {
+                    Some(result) => result,
+                    None => Err(e),
+                }
+            }
+            (result, _) => result,
+        }
+    }
+    .instrument(bridge_span)
+    .await;
+    if let Some(recording) = &context.recording {
+        recording.bridge_response(&response);
+    }
+    let response = response?;
+    debug!(elapsed_ms = context.elapsed().as_millis() as u64, "PHP worker responded");
+    let response = html.apply(context, php_response(response, socket_bridge.codec(), validation)?).await?;
+    etags.tag(&method, response).await
+}
+
+/// HTTP response for the PHP worker's answer to a request frame
+///
+/// The `data` of a successful answer is read by `codec`, the bridge's
+/// `WORKER_PROTOCOL`.
+#[doc(hidden)]
+pub fn php_response(response: PhpResponse, codec: &dyn WorkerCodec, validation: ResponseValidation) -> Result<Response<Body>> {
+    match response.success {
+        true => match response.data {
+            Some(response_data) => codec.decode_response(response_data, validation),
+            // When response.data is None, report the error if available
+            None => match response.error {
+                Some(error_msg) => Err(ServerError::Application(error_msg).into()),
+                None => Err(ServerError::UpstreamMalformed("response has neither data nor error".to_string()).into()),
+            },
+        },
+        false => {
+            let error_msg = response
+                .error
+                .unwrap_or_else(|| "Unknown error from Laravel".to_string());
+            Err(ServerError::Application(error_msg).into())
+        }
+    }
+}
+
+/// Version of the frame format of [`request_frame`] and [`worker_response`]
+///
+/// Raised with changes the PHP worker has to follow.
+pub const WORKER_PROTOCOL_VERSION: u32 = 1;
+
+/// Build the frame PHP expects for `payload`
+///
+/// The frame takes ownership of the payload, so only the body is copied
+/// (into the JSON string). A body that is not UTF-8 is sent base64-encoded
+/// with `"content_encoding": "base64"` instead of being dropped. A form
+/// body is also decoded into `parameters`, next to the query string.
+///
+/// This is the `laravel-rust` shape; the bridge's
+/// [`WorkerCodec`] puts it in the shape of another `WORKER_PROTOCOL` when
+/// the frame is sent.
+#[doc(hidden)]
+pub fn request_frame(payload: HttpRequestPayload, request_id: &str) -> serde_json::Value {
+    let content_type = payload.headers.get("content-type").cloned().unwrap_or_default();
+    let content_length = payload.body.as_ref().map_or(0, |body| body.len());
+    let (content, content_encoding) = match &payload.body {
+        None => (serde_json::Value::Null, None),
+        Some(body) => match std::str::from_utf8(body) {
+            Ok(text) => (text.into(), None),
+            Err(_) => (
+                base64::Engine::encode(&base64::engine::general_purpose::STANDARD, body).into(),
+                Some("base64"),
+            ),
+        },
+    };
+
     // Create a direct HTTP request format that matches what PHP expects
-    let http_request_data = serde_json::json!({
-        "uri": payload.uri.clone(),
-        "method": payload.method.clone(),
-        "headers": payload.headers.clone(),
-        "parameters": payload.query_params.clone(),
-        "content": payload.body.clone(),
+    let mut frame = serde_json::json!({
         "server": {
-            "REQUEST_METHOD": payload.method.clone(),
-            "REQUEST_URI": payload.uri.clone(),
-            "CONTENT_TYPE": payload.headers.get("content-type").unwrap_or(&"".to_string()).clone(),
-            "CONTENT_LENGTH": payload.body.as_ref().map(|b| b.len().to_string()).unwrap_or("0".to_string())
+            "REQUEST_METHOD": payload.method,
+            "REQUEST_URI": payload.uri,
+            "CONTENT_TYPE": content_type,
+            "CONTENT_LENGTH": content_length.to_string(),
+            // Same id as in our logs, for the Laravel log context
+            "REQUEST_ID": request_id
         }
     });
+    frame["uri"] = payload.uri.into();
+    frame["method"] = payload.method.into();
+    frame["headers"] = serde_json::json!(payload.headers);
+    let mut parameters: serde_json::Map<String, serde_json::Value> =
+        payload.query_params.into_iter().map(|(name, value)| (name, value.into())).collect();
+    if crate::form_params::is_form(&content_type) {
+        if let Some(body) = &payload.body {
+            crate::form_params::merge_into(&mut parameters, body);
+        }
+    }
+    frame["parameters"] = parameters.into();
+    frame["content"] = content;
+    if let Some(encoding) = content_encoding {
+        frame["content_encoding"] = encoding.into();
+    }
+    frame
+}
 
-    // Send HTTP request data directly (not as a command)
-    let response = socket_bridge.send_http_request(http_request_data).await;
-
-    match response {
-        Ok(response) => {
-            // Process the response from Laravel
-            match response.success {
-                true => {
-                    if let Some(response_data) = response.data {
-                        // Parse Laravel's response - it might be in the format:
-                        // {"body": "...", "headers": {...}, "status": 200}
-                        let http_response: HttpResponsePayload = parse_laravel_response(response_data).unwrap_or_else(|e| {
-                            error!("Failed to parse Laravel response: {}", e);
-
-                            // Fallback for other response formats
-                            HttpResponsePayload {
-                                status: 200,
-                                headers: std::collections::HashMap::new(),
-                                body: format!("Error parsing Laravel response: {}", e),
-                            }
-                        });
-
-                        // Determine content type and handle response body appropriately
-                        let content_type = http_response
-                            .headers
-                            .get("content-type")
-                            .or(http_response.headers.get("Content-Type"))
-                            .and_then(|ct| ct.split(';').next()) // Extract main content type, ignore parameters like charset
-                            .unwrap_or("text/html")
-                            .to_lowercase();
-
-                        let response_body = if content_type.contains("application/json") {
-                            // For JSON responses, ensure proper formatting and validate JSON
-                            match serde_json::from_str::<serde_json::Value>(&http_response.body) {
-                                Ok(json_value) => {
-                                    // The response is valid JSON, use it as-is
-                                    Body::from(
-                                        serde_json::to_string(&json_value)
-                                            .map_err(|e| anyhow::anyhow!("Failed to serialize JSON response: {}", e))?,
-                                    )
-                                }
-                                Err(_) => {
-                                    // The response claims to be JSON but is not valid JSON, return as-is
-                                    Body::from(http_response.body)
-                                }
-                            }
-                        } else if content_type.contains("text/") || content_type.contains("application/javascript") {
-                            // For text-based responses, return as-is
-                            Body::from(http_response.body)
-                        } else if content_type.contains("application/octet-stream")
-                            || content_type.contains("image/")
-                            || content_type.contains("audio/")
-                            || content_type.contains("video/")
-                        {
-                            // For binary responses, we need to handle the body differently
-                            // If the body is base64 encoded, we should decode it
-                            match base64::Engine::decode(
-                                &base64::engine::general_purpose::STANDARD,
-                                &http_response.body,
-                            ) {
-                                Ok(decoded_bytes) => Body::from(decoded_bytes),
-                                Err(_) => Body::from(http_response.body), // If not base64, treat as string
-                            }
-                        } else {
-                            // For other content types, return as-is
-                            Body::from(http_response.body)
-                        };
+/// Turn the `data` of a successful worker response into the HTTP response
+///
+/// JSON and text bodies are passed on in the buffer they were received in;
+/// only base64-encoded binary bodies are decoded. `validation` decides
+/// whether a response the parser would have to guess about is served.
+#[doc(hidden)]
+pub fn worker_response(response_data: serde_json::Value, validation: ResponseValidation) -> Result<Response<Body>> {
+    validation.check(&response_data)?;
 
-                        // Build response
-                        let mut response_builder = Response::builder()
-                            .status(StatusCode::from_u16(http_response.status)
-                                .map_err(|_| anyhow::anyhow!("Invalid status code: {}", http_response.status))?);
-
-                        // Add headers
-                        for (key, value) in http_response.headers {
-                            match hyper::header::HeaderName::from_bytes(key.as_bytes()) {
-                                Ok(header_name) => {
-                                    // Убираем потенциальные символы новой строки или пробелы в значениях заголовков
-                                    let clean_value = value.trim().to_string();
-                                    if !clean_value.is_empty() {
-                                        response_builder = response_builder.header(header_name, clean_value);
-                                    }
-                                }
-                                Err(_) => {
-                                    // If header name is invalid, log and continue
-                                    tracing::warn!("Invalid header name: {}", key);
-                                }
-                            }
-                        }
+    // Parse Laravel's response - it might be in the format:
+    // {"body": "...", "headers": {...}, "status": 200}
+    let http_response: HttpResponsePayload = parse_laravel_response(response_data)
+        .map_err(|e| ServerError::UpstreamMalformed(format!("{:#}", e)))?;
 
-                        Ok(response_builder.body(response_body)?)
-                    } else {
-                        // When response.data is None, return error response if available
-                        if let Some(error_msg) = response.error {
-                            Ok(Response::builder()
-                                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                                .body(Body::from(error_msg))?)
-                        } else {
-                            // If no data and no error, return a default response
-                            Ok(Response::builder()
-                                .status(StatusCode::OK)
-                                .body(Body::from("Laravel returned empty response"))?)
-                        }
-                    }
-                }
-                false => {
-                    let error_msg = response
-                        .error
-                        .unwrap_or_else(|| "Unknown error from Laravel".to_string());
-                    Ok(Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .body(Body::from(error_msg))?)
+    // Determine content type and handle response body appropriately
+    let content_type = http_response
+        .headers
+        .get("content-type")
+        .or(http_response.headers.get("Content-Type"))
+        .and_then(|ct| ct.split(';').next()) // Extract main content type, ignore parameters like charset
+        .unwrap_or("text/html")
+        .to_lowercase();
+
+    let response_body = if content_type.contains("application/octet-stream")
+        || content_type.contains("image/")
+        || content_type.contains("audio/")
+        || content_type.contains("video/")
+    {
+        // For binary responses, we need to handle the body differently
+        // If the body is base64 encoded, we should decode it
+        match base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &http_response.body) {
+            Ok(decoded_bytes) => Body::from(decoded_bytes),
+            Err(_) => Body::from(http_response.body), // If not base64, treat as string
+        }
+    } else {
+        // JSON, text and other content types are returned as-is
+        Body::from(http_response.body)
+    };
+
+    // Build response
+    let mut response_builder = Response::builder()
+        .status(StatusCode::from_u16(http_response.status).map_err(|_| {
+            ServerError::UpstreamMalformed(format!("invalid status code {}", http_response.status))
+        })?);
+
+    // Add headers
+    for (key, value) in http_response.headers {
+        match hyper::header::HeaderName::from_bytes(key.as_bytes()) {
+            Ok(header_name) => {
+                // Убираем потенциальные символы новой строки или пробелы в значениях заголовков
+                let clean_value = value.trim().to_string();
+                if !clean_value.is_empty() {
+                    response_builder = response_builder.header(header_name, clean_value);
                 }
             }
+            Err(_) => {
+                // If header name is invalid, log and continue
+                tracing::warn!("Invalid header name: {}", key);
+            }
         }
-        Err(e) => {
-            error!("Failed to connect to Laravel socket: {}", e);
-            // Provide more detailed error information
-            let error_msg = format!("Service Unavailable - Laravel backend not responding. Error: {}", e);
-            Ok(Response::builder()
-                .status(StatusCode::SERVICE_UNAVAILABLE)
-                .body(Body::from(error_msg))?)
-        }
     }
+
+    Ok(response_builder.body(response_body)?)
+}
+
+/// Send a request frame within the limits of its class
+async fn send_frame(
+    socket_bridge: &SocketBridge,
+    class: RequestClass,
+    http_request_data: serde_json::Value,
+    timeout: Duration,
+) -> Result<PhpResponse> {
+    match class {
+        RequestClass::Health => socket_bridge.send_priority_request(http_request_data, timeout).await,
+        RequestClass::Normal => socket_bridge.send_http_request_within(http_request_data, timeout).await,
+    }
+}
+
+/// Methods that can be resent without changing the outcome
+fn is_idempotent(method: &str) -> bool {
+    matches!(method, "GET" | "HEAD" | "OPTIONS")
+}
+
+/// Resend a request that never reached the PHP worker, once
+///
+/// The retry gets what is left of the request's deadline, and the frame
+/// carries that smaller budget; `None` when nothing is left, in which case
+/// the original error stands. The worker sees the retried request with the
+/// `HTTP_X_BRIDGE_RETRY=1` server variable.
+async fn retry_once(
+    socket_bridge: &SocketBridge,
+    class: RequestClass,
+    mut http_request_data: serde_json::Value,
+    error: &anyhow::Error,
+    deadline: Deadline,
+) -> Option<Result<PhpResponse>> {
+    let remaining = deadline.stamp(&mut http_request_data);
+    if remaining.is_zero() {
+        return None;
+    }
+    warn!("Retrying idempotent request after a connection failure: {:#}", error);
+    http_request_data["server"]["HTTP_X_BRIDGE_RETRY"] = "1".into();
+
+    let result = send_frame(socket_bridge, class, http_request_data, remaining).await;
+    metrics().describe(
+        "bridge_request_retries_total",
+        MetricKind::Counter,
+        "Idempotent requests resent after a connection failure, by outcome",
+    );
+    let outcome = if result.is_ok() { "success" } else { "failure" };
+    metrics().inc_counter("bridge_request_retries_total", &[("outcome", outcome)]);
+    Some(result)
 }
 
 /// Parse Laravel response format
+///
+/// Takes the response by value so the body string is moved into the
+/// payload rather than copied.
 fn parse_laravel_response(
     response_data: serde_json::Value,
 ) -> Result<HttpResponsePayload> {
+    let mut obj = match response_data {
+        serde_json::Value::Object(obj) => obj,
+        // Если это строка, возвращаем как тело с 200 статусом
+        serde_json::Value::String(body) => return Ok(plain_response(Bytes::from(body))),
+        // Числа, булевы значения, массивы и null отдаем как JSON с 200 статусом
+        other => return Ok(plain_response(Bytes::from(other.to_string()))),
+    };
+
     // Check if response_data has the format: {"body": "...", "headers": {...}, "status": 200}
-    if let Some(obj) = response_data.as_object() {
-        // Check if it has the expected format with body, headers, and status
-        if obj.contains_key("body") && obj.contains_key("headers") && obj.contains_key("status") {
-            let body = obj.get("body").and_then(|v| v.as_str()).unwrap_or("").to_string();
-
-            let status = obj.get("status").and_then(|v| v.as_u64()).unwrap_or(200) as u16;
-
-            let mut headers = std::collections::HashMap::new();
-            if let Some(headers_val) = obj.get("headers").and_then(|v| v.as_object()) {
-                for (key, value) in headers_val {
-                    // Laravel возвращает заголовки как массивы значений, берем первое значение
-                    if let Some(arr) = value.as_array() {
-                        if let Some(first_val) = arr.first() {
-                            if let Some(str_val) = first_val.as_str() {
-                                headers.insert(key.clone(), str_val.to_string());
-                            } else {
-                                headers.insert(key.clone(), first_val.to_string());
-                            }
-                        } else {
-                            // Если массив пуст, добавляем пустую строку
-                            headers.insert(key.clone(), String::new());
-                        }
-                    } else if let Some(str_val) = value.as_str() {
+    if obj.contains_key("body") && obj.contains_key("headers") && obj.contains_key("status") {
+        let status = obj.get("status").and_then(|v| v.as_u64()).unwrap_or(200) as u16;
+        let headers = laravel_headers(&obj);
+        let body = match obj.remove("body") {
+            Some(serde_json::Value::String(body)) => Bytes::from(body),
+            _ => Bytes::new(),
+        };
+
+        return Ok(HttpResponsePayload { status, headers, body });
+    }
+
+    // Check if it has a "status" field but different structure (like direct Laravel HTTP response)
+    if obj.contains_key("status") {
+        let status = obj.get("status").and_then(|v| v.as_u64()).unwrap_or(200) as u16;
+        let headers = laravel_headers(&obj);
+
+        // Try to get body from various possible fields
+        let body = match obj.remove("body") {
+            Some(serde_json::Value::String(body)) => body,
+            Some(body_val) => body_val.to_string(),
+            // If no explicit body, serialize the entire object as fallback
+            None => serde_json::Value::Object(obj).to_string(),
+        };
+
+        return Ok(HttpResponsePayload { status, headers, body: Bytes::from(body) });
+    }
+
+    // Check if it's a response from Laravel that has "originalContent" or other fields
+    // Some Laravel responses might have different field names
+    if let Some(original) = obj.get("originalContent") {
+        // This looks like a Laravel response object
+        return Ok(plain_response(Bytes::from(original.to_string())));
+    }
+
+    // Для всех остальных случаев, включая объекты без ожидаемых полей
+    // возвращаем сериализованный JSON как тело с 200 статусом
+    Ok(plain_response(Bytes::from(serde_json::Value::Object(obj).to_string())))
+}
+
+/// 200 response with `body` and no headers
+fn plain_response(body: Bytes) -> HttpResponsePayload {
+    HttpResponsePayload {
+        status: 200,
+        headers: std::collections::HashMap::new(),
+        body,
+    }
+}
+
+/// Response headers from the `headers` field of a Laravel response
+fn laravel_headers(obj: &serde_json::Map<String, serde_json::Value>) -> std::collections::HashMap<String, String> {
+    let mut headers = std::collections::HashMap::new();
+    if let Some(headers_val) = obj.get("headers").and_then(|v| v.as_object()) {
+        for (key, value) in headers_val {
+            // Laravel возвращает заголовки как массивы значений, берем первое значение
+            if let Some(arr) = value.as_array() {
+                if let Some(first_val) = arr.first() {
+                    if let Some(str_val) = first_val.as_str() {
                         headers.insert(key.clone(), str_val.to_string());
                     } else {
-                        // Если значение не массив и не строка, преобразуем в строку
-                        headers.insert(key.clone(), value.to_string());
+                        headers.insert(key.clone(), first_val.to_string());
                     }
+                } else {
+                    // Если массив пуст, добавляем пустую строку
+                    headers.insert(key.clone(), String::new());
                 }
+            } else if let Some(str_val) = value.as_str() {
+                headers.insert(key.clone(), str_val.to_string());
+            } else {
+                // Если значение не массив и не строка, преобразуем в строку
+                headers.insert(key.clone(), value.to_string());
             }
+        }
+    }
+    headers
+}
+
+/// Serde for bodies held as [`Bytes`]
+///
+/// A UTF-8 body is a string, anything else an array of bytes; both forms
+/// are accepted when deserializing.
+mod body_serde {
+    use std::fmt;
 
-            return Ok(HttpResponsePayload { status, headers, body });
+    use hyper::body::Bytes;
+    use serde::de::{self, Deserializer, SeqAccess, Visitor};
+    use serde::Serializer;
+
+    pub fn serialize<S: Serializer>(body: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+        match std::str::from_utf8(body) {
+            Ok(text) => serializer.serialize_str(text),
+            Err(_) => serializer.serialize_bytes(body),
         }
+    }
 
-        // Check if it has a "status" field but different structure (like direct Laravel HTTP response)
-        if obj.contains_key("status") {
-            let status = obj.get("status").and_then(|v| v.as_u64()).unwrap_or(200) as u16;
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+        deserializer.deserialize_any(BodyVisitor)
+    }
 
-            // Try to get body from various possible fields
-            let body = if let Some(body_val) = obj.get("body") {
-                if let Some(body_str) = body_val.as_str() {
-                    body_str.to_string()
-                } else {
-                    serde_json::to_string(body_val).unwrap_or_else(|_| "{}".to_string())
-                }
-            } else {
-                // If no explicit body, serialize the entire object as fallback
-                serde_json::to_string(&response_data).unwrap_or_else(|_| "{}".to_string())
-            };
+    struct BodyVisitor;
 
-            // Get headers if they exist
-            let mut headers = std::collections::HashMap::new();
-            if let Some(headers_val) = obj.get("headers").and_then(|v| v.as_object()) {
-                for (key, value) in headers_val {
-                    // Laravel может возвращать заголовки как массивы значений
-                    if let Some(arr) = value.as_array() {
-                        if let Some(first_val) = arr.first() {
-                            if let Some(str_val) = first_val.as_str() {
-                                headers.insert(key.clone(), str_val.to_string());
-                            } else {
-                                headers.insert(key.clone(), first_val.to_string());
-                            }
-                        } else {
-                            // Если массив пуст, добавляем пустую строку
-                            headers.insert(key.clone(), String::new());
-                        }
-                    } else if let Some(str_val) = value.as_str() {
-                        headers.insert(key.clone(), str_val.to_string());
-                    } else {
-                        headers.insert(key.clone(), value.to_string());
-                    }
-                }
-            }
+    impl<'de> Visitor<'de> for BodyVisitor {
+        type Value = Bytes;
 
-            return Ok(HttpResponsePayload { status, headers, body });
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a string or an array of bytes")
         }
 
-        // Check if it's a response from Laravel that has "originalContent" or other fields
-        // Some Laravel responses might have different field names
-        if obj.contains_key("originalContent") {
-            // This looks like a Laravel response object
-            let body = obj
-                .get("originalContent")
-                .map(|v| serde_json::to_string(v).unwrap_or_else(|_| "{}".to_string()))
-                .unwrap_or_else(|| "{}".to_string());
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Bytes, E> {
+            Ok(Bytes::copy_from_slice(value.as_bytes()))
+        }
 
-            return Ok(HttpResponsePayload {
-                status: 200,
-                headers: std::collections::HashMap::new(),
-                body,
-            });
+        fn visit_string<E: de::Error>(self, value: String) -> Result<Bytes, E> {
+            Ok(Bytes::from(value))
         }
-    }
 
-    // Laravel может возвращать ответы в других форматах, например:
-    // 1. Просто строку или JSON как тело ответа
-    // 2. Массив с информацией об ответе
-    // 3. Объект с другими полями
+        fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Bytes, E> {
+            Ok(Bytes::copy_from_slice(value))
+        }
 
-    // Если это строка, возвращаем как тело с 200 статусом
-    if let Some(body_str) = response_data.as_str() {
-        return Ok(HttpResponsePayload {
-            status: 200,
-            headers: std::collections::HashMap::new(),
-            body: body_str.to_string(),
-        });
-    }
+        fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<Bytes, E> {
+            Ok(Bytes::from(value))
+        }
 
-    // Если это число, преобразуем в строку
-    if response_data.is_number() {
-        return Ok(HttpResponsePayload {
-            status: 200,
-            headers: std::collections::HashMap::new(),
-            body: response_data.to_string(),
-        });
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Bytes, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(byte) = seq.next_element::<u8>()? {
+                bytes.push(byte);
+            }
+            Ok(Bytes::from(bytes))
+        }
     }
 
-    // Если это булевое значение
-    if response_data.is_boolean() {
-        return Ok(HttpResponsePayload {
-            status: 200,
-            headers: std::collections::HashMap::new(),
-            body: response_data.to_string(),
-        });
-    }
+    /// The same for `Option<Bytes>`
+    pub mod option {
+        use hyper::body::Bytes;
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-    // Для всех остальных случаев, включая объекты без ожидаемых полей
-    // возвращаем сериализованный JSON как тело с 200 статусом
-    Ok(HttpResponsePayload {
-        status: 200,
-        headers: std::collections::HashMap::new(),
-        body: serde_json::to_string(&response_data).unwrap_or_else(|_| "{}".to_string()),
-    })
+        struct Body<'a>(&'a Bytes);
+
+        impl Serialize for Body<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                super::serialize(self.0, serializer)
+            }
+        }
+
+        #[derive(Deserialize)]
+        struct OwnedBody(#[serde(with = "super")] Bytes);
+
+        pub fn serialize<S: Serializer>(body: &Option<Bytes>, serializer: S) -> Result<S::Ok, S::Error> {
+            match body {
+                Some(body) => serializer.serialize_some(&Body(body)),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Bytes>, D::Error> {
+            Ok(Option::<OwnedBody>::deserialize(deserializer)?.map(|body| body.0))
+        }
+    }
 }
 
 /// Extract query parameters from URI
@@ -560,6 +1697,17 @@ fn extract_query_params(query: Option<&str>) -> std::collections::HashMap<String
     params
 }
 
+/// Info endpoint settings, with their problems as one error
+fn runtime_info_from_env() -> Result<RuntimeInfo> {
+    RuntimeInfo::from_env().map_err(|problems| settings_error("info endpoint settings", problems))
+}
+
+/// Certificate, key and client CA bundle loaded now, so a bad file stops the start
+fn tls_from_env() -> Result<Option<TlsListener>> {
+    let config = TlsConfig::from_env().map_err(|problems| settings_error("TLS settings", problems))?;
+    config.map(|config| config.listener()).transpose()
+}
+
 /// Create an internal server error response
 fn internal_server_error() -> Response<Body> {
     Response::builder()
@@ -0,0 +1,149 @@
+//! Graceful shutdown
+//!
+//! [`Shutdown`] is the trigger an embedder fires to stop a server started
+//! with [`HttpServer::start_with_shutdown`](crate::server::HttpServer::start_with_shutdown);
+//! [`ShutdownSignals`] turns SIGINT/SIGTERM/SIGQUIT into a [`ShutdownMode`],
+//! which decides how long in-flight requests may take to finish.
+
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::bridge::socket_bridge::SocketBridge;
+
+/// How urgently the process is shutting down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownMode {
+    /// SIGINT/SIGTERM: wait up to `SHUTDOWN_DRAIN_TIMEOUT_MS` for in-flight requests
+    Graceful,
+    /// SIGQUIT: wait only `SHUTDOWN_FAST_DRAIN_TIMEOUT_MS`
+    Fast,
+}
+
+impl ShutdownMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShutdownMode::Graceful => "graceful",
+            ShutdownMode::Fast => "fast",
+        }
+    }
+
+    /// How long to wait for in-flight requests
+    pub fn drain_timeout(&self) -> Duration {
+        let (var, default) = match self {
+            ShutdownMode::Graceful => ("SHUTDOWN_DRAIN_TIMEOUT_MS", 10_000),
+            ShutdownMode::Fast => ("SHUTDOWN_FAST_DRAIN_TIMEOUT_MS", 1_000),
+        };
+        Duration::from_millis(std::env::var(var).ok().and_then(|v| v.parse().ok()).unwrap_or(default))
+    }
+}
+
+/// Subscription to the process shutdown signals (SIGINT, SIGTERM, SIGQUIT)
+///
+/// Create it before starting services, so a signal during startup is not
+/// lost.
+pub struct ShutdownSignals {
+    interrupt: Signal,
+    terminate: Signal,
+    quit: Signal,
+}
+
+impl ShutdownSignals {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+            quit: signal(SignalKind::quit())?,
+        })
+    }
+
+    /// Wait for the first shutdown signal
+    ///
+    /// # Returns
+    ///
+    /// * `(ShutdownMode, &str)` - the shutdown mode and the name of the signal
+    pub async fn recv(mut self) -> (ShutdownMode, &'static str) {
+        tokio::select! {
+            _ = self.interrupt.recv() => (ShutdownMode::Graceful, "SIGINT"),
+            _ = self.terminate.recv() => (ShutdownMode::Graceful, "SIGTERM"),
+            _ = self.quit.recv() => (ShutdownMode::Fast, "SIGQUIT"),
+        }
+    }
+}
+
+/// Trigger that stops one or more servers
+///
+/// Pass [`signal`](Self::signal) to `start_with_shutdown`; once
+/// [`trigger`](Self::trigger) is called the server stops accepting
+/// connections and its future resolves after in-flight requests complete.
+#[derive(Debug)]
+pub struct Shutdown {
+    sender: watch::Sender<bool>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self {
+            sender: watch::channel(false).0,
+        }
+    }
+
+    /// Future that resolves once shutdown is triggered (or the trigger is dropped)
+    pub fn signal(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut receiver = self.sender.subscribe();
+        async move {
+            let _ = receiver.wait_for(|stopped| *stopped).await;
+        }
+    }
+
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `SHUTDOWN_NOTIFY_TIMEOUT_MS`: how long Laravel gets to acknowledge the shutdown
+pub fn notify_timeout() -> Duration {
+    Duration::from_millis(
+        std::env::var("SHUTDOWN_NOTIFY_TIMEOUT_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(2000),
+    )
+}
+
+/// Tell Laravel the process is about to exit
+///
+/// Sends the `terminating` command so the application can run its shutdown
+/// hooks (flushing metrics, closing connections). Best effort: never takes
+/// longer than `SHUTDOWN_NOTIFY_TIMEOUT_MS`.
+pub async fn notify_laravel_terminating(socket_bridge: &SocketBridge) {
+    notify_laravel_terminating_within(socket_bridge, notify_timeout()).await
+}
+
+/// [`notify_laravel_terminating`] with a timeout read beforehand
+pub async fn notify_laravel_terminating_within(socket_bridge: &SocketBridge, timeout: Duration) {
+    info!("📣 Notifying Laravel about shutdown");
+    match tokio::time::timeout(timeout, socket_bridge.send_command("terminating", None)).await {
+        Ok(Ok(response)) if response.success => info!("Laravel acknowledged the shutdown notification"),
+        Ok(Ok(response)) => warn!(
+            "Laravel returned an error for the shutdown notification: {}",
+            response.error.unwrap_or_default()
+        ),
+        Ok(Err(e)) => warn!("Failed to notify Laravel about shutdown: {:#}", e),
+        Err(_) => warn!("Laravel did not acknowledge the shutdown notification within {:?}", timeout),
+    }
+}
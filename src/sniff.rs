@@ -0,0 +1,45 @@
+//! Content type of a static file from its first bytes
+//!
+//! Used when the file name has no known extension, as with uploads stored
+//! under their hash. Only a few well-known signatures are recognized; text
+//! is assumed when the bytes are valid UTF-8 without control characters.
+//! Anything else stays `application/octet-stream`.
+
+/// Bytes of a file looked at, read before the rest of the file
+pub const SNIFF_LEN: usize = 512;
+
+/// Content type recognized from the first bytes of a file
+pub fn content_type(head: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"PK\x05\x06", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+    ];
+
+    if let Some((_, content_type)) = SIGNATURES.iter().find(|(signature, _)| head.starts_with(signature)) {
+        return Some(content_type);
+    }
+    if head.len() >= 12 && head.starts_with(b"RIFF") && &head[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    is_text(head).then_some("text/plain; charset=utf-8")
+}
+
+/// Whether `head` is UTF-8 text, allowing a character cut off at the end
+fn is_text(head: &[u8]) -> bool {
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        // Only a character cut off by the end of `head` is incomplete
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).unwrap_or_default(),
+        Err(_) => return false,
+    };
+    !text.is_empty()
+        && !text
+            .chars()
+            .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c'))
+}
@@ -0,0 +1,125 @@
+//! Detection of a hung PHP worker
+//!
+//! A request stuck in an endless loop keeps the worker process alive, so
+//! the supervisor never sees it exit, while every other request queues
+//! behind it. The watchdog looks at the bridge's progress: when frames have
+//! been in flight for `PHP_WORKER_STALL_TIMEOUT` seconds without the worker
+//! answering any of them, it pings the worker over a fresh connection. A
+//! worker that answers is only busy; one that does not is considered stalled:
+//!
+//! * the event is logged at error level and counted in
+//!   `php_worker_stalls_total`;
+//! * the frames in flight fail at once with 503;
+//! * the worker gets SIGTERM, and SIGKILL after
+//!   `PHP_WORKER_STALL_KILL_GRACE_MS`;
+//! * a new worker is started, with restart reason `stall`.
+//!
+//! The watchdog stops when shutdown begins, so a worker draining its last
+//! requests is never replaced.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::{debug, error, info};
+
+use crate::bridge::socket_bridge::SocketBridge;
+use crate::metrics::{metrics, MetricKind};
+use crate::supervisor::{RestartReason, WorkerSupervisor};
+
+/// Default for `PHP_WORKER_STALL_TIMEOUT`, in seconds
+pub const DEFAULT_STALL_TIMEOUT_SECS: u64 = 60;
+
+/// Stall detection settings
+#[derive(Debug, Clone)]
+pub struct StallConfig {
+    /// Time without an answer, with frames in flight, before the worker is pinged (None disables)
+    pub timeout: Option<Duration>,
+    /// How long the ping may take
+    pub ping_timeout: Duration,
+    /// Time between SIGTERM and SIGKILL for a stalled worker
+    pub kill_grace: Duration,
+    /// How often the progress is checked
+    pub check_interval: Duration,
+}
+
+impl StallConfig {
+    pub fn from_env() -> Self {
+        let env_or = |name: &str, default: u64| std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+        Self {
+            timeout: Some(env_or("PHP_WORKER_STALL_TIMEOUT", DEFAULT_STALL_TIMEOUT_SECS))
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs),
+            ping_timeout: Duration::from_millis(env_or("PHP_WORKER_STALL_PING_TIMEOUT_MS", 1000)),
+            kill_grace: Duration::from_millis(env_or("PHP_WORKER_STALL_KILL_GRACE_MS", 2000)),
+            check_interval: Duration::from_millis(env_or("PHP_WORKER_POLL_INTERVAL_MS", 500).max(1)),
+        }
+    }
+}
+
+/// Watch `socket_bridge` and have `supervisor` replace a stalled worker
+///
+/// Returns `None` when stall detection is disabled. Abort the task when
+/// shutdown begins.
+pub fn spawn_watchdog(
+    config: StallConfig,
+    socket_bridge: Arc<SocketBridge>,
+    supervisor: Arc<WorkerSupervisor>,
+) -> Option<tokio::task::JoinHandle<()>> {
+    let timeout = config.timeout?;
+    metrics().describe(
+        "php_worker_stalls_total",
+        MetricKind::Counter,
+        "PHP workers killed for not answering with requests in flight",
+    );
+    info!(stall_timeout_secs = timeout.as_secs(), "Watching the PHP worker for stalls");
+
+    Some(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(config.check_interval);
+        loop {
+            ticker.tick().await;
+            if supervisor.is_stopping() {
+                break;
+            }
+            let in_flight = socket_bridge.in_flight();
+            let stalled_for = socket_bridge.since_progress();
+            if in_flight == 0 || stalled_for < timeout {
+                continue;
+            }
+            let Some(pid) = supervisor.pid() else {
+                continue;
+            };
+
+            // A worker busy with slow requests still answers
+            let ping_error = match socket_bridge.ping_direct(config.ping_timeout).await {
+                Ok(()) => {
+                    debug!(in_flight, stalled_for_ms = stalled_for.as_millis() as u64, "PHP worker is slow but answers a ping");
+                    continue;
+                }
+                Err(e) => format!("{:#}", e),
+            };
+
+            error!(
+                pid,
+                in_flight,
+                stalled_for_ms = stalled_for.as_millis() as u64,
+                stall_timeout_ms = timeout.as_millis() as u64,
+                ping_error = %ping_error,
+                kill_grace_ms = config.kill_grace.as_millis() as u64,
+                "🧊 PHP worker stalled, failing its requests and restarting it"
+            );
+            metrics().inc_counter("php_worker_stalls_total", &[]);
+            socket_bridge.fail_in_flight();
+
+            let restarting = supervisor.clone();
+            let grace = config.kill_grace;
+            match tokio::task::spawn_blocking(move || restarting.restart_gracefully(RestartReason::Stall, grace)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => error!(error = %e, "Failed to restart the stalled PHP worker"),
+                Err(e) => error!(error = %e, "Restart of the stalled PHP worker panicked"),
+            }
+            socket_bridge.reset_connections().await;
+            // The new worker gets a full timeout of its own
+            socket_bridge.mark_progress();
+        }
+    }))
+}
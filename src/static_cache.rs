@@ -0,0 +1,383 @@
+//! Cache-Control policy for static files
+//!
+//! An ordered list of rules, each a path pattern and the Cache-Control value
+//! to send for matching files. Rules are evaluated top-down and the first
+//! match wins; paths matching no rule get the default value.
+//!
+//! A pattern without wildcards matches as a path prefix (`/build/`). With
+//! wildcards it must match the whole path: `*` matches within one path
+//! segment, `**` matches across segments, `?` matches one character.
+//!
+//! Between the rules and the default come the assets named in the Vite
+//! (`build/manifest.json`) and Mix (`mix-manifest.json`) manifests: their
+//! URLs change with their content, so they get the long-lived
+//! `STATIC_CACHE_IMMUTABLE` value. See [`AssetManifest`].
+//!
+//! ```toml
+//! [static]
+//! cache_rules = [
+//!     { pattern = "/assets/img/", cache_control = "no-cache" },
+//!     { pattern = "/build/", cache_control = "public, max-age=31536000", immutable = true },
+//! ]
+//! cache_default = "public, max-age=86400"
+//! ```
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use hyper::header::HeaderValue;
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+/// Rules used when `STATIC_CACHE_RULES` is not set
+///
+/// None: which files are versioned is known from the build manifests, and
+/// everything else gets the short default.
+pub const DEFAULT_RULES: &str = "[]";
+
+/// Cache-Control for paths matching no rule and no manifest entry
+///
+/// Short, and revalidated once stale, as the file may change in place.
+pub const DEFAULT_CACHE_CONTROL: &str = "public, max-age=300, must-revalidate";
+
+/// Cache-Control for assets named in a build manifest
+pub const DEFAULT_IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// How a rule pattern is matched against the request path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathPattern {
+    Prefix(String),
+    Glob(String),
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str) -> Self {
+        if pattern.contains(['*', '?']) {
+            PathPattern::Glob(pattern.to_string())
+        } else {
+            PathPattern::Prefix(pattern.to_string())
+        }
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        match self {
+            PathPattern::Prefix(prefix) => path.starts_with(prefix.as_str()),
+            PathPattern::Glob(glob) => glob_match(glob, path),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheRule {
+    pub pattern: PathPattern,
+    pub cache_control: HeaderValue,
+}
+
+/// A rule as written in the configuration
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    pattern: String,
+    cache_control: String,
+    #[serde(default)]
+    immutable: bool,
+}
+
+/// Ordered Cache-Control rules plus the values for versioned and other files
+#[derive(Debug, Clone)]
+pub struct CachePolicy {
+    rules: Vec<CacheRule>,
+    immutable: HeaderValue,
+    default: HeaderValue,
+}
+
+impl CachePolicy {
+    /// Load from `STATIC_CACHE_RULES`, `STATIC_CACHE_IMMUTABLE` and `STATIC_CACHE_DEFAULT`
+    ///
+    /// # Returns
+    ///
+    /// * `Err(problems)` - the variable and message for each invalid value
+    pub fn from_env() -> Result<Self, Vec<(&'static str, String)>> {
+        let rules = std::env::var("STATIC_CACHE_RULES")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_RULES.to_string());
+        let mut problems = Vec::new();
+        let mut header_value = |env: &'static str, default: &str| {
+            let value = std::env::var(env)
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+                .unwrap_or_else(|| default.to_string());
+            HeaderValue::from_str(&value).map_err(|_| {
+                problems.push((env, format!("{:?} is not a valid header value", value)));
+            })
+        };
+        let immutable = header_value("STATIC_CACHE_IMMUTABLE", DEFAULT_IMMUTABLE_CACHE_CONTROL);
+        let default = header_value("STATIC_CACHE_DEFAULT", DEFAULT_CACHE_CONTROL);
+        let rules = Self::parse_rules(&rules).map_err(|errors| {
+            problems.extend(errors.into_iter().map(|e| ("STATIC_CACHE_RULES", e)));
+        });
+
+        match (rules, immutable, default) {
+            (Ok(rules), Ok(immutable), Ok(default)) => Ok(Self {
+                rules,
+                immutable,
+                default,
+            }),
+            _ => Err(problems),
+        }
+    }
+
+    /// Parse a JSON array of `{pattern, cache_control, immutable}` rules
+    pub fn parse_rules(json: &str) -> Result<Vec<CacheRule>, Vec<String>> {
+        let specs: Vec<RuleSpec> = serde_json::from_str(json).map_err(|e| {
+            vec![format!(
+                "must be a list of {{ pattern, cache_control, immutable }} rules: {}",
+                e
+            )]
+        })?;
+
+        let mut rules = Vec::new();
+        let mut problems = Vec::new();
+        for (index, spec) in specs.into_iter().enumerate() {
+            if !spec.pattern.starts_with('/') && !spec.pattern.starts_with('*') {
+                problems.push(format!(
+                    "rule {} pattern {:?} must start with / or *",
+                    index + 1,
+                    spec.pattern
+                ));
+                continue;
+            }
+            let value = if spec.immutable {
+                format!("{}, immutable", spec.cache_control)
+            } else {
+                spec.cache_control
+            };
+            let Ok(cache_control) = HeaderValue::from_str(&value) else {
+                problems.push(format!("rule {} has an invalid cache_control {:?}", index + 1, value));
+                continue;
+            };
+            rules.push(CacheRule {
+                pattern: PathPattern::new(&spec.pattern),
+                cache_control,
+            });
+        }
+
+        if problems.is_empty() {
+            Ok(rules)
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Cache-Control value for a request path
+    ///
+    /// `versioned` tells whether the request names an asset from a build
+    /// manifest; a matching rule still takes precedence.
+    pub fn cache_control(&self, path: &str, versioned: bool) -> &HeaderValue {
+        match self.rules.iter().find(|rule| rule.pattern.matches(path)) {
+            Some(rule) => &rule.cache_control,
+            None if versioned => &self.immutable,
+            None => &self.default,
+        }
+    }
+}
+
+/// Match a whole path against a glob with `**`, `*` and `?`
+fn glob_match(pattern: &str, path: &str) -> bool {
+    if let Some(rest) = pattern.strip_prefix("**") {
+        return (0..=path.len())
+            .filter(|&i| path.is_char_boundary(i))
+            .any(|i| glob_match(rest, &path[i..]));
+    }
+
+    let mut chars = pattern.chars();
+    match chars.next() {
+        None => path.is_empty(),
+        Some('*') => {
+            let segment_end = path.find('/').unwrap_or(path.len());
+            (0..=segment_end)
+                .filter(|&i| path.is_char_boundary(i))
+                .any(|i| glob_match(chars.as_str(), &path[i..]))
+        }
+        Some('?') => match path.chars().next() {
+            Some(c) if c != '/' => glob_match(chars.as_str(), &path[c.len_utf8()..]),
+            _ => false,
+        },
+        Some(c) => path.starts_with(c) && glob_match(chars.as_str(), &path[c.len_utf8()..]),
+    }
+}
+
+/// Build manifests, relative to the public directory, and the URL prefix of their entries
+const MANIFESTS: [(&str, ManifestKind); 3] = [
+    ("build/manifest.json", ManifestKind::Vite),
+    // Vite 5 writes it here unless the Laravel plugin sets `manifest: 'manifest.json'`
+    ("build/.vite/manifest.json", ManifestKind::Vite),
+    ("mix-manifest.json", ManifestKind::Mix),
+];
+
+#[derive(Debug, Clone, Copy)]
+enum ManifestKind {
+    /// `{"resources/js/app.js": {"file": "assets/app-4ed993c7.js", "css": [...], "assets": [...]}}`
+    Vite,
+    /// `{"/js/app.js": "/js/app.js?id=6a8b3d2f"}`
+    Mix,
+}
+
+/// A Vite manifest chunk; only the emitted files matter
+#[derive(Debug, Deserialize)]
+struct ViteChunk {
+    file: String,
+    #[serde(default)]
+    css: Vec<String>,
+    #[serde(default)]
+    assets: Vec<String>,
+}
+
+/// Assets named in the Vite and Mix manifests under the public directory
+///
+/// Vite puts a content hash in the file name, so every file in its manifest
+/// is versioned by its path. Mix usually appends `?id=<hash>` instead; such
+/// a file only counts as versioned when requested with that exact query,
+/// since the bare URL serves whatever version is deployed.
+///
+/// A manifest that cannot be read or parsed contributes nothing, so its
+/// files fall back to the short default rather than being cached for a
+/// year. [`spawn_watcher`](Self::spawn_watcher) reloads the manifests when
+/// a deploy replaces them.
+#[derive(Debug)]
+pub struct AssetManifest {
+    public_dir: PathBuf,
+    /// URL path and the query string it must be requested with, if any
+    versioned: RwLock<HashMap<String, Option<String>>>,
+    /// Modification time and size of each manifest when last read
+    signatures: Mutex<Vec<Option<(SystemTime, u64)>>>,
+}
+
+impl AssetManifest {
+    /// Read the manifests found under `public_dir`
+    pub fn load(public_dir: impl Into<PathBuf>) -> Arc<Self> {
+        let manifest = Arc::new(Self {
+            public_dir: public_dir.into(),
+            versioned: RwLock::new(HashMap::new()),
+            signatures: Mutex::new(Vec::new()),
+        });
+        manifest.reload();
+        manifest
+    }
+
+    /// Whether `path` (with `query`) names a versioned asset
+    pub fn is_versioned(&self, path: &str, query: Option<&str>) -> bool {
+        match self.versioned.read().unwrap_or_else(|e| e.into_inner()).get(path) {
+            Some(None) => true,
+            Some(Some(required)) => query == Some(required.as_str()),
+            None => false,
+        }
+    }
+
+    /// Number of versioned assets
+    pub fn len(&self) -> usize {
+        self.versioned.read().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Re-read the manifests if any of them changed; returns whether they did
+    pub fn reload(&self) -> bool {
+        let signatures: Vec<_> = MANIFESTS
+            .iter()
+            .map(|(file, _)| {
+                let metadata = std::fs::metadata(self.public_dir.join(file)).ok()?;
+                Some((metadata.modified().ok()?, metadata.len()))
+            })
+            .collect();
+        {
+            let mut previous = self.signatures.lock().unwrap_or_else(|e| e.into_inner());
+            if *previous == signatures {
+                return false;
+            }
+            *previous = signatures;
+        }
+
+        let mut versioned = HashMap::new();
+        for (file, kind) in MANIFESTS {
+            let path = self.public_dir.join(file);
+            let Ok(contents) = std::fs::read(&path) else {
+                continue;
+            };
+            match parse_manifest(&contents, kind) {
+                Ok(entries) => {
+                    info!(manifest = %path.display(), assets = entries.len(), "Loaded build manifest");
+                    versioned.extend(entries);
+                }
+                Err(e) => warn!(
+                    manifest = %path.display(),
+                    "Ignoring unreadable build manifest, its assets get the default Cache-Control: {}",
+                    e
+                ),
+            }
+        }
+        *self.versioned.write().unwrap_or_else(|e| e.into_inner()) = versioned;
+        true
+    }
+
+    /// Re-read the manifests even if they look unchanged; returns whether any was found
+    ///
+    /// A deploy may replace a manifest within the same second with one of
+    /// the same size, which [`reload`](Self::reload) cannot tell apart.
+    pub fn invalidate(&self) -> bool {
+        self.signatures.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        self.reload();
+        !self.is_empty()
+    }
+
+    /// Reload the manifests every `interval` while the manifest is in use
+    pub fn spawn_watcher(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let manifest = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(manifest) = manifest.upgrade() else {
+                    break;
+                };
+                let public_dir = manifest.public_dir.clone();
+                let reloaded = tokio::task::spawn_blocking(move || manifest.reload()).await;
+                if matches!(reloaded, Ok(true)) {
+                    debug!(public_dir = %public_dir.display(), "Build manifests changed, reloaded");
+                }
+            }
+        })
+    }
+}
+
+/// URL paths of the assets in one manifest
+fn parse_manifest(contents: &[u8], kind: ManifestKind) -> Result<Vec<(String, Option<String>)>, serde_json::Error> {
+    Ok(match kind {
+        ManifestKind::Vite => {
+            let chunks: HashMap<String, ViteChunk> = serde_json::from_slice(contents)?;
+            chunks
+                .into_values()
+                .flat_map(|chunk| std::iter::once(chunk.file).chain(chunk.css).chain(chunk.assets))
+                .map(|file| (format!("/build/{}", file.trim_start_matches('/')), None))
+                .collect()
+        }
+        ManifestKind::Mix => {
+            let entries: HashMap<String, String> = serde_json::from_slice(contents)?;
+            entries
+                .into_iter()
+                .filter_map(|(source, versioned)| match versioned.split_once('?') {
+                    Some((path, query)) => Some((path.to_string(), Some(query.to_string()))),
+                    // A hashed file name without a query; an unversioned entry maps to itself
+                    None => (versioned != source).then_some((versioned, None)),
+                })
+                .collect()
+        }
+    })
+}
+
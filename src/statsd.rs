@@ -0,0 +1,326 @@
+//! DogStatsD metrics export
+//!
+//! With `STATSD_ADDR` set, every update recorded in the [`metrics`]
+//! registry is also sent to a DogStatsD agent over UDP, for hosts where the
+//! Prometheus endpoint cannot be scraped. Names get `STATSD_PREFIX`, labels
+//! become tags next to the constant `STATSD_TAGS`:
+//!
+//! * counters are summed and sent as `c` once per `STATSD_FLUSH_INTERVAL_MS`
+//! * gauges send their last value as `g`
+//! * summary observations (e.g. request durations) are sent as `d`
+//!   distributions, several values per line
+//!
+//! The request path only pushes into a bounded queue; a background thread
+//! aggregates, packs lines into datagrams and sends them. When the queue is
+//! full or the socket fails, samples are dropped and counted in
+//! `statsd_dropped_total{reason}`; nothing is retried and nothing blocks.
+
+use std::collections::HashMap;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use once_cell::sync::OnceCell;
+use tracing::{debug, info};
+
+use crate::metrics::{metrics, MetricKind, MetricsSink};
+
+/// Largest datagram sent, safely below a 1500 byte MTU
+const MAX_PACKET_SIZE: usize = 1432;
+
+/// Default prefix of every metric name
+const DEFAULT_PREFIX: &str = "laravel_rust.";
+
+/// Default aggregation and flush interval
+const DEFAULT_FLUSH_INTERVAL_MS: u64 = 1000;
+
+/// Default number of samples buffered between flushes
+const DEFAULT_QUEUE_SIZE: usize = 10_000;
+
+static INSTALLED: OnceCell<()> = OnceCell::new();
+
+/// DogStatsD exporter settings
+#[derive(Debug, Clone)]
+pub struct StatsdConfig {
+    /// Agent address (`host:port`)
+    pub addr: String,
+    pub prefix: String,
+    /// Constant tags added to every metric (`key:value`)
+    pub tags: Vec<String>,
+    pub flush_interval: Duration,
+    /// Samples buffered before new ones are dropped
+    pub queue_size: usize,
+}
+
+impl StatsdConfig {
+    /// Read the `STATSD_*` variables; `None` unless `STATSD_ADDR` is set
+    pub fn from_env() -> Option<Self> {
+        let addr = std::env::var("STATSD_ADDR").ok().filter(|v| !v.trim().is_empty())?;
+
+        let mut prefix = std::env::var("STATSD_PREFIX").unwrap_or_else(|_| DEFAULT_PREFIX.to_string());
+        if !prefix.is_empty() && !prefix.ends_with('.') {
+            prefix.push('.');
+        }
+        let tags = std::env::var("STATSD_TAGS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(sanitize)
+            .collect();
+        let flush_interval_ms = std::env::var("STATSD_FLUSH_INTERVAL_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&ms| ms > 0)
+            .unwrap_or(DEFAULT_FLUSH_INTERVAL_MS);
+        let queue_size = std::env::var("STATSD_QUEUE_SIZE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&n| n > 0)
+            .unwrap_or(DEFAULT_QUEUE_SIZE);
+
+        Some(Self {
+            addr: addr.trim().to_string(),
+            prefix,
+            tags,
+            flush_interval: Duration::from_millis(flush_interval_ms),
+            queue_size,
+        })
+    }
+}
+
+/// Start exporting the registry to the agent at `config.addr`
+///
+/// Only the first call in a process installs an exporter.
+///
+/// # Returns
+///
+/// * `Err` - the address cannot be resolved or no UDP socket can be opened
+pub fn install(config: StatsdConfig) -> Result<()> {
+    if INSTALLED.get().is_some() {
+        return Ok(());
+    }
+
+    let target = config
+        .addr
+        .to_socket_addrs()
+        .with_context(|| format!("Invalid STATSD_ADDR {:?}", config.addr))?
+        .next()
+        .ok_or_else(|| anyhow!("STATSD_ADDR {:?} did not resolve to an address", config.addr))?;
+    let local = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(local).context("Failed to open the StatsD UDP socket")?;
+    socket.connect(target).context("Failed to connect the StatsD UDP socket")?;
+    socket.set_nonblocking(true)?;
+
+    if INSTALLED.set(()).is_err() {
+        return Ok(());
+    }
+
+    let (sender, receiver) = mpsc::sync_channel(config.queue_size);
+    let drops = Arc::new(Drops::default());
+    info!(
+        addr = %target,
+        prefix = %config.prefix,
+        flush_interval_ms = config.flush_interval.as_millis() as u64,
+        "📤 Exporting metrics to DogStatsD"
+    );
+
+    let exporter = Exporter {
+        socket,
+        config,
+        drops: drops.clone(),
+    };
+    std::thread::Builder::new()
+        .name("statsd-exporter".into())
+        .spawn(move || exporter.run(receiver))?;
+    metrics().add_sink(Arc::new(StatsdSink { queue: sender, drops }));
+    Ok(())
+}
+
+/// Samples dropped since the last flush, by reason
+#[derive(Default)]
+struct Drops {
+    queue_full: AtomicU64,
+    send_error: AtomicU64,
+}
+
+/// One update taken off the request path
+struct Sample {
+    name: &'static str,
+    kind: MetricKind,
+    labels: Vec<(String, String)>,
+    value: f64,
+}
+
+/// Registry sink that hands samples to the exporter thread
+struct StatsdSink {
+    queue: SyncSender<Sample>,
+    drops: Arc<Drops>,
+}
+
+impl MetricsSink for StatsdSink {
+    fn record(&self, name: &'static str, kind: MetricKind, labels: &[(&str, &str)], value: f64) {
+        let sample = Sample {
+            name,
+            kind,
+            labels: labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            value,
+        };
+        match self.queue.try_send(sample) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.drops.queue_full.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Metric name and rendered tags, the aggregation key
+type SeriesKey = (String, String);
+
+/// Samples aggregated within one flush interval
+#[derive(Default)]
+struct Batch {
+    counters: HashMap<SeriesKey, f64>,
+    gauges: HashMap<SeriesKey, f64>,
+    distributions: HashMap<SeriesKey, Vec<f64>>,
+}
+
+struct Exporter {
+    socket: UdpSocket,
+    config: StatsdConfig,
+    drops: Arc<Drops>,
+}
+
+impl Exporter {
+    fn run(self, queue: Receiver<Sample>) {
+        let mut batch = Batch::default();
+        let mut next_flush = Instant::now() + self.config.flush_interval;
+
+        loop {
+            match queue.recv_timeout(next_flush.saturating_duration_since(Instant::now())) {
+                Ok(sample) => self.add(&mut batch, sample),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.flush(batch);
+                    return;
+                }
+            }
+            // Checked after every sample too, so steady traffic cannot delay the flush
+            if Instant::now() >= next_flush {
+                self.flush(std::mem::take(&mut batch));
+                next_flush = Instant::now() + self.config.flush_interval;
+            }
+        }
+    }
+
+    fn add(&self, batch: &mut Batch, sample: Sample) {
+        let key = (format!("{}{}", self.config.prefix, sample.name), self.tags(&sample.labels));
+        match sample.kind {
+            MetricKind::Counter => *batch.counters.entry(key).or_default() += sample.value,
+            MetricKind::Gauge => {
+                batch.gauges.insert(key, sample.value);
+            }
+            MetricKind::Summary => batch.distributions.entry(key).or_default().push(sample.value),
+        }
+    }
+
+    /// `|#tag,...` suffix with the constant tags and the sample's labels
+    fn tags(&self, labels: &[(String, String)]) -> String {
+        let tags: Vec<String> = self
+            .config
+            .tags
+            .iter()
+            .cloned()
+            .chain(labels.iter().map(|(k, v)| format!("{}:{}", sanitize(k), sanitize(v))))
+            .collect();
+        if tags.is_empty() {
+            String::new()
+        } else {
+            format!("|#{}", tags.join(","))
+        }
+    }
+
+    fn flush(&self, batch: Batch) {
+        let mut lines = Vec::new();
+        for ((name, tags), value) in &batch.counters {
+            lines.push(format!("{}:{}|c{}", name, value, tags));
+        }
+        for ((name, tags), value) in &batch.gauges {
+            lines.push(format!("{}:{}|g{}", name, value, tags));
+        }
+        for ((name, tags), values) in &batch.distributions {
+            distribution_lines(name, tags, values, &mut lines);
+        }
+
+        let mut packet = String::new();
+        let mut in_packet = 0;
+        for line in lines {
+            if !packet.is_empty() && packet.len() + 1 + line.len() > MAX_PACKET_SIZE {
+                self.send(&packet, in_packet);
+                packet.clear();
+                in_packet = 0;
+            }
+            if !packet.is_empty() {
+                packet.push('\n');
+            }
+            packet.push_str(&line);
+            in_packet += 1;
+        }
+        if !packet.is_empty() {
+            self.send(&packet, in_packet);
+        }
+
+        // Reported through the registry, so they reach both exporters
+        for (reason, counter) in [("queue_full", &self.drops.queue_full), ("send_error", &self.drops.send_error)] {
+            let dropped = counter.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                metrics().describe(
+                    "statsd_dropped_total",
+                    MetricKind::Counter,
+                    "Metric samples or lines not delivered to the StatsD agent, by reason",
+                );
+                metrics().add_counter("statsd_dropped_total", &[("reason", reason)], dropped);
+            }
+        }
+    }
+
+    fn send(&self, packet: &str, lines: u64) {
+        if let Err(e) = self.socket.send(packet.as_bytes()) {
+            debug!(error = %e, lines, "Failed to send metrics to StatsD");
+            self.drops.send_error.fetch_add(lines, Ordering::Relaxed);
+        }
+    }
+}
+
+/// `name:v1:v2:...|d|#tags` lines, split so each fits in a datagram
+fn distribution_lines(name: &str, tags: &str, values: &[f64], lines: &mut Vec<String>) {
+    let suffix = format!("|d{}", tags);
+    let mut line = String::new();
+    for value in values {
+        let rendered = format!(":{}", value);
+        if !line.is_empty() && line.len() + rendered.len() + suffix.len() > MAX_PACKET_SIZE {
+            line.push_str(&suffix);
+            lines.push(std::mem::take(&mut line));
+        }
+        if line.is_empty() {
+            line.push_str(name);
+        }
+        line.push_str(&rendered);
+    }
+    if !line.is_empty() {
+        line.push_str(&suffix);
+        lines.push(line);
+    }
+}
+
+/// Replace the characters that delimit DogStatsD fields
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if matches!(c, '|' | ',' | '#' | '\n') { '_' } else { c })
+        .collect()
+}
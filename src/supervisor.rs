@@ -0,0 +1,471 @@
+//! PHP worker supervision
+//!
+//! Owns the PHP worker child process, restarts it when it exits unexpectedly
+//! and keeps a restart history so a crash-looping worker shows up in stats,
+//! metrics and the error log instead of silently degrading traffic.
+
+use std::collections::{HashMap, VecDeque};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::json;
+use tracing::{error, info, warn};
+
+use crate::metrics::{metrics, MetricKind};
+
+/// Function used to spawn a fresh PHP worker process
+pub type SpawnFn = dyn Fn() -> Result<Child> + Send + Sync;
+
+/// Called when the worker is gone and a new one is about to start
+pub type RestartListener = dyn Fn(RestartReason) + Send + Sync;
+
+/// Why the worker was (re)started
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(dead_code)]
+pub enum RestartReason {
+    /// The worker exited on its own
+    Crash,
+    /// Planned recycle after serving its quota of requests
+    Recycle,
+    /// The worker exceeded its memory budget
+    Memory,
+    /// Restart requested by an operator (admin API)
+    Operator,
+    /// The worker stopped answering with requests in flight
+    Stall,
+}
+
+impl RestartReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RestartReason::Crash => "crash",
+            RestartReason::Recycle => "recycle",
+            RestartReason::Memory => "memory",
+            RestartReason::Operator => "operator",
+            RestartReason::Stall => "stall",
+        }
+    }
+}
+
+/// Supervisor configuration
+#[derive(Debug, Clone)]
+pub struct SupervisorConfig {
+    /// Restart the worker automatically when it exits
+    pub auto_restart: bool,
+    /// How often the child process is polled for exit
+    pub poll_interval: Duration,
+    /// Delay before restarting a worker that exited
+    pub restart_delay: Duration,
+    /// Number of recent restarts kept in stats
+    pub restart_history: usize,
+    /// Restarts within one minute that trigger an error-level event
+    pub restart_alert_per_minute: usize,
+}
+
+impl SupervisorConfig {
+    pub fn from_env() -> Self {
+        Self {
+            auto_restart: std::env::var("PHP_WORKER_AUTO_RESTART")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            poll_interval: Duration::from_millis(
+                std::env::var("PHP_WORKER_POLL_INTERVAL_MS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(500),
+            ),
+            restart_delay: Duration::from_millis(
+                std::env::var("PHP_WORKER_RESTART_DELAY_MS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(1000),
+            ),
+            restart_history: std::env::var("PHP_WORKER_RESTART_HISTORY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10),
+            restart_alert_per_minute: std::env::var("PHP_WORKER_RESTART_ALERT_PER_MINUTE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(5),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct RestartRecord {
+    at_unix_ms: u64,
+    reason: RestartReason,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ExitRecord {
+    at_unix_ms: u64,
+    code: Option<i32>,
+    signal: Option<i32>,
+}
+
+#[derive(Debug, Default)]
+struct WorkerStats {
+    restarts_total: u64,
+    recent_restarts: VecDeque<RestartRecord>,
+    /// Monotonic timestamps of restarts within the alert window
+    restart_window: VecDeque<Instant>,
+    last_exit: Option<ExitRecord>,
+    last_restart_reason: Option<RestartReason>,
+    last_failure: Option<String>,
+}
+
+/// The supervised worker process
+enum Worker {
+    /// Spawned by this process
+    Child(Child),
+    /// Handed over by the process this one replaced (binary upgrade)
+    ///
+    /// Not our child, so it can only be watched by PID and its exit status
+    /// goes to its actual parent. While the old process is still draining,
+    /// an exited worker lingers as its zombie and still looks alive.
+    Adopted(u32),
+}
+
+impl Worker {
+    fn id(&self) -> u32 {
+        match self {
+            Worker::Child(child) => child.id(),
+            Worker::Adopted(pid) => *pid,
+        }
+    }
+
+    /// `Some` once the worker has exited, with its status when known
+    fn try_wait(&mut self) -> std::io::Result<Option<Option<ExitStatus>>> {
+        match self {
+            Worker::Child(child) => Ok(child.try_wait()?.map(Some)),
+            // SAFETY: signal 0 only checks that the process exists
+            Worker::Adopted(pid) => Ok((unsafe { libc::kill(*pid as libc::pid_t, 0) } != 0).then_some(None)),
+        }
+    }
+
+    /// Ask the worker to exit with SIGTERM, and kill it if it is still running after `grace`
+    fn terminate(&mut self, grace: Duration) -> Option<ExitStatus> {
+        // SAFETY: plain kill(2) on the worker's PID
+        unsafe { libc::kill(self.id() as libc::pid_t, libc::SIGTERM) };
+        let deadline = Instant::now() + grace;
+        while Instant::now() < deadline {
+            match self.try_wait() {
+                Ok(Some(status)) => return status,
+                Ok(None) => std::thread::sleep(Duration::from_millis(50)),
+                Err(_) => break,
+            }
+        }
+        self.kill()
+    }
+
+    /// Kill the worker and collect its exit status when possible
+    fn kill(&mut self) -> Option<ExitStatus> {
+        match self {
+            Worker::Child(child) => {
+                let _ = child.kill();
+                child.wait().ok()
+            }
+            Worker::Adopted(pid) => {
+                // SAFETY: plain kill(2) on the PID we were handed
+                unsafe { libc::kill(*pid as libc::pid_t, libc::SIGKILL) };
+                None
+            }
+        }
+    }
+}
+
+/// Supervises a single PHP worker process
+pub struct WorkerSupervisor {
+    config: SupervisorConfig,
+    spawn: Box<SpawnFn>,
+    child: Mutex<Option<Worker>>,
+    stats: Mutex<WorkerStats>,
+    started: AtomicBool,
+    stopping: AtomicBool,
+    restart_listeners: Mutex<Vec<Box<RestartListener>>>,
+}
+
+impl WorkerSupervisor {
+    pub fn new(config: SupervisorConfig, spawn: Box<SpawnFn>) -> Arc<Self> {
+        metrics().describe(
+            "php_worker_restarts_total",
+            MetricKind::Counter,
+            "PHP worker restarts by reason",
+        );
+        metrics().describe(
+            "php_worker_exits_total",
+            MetricKind::Counter,
+            "PHP worker process exits observed by the supervisor",
+        );
+
+        Arc::new(Self {
+            config,
+            spawn,
+            child: Mutex::new(None),
+            stats: Mutex::new(WorkerStats::default()),
+            started: AtomicBool::new(false),
+            stopping: AtomicBool::new(false),
+            restart_listeners: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Spawn the initial worker process
+    pub fn start(&self) -> Result<()> {
+        match (self.spawn)() {
+            Ok(child) => {
+                *self.child.lock().unwrap_or_else(|e| e.into_inner()) = Some(Worker::Child(child));
+                self.started.store(true, Ordering::SeqCst);
+                Ok(())
+            }
+            Err(e) => {
+                self.lock_stats().last_failure = Some(e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    /// Supervise a running worker handed over by the previous process
+    ///
+    /// Instead of [`start`](Self::start) after a binary upgrade. When it
+    /// exits, a worker of our own replaces it as usual.
+    pub fn adopt(&self, pid: u32) {
+        *self.child.lock().unwrap_or_else(|e| e.into_inner()) = Some(Worker::Adopted(pid));
+        self.started.store(true, Ordering::SeqCst);
+    }
+
+    /// Stop supervising and leave the worker running, for a new process to adopt
+    pub fn release(&self) -> Option<u32> {
+        self.stopping.store(true, Ordering::SeqCst);
+        let worker = self.child.lock().unwrap_or_else(|e| e.into_inner()).take();
+        worker.map(|worker| worker.id())
+    }
+
+    /// Call `listener` whenever a worker is gone and a replacement is on its way
+    ///
+    /// That is after a crash (with auto-restart on) and on every restart.
+    pub fn on_restart(&self, listener: Box<RestartListener>) {
+        self.restart_listeners.lock().unwrap_or_else(|e| e.into_inner()).push(listener);
+    }
+
+    fn notify_restart(&self, reason: RestartReason) {
+        for listener in self.restart_listeners.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            listener(reason);
+        }
+    }
+
+    /// Whether the supervisor is shutting down or has released the worker
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    /// PID of the running worker, if any
+    pub fn pid(&self) -> Option<u32> {
+        self.child.lock().unwrap_or_else(|e| e.into_inner()).as_ref().map(|c| c.id())
+    }
+
+    /// Start the background task that watches the worker and restarts it on exit
+    pub fn spawn_monitor(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let supervisor = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(supervisor.config.poll_interval);
+            loop {
+                interval.tick().await;
+                if supervisor.stopping.load(Ordering::SeqCst) {
+                    break;
+                }
+                supervisor.check_worker().await;
+            }
+        })
+    }
+
+    async fn check_worker(&self) {
+        // Only supervise a worker that was started at least once
+        if !self.started.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let exited = {
+            let mut child = self.child.lock().unwrap_or_else(|e| e.into_inner());
+            match child.as_mut().map(|c| c.try_wait()) {
+                Some(Ok(Some(status))) => {
+                    *child = None;
+                    Some(Some(status))
+                }
+                Some(Ok(None)) => None,
+                Some(Err(e)) => {
+                    warn!("Failed to poll PHP worker status: {}", e);
+                    None
+                }
+                // Worker is gone and a previous restart attempt failed
+                None => Some(None),
+            }
+        };
+
+        let Some(status) = exited else {
+            return;
+        };
+
+        if let Some(status) = status {
+            self.record_exit(status);
+        }
+
+        if !self.config.auto_restart || self.stopping.load(Ordering::SeqCst) {
+            return;
+        }
+        self.notify_restart(RestartReason::Crash);
+
+        tokio::time::sleep(self.config.restart_delay).await;
+        if self.stopping.load(Ordering::SeqCst) {
+            return;
+        }
+
+        if let Err(e) = self.restart(RestartReason::Crash) {
+            error!("Failed to restart PHP worker: {}", e);
+        }
+    }
+
+    /// Replace the current worker with a fresh process
+    pub fn restart(&self, reason: RestartReason) -> Result<()> {
+        self.replace(reason, None)
+    }
+
+    /// Replace the current worker, giving it `grace` to exit on SIGTERM before it is killed
+    ///
+    /// Blocks for up to `grace`.
+    pub fn restart_gracefully(&self, reason: RestartReason, grace: Duration) -> Result<()> {
+        self.replace(reason, Some(grace))
+    }
+
+    /// Kill the current worker and spawn a new one, unless the supervisor is stopping
+    fn replace(&self, reason: RestartReason, grace: Option<Duration>) -> Result<()> {
+        let mut child = self.child.lock().unwrap_or_else(|e| e.into_inner());
+
+        let replacing = child.take();
+        let replaced = replacing.is_some();
+        if let Some(mut old) = replacing {
+            let status = match grace {
+                Some(grace) => old.terminate(grace),
+                None => old.kill(),
+            };
+            self.record_exit(status);
+        }
+        if self.stopping.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        if replaced {
+            self.notify_restart(reason);
+        }
+
+        match (self.spawn)() {
+            Ok(new_child) => {
+                info!(
+                    pid = new_child.id(),
+                    reason = reason.as_str(),
+                    "PHP worker restarted"
+                );
+                *child = Some(Worker::Child(new_child));
+                self.started.store(true, Ordering::SeqCst);
+                self.record_restart(reason);
+                Ok(())
+            }
+            Err(e) => {
+                self.lock_stats().last_failure = Some(e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    /// Stop supervising and terminate the worker
+    pub fn shutdown(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        let mut child = self.child.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(mut worker) = child.take() {
+            worker.kill();
+        }
+    }
+
+    /// Record an exit; `status` is unknown for an adopted worker
+    fn record_exit(&self, status: Option<ExitStatus>) {
+        let record = ExitRecord {
+            at_unix_ms: unix_millis(),
+            code: status.and_then(|s| s.code()),
+            signal: status.and_then(|s| s.signal()),
+        };
+        warn!(code = ?record.code, signal = ?record.signal, "PHP worker exited");
+        metrics().inc_counter("php_worker_exits_total", &[]);
+        self.lock_stats().last_exit = Some(record);
+    }
+
+    fn record_restart(&self, reason: RestartReason) {
+        metrics().inc_counter("php_worker_restarts_total", &[("reason", reason.as_str())]);
+
+        let mut stats = self.lock_stats();
+        stats.restarts_total += 1;
+        stats.last_restart_reason = Some(reason);
+        stats.last_failure = None;
+        stats.recent_restarts.push_back(RestartRecord {
+            at_unix_ms: unix_millis(),
+            reason,
+        });
+        while stats.recent_restarts.len() > self.config.restart_history {
+            stats.recent_restarts.pop_front();
+        }
+
+        let now = Instant::now();
+        stats.restart_window.push_back(now);
+        while let Some(first) = stats.restart_window.front() {
+            if now.duration_since(*first) > Duration::from_secs(60) {
+                stats.restart_window.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        let threshold = self.config.restart_alert_per_minute;
+        if threshold > 0 && stats.restart_window.len() >= threshold {
+            error!(
+                restarts_last_minute = stats.restart_window.len(),
+                threshold,
+                last_reason = reason.as_str(),
+                "PHP worker is crash-looping"
+            );
+        }
+    }
+
+    /// Snapshot of the worker state and restart history
+    pub fn get_stats(&self) -> HashMap<String, serde_json::Value> {
+        let pid = self.pid();
+        let stats = self.lock_stats();
+
+        let mut map = HashMap::new();
+        map.insert("running".to_string(), json!(pid.is_some()));
+        map.insert("pid".to_string(), json!(pid));
+        map.insert("restarts_total".to_string(), json!(stats.restarts_total));
+        map.insert("restarts_last_minute".to_string(), json!(stats.restart_window.len()));
+        map.insert("recent_restarts".to_string(), json!(stats.recent_restarts));
+        map.insert("last_exit".to_string(), json!(stats.last_exit));
+        map.insert("last_restart_reason".to_string(), json!(stats.last_restart_reason));
+        map.insert("last_failure".to_string(), json!(stats.last_failure));
+        map.insert("limits".to_string(), json!(crate::worker_limits::applied_limits()));
+        map
+    }
+
+    fn lock_stats(&self) -> std::sync::MutexGuard<'_, WorkerStats> {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
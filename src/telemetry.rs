@@ -0,0 +1,156 @@
+//! OpenTelemetry trace export (`otel` feature)
+//!
+//! With the feature enabled and `OTEL_EXPORTER_OTLP_ENDPOINT` (or
+//! `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`) set, every request span is exported
+//! over OTLP/HTTP in batches, together with a child span per call to the PHP
+//! worker. The W3C `traceparent` of that call is passed to PHP as a header
+//! and as the `HTTP_TRACEPARENT` server variable, so the Laravel OTel SDK
+//! continues the trace. The other standard `OTEL_*` variables (headers,
+//! timeout, sampler, service name) are read by the SDK.
+//!
+//! Without the feature, or without an endpoint, nothing here has any
+//! effect.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+use tracing::Span;
+
+/// Set once the exporter is installed
+static EXPORTING: AtomicBool = AtomicBool::new(false);
+
+/// Keeps the exporter running; dropping it flushes the pending spans
+pub struct TelemetryGuard {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take() {
+            EXPORTING.store(false, Ordering::Release);
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush traces: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+fn endpoint_configured() -> bool {
+    ["OTEL_EXPORTER_OTLP_ENDPOINT", "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"]
+        .iter()
+        .any(|name| std::env::var(name).is_ok_and(|v| !v.trim().is_empty()))
+}
+
+fn exporting() -> bool {
+    EXPORTING.load(Ordering::Acquire)
+}
+
+/// Layer exporting spans, if the feature is enabled and an endpoint is set
+#[cfg(feature = "otel")]
+pub fn layer<S>() -> Result<(Option<impl tracing_subscriber::Layer<S>>, TelemetryGuard)>
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::WithExportConfig;
+
+    if !endpoint_configured() {
+        return Ok((None, TelemetryGuard { provider: None }));
+    }
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_protocol(opentelemetry_otlp::Protocol::HttpBinary)
+        .build()?;
+
+    let mut resource = opentelemetry_sdk::Resource::builder();
+    if std::env::var("OTEL_SERVICE_NAME").is_err() {
+        resource = resource.with_service_name(env!("CARGO_PKG_NAME"));
+    }
+    let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource.build())
+        .build();
+
+    opentelemetry::global::set_text_map_propagator(opentelemetry_sdk::propagation::TraceContextPropagator::new());
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    EXPORTING.store(true, Ordering::Release);
+
+    Ok((
+        Some(tracing_opentelemetry::layer().with_tracer(tracer)),
+        TelemetryGuard { provider: Some(provider) },
+    ))
+}
+
+/// Layer exporting spans; the `otel` feature is disabled, so there is none
+#[cfg(not(feature = "otel"))]
+pub fn layer() -> Result<(Option<tracing_subscriber::layer::Identity>, TelemetryGuard)> {
+    Ok((None, TelemetryGuard {}))
+}
+
+/// Continue a trace started by the client, from its `traceparent` header
+pub fn set_remote_parent(span: &Span, headers: &hyper::HeaderMap) {
+    #[cfg(feature = "otel")]
+    if exporting() {
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.extract(&otel::HeaderExtractor(headers))
+        });
+        let _ = span.set_parent(parent);
+    }
+    #[cfg(not(feature = "otel"))]
+    let _ = (span, headers);
+}
+
+/// Span around one call to the PHP worker; disabled unless traces are exported
+pub fn bridge_span() -> Span {
+    if exporting() {
+        tracing::info_span!("bridge_call", otel.kind = "client")
+    } else {
+        Span::none()
+    }
+}
+
+/// Pass the trace context of `span` to PHP with the request
+///
+/// Each propagation field (`traceparent`, `tracestate`) is added both as a
+/// header and as an `HTTP_*` server variable.
+pub fn inject_trace_context(span: &Span, request: &mut serde_json::Value) {
+    #[cfg(feature = "otel")]
+    if exporting() {
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let mut carrier = std::collections::HashMap::new();
+        opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&span.context(), &mut carrier)
+        });
+        for (name, value) in carrier {
+            request["server"][format!("HTTP_{}", name.to_ascii_uppercase())] = value.clone().into();
+            request["headers"][name] = value.into();
+        }
+    }
+    #[cfg(not(feature = "otel"))]
+    let _ = (span, request);
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use opentelemetry::propagation::Extractor;
+
+    /// Read propagation fields from hyper's headers
+    pub(super) struct HeaderExtractor<'a>(pub &'a hyper::HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(|name| name.as_str()).collect()
+        }
+    }
+}
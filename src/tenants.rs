@@ -0,0 +1,320 @@
+//! Several Laravel applications behind one listener
+//!
+//! `TENANTS` maps path prefixes to other applications, each with a PHP
+//! worker socket and a public directory of its own; every other path goes to
+//! the application at `SOCKET_PATH` as before. The longest matching prefix
+//! wins, and a prefix matches whole path segments: `/admin` covers `/admin`
+//! and `/admin/users`, not `/administrator`.
+//!
+//! ```toml
+//! [server]
+//! tenants = [
+//!     { prefix = "/admin", socket = "/run/admin.sock", public_dir = "/srv/admin/public", strip_prefix = true },
+//! ]
+//! ```
+//!
+//! With `strip_prefix` the application sees `/users` for `/admin/users` and
+//! gets the prefix in `X-Forwarded-Prefix`; without it, the full path.
+//! Static files are looked up in `public_dir` under the path the application
+//! sees; without `public_dir` every request goes to the worker. `protocol`
+//! picks the application's `WORKER_PROTOCOL`, the main one's by default.
+//!
+//! Tenant workers are not started by this process. Each runs under its own
+//! process manager (`php artisan laravel-rust:serve` with its `SOCKET_PATH`),
+//! so its socket file is left in place on shutdown and it gets no
+//! terminating notification.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use hyper::{StatusCode, Uri};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{debug, info};
+
+use crate::bridge::connection_pool::Framing;
+use crate::bridge::socket_bridge::SocketBridge;
+use crate::config::AppConfig;
+use crate::metrics::{metrics, MetricKind};
+use crate::file_cache::FileCache;
+use crate::static_cache::AssetManifest;
+use crate::worker_protocol::WorkerProtocol;
+
+/// Header carrying the prefix stripped from the path the application sees
+pub const FORWARDED_PREFIX_HEADER: &str = "x-forwarded-prefix";
+
+/// `TENANTS` entry as written in the config
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TenantSpec {
+    pub prefix: String,
+    /// Socket of the application's PHP worker
+    pub socket: String,
+    #[serde(default)]
+    pub public_dir: Option<String>,
+    #[serde(default)]
+    pub strip_prefix: bool,
+    /// `WORKER_PROTOCOL` of the application's worker
+    #[serde(default)]
+    pub protocol: Option<String>,
+}
+
+impl TenantSpec {
+    pub fn from_env() -> Result<Vec<Self>, Vec<(&'static str, String)>> {
+        let Some(json) = std::env::var("TENANTS").ok().filter(|v| !v.trim().is_empty()) else {
+            return Ok(Vec::new());
+        };
+        let main_socket = std::env::var("SOCKET_PATH").unwrap_or_else(|_| "/tmp/rust_php_bridge.sock".to_string());
+        let specs = Self::parse_list(&json).map_err(|errors| errors.into_iter().map(|e| ("TENANTS", e)).collect::<Vec<_>>())?;
+        match specs.iter().find(|spec| spec.socket == main_socket) {
+            Some(spec) => Err(vec![("TENANTS", format!("{}: socket {:?} is the SOCKET_PATH of the main application", spec.prefix, spec.socket))]),
+            None => Ok(specs),
+        }
+    }
+
+    /// Parse a JSON array of `{prefix, socket, public_dir, strip_prefix, protocol}` entries
+    fn parse_list(json: &str) -> Result<Vec<Self>, Vec<String>> {
+        let entries: Vec<Self> = serde_json::from_str(json).map_err(|e| {
+            vec![format!("must be a list of {{ prefix, socket, public_dir, strip_prefix, protocol }} entries: {}", e)]
+        })?;
+
+        let mut specs: Vec<Self> = Vec::new();
+        let mut problems = Vec::new();
+        for mut spec in entries {
+            let prefix = spec.prefix.trim_end_matches('/');
+            if !spec.prefix.starts_with('/') || prefix.is_empty() {
+                problems.push(format!("prefix {:?} must start with / and not be / itself", spec.prefix));
+                continue;
+            }
+            spec.prefix = prefix.to_string();
+            if specs.iter().any(|other| other.prefix == spec.prefix) {
+                problems.push(format!("prefix {:?} appears more than once", spec.prefix));
+                continue;
+            }
+            if spec.socket.trim().is_empty() {
+                problems.push(format!("{}: needs the socket of its PHP worker", spec.prefix));
+                continue;
+            }
+            if let Some(protocol) = spec.protocol.as_deref().filter(|name| WorkerProtocol::parse(name).is_none()) {
+                problems.push(format!(
+                    "{}: protocol {:?} must be one of {}",
+                    spec.prefix,
+                    protocol,
+                    WorkerProtocol::NAMES.join(", ")
+                ));
+                continue;
+            }
+            spec.public_dir = spec.public_dir.filter(|dir| !dir.trim().is_empty());
+            specs.push(spec);
+        }
+
+        if problems.is_empty() {
+            Ok(specs)
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// Application served under one `TENANTS` prefix
+pub struct Tenant {
+    prefix: String,
+    strip_prefix: bool,
+    public_dir: Option<String>,
+    /// Versioned assets from the Vite/Mix manifests in `public_dir`
+    assets: Option<Arc<AssetManifest>>,
+    socket_bridge: Arc<SocketBridge>,
+    /// Set once the worker socket has accepted a connection
+    ready: AtomicBool,
+    requests: AtomicU64,
+    /// Requests answered with a 5xx status
+    errors: AtomicU64,
+}
+
+impl Tenant {
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Whether `path` is `prefix` or below it
+    fn matches(&self, path: &str) -> bool {
+        path.strip_prefix(self.prefix.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// Whether the application sees paths without the prefix
+    pub fn strips_prefix(&self) -> bool {
+        self.strip_prefix
+    }
+
+    /// Path the application sees for the request path `path`
+    pub fn app_path<'a>(&self, path: &'a str) -> &'a str {
+        if !self.strip_prefix {
+            return path;
+        }
+        match &path[self.prefix.len().min(path.len())..] {
+            "" => "/",
+            rest => rest,
+        }
+    }
+
+    /// URI the application sees for `uri`
+    pub fn app_uri(&self, uri: &Uri) -> String {
+        match uri.query() {
+            Some(query) => format!("{}?{}", self.app_path(uri.path()), query),
+            None => self.app_path(uri.path()).to_string(),
+        }
+    }
+
+    /// Public directory and its asset manifest, when static files are served
+    pub fn static_root(&self) -> Option<(&str, &AssetManifest)> {
+        Some((self.public_dir.as_deref()?, self.assets.as_deref()?))
+    }
+
+    pub fn socket_bridge(&self) -> &Arc<SocketBridge> {
+        &self.socket_bridge
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    fn record(&self, status: StatusCode) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if status.is_server_error() {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        metrics().inc_counter("tenant_requests_total", &[("tenant", &self.prefix), ("status", status.as_str())]);
+    }
+
+    fn report(&self) -> serde_json::Value {
+        json!({
+            "prefix": self.prefix,
+            "socket_path": self.socket_bridge.socket_path(),
+            "public_dir": self.public_dir,
+            "strip_prefix": self.strip_prefix,
+            "protocol": self.socket_bridge.codec().name(),
+            "ready": self.is_ready(),
+            "requests": self.requests.load(Ordering::Relaxed),
+            "errors": self.errors.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Applications of `TENANTS`, longest prefix first
+#[derive(Default)]
+pub struct Tenants(Vec<Arc<Tenant>>);
+
+impl Tenants {
+    /// Connect to the workers of `specs` with the bridge settings of `app_config`
+    pub fn new(specs: Vec<TenantSpec>, app_config: &AppConfig) -> Result<Self> {
+        if !specs.is_empty() {
+            metrics().describe(
+                "tenant_requests_total",
+                MetricKind::Counter,
+                "Requests to TENANTS applications, by prefix and status code",
+            );
+        }
+
+        let mut tenants = Vec::with_capacity(specs.len());
+        for spec in specs {
+            let protocol = spec.protocol.as_deref().and_then(WorkerProtocol::parse).unwrap_or_else(WorkerProtocol::from_env);
+            let socket_bridge = SocketBridge::for_socket(app_config, &spec.socket, protocol)?;
+            // The worker belongs to another process manager
+            socket_bridge.keep_socket_file();
+            tenants.push(Arc::new(Tenant {
+                assets: spec.public_dir.as_deref().map(AssetManifest::load),
+                prefix: spec.prefix,
+                strip_prefix: spec.strip_prefix,
+                public_dir: spec.public_dir,
+                socket_bridge,
+                ready: AtomicBool::new(false),
+                requests: AtomicU64::new(0),
+                errors: AtomicU64::new(0),
+            }));
+        }
+        tenants.sort_by_key(|tenant| std::cmp::Reverse(tenant.prefix.len()));
+        Ok(Self(tenants))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Application serving `path`, or `None` for the main one
+    pub fn select(&self, path: &str) -> Option<&Arc<Tenant>> {
+        self.0.iter().find(|tenant| tenant.matches(path))
+    }
+
+    /// Count a response to `path` for the application serving it
+    pub fn record(&self, path: &str, status: StatusCode) {
+        if let Some(tenant) = self.select(path) {
+            tenant.record(status);
+        }
+    }
+
+    /// Prefixes of the applications whose worker has not been reached yet
+    pub fn not_ready(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().filter(|tenant| !tenant.is_ready()).map(|tenant| tenant.prefix.as_str())
+    }
+
+    /// Mark each application ready once its worker socket accepts a connection
+    ///
+    /// Goridge workers connect to the server instead, so theirs is ready
+    /// once a worker answers a ping.
+    pub fn spawn_readiness_probes(&self, interval: Duration) {
+        for tenant in &self.0 {
+            let tenant = tenant.clone();
+            tokio::spawn(async move {
+                loop {
+                    let socket_path = tenant.socket_bridge.socket_path();
+                    let reached = match tenant.socket_bridge.codec().framing() {
+                        Framing::LengthPrefixed => tokio::net::UnixStream::connect(&socket_path).await.map(drop).map_err(anyhow::Error::from),
+                        Framing::Goridge => tenant.socket_bridge.ping_direct(interval.max(Duration::from_secs(1))).await,
+                    };
+                    match reached {
+                        Ok(_) => {
+                            tenant.ready.store(true, Ordering::Release);
+                            info!(prefix = %tenant.prefix, socket_path, "✅ Tenant PHP worker ready, proxying requests");
+                            break;
+                        }
+                        Err(e) => debug!(prefix = %tenant.prefix, socket_path, error = %e, "Tenant PHP worker not reachable yet"),
+                    }
+                    tokio::time::sleep(interval).await;
+                }
+            });
+        }
+    }
+
+    /// Re-read the asset manifests whenever `cache` is flushed
+    pub fn track_manifests(&self, cache: &FileCache) {
+        for assets in self.0.iter().filter_map(|tenant| tenant.assets.as_ref()) {
+            cache.track_manifest(assets);
+        }
+    }
+
+    /// Re-read the asset manifests every `interval`
+    pub fn spawn_manifest_watchers(&self, interval: Duration) {
+        for assets in self.0.iter().filter_map(|tenant| tenant.assets.as_ref()) {
+            assets.spawn_watcher(interval);
+        }
+    }
+
+    /// Per-application readiness and request counts, for the admin stats
+    pub fn report(&self) -> serde_json::Value {
+        self.0.iter().map(|tenant| tenant.report()).collect()
+    }
+
+    /// Close the connections to every tenant worker
+    pub async fn cleanup(&self) {
+        for tenant in &self.0 {
+            tenant.socket_bridge.cleanup().await;
+        }
+    }
+}
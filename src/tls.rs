@@ -0,0 +1,546 @@
+//! TLS termination on the HTTP listener
+//!
+//! With `TLS_ENABLED=true` the HTTP listener speaks HTTPS itself, so no
+//! proxy is needed in front of it just for TLS. The certificate chain
+//! (`TLS_CERT_PATH`) and the private key (`TLS_KEY_PATH`) are PEM files.
+//! They are read when the server is built, before privileges are dropped,
+//! and a file that cannot be read or parsed, or a key that does not belong
+//! to the certificate, stops the start with an error naming the file. New
+//! files take a restart or a binary upgrade.
+//!
+//! Handshakes run beside the accept loop, so a slow client holds up no one
+//! else, and one not finished within `HANDSHAKE_TIMEOUT` is dropped. Failed
+//! handshakes are logged at debug level and counted in
+//! `http_tls_handshake_failures_total`. Only HTTP/1.1 is offered over ALPN:
+//! WebSocket upgrades and the drain's `Connection: close` rely on it.
+//!
+//! Requests that arrive over TLS reach Laravel with the `HTTPS` server
+//! variable set to `on`, so `url()` builds `https://` links.
+//!
+//! With `TLS_CLIENT_CA` clients are asked for a certificate, verified
+//! against the CA bundle in that file. `TLS_CLIENT_AUTH=require` (the
+//! default) refuses clients without one; `optional` lets them in and says
+//! so. A rejected certificate is logged with the peer address at warn
+//! level and counted in `http_tls_client_cert_rejections_total`. Laravel
+//! gets what the certificate proved in the server variables nginx and
+//! mod_ssl use ([`PeerCertificate::server_vars`]).
+
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use anyhow::{Context as _, Result};
+use futures::stream::{FuturesUnordered, StreamExt};
+use hyper::server::accept::Accept;
+use hyper::{Body, Request, Response, Uri};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::crypto::{self, CryptoProvider};
+use tokio_rustls::rustls::pki_types::pem::{self, PemObject};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use tokio_rustls::rustls::server::danger::ClientCertVerifier;
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, ServerConfig, SignatureScheme};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use tracing::{debug, warn};
+use x509_parser::time::ASN1Time;
+use x509_parser::x509::X509Name;
+
+use crate::accept::{AddrStream, Incoming};
+use crate::metrics::{metrics, MetricKind};
+
+/// Longest a client may take to finish the handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Certificate and key of the HTTP listener
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// CA bundle client certificates are verified against; no client certificates without it
+    pub client_ca: Option<PathBuf>,
+    pub client_auth: ClientAuth,
+}
+
+/// Whether clients must present a certificate when `TLS_CLIENT_CA` is set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClientAuth {
+    #[default]
+    Require,
+    /// Clients without a certificate are let in; a certificate they present must still verify
+    Optional,
+}
+
+impl ClientAuth {
+    /// Accepted values of `TLS_CLIENT_AUTH`
+    pub const NAMES: &'static [&'static str] = &["require", "optional"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "require" => Some(Self::Require),
+            "optional" => Some(Self::Optional),
+            _ => None,
+        }
+    }
+}
+
+impl TlsConfig {
+    /// Settings from the environment; `None` unless `TLS_ENABLED` is set
+    pub fn from_env() -> Result<Option<Self>, Vec<(&'static str, String)>> {
+        if !std::env::var("TLS_ENABLED").is_ok_and(|v| v == "true" || v == "1") {
+            return Ok(None);
+        }
+        let mut problems = Vec::new();
+        let mut path = |env: &'static str| {
+            let value = std::env::var(env).unwrap_or_default();
+            if value.trim().is_empty() {
+                problems.push((env, "is required when TLS_ENABLED=true".to_string()));
+            }
+            PathBuf::from(value.trim())
+        };
+        let cert_path = path("TLS_CERT_PATH");
+        let key_path = path("TLS_KEY_PATH");
+        let client_ca = std::env::var("TLS_CLIENT_CA").ok().filter(|v| !v.trim().is_empty()).map(|v| PathBuf::from(v.trim()));
+        let client_auth = match std::env::var("TLS_CLIENT_AUTH").ok().filter(|v| !v.trim().is_empty()) {
+            None => ClientAuth::default(),
+            Some(value) => ClientAuth::parse(value.trim()).unwrap_or_else(|| {
+                problems.push(("TLS_CLIENT_AUTH", format!("must be one of {}", ClientAuth::NAMES.join(", "))));
+                ClientAuth::default()
+            }),
+        };
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        Ok(Some(Self {
+            cert_path,
+            key_path,
+            client_ca,
+            client_auth,
+        }))
+    }
+
+    /// Load the certificate chain, the key and the client CA bundle, failing with the file at fault
+    pub fn listener(&self) -> Result<TlsListener> {
+        let certs = load_certs(&self.cert_path, "TLS_CERT_PATH")?;
+        let key = PrivateKeyDer::from_pem_file(&self.key_path).map_err(|e| match e {
+            pem::Error::NoItemsFound => anyhow::anyhow!("no private key in TLS_KEY_PATH {}", self.key_path.display()),
+            e => anyhow::anyhow!("cannot load the private key from TLS_KEY_PATH {}: {}", self.key_path.display(), e),
+        })?;
+        let builder = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .context("cannot set up TLS")?;
+        let builder = match &self.client_ca {
+            None => builder.with_no_client_auth(),
+            Some(path) => builder.with_client_cert_verifier(self.client_verifier(path)?),
+        };
+        let mut config = builder
+            .with_single_cert(certs, key)
+            .with_context(|| {
+                format!(
+                    "private key in TLS_KEY_PATH {} cannot be used with the certificate in TLS_CERT_PATH {}",
+                    self.key_path.display(),
+                    self.cert_path.display()
+                )
+            })?;
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(TlsListener {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            client_auth: self.client_ca.as_ref().map(|_| self.client_auth),
+        })
+    }
+
+    /// Verifier of client certificates against the CA bundle at `path`
+    fn client_verifier(&self, path: &Path) -> Result<Arc<dyn ClientCertVerifier>> {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(path, "TLS_CLIENT_CA")? {
+            roots
+                .add(cert)
+                .map_err(|e| anyhow::anyhow!("invalid CA certificate in TLS_CLIENT_CA {}: {}", path.display(), e))?;
+        }
+        let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider());
+        let verifier = match self.client_auth {
+            ClientAuth::Require => verifier,
+            ClientAuth::Optional => verifier.allow_unauthenticated(),
+        };
+        verifier
+            .build()
+            .map_err(|e| anyhow::anyhow!("cannot verify client certificates with TLS_CLIENT_CA {}: {}", path.display(), e))
+    }
+}
+
+/// The HTTP listener's TLS setup, loaded
+#[derive(Clone)]
+pub struct TlsListener {
+    acceptor: TlsAcceptor,
+    /// Set when clients are asked for a certificate
+    client_auth: Option<ClientAuth>,
+}
+
+/// Certificates of a PEM file, leaf first; an error naming `env` if there are none
+fn load_certs(path: &Path, env: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow::anyhow!("cannot load the certificate from {} {}: {}", env, path.display(), e))?;
+    anyhow::ensure!(!certs.is_empty(), "no certificate in {} {}", env, path.display());
+    Ok(certs)
+}
+
+/// What the client certificate of a connection proved, on a listener with `TLS_CLIENT_CA`
+#[derive(Debug, Clone)]
+pub enum PeerCertificate {
+    /// Verified against `TLS_CLIENT_CA`
+    Verified(Arc<ClientCertificate>),
+    /// None was presented, which `TLS_CLIENT_AUTH=optional` allows
+    NotPresented,
+}
+
+impl PeerCertificate {
+    /// Server variables for Laravel, named as nginx and mod_ssl name them
+    ///
+    /// `SSL_CLIENT_VERIFY` is `SUCCESS` or `NONE`; without a certificate
+    /// it is the only one set.
+    pub fn server_vars(&self) -> Vec<(&'static str, String)> {
+        let Self::Verified(cert) = self else {
+            return vec![("SSL_CLIENT_VERIFY", "NONE".to_string())];
+        };
+        vec![
+            ("SSL_CLIENT_VERIFY", "SUCCESS".to_string()),
+            ("SSL_CLIENT_S_DN", cert.subject.clone()),
+            ("SSL_CLIENT_I_DN", cert.issuer.clone()),
+            ("SSL_CLIENT_M_SERIAL", cert.serial.clone()),
+            ("SSL_CLIENT_FINGERPRINT", cert.fingerprint.clone()),
+            ("SSL_CLIENT_V_START", cert.not_before.clone()),
+            ("SSL_CLIENT_V_END", cert.not_after.clone()),
+        ]
+    }
+}
+
+/// Fields of a verified client certificate, formatted as nginx formats them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    /// Subject in RFC 2253 form, most specific first: `CN=api-client,O=Example`
+    pub subject: String,
+    pub issuer: String,
+    /// Serial number in upper-case hex
+    pub serial: String,
+    /// SHA-1 of the DER certificate in lower-case hex, as `$ssl_client_fingerprint`
+    pub fingerprint: String,
+    /// Start of validity: `Jan  2 03:04:05 2026 GMT`
+    pub not_before: String,
+    pub not_after: String,
+}
+
+impl ClientCertificate {
+    pub fn parse(der: &[u8]) -> Result<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).map_err(|e| anyhow::anyhow!("invalid client certificate: {}", e))?;
+        let fingerprint = ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, der);
+        Ok(Self {
+            subject: rfc2253(cert.subject()),
+            issuer: rfc2253(cert.issuer()),
+            serial: cert.raw_serial().iter().map(|byte| format!("{:02X}", byte)).collect(),
+            fingerprint: fingerprint.as_ref().iter().map(|byte| format!("{:02x}", byte)).collect(),
+            not_before: openssl_time(cert.validity().not_before),
+            not_after: openssl_time(cert.validity().not_after),
+        })
+    }
+}
+
+/// A name as RFC 2253 writes it: the last RDN first, `,` between them, `+` within one
+fn rfc2253(name: &X509Name<'_>) -> String {
+    let registry = x509_parser::objects::oid_registry();
+    name.iter_rdn()
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .map(|rdn| {
+            rdn.iter()
+                .map(|attr| {
+                    let value = match attr.as_str() {
+                        Ok(value) => escape_dn_value(value),
+                        // Values that are not strings are written as `#` and their DER, hex-encoded
+                        Err(_) => format!("#{}", attr.attr_value().as_bytes().iter().map(|b| format!("{:02x}", b)).collect::<String>()),
+                    };
+                    match x509_parser::objects::oid2abbrev(attr.attr_type(), registry) {
+                        Ok(abbrev) => format!("{}={}", abbrev, value),
+                        Err(_) => format!("{}={}", attr.attr_type().to_id_string(), value),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("+")
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// An attribute value with the characters RFC 2253 reserves escaped
+fn escape_dn_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let last = value.chars().count().saturating_sub(1);
+    for (at, c) in value.chars().enumerate() {
+        let edge = (at == 0 && (c == '#' || c == ' ')) || (at == last && c == ' ');
+        if edge || matches!(c, ',' | '+' | '"' | '\\' | '<' | '>' | ';') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A certificate time as OpenSSL prints it: `Jan  2 03:04:05 2026 GMT`
+fn openssl_time(time: ASN1Time) -> String {
+    // Certificate times are in UTC, which the display writes as +00:00
+    let display = time.to_string();
+    match display.strip_suffix(" +00:00") {
+        Some(time) => format!("{} GMT", time),
+        None => display,
+    }
+}
+
+/// The ring provider, whatever other crates in the build enable
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(crypto::ring::default_provider())
+}
+
+/// Accepted connection of the HTTP listener, plain or over TLS
+pub enum Connection {
+    Plain(AddrStream),
+    /// With the client certificate when the listener asks for one
+    Tls(Box<TlsStream<AddrStream>>, Option<PeerCertificate>),
+}
+
+impl Connection {
+    pub fn remote_addr(&self) -> SocketAddr {
+        match self {
+            Self::Plain(stream) => stream.remote_addr(),
+            Self::Tls(stream, _) => stream.get_ref().0.remote_addr(),
+        }
+    }
+
+    /// Whether the client connected over TLS
+    pub fn is_tls(&self) -> bool {
+        matches!(self, Self::Tls(..))
+    }
+
+    /// The client certificate, when the listener asks for one (`TLS_CLIENT_CA`)
+    pub fn peer_certificate(&self) -> Option<&PeerCertificate> {
+        match self {
+            Self::Plain(_) => None,
+            Self::Tls(_, peer) => peer.as_ref(),
+        }
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Tls(stream, _) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Tls(stream, _) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[io::IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            Self::Tls(stream, _) => Pin::new(stream.as_mut()).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Self::Plain(stream) => stream.is_write_vectored(),
+            Self::Tls(stream, _) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Tls(stream, _) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            Self::Tls(stream, _) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
+
+/// A handshake in progress; `None` once it has failed
+type Handshake = Pin<Box<dyn Future<Output = Option<Connection>> + Send>>;
+
+/// Connections of the HTTP listener, handed on once their TLS handshake is done
+pub struct Connections {
+    incoming: Incoming,
+    /// `None` serves plain HTTP
+    tls: Option<TlsListener>,
+    handshakes: FuturesUnordered<Handshake>,
+}
+
+impl Connections {
+    pub fn new(incoming: Incoming, tls: Option<TlsListener>) -> Self {
+        if let Some(tls) = &tls {
+            metrics().describe(
+                "http_tls_handshake_failures_total",
+                MetricKind::Counter,
+                "TLS handshakes on the HTTP listener that failed or timed out",
+            );
+            if tls.client_auth.is_some() {
+                metrics().describe(
+                    "http_tls_client_cert_rejections_total",
+                    MetricKind::Counter,
+                    "TLS handshakes refused for a client certificate that is missing or does not verify against TLS_CLIENT_CA",
+                );
+            }
+        }
+        Self {
+            incoming,
+            tls,
+            handshakes: FuturesUnordered::new(),
+        }
+    }
+}
+
+impl Accept for Connections {
+    type Conn = Connection;
+    type Error = io::Error;
+
+    fn poll_accept(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Connection, io::Error>>> {
+        let this = self.get_mut();
+        let Some(tls) = &this.tls else {
+            return Pin::new(&mut this.incoming).poll_accept(cx).map_ok(Connection::Plain);
+        };
+
+        // Start the handshake of everything accepted so far
+        while let Poll::Ready(accepted) = Pin::new(&mut this.incoming).poll_accept(cx) {
+            match accepted {
+                Some(Ok(stream)) => this.handshakes.push(handshake(tls.clone(), stream)),
+                Some(Err(error)) => return Poll::Ready(Some(Err(error))),
+                None => return Poll::Ready(None),
+            }
+        }
+        while let Poll::Ready(Some(done)) = this.handshakes.poll_next_unpin(cx) {
+            if let Some(connection) = done {
+                return Poll::Ready(Some(Ok(connection)));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+fn handshake(tls: TlsListener, stream: AddrStream) -> Handshake {
+    let peer = stream.remote_addr();
+    Box::pin(async move {
+        let error = match tokio::time::timeout(HANDSHAKE_TIMEOUT, tls.acceptor.accept(stream)).await {
+            Ok(Ok(stream)) => match tls.client_auth.map(|_| peer_certificate(&stream)).transpose() {
+                Ok(certificate) => return Some(Connection::Tls(Box::new(stream), certificate)),
+                Err(e) => format!("{:#}", e),
+            },
+            Ok(Err(error)) if is_client_cert_rejection(&error) => {
+                warn!(peer = %peer, error = %error, "TLS client certificate rejected");
+                metrics().inc_counter("http_tls_client_cert_rejections_total", &[]);
+                error.to_string()
+            }
+            Ok(Err(error)) => error.to_string(),
+            Err(_) => format!("not finished within {} s", HANDSHAKE_TIMEOUT.as_secs()),
+        };
+        debug!(peer = %peer, error = %error, "TLS handshake failed");
+        metrics().inc_counter("http_tls_handshake_failures_total", &[]);
+        None
+    })
+}
+
+/// The client certificate rustls verified for `stream`, or that none was presented
+fn peer_certificate(stream: &TlsStream<AddrStream>) -> Result<PeerCertificate> {
+    match stream.get_ref().1.peer_certificates().and_then(|certs| certs.first()) {
+        Some(leaf) => Ok(PeerCertificate::Verified(Arc::new(ClientCertificate::parse(leaf)?))),
+        None => Ok(PeerCertificate::NotPresented),
+    }
+}
+
+/// Whether a handshake failed over the client's certificate, missing or not verified
+fn is_client_cert_rejection(error: &io::Error) -> bool {
+    matches!(
+        error.get_ref().and_then(|inner| inner.downcast_ref::<tokio_rustls::rustls::Error>()),
+        Some(tokio_rustls::rustls::Error::InvalidCertificate(_) | tokio_rustls::rustls::Error::NoCertificatesPresented)
+    )
+}
+
+/// `GET uri` over HTTPS without verifying the server's certificate
+///
+/// For the `check` subcommand, which asks its own listener over loopback:
+/// the certificate names the public host, not the address connected to,
+/// and only whether the listener answers matters.
+pub async fn get_unverified(uri: &Uri) -> Result<Response<Body>> {
+    let authority = uri.authority().context("URL has no host")?.clone();
+    let host = authority.host().trim_start_matches('[').trim_end_matches(']');
+    let server_name = ServerName::try_from(host.to_string()).context("invalid host name")?;
+    let config = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .context("cannot set up TLS")?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AnyCertificate(provider())))
+        .with_no_client_auth();
+
+    let stream = tokio::net::TcpStream::connect(authority.as_str()).await?;
+    let stream = TlsConnector::from(Arc::new(config)).connect(server_name, stream).await?;
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await?;
+    tokio::spawn(connection);
+    let request = Request::get(uri.clone()).header(hyper::header::HOST, authority.as_str()).body(Body::empty())?;
+    Ok(sender.send_request(request).await?)
+}
+
+/// Accepts any certificate; signatures are still checked
+#[derive(Debug)]
+struct AnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
@@ -0,0 +1,88 @@
+//! Trailing slash normalization
+//!
+//! `/pricing` and `/pricing/` are different URIs to Laravel, to caches and
+//! to search engines. `TRAILING_SLASH` picks one spelling and redirects the
+//! other to it before the request reaches the static file handler or the
+//! PHP worker:
+//!
+//! * `off` (default) - both are passed on unchanged;
+//! * `strip` - `/pricing/` is redirected to `/pricing`;
+//! * `add` - `/pricing` is redirected to `/pricing/`.
+//!
+//! GET and HEAD get `301 Moved Permanently`, other methods `308 Permanent
+//! Redirect`, so the client repeats them with their body. The query string
+//! is kept. The root path, paths starting with `//` (a `Location` of
+//! `//host` would leave the site) and paths naming a file or directory in
+//! `public/` are never redirected; `add` also leaves paths of static files,
+//! such as `/app.css`, alone.
+
+use std::path::Path;
+
+use hyper::header::{self, HeaderValue};
+use hyper::{Body, Method, Response, StatusCode};
+
+/// Which spelling of a path with or without a trailing slash is canonical
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailingSlash {
+    #[default]
+    Off,
+    Strip,
+    Add,
+}
+
+impl TrailingSlash {
+    pub fn from_env() -> Result<Self, Vec<(&'static str, String)>> {
+        match std::env::var("TRAILING_SLASH").unwrap_or_default().to_ascii_lowercase().as_str() {
+            "" | "off" => Ok(Self::Off),
+            "strip" => Ok(Self::Strip),
+            "add" => Ok(Self::Add),
+            other => Err(vec![("TRAILING_SLASH", format!("must be one of off, strip, add, got {:?}", other))]),
+        }
+    }
+
+    /// Canonical spelling of `path`, if it differs
+    ///
+    /// `app_path` is the path within its application (without a stripped
+    /// tenant prefix), and `is_static` tells whether the static file
+    /// handler would serve it. Files on disk are checked separately with
+    /// [`Self::exists_in`].
+    pub fn canonical(self, path: &str, app_path: &str, is_static: bool) -> Option<String> {
+        if app_path == "/" || path.starts_with("//") {
+            return None;
+        }
+        match self {
+            Self::Off => None,
+            Self::Strip if path.ends_with('/') => {
+                Some(path.trim_end_matches('/').to_string()).filter(|stripped| !stripped.is_empty())
+            }
+            Self::Add if !path.ends_with('/') && !is_static => Some(format!("{}/", path)),
+            _ => None,
+        }
+    }
+
+    /// Whether `app_path` names a file or directory below `public_dir`
+    pub async fn exists_in(public_dir: &str, app_path: &str) -> bool {
+        let relative = app_path.trim_matches('/');
+        !relative.split('/').any(|segment| segment == "..")
+            && tokio::fs::metadata(Path::new(public_dir).join(relative)).await.is_ok()
+    }
+
+    /// Redirect of a `method` request to `path`, keeping `query`
+    pub fn redirect(method: &Method, path: &str, query: Option<&str>) -> Response<Body> {
+        let status = if method == Method::GET || method == Method::HEAD {
+            StatusCode::MOVED_PERMANENTLY
+        } else {
+            StatusCode::PERMANENT_REDIRECT
+        };
+        let location = match query {
+            Some(query) => format!("{}?{}", path, query),
+            None => path.to_string(),
+        };
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = status;
+        if let Ok(location) = HeaderValue::from_str(&location) {
+            response.headers_mut().insert(header::LOCATION, location);
+        }
+        response
+    }
+}
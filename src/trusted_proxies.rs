@@ -0,0 +1,76 @@
+//! Client address behind reverse proxies
+//!
+//! Behind a load balancer every connection comes from the balancer, and the
+//! client's address is only in `X-Forwarded-For`. Anyone can send that
+//! header, so it is believed only from the networks in `TRUSTED_PROXIES`:
+//! starting with the connecting peer, each trusted hop vouches for the
+//! entry to its left, and the first address that is not a trusted proxy is
+//! the client. An entry that is not an IP address stops the walk at the
+//! last trusted hop. With `TRUSTED_PROXIES` unset the peer address is the
+//! client address, whatever the request carries.
+
+use std::net::IpAddr;
+
+use hyper::HeaderMap;
+
+use crate::cidr::{self, Cidr};
+
+/// Header the proxies append the address they received the request from to
+pub const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Networks whose `X-Forwarded-For` is believed
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(Vec<Cidr>);
+
+impl TrustedProxies {
+    pub fn from_env() -> Result<Self, Vec<String>> {
+        match std::env::var("TRUSTED_PROXIES") {
+            Ok(list) => cidr::parse_list(&list).map(Self),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Whether `ip` is a trusted proxy
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        cidr::any_contains(&self.0, ip)
+    }
+
+    /// Address of the client the request from `peer` was made for
+    pub fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = peer.to_canonical();
+        if !self.contains(client) {
+            return client;
+        }
+        // Proxies append, so the rightmost entry was written by the peer
+        let hops = headers
+            .get_all(FORWARDED_FOR_HEADER)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect::<Vec<_>>();
+        for hop in hops.into_iter().rev() {
+            let Some(ip) = parse_hop(hop) else {
+                break;
+            };
+            client = ip.to_canonical();
+            if !self.contains(client) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// One `X-Forwarded-For` entry: an address, optionally with a port
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim();
+    if let Ok(ip) = hop.parse() {
+        return Some(ip);
+    }
+    // `[2001:db8::1]:443` or `192.0.2.1:443`
+    let host = match hop.strip_prefix('[') {
+        Some(rest) => rest.split_once(']')?.0,
+        None => hop.rsplit_once(':')?.0,
+    };
+    host.parse().ok()
+}
@@ -0,0 +1,431 @@
+//! Binary upgrade without dropping connections
+//!
+//! On SIGUSR1 (or `POST /admin/upgrade`) the server starts a new copy of its
+//! binary and hands it the listening sockets the way systemd socket
+//! activation does: as file descriptors from 3 on, counted by `LISTEN_FDS`
+//! and named by `LISTEN_FDNAMES`. The new process adopts them in `bind`
+//! instead of binding, and reports back over a pipe (the `upgrade_ready`
+//! descriptor) once it can serve requests. Only then does the old process
+//! stop accepting, drain and exit. The sockets stay open throughout, so
+//! connections arriving during the swap wait in the accept queue instead of
+//! being refused.
+//!
+//! The PHP worker is either handed over to the new process, which then
+//! watches it by PID, or left to the new process to start its own while the
+//! old one stops its worker after draining (`UPGRADE_WORKER`).
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use once_cell::sync::{Lazy, OnceCell};
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
+
+use crate::metrics::{metrics, MetricKind};
+
+/// First descriptor of the `LISTEN_FDS` range
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Name of the descriptor the new process reports readiness on
+const READY_FD_NAME: &str = "upgrade_ready";
+
+/// PID of a PHP worker handed over to the new process
+const WORKER_PID_ENV: &str = "LARAVEL_RUST_UPGRADE_WORKER_PID";
+
+/// Variables describing inherited descriptors, never passed on
+const HANDOFF_ENV: &[&str] = &["LISTEN_FDS", "LISTEN_FDNAMES", "LISTEN_PID", WORKER_PID_ENV];
+
+/// What happens to the PHP worker during an upgrade
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerHandoff {
+    /// The new process takes over the running worker
+    Handover,
+    /// The new process starts its own worker; the old one stops its worker after draining
+    Restart,
+}
+
+impl WorkerHandoff {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WorkerHandoff::Handover => "handover",
+            WorkerHandoff::Restart => "restart",
+        }
+    }
+}
+
+/// Upgrade settings, read when an upgrade is requested
+#[derive(Debug, Clone)]
+pub struct UpgradeConfig {
+    pub worker: WorkerHandoff,
+    /// How long the new process may take to report readiness
+    pub timeout: Duration,
+    /// Binary to start; by default the one this process was started from
+    pub binary: PathBuf,
+}
+
+impl UpgradeConfig {
+    pub fn from_env() -> Result<Self> {
+        let binary = match std::env::var_os("UPGRADE_BINARY").filter(|v| !v.is_empty()) {
+            Some(binary) => PathBuf::from(binary),
+            None => STARTUP
+                .get()
+                .and_then(|startup| startup.binary.clone())
+                .ok_or_else(|| anyhow!("cannot tell which binary this process was started from; set UPGRADE_BINARY"))?,
+        };
+        Ok(Self {
+            worker: match std::env::var("UPGRADE_WORKER").as_deref() {
+                Ok("restart") => WorkerHandoff::Restart,
+                _ => WorkerHandoff::Handover,
+            },
+            timeout: Duration::from_millis(
+                std::env::var("UPGRADE_TIMEOUT_MS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(30_000),
+            ),
+            binary,
+        })
+    }
+}
+
+/// How this process was started, captured before anything changes it
+struct Startup {
+    /// Environment before `.env` and the config file were applied
+    env: Vec<(OsString, OsString)>,
+    /// Resolved now, since a deploy may replace the file under its path later
+    binary: Option<PathBuf>,
+}
+
+static STARTUP: OnceCell<Startup> = OnceCell::new();
+
+#[derive(Default)]
+struct Descriptors {
+    /// Passed in by the process that started this one, by name
+    inherited: HashMap<String, OwnedFd>,
+    /// Worker PID passed in along with them
+    inherited_worker: Option<u32>,
+    /// Listeners handed to a new process on upgrade; `None` until enabled
+    handoff: Option<Vec<(&'static str, OwnedFd)>>,
+}
+
+static DESCRIPTORS: Lazy<Mutex<Descriptors>> = Lazy::new(Mutex::default);
+
+fn descriptors() -> std::sync::MutexGuard<'static, Descriptors> {
+    DESCRIPTORS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Capture the startup environment and adopt descriptors passed in by `LISTEN_FDS`
+///
+/// Call first thing in `main`, before `.env` is loaded and before any child
+/// process is spawned: the inherited descriptors are marked close-on-exec
+/// and the variables describing them removed, so they do not leak further.
+pub fn init() {
+    let _ = STARTUP.set(Startup {
+        env: std::env::vars_os()
+            .filter(|(key, _)| !HANDOFF_ENV.iter().any(|name| key == name))
+            .collect(),
+        binary: std::env::current_exe().ok(),
+    });
+
+    let mut descriptors = descriptors();
+    descriptors.inherited_worker = std::env::var(WORKER_PID_ENV).ok().and_then(|v| v.parse().ok());
+    let for_us = std::env::var("LISTEN_PID")
+        .map(|pid| pid == std::process::id().to_string())
+        .unwrap_or(true);
+    let count: RawFd = std::env::var("LISTEN_FDS").ok().and_then(|v| v.parse().ok()).unwrap_or(0);
+    if for_us && count > 0 {
+        let names = std::env::var("LISTEN_FDNAMES").unwrap_or_default();
+        let mut names = names.split(':');
+        for fd in LISTEN_FDS_START..LISTEN_FDS_START + count {
+            let name = names.next().filter(|name| !name.is_empty()).unwrap_or("unknown");
+            // SAFETY: LISTEN_FDS promises these descriptors are open and ours
+            unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            descriptors.inherited.insert(name.to_string(), fd);
+        }
+    }
+    for name in HANDOFF_ENV {
+        std::env::remove_var(name);
+    }
+}
+
+/// Names of the descriptors passed in by the previous process
+pub fn inherited_names() -> Vec<String> {
+    let mut names: Vec<String> = descriptors().inherited.keys().cloned().collect();
+    names.sort();
+    names
+}
+
+/// PHP worker handed over by the previous process, if it is still running
+pub fn take_inherited_worker() -> Option<u32> {
+    let pid = descriptors().inherited_worker.take()?;
+    // SAFETY: signal 0 only checks that the process exists
+    (unsafe { libc::kill(pid as libc::pid_t, 0) } == 0).then_some(pid)
+}
+
+/// Inherited listener `name`, if it is bound to `addr`
+///
+/// A listener bound elsewhere (the address changed in the configuration) is
+/// closed, and the caller binds a new one.
+pub(crate) fn take_tcp_listener(name: &str, addr: SocketAddr) -> Option<TcpListener> {
+    let fd = descriptors().inherited.remove(name)?;
+    let listener = TcpListener::from(fd);
+    match listener.local_addr() {
+        Ok(local) if local == addr => {
+            info!(listener = name, addr = %addr, "Adopted inherited listener");
+            Some(listener)
+        }
+        Ok(local) => {
+            warn!(listener = name, inherited = %local, configured = %addr, "Inherited listener has another address, binding anew");
+            None
+        }
+        Err(e) => {
+            warn!(listener = name, error = %e, "Inherited descriptor is not a listening socket, binding anew");
+            None
+        }
+    }
+}
+
+/// Keep the listeners registered from now on for a future upgrade
+pub fn enable_handoff() {
+    descriptors().handoff.get_or_insert_with(Vec::new);
+}
+
+/// Remember `listener` as `name` for a future upgrade; no-op unless enabled
+pub(crate) fn register(name: &'static str, listener: &TcpListener) {
+    let mut descriptors = descriptors();
+    let Some(handoff) = descriptors.handoff.as_mut() else {
+        return;
+    };
+    match listener.try_clone() {
+        Ok(listener) => {
+            handoff.retain(|(existing, _)| *existing != name);
+            handoff.push((name, listener.into()));
+        }
+        Err(e) => warn!(listener = name, error = %e, "Listener cannot be handed over on upgrade"),
+    }
+}
+
+/// Whether this process was started by an upgrade and has yet to report readiness
+pub fn is_successor() -> bool {
+    descriptors().inherited.contains_key(READY_FD_NAME)
+}
+
+/// Tell the previous process it can stop accepting connections
+pub fn notify_ready() {
+    let Some(fd) = descriptors().inherited.remove(READY_FD_NAME) else {
+        return;
+    };
+    if let Err(e) = std::fs::File::from(fd).write_all(b"1") {
+        warn!(error = %e, "Failed to report readiness to the previous process");
+    }
+}
+
+/// Start the new process and wait until it is ready to serve
+///
+/// # Arguments
+///
+/// * `config` - binary, worker handling and readiness timeout
+/// * `worker_pid` - PHP worker to hand over, with `WorkerHandoff::Handover`
+///
+/// # Returns
+///
+/// * `Ok(u32)` - PID of the new process, which is now accepting connections
+/// * `Err` - the process could not be started or did not become ready; it has been killed
+pub async fn spawn_successor(config: &UpgradeConfig, worker_pid: Option<u32>) -> Result<u32> {
+    let startup = STARTUP.get().ok_or_else(|| anyhow!("upgrade::init was not called"))?;
+
+    let mut fds = Vec::new();
+    let mut names = Vec::new();
+    for (name, fd) in descriptors().handoff.iter().flatten() {
+        fds.push(fd.try_clone().with_context(|| format!("duplicating the {} listener", name))?);
+        names.push(*name);
+    }
+    if fds.is_empty() {
+        bail!("no listeners to hand over");
+    }
+    let (ready_read, ready_write) = pipe()?;
+    fds.push(ready_write);
+    names.push(READY_FD_NAME);
+
+    // Move every descriptor above the target range, so placing one at its
+    // target in the child never closes another that is still to be placed
+    let count = fds.len() as RawFd;
+    let sources = fds
+        .iter()
+        .map(|fd| {
+            // SAFETY: F_DUPFD_CLOEXEC on a descriptor we own returns a new one or -1
+            let moved = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, LISTEN_FDS_START + count) };
+            if moved < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            Ok(unsafe { OwnedFd::from_raw_fd(moved) })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    drop(fds);
+
+    let mut command = Command::new(&config.binary);
+    command
+        .args(std::env::args_os().skip(1))
+        .env_clear()
+        .envs(startup.env.iter().map(|(key, value)| (key, value)))
+        .env("LISTEN_FDS", count.to_string())
+        .env("LISTEN_FDNAMES", names.join(":"));
+    if let Some(pid) = worker_pid.filter(|_| config.worker == WorkerHandoff::Handover) {
+        command.env(WORKER_PID_ENV, pid.to_string());
+    }
+    let raw: Vec<RawFd> = sources.iter().map(|fd| fd.as_raw_fd()).collect();
+    // SAFETY: only dup2 runs between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            for (target, source) in (LISTEN_FDS_START..).zip(&raw) {
+                if libc::dup2(*source, target) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("starting {}", config.binary.display()))?;
+    // The child has its copies; the pipe only reaches EOF once no writer is left here
+    drop(sources);
+    let pid = child.id();
+    info!(
+        pid,
+        binary = %config.binary.display(),
+        listeners = ?&names[..names.len() - 1],
+        worker = config.worker.as_str(),
+        "Started the new server process, waiting for it to become ready"
+    );
+
+    let ready = tokio::task::spawn_blocking(move || {
+        let mut byte = [0u8; 1];
+        std::fs::File::from(ready_read).read(&mut byte)
+    });
+    let error = match tokio::time::timeout(config.timeout, ready).await {
+        Ok(Ok(Ok(1))) => {
+            metrics().inc_counter("binary_upgrades_total", &[("outcome", "success")]);
+            return Ok(pid);
+        }
+        Ok(Ok(Ok(_))) => anyhow!("the new process exited before it was ready"),
+        Ok(Ok(Err(e))) => anyhow!("reading the readiness pipe: {}", e),
+        Ok(Err(e)) => anyhow!("readiness task failed: {}", e),
+        Err(_) => anyhow!("the new process was not ready within {:?}", config.timeout),
+    };
+    metrics().inc_counter("binary_upgrades_total", &[("outcome", "failed")]);
+    let _ = child.kill();
+    let _ = tokio::task::spawn_blocking(move || child.wait()).await;
+    Err(error.context(format!("upgrade to {} failed", config.binary.display())))
+}
+
+/// Close-on-exec pipe, as (read end, write end)
+fn pipe() -> Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0 as RawFd; 2];
+    // SAFETY: pipe2 fills both descriptors on success
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+/// Reply channel of an upgrade requested through the admin listener
+type Reply = oneshot::Sender<std::result::Result<u32, String>>;
+
+/// Requests an upgrade from the admin listener
+#[derive(Debug, Clone)]
+pub struct UpgradeTrigger {
+    sender: mpsc::Sender<Reply>,
+}
+
+impl UpgradeTrigger {
+    /// Request an upgrade and wait for its outcome
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u32)` - PID of the new process
+    /// * `Err` - why the upgrade failed, or that the server is already shutting down
+    pub async fn upgrade(&self) -> std::result::Result<u32, String> {
+        let (reply, outcome) = oneshot::channel();
+        self.sender
+            .send(reply)
+            .await
+            .map_err(|_| "the server is shutting down".to_string())?;
+        outcome
+            .await
+            .unwrap_or_else(|_| Err("the server is shutting down".to_string()))
+    }
+}
+
+/// One upgrade request, from SIGUSR1 or the admin listener
+pub struct UpgradeRequest {
+    reply: Option<Reply>,
+}
+
+impl UpgradeRequest {
+    pub fn source(&self) -> &'static str {
+        match self.reply {
+            Some(_) => "admin",
+            None => "SIGUSR1",
+        }
+    }
+
+    /// Report the outcome (new PID or error) to the admin caller, if any
+    pub fn reply(self, outcome: std::result::Result<u32, String>) {
+        if let Some(reply) = self.reply {
+            let _ = reply.send(outcome);
+        }
+    }
+}
+
+/// Subscription to upgrade requests
+///
+/// Create it before starting services: until SIGUSR1 is subscribed, its
+/// default action terminates the process.
+pub struct UpgradeRequests {
+    signal: Signal,
+    sender: mpsc::Sender<Reply>,
+    receiver: mpsc::Receiver<Reply>,
+}
+
+impl UpgradeRequests {
+    pub fn new() -> Result<Self> {
+        metrics().describe(
+            "binary_upgrades_total",
+            MetricKind::Counter,
+            "Binary upgrades started by this process, by outcome",
+        );
+        let (sender, receiver) = mpsc::channel(1);
+        Ok(Self {
+            signal: signal(SignalKind::user_defined1())?,
+            sender,
+            receiver,
+        })
+    }
+
+    pub fn trigger(&self) -> UpgradeTrigger {
+        UpgradeTrigger {
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// Wait for the next upgrade request
+    pub async fn recv(&mut self) -> UpgradeRequest {
+        tokio::select! {
+            _ = self.signal.recv() => UpgradeRequest { reply: None },
+            Some(reply) = self.receiver.recv() => UpgradeRequest { reply: Some(reply) },
+        }
+    }
+}
@@ -0,0 +1,506 @@
+//! WebSocket fan-out of Laravel broadcast events
+//!
+//! With `WS_ENABLED=true`, browsers connect to `WS_PATH` on the HTTP listener
+//! and subscribe to channels; Laravel pushes events to `WS_PUSH_SOCKET`, one
+//! JSON object per line, and every subscriber of the event's channel gets a
+//! copy. Nothing is persisted: a client that is not connected, or whose send
+//! queue is full, misses the event.
+//!
+//! Client messages are JSON text frames:
+//!
+//! * `{"type": "subscribe", "channel": "orders"}`
+//! * `{"type": "unsubscribe", "channel": "orders"}`
+//!
+//! The server answers with `connected` (carrying the `socket_id`),
+//! `subscribed`, `unsubscribed`, `error` and `event` messages. Channels named
+//! `private-*` or `presence-*` are authorized by Laravel through the
+//! `broadcast.auth` command, which gets the channel, the socket id and the
+//! `Cookie` / `Authorization` headers of the upgrade request.
+
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use futures::{SinkExt, StreamExt};
+use hyper::upgrade::Upgraded;
+use hyper::{header, Body, Request, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::io::AsyncBufReadExt;
+use tokio::net::UnixListener;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Role, WebSocketConfig};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::bridge::socket_bridge::SocketBridge;
+use crate::errors::{ServerError, UnavailableReason};
+use crate::metrics::{metrics, MetricKind};
+
+/// Largest message a client may send
+const MAX_CLIENT_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Channel prefixes that need Laravel's authorization
+const PRIVATE_PREFIXES: [&str; 2] = ["private-", "presence-"];
+
+/// Upgrade request headers passed to `broadcast.auth`
+const AUTH_HEADERS: [header::HeaderName; 2] = [header::COOKIE, header::AUTHORIZATION];
+
+static SOCKET_ID: AtomicU64 = AtomicU64::new(1);
+
+/// WebSocket endpoint settings
+#[derive(Debug, Clone)]
+pub struct BroadcastConfig {
+    /// Request path that accepts WebSocket upgrades
+    pub path: String,
+    /// Unix socket Laravel pushes events to
+    pub push_socket: String,
+    pub max_connections: usize,
+    /// Channels one connection may subscribe to
+    pub max_subscriptions: usize,
+    /// Ping interval; a client that has not answered by the next ping, or
+    /// does not take a message within it, is dropped
+    pub ping_interval: Duration,
+    /// Events queued per connection before further ones are dropped
+    pub queue_size: usize,
+}
+
+impl BroadcastConfig {
+    /// Settings from the environment, or `None` unless `WS_ENABLED` is set
+    pub fn from_env() -> Option<Self> {
+        let enabled = std::env::var("WS_ENABLED").is_ok_and(|v| v == "true" || v == "1");
+        if !enabled {
+            return None;
+        }
+        let number = |name: &str, default: u64| {
+            std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+        };
+        Some(Self {
+            path: std::env::var("WS_PATH").unwrap_or_else(|_| "/ws".to_string()),
+            push_socket: std::env::var("WS_PUSH_SOCKET").unwrap_or_else(|_| "/tmp/rust_php_push.sock".to_string()),
+            max_connections: number("WS_MAX_CONNECTIONS", 10_000) as usize,
+            max_subscriptions: number("WS_MAX_SUBSCRIPTIONS", 100) as usize,
+            ping_interval: Duration::from_millis(number("WS_PING_INTERVAL_MS", 30_000)),
+            queue_size: number("WS_QUEUE_SIZE", 256).max(1) as usize,
+        })
+    }
+}
+
+/// Event pushed by Laravel
+#[derive(Debug, Deserialize)]
+struct PushMessage {
+    channel: String,
+    event: String,
+    #[serde(default)]
+    payload: serde_json::Value,
+    /// Socket id of the client that caused the event, which does not get it (`toOthers()`)
+    #[serde(default)]
+    socket_id: Option<String>,
+}
+
+/// Message from a client
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ClientMessage {
+    Subscribe { channel: String },
+    Unsubscribe { channel: String },
+}
+
+/// Subscribers by channel, and the connections to close on shutdown
+pub struct BroadcastHub {
+    config: BroadcastConfig,
+    socket_bridge: Arc<SocketBridge>,
+    /// Send queue of every subscriber, by channel and socket id
+    channels: Mutex<HashMap<String, HashMap<String, mpsc::Sender<String>>>>,
+    /// Open connections; also what `closed` waits on
+    connections: watch::Sender<usize>,
+    closing: watch::Sender<bool>,
+}
+
+impl BroadcastHub {
+    pub fn new(config: BroadcastConfig, socket_bridge: Arc<SocketBridge>) -> Arc<Self> {
+        metrics().describe("websocket_connections", MetricKind::Gauge, "Open WebSocket connections");
+        metrics().describe(
+            "websocket_events_total",
+            MetricKind::Counter,
+            "Broadcast events per subscriber, by outcome (delivered, dropped when the send queue was full)",
+        );
+        metrics().describe(
+            "websocket_auth_total",
+            MetricKind::Counter,
+            "Private channel authorizations by outcome (allowed, denied, error)",
+        );
+        metrics().set_gauge("websocket_connections", &[], 0.0);
+
+        Arc::new(Self {
+            config,
+            socket_bridge,
+            channels: Mutex::new(HashMap::new()),
+            connections: watch::channel(0).0,
+            closing: watch::channel(false).0,
+        })
+    }
+
+    pub fn config(&self) -> &BroadcastConfig {
+        &self.config
+    }
+
+    pub fn connections(&self) -> usize {
+        *self.connections.borrow()
+    }
+
+    /// Answer a WebSocket upgrade request and serve the connection in the background
+    ///
+    /// Anything but a valid upgrade gets a 400; above `WS_MAX_CONNECTIONS`
+    /// the request fails with a 503.
+    pub fn accept(self: &Arc<Self>, mut req: Request<Body>, client_ip: IpAddr) -> Result<Response<Body>> {
+        let Some(key) = handshake_key(&req) else {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header(header::CONTENT_TYPE, "text/plain")
+                .body(Body::from("expected a WebSocket upgrade"))?);
+        };
+        if *self.closing.borrow() {
+            return Err(ServerError::Unavailable {
+                reason: UnavailableReason::Maintenance,
+                retry_after: None,
+                message: "shutting down".to_string(),
+            }
+            .into());
+        }
+
+        let admitted = self.connections.send_if_modified(|count| {
+            let admitted = *count < self.config.max_connections;
+            *count += usize::from(admitted);
+            admitted
+        });
+        if !admitted {
+            return Err(ServerError::Unavailable {
+                reason: UnavailableReason::Overloaded,
+                retry_after: None,
+                message: format!("WS_MAX_CONNECTIONS ({}) reached", self.config.max_connections),
+            }
+            .into());
+        }
+        metrics().set_gauge("websocket_connections", &[], self.connections() as f64);
+
+        let auth_headers: HashMap<String, String> = AUTH_HEADERS
+            .iter()
+            .filter_map(|name| Some((name.to_string(), req.headers().get(name)?.to_str().ok()?.to_string())))
+            .collect();
+        let on_upgrade = hyper::upgrade::on(&mut req);
+        let hub = self.clone();
+        let socket_id = format!("{}.{}", std::process::id(), SOCKET_ID.fetch_add(1, Ordering::Relaxed));
+        let span = info_span!("websocket", socket_id = %socket_id, client_ip = %client_ip);
+        tokio::spawn(
+            async move {
+                match on_upgrade.await {
+                    Ok(upgraded) => {
+                        let config = WebSocketConfig {
+                            max_message_size: Some(MAX_CLIENT_MESSAGE_SIZE),
+                            max_frame_size: Some(MAX_CLIENT_MESSAGE_SIZE),
+                            ..Default::default()
+                        };
+                        let ws = WebSocketStream::from_raw_socket(upgraded, Role::Server, Some(config)).await;
+                        hub.serve(ws, &socket_id, auth_headers).await;
+                    }
+                    Err(e) => debug!(error = %e, "WebSocket upgrade failed"),
+                }
+                hub.connections.send_modify(|count| *count -= 1);
+                metrics().set_gauge("websocket_connections", &[], hub.connections() as f64);
+            }
+            .instrument(span),
+        );
+
+        Ok(Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(header::CONNECTION, "Upgrade")
+            .header(header::UPGRADE, "websocket")
+            .header(header::SEC_WEBSOCKET_ACCEPT, derive_accept_key(key.as_bytes()))
+            .body(Body::empty())?)
+    }
+
+    /// Exchange messages with one client until either side closes
+    async fn serve(&self, mut ws: WebSocketStream<Upgraded>, socket_id: &str, auth_headers: HashMap<String, String>) {
+        debug!("WebSocket connected");
+        let (sender, mut queue) = mpsc::channel(self.config.queue_size);
+        let mut subscriptions = HashSet::new();
+        let mut closing = self.closing.subscribe();
+        let mut ping = tokio::time::interval_at(
+            tokio::time::Instant::now() + self.config.ping_interval,
+            self.config.ping_interval,
+        );
+        let mut awaiting_pong = false;
+
+        let connected = json!({ "type": "connected", "socket_id": socket_id }).to_string();
+        if ws.send(Message::Text(connected)).await.is_ok() {
+            loop {
+                let outgoing = tokio::select! {
+                    incoming = ws.next() => match incoming {
+                        Some(Ok(Message::Text(text))) => {
+                            awaiting_pong = false;
+                            self.handle_client_message(&text, socket_id, &sender, &mut subscriptions, &auth_headers).await
+                        }
+                        Some(Ok(Message::Close(_))) | None => break,
+                        Some(Ok(_)) => {
+                            // Pings are answered by tungstenite; any frame shows the client is alive
+                            awaiting_pong = false;
+                            continue;
+                        }
+                        Some(Err(e)) => {
+                            debug!(error = %e, "WebSocket read failed");
+                            break;
+                        }
+                    },
+                    Some(event) = queue.recv() => Message::Text(event),
+                    _ = ping.tick() => {
+                        if awaiting_pong {
+                            debug!("No pong within WS_PING_INTERVAL_MS, closing");
+                            break;
+                        }
+                        awaiting_pong = true;
+                        Message::Ping(Vec::new())
+                    }
+                    _ = shutting_down(&mut closing) => {
+                        let frame = CloseFrame {
+                            code: CloseCode::Away,
+                            reason: "server shutting down".into(),
+                        };
+                        let _ = ws.close(Some(frame)).await;
+                        break;
+                    }
+                };
+                // A client that stops reading must not hold up shutdown or its task forever
+                let sent = tokio::select! {
+                    sent = tokio::time::timeout(self.config.ping_interval, ws.send(outgoing)) => matches!(sent, Ok(Ok(()))),
+                    _ = shutting_down(&mut closing) => false,
+                };
+                if !sent {
+                    break;
+                }
+            }
+        }
+
+        let mut channels = self.lock();
+        for channel in subscriptions {
+            if let Some(subscribers) = channels.get_mut(&channel) {
+                subscribers.remove(socket_id);
+                if subscribers.is_empty() {
+                    channels.remove(&channel);
+                }
+            }
+        }
+        debug!("WebSocket disconnected");
+    }
+
+    /// Apply a subscribe or unsubscribe message and build the reply
+    async fn handle_client_message(
+        &self,
+        text: &str,
+        socket_id: &str,
+        sender: &mpsc::Sender<String>,
+        subscriptions: &mut HashSet<String>,
+        auth_headers: &HashMap<String, String>,
+    ) -> Message {
+        let reply = match serde_json::from_str::<ClientMessage>(text) {
+            Err(e) => json!({ "type": "error", "message": format!("invalid message: {}", e) }),
+            Ok(ClientMessage::Subscribe { channel }) => {
+                if subscriptions.contains(&channel) {
+                    json!({ "type": "subscribed", "channel": channel })
+                } else if subscriptions.len() >= self.config.max_subscriptions {
+                    json!({ "type": "error", "channel": channel, "message": "too many subscriptions" })
+                } else {
+                    match self.authorize(&channel, socket_id, auth_headers).await {
+                        Ok(()) => {
+                            self.lock()
+                                .entry(channel.clone())
+                                .or_default()
+                                .insert(socket_id.to_string(), sender.clone());
+                            subscriptions.insert(channel.clone());
+                            json!({ "type": "subscribed", "channel": channel })
+                        }
+                        Err(message) => json!({ "type": "error", "channel": channel, "message": message }),
+                    }
+                }
+            }
+            Ok(ClientMessage::Unsubscribe { channel }) => {
+                if subscriptions.remove(&channel) {
+                    let mut channels = self.lock();
+                    if let Some(subscribers) = channels.get_mut(&channel) {
+                        subscribers.remove(socket_id);
+                        if subscribers.is_empty() {
+                            channels.remove(&channel);
+                        }
+                    }
+                }
+                json!({ "type": "unsubscribed", "channel": channel })
+            }
+        };
+        Message::Text(reply.to_string())
+    }
+
+    /// Ask Laravel whether the client may join a private or presence channel
+    async fn authorize(
+        &self,
+        channel: &str,
+        socket_id: &str,
+        auth_headers: &HashMap<String, String>,
+    ) -> Result<(), String> {
+        if !PRIVATE_PREFIXES.iter().any(|prefix| channel.starts_with(prefix)) {
+            return Ok(());
+        }
+
+        let data = HashMap::from([
+            ("channel_name".to_string(), json!(channel)),
+            ("socket_id".to_string(), json!(socket_id)),
+            ("headers".to_string(), json!(auth_headers)),
+        ]);
+        let (outcome, result) = match self.socket_bridge.send_command("broadcast.auth", Some(data)).await {
+            Ok(response) if response.success => ("allowed", Ok(())),
+            Ok(response) => {
+                let reason = response.error.unwrap_or_else(|| "not authorized".to_string());
+                ("denied", Err(format!("subscription denied: {}", reason)))
+            }
+            Err(e) => {
+                warn!(channel, "Channel authorization failed: {:#}", e);
+                ("error", Err("authorization is unavailable".to_string()))
+            }
+        };
+        metrics().inc_counter("websocket_auth_total", &[("outcome", outcome)]);
+        debug!(channel, outcome, "Channel authorization");
+        result
+    }
+
+    /// Send an event to every subscriber of its channel
+    ///
+    /// Returns the number of subscribers it was queued for.
+    fn publish(&self, message: PushMessage) -> usize {
+        let event = json!({
+            "type": "event",
+            "channel": message.channel,
+            "event": message.event,
+            "payload": message.payload,
+        })
+        .to_string();
+
+        let channels = self.lock();
+        let Some(subscribers) = channels.get(&message.channel) else {
+            return 0;
+        };
+        let (mut delivered, mut dropped) = (0, 0);
+        for (socket_id, sender) in subscribers {
+            if message.socket_id.as_deref() == Some(socket_id.as_str()) {
+                continue;
+            }
+            match sender.try_send(event.clone()) {
+                Ok(()) => delivered += 1,
+                Err(mpsc::error::TrySendError::Full(_)) => dropped += 1,
+                Err(mpsc::error::TrySendError::Closed(_)) => {}
+            }
+        }
+        metrics().add_counter("websocket_events_total", &[("outcome", "delivered")], delivered as u64);
+        if dropped > 0 {
+            metrics().add_counter("websocket_events_total", &[("outcome", "dropped")], dropped as u64);
+            debug!(channel = %message.channel, dropped, "Send queues full, event dropped for some subscribers");
+        }
+        delivered
+    }
+
+    /// Listen on `WS_PUSH_SOCKET` for events from Laravel
+    ///
+    /// A stale socket file from a previous run is replaced.
+    pub fn spawn_push_listener(self: &Arc<Self>) -> Result<JoinHandle<()>> {
+        let path = &self.config.push_socket;
+        if std::fs::symlink_metadata(path).is_ok() {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        info!("📣 Accepting broadcast events on {}", path);
+
+        let hub = self.clone();
+        Ok(tokio::spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        warn!(error = %e, "Failed to accept a push connection");
+                        continue;
+                    }
+                };
+                let hub = hub.clone();
+                tokio::spawn(async move {
+                    let mut lines = tokio::io::BufReader::new(stream).lines();
+                    loop {
+                        match lines.next_line().await {
+                            Ok(Some(line)) if line.trim().is_empty() => {}
+                            Ok(Some(line)) => match serde_json::from_str::<PushMessage>(&line) {
+                                Ok(message) => {
+                                    let channel = message.channel.clone();
+                                    let subscribers = hub.publish(message);
+                                    debug!(channel = %channel, subscribers, "Broadcast event pushed");
+                                }
+                                Err(e) => warn!(error = %e, "Ignoring malformed push message"),
+                            },
+                            Ok(None) => break,
+                            Err(e) => {
+                                debug!(error = %e, "Push connection failed");
+                                break;
+                            }
+                        }
+                    }
+                });
+            }
+        }))
+    }
+
+    /// Close every connection with a going-away frame
+    pub fn close_all(&self) {
+        self.closing.send_replace(true);
+    }
+
+    /// Resolves once every connection has ended
+    pub async fn closed(&self) {
+        let _ = self.connections.subscribe().wait_for(|count| *count == 0).await;
+    }
+
+    /// Remove the push socket file
+    pub fn cleanup(&self) {
+        let _ = std::fs::remove_file(&self.config.push_socket);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, HashMap<String, mpsc::Sender<String>>>> {
+        self.channels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Resolves once the hub starts closing connections
+async fn shutting_down(closing: &mut watch::Receiver<bool>) {
+    let _ = closing.wait_for(|closing| *closing).await;
+}
+
+/// `Sec-WebSocket-Key` of a valid version 13 upgrade request
+fn handshake_key(req: &Request<Body>) -> Option<String> {
+    let headers = req.headers();
+    let has_token = |name: header::HeaderName, token: &str| {
+        headers.get_all(name).iter().any(|value| {
+            value
+                .to_str()
+                .is_ok_and(|value| value.split(',').any(|part| part.trim().eq_ignore_ascii_case(token)))
+        })
+    };
+    if req.method() != hyper::Method::GET
+        || !has_token(header::CONNECTION, "upgrade")
+        || !has_token(header::UPGRADE, "websocket")
+        || headers.get(header::SEC_WEBSOCKET_VERSION).and_then(|v| v.to_str().ok()) != Some("13")
+    {
+        return None;
+    }
+    headers.get(header::SEC_WEBSOCKET_KEY)?.to_str().ok().map(str::to_string)
+}
@@ -0,0 +1,236 @@
+//! Resource limits for the PHP worker process
+//!
+//! Applies niceness and rlimits to the child between fork and exec, and
+//! optionally places it into a cgroup v2 with `cpu.max` / `memory.max`,
+//! so a runaway PHP request cannot starve the Rust front-end.
+
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use serde_json::json;
+use tracing::{info, warn};
+
+/// Limits that were verified on the most recently spawned worker
+static APPLIED_LIMITS: Lazy<Mutex<Option<serde_json::Value>>> = Lazy::new(|| Mutex::new(None));
+
+/// What to do when a configured limit cannot be applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitFailurePolicy {
+    /// Log a warning and keep the worker running without the limit
+    Warn,
+    /// Refuse to start the worker
+    Fail,
+}
+
+/// Resource limits for the PHP worker
+#[derive(Debug, Clone)]
+pub struct WorkerLimits {
+    pub nice: Option<i32>,
+    pub rlimit_as: Option<u64>,
+    pub rlimit_nofile: Option<u64>,
+    pub cgroup_path: Option<PathBuf>,
+    pub cpu_max: Option<String>,
+    pub memory_max: Option<String>,
+    pub on_failure: LimitFailurePolicy,
+}
+
+impl WorkerLimits {
+    pub fn from_env() -> Self {
+        Self {
+            nice: parse_env("PHP_WORKER_NICE", |v| v.parse().ok()),
+            rlimit_as: parse_env("PHP_WORKER_RLIMIT_AS", parse_size),
+            rlimit_nofile: parse_env("PHP_WORKER_RLIMIT_NOFILE", |v| v.parse().ok()),
+            cgroup_path: std::env::var("PHP_WORKER_CGROUP")
+                .ok()
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from),
+            cpu_max: std::env::var("PHP_WORKER_CPU_MAX").ok().filter(|v| !v.is_empty()),
+            memory_max: std::env::var("PHP_WORKER_MEMORY_MAX").ok().filter(|v| !v.is_empty()),
+            on_failure: match std::env::var("PHP_WORKER_LIMITS_ON_FAILURE").as_deref() {
+                Ok("fail") => LimitFailurePolicy::Fail,
+                _ => LimitFailurePolicy::Warn,
+            },
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.nice.is_none() && self.rlimit_as.is_none() && self.rlimit_nofile.is_none() && self.cgroup_path.is_none()
+    }
+
+    /// Install a pre-exec hook applying niceness and rlimits in the child
+    pub fn apply_to_command(&self, cmd: &mut Command) {
+        if self.nice.is_none() && self.rlimit_as.is_none() && self.rlimit_nofile.is_none() {
+            return;
+        }
+
+        let nice = self.nice;
+        let rlimit_as = self.rlimit_as;
+        let rlimit_nofile = self.rlimit_nofile;
+        let fail = self.on_failure == LimitFailurePolicy::Fail;
+
+        // SAFETY: the closure only performs async-signal-safe syscalls
+        // (setpriority/setrlimit) and does not allocate.
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(nice) = nice {
+                    if libc::setpriority(libc::PRIO_PROCESS as _, 0, nice) != 0 && fail {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                if let Some(limit) = rlimit_as {
+                    set_rlimit(libc::RLIMIT_AS, limit, fail)?;
+                }
+                if let Some(limit) = rlimit_nofile {
+                    set_rlimit(libc::RLIMIT_NOFILE, limit, fail)?;
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// Move the spawned worker into its cgroup and verify the applied limits
+    ///
+    /// # Returns
+    ///
+    /// * `Err` - only when a limit could not be applied and the policy is `fail`
+    pub fn after_spawn(&self, child: &Child) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let pid = child.id();
+        let mut problems = Vec::new();
+
+        if let Some(cgroup) = &self.cgroup_path {
+            if let Err(e) = self.attach_cgroup(pid) {
+                problems.push(format!("cgroup {}: {}", cgroup.display(), e));
+            }
+        }
+
+        let applied = read_applied_limits(pid);
+        if let Some(nice) = self.nice {
+            if applied.get("nice").and_then(|v| v.as_i64()) != Some(nice as i64) {
+                problems.push(format!("nice {} was not applied", nice));
+            }
+        }
+        if let Some(limit) = self.rlimit_as {
+            if applied.get("rlimit_as").and_then(|v| v.as_u64()) != Some(limit) {
+                problems.push(format!("RLIMIT_AS {} was not applied", limit));
+            }
+        }
+        if let Some(limit) = self.rlimit_nofile {
+            if applied.get("rlimit_nofile").and_then(|v| v.as_u64()) != Some(limit) {
+                problems.push(format!("RLIMIT_NOFILE {} was not applied", limit));
+            }
+        }
+
+        info!(pid, limits = %applied, "PHP worker resource limits applied");
+        *APPLIED_LIMITS.lock().unwrap_or_else(|e| e.into_inner()) = Some(applied);
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+
+        match self.on_failure {
+            LimitFailurePolicy::Warn => {
+                for problem in &problems {
+                    warn!(pid, "Failed to apply PHP worker limit: {}", problem);
+                }
+                Ok(())
+            }
+            LimitFailurePolicy::Fail => Err(anyhow!("Failed to apply PHP worker limits: {}", problems.join("; "))),
+        }
+    }
+
+    fn attach_cgroup(&self, pid: u32) -> Result<()> {
+        let Some(cgroup) = &self.cgroup_path else {
+            return Ok(());
+        };
+
+        std::fs::create_dir_all(cgroup)?;
+        if let Some(cpu_max) = &self.cpu_max {
+            std::fs::write(cgroup.join("cpu.max"), cpu_max)?;
+        }
+        if let Some(memory_max) = &self.memory_max {
+            std::fs::write(cgroup.join("memory.max"), memory_max)?;
+        }
+        std::fs::write(cgroup.join("cgroup.procs"), pid.to_string())?;
+        Ok(())
+    }
+}
+
+/// Limits verified on the most recently spawned worker, for stats
+pub fn applied_limits() -> Option<serde_json::Value> {
+    APPLIED_LIMITS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+fn set_rlimit(resource: libc::__rlimit_resource_t, limit: u64, fail: bool) -> std::io::Result<()> {
+    let rlim = libc::rlimit {
+        rlim_cur: limit as libc::rlim_t,
+        rlim_max: limit as libc::rlim_t,
+    };
+    // SAFETY: plain syscall with a valid pointer to a stack value
+    if unsafe { libc::setrlimit(resource, &rlim) } != 0 && fail {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Read the effective niceness, rlimits and cgroup of a running process
+fn read_applied_limits(pid: u32) -> serde_json::Value {
+    // SAFETY: getpriority has no memory-safety preconditions
+    let nice = unsafe {
+        *libc::__errno_location() = 0;
+        let value = libc::getpriority(libc::PRIO_PROCESS as _, pid as libc::id_t);
+        (*libc::__errno_location() == 0).then_some(value)
+    };
+
+    let limits = std::fs::read_to_string(format!("/proc/{}/limits", pid)).unwrap_or_default();
+    let soft_limit = |name: &str| -> Option<u64> {
+        limits
+            .lines()
+            .find(|line| line.starts_with(name))
+            .and_then(|line| line[name.len()..].split_whitespace().next())
+            .and_then(|v| v.parse().ok())
+    };
+
+    let cgroup = std::fs::read_to_string(format!("/proc/{}/cgroup", pid))
+        .ok()
+        .and_then(|c| c.lines().find_map(|l| l.strip_prefix("0::").map(str::to_string)));
+
+    json!({
+        "nice": nice,
+        "rlimit_as": soft_limit("Max address space"),
+        "rlimit_nofile": soft_limit("Max open files"),
+        "cgroup": cgroup,
+    })
+}
+
+fn parse_env<T>(name: &str, parse: impl Fn(&str) -> Option<T>) -> Option<T> {
+    let value = std::env::var(name).ok().filter(|v| !v.is_empty())?;
+    let parsed = parse(value.trim());
+    if parsed.is_none() {
+        warn!("Ignoring invalid {}={}", name, value);
+    }
+    parsed
+}
+
+/// Parse a byte size with an optional K/M/G suffix (binary units); `KB`,
+/// `MB` and `GB` are accepted as well
+pub(crate) fn parse_size(value: &str) -> Option<u64> {
+    let value = match value.strip_suffix(['B', 'b']) {
+        Some(unit) if unit.ends_with(|c: char| matches!(c.to_ascii_uppercase(), 'K' | 'M' | 'G')) => unit,
+        _ => value,
+    };
+    let (digits, multiplier) = match value.chars().last()?.to_ascii_uppercase() {
+        'K' => (&value[..value.len() - 1], 1024),
+        'M' => (&value[..value.len() - 1], 1024 * 1024),
+        'G' => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+    digits.trim().parse::<u64>().ok().map(|n| n * multiplier)
+}
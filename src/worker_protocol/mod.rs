@@ -0,0 +1,102 @@
+//! Shapes of the request and response frames a PHP worker understands
+//!
+//! The server builds every HTTP request frame in its own `laravel-rust`
+//! shape ([`request_frame`](crate::server::request_frame)) and adds to it on
+//! the way to the worker: `HTTPS`, the deadline, trace context, the retry
+//! marker. Right before the frame is written, the bridge's [`WorkerCodec`]
+//! puts it in the shape its worker expects, and the `data` of the worker's
+//! answer goes through the same codec to become the HTTP response.
+//! `WORKER_PROTOCOL` picks the codec:
+//!
+//! * `laravel-rust` ([`NativeCodec`]) - this server's format, passed through
+//! * `octane` ([`OctaneCodec`]) - the Swoole request and response shape
+//!   Laravel Octane's workers are written against
+//! * `psr7` ([`Psr7Codec`]) - RoadRunner's PSR-7 payloads in goridge
+//!   frames, for `spiral/roadrunner-http` workers
+//!
+//! Command frames (`ping`, `health`, ...) are not HTTP requests and are
+//! sent unchanged whatever the protocol; in goridge framing only `ping`
+//! and `goodbye` have an equivalent, RoadRunner's control frames.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use hyper::{Body, Response};
+use serde_json::Value;
+
+use crate::bridge::connection_pool::Framing;
+use crate::response_validation::ResponseValidation;
+
+mod native;
+mod octane;
+mod psr7;
+
+pub use native::NativeCodec;
+pub use octane::OctaneCodec;
+pub use psr7::Psr7Codec;
+
+/// Maps HTTP request frames to a worker's wire shape and its answers back
+pub trait WorkerCodec: Send + Sync + 'static {
+    /// Value of `WORKER_PROTOCOL` that selects this codec
+    fn name(&self) -> &'static str;
+
+    /// The worker's request frame for a frame in the `laravel-rust` shape
+    fn encode_request(&self, frame: Value) -> Value;
+
+    /// The HTTP response for the `data` of the worker's successful answer
+    ///
+    /// A response that cannot be served fails with
+    /// [`ServerError::UpstreamMalformed`](crate::errors::ServerError::UpstreamMalformed).
+    fn decode_response(&self, data: Value, validation: ResponseValidation) -> Result<Response<Body>>;
+
+    /// How the worker's frames are laid out on the socket
+    fn framing(&self) -> Framing {
+        Framing::LengthPrefixed
+    }
+
+    /// Directory of files written for the encoded `request`, removed once the worker has answered
+    fn scratch_dir(&self, _request: &Value) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Protocol spoken with the PHP worker, from `WORKER_PROTOCOL`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WorkerProtocol {
+    #[default]
+    LaravelRust,
+    Octane,
+    Psr7,
+}
+
+impl WorkerProtocol {
+    /// Accepted values of `WORKER_PROTOCOL`
+    pub const NAMES: &'static [&'static str] = &["laravel-rust", "octane", "psr7"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "laravel-rust" => Some(Self::LaravelRust),
+            "octane" => Some(Self::Octane),
+            "psr7" => Some(Self::Psr7),
+            _ => None,
+        }
+    }
+
+    /// Protocol from `WORKER_PROTOCOL`; other values are refused by config validation
+    pub fn from_env() -> Self {
+        std::env::var("WORKER_PROTOCOL").ok().and_then(|name| Self::parse(&name)).unwrap_or_default()
+    }
+
+    pub fn codec(self) -> &'static dyn WorkerCodec {
+        match self {
+            Self::LaravelRust => &NativeCodec,
+            Self::Octane => &OctaneCodec,
+            Self::Psr7 => &Psr7Codec,
+        }
+    }
+}
+
+/// Whether `frame` is a command frame rather than an HTTP request frame
+pub(crate) fn is_command(frame: &Value) -> bool {
+    frame.get("command").is_some_and(Value::is_string)
+}
@@ -0,0 +1,26 @@
+//! The `laravel-rust` protocol: frames as the server builds them
+
+use anyhow::Result;
+use hyper::{Body, Response};
+use serde_json::Value;
+
+use super::WorkerCodec;
+use crate::response_validation::ResponseValidation;
+
+/// This server's own protocol, which the `laravel-rust:serve` worker speaks
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeCodec;
+
+impl WorkerCodec for NativeCodec {
+    fn name(&self) -> &'static str {
+        "laravel-rust"
+    }
+
+    fn encode_request(&self, frame: Value) -> Value {
+        frame
+    }
+
+    fn decode_response(&self, data: Value, validation: ResponseValidation) -> Result<Response<Body>> {
+        crate::server::worker_response(data, validation)
+    }
+}
@@ -0,0 +1,207 @@
+//! The `octane` protocol: Swoole's request and response shape
+//!
+//! Octane's Swoole client builds the Laravel request from a Swoole
+//! request and writes the Laravel response back to a Swoole response, so
+//! a worker written against Octane expects the request in that shape:
+//!
+//! ```json
+//! {
+//!   "server": {"request_method": "POST", "request_uri": "/login?next=/", "path_info": "/login",
+//!              "query_string": "next=/", "server_protocol": "HTTP/1.1", "content_type": "...", ...},
+//!   "header": {"host": "example.com", "cookie": "theme=dark", ...},
+//!   "get": {"next": "/"},
+//!   "post": {"email": "jane@example.com"},
+//!   "cookie": {"theme": "dark"},
+//!   "files": {},
+//!   "content": "email=jane%40example.com"
+//! }
+//! ```
+//!
+//! Server variable names are lower case, as Swoole has them, including the
+//! ones the server adds (`https`, `x_request_deadline_ms`, ...). `post` is
+//! only filled for form bodies; multipart bodies are left to the worker in
+//! `content`, so `files` is always empty. Binary bodies are base64-encoded
+//! with `"content_encoding": "base64"`, as in the native protocol.
+//!
+//! The worker answers with the response as Octane hands it to Swoole:
+//!
+//! ```json
+//! {"status": 200, "headers": {"Set-Cookie": ["a=1", "b=2"]}, "content": "..."}
+//! ```
+//!
+//! Every value of a header is sent, so several cookies survive. A streamed
+//! response carries `"chunks": [...]` instead of `content`, and is sent to
+//! the client chunk by chunk without a `Content-Length`. `content` and
+//! chunks may be base64-encoded with `content_encoding`.
+
+use anyhow::Result;
+use hyper::body::Bytes;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Body, Response, StatusCode};
+use serde_json::{json, Map, Value};
+use tracing::warn;
+
+use super::WorkerCodec;
+use crate::errors::ServerError;
+use crate::response_validation::ResponseValidation;
+
+/// Laravel Octane's Swoole request and response shape
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OctaneCodec;
+
+impl WorkerCodec for OctaneCodec {
+    fn name(&self) -> &'static str {
+        "octane"
+    }
+
+    fn encode_request(&self, mut frame: Value) -> Value {
+        let uri = frame["uri"].as_str().unwrap_or("/").to_string();
+        let (path, query) = uri.split_once('?').unwrap_or((&uri, ""));
+
+        let mut server: Map<String, Value> = take_object(&mut frame["server"])
+            .into_iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value))
+            .collect();
+        server.insert("path_info".to_string(), path.into());
+        server.insert("query_string".to_string(), query.into());
+        server.insert("server_protocol".to_string(), "HTTP/1.1".into());
+
+        let header: Map<String, Value> = take_object(&mut frame["headers"])
+            .into_iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value))
+            .collect();
+
+        let mut get = Map::new();
+        crate::form_params::merge_into(&mut get, query.as_bytes());
+        let mut post = Map::new();
+        let content_type = header.get("content-type").and_then(Value::as_str).unwrap_or_default();
+        if crate::form_params::is_form(content_type) && frame.get("content_encoding").is_none() {
+            if let Some(body) = frame["content"].as_str() {
+                crate::form_params::merge_into(&mut post, body.as_bytes());
+            }
+        }
+        let cookie = header.get("cookie").and_then(Value::as_str).map(cookies).unwrap_or_default();
+
+        let mut request = json!({
+            "server": server,
+            "header": header,
+            "get": get,
+            "post": post,
+            "cookie": cookie,
+            "files": {},
+            "content": frame["content"].take(),
+        });
+        if let Some(encoding) = frame.get_mut("content_encoding") {
+            request["content_encoding"] = encoding.take();
+        }
+        request
+    }
+
+    fn decode_response(&self, data: Value, validation: ResponseValidation) -> Result<Response<Body>> {
+        let Value::Object(mut data) = data else {
+            return Err(malformed(format!("response is {} rather than an object", kind(&data))));
+        };
+
+        let status = match data.get("status") {
+            Some(status) => status.as_u64().ok_or_else(|| malformed(format!("status {} is not a number", status)))?,
+            None if validation == ResponseValidation::Strict => return Err(malformed("response has no status".to_string())),
+            None => 200,
+        };
+        let status = u16::try_from(status)
+            .ok()
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .ok_or_else(|| malformed(format!("invalid status code {}", status)))?;
+        let mut response = Response::builder().status(status);
+
+        match data.remove("headers") {
+            None | Some(Value::Null) => {}
+            Some(Value::Object(headers)) => {
+                for (name, values) in headers {
+                    let values = match values {
+                        Value::Array(values) => values,
+                        value => vec![value],
+                    };
+                    for value in values {
+                        let Some(value) = value.as_str() else {
+                            if validation == ResponseValidation::Strict {
+                                return Err(malformed(format!("header {} has a value that is not a string", name)));
+                            }
+                            warn!(header = %name, "Skipping Octane response header value that is not a string");
+                            continue;
+                        };
+                        match (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value.trim())) {
+                            (Ok(name), Ok(value)) => response = response.header(name, value),
+                            _ => warn!(header = %name, "Skipping invalid Octane response header"),
+                        }
+                    }
+                }
+            }
+            Some(headers) => return Err(malformed(format!("headers are {} rather than an object", kind(&headers)))),
+        }
+
+        let encoding = data.get("content_encoding").and_then(Value::as_str).map(str::to_string);
+        let body = match data.remove("chunks") {
+            Some(Value::Array(chunks)) => {
+                let chunks = chunks
+                    .into_iter()
+                    .map(|chunk| content(chunk, encoding.as_deref(), validation))
+                    .collect::<Result<Vec<_>>>()?;
+                Body::wrap_stream(futures::stream::iter(chunks.into_iter().map(Ok::<_, std::io::Error>)))
+            }
+            Some(chunks) => return Err(malformed(format!("chunks are {} rather than an array", kind(&chunks)))),
+            None => Body::from(content(data.remove("content").unwrap_or(Value::Null), encoding.as_deref(), validation)?),
+        };
+        response.body(body).map_err(|e| malformed(format!("response cannot be sent: {}", e)))
+    }
+}
+
+/// The object at `value`, left `null`; empty when it is not an object
+fn take_object(value: &mut Value) -> Map<String, Value> {
+    match value.take() {
+        Value::Object(object) => object,
+        _ => Map::new(),
+    }
+}
+
+/// A `Cookie` header as Swoole parses it: `name=value` pairs, URL-decoded
+fn cookies(header: &str) -> Map<String, Value> {
+    header
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .filter_map(|(name, value)| Some((name.to_string(), crate::form_params::decode(value.as_bytes())?.into())))
+        .collect()
+}
+
+/// Bytes of a `content` value or chunk
+fn content(value: Value, encoding: Option<&str>, validation: ResponseValidation) -> Result<Bytes> {
+    let text = match value {
+        Value::Null => return Ok(Bytes::new()),
+        Value::String(text) => text,
+        other if validation == ResponseValidation::Strict => {
+            return Err(malformed(format!("content is {} rather than a string", kind(&other))))
+        }
+        other => other.to_string(),
+    };
+    match encoding {
+        None => Ok(Bytes::from(text)),
+        Some("base64") => base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &text)
+            .map(Bytes::from)
+            .map_err(|e| malformed(format!("content is not valid base64: {}", e))),
+        Some(other) => Err(malformed(format!("unknown content_encoding {:?}", other))),
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+fn malformed(message: String) -> anyhow::Error {
+    ServerError::UpstreamMalformed(format!("Octane response: {}", message)).into()
+}
@@ -0,0 +1,341 @@
+//! The `psr7` protocol: RoadRunner's PSR-7 worker payloads
+//!
+//! Workers built on `spiral/roadrunner-http` read each request as a
+//! goridge frame (see [`goridge`](crate::bridge::goridge)) whose JSON
+//! context describes it the way RoadRunner's HTTP plugin does, with the
+//! body after it:
+//!
+//! ```json
+//! {
+//!   "context": {
+//!     "remoteAddr": "10.0.0.7", "protocol": "HTTP/1.1", "method": "POST",
+//!     "uri": "https://example.com/login?next=%2Fhome",
+//!     "header": {"Content-Type": ["application/x-www-form-urlencoded"], ...},
+//!     "cookies": {"theme": "dark"}, "rawQuery": "next=%2Fhome",
+//!     "parsed": true, "uploads": null, "attributes": {"REQUEST_ID": "...", ...}
+//!   },
+//!   "body": "{\"email\":\"jane@example.com\"}"
+//! }
+//! ```
+//!
+//! Header names are in canonical form and every value is a list. Form and
+//! multipart bodies arrive parsed, as RoadRunner sends them: `parsed` is
+//! true and the body is the JSON of the fields. Each uploaded file of a
+//! multipart body is written to a temporary directory and described in
+//! `uploads` (`name`, `mime`, `size`, `error`, `tmpName`), under its field
+//! name; the directory is removed once the worker has answered. The server
+//! variables (`REQUEST_ID`, `HTTPS`, the deadline, ...) become request
+//! attributes.
+//!
+//! The worker answers with the status and headers in the context and the
+//! body after it:
+//!
+//! ```json
+//! {"context": {"status": 201, "headers": {"Set-Cookie": ["a=1", "b=2"]}}, "body": "created"}
+//! ```
+//!
+//! Every value of a header is sent. A response the worker streams in
+//! several frames is joined before it is sent on. Binary bodies are
+//! base64-encoded with `"body_encoding": "base64"` on both ways.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use hyper::body::Bytes;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Body, Response, StatusCode};
+use serde_json::{json, Map, Value};
+use tracing::{debug, warn};
+
+use super::WorkerCodec;
+use crate::bridge::connection_pool::Framing;
+use crate::errors::ServerError;
+use crate::response_validation::ResponseValidation;
+
+/// RoadRunner's PSR-7 request and response payloads, in goridge frames
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Psr7Codec;
+
+impl WorkerCodec for Psr7Codec {
+    fn name(&self) -> &'static str {
+        "psr7"
+    }
+
+    fn encode_request(&self, mut frame: Value) -> Value {
+        let target = frame["uri"].as_str().unwrap_or("/").to_string();
+        let query = target.split_once('?').map_or("", |(_, query)| query);
+        let server = match frame["server"].take() {
+            Value::Object(server) => server,
+            _ => Map::new(),
+        };
+        let headers = match frame["headers"].take() {
+            Value::Object(headers) => headers,
+            _ => Map::new(),
+        };
+        let header_value = |name: &str| headers.get(name).and_then(Value::as_str).unwrap_or_default().to_string();
+
+        let scheme = match server.get("HTTPS").and_then(Value::as_str) {
+            Some("on") => "https",
+            _ => "http",
+        };
+        let host = header_value("host");
+        let uri = match host.is_empty() {
+            true => target.clone(),
+            false => format!("{}://{}{}", scheme, host, target),
+        };
+        let cookies = header_value("cookie")
+            .split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .filter_map(|(name, value)| Some((name.to_string(), crate::form_params::decode(value.as_bytes())?.into())))
+            .collect::<Map<String, Value>>();
+
+        let mut body = body_bytes(&frame);
+        let content_type = header_value("content-type");
+        let mut parsed = false;
+        let mut uploads = Value::Null;
+        let mut upload_dir = None;
+        if crate::form_params::is_form(&content_type) {
+            let mut fields = Map::new();
+            crate::form_params::merge_into(&mut fields, &body);
+            body = Value::Object(fields).to_string().into_bytes();
+            parsed = true;
+        } else if let Some(boundary) = multipart_boundary(&content_type) {
+            let mut form = MultipartForm::default();
+            match parse_multipart(&body, &boundary, &mut form) {
+                Ok(()) => {
+                    body = Value::Object(form.fields).to_string().into_bytes();
+                    parsed = true;
+                    if !form.uploads.is_empty() {
+                        uploads = Value::Object(form.uploads);
+                    }
+                    upload_dir = form.dir;
+                }
+                Err(e) => {
+                    debug!(error = %e, "Multipart body not parsed, sending it as is");
+                    if let Some(dir) = form.dir {
+                        let _ = std::fs::remove_dir_all(dir);
+                    }
+                }
+            }
+        }
+
+        let header: Map<String, Value> = headers
+            .iter()
+            .map(|(name, value)| (canonical_header(name), json!([value])))
+            .collect();
+        let mut request = json!({
+            "context": {
+                "remoteAddr": server.get("REMOTE_ADDR").cloned().unwrap_or_else(|| "".into()),
+                "protocol": "HTTP/1.1",
+                "method": frame["method"].take(),
+                "uri": uri,
+                "header": header,
+                "cookies": cookies,
+                "rawQuery": query,
+                "parsed": parsed,
+                "uploads": uploads,
+                "attributes": server,
+            },
+        });
+        match String::from_utf8(body) {
+            Ok(text) => request["body"] = text.into(),
+            Err(e) => {
+                request["body"] = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, e.as_bytes()).into();
+                request["body_encoding"] = "base64".into();
+            }
+        }
+        // Not part of the frame: only the context and body are written
+        if let Some(dir) = upload_dir {
+            request["upload_dir"] = dir.to_string_lossy().into_owned().into();
+        }
+        request
+    }
+
+    fn decode_response(&self, data: Value, validation: ResponseValidation) -> Result<Response<Body>> {
+        let Value::Object(mut data) = data else {
+            return Err(malformed("response is not an object".to_string()));
+        };
+        let context = match data.remove("context") {
+            Some(Value::Object(context)) => context,
+            Some(Value::Null) | None if validation == ResponseValidation::Lenient => Map::new(),
+            _ => return Err(malformed("response has no context object".to_string())),
+        };
+
+        let status = match context.get("status") {
+            Some(status) => status.as_u64().ok_or_else(|| malformed(format!("status {} is not a number", status)))?,
+            None if validation == ResponseValidation::Strict => return Err(malformed("response has no status".to_string())),
+            None => 200,
+        };
+        let status = u16::try_from(status)
+            .ok()
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .ok_or_else(|| malformed(format!("invalid status code {}", status)))?;
+        let mut response = Response::builder().status(status);
+
+        match context.get("headers") {
+            None | Some(Value::Null) => {}
+            Some(Value::Object(headers)) => {
+                for (name, values) in headers {
+                    let values = match values {
+                        Value::Array(values) => values.as_slice(),
+                        value => std::slice::from_ref(value),
+                    };
+                    for value in values {
+                        let Some(value) = value.as_str() else {
+                            if validation == ResponseValidation::Strict {
+                                return Err(malformed(format!("header {} has a value that is not a string", name)));
+                            }
+                            warn!(header = %name, "Skipping PSR-7 response header value that is not a string");
+                            continue;
+                        };
+                        match (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value.trim())) {
+                            (Ok(name), Ok(value)) => response = response.header(name, value),
+                            _ => warn!(header = %name, "Skipping invalid PSR-7 response header"),
+                        }
+                    }
+                }
+            }
+            Some(_) => return Err(malformed("headers are not an object".to_string())),
+        }
+
+        let body = match (data.remove("body"), data.get("body_encoding").and_then(Value::as_str)) {
+            (None | Some(Value::Null), _) => Bytes::new(),
+            (Some(Value::String(text)), None) => Bytes::from(text),
+            (Some(Value::String(text)), Some("base64")) => {
+                base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &text)
+                    .map(Bytes::from)
+                    .map_err(|e| malformed(format!("body is not valid base64: {}", e)))?
+            }
+            (Some(Value::String(_)), Some(other)) => return Err(malformed(format!("unknown body_encoding {:?}", other))),
+            (Some(_), _) => return Err(malformed("body is not a string".to_string())),
+        };
+        response.body(Body::from(body)).map_err(|e| malformed(format!("response cannot be sent: {}", e)))
+    }
+
+    fn framing(&self) -> Framing {
+        Framing::Goridge
+    }
+
+    fn scratch_dir(&self, request: &Value) -> Option<PathBuf> {
+        request["upload_dir"].as_str().map(PathBuf::from)
+    }
+}
+
+/// `Content-Type` as Go canonicalizes header names: `Content-Type`, `X-Request-Id`
+fn canonical_header(name: &str) -> String {
+    name.split('-')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + &chars.as_str().to_ascii_lowercase(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Bytes of the native frame's `content`
+fn body_bytes(frame: &Value) -> Vec<u8> {
+    let content = frame["content"].as_str().unwrap_or_default();
+    match frame["content_encoding"].as_str() {
+        Some("base64") => base64::Engine::decode(&base64::engine::general_purpose::STANDARD, content).unwrap_or_default(),
+        _ => content.as_bytes().to_vec(),
+    }
+}
+
+/// The `boundary` of a `multipart/form-data` content type
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    if !params.next()?.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .filter_map(|param| param.trim().split_once('='))
+        .find(|(name, _)| name.eq_ignore_ascii_case("boundary"))
+        .map(|(_, boundary)| boundary.trim_matches('"').to_string())
+        .filter(|boundary| !boundary.is_empty())
+}
+
+/// Fields and uploaded files of a multipart body
+#[derive(Default)]
+struct MultipartForm {
+    fields: Map<String, Value>,
+    uploads: Map<String, Value>,
+    /// Directory holding the uploaded files, if there are any
+    dir: Option<PathBuf>,
+}
+
+/// Split a multipart body into the fields of `form`, writing uploaded files to a new temporary directory
+///
+/// The directory is in `form` as soon as it is created, also when parsing fails later.
+fn parse_multipart(body: &[u8], boundary: &str, form: &mut MultipartForm) -> Result<()> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut rest = match find(body, &delimiter) {
+        Some(at) => &body[at + delimiter.len()..],
+        None => anyhow::bail!("body has no {} boundary", boundary),
+    };
+    while !rest.starts_with(b"--") {
+        let rest_of_line = rest.strip_prefix(b"\r\n").ok_or_else(|| anyhow::anyhow!("boundary line is not terminated"))?;
+        let end = find(rest_of_line, &[b"\r\n", delimiter.as_slice()].concat())
+            .ok_or_else(|| anyhow::anyhow!("part is not closed by a boundary"))?;
+        let part = &rest_of_line[..end];
+        rest = &rest_of_line[end + 2 + delimiter.len()..];
+
+        let split = find(part, b"\r\n\r\n").ok_or_else(|| anyhow::anyhow!("part has no header end"))?;
+        let (headers, content) = (String::from_utf8_lossy(&part[..split]), &part[split + 4..]);
+        let mut name = None;
+        let mut filename = None;
+        let mut mime = "application/octet-stream".to_string();
+        for line in headers.lines() {
+            let Some((header, value)) = line.split_once(':') else { continue };
+            if header.trim().eq_ignore_ascii_case("content-type") {
+                mime = value.trim().to_string();
+            } else if header.trim().eq_ignore_ascii_case("content-disposition") {
+                for param in value.split(';').skip(1) {
+                    match param.trim().split_once('=') {
+                        Some(("name", value)) => name = Some(value.trim_matches('"').to_string()),
+                        Some(("filename", value)) => filename = Some(value.trim_matches('"').to_string()),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        let Some(name) = name else { continue };
+
+        let Some(filename) = filename else {
+            crate::form_params::insert(&mut form.fields, &name, String::from_utf8_lossy(content).into_owned().into());
+            continue;
+        };
+        let upload = match filename.is_empty() {
+            // No file chosen: UPLOAD_ERR_NO_FILE
+            true => json!({"name": "", "mime": "", "size": 0, "error": 4, "tmpName": ""}),
+            false => {
+                let dir = match &form.dir {
+                    Some(dir) => dir.clone(),
+                    None => form.dir.insert(tempfile::Builder::new().prefix("laravel-rust-uploads").tempdir()?.keep()).clone(),
+                };
+                let file = tempfile::Builder::new().prefix("upload").tempfile_in(&dir)?;
+                std::fs::write(file.path(), content)?;
+                let (_, path) = file.keep()?;
+                json!({
+                    "name": filename,
+                    "mime": mime,
+                    "size": content.len(),
+                    "error": 0,
+                    "tmpName": path.to_string_lossy(),
+                })
+            }
+        };
+        crate::form_params::insert(&mut form.uploads, &name, upload);
+    }
+    Ok(())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn malformed(message: String) -> anyhow::Error {
+    ServerError::UpstreamMalformed(format!("PSR-7 response: {}", message)).into()
+}
@@ -0,0 +1,114 @@
+//! The adaptive concurrency limit against a worker that degrades under load
+//!
+//! The `MockWorker` answers after `SERVICE_TIME` on each of the `SLOTS`
+//! pooled connections, one frame per connection at a time, so past `SLOTS`
+//! requests in flight the latency the bridge sees grows with concurrency.
+//! `CLIENTS` clients send requests back to back. The limit must settle in a
+//! band around the worker's capacity rather than climb towards `CLIENTS`,
+//! and every request above it must be answered with `503 overloaded` and
+//! counted in `bridge_requests_shed_total` and `http_unavailable_responses_total`.
+//! Needs the `test-worker` feature:
+//! `cargo test --features test-worker --test adaptive_concurrency`.
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::{free_port, Server};
+use laravel_rust_server::mock_worker::{MockWorker, Reply, Rule, Script};
+use serde_json::Value;
+
+const SLOTS: usize = 8;
+const SERVICE_TIME: Duration = Duration::from_millis(20);
+const CLIENTS: usize = 64;
+const WARM_UP: Duration = Duration::from_secs(2);
+const MEASURED: Duration = Duration::from_secs(3);
+
+#[derive(Default)]
+struct Totals {
+    served: AtomicUsize,
+    shed: AtomicUsize,
+    other: AtomicUsize,
+}
+
+async fn client(http: reqwest::Client, url: String, totals: Arc<Totals>, until: Instant) {
+    while Instant::now() < until {
+        let response = http.get(&url).send().await.unwrap();
+        match response.status().as_u16() {
+            200 => totals.served.fetch_add(1, Ordering::SeqCst),
+            503 => {
+                let body: Value = response.json().await.unwrap();
+                assert_eq!(body["reason"], "overloaded", "{}", body);
+                // A shed request is answered at once; pause as a client honouring it would
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                totals.shed.fetch_add(1, Ordering::SeqCst)
+            }
+            _ => totals.other.fetch_add(1, Ordering::SeqCst),
+        };
+    }
+}
+
+/// Value of the series `name`, labels included, in the admin listener's `/metrics`
+async fn metric(admin_url: &str, name: &str) -> f64 {
+    let metrics = reqwest::get(admin_url).await.unwrap().text().await.unwrap();
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("no {} in:\n{}", name, metrics))
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn the_limit_follows_the_worker_and_sheds_the_excess() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = Script {
+        rules: vec![Rule::any(Reply::respond(200, "ok")).delayed(SERVICE_TIME)],
+        ..Script::default()
+    };
+    let _worker = MockWorker::start(dir.path().join("worker.sock"), script).unwrap();
+    let admin_port = free_port().to_string();
+    let slots = SLOTS.to_string();
+    let settings = [
+        ("ADAPTIVE_CONCURRENCY", "true"),
+        ("ADAPTIVE_CONCURRENCY_INITIAL", "4"),
+        ("ADAPTIVE_CONCURRENCY_MAX", "256"),
+        ("ADAPTIVE_CONCURRENCY_WINDOW_MS", "100"),
+        ("SOCKET_POOL_MIN", slots.as_str()),
+        ("SOCKET_POOL_MAX", slots.as_str()),
+        ("ADMIN_ENABLED", "true"),
+        ("ADMIN_PORT", admin_port.as_str()),
+    ];
+    let server = Server::start(dir.path(), &settings).await;
+    let admin_url = format!("http://127.0.0.1:{}/metrics", admin_port);
+
+    let http = reqwest::Client::new();
+    let totals = Arc::new(Totals::default());
+    let until = Instant::now() + WARM_UP + MEASURED;
+    let clients: Vec<_> = (0..CLIENTS)
+        .map(|i| tokio::spawn(client(http.clone(), format!("{}/page/{}", server.url, i), totals.clone(), until)))
+        .collect();
+
+    tokio::time::sleep(WARM_UP).await;
+    let mut limits = Vec::new();
+    while Instant::now() + Duration::from_millis(250) < until {
+        limits.push(metric(&admin_url, "bridge_concurrency_limit").await);
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+    for client in clients {
+        client.await.unwrap();
+    }
+
+    // Around the worker's capacity: not starved, and far from the clients' concurrency
+    let band = (SLOTS / 2) as f64..=(SLOTS * 4) as f64;
+    assert!(limits.iter().all(|limit| band.contains(limit)), "limit left {:?}: {:?}", band, limits);
+
+    let served = totals.served.load(Ordering::SeqCst);
+    let shed = totals.shed.load(Ordering::SeqCst);
+    assert_eq!(totals.other.load(Ordering::SeqCst), 0, "requests neither served nor shed");
+    assert!(served > 0 && shed > 0, "served {}, shed {}", served, shed);
+    assert_eq!(metric(&admin_url, "bridge_requests_shed_total").await, shed as f64);
+    assert_eq!(metric(&admin_url, r#"http_unavailable_responses_total{reason="overloaded"}"#).await, shed as f64);
+}
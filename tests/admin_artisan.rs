@@ -0,0 +1,74 @@
+//! Arguments of artisan commands run through the admin listener
+//!
+//! Uses `echo` in place of PHP, so the answer's `stdout` is the command
+//! line artisan would have been given. Checks that `--no-interaction`
+//! comes before the caller's arguments, that options are only passed when
+//! listed for the command in `ADMIN_ARTISAN_COMMANDS`, and that a bare
+//! `--` is refused.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use hyper::StatusCode;
+use laravel_rust_server::admin_artisan::{ArtisanConfig, ArtisanRunner};
+use serde_json::{json, Value};
+
+fn runner() -> ArtisanRunner {
+    ArtisanRunner::new(ArtisanConfig {
+        allowed: vec!["cache:clear".to_string(), "migrate".to_string()],
+        options: HashMap::from([
+            ("cache:clear".to_string(), Vec::new()),
+            ("migrate".to_string(), vec!["--force".to_string(), "--step".to_string()]),
+        ]),
+        timeout: Duration::from_secs(5),
+        max_output_bytes: 4096,
+        php_path: "echo".to_string(),
+        laravel_path: ".".to_string(),
+    })
+}
+
+async fn run(command: &str, args: Value) -> (StatusCode, Value) {
+    let body = json!({ "command": command, "args": args }).to_string();
+    runner().handle(body.as_bytes(), "127.0.0.1", true).await
+}
+
+#[tokio::test]
+async fn no_interaction_comes_before_the_arguments() {
+    let (status, body) = run("cache:clear", json!(["files"])).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["stdout"], "artisan cache:clear --no-interaction files\n");
+}
+
+#[tokio::test]
+async fn listed_options_are_passed() {
+    let (status, body) = run("migrate", json!(["--force", "--step=2"])).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["stdout"], "artisan migrate --no-interaction --force --step=2\n");
+}
+
+#[tokio::test]
+async fn unlisted_options_are_refused() {
+    for (command, args) in [
+        ("cache:clear", json!(["--env=production"])),
+        ("migrate", json!(["--database=other"])),
+        ("migrate", json!(["-v"])),
+        ("migrate", json!(["--forced"])),
+    ] {
+        let (status, body) = run(command, args.clone()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{} {}", command, args);
+        assert!(body.get("stdout").is_none(), "{} {} was run", command, args);
+    }
+}
+
+#[tokio::test]
+async fn a_bare_double_dash_is_refused() {
+    let (status, body) = run("migrate", json!(["--", "--force"])).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "\"--\" is not accepted in args");
+}
+
+#[tokio::test]
+async fn commands_off_the_list_are_refused() {
+    let (status, _) = run("db:wipe", json!([])).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
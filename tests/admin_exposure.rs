@@ -0,0 +1,67 @@
+//! Refusing an admin listener that anyone could use
+//!
+//! An admin listener on a host other than loopback must have credentials
+//! or `ADMIN_ALLOW_IPS`, otherwise it is refused with an error naming the
+//! settings that would fix it. `ADMIN_ALLOW_INSECURE` lets it start anyway.
+
+use laravel_rust_server::admin::AdminConfig;
+use laravel_rust_server::admin_auth::AdminAuthConfig;
+
+fn config(host: &str, auth: AdminAuthConfig) -> AdminConfig {
+    AdminConfig {
+        enabled: true,
+        host: host.to_string(),
+        port: 9090,
+        log_level_revert_after: None,
+        auth,
+        allow_insecure: false,
+    }
+}
+
+#[test]
+fn refuses_public_host_without_auth_or_allow_list() {
+    for host in ["0.0.0.0", "10.1.2.3", "::"] {
+        let error = config(host, AdminAuthConfig::default()).check_exposure().unwrap_err().to_string();
+        assert!(error.contains(host), "{}", error);
+        for env in ["ADMIN_TOKEN", "ADMIN_ALLOW_IPS", "ADMIN_ALLOW_INSECURE"] {
+            assert!(error.contains(env), "{}", error);
+        }
+    }
+}
+
+#[test]
+fn accepts_loopback_host_without_auth() {
+    for host in ["127.0.0.1", "::1", "localhost"] {
+        assert!(config(host, AdminAuthConfig::default()).check_exposure().is_ok(), "{}", host);
+    }
+}
+
+#[test]
+fn accepts_public_host_with_credentials_or_allow_list() {
+    let token = AdminAuthConfig {
+        token: Some("0123456789abcdef".to_string()),
+        ..AdminAuthConfig::default()
+    };
+    assert!(config("0.0.0.0", token).check_exposure().is_ok());
+
+    let basic = AdminAuthConfig {
+        basic: Some(("ops".to_string(), "secret".to_string())),
+        ..AdminAuthConfig::default()
+    };
+    assert!(config("0.0.0.0", basic).check_exposure().is_ok());
+
+    let allow_ips = AdminAuthConfig {
+        allow_ips: Some(vec!["10.0.0.0/8".parse().unwrap()]),
+        ..AdminAuthConfig::default()
+    };
+    assert!(config("0.0.0.0", allow_ips).check_exposure().is_ok());
+}
+
+#[test]
+fn insecure_opt_out_starts_public_host_without_auth() {
+    let config = AdminConfig {
+        allow_insecure: true,
+        ..config("0.0.0.0", AdminAuthConfig::default())
+    };
+    assert!(config.check_exposure().is_ok());
+}
@@ -0,0 +1,100 @@
+//! Cache-Control rules for static files
+//!
+//! Patterns without wildcards must match as path prefixes, and globs the
+//! whole path, with `*` staying within a segment, `**` crossing segments
+//! and `?` standing for one character. With overlapping rules the specific
+//! ones listed above the general ones must win for their paths while the
+//! general ones still catch the rest, a general rule listed first must
+//! shadow them, and paths matching no rule must fall back to the manifest
+//! value or the default.
+
+use laravel_rust_server::static_cache::{CachePolicy, PathPattern};
+
+#[test]
+fn prefixes_and_globs_match_as_documented() {
+    let prefix = PathPattern::new("/build/");
+    assert_eq!(prefix, PathPattern::Prefix("/build/".to_string()));
+    assert!(prefix.matches("/build/app.js"));
+    assert!(prefix.matches("/build/assets/deep/app.js"));
+    assert!(!prefix.matches("/builds/app.js"));
+
+    let one_segment = PathPattern::new("/img/*.png");
+    assert!(matches!(one_segment, PathPattern::Glob(_)));
+    assert!(one_segment.matches("/img/logo.png"));
+    assert!(!one_segment.matches("/img/icons/logo.png"));
+    assert!(!one_segment.matches("/img/logo.png.bak"));
+
+    let any_depth = PathPattern::new("/fonts/**.woff2");
+    assert!(any_depth.matches("/fonts/inter.woff2"));
+    assert!(any_depth.matches("/fonts/inter/latin/400.woff2"));
+    assert!(!any_depth.matches("/fonts/inter.woff"));
+
+    let one_char = PathPattern::new("/v?/app.js");
+    assert!(one_char.matches("/v1/app.js"));
+    assert!(!one_char.matches("/v10/app.js"));
+    assert!(!one_char.matches("/v//app.js"));
+
+    let anywhere = PathPattern::new("**.map");
+    assert!(anywhere.matches("/build/assets/app.js.map"));
+    assert!(PathPattern::new("/привет/*").matches("/привет/мир.txt"));
+}
+
+#[test]
+fn rules_that_do_not_parse_are_reported() {
+    let rules = r#"[
+        {"pattern": "build/", "cache_control": "no-cache"},
+        {"pattern": "/a/", "cache_control": "bad\nvalue"}
+    ]"#;
+    let problems = CachePolicy::parse_rules(rules).unwrap_err();
+    assert_eq!(problems.len(), 2, "{:?}", problems);
+    assert!(problems[0].contains("must start with / or *"), "{:?}", problems);
+    assert!(problems[1].contains("invalid cache_control"), "{:?}", problems);
+    assert!(CachePolicy::parse_rules(r#"{"pattern": "/"}"#).is_err());
+    assert!(CachePolicy::parse_rules(r#"[{"pattern": "/", "cache_control": "x", "ttl": 5}]"#).is_err());
+}
+
+/// The only test that sets the `STATIC_CACHE_*` variables of this process
+#[test]
+fn overlapping_rules_resolve_top_down() {
+    std::env::set_var("STATIC_CACHE_DEFAULT", "public, max-age=60");
+    std::env::set_var("STATIC_CACHE_IMMUTABLE", "public, max-age=31536000, immutable");
+    let policy = |rules: &str| {
+        std::env::set_var("STATIC_CACHE_RULES", rules);
+        CachePolicy::from_env().unwrap()
+    };
+    let cache_control = |policy: &CachePolicy, path: &str, versioned: bool| {
+        policy.cache_control(path, versioned).to_str().unwrap().to_string()
+    };
+
+    // Most specific first: each path gets its closest rule
+    let specific_first = policy(
+        r#"[
+            {"pattern": "/assets/img/logo.svg", "cache_control": "public, max-age=86400"},
+            {"pattern": "/assets/img/**.png", "cache_control": "no-cache"},
+            {"pattern": "/assets/img/", "cache_control": "public, max-age=300"},
+            {"pattern": "/assets/", "cache_control": "public, max-age=3600", "immutable": true}
+        ]"#,
+    );
+    assert_eq!(cache_control(&specific_first, "/assets/img/logo.svg", false), "public, max-age=86400");
+    assert_eq!(cache_control(&specific_first, "/assets/img/icons/star.png", false), "no-cache");
+    assert_eq!(cache_control(&specific_first, "/assets/img/photo.jpg", false), "public, max-age=300");
+    assert_eq!(cache_control(&specific_first, "/assets/app.css", false), "public, max-age=3600, immutable");
+
+    // A rule beats the manifest; paths under no rule fall back to it or the default
+    assert_eq!(cache_control(&specific_first, "/assets/img/photo.jpg", true), "public, max-age=300");
+    assert_eq!(cache_control(&specific_first, "/build/app-1a2b.js", true), "public, max-age=31536000, immutable");
+    assert_eq!(cache_control(&specific_first, "/favicon.ico", false), "public, max-age=60");
+
+    // Listed first, a general rule shadows the specific ones below it
+    let general_first = policy(
+        r#"[
+            {"pattern": "/assets/", "cache_control": "public, max-age=3600"},
+            {"pattern": "/assets/img/", "cache_control": "no-cache"}
+        ]"#,
+    );
+    assert_eq!(cache_control(&general_first, "/assets/img/photo.jpg", false), "public, max-age=3600");
+
+    std::env::remove_var("STATIC_CACHE_RULES");
+    std::env::remove_var("STATIC_CACHE_DEFAULT");
+    std::env::remove_var("STATIC_CACHE_IMMUTABLE");
+}
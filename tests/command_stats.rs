@@ -0,0 +1,142 @@
+//! Lifetime counters of the commands sent through one C handle
+//!
+//! Sixteen threads send commands through the same handle to a mock worker
+//! that answers `ping` and never answers `hang`, while another thread reads
+//! `laravel_rust_get_stats`. In flight must never exceed the number of
+//! threads and must be back to 0 at the end; the total, the timeouts and
+//! the bytes sent and received must add up to what the threads counted.
+//! Needs the `test-worker` feature:
+//! `cargo test --features test-worker --test command_stats`.
+
+mod common;
+
+use std::ffi::{CStr, CString};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use common::free_port;
+use laravel_rust_server::laravel_integration::{
+    laravel_rust_destroy, laravel_rust_free_string, laravel_rust_get_stats, laravel_rust_init,
+    laravel_rust_send_command, laravel_rust_set_option, laravel_rust_start, LaravelRustServer, LaravelRustStatus,
+};
+use laravel_rust_server::mock_worker::{MockWorker, Reply, Rule, Script};
+use serde_json::{json, Value};
+
+const THREADS: usize = 16;
+const COMMANDS: usize = 20;
+
+/// The handle, shared by the threads as the C API allows
+#[derive(Clone, Copy)]
+struct Handle(*mut LaravelRustServer);
+
+unsafe impl Send for Handle {}
+
+impl Handle {
+    fn set(self, key: &str, value: &str) {
+        let (key, value) = (CString::new(key).unwrap(), CString::new(value).unwrap());
+        assert_eq!(unsafe { laravel_rust_set_option(self.0, key.as_ptr(), value.as_ptr()) }, LaravelRustStatus::Ok);
+    }
+
+    /// Status and, on success, the length of the response
+    fn send(self, command: &str) -> (LaravelRustStatus, usize) {
+        let command = CString::new(command).unwrap();
+        let mut response = ptr::null_mut();
+        let status = unsafe { laravel_rust_send_command(self.0, command.as_ptr(), ptr::null(), &mut response) };
+        if response.is_null() {
+            return (status, 0);
+        }
+        let len = unsafe { CStr::from_ptr(response) }.to_bytes().len();
+        unsafe { laravel_rust_free_string(response) };
+        (status, len)
+    }
+
+    fn commands(self) -> Value {
+        let mut out = ptr::null_mut();
+        assert_eq!(unsafe { laravel_rust_get_stats(self.0, &mut out) }, LaravelRustStatus::Ok);
+        let stats: Value = serde_json::from_slice(unsafe { CStr::from_ptr(out) }.to_bytes()).unwrap();
+        unsafe { laravel_rust_free_string(out) };
+        stats["commands"].clone()
+    }
+}
+
+#[test]
+fn counters_add_up_under_concurrent_commands() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let socket_path = dir.path().join("worker.sock");
+    let script = Script {
+        rules: vec![Rule {
+            command: Some("hang".to_string()),
+            ..Rule::any(Reply::Hang)
+        }],
+        ..Script::default()
+    };
+    let _worker = runtime.block_on(async { MockWorker::start(&socket_path, script) }).unwrap();
+
+    let mut server = ptr::null_mut();
+    assert_eq!(unsafe { laravel_rust_init(&mut server) }, LaravelRustStatus::Ok);
+    let handle = Handle(server);
+    let port = free_port();
+    handle.set("host", "127.0.0.1");
+    handle.set("port", &port.to_string());
+    handle.set("socket_path", socket_path.to_str().unwrap());
+    handle.set("read_timeout_ms", "200");
+    assert_eq!(unsafe { laravel_rust_start(server, ptr::null()) }, LaravelRustStatus::Ok);
+    let before = handle.commands();
+    assert_eq!(before["total"], 0);
+
+    let done = Arc::new(AtomicBool::new(false));
+    let watcher = {
+        let done = done.clone();
+        std::thread::spawn(move || {
+            let mut most = 0;
+            while !done.load(Ordering::Relaxed) {
+                most = most.max(handle.commands()["in_flight"].as_u64().unwrap());
+            }
+            most
+        })
+    };
+    let senders: Vec<_> = (0..THREADS)
+        .map(|_| {
+            std::thread::spawn(move || {
+                // (sent, received, timeouts)
+                let mut counted = (0, 0, 0);
+                for i in 0..COMMANDS {
+                    let command = if i % 4 == 0 { "hang" } else { "ping" };
+                    let (status, received) = handle.send(command);
+                    match status {
+                        LaravelRustStatus::Ok => counted.1 += received,
+                        LaravelRustStatus::Timeout => counted.2 += 1,
+                        other => panic!("{} answered {:?}", command, other),
+                    }
+                    counted.0 += command.len();
+                }
+                counted
+            })
+        })
+        .collect();
+    let (sent, received, timeouts) = senders
+        .into_iter()
+        .map(|sender| sender.join().unwrap())
+        .fold((0, 0, 0), |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2));
+    done.store(true, Ordering::Relaxed);
+    let most_in_flight = watcher.join().unwrap();
+
+    let commands = handle.commands();
+    assert_eq!(commands["total"], THREADS * COMMANDS);
+    assert_eq!(commands["in_flight"], 0);
+    assert!(most_in_flight as usize <= THREADS, "{} in flight from {} threads", most_in_flight, THREADS);
+    assert!(most_in_flight > 1, "the commands never overlapped");
+    // Every `hang` times out; a `ping` stuck behind them may too
+    assert!(timeouts >= THREADS * COMMANDS / 4, "only {} timeouts", timeouts);
+    assert_eq!(
+        commands["failed"],
+        json!({"bridge_unavailable": 0, "timeout": timeouts, "invalid_argument": 0, "internal": 0})
+    );
+    assert_eq!(commands["bytes_sent"], sent);
+    assert_eq!(commands["bytes_received"], received);
+    assert!(commands["uptime_secs"].as_f64().unwrap() > 0.0);
+
+    unsafe { laravel_rust_destroy(server) };
+}
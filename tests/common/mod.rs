@@ -0,0 +1,149 @@
+//! Harness shared by the integration tests
+//!
+//! Picks free ports, builds the `laravel-rust-server` command with the
+//! settings every run shares, and keeps a spawned server that is killed
+//! when dropped. Each test binary uses only part of it.
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+/// The server binary built for the tests
+pub const BINARY: &str = env!("CARGO_BIN_EXE_laravel-rust-server");
+
+/// A port nobody listens on; the server binds it again right after
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// `Command` for the binary in `dir`, listening on `port`
+///
+/// The worker socket is `worker.sock` in `dir`, logs go to `dir/logs`, and
+/// no PHP worker is restarted. Callers add or override settings with `env`.
+pub fn command(dir: &Path, port: u16) -> Command {
+    let mut command = Command::new(BINARY);
+    command
+        .current_dir(dir)
+        .env("HTTP_HOST", "127.0.0.1")
+        .env("HTTP_PORT", port.to_string())
+        .env("SOCKET_PATH", dir.join("worker.sock"))
+        .env("LARAVEL_PATH", dir)
+        .env("LOG_DIR", dir.join("logs"))
+        .env("PHP_WORKER_AUTO_RESTART", "false")
+        .env("SOCKET_WAIT_INTERVAL_MS", "50")
+        .stdin(Stdio::null());
+    command
+}
+
+/// The server binary, stopped when dropped
+pub struct Server {
+    pub child: Child,
+    pub port: u16,
+    /// `http://127.0.0.1:<port>`
+    pub url: String,
+    /// Where its standard error goes
+    pub log: PathBuf,
+}
+
+impl Server {
+    /// Spawn `command` for `port`, with standard error in `log`
+    ///
+    /// Standard output is discarded unless `command` already sends it
+    /// somewhere; `Command` has no getter for that, so callers keeping it
+    /// use [`Server::spawn_with_stdout`].
+    pub fn spawn(mut command: Command, port: u16, log: PathBuf) -> Self {
+        command.stdout(Stdio::null());
+        Self::spawn_with_stdout(command, port, log)
+    }
+
+    /// [`Server::spawn`] keeping the standard output `command` has
+    pub fn spawn_with_stdout(mut command: Command, port: u16, log: PathBuf) -> Self {
+        let child = command.stderr(std::fs::File::create(&log).unwrap()).spawn().unwrap();
+        Self {
+            child,
+            port,
+            url: format!("http://127.0.0.1:{}", port),
+            log,
+        }
+    }
+
+    /// Spawn the binary in `dir` with `settings` and wait until it is ready
+    pub async fn start(dir: &Path, settings: &[(&str, &str)]) -> Self {
+        let port = free_port();
+        let mut command = command(dir, port);
+        command.envs(settings.iter().copied());
+        let server = Self::spawn(command, port, dir.join("server.log"));
+        server.wait_ready().await;
+        server
+    }
+
+    /// What the server wrote to standard error so far
+    pub fn output(&self) -> String {
+        std::fs::read_to_string(&self.log).unwrap_or_default()
+    }
+
+    /// `GET path` on the server
+    pub async fn get(&self, path: &str) -> reqwest::Response {
+        reqwest::get(format!("{}{}", self.url, path)).await.unwrap()
+    }
+
+    /// Status of `GET path`, or None while nothing answers
+    pub async fn status(&self, path: &str) -> Option<u16> {
+        let response = reqwest::get(format!("{}{}", self.url, path)).await.ok()?;
+        Some(response.status().as_u16())
+    }
+
+    /// Wait up to 10 seconds for `/readyz` to answer 200
+    pub async fn wait_ready(&self) {
+        self.wait_readyz(200, Duration::from_secs(10)).await;
+    }
+
+    /// Wait up to `within` for `/readyz` to answer `status`
+    pub async fn wait_readyz(&self, status: u16, within: Duration) {
+        let until = Instant::now() + within;
+        while self.status("/readyz").await != Some(status) {
+            assert!(Instant::now() < until, "/readyz never answered {}:\n{}", status, self.output());
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// Wait up to 10 seconds for the server to exit on its own
+    pub async fn wait_exit(&mut self) -> ExitStatus {
+        let until = Instant::now() + Duration::from_secs(10);
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status;
+            }
+            assert!(Instant::now() < until, "still running:\n{}", self.output());
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// [`Server::wait_ready`] for tests without a runtime
+    pub fn wait_ready_blocking(&self) {
+        let until = Instant::now() + Duration::from_secs(10);
+        loop {
+            if let Ok(mut stream) = TcpStream::connect(("127.0.0.1", self.port)) {
+                stream.set_read_timeout(Some(Duration::from_secs(3))).unwrap();
+                stream.write_all(b"GET /readyz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+                let mut response = Vec::new();
+                let _ = stream.read_to_end(&mut response);
+                if response.starts_with(b"HTTP/1.1 200") {
+                    return;
+                }
+            }
+            assert!(Instant::now() < until, "server not ready:\n{}", self.output());
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
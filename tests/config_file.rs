@@ -0,0 +1,149 @@
+//! Settings layered from `CONFIG_PATH` under `.env`, the environment and flags
+//!
+//! The same settings written as TOML and as YAML must load alike, with
+//! unknown keys listed rather than rejected. Through the binary's `config`
+//! subcommand, a value from the file must give way to `.env`, `.env` to
+//! the environment and the environment to a flag, a setting nobody sets
+//! must keep its default, unknown keys must be warned about with the file
+//! they are in, and validation must see the merged values.
+//! Needs the `laravel-rust-server` binary, which cargo builds for the test.
+
+mod common;
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use laravel_rust_server::config_loader::ConfigFile;
+use serde_json::Value;
+
+const TOML: &str = r#"
+[server]
+host = "10.0.0.1"
+port = 9001
+bogus = true
+
+[connection]
+socket_path = "/run/file.sock"
+pool_max = 7
+
+[nonsense]
+key = 1
+"#;
+
+const YAML: &str = r#"
+server:
+  host: 10.0.0.1
+  port: 9001
+  bogus: true
+connection:
+  socket_path: /run/file.sock
+  pool_max: 7
+nonsense:
+  key: 1
+"#;
+
+/// Run the binary in `dir` with only `env` set
+fn run(dir: &Path, env: &[(&str, &str)], args: &[&str]) -> Output {
+    Command::new(common::BINARY)
+        .current_dir(dir)
+        .env_clear()
+        .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+        .envs(env.iter().copied())
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// `config show --format json`, with the stderr it logged
+fn show(dir: &Path, env: &[(&str, &str)], flags: &[&str]) -> (Value, String) {
+    let args: Vec<&str> = flags.iter().copied().chain(["config", "show", "--format", "json"]).collect();
+    let output = run(dir, env, &args);
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(output.status.success(), "{}", stderr);
+    (serde_json::from_slice(&output.stdout).unwrap(), stderr)
+}
+
+/// Value and source of a setting in `config show` output
+fn setting(shown: &Value, section: &str, name: &str) -> (String, String) {
+    let entry = &shown[section][name];
+    (entry["value"].as_str().unwrap().to_string(), entry["source"].as_str().unwrap().to_string())
+}
+
+#[test]
+fn toml_and_yaml_load_the_same_settings() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut loaded = Vec::new();
+    for (name, contents) in [("config.toml", TOML), ("config.yaml", YAML), ("config.yml", YAML)] {
+        let path = dir.path().join(name);
+        std::fs::write(&path, contents).unwrap();
+        loaded.push(ConfigFile::load(&path).unwrap());
+    }
+
+    let toml = &loaded[0];
+    let values: Vec<_> = toml.values.iter().map(|(key, value)| (*key, value.as_str())).collect();
+    assert_eq!(
+        values,
+        [
+            ("connection.pool_max", "7"),
+            ("connection.socket_path", "/run/file.sock"),
+            ("server.host", "10.0.0.1"),
+            ("server.port", "9001"),
+        ]
+    );
+    assert_eq!(toml.unknown_keys, ["nonsense.key", "server.bogus"]);
+    for yaml in &loaded[1..] {
+        assert_eq!(yaml.values, toml.values, "{}", yaml.path.display());
+        assert_eq!(yaml.unknown_keys, toml.unknown_keys, "{}", yaml.path.display());
+    }
+
+    let broken = dir.path().join("broken.toml");
+    std::fs::write(&broken, "[server\nport = 1").unwrap();
+    let error = ConfigFile::load(&broken).unwrap_err().to_string();
+    assert!(error.starts_with(&format!("Invalid TOML in {}", broken.display())), "{}", error);
+}
+
+#[test]
+fn each_layer_overrides_the_ones_below() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("config.toml"), TOML).unwrap();
+    std::fs::write(dir.path().join(".env"), "HTTP_HOST=10.0.0.2\nSOCKET_PATH=/run/dotenv.sock\n").unwrap();
+
+    // Without a file, the environment alone decides as before
+    let (shown, _) = show(dir.path(), &[], &[]);
+    assert_eq!(setting(&shown, "server", "port"), ("8080".to_string(), "default".to_string()));
+    assert_eq!(setting(&shown, "server", "host"), ("10.0.0.2".to_string(), ".env".to_string()));
+
+    let (shown, stderr) = show(
+        dir.path(),
+        &[("CONFIG_PATH", "config.toml"), ("HTTP_HOST", "10.0.0.3")],
+        &["--port", "9004"],
+    );
+    let expected = [
+        ("server", "port", "9004", "cli"),
+        ("server", "host", "10.0.0.3", "env"),
+        ("connection", "socket_path", "/run/dotenv.sock", ".env"),
+        ("connection", "pool_max", "7", "file"),
+        ("connection", "pool_min", "2", "default"),
+    ];
+    for (section, name, value, source) in expected {
+        assert_eq!(setting(&shown, section, name), (value.to_string(), source.to_string()), "{}.{}", section, name);
+    }
+    for key in ["server.bogus", "nonsense.key"] {
+        assert!(stderr.contains(&format!("Unknown config key `{}` in config.toml", key)), "{}", stderr);
+    }
+}
+
+#[test]
+fn validation_sees_the_merged_values() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("config.toml"), "[connection]\npool_min = 4\npool_max = 2\n").unwrap();
+
+    let output = run(dir.path(), &[], &["--config", "config.toml", "config", "validate"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("SOCKET_POOL_MIN (config key `connection.pool_min`)"), "{}", stderr);
+
+    // The environment raises the file's pool_max above its pool_min
+    let output = run(dir.path(), &[("SOCKET_POOL_MAX", "8")], &["--config", "config.toml", "config", "validate"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
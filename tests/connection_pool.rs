@@ -0,0 +1,237 @@
+//! The worker connection pool and its framing, over in-memory connections
+//!
+//! Every test runs the pool on a `DuplexTransport` against a worker in the
+//! same process, so nothing touches the filesystem. The counting worker
+//! answers every frame after a short pause and keeps count of the
+//! connections it has open. Hundreds of concurrent requests through pools
+//! of various shard counts must all be answered without the worker ever
+//! seeing more than `max_connections` at once, and connections must be
+//! reused rather than reopened. Response frames that are empty, cut short
+//! or never sent must fail with their `FrameError` and close the connection.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use laravel_rust_server::bridge::connection_pool::{ConnectionPool, ConnectionPoolConfig, FrameError, Framing};
+use laravel_rust_server::bridge::transport::{DuplexListener, DuplexTransport};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+type Pool = ConnectionPool<DuplexTransport>;
+
+/// Bytes buffered in each direction of an in-memory connection
+const BUFFER: usize = 64 * 1024;
+
+/// Connections the worker has seen
+#[derive(Default)]
+struct Seen {
+    open: AtomicUsize,
+    most_open: AtomicUsize,
+    accepted: AtomicUsize,
+}
+
+/// Worker answering every frame with its `uri` after `delay`
+fn spawn_worker(mut listener: DuplexListener, delay: Duration) -> Arc<Seen> {
+    let seen = Arc::new(Seen::default());
+    let counts = seen.clone();
+    tokio::spawn(async move {
+        while let Some(stream) = listener.accept().await {
+            counts.accepted.fetch_add(1, Ordering::SeqCst);
+            let open = counts.open.fetch_add(1, Ordering::SeqCst) + 1;
+            counts.most_open.fetch_max(open, Ordering::SeqCst);
+            let counts = counts.clone();
+            tokio::spawn(async move {
+                let _ = answer(stream, delay).await;
+                counts.open.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+    seen
+}
+
+async fn read_frame(stream: &mut DuplexStream) -> std::io::Result<Value> {
+    let len = stream.read_u32().await? as usize;
+    let mut frame = vec![0; len];
+    stream.read_exact(&mut frame).await?;
+    Ok(serde_json::from_slice(&frame)?)
+}
+
+async fn answer(mut stream: DuplexStream, delay: Duration) -> std::io::Result<()> {
+    loop {
+        let frame = read_frame(&mut stream).await?;
+        tokio::time::sleep(delay).await;
+        let response = serde_json::to_vec(&json!({"id": null, "success": true, "data": frame["uri"], "error": null}))?;
+        stream.write_u32(response.len() as u32).await?;
+        stream.write_all(&response).await?;
+    }
+}
+
+fn config(max_connections: usize, shards: usize) -> ConnectionPoolConfig {
+    ConnectionPoolConfig {
+        socket_path: String::new(),
+        min_connections: 0,
+        max_connections,
+        connection_timeout: Duration::from_secs(1),
+        read_timeout: Duration::from_secs(5),
+        health_check_interval: Duration::from_secs(30),
+        shards,
+        max_uses: None,
+        goodbye_frame: false,
+        framing: Framing::LengthPrefixed,
+    }
+}
+
+/// A pool over a fresh in-memory transport, and the listener its connections arrive at
+fn pool(config: ConnectionPoolConfig) -> (Arc<Pool>, DuplexListener) {
+    let (transport, listener) = DuplexTransport::new(BUFFER);
+    (Arc::new(ConnectionPool::with_transport(config, transport)), listener)
+}
+
+/// Send `requests` frames at once through `pool`, checking each is answered
+async fn send_all(pool: &Arc<Pool>, requests: usize) {
+    let tasks: Vec<_> = (0..requests)
+        .map(|i| {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                let uri = format!("/{}", i);
+                let response = pool.send_http_request(json!({ "uri": uri })).await.expect("answered");
+                assert_eq!(response.data, Some(json!(uri)));
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn max_connections_holds_under_concurrency_for_any_shard_count() {
+    for shards in [1, 3, 4, 16] {
+        let (pool, listener) = pool(config(4, shards));
+        let seen = spawn_worker(listener, Duration::from_millis(2));
+
+        send_all(&pool, 300).await;
+
+        let most_open = seen.most_open.load(Ordering::SeqCst);
+        assert!(most_open <= 4, "{} shards: {} open at once", shards, most_open);
+        assert!(pool.idle_connections() <= 4, "{} shards", shards);
+        assert!(seen.accepted.load(Ordering::SeqCst) <= 4, "{} shards: connections were reopened", shards);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn idle_connections_in_any_shard_are_reused() {
+    let (pool, listener) = pool(config(8, 8));
+    let seen = spawn_worker(listener, Duration::ZERO);
+
+    // One at a time, so each request starts at another shard than the one holding the connection
+    for i in 0..20 {
+        pool.send_http_request(json!({ "uri": format!("/{}", i) })).await.unwrap();
+    }
+    assert_eq!(seen.accepted.load(Ordering::SeqCst), 1);
+    assert_eq!(pool.idle_connections(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn initialize_fills_the_pool_within_its_limit() {
+    let (pool, listener) = pool(ConnectionPoolConfig {
+        min_connections: 10,
+        ..config(3, 2)
+    });
+    let seen = spawn_worker(listener, Duration::ZERO);
+
+    pool.initialize().await.unwrap();
+    pool.initialize().await.unwrap();
+    assert_eq!(pool.idle_connections(), 3);
+    send_all(&pool, 50).await;
+    assert_eq!(seen.accepted.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn closed_idle_connections_are_replaced() {
+    let (pool, mut listener) = pool(config(2, 2));
+
+    // The first connection is answered once and then closed by the worker
+    let first = tokio::spawn(async move {
+        let mut stream = listener.accept().await.unwrap();
+        read_frame(&mut stream).await.unwrap();
+        let response = br#"{"id":null,"success":true,"data":"first","error":null}"#;
+        stream.write_u32(response.len() as u32).await.unwrap();
+        stream.write_all(response).await.unwrap();
+        drop(stream);
+        listener
+    });
+    assert_eq!(pool.send_http_request(json!({"uri": "/"})).await.unwrap().data, Some(json!("first")));
+    let seen = spawn_worker(first.await.unwrap(), Duration::ZERO);
+
+    assert_eq!(pool.send_http_request(json!({"uri": "/again"})).await.unwrap().data, Some(json!("/again")));
+    assert_eq!(seen.accepted.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn an_unreachable_worker_fails_to_connect() {
+    let (pool, listener) = pool(config(1, 1));
+    drop(listener);
+
+    let error = pool.send_http_request(json!({"uri": "/"})).await.unwrap_err();
+    let io_error = error.chain().find_map(|cause| cause.downcast_ref::<std::io::Error>()).expect("an io error");
+    assert_eq!(io_error.kind(), std::io::ErrorKind::ConnectionRefused);
+}
+
+/// Send one frame to a worker that reads it and then writes `reply` raw
+///
+/// Returns the pool's error, checking the connection was not kept. With
+/// `close`, the worker closes the connection after writing.
+async fn broken_exchange(reply: &'static [u8], close: bool) -> FrameError {
+    let (pool, mut listener) = pool(ConnectionPoolConfig {
+        read_timeout: Duration::from_millis(100),
+        ..config(1, 1)
+    });
+    let worker = tokio::spawn(async move {
+        let mut stream = listener.accept().await.unwrap();
+        read_frame(&mut stream).await.unwrap();
+        stream.write_all(reply).await.unwrap();
+        if !close {
+            // Keep the connection open, silent, until the pool gives up on it
+            let _ = stream.read(&mut [0; 1]).await;
+        }
+    });
+
+    let error = pool.send_http_request(json!({"uri": "/"})).await.unwrap_err();
+    assert_eq!(pool.idle_connections(), 0, "the connection went back to the pool");
+    drop(pool);
+    worker.await.unwrap();
+    match error.downcast::<FrameError>() {
+        Ok(frame_error) => frame_error,
+        Err(other) => panic!("not a frame error: {:#}", other),
+    }
+}
+
+#[tokio::test]
+async fn a_zero_length_frame_is_a_protocol_error() {
+    assert!(matches!(broken_exchange(&[0, 0, 0, 0], false).await, FrameError::Empty));
+}
+
+#[tokio::test]
+async fn a_connection_closed_mid_frame_is_reported_as_such() {
+    // Before the response, within the prefix, and half way through the body
+    let error = broken_exchange(b"", true).await;
+    assert!(matches!(error, FrameError::ClosedMidResponse { received: 0 }), "{:?}", error);
+    let error = broken_exchange(&[0, 0], true).await;
+    assert!(matches!(error, FrameError::ClosedMidResponse { received: 2 }), "{:?}", error);
+    let error = broken_exchange(b"\0\0\0\x10{\"id\":", true).await;
+    assert!(matches!(error, FrameError::ClosedMidResponse { received: 10 }), "{:?}", error);
+}
+
+#[tokio::test]
+async fn a_frame_that_is_not_a_response_is_invalid() {
+    assert!(matches!(broken_exchange(b"\0\0\0\x05hello", false).await, FrameError::Invalid(_)));
+}
+
+#[tokio::test]
+async fn a_silent_worker_times_out_each_read() {
+    // No answer at all, and a prefix announcing more than is ever sent
+    assert!(matches!(broken_exchange(b"", false).await, FrameError::TimedOut(_)));
+    assert!(matches!(broken_exchange(b"\0\0\0\x40{}", false).await, FrameError::TimedOut(_)));
+}
@@ -0,0 +1,80 @@
+//! The time budget stamped into worker request frames
+//!
+//! A deadline runs from the moment the request arrived, so the budget
+//! written into a frame must already be short by the time spent before it
+//! is sent, and a frame stamped again for a retry must carry less by the
+//! time the failed attempt took. A caller's budget may only shorten the
+//! read timeout, and the budget never drops below zero.
+
+use std::time::{Duration, Instant};
+
+use hyper::HeaderMap;
+use laravel_rust_server::deadline::{caller_budget, Deadline, DEADLINE_HEADER, DEADLINE_SERVER_VAR};
+use serde_json::{json, Value};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn frame() -> Value {
+    json!({"method": "GET", "uri": "/", "headers": {}, "server": {}})
+}
+
+/// Budget in the frame, checking the header and the server variable agree
+fn stamped(frame: &Value) -> u64 {
+    let server = frame["server"][DEADLINE_SERVER_VAR].as_str().unwrap();
+    assert_eq!(frame["headers"][DEADLINE_HEADER], server);
+    server.parse().unwrap()
+}
+
+#[test]
+fn the_budget_shrinks_by_the_time_already_spent() {
+    let arrived = Instant::now() - Duration::from_millis(1500);
+    let deadline = Deadline::new(arrived, TIMEOUT, None);
+
+    let mut first = frame();
+    let remaining = deadline.stamp(&mut first);
+    let budget = stamped(&first);
+    assert_eq!(budget, remaining.as_millis() as u64);
+    assert!((8000..=8500).contains(&budget), "{} ms after 1.5 s of a 10 s timeout", budget);
+
+    // A retry after a failed attempt is stamped again with what is left
+    std::thread::sleep(Duration::from_millis(300));
+    let mut retry = first.clone();
+    deadline.stamp(&mut retry);
+    let retried = stamped(&retry);
+    assert!(budget - retried >= 300, "{} ms, then {} ms after 300 ms", budget, retried);
+    assert!(budget - retried < 1000, "{} ms, then {} ms after 300 ms", budget, retried);
+}
+
+#[test]
+fn a_caller_budget_only_shortens_the_timeout() {
+    let now = Instant::now();
+    let mut shorter = frame();
+    Deadline::new(now, TIMEOUT, Some(Duration::from_millis(1000))).stamp(&mut shorter);
+    assert!((900..=1000).contains(&stamped(&shorter)), "{}", stamped(&shorter));
+
+    let mut longer = frame();
+    Deadline::new(now, TIMEOUT, Some(Duration::from_secs(99))).stamp(&mut longer);
+    assert!((9900..=10_000).contains(&stamped(&longer)), "{}", stamped(&longer));
+}
+
+#[test]
+fn a_passed_deadline_leaves_nothing() {
+    let deadline = Deadline::new(Instant::now() - Duration::from_secs(11), TIMEOUT, None);
+    assert_eq!(deadline.remaining(), Duration::ZERO);
+    let mut frame = json!({"server": {}});
+    assert_eq!(deadline.stamp(&mut frame), Duration::ZERO);
+    assert_eq!(frame["server"][DEADLINE_SERVER_VAR], "0");
+    assert!(frame.get("headers").is_none(), "a header added to a frame without headers");
+}
+
+#[test]
+fn caller_budgets_are_whole_milliseconds() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(caller_budget(&headers), None);
+    headers.insert(DEADLINE_HEADER, " 1500 ".parse().unwrap());
+    assert_eq!(caller_budget(&headers), Some(Duration::from_millis(1500)));
+    for invalid in ["-5", "1.5", "soon", ""] {
+        headers.insert(DEADLINE_HEADER, invalid.parse().unwrap());
+        assert_eq!(caller_budget(&headers), None, "{:?}", invalid);
+    }
+}
@@ -0,0 +1,43 @@
+//! The template printed by `--print-default-config`
+//!
+//! The binary's output is written to a file and loaded back with the config
+//! loader. Every key in it must be known, every setting with a compiled-in
+//! default must come back with that default, and every setting without one
+//! must be listed, commented out, with its environment variable.
+//! Needs the `laravel-rust-server` binary, which cargo builds for the test.
+
+mod common;
+
+use std::process::Command;
+
+use laravel_rust_server::config_loader::{render_default_template, ConfigFile, SETTINGS};
+
+#[test]
+fn the_template_loads_back_as_the_defaults() {
+    let output = Command::new(common::BINARY)
+        .arg("--print-default-config")
+        .env_remove("CONFIG_PATH")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let template = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(template, render_default_template());
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    std::fs::write(&path, &template).unwrap();
+    let loaded = ConfigFile::load(&path).unwrap();
+    assert!(loaded.unknown_keys.is_empty(), "unknown keys: {:?}", loaded.unknown_keys);
+
+    for setting in SETTINGS {
+        // Whole sections such as [mime] say their variable takes a JSON object
+        let described = [")\n", ", as a JSON object)\n"]
+            .iter()
+            .any(|end| template.contains(&format!("(env: {}{}", setting.env, end)));
+        assert!(described, "{} not described", setting.env);
+        let value = loaded.values.get(setting.key).map(String::as_str);
+        assert_eq!(value, setting.default, "{}", setting.key);
+    }
+    let defaults = SETTINGS.iter().filter(|setting| setting.default.is_some()).count();
+    assert_eq!(loaded.values.len(), defaults);
+}
@@ -0,0 +1,138 @@
+//! Backends discovered as worker sockets in a directory
+//!
+//! Runs `Backends` against mock workers started and stopped in a temporary
+//! directory while it is in use: a socket that appears joins routing once
+//! its worker answers `ping`, requests are spread over every backend, and a
+//! backend whose socket is removed or whose worker stops answering leaves
+//! routing at the next rescan. Needs the `test-worker` feature:
+//! `cargo test --features test-worker --test discovery`.
+
+use std::path::Path;
+use std::time::Duration;
+
+use laravel_rust_server::bridge::connection_pool::{ConnectionPoolConfig, Framing};
+use laravel_rust_server::bridge::discovery::{Backends, DiscoveryConfig};
+use laravel_rust_server::mock_worker::{MockWorker, Reply, Rule, Script};
+use serde_json::json;
+
+fn backends(dir: &Path) -> Backends {
+    Backends::new(
+        DiscoveryConfig {
+            dir: dir.to_path_buf(),
+            interval: Duration::from_millis(50),
+            ping_timeout: Duration::from_millis(200),
+        },
+        ConnectionPoolConfig {
+            socket_path: String::new(),
+            min_connections: 0,
+            max_connections: 4,
+            connection_timeout: Duration::from_secs(1),
+            read_timeout: Duration::from_secs(5),
+            health_check_interval: Duration::from_secs(30),
+            shards: 1,
+            max_uses: None,
+            goodbye_frame: false,
+            framing: Framing::LengthPrefixed,
+        },
+    )
+}
+
+/// Send `requests` frames, each to the backend `pick` chooses
+async fn send(backends: &Backends, requests: usize) {
+    for i in 0..requests {
+        let backend = backends.pick().expect("a backend");
+        let response = backend.pool().send_http_request(json!({"method": "GET", "uri": format!("/{}", i)})).await;
+        assert!(response.unwrap().success, "{}", backend.socket_path());
+    }
+}
+
+/// Frames each worker answered while `f` ran
+async fn frames_during<F: std::future::Future>(workers: &[&MockWorker], f: F) -> Vec<usize> {
+    let before: Vec<_> = workers.iter().map(|worker| worker.stats().frames()).collect();
+    f.await;
+    workers.iter().zip(before).map(|(worker, before)| worker.stats().frames() - before).collect()
+}
+
+#[tokio::test]
+async fn workers_join_and_leave_routing_as_their_sockets_come_and_go() {
+    let dir = tempfile::tempdir().unwrap();
+    let backends = backends(dir.path());
+    backends.rescan().await;
+    assert!(backends.is_empty());
+    assert!(backends.pick().is_none());
+
+    // Only answering *.sock files are taken
+    let a = MockWorker::start(dir.path().join("a.sock"), Script::default()).unwrap();
+    let b = MockWorker::start(dir.path().join("b.sock"), Script::default()).unwrap();
+    std::fs::write(dir.path().join("notes.txt"), "not a socket").unwrap();
+    std::os::unix::net::UnixListener::bind(dir.path().join("stale.sock")).unwrap();
+    backends.rescan().await;
+    assert_eq!(backends.len(), 2);
+
+    assert_eq!(frames_during(&[&a, &b], send(&backends, 10)).await, [5, 5]);
+    let report = backends.report();
+    assert_eq!(report["backends"].as_array().unwrap().len(), 2);
+    assert_eq!(report["backends"][0]["requests"], 5);
+
+    // A third worker starts
+    let c = MockWorker::start(dir.path().join("c.sock"), Script::default()).unwrap();
+    backends.rescan().await;
+    assert_eq!(backends.len(), 3);
+    assert_eq!(frames_during(&[&a, &b, &c], send(&backends, 9)).await, [3, 3, 3]);
+
+    // The second stops and its socket is removed
+    drop(b);
+    backends.rescan().await;
+    assert_eq!(backends.len(), 2);
+    assert_eq!(frames_during(&[&a, &c], send(&backends, 8)).await, [4, 4]);
+    let sockets: Vec<_> = backends.report()["backends"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|backend| backend["socket_path"].as_str().unwrap().to_string())
+        .collect();
+    assert!(sockets.iter().all(|socket| !socket.ends_with("b.sock")), "{:?}", sockets);
+}
+
+#[tokio::test]
+async fn a_worker_that_stops_answering_ping_leaves_routing() {
+    let dir = tempfile::tempdir().unwrap();
+    let backends = backends(dir.path());
+    let a = MockWorker::start(dir.path().join("a.sock"), Script::default()).unwrap();
+    let _b = MockWorker::start(dir.path().join("b.sock"), Script::default()).unwrap();
+    backends.rescan().await;
+    assert_eq!(backends.len(), 2);
+
+    // Same socket file, but the worker behind it now hangs on ping
+    let hanging = Script {
+        rules: vec![Rule {
+            command: Some("ping".to_string()),
+            ..Rule::any(Reply::Hang)
+        }],
+        default: Reply::default(),
+    };
+    let _b = MockWorker::start(dir.path().join("b.sock"), hanging).unwrap();
+    backends.rescan().await;
+    assert_eq!(backends.len(), 1);
+    assert_eq!(frames_during(&[&a], send(&backends, 4)).await, [4]);
+}
+
+#[tokio::test]
+async fn the_watcher_picks_up_new_sockets() {
+    let dir = tempfile::tempdir().unwrap();
+    let backends = std::sync::Arc::new(backends(dir.path()));
+    let watcher = backends.spawn_watcher();
+
+    let _a = MockWorker::start(dir.path().join("a.sock"), Script::default()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while backends.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the socket was discovered");
+
+    // The watcher only holds the backends weakly
+    drop(backends);
+    tokio::time::timeout(Duration::from_secs(5), watcher).await.expect("the watcher stopped").unwrap();
+}
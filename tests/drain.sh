@@ -0,0 +1,140 @@
+#!/usr/bin/env bash
+# Drain mode: out of rotation and back without restarting anything.
+#
+#   cargo build --release
+#   tests/drain.sh ./target/release/laravel-rust-server
+#
+# Starts the server with a stand-in worker socket that holds each
+# connection for a second, DRAIN_GRACE_MS=2000 and the admin listener, then
+# drains it through the admin API while a request is in flight and a
+# keep-alive connection is open. The in-flight request and the open
+# connection (within the grace period) must reach the worker; a new
+# connection must get 503 with Retry-After while /healthz still answers and
+# /readyz fails. After the grace period the open connection is refused
+# too. Undraining, through the admin API and through SIGUSR2, must serve
+# requests again at once. Requests that reach the stand-in worker end in an
+# error of their own, so only whether a response is the drain's
+# `maintenance` 503 is checked.
+# HTTP_PORT and ADMIN_PORT can be overridden from the environment.
+
+set -euo pipefail
+
+BINARY=${1:?usage: $0 path/to/laravel-rust-server}
+BINARY=$(cd "$(dirname "$BINARY")" && pwd)/$(basename "$BINARY")
+HTTP_PORT=${HTTP_PORT:-18080}
+ADMIN_PORT=${ADMIN_PORT:-19090}
+URL=http://127.0.0.1:$HTTP_PORT
+ADMIN=http://127.0.0.1:$ADMIN_PORT
+
+WORK=$(mktemp -d)
+SERVER_PID=
+WORKER_PID=
+FAILED=0
+cleanup() {
+    for pid in $SERVER_PID $WORKER_PID; do
+        kill "$pid" 2>/dev/null || true
+        wait "$pid" 2>/dev/null || true
+    done
+    rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+python3 -c '
+import socket, sys, threading, time
+def hold(conn):
+    time.sleep(1)
+    conn.close()
+s = socket.socket(socket.AF_UNIX)
+s.bind(sys.argv[1])
+s.listen(128)
+while True:
+    threading.Thread(target=hold, args=(s.accept()[0],), daemon=True).start()
+' "$WORK/worker.sock" &
+WORKER_PID=$!
+(
+    cd "$WORK"
+    export HTTP_HOST=127.0.0.1 HTTP_PORT SOCKET_PATH="$WORK/worker.sock" LARAVEL_PATH="$WORK"
+    export LOG_DIR="$WORK/logs" PHP_WORKER_AUTO_RESTART=false SOCKET_RETRY_IDEMPOTENT=false
+    export ADMIN_ENABLED=true ADMIN_HOST=127.0.0.1 ADMIN_PORT DRAIN_GRACE_MS=2000 UNAVAILABLE_RETRY_AFTER_SECS=7
+    exec "$BINARY"
+) >"$WORK/server.out" 2>&1 &
+SERVER_PID=$!
+for _ in $(seq 50); do
+    [ "$(curl -s -o /dev/null -w '%{http_code}' "$URL/readyz")" = 200 ] && break
+    sleep 0.2
+done
+
+check() {
+    local name=$1 want=$2 got=$3
+    if [ "$got" = "$want" ]; then
+        echo "ok - $name"
+    else
+        echo "FAIL: $name: got $got, expected $want"
+        FAILED=1
+    fi
+}
+
+# refused PATH - "refused" for the drain's 503, "served" for anything else
+refused() {
+    [[ $(curl -s -w ' %{http_code}' "$URL$1") == *maintenance*503 ]] && echo refused || echo served
+}
+
+check "served before the drain" served "$(refused /)"
+
+# A keep-alive connection opened before the drain, asked again within and after the grace period
+python3 -c '
+import http.client, sys, time
+conn = http.client.HTTPConnection("127.0.0.1", int(sys.argv[1]))
+def ask():
+    conn.request("GET", "/kept")
+    response = conn.getresponse()
+    body = response.read()
+    refused = response.status == 503 and b"maintenance" in body
+    return "refused" if refused else "served"
+print(ask(), flush=True)
+time.sleep(0.5)
+print(ask(), flush=True)
+time.sleep(2.5)
+print(ask(), flush=True)
+' "$HTTP_PORT" >"$WORK/kept" &
+KEPT_PID=$!
+# In flight when the drain starts: the worker holds it for a second
+curl -s -w ' %{http_code}' "$URL/inflight" >"$WORK/inflight" &
+INFLIGHT_PID=$!
+sleep 0.3
+
+check "drain through the admin API" true "$(curl -s -X POST "$ADMIN/admin/drain" | python3 -c 'import json,sys; print(str(json.load(sys.stdin)["changed"]).lower())')"
+check "second drain changes nothing" false "$(curl -s -X POST "$ADMIN/admin/drain" | python3 -c 'import json,sys; print(str(json.load(sys.stdin)["changed"]).lower())')"
+check "new connection refused" refused "$(refused /)"
+check "static files refused" refused "$(refused /app.css)"
+check "/healthz still answers" 200 "$(curl -s -o /dev/null -w '%{http_code}' "$URL/healthz")"
+check "/readyz fails" "503 draining" "$(curl -s -w ' %{http_code}' "$URL/readyz" | awk '{print $2, $1}')"
+check "Retry-After sent" 7 "$(curl -s -D - -o /dev/null "$URL/" | tr -d '\r' | awk -F': ' 'tolower($1) == "retry-after" {print $2}')"
+check "stats show the trigger" "admin API from 127.0.0.1" "$(curl -s "$ADMIN/admin/stats" | python3 -c 'import json,sys; print(json.load(sys.stdin)["drain"]["trigger"])')"
+check "draining gauge" 1 "$(curl -s "$ADMIN/metrics" | awk '$1 == "http_draining" {print $2}')"
+
+wait "$INFLIGHT_PID" "$KEPT_PID"
+check "in-flight request reached the worker" served "$([[ $(cat "$WORK/inflight") == *maintenance*503 ]] && echo refused || echo served)"
+check "open connection served before, within and after the grace period" "served served refused" "$(tr '\n' ' ' <"$WORK/kept" | sed 's/ $//')"
+
+check "undrain through the admin API" true "$(curl -s -X POST "$ADMIN/admin/undrain" | python3 -c 'import json,sys; print(str(json.load(sys.stdin)["changed"]).lower())')"
+check "served after undraining" served "$(refused /)"
+check "/readyz passes again" 200 "$(curl -s -o /dev/null -w '%{http_code}' "$URL/readyz")"
+
+kill -USR2 "$SERVER_PID"
+sleep 0.3
+check "SIGUSR2 drains" refused "$(refused /)"
+kill -USR2 "$SERVER_PID"
+sleep 0.3
+check "second SIGUSR2 undrains" served "$(refused /)"
+check "transitions logged with their trigger" 2 "$(grep -c 'Draining.*trigger\|trigger.*Draining' "$WORK/server.out" || true)"
+
+if ! kill -0 "$SERVER_PID" 2>/dev/null; then
+    echo "FAIL: server exited"
+    FAILED=1
+fi
+if [ "$FAILED" -ne 0 ]; then
+    tail -n 20 "$WORK/server.out"
+    exit 1
+fi
+echo "ok - drain"
@@ -0,0 +1,64 @@
+//! Duration settings written with units or as bare integers
+//!
+//! `parse_duration` must read every unit, read bare integers in the
+//! setting's own unit (seconds for the few `*_SECS`-style settings,
+//! milliseconds otherwise), and refuse unknown units, signs, fractions and
+//! values that overflow with an error naming the setting.
+//! `normalize_duration` must rewrite values as bare integers in that unit
+//! and refuse sub-second values for settings kept in whole seconds.
+
+use std::time::Duration;
+
+use laravel_rust_server::config_loader::{duration_unit, normalize_duration, parse_duration, DurationUnit};
+
+#[test]
+fn units_are_read() {
+    for (value, expected) in [
+        ("250ms", Duration::from_millis(250)),
+        ("5s", Duration::from_secs(5)),
+        ("2m", Duration::from_secs(120)),
+        ("1h", Duration::from_secs(3600)),
+        ("0s", Duration::ZERO),
+        (" 10 s ", Duration::from_secs(10)),
+    ] {
+        assert_eq!(parse_duration("SOCKET_READ_TIMEOUT_MS", value).unwrap(), expected, "{:?}", value);
+    }
+}
+
+#[test]
+fn bare_integers_are_in_the_unit_of_the_setting() {
+    assert_eq!(duration_unit("SOCKET_READ_TIMEOUT_MS"), Some(DurationUnit::Millis));
+    assert_eq!(duration_unit("SOCKET_CONNECTION_TIMEOUT"), Some(DurationUnit::Secs));
+    assert_eq!(duration_unit("HTTP_PORT"), None);
+
+    assert_eq!(parse_duration("SOCKET_READ_TIMEOUT_MS", "1500").unwrap(), Duration::from_millis(1500));
+    assert_eq!(parse_duration("SOCKET_CONNECTION_TIMEOUT", "5").unwrap(), Duration::from_secs(5));
+    assert_eq!(parse_duration("UNAVAILABLE_RETRY_AFTER_SECS", "30").unwrap(), Duration::from_secs(30));
+}
+
+#[test]
+fn invalid_values_are_refused_naming_the_setting() {
+    let values = ["", "ms", "5d", "-5s", "+5s", "1.5s", "5 seconds", "1h30m"];
+    // Too large for a u64, and too large once converted to milliseconds
+    let overflowing = ["99999999999999999999", "307445734561825861m"];
+    for value in values.into_iter().chain(overflowing) {
+        let error = parse_duration("SOCKET_READ_TIMEOUT_MS", value).expect_err(value).to_string();
+        assert!(error.contains("SOCKET_READ_TIMEOUT_MS"), "{:?}: {}", value, error);
+        assert!(error.contains("milliseconds"), "{:?}: {}", value, error);
+    }
+    let error = parse_duration("SOCKET_CONNECTION_TIMEOUT", "soon").unwrap_err().to_string();
+    assert!(error.contains("seconds"), "{}", error);
+}
+
+#[test]
+fn values_are_normalized_to_bare_integers() {
+    assert_eq!(normalize_duration("SOCKET_READ_TIMEOUT_MS", "2m").unwrap(), "120000");
+    assert_eq!(normalize_duration("SOCKET_READ_TIMEOUT_MS", "750").unwrap(), "750");
+    assert_eq!(normalize_duration("SOCKET_CONNECTION_TIMEOUT", "2m").unwrap(), "120");
+    assert_eq!(normalize_duration("SOCKET_CONNECTION_TIMEOUT", "3000ms").unwrap(), "3");
+    assert!(normalize_duration("SOCKET_CONNECTION_TIMEOUT", "1500ms").is_err());
+
+    // Other settings and empty values are left alone
+    assert_eq!(normalize_duration("HTTP_PORT", "8080").unwrap(), "8080");
+    assert_eq!(normalize_duration("SOCKET_READ_TIMEOUT_MS", "").unwrap(), "");
+}
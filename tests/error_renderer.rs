@@ -0,0 +1,96 @@
+//! A custom `ErrorRenderer` handed to `HttpServer`
+//!
+//! Runs the server in-process with a renderer of its own against a mock
+//! worker, and provokes every class of locally generated error: a request
+//! before the worker is ready, a missing static file, a failing, broken or
+//! silent worker, an oversized body and a panicking handler. Each must be
+//! rendered by that renderer with the error's status, given the media type
+//! negotiated from `Accept`, and still get `X-Request-Id`, and `Retry-After`
+//! for the 503.
+//! Needs the `test-worker` feature:
+//! `cargo test --features test-worker --test error_renderer`.
+
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use hyper::{Body, Response};
+use laravel_rust_server::bridge::socket_bridge::SocketBridge;
+use laravel_rust_server::errors::{ErrorRenderer, ErrorReport};
+use laravel_rust_server::mock_worker::{MockWorker, Reply, Rule, Script};
+use laravel_rust_server::request_context::RequestContext;
+use laravel_rust_server::server::HttpServer;
+
+/// Plain text naming the error's kind and the negotiated media type
+struct Branded;
+
+impl ErrorRenderer for Branded {
+    fn render(&self, report: &ErrorReport, _context: &RequestContext, content_type: &str) -> Response<Body> {
+        Response::builder()
+            .status(report.error.status())
+            .header("content-type", "text/plain")
+            .header("x-renderer", "branded")
+            .body(Body::from(format!("{} as {}", report.error.kind(), content_type)))
+            .unwrap()
+    }
+}
+
+/// Status, body and whether `Retry-After` was set, checking the renderer and request id
+async fn branded(request: reqwest::RequestBuilder) -> (u16, String, bool) {
+    let response = request.send().await.unwrap();
+    let status = response.status().as_u16();
+    assert_eq!(response.headers()["x-renderer"], "branded", "{}", status);
+    assert!(response.headers().contains_key("x-request-id"), "{}", status);
+    let retry_after = response.headers().contains_key("retry-after");
+    (status, response.text().await.unwrap(), retry_after)
+}
+
+#[tokio::test]
+async fn every_error_class_goes_through_the_custom_renderer() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("public")).unwrap();
+    let socket_path = dir.path().join("worker.sock");
+    let script = Script {
+        rules: vec![
+            Rule::path("/fail", Reply::Fail { message: "SQLSTATE[HY000]".to_string() }),
+            Rule::path("/garbage", Reply::Garbage),
+            Rule::path("/hang", Reply::Hang),
+        ],
+        ..Script::default()
+    };
+    let _worker = MockWorker::start(&socket_path, script).unwrap();
+
+    // The only test in this binary, so the settings cannot leak into another
+    std::env::set_var("HTTP_HOST", "127.0.0.1");
+    std::env::set_var("HTTP_PORT", "0");
+    std::env::set_var("SOCKET_PATH", &socket_path);
+    std::env::set_var("PUBLIC_PATH", dir.path().join("public"));
+    std::env::set_var("SOCKET_READ_TIMEOUT_MS", "300");
+    std::env::set_var("SOCKET_MAX_FRAME_SIZE", "4096");
+    let bridge = SocketBridge::new().unwrap();
+    let server = HttpServer::new(bridge).await.unwrap().with_error_renderer(Arc::new(Branded));
+    let url = format!("http://{}", server.bind().unwrap());
+    let ready = server.readiness();
+    tokio::spawn(async move { server.start().await });
+
+    let client = reqwest::Client::new();
+    let (status, body, retry_after) = branded(client.get(format!("{}/orders", url))).await;
+    assert_eq!((status, body.as_str(), retry_after), (503, "bridge_unavailable as application/json", true));
+    ready.store(true, Ordering::Release);
+
+    let problem = client.get(format!("{}/missing.css", url)).header("accept", "application/problem+json");
+    let (status, body, _) = branded(problem).await;
+    assert_eq!((status, body.as_str()), (404, "not_found as application/problem+json"));
+
+    let requests = [
+        (client.get(format!("{}/fail", url)), 500, "application_error"),
+        (client.get(format!("{}/garbage", url)), 502, "upstream_malformed"),
+        (client.get(format!("{}/hang", url)), 504, "bridge_timeout"),
+        (client.post(format!("{}/upload", url)).body(vec![b'x'; 8192]), 413, "payload_too_large"),
+        (client.get(format!("{}/__test/panic", url)), 500, "internal_error"),
+    ];
+    for (request, expected_status, kind) in requests {
+        let (status, body, retry_after) = branded(request).await;
+        assert_eq!((status, body.as_str()), (expected_status, format!("{} as application/json", kind).as_str()));
+        assert!(!retry_after, "{}", kind);
+    }
+}
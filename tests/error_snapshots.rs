@@ -0,0 +1,104 @@
+//! Rendered error responses in production and debug detail
+//!
+//! A bridge that refuses connections and a response that cannot be parsed
+//! are rendered by both renderers in both modes and compared with the
+//! snapshots in `tests/fixtures/error_responses`: status, headers and body.
+//! Production bodies must carry only the generic message, the status and
+//! the request id; debug bodies add the chain, the component and the
+//! timing, whose value is masked since it varies from run to run.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+
+use hyper::{Body, Request};
+use laravel_rust_server::errors::{
+    handle_error_response, ErrorDetail, ErrorRenderer, JsonErrorRenderer, ProblemJsonRenderer, ServerError,
+};
+use laravel_rust_server::request_context::RequestContext;
+use serde_json::{json, Map, Value};
+
+fn fixture(name: &str) -> Value {
+    let path = format!("{}/tests/fixtures/error_responses/{}", env!("CARGO_MANIFEST_DIR"), name);
+    serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap()
+}
+
+/// A connection refused while forwarding, as the bridge reports it
+fn bridge_down() -> anyhow::Error {
+    anyhow::Error::from(io::Error::new(io::ErrorKind::ConnectionRefused, "Connection refused (os error 111)"))
+        .context(ServerError::bridge_down("Failed to connect to socket '/tmp/laravel.sock'"))
+}
+
+/// A worker response that is not valid JSON
+fn parse_failure() -> anyhow::Error {
+    let cause = serde_json::from_str::<Value>("{\"status\": 200,").unwrap_err();
+    let malformed = ServerError::UpstreamMalformed("unreadable response from the worker".to_string());
+    anyhow::Error::from(cause).context(malformed)
+}
+
+/// Status, headers and body of the response, with the timing masked
+async fn snapshot(error: anyhow::Error, detail: ErrorDetail, renderer: &dyn ErrorRenderer) -> Value {
+    let request = Request::builder().uri("/orders/7").header("x-request-id", "req-1").body(Body::empty()).unwrap();
+    let context = RequestContext::new(&request, IpAddr::V4(Ipv4Addr::LOCALHOST));
+    let response = handle_error_response(error, &context, detail, renderer);
+
+    let status = response.status().as_u16();
+    let headers: Map<String, Value> = response
+        .headers()
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_str().unwrap().into()))
+        .collect();
+    let mut body: Value = serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await.unwrap()).unwrap();
+    if let Some(elapsed) = body.get_mut("elapsed_ms") {
+        assert!(elapsed.is_u64(), "elapsed_ms is not a number: {}", elapsed);
+        *elapsed = 0.into();
+    }
+    json!({"status": status, "headers": headers, "body": body})
+}
+
+#[tokio::test]
+async fn bridge_down_in_production() {
+    assert_eq!(
+        snapshot(bridge_down(), ErrorDetail::Production, &JsonErrorRenderer).await,
+        fixture("bridge_down.production.json")
+    );
+    assert_eq!(
+        snapshot(bridge_down(), ErrorDetail::Production, &ProblemJsonRenderer).await,
+        fixture("bridge_down.production.problem.json")
+    );
+}
+
+#[tokio::test]
+async fn bridge_down_in_debug() {
+    assert_eq!(
+        snapshot(bridge_down(), ErrorDetail::Debug, &JsonErrorRenderer).await,
+        fixture("bridge_down.debug.json")
+    );
+    assert_eq!(
+        snapshot(bridge_down(), ErrorDetail::Debug, &ProblemJsonRenderer).await,
+        fixture("bridge_down.debug.problem.json")
+    );
+}
+
+#[tokio::test]
+async fn parse_failure_in_production() {
+    assert_eq!(
+        snapshot(parse_failure(), ErrorDetail::Production, &JsonErrorRenderer).await,
+        fixture("parse_failure.production.json")
+    );
+    assert_eq!(
+        snapshot(parse_failure(), ErrorDetail::Production, &ProblemJsonRenderer).await,
+        fixture("parse_failure.production.problem.json")
+    );
+}
+
+#[tokio::test]
+async fn parse_failure_in_debug() {
+    assert_eq!(
+        snapshot(parse_failure(), ErrorDetail::Debug, &JsonErrorRenderer).await,
+        fixture("parse_failure.debug.json")
+    );
+    assert_eq!(
+        snapshot(parse_failure(), ErrorDetail::Debug, &ProblemJsonRenderer).await,
+        fixture("parse_failure.debug.problem.json")
+    );
+}
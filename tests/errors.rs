@@ -0,0 +1,146 @@
+//! Status codes and bodies of each `ServerError` variant
+//!
+//! Every variant is rendered through `handle_error_response` with the
+//! default JSON renderer in production detail. Each must get its own status
+//! and `error` kind, the generic message rather than the internal one, the
+//! request id, and for 503s the reason with a matching `Retry-After`.
+//! Errors that are not a `ServerError`, or that wrap one in context, must
+//! be classified as the server would.
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+
+use hyper::{Body, Request, StatusCode};
+use laravel_rust_server::errors::{
+    handle_error_response, ErrorDetail, JsonErrorRenderer, ServerError, UnavailableReason,
+};
+use laravel_rust_server::request_context::RequestContext;
+use serde_json::{json, Value};
+
+fn context() -> RequestContext {
+    let request = Request::builder().uri("/orders").header("x-request-id", "req-1").body(Body::empty()).unwrap();
+    RequestContext::new(&request, IpAddr::V4(Ipv4Addr::LOCALHOST))
+}
+
+/// Status, `Retry-After` and JSON body of the response for `error`
+async fn render(error: impl Into<anyhow::Error>) -> (StatusCode, Option<String>, Value) {
+    let response = handle_error_response(error.into(), &context(), ErrorDetail::Production, &JsonErrorRenderer);
+    let status = response.status();
+    assert_eq!(response.headers()["content-type"], "application/json");
+    assert_eq!(response.headers()["x-request-id"], "req-1");
+    let retry_after = response.headers().get("retry-after").map(|v| v.to_str().unwrap().to_string());
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, retry_after, serde_json::from_slice(&body).unwrap())
+}
+
+fn unavailable(reason: UnavailableReason, retry_after: Option<Duration>) -> ServerError {
+    ServerError::Unavailable {
+        reason,
+        retry_after,
+        message: "socket /run/app.sock refused".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn bridge_down_is_503_with_the_default_retry_after() {
+    let (status, retry_after, body) = render(ServerError::bridge_down("socket /run/app.sock refused")).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(retry_after.as_deref(), Some("5"));
+    assert_eq!(
+        body,
+        json!({
+            "error": "bridge_unavailable",
+            "status": 503,
+            "message": "The service is temporarily unavailable",
+            "request_id": "req-1",
+            "reason": "bridge_down",
+            "retry_after": 5,
+        })
+    );
+}
+
+#[tokio::test]
+async fn overloaded_is_503_with_its_retry_after_rounded_up() {
+    let overloaded = unavailable(UnavailableReason::Overloaded, Some(Duration::from_millis(1200)));
+    let (status, retry_after, body) = render(overloaded).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(retry_after.as_deref(), Some("2"));
+    assert_eq!(body["error"], "overloaded");
+    assert_eq!(body["message"], "The server is overloaded");
+    assert_eq!(body["reason"], "overloaded");
+    assert_eq!(body["retry_after"], 2);
+}
+
+#[tokio::test]
+async fn maintenance_is_503() {
+    let maintenance = unavailable(UnavailableReason::Maintenance, Some(Duration::from_secs(30)));
+    let (status, retry_after, body) = render(maintenance).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(retry_after.as_deref(), Some("30"));
+    assert_eq!(body["error"], "maintenance");
+    assert_eq!(body["message"], "The service is down for maintenance");
+    assert_eq!(body["reason"], "maintenance");
+}
+
+#[tokio::test]
+async fn bridge_timeout_is_504() {
+    let (status, retry_after, body) = render(ServerError::BridgeTimeout("no answer in 30s".to_string())).await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(retry_after, None);
+    assert_eq!(
+        body,
+        json!({"error": "bridge_timeout", "status": 504, "message": "The request timed out", "request_id": "req-1"})
+    );
+}
+
+#[tokio::test]
+async fn upstream_malformed_is_502() {
+    let (status, _, body) = render(ServerError::UpstreamMalformed("status 999".to_string())).await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(body["error"], "upstream_malformed");
+    assert_eq!(body["message"], "The application returned an invalid response");
+}
+
+#[tokio::test]
+async fn application_error_is_500() {
+    let (status, _, body) = render(ServerError::Application("SQLSTATE[HY000]".to_string())).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body["error"], "application_error");
+    assert_eq!(body["message"], "The application encountered an error");
+}
+
+#[tokio::test]
+async fn payload_too_large_is_413() {
+    let (status, _, body) = render(ServerError::PayloadTooLarge("20 MB body".to_string())).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body["error"], "payload_too_large");
+    assert_eq!(body["message"], "The request is too large");
+}
+
+#[tokio::test]
+async fn not_found_is_404() {
+    let (status, _, body) = render(ServerError::NotFound("/var/www/public/missing.js".to_string())).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, json!({"error": "not_found", "status": 404, "message": "Not found", "request_id": "req-1"}));
+}
+
+#[tokio::test]
+async fn internal_error_is_500() {
+    let (status, _, body) = render(ServerError::Internal("invariant broken".to_string())).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body["error"], "internal_error");
+    assert_eq!(body["message"], "Internal server error");
+}
+
+#[tokio::test]
+async fn other_errors_are_internal_and_context_keeps_the_class() {
+    let (status, _, body) = render(anyhow::anyhow!("disk on fire")).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body["error"], "internal_error");
+
+    let wrapped =
+        anyhow::Error::from(ServerError::BridgeTimeout("no answer".to_string())).context("forwarding GET /orders");
+    let (status, _, body) = render(wrapped).await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(body["error"], "bridge_timeout");
+}
@@ -0,0 +1,94 @@
+//! `Expect: 100-continue` uploads
+//!
+//! Runs the binary against a mock worker with `SOCKET_MAX_FRAME_SIZE` of
+//! 1000 bytes and a raw `TcpStream` client that, like curl and most SDKs,
+//! sends the body only after the interim response. A small declared body
+//! must get `100 Continue` at once and then the worker's answer. A declared
+//! length over the frame size must get the final 413 at once, with no
+//! `100 Continue` before it. A chunked body declares no length, so it gets
+//! `100 Continue`, and then a 413 as soon as it outgrows the frame, without
+//! the server waiting for the rest of it.
+//! Needs the `test-worker` feature:
+//! `cargo test --features test-worker --test expect_continue`.
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use common::{command, free_port, Server};
+use laravel_rust_server::mock_worker::{MockWorker, Script};
+
+fn connect(port: u16) -> TcpStream {
+    let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(3))).unwrap();
+    stream
+}
+
+/// Status line and headers of the next response, or "" if none arrives in time
+fn read_head(stream: &mut TcpStream) -> String {
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut byte) {
+            Ok(1) => head.push(byte[0]),
+            _ => break,
+        }
+    }
+    String::from_utf8_lossy(&head).into_owned()
+}
+
+/// Send upload headers with `Expect: 100-continue` and the given framing header
+fn send_headers(stream: &mut TcpStream, framing: &str) {
+    let head = format!(
+        "POST /upload HTTP/1.1\r\nHost: localhost\r\nExpect: 100-continue\r\n\
+         Content-Type: application/octet-stream\r\n{}\r\n\r\n",
+        framing
+    );
+    stream.write_all(head.as_bytes()).unwrap();
+}
+
+#[test]
+fn expect_continue_is_answered_before_the_body() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let _worker = runtime
+        .block_on(async { MockWorker::start(dir.path().join("worker.sock"), Script::default()) })
+        .unwrap();
+    let port = free_port();
+    let mut command = command(dir.path(), port);
+    command.env("SOCKET_MAX_FRAME_SIZE", "1000");
+    let server = Server::spawn(command, port, dir.path().join("server.log"));
+    server.wait_ready_blocking();
+
+    // A body that fits: 100 Continue, then the worker's answer
+    let mut stream = connect(port);
+    send_headers(&mut stream, "Content-Length: 10");
+    let interim = read_head(&mut stream);
+    assert!(interim.starts_with("HTTP/1.1 100 Continue"), "{:?}", interim);
+    stream.write_all(b"0123456789").unwrap();
+    let head = read_head(&mut stream);
+    assert!(head.starts_with("HTTP/1.1 200"), "{:?}", head);
+
+    // A declared length over the frame size: the final 413, without 100 Continue
+    let mut stream = connect(port);
+    send_headers(&mut stream, "Content-Length: 5000");
+    let head = read_head(&mut stream);
+    assert!(head.starts_with("HTTP/1.1 413"), "{:?}", head);
+
+    // A chunked body: 100 Continue, then 413 once it passes the frame size,
+    // although the last chunk is never sent
+    let mut stream = connect(port);
+    send_headers(&mut stream, "Transfer-Encoding: chunked");
+    let interim = read_head(&mut stream);
+    assert!(interim.starts_with("HTTP/1.1 100 Continue"), "{:?}", interim);
+    let chunk = vec![b'x'; 600];
+    for _ in 0..2 {
+        stream.write_all(format!("{:x}\r\n", chunk.len()).as_bytes()).unwrap();
+        stream.write_all(&chunk).unwrap();
+        stream.write_all(b"\r\n").unwrap();
+    }
+    let head = read_head(&mut stream);
+    assert!(head.starts_with("HTTP/1.1 413"), "{:?}", head);
+}
@@ -0,0 +1,92 @@
+#!/usr/bin/env bash
+# Clients sending Expect: 100-continue are not left waiting.
+#
+#   cargo build --release
+#   tests/expect_continue.sh ./target/release/laravel-rust-server
+#
+# Starts the server with a stand-in worker socket and SOCKET_MAX_FRAME_SIZE
+# of 1000 bytes, then sends request headers with Expect: 100-continue from
+# a client that transmits the body only after an interim response, as curl
+# and most SDKs do. A small body must get 100 Continue at once. A declared
+# length over the frame size must get the final 413 at once, with no 100
+# Continue before it. HTTP_PORT can be overridden from the environment.
+
+set -euo pipefail
+
+BINARY=${1:?usage: $0 path/to/laravel-rust-server}
+BINARY=$(cd "$(dirname "$BINARY")" && pwd)/$(basename "$BINARY")
+HTTP_PORT=${HTTP_PORT:-18080}
+URL=http://127.0.0.1:$HTTP_PORT
+
+WORK=$(mktemp -d)
+SERVER_PID=
+WORKER_PID=
+FAILED=0
+cleanup() {
+    for pid in $SERVER_PID $WORKER_PID; do
+        kill "$pid" 2>/dev/null || true
+        wait "$pid" 2>/dev/null || true
+    done
+    rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+python3 -c '
+import socket, sys
+s = socket.socket(socket.AF_UNIX)
+s.bind(sys.argv[1])
+s.listen(128)
+while True:
+    s.accept()[0].close()
+' "$WORK/worker.sock" &
+WORKER_PID=$!
+(
+    cd "$WORK"
+    export HTTP_HOST=127.0.0.1 HTTP_PORT SOCKET_PATH="$WORK/worker.sock" LARAVEL_PATH="$WORK"
+    export LOG_DIR="$WORK/logs" PHP_WORKER_AUTO_RESTART=false SOCKET_MAX_FRAME_SIZE=1000
+    exec "$BINARY"
+) >"$WORK/server.out" 2>&1 &
+SERVER_PID=$!
+for _ in $(seq 50); do
+    [ "$(curl -s -o /dev/null -w '%{http_code}' "$URL/readyz")" = 200 ] && break
+    sleep 0.2
+done
+
+check() {
+    local name=$1 ok=$2
+    if [ "$ok" = true ]; then
+        echo "ok - $name"
+    else
+        echo "FAIL: $name"
+        FAILED=1
+    fi
+}
+# Status line of the first response to headers declaring $1 body bytes,
+# "none" if nothing arrives within 3 seconds
+first_status() {
+    python3 -c '
+import socket, sys
+port, length = int(sys.argv[1]), int(sys.argv[2])
+s = socket.create_connection(("127.0.0.1", port))
+s.sendall(b"POST /upload HTTP/1.1\r\nHost: localhost\r\nExpect: 100-continue\r\n"
+          b"Content-Type: application/octet-stream\r\nContent-Length: %d\r\n\r\n" % length)
+s.settimeout(3)
+try:
+    head = s.recv(4096)
+except socket.timeout:
+    head = b""
+print(head.split(b"\r\n")[0].decode() or "none")
+' "$HTTP_PORT" "$1"
+}
+
+status=$(first_status 100)
+check "a small body gets 100 Continue (got $status)" "$([ "$status" = "HTTP/1.1 100 Continue" ] && echo true || echo false)"
+status=$(first_status 1000000)
+check "an oversized body gets 413 without waiting for it (got $status)" \
+    "$([ "$status" = "HTTP/1.1 413 Payload Too Large" ] && echo true || echo false)"
+
+if [ "$FAILED" -ne 0 ]; then
+    tail -n 20 "$WORK/server.out"
+    exit 1
+fi
+echo "ok - expect 100-continue"
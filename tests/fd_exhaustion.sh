@@ -0,0 +1,114 @@
+#!/usr/bin/env bash
+# The server survives running out of file descriptors.
+#
+#   cargo build --release
+#   tests/fd_exhaustion.sh ./target/release/laravel-rust-server
+#
+# Starts the server with a stand-in worker socket, lowers its soft
+# RLIMIT_NOFILE to a few descriptors above what it has open, and holds
+# enough idle client connections to use them up. accept() then fails with
+# EMFILE: a request must not get through, but the server must keep running.
+# Once the idle connections close, requests must be served again and
+# http_accept_errors_total{errno="EMFILE"} must have counted the failures.
+# The limit is restored afterwards. HTTP_PORT and ADMIN_PORT can be
+# overridden from the environment.
+
+set -euo pipefail
+
+BINARY=${1:?usage: $0 path/to/laravel-rust-server}
+BINARY=$(cd "$(dirname "$BINARY")" && pwd)/$(basename "$BINARY")
+HTTP_PORT=${HTTP_PORT:-18080}
+ADMIN_PORT=${ADMIN_PORT:-18081}
+URL=http://127.0.0.1:$HTTP_PORT
+
+WORK=$(mktemp -d)
+SERVER_PID=
+WORKER_PID=
+HOLDER_PID=
+FAILED=0
+cleanup() {
+    for pid in $HOLDER_PID $SERVER_PID $WORKER_PID; do
+        kill "$pid" 2>/dev/null || true
+        wait "$pid" 2>/dev/null || true
+    done
+    rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+python3 -c '
+import socket, sys
+s = socket.socket(socket.AF_UNIX)
+s.bind(sys.argv[1])
+s.listen(128)
+while True:
+    s.accept()[0].close()
+' "$WORK/worker.sock" &
+WORKER_PID=$!
+(
+    cd "$WORK"
+    export HTTP_HOST=127.0.0.1 HTTP_PORT SOCKET_PATH="$WORK/worker.sock" LARAVEL_PATH="$WORK"
+    export LOG_DIR="$WORK/logs" PHP_WORKER_AUTO_RESTART=false ACCEPT_ERROR_BACKOFF_MS=50
+    export ADMIN_ENABLED=true ADMIN_HOST=127.0.0.1 ADMIN_PORT
+    exec "$BINARY"
+) >"$WORK/server.out" 2>&1 &
+SERVER_PID=$!
+for _ in $(seq 50); do
+    [ "$(curl -s -o /dev/null -w '%{http_code}' "$URL/readyz")" = 200 ] && break
+    sleep 0.2
+done
+
+check() {
+    local name=$1 ok=$2
+    if [ "$ok" = true ]; then
+        echo "ok - $name"
+    else
+        echo "FAIL: $name"
+        FAILED=1
+    fi
+}
+set_soft_limit() {
+    python3 -c '
+import resource, sys
+pid, soft = int(sys.argv[1]), int(sys.argv[2])
+_, hard = resource.prlimit(pid, resource.RLIMIT_NOFILE)
+print(resource.prlimit(pid, resource.RLIMIT_NOFILE, (soft, hard))[0])
+' "$SERVER_PID" "$1"
+}
+
+open_fds=$(ls "/proc/$SERVER_PID/fd" | wc -l)
+previous=$(set_soft_limit $((open_fds + 5)))
+
+# Idle connections take the remaining descriptors, and more wait in the backlog
+python3 -c '
+import socket, sys, time
+held = [socket.create_connection(("127.0.0.1", int(sys.argv[1]))) for _ in range(20)]
+time.sleep(3600)
+' "$HTTP_PORT" &
+HOLDER_PID=$!
+sleep 1
+
+code=$(curl -s -o /dev/null -w '%{http_code}' --max-time 1 "$URL/healthz" || true)
+check "no request is served while descriptors are exhausted (got $code)" "$([ "$code" != 200 ] && echo true || echo false)"
+check "the server keeps running" "$(kill -0 "$SERVER_PID" 2>/dev/null && echo true || echo false)"
+
+kill "$HOLDER_PID"
+wait "$HOLDER_PID" 2>/dev/null || true
+HOLDER_PID=
+code=000
+for _ in $(seq 20); do
+    code=$(curl -s -o /dev/null -w '%{http_code}' --max-time 1 "$URL/healthz" || true)
+    [ "$code" = 200 ] && break
+    sleep 0.2
+done
+check "requests are served once connections close (got $code)" "$([ "$code" = 200 ] && echo true || echo false)"
+set_soft_limit "$previous" >/dev/null
+
+errors=$(curl -s "http://127.0.0.1:$ADMIN_PORT/metrics" | awk '/^http_accept_errors_total\{errno="EMFILE"\}/ { print $2 }')
+check "EMFILE is counted in http_accept_errors_total (got ${errors:-nothing})" "$([ "${errors:-0}" -gt 0 ] && echo true || echo false)"
+check "the shortage was logged" "$(grep -q 'Cannot accept connections' "$WORK/server.out" && echo true || echo false)"
+
+if [ "$FAILED" -ne 0 ]; then
+    tail -n 20 "$WORK/server.out"
+    exit 1
+fi
+echo "ok - file descriptor exhaustion"
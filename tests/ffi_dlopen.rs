@@ -0,0 +1,104 @@
+//! The C API as a host process sees it: through the shared library
+//!
+//! Opens the `cdylib` cargo built next to the test binary
+//! (`target/<profile>/liblaravel_rust_server.so`) with `dlopen`, resolves
+//! the exported functions by name and round-trips a command through a
+//! `MockWorker`, releasing the response with `laravel_rust_free_string`.
+//! This catches what linking the `rlib` cannot: a function missing from the
+//! export table or a symbol renamed away from the header.
+//! Needs the `test-worker` feature:
+//! `cargo test --features test-worker --test ffi_dlopen`.
+
+mod common;
+
+use std::ffi::{c_char, CStr, CString};
+use std::path::PathBuf;
+use std::ptr;
+
+use common::free_port;
+use laravel_rust_server::laravel_integration::{LaravelRustServer, LaravelRustStatus};
+use laravel_rust_server::mock_worker::{MockWorker, Reply, Rule, Script};
+use libloading::{Library, Symbol};
+use serde_json::{json, Value};
+
+type Init = unsafe extern "C" fn(*mut *mut LaravelRustServer) -> LaravelRustStatus;
+type SetOption = unsafe extern "C" fn(*mut LaravelRustServer, *const c_char, *const c_char) -> LaravelRustStatus;
+type Start = unsafe extern "C" fn(*mut LaravelRustServer, *const c_char) -> LaravelRustStatus;
+type SendCommand =
+    unsafe extern "C" fn(*mut LaravelRustServer, *const c_char, *const c_char, *mut *mut c_char) -> LaravelRustStatus;
+type FreeString = unsafe extern "C" fn(*mut c_char);
+type Stop = unsafe extern "C" fn(*mut LaravelRustServer) -> LaravelRustStatus;
+type Destroy = unsafe extern "C" fn(*mut LaravelRustServer);
+
+/// The shared library in the target directory of this test binary
+fn library_path() -> PathBuf {
+    // target/<profile>/deps/<test binary>
+    let exe = std::env::current_exe().unwrap();
+    let profile_dir = exe.parent().and_then(|deps| deps.parent()).unwrap();
+    let path = profile_dir.join(libloading::library_filename("laravel_rust_server"));
+    assert!(path.exists(), "{} was not built", path.display());
+    path
+}
+
+fn symbol<'lib, T>(library: &'lib Library, name: &str) -> Symbol<'lib, T> {
+    unsafe { library.get(name.as_bytes()) }.unwrap_or_else(|e| panic!("{} is not exported: {}", name, e))
+}
+
+#[test]
+fn a_command_round_trips_through_the_shared_library() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let socket_path = dir.path().join("worker.sock");
+    let script = Script {
+        rules: vec![Rule {
+            command: Some("inspire".to_string()),
+            ..Rule::any(Reply::Data {
+                data: json!({"quote": "Simplicity is the ultimate sophistication."}),
+            })
+        }],
+        ..Script::default()
+    };
+    let worker = runtime.block_on(async { MockWorker::start(&socket_path, script) }).unwrap();
+
+    let library = unsafe { Library::new(library_path()) }.unwrap();
+    let init: Symbol<Init> = symbol(&library, "laravel_rust_init");
+    let set_option: Symbol<SetOption> = symbol(&library, "laravel_rust_set_option");
+    let start: Symbol<Start> = symbol(&library, "laravel_rust_start");
+    let send_command: Symbol<SendCommand> = symbol(&library, "laravel_rust_send_command");
+    let free_string: Symbol<FreeString> = symbol(&library, "laravel_rust_free_string");
+    let stop: Symbol<Stop> = symbol(&library, "laravel_rust_stop");
+    let destroy: Symbol<Destroy> = symbol(&library, "laravel_rust_destroy");
+
+    let mut server = ptr::null_mut();
+    assert_eq!(unsafe { init(&mut server) }, LaravelRustStatus::Ok);
+    let port = free_port().to_string();
+    for (key, value) in [("host", "127.0.0.1"), ("port", &port), ("socket_path", socket_path.to_str().unwrap())] {
+        let (key, value) = (CString::new(key).unwrap(), CString::new(value).unwrap());
+        assert_eq!(unsafe { set_option(server, key.as_ptr(), value.as_ptr()) }, LaravelRustStatus::Ok);
+    }
+    assert_eq!(unsafe { start(server, ptr::null()) }, LaravelRustStatus::Ok);
+
+    let frames = worker.stats().frames();
+    let command = CString::new("inspire").unwrap();
+    let data = CString::new(r#"{"topic": "design"}"#).unwrap();
+    let mut response = ptr::null_mut();
+    let status = unsafe { send_command(server, command.as_ptr(), data.as_ptr(), &mut response) };
+    assert_eq!(status, LaravelRustStatus::Ok);
+    assert!(!response.is_null());
+    let reply: Value = serde_json::from_slice(unsafe { CStr::from_ptr(response) }.to_bytes()).unwrap();
+    unsafe { free_string(response) };
+    assert_eq!(reply["success"], true, "{}", reply);
+    assert_eq!(reply["data"]["quote"], "Simplicity is the ultimate sophistication.", "{}", reply);
+    assert_eq!(worker.stats().frames() - frames, 1);
+
+    // A command the worker does not know is acknowledged with its name
+    let command = CString::new("ping").unwrap();
+    let status = unsafe { send_command(server, command.as_ptr(), ptr::null(), &mut response) };
+    assert_eq!(status, LaravelRustStatus::Ok);
+    let reply: Value = serde_json::from_slice(unsafe { CStr::from_ptr(response) }.to_bytes()).unwrap();
+    unsafe { free_string(response) };
+    assert_eq!(reply["data"]["command"], "ping", "{}", reply);
+
+    assert_eq!(unsafe { stop(server) }, LaravelRustStatus::Ok);
+    unsafe { destroy(server) };
+}
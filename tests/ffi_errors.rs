@@ -0,0 +1,160 @@
+//! Status codes and `laravel_rust_last_error` across the C API
+//!
+//! Forces each failure class through the exported functions: invalid
+//! arguments, calls that need a running server, a second start, a taken
+//! port, a PHP worker that is missing, one that never answers and one that
+//! answers with garbage. Each must return its own status and leave a
+//! message describing it for `laravel_rust_last_error`, on the handle or,
+//! for calls without one, globally; a later successful call must not clear
+//! it. Needs the `test-worker` feature:
+//! `cargo test --features test-worker --test ffi_errors`.
+
+mod common;
+
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use common::free_port;
+use laravel_rust_server::laravel_integration::{
+    laravel_rust_destroy, laravel_rust_free_string, laravel_rust_get_option, laravel_rust_get_stats,
+    laravel_rust_init, laravel_rust_last_error, laravel_rust_send_command, laravel_rust_set_option,
+    laravel_rust_start, laravel_rust_stop, LaravelRustServer, LaravelRustStatus,
+};
+use laravel_rust_server::mock_worker::{MockWorker, Reply, Rule, Script};
+
+/// Message of the last failure, on `server` or globally for null
+fn last_error(server: *mut LaravelRustServer) -> Option<String> {
+    let message = unsafe { laravel_rust_last_error(server) };
+    if message.is_null() {
+        return None;
+    }
+    let text = unsafe { CStr::from_ptr(message) }.to_str().unwrap().to_string();
+    unsafe { laravel_rust_free_string(message) };
+    Some(text)
+}
+
+fn init() -> *mut LaravelRustServer {
+    let mut server = ptr::null_mut();
+    assert_eq!(unsafe { laravel_rust_init(&mut server) }, LaravelRustStatus::Ok);
+    server
+}
+
+fn set(server: *mut LaravelRustServer, key: &str, value: &str) -> LaravelRustStatus {
+    let (key, value) = (CString::new(key).unwrap(), CString::new(value).unwrap());
+    unsafe { laravel_rust_set_option(server, key.as_ptr(), value.as_ptr()) }
+}
+
+/// Status of sending `command`; the response, if any, is freed
+fn send(server: *mut LaravelRustServer, command: &str, data: Option<&str>) -> LaravelRustStatus {
+    let command = CString::new(command).unwrap();
+    let data = data.map(|data| CString::new(data).unwrap());
+    let mut response = ptr::null_mut();
+    let data = data.as_ref().map_or(ptr::null(), |d| d.as_ptr());
+    let status = unsafe { laravel_rust_send_command(server, command.as_ptr(), data, &mut response) };
+    assert_eq!(response.is_null(), status != LaravelRustStatus::Ok, "{:?}", status);
+    unsafe { laravel_rust_free_string(response) };
+    status
+}
+
+#[test]
+fn invalid_arguments_and_a_stopped_server_are_reported() {
+    assert_eq!(unsafe { laravel_rust_init(ptr::null_mut()) }, LaravelRustStatus::InvalidArgument);
+    assert_eq!(last_error(ptr::null_mut()).as_deref(), Some("out_server is null"));
+
+    let server = init();
+    assert_eq!(last_error(server), None);
+
+    let mut out = ptr::null_mut();
+    let status = unsafe { laravel_rust_send_command(server, ptr::null(), ptr::null(), &mut out) };
+    assert_eq!(status, LaravelRustStatus::InvalidArgument);
+    assert_eq!(last_error(server).as_deref(), Some("command is null"));
+    assert_eq!(send(server, "ping", Some("[1, 2]")), LaravelRustStatus::InvalidArgument);
+    assert!(last_error(server).unwrap().contains("JSON object"), "{:?}", last_error(server));
+    assert_eq!(send(server, "ping", Some("{not json")), LaravelRustStatus::InvalidArgument);
+
+    assert_eq!(set(server, "no_such_option", "1"), LaravelRustStatus::InvalidArgument);
+    assert!(last_error(server).unwrap().contains("no_such_option"));
+    assert_eq!(set(server, "port", "not a port"), LaravelRustStatus::InvalidArgument);
+    assert!(last_error(server).unwrap().contains("port"));
+    let key = CString::new("no_such_option").unwrap();
+    assert_eq!(unsafe { laravel_rust_get_option(server, key.as_ptr(), &mut out) }, LaravelRustStatus::InvalidArgument);
+    assert!(out.is_null());
+
+    // A success keeps the message of the last failure
+    assert_eq!(set(server, "port", "8081"), LaravelRustStatus::Ok);
+    assert!(last_error(server).unwrap().contains("no_such_option"));
+
+    assert_eq!(unsafe { laravel_rust_get_stats(server, &mut out) }, LaravelRustStatus::NotStarted);
+    assert!(out.is_null());
+    assert!(last_error(server).is_some());
+    assert_eq!(unsafe { laravel_rust_stop(server) }, LaravelRustStatus::NotStarted);
+    assert_eq!(unsafe { laravel_rust_get_stats(server, ptr::null_mut()) }, LaravelRustStatus::InvalidArgument);
+    assert_eq!(last_error(server).as_deref(), Some("out_json is null"));
+
+    // Without a handle the failure can only be kept globally
+    assert_eq!(unsafe { laravel_rust_stop(ptr::null_mut()) }, LaravelRustStatus::InvalidArgument);
+    assert_eq!(last_error(ptr::null_mut()).as_deref(), Some("server is null"));
+
+    unsafe { laravel_rust_destroy(server) };
+}
+
+#[test]
+fn start_and_worker_failures_get_their_own_status() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let socket_path = dir.path().join("worker.sock");
+    let script = Script {
+        rules: vec![
+            Rule {
+                command: Some("hang".to_string()),
+                ..Rule::any(Reply::Hang)
+            },
+            Rule {
+                command: Some("garbage".to_string()),
+                ..Rule::any(Reply::Garbage)
+            },
+        ],
+        ..Script::default()
+    };
+    let _worker = runtime.block_on(async { MockWorker::start(&socket_path, script) }).unwrap();
+
+    // The only test here that starts a server, which sets the process environment
+    let server = init();
+    let port = free_port().to_string();
+    assert_eq!(set(server, "host", "127.0.0.1"), LaravelRustStatus::Ok);
+    assert_eq!(set(server, "port", &port), LaravelRustStatus::Ok);
+    assert_eq!(set(server, "socket_path", socket_path.to_str().unwrap()), LaravelRustStatus::Ok);
+    assert_eq!(set(server, "read_timeout_ms", "200"), LaravelRustStatus::Ok);
+    assert_eq!(unsafe { laravel_rust_start(server, ptr::null()) }, LaravelRustStatus::Ok);
+    assert_eq!(unsafe { laravel_rust_start(server, ptr::null()) }, LaravelRustStatus::AlreadyRunning);
+    assert_eq!(last_error(server).as_deref(), Some("server is already running"));
+
+    assert_eq!(send(server, "ping", None), LaravelRustStatus::Ok);
+    assert_eq!(send(server, "hang", None), LaravelRustStatus::Timeout);
+    assert!(last_error(server).unwrap().starts_with("PHP worker timed out"), "{:?}", last_error(server));
+    assert_eq!(send(server, "garbage", None), LaravelRustStatus::Internal);
+    assert_eq!(send(server, "ping", None), LaravelRustStatus::Ok);
+
+    // A second handle on the same port cannot start
+    let other = init();
+    assert_eq!(set(other, "host", "127.0.0.1"), LaravelRustStatus::Ok);
+    assert_eq!(set(other, "port", &port), LaravelRustStatus::Ok);
+    assert_eq!(unsafe { laravel_rust_start(other, ptr::null()) }, LaravelRustStatus::StartFailed);
+    assert!(last_error(other).is_some());
+
+    // It starts on a free one, but finds no worker behind its socket
+    let missing = dir.path().join("missing.sock");
+    assert_eq!(set(other, "port", &free_port().to_string()), LaravelRustStatus::Ok);
+    assert_eq!(set(other, "socket_path", missing.to_str().unwrap()), LaravelRustStatus::Ok);
+    assert_eq!(unsafe { laravel_rust_start(other, ptr::null()) }, LaravelRustStatus::Ok);
+    assert_eq!(send(other, "ping", None), LaravelRustStatus::BridgeUnavailable);
+    assert!(last_error(other).is_some());
+
+    assert_eq!(unsafe { laravel_rust_stop(other) }, LaravelRustStatus::Ok);
+    assert_eq!(unsafe { laravel_rust_stop(server) }, LaravelRustStatus::Ok);
+    assert_eq!(unsafe { laravel_rust_stop(server) }, LaravelRustStatus::NotStarted);
+    unsafe {
+        laravel_rust_destroy(other);
+        laravel_rust_destroy(server);
+    }
+}
@@ -0,0 +1,138 @@
+//! Options set on a C handle before `laravel_rust_start`
+//!
+//! Every supported key must accept a valid value and read it back through
+//! `laravel_rust_get_option`, with timeouts given in another unit converted
+//! to the key's own; an invalid value or unknown key must be refused with
+//! `InvalidArgument` and leave the stored value alone. An option not set
+//! reads as its environment variable, else its default. On start the
+//! options must override the environment, and the start JSON the options,
+//! and the environment must be as before once the start returns, so a
+//! second handle never sees the first one's values. The running server must
+//! still apply a setting given only in the start JSON, and an invalid one
+//! must fail the start with `StartFailed`.
+
+mod common;
+
+use std::ffi::{CStr, CString};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::ptr;
+
+use common::free_port;
+use laravel_rust_server::laravel_integration::{
+    laravel_rust_destroy, laravel_rust_free_string, laravel_rust_get_option, laravel_rust_get_stats,
+    laravel_rust_init, laravel_rust_set_option, laravel_rust_start, laravel_rust_stop, LaravelRustServer,
+    LaravelRustStatus,
+};
+use serde_json::Value;
+
+fn set(server: *mut LaravelRustServer, key: &str, value: &str) -> LaravelRustStatus {
+    let (key, value) = (CString::new(key).unwrap(), CString::new(value).unwrap());
+    unsafe { laravel_rust_set_option(server, key.as_ptr(), value.as_ptr()) }
+}
+
+fn get(server: *mut LaravelRustServer, key: &str) -> Option<String> {
+    let key = CString::new(key).unwrap();
+    let mut value = ptr::null_mut();
+    assert_eq!(unsafe { laravel_rust_get_option(server, key.as_ptr(), &mut value) }, LaravelRustStatus::Ok);
+    if value.is_null() {
+        return None;
+    }
+    let text = unsafe { CStr::from_ptr(value) }.to_str().unwrap().to_string();
+    unsafe { laravel_rust_free_string(value) };
+    Some(text)
+}
+
+/// Status line of `GET path` on `port`
+fn status_line(port: &str, path: &str) -> String {
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response.lines().next().unwrap_or_default().to_string()
+}
+
+#[test]
+fn options_are_validated_read_back_and_applied_on_start() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket_path = dir.path().join("worker.sock");
+    let socket_path = socket_path.to_str().unwrap();
+    let port = free_port().to_string();
+    let mut server = ptr::null_mut();
+    assert_eq!(unsafe { laravel_rust_init(&mut server) }, LaravelRustStatus::Ok);
+
+    // The only test in this binary, so the environment is its own
+    std::env::remove_var("SOCKET_PATH");
+    std::env::set_var("SHUTDOWN_NOTIFY_TIMEOUT_MS", "750");
+    std::env::remove_var("SHUTDOWN_DRAIN_TIMEOUT_MS");
+    assert_eq!(get(server, "notify_timeout_ms").as_deref(), Some("750"));
+    assert_eq!(get(server, "drain_timeout_ms").as_deref(), Some("10000"));
+
+    // key, valid value, value read back, invalid values
+    let options = [
+        ("host", "127.0.0.1", "127.0.0.1", &["localhost", "300.0.0.1"][..]),
+        ("port", port.as_str(), port.as_str(), &["0", "65536", "http"][..]),
+        ("socket_path", socket_path, socket_path, &["/no/such/dir/app.sock"][..]),
+        ("log_level", "debug", "debug", &["loud"][..]),
+        ("connection_timeout", "2m", "120", &["0", "soon"][..]),
+        ("read_timeout_ms", "1500ms", "1500", &["0", "1.5s"][..]),
+        ("notify_timeout_ms", "1s", "1000", &["0"][..]),
+        ("drain_timeout_ms", "0", "0", &["later"][..]),
+    ];
+    for (key, value, read_back, invalid) in options {
+        assert_eq!(set(server, key, value), LaravelRustStatus::Ok, "{} = {:?}", key, value);
+        assert_eq!(get(server, key).as_deref(), Some(read_back), "{}", key);
+        for bad in invalid {
+            assert_eq!(set(server, key, bad), LaravelRustStatus::InvalidArgument, "{} = {:?}", key, bad);
+            assert_eq!(get(server, key).as_deref(), Some(read_back), "{} after {:?}", key, bad);
+        }
+    }
+    assert_eq!(set(server, "no_such_option", "1"), LaravelRustStatus::InvalidArgument);
+    let key = CString::new("no_such_option").unwrap();
+    let mut value = ptr::null_mut();
+    let status = unsafe { laravel_rust_get_option(server, key.as_ptr(), &mut value) };
+    assert_eq!(status, LaravelRustStatus::InvalidArgument);
+
+    // Options win over the environment, the start JSON over the options
+    std::env::set_var("HTTP_PORT", "1");
+    std::env::set_var("LOG_LEVEL", "error");
+    std::env::remove_var("SOCKET_READ_TIMEOUT_MS");
+    let json_socket_path = dir.path().join("json.sock");
+    let json_socket_path = json_socket_path.to_str().unwrap();
+    let invalid = CString::new(r#"{"IP_DENY": "not-an-address"}"#).unwrap();
+    assert_eq!(unsafe { laravel_rust_start(server, invalid.as_ptr()) }, LaravelRustStatus::StartFailed);
+    let config = format!(
+        r#"{{"SOCKET_READ_TIMEOUT_MS": 900, "SOCKET_PATH": {:?}, "IP_DENY": "127.0.0.1"}}"#,
+        json_socket_path
+    );
+    let config = CString::new(config).unwrap();
+    assert_eq!(unsafe { laravel_rust_start(server, config.as_ptr()) }, LaravelRustStatus::Ok);
+
+    // Read when the server was built, so applied after the environment is restored
+    assert!(status_line(&port, "/healthz").starts_with("HTTP/1.1 200"));
+    assert!(status_line(&port, "/").starts_with("HTTP/1.1 403"));
+
+    // The environment is left as it was
+    assert_eq!(std::env::var("HTTP_PORT").unwrap(), "1");
+    assert_eq!(std::env::var("LOG_LEVEL").unwrap(), "error");
+    assert_eq!(std::env::var("SHUTDOWN_NOTIFY_TIMEOUT_MS").unwrap(), "750");
+    assert!(std::env::var("SOCKET_READ_TIMEOUT_MS").is_err());
+    assert!(std::env::var("SOCKET_PATH").is_err());
+    assert!(std::env::var("IP_DENY").is_err());
+    let mut other = ptr::null_mut();
+    assert_eq!(unsafe { laravel_rust_init(&mut other) }, LaravelRustStatus::Ok);
+    assert_eq!(get(other, "port").as_deref(), Some("1"));
+    assert_eq!(get(other, "log_level").as_deref(), Some("error"));
+    unsafe { laravel_rust_destroy(other) };
+
+    let mut stats = ptr::null_mut();
+    assert_eq!(unsafe { laravel_rust_get_stats(server, &mut stats) }, LaravelRustStatus::Ok);
+    let parsed: Value = serde_json::from_slice(unsafe { CStr::from_ptr(stats) }.to_bytes()).unwrap();
+    unsafe { laravel_rust_free_string(stats) };
+    assert_eq!(parsed["server"]["address"], format!("127.0.0.1:{}", port));
+    assert_eq!(parsed["server"]["socket_path"], json_socket_path);
+
+    assert_eq!(unsafe { laravel_rust_stop(server) }, LaravelRustStatus::Ok);
+    unsafe { laravel_rust_destroy(server) };
+}
@@ -0,0 +1,76 @@
+//! The C ABI of the shared library against `include/laravel_rust.h`
+//!
+//! Lists the symbols the cdylib built alongside the tests exports with
+//! `nm` and compares the `laravel_rust_*` ones with the functions the
+//! header declares: every declared function must be exported, and nothing
+//! prefixed `laravel_rust_` may be exported without being declared.
+//! `laravel_rust_live_strings` is only expected with `string-registry`.
+//! Needs `nm` from binutils or LLVM.
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Functions only exported with a feature enabled
+const FEATURE_SYMBOLS: [(&str, bool); 1] = [("laravel_rust_live_strings", cfg!(feature = "string-registry"))];
+
+/// The cdylib cargo built next to this test binary
+fn shared_library() -> PathBuf {
+    let name = format!("{}laravel_rust_server{}", std::env::consts::DLL_PREFIX, std::env::consts::DLL_SUFFIX);
+    let exe = std::env::current_exe().unwrap();
+    let deps = exe.parent().unwrap();
+    [deps.join(&name), deps.parent().unwrap().join(&name)]
+        .into_iter()
+        .find(|path| path.is_file())
+        .unwrap_or_else(|| panic!("{} not found next to {}", name, exe.display()))
+}
+
+/// Names of the functions declared in the header
+fn declared() -> BTreeSet<String> {
+    let header = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/include/laravel_rust.h")).unwrap();
+    header
+        .lines()
+        .filter(|line| !line.trim_start().starts_with(['*', '/']))
+        .filter_map(|line| {
+            let start = line.find("laravel_rust_")?;
+            let name = &line[start..line[start..].find('(')? + start];
+            name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_').then(|| name.to_string())
+        })
+        .collect()
+}
+
+/// `laravel_rust_*` symbols the library exports
+fn exported() -> BTreeSet<String> {
+    let output = Command::new("nm")
+        .args(["-g", "--defined-only"])
+        .arg(shared_library())
+        .output()
+        .expect("nm to run");
+    assert!(output.status.success(), "nm failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        // Mach-O prefixes C symbols with an underscore
+        .map(|symbol| symbol.strip_prefix('_').filter(|s| s.starts_with("laravel_rust_")).unwrap_or(symbol))
+        .filter(|symbol| symbol.starts_with("laravel_rust_"))
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn every_declared_function_is_exported_and_nothing_else() {
+    let mut declared = declared();
+    assert!(declared.contains("laravel_rust_init"), "no functions found in the header: {:?}", declared);
+    for (symbol, enabled) in FEATURE_SYMBOLS {
+        assert!(declared.contains(symbol), "{} missing from the header", symbol);
+        if !enabled {
+            declared.remove(symbol);
+        }
+    }
+
+    let exported = exported();
+    let missing: Vec<_> = declared.difference(&exported).collect();
+    let undeclared: Vec<_> = exported.difference(&declared).collect();
+    assert!(missing.is_empty(), "declared in include/laravel_rust.h but not exported: {:?}", missing);
+    assert!(undeclared.is_empty(), "exported but not declared in include/laravel_rust.h: {:?}", undeclared);
+}
@@ -0,0 +1,119 @@
+//! Static files kept in memory and their invalidation
+//!
+//! Files are written to a temporary public directory and read through the
+//! cache. A file overwritten on disk must keep being served from memory
+//! until a rescan or a flush evicts it, after which its new contents and a
+//! new ETag are served. A flush with a prefix must evict only the files
+//! under it, responses taken before a flush must keep their contents, files
+//! over the budget must be served without being kept, and a flush must
+//! re-read a build manifest a rescan-by-signature would miss.
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use laravel_rust_server::file_cache::FileCache;
+use laravel_rust_server::static_cache::AssetManifest;
+
+const MAX_LEN: u64 = 1024 * 1024;
+
+fn write(dir: &Path, name: &str, contents: &str) -> std::path::PathBuf {
+    let path = dir.join(name);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+async fn contents(cache: &FileCache, url_path: &str, path: &Path) -> String {
+    let file = cache.get(url_path, path, MAX_LEN).await.unwrap().expect("the file to be read into memory");
+    String::from_utf8(file.contents.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn changed_files_are_served_from_memory_until_a_rescan() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write(dir.path(), "app.css", "body{}");
+    let cache = FileCache::new(MAX_LEN);
+
+    let first = cache.get("/app.css", &path, MAX_LEN).await.unwrap().unwrap();
+    assert_eq!(cache.usage(), (1, 6));
+    std::fs::write(&path, "body{color:red}").unwrap();
+    assert_eq!(contents(&cache, "/app.css", &path).await, "body{}");
+
+    assert_eq!(cache.rescan(), 1);
+    let second = cache.get("/app.css", &path, MAX_LEN).await.unwrap().unwrap();
+    assert_eq!(&second.contents[..], b"body{color:red}");
+    assert_ne!(first.etag, second.etag);
+    assert!(second.etag.to_str().unwrap().starts_with('"'), "not a strong ETag: {:?}", second.etag);
+
+    // Unchanged files stay; deleted ones go
+    assert_eq!(cache.rescan(), 0);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(cache.rescan(), 1);
+    assert_eq!(cache.usage(), (0, 0));
+}
+
+#[tokio::test]
+async fn a_flush_evicts_the_files_under_its_prefix() {
+    let dir = tempfile::tempdir().unwrap();
+    let app = write(dir.path(), "build/app.js", "one");
+    let vendor = write(dir.path(), "build/vendor.js", "two");
+    let logo = write(dir.path(), "img/logo.svg", "<svg/>");
+    let cache = FileCache::new(MAX_LEN);
+    for (url_path, path) in [("/build/app.js", &app), ("/build/vendor.js", &vendor), ("/img/logo.svg", &logo)] {
+        cache.get(url_path, path, MAX_LEN).await.unwrap();
+    }
+    let in_flight = cache.get("/build/app.js", &app, MAX_LEN).await.unwrap().unwrap();
+
+    std::fs::write(&app, "new").unwrap();
+    std::fs::write(&logo, "<svg></svg>").unwrap();
+    assert_eq!(cache.flush(Some("/build/")), 2);
+    assert_eq!(cache.usage(), (1, 6));
+    assert_eq!(&in_flight.contents[..], b"one");
+    assert_eq!(contents(&cache, "/build/app.js", &app).await, "new");
+    assert_eq!(contents(&cache, "/img/logo.svg", &logo).await, "<svg/>");
+
+    assert_eq!(cache.flush(None), 2);
+    assert_eq!(contents(&cache, "/img/logo.svg", &logo).await, "<svg></svg>");
+}
+
+#[tokio::test]
+async fn files_over_the_budget_are_served_but_not_kept() {
+    let dir = tempfile::tempdir().unwrap();
+    let small = write(dir.path(), "small.txt", "12345");
+    let large = write(dir.path(), "large.txt", "1234567890");
+    let cache = FileCache::new(8);
+
+    assert_eq!(contents(&cache, "/small.txt", &small).await, "12345");
+    assert_eq!(contents(&cache, "/large.txt", &large).await, "1234567890");
+    assert_eq!(cache.usage(), (1, 5));
+
+    // Beyond the length limit the caller reads the file itself
+    assert!(cache.get("/large.txt", &large, 4).await.unwrap().is_none());
+    assert!(FileCache::new(0).get("/small.txt", &small, MAX_LEN).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn a_flush_rereads_manifests_that_look_unchanged() {
+    let dir = tempfile::tempdir().unwrap();
+    let manifest_path = write(dir.path(), "build/manifest.json", r#"{"a.js": {"file": "assets/a-1111.js"}}"#);
+    let modified = SystemTime::now() - Duration::from_secs(60);
+    std::fs::File::options().write(true).open(&manifest_path).unwrap().set_modified(modified).unwrap();
+    let manifest = AssetManifest::load(dir.path());
+    let cache = FileCache::new(MAX_LEN);
+    cache.track_manifest(&manifest);
+    assert!(manifest.is_versioned("/build/assets/a-1111.js", None));
+
+    // Same size and modification time, as a quick redeploy can leave it
+    std::fs::write(&manifest_path, r#"{"a.js": {"file": "assets/a-2222.js"}}"#).unwrap();
+    std::fs::File::options().write(true).open(&manifest_path).unwrap().set_modified(modified).unwrap();
+    assert!(!manifest.reload());
+    assert!(manifest.is_versioned("/build/assets/a-1111.js", None));
+
+    cache.flush(None);
+    assert!(manifest.is_versioned("/build/assets/a-2222.js", None));
+    assert!(!manifest.is_versioned("/build/assets/a-1111.js", None));
+
+    // Manifests of dropped static roots are forgotten
+    drop(manifest);
+    assert_eq!(cache.flush(None), 0);
+}
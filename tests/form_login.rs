@@ -0,0 +1,53 @@
+//! A form login through the HTTP listener
+//!
+//! Posts `application/x-www-form-urlencoded` credentials to the binary, with
+//! a query string naming some of the same fields, against a `MockWorker`
+//! that echoes the frame it got. The worker must see the query and the body
+//! merged into `parameters`, with the body's value where both set a field,
+//! and the raw body still in `content`.
+//! Needs the `test-worker` feature:
+//! `cargo test --features test-worker --test form_login`.
+
+mod common;
+
+use common::Server;
+use laravel_rust_server::mock_worker::{MockWorker, Reply, Rule, Script};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn form_fields_reach_the_worker_as_parameters() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = Script {
+        rules: vec![Rule::path("/login", Reply::Echo)],
+        ..Script::default()
+    };
+    let _worker = MockWorker::start(dir.path().join("worker.sock"), script).unwrap();
+    let server = Server::start(dir.path(), &[]).await;
+
+    let body = "email=jane%40example.com&password=s3cret+pass&remember=on";
+    let response = reqwest::Client::new()
+        .post(format!("{}/login?email=query%40example.com&redirect=%2Fdashboard", server.url))
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let frame: Value = response.json().await.unwrap();
+
+    assert_eq!(frame["method"], "POST");
+    assert_eq!(
+        frame["parameters"],
+        json!({
+            "email": "jane@example.com",
+            "password": "s3cret pass",
+            "remember": "on",
+            "redirect": "/dashboard",
+        }),
+        "{}",
+        frame
+    );
+    assert_eq!(frame["content"], body);
+    assert_eq!(frame["server"]["CONTENT_TYPE"], "application/x-www-form-urlencoded");
+    assert_eq!(frame["server"]["CONTENT_LENGTH"], body.len().to_string());
+}
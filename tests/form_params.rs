@@ -0,0 +1,102 @@
+//! Form bodies decoded into the `parameters` of the worker frame
+//!
+//! Builds the frame for a login form posted to `/login?redirect=/home` the
+//! way the HTTP listener does, and checks that the worker would see the
+//! form fields next to the query string, with the raw body still in
+//! `content`. Further cases cover bracket arrays, `+` and `%XX` decoding,
+//! body values replacing query values, skipped malformed pairs and bodies
+//! of other content types.
+
+use hyper::body::Bytes;
+use laravel_rust_server::server::{request_frame, HttpRequestPayload};
+use serde_json::{json, Value};
+
+const FORM: &str = "application/x-www-form-urlencoded";
+
+/// `parameters` and `content` of the frame for a POST of `body` to `uri`
+fn frame(uri: &str, content_type: &str, body: &str) -> (Value, Value) {
+    let mut payload = HttpRequestPayload::synthetic("POST", uri, Some(Bytes::from(body.to_string())));
+    payload.headers.insert("content-type".to_string(), content_type.to_string());
+    let frame = request_frame(payload, "form-params-test");
+    (frame["parameters"].clone(), frame["content"].clone())
+}
+
+#[test]
+fn login_form_fields_reach_the_worker_next_to_the_query_string() {
+    let login = "_token=abc123&email=jane%40example.com&password=p%26ss+word&remember=on";
+    let (parameters, content) = frame("/login?redirect=/home", FORM, login);
+    assert_eq!(
+        parameters,
+        json!({
+            "redirect": "/home",
+            "_token": "abc123",
+            "email": "jane@example.com",
+            "password": "p&ss word",
+            "remember": "on",
+        })
+    );
+    assert_eq!(content, json!(login), "the raw body is still sent in content");
+}
+
+#[test]
+fn a_charset_parameter_does_not_stop_parsing() {
+    let (parameters, _) = frame("/login", "application/x-www-form-urlencoded; charset=UTF-8", "email=a%40b.c");
+    assert_eq!(parameters, json!({"email": "a@b.c"}));
+}
+
+#[test]
+fn bracketed_names_build_arrays() {
+    let (parameters, _) = frame(
+        "/",
+        FORM,
+        "tags[]=a&tags[]=b&user[name]=Jane&user[roles][]=admin&user[roles][]=editor&matrix[1][]=x",
+    );
+    assert_eq!(
+        parameters,
+        json!({
+            "tags": ["a", "b"],
+            "user": {"name": "Jane", "roles": ["admin", "editor"]},
+            "matrix": {"1": ["x"]},
+        })
+    );
+}
+
+#[test]
+fn appended_values_take_the_next_free_index_as_in_php() {
+    let (parameters, _) = frame("/", FORM, "list[]=a&list[key]=b&list[]=c&list[7]=d&list[]=e");
+    assert_eq!(parameters, json!({"list": {"0": "a", "key": "b", "1": "c", "7": "d", "8": "e"}}));
+}
+
+#[test]
+fn body_values_replace_query_values_of_the_same_name() {
+    let (parameters, _) = frame("/search?page=2&q=query", FORM, "q=body&extra");
+    assert_eq!(parameters, json!({"page": "2", "q": "body", "extra": ""}));
+}
+
+#[test]
+fn malformed_pairs_are_skipped_and_a_stray_percent_is_kept() {
+    let (parameters, _) = frame("/", FORM, "ok=1&=nameless&bad=%FF&open[x=1&50%=off&&last=2");
+    assert_eq!(parameters, json!({"ok": "1", "50%": "off", "last": "2"}));
+}
+
+#[test]
+fn other_bodies_are_not_parsed() {
+    let (parameters, _) = frame("/api?q=1", "application/json", r#"{"email":"a@b.c"}"#);
+    assert_eq!(parameters, json!({"q": "1"}));
+}
+
+#[test]
+fn only_the_first_1000_pairs_are_read() {
+    let many = (0..1200).map(|i| format!("f{}=v", i)).collect::<Vec<_>>().join("&");
+    let (parameters, _) = frame("/", FORM, &many);
+    let fields = parameters.as_object().expect("parameters is an object");
+    assert_eq!(fields.len(), 1000);
+    assert!(!fields.contains_key("f1000"));
+}
+
+#[test]
+fn names_nested_deeper_than_64_levels_are_skipped() {
+    let deep = format!("a{}=x&b=y", "[k]".repeat(65));
+    let (parameters, _) = frame("/", FORM, &deep);
+    assert_eq!(parameters, json!({"b": "y"}));
+}
@@ -0,0 +1,131 @@
+//! Round trip through the gRPC `Bridge` service with the tonic client
+//!
+//! Starts the binary with `GRPC_ENABLED=true` against a mock worker. A
+//! command's data and the worker's answer, failures included, must come
+//! back through `Execute`, malformed requests must be refused with
+//! `INVALID_ARGUMENT`, and `GetStats` must return the stats document with
+//! the calls counted. A call must be shed with `UNAVAILABLE` while an HTTP
+//! request holds the only slot of the adaptive limit, and one in flight at
+//! `SIGTERM` must still be answered before the server exits.
+//! Needs the `grpc` and `test-worker` features:
+//! `cargo test --features grpc,test-worker --test grpc`.
+
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+
+use common::{free_port, Server};
+
+use laravel_rust_server::grpc::proto::bridge_client::BridgeClient;
+use laravel_rust_server::grpc::proto::{CommandRequest, StatsRequest};
+use laravel_rust_server::mock_worker::{MockWorker, Reply, Rule, Script};
+use serde_json::{json, Value};
+use tonic::transport::Channel;
+use tonic::Code;
+
+/// Rule answering the command `name` with `reply`
+fn command(name: &str, reply: Reply) -> Rule {
+    Rule {
+        command: Some(name.to_string()),
+        ..Rule::any(reply)
+    }
+}
+
+/// Start the server with the gRPC listener on and a client connected to it
+async fn start(dir: &Path, settings: &[(&str, &str)]) -> (Server, BridgeClient<Channel>) {
+    let grpc_port = free_port().to_string();
+    let mut all = vec![("GRPC_ENABLED", "true"), ("GRPC_HOST", "127.0.0.1"), ("GRPC_PORT", grpc_port.as_str())];
+    all.extend_from_slice(settings);
+    let server = Server::start(dir, &all).await;
+    let client = BridgeClient::connect(format!("http://127.0.0.1:{}", grpc_port)).await.unwrap();
+    (server, client)
+}
+
+fn request(command: &str, json_data: &str) -> CommandRequest {
+    CommandRequest {
+        command: command.to_string(),
+        json_data: json_data.to_string(),
+    }
+}
+
+/// Value of `grpc_requests_total` for `method` and `code` in a stats document
+fn calls(stats: &Value, method: &str, code: &str) -> f64 {
+    let series = stats["metrics"]["grpc_requests_total"]["series"].as_array().cloned().unwrap_or_default();
+    series
+        .iter()
+        .find(|s| s["labels"] == json!({"method": method, "code": code}))
+        .map_or(0.0, |s| s["value"].as_f64().unwrap())
+}
+
+#[tokio::test]
+async fn commands_and_stats_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = Script {
+        rules: vec![
+            command("report", Reply::Data { data: json!({"rows": 3}) }),
+            command("broken", Reply::Fail { message: "cache store unreachable".to_string() }),
+        ],
+        ..Script::default()
+    };
+    let _worker = MockWorker::start(dir.path().join("worker.sock"), script).unwrap();
+    let (_server, mut client) = start(dir.path(), &[]).await;
+
+    let response = client.execute(request("report", r#"{"month": "2026-09"}"#)).await.unwrap().into_inner();
+    assert!(response.success, "{:?}", response);
+    assert_eq!(serde_json::from_str::<Value>(&response.json_data).unwrap(), json!({"rows": 3}));
+    assert_eq!(response.error, "");
+
+    let response = client.execute(request("broken", "")).await.unwrap().into_inner();
+    assert!(!response.success);
+    assert_eq!(response.error, "cache store unreachable");
+
+    for (command, json_data) in [("", ""), ("report", "[1, 2]"), ("report", "{not json")] {
+        let status = client.execute(request(command, json_data)).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "{:?} {:?}: {}", command, json_data, status.message());
+    }
+
+    let stats = client.get_stats(StatsRequest {}).await.unwrap().into_inner();
+    let stats: Value = serde_json::from_str(&stats.json_data).unwrap();
+    assert!(stats["build"]["version"].is_string(), "{}", stats["build"]);
+    assert_eq!(calls(&stats, "Execute", "Ok"), 2.0);
+    assert_eq!(calls(&stats, "Execute", "InvalidArgument"), 3.0);
+}
+
+#[tokio::test]
+async fn commands_share_the_http_limit_and_finish_at_shutdown() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = Script {
+        rules: vec![
+            Rule::path("/slow", Reply::respond(200, "slow")).delayed(Duration::from_millis(800)),
+            command("warm", Reply::Data { data: json!({"warmed": true}) }).delayed(Duration::from_millis(800)),
+        ],
+        ..Script::default()
+    };
+    let _worker = MockWorker::start(dir.path().join("worker.sock"), script).unwrap();
+    let settings = [
+        ("ADAPTIVE_CONCURRENCY", "true"),
+        ("ADAPTIVE_CONCURRENCY_INITIAL", "1"),
+        ("ADAPTIVE_CONCURRENCY_MAX", "1"),
+    ];
+    let (mut server, mut client) = start(dir.path(), &settings).await;
+
+    // An HTTP request holds the only slot
+    let slow = tokio::spawn(reqwest::get(format!("{}/slow", server.url)));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let status = client.execute(request("ping", "")).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable, "{}", status.message());
+    assert_eq!(slow.await.unwrap().unwrap().status(), 200);
+
+    // A command in flight at SIGTERM is answered before the server exits
+    let mut warming = client.clone();
+    let warm = tokio::spawn(async move { warming.execute(request("warm", "")).await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(unsafe { libc::kill(server.child.id() as i32, libc::SIGTERM) }, 0);
+    let response = warm.await.unwrap().unwrap().into_inner();
+    assert!(response.success);
+    assert_eq!(serde_json::from_str::<Value>(&response.json_data).unwrap(), json!({"warmed": true}));
+
+    let status = server.wait_exit().await;
+    assert!(status.success(), "{:?}", status);
+}
@@ -0,0 +1,112 @@
+#!/usr/bin/env bash
+# Spoofable headers reach Laravel only from trusted proxies.
+#
+#   cargo build --release
+#   tests/header_scrub.sh ./target/release/laravel-rust-server
+#
+# Starts the server with a stand-in worker socket, TRUSTED_PROXIES=127.0.0.2
+# and RECORD_ENABLED=true, then sends the same forwarding, identity and
+# internal headers from 127.0.0.1 (untrusted) and from 127.0.0.2 (trusted).
+# The recordings show the headers that were sent to the worker: from the
+# untrusted peer X-Forwarded-For must be its own address and the others
+# must be gone; from the trusted one they must be passed on. The internal
+# header must be gone from both. Needs a loopback interface answering on
+# 127.0.0.2, as on Linux. HTTP_PORT can be overridden from the environment.
+
+set -euo pipefail
+
+BINARY=${1:?usage: $0 path/to/laravel-rust-server}
+BINARY=$(cd "$(dirname "$BINARY")" && pwd)/$(basename "$BINARY")
+HTTP_PORT=${HTTP_PORT:-18080}
+URL=http://127.0.0.1:$HTTP_PORT
+
+WORK=$(mktemp -d)
+SERVER_PID=
+WORKER_PID=
+FAILED=0
+cleanup() {
+    for pid in $SERVER_PID $WORKER_PID; do
+        kill "$pid" 2>/dev/null || true
+        wait "$pid" 2>/dev/null || true
+    done
+    rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+python3 -c '
+import socket, sys
+s = socket.socket(socket.AF_UNIX)
+s.bind(sys.argv[1])
+s.listen(128)
+while True:
+    s.accept()[0].close()
+' "$WORK/worker.sock" &
+WORKER_PID=$!
+(
+    cd "$WORK"
+    export HTTP_HOST=127.0.0.1 HTTP_PORT SOCKET_PATH="$WORK/worker.sock" LARAVEL_PATH="$WORK"
+    export LOG_DIR="$WORK/logs" PHP_WORKER_AUTO_RESTART=false TRUSTED_PROXIES=127.0.0.2
+    export RECORD_ENABLED=true RECORD_DIR="$WORK/recordings"
+    exec "$BINARY"
+) >"$WORK/server.out" 2>&1 &
+SERVER_PID=$!
+for _ in $(seq 50); do
+    [ "$(curl -s -o /dev/null -w '%{http_code}' "$URL/readyz")" = 200 ] && break
+    sleep 0.2
+done
+
+check() {
+    local name=$1 ok=$2
+    if [ "$ok" = true ]; then
+        echo "ok - $name"
+    else
+        echo "FAIL: $name"
+        FAILED=1
+    fi
+}
+send() {
+    curl -s -o /dev/null --interface "$1" \
+        -H 'X-Forwarded-For: 10.0.0.1' -H 'X-Forwarded-Proto: https' -H 'X-Real-IP: 10.0.0.1' \
+        -H 'X-Request-Id: spoofed-id' -H 'X-Internal-Auth: letmein' "$URL$2"
+}
+# Value of header $2 in the recorded request to path $1, or "absent"
+recorded() {
+    python3 -c '
+import glob, json, sys
+for file in glob.glob(sys.argv[1] + "/*.json"):
+    request = json.load(open(file))["request"]
+    if request["uri"] == sys.argv[2]:
+        print(request["headers"].get(sys.argv[3], "absent"))
+        break
+else:
+    print("no recording")
+' "$WORK/recordings" "$1" "$2"
+}
+
+send 127.0.0.1 /from-client
+send 127.0.0.2 /from-proxy
+sleep 0.5
+
+check "an untrusted peer's X-Forwarded-For is replaced (got $(recorded /from-client x-forwarded-for))" \
+    "$([ "$(recorded /from-client x-forwarded-for)" = 127.0.0.1 ] && echo true || echo false)"
+for header in x-forwarded-proto x-real-ip x-internal-auth; do
+    check "$header from an untrusted peer is removed" \
+        "$([ "$(recorded /from-client "$header")" = absent ] && echo true || echo false)"
+done
+check "an untrusted peer's X-Request-Id is not used" \
+    "$([ "$(recorded /from-client x-request-id)" != spoofed-id ] && echo true || echo false)"
+
+check "a trusted proxy's X-Forwarded-For is kept (got $(recorded /from-proxy x-forwarded-for))" \
+    "$([ "$(recorded /from-proxy x-forwarded-for)" = 10.0.0.1 ] && echo true || echo false)"
+check "a trusted proxy's X-Forwarded-Proto is kept" \
+    "$([ "$(recorded /from-proxy x-forwarded-proto)" = https ] && echo true || echo false)"
+check "a trusted proxy's X-Request-Id is used" \
+    "$([ "$(recorded /from-proxy x-request-id)" = spoofed-id ] && echo true || echo false)"
+check "X-Internal-Auth from a trusted proxy is removed" \
+    "$([ "$(recorded /from-proxy x-internal-auth)" = absent ] && echo true || echo false)"
+
+if [ "$FAILED" -ne 0 ]; then
+    tail -n 20 "$WORK/server.out"
+    exit 1
+fi
+echo "ok - header scrubbing"
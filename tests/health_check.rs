@@ -0,0 +1,90 @@
+//! The `check` subcommand used by container health checks
+//!
+//! Runs `laravel-rust-server check` against a server that is ready and a
+//! port nobody listens on, and `check --bridge-only` against a mock worker,
+//! a missing socket and a worker that never answers. Healthy checks must
+//! exit 0 and print one line to stdout, unhealthy ones exit 1 with one line
+//! on stderr, within the timeout. No check may write a log file or remove
+//! the worker's socket.
+//! Needs the `test-worker` feature:
+//! `cargo test --features test-worker --test health_check`.
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+use std::time::{Duration, Instant};
+
+use common::{command, free_port, Server};
+use laravel_rust_server::mock_worker::{MockWorker, Reply, Rule, Script};
+
+/// Run `check` with `args`, checking the one-line result and that nothing was logged
+fn check(dir: &Path, port: u16, args: &[&str]) -> (bool, String, Duration) {
+    let started = Instant::now();
+    let Output { status, stdout, stderr } = command(dir, port).arg("check").args(args).output().unwrap();
+    let elapsed = started.elapsed();
+    let (stdout, stderr) = (String::from_utf8(stdout).unwrap(), String::from_utf8(stderr).unwrap());
+    let (line, other) = if status.success() { (&stdout, &stderr) } else { (&stderr, &stdout) };
+    assert_eq!(line.lines().count(), 1, "not one line: {:?}", line);
+    assert_eq!(other, "", "{:?}", args);
+    assert!(matches!(status.code(), Some(0 | 1)), "{:?}", status);
+    assert!(!dir.join("logs").exists(), "check wrote to LOG_DIR");
+    (status.success(), line.trim_end().to_string(), elapsed)
+}
+
+#[test]
+fn a_ready_server_is_healthy_and_a_closed_port_is_not() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let server_dir = tempfile::tempdir().unwrap();
+    let socket_path = server_dir.path().join("worker.sock");
+    let _worker = runtime.block_on(async { MockWorker::start(socket_path, Script::default()) }).unwrap();
+    let port = free_port();
+    let _server = Server::spawn(command(server_dir.path(), port), port, server_dir.path().join("server.log"));
+
+    let until = Instant::now() + Duration::from_secs(10);
+    let (healthy, line, _) = loop {
+        let result = check(dir.path(), port, &[]);
+        if result.0 || Instant::now() > until {
+            break result;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    assert!(healthy, "{}", line);
+    assert_eq!(line, format!("healthy: http://127.0.0.1:{}/readyz returned 200 OK", port));
+
+    let (healthy, line, elapsed) = check(dir.path(), free_port(), &["--timeout", "1s"]);
+    assert!(!healthy);
+    assert!(line.starts_with("unhealthy: http://127.0.0.1:"), "{}", line);
+    assert!(elapsed < Duration::from_secs(2), "took {:?}", elapsed);
+}
+
+#[test]
+fn bridge_only_pings_the_worker_socket() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let socket_path = dir.path().join("worker.sock");
+    let port = free_port();
+
+    // Nothing at SOCKET_PATH yet
+    let (healthy, line, _) = check(dir.path(), port, &["--bridge-only"]);
+    assert!(!healthy);
+    assert!(line.starts_with("unhealthy: "), "{}", line);
+
+    let worker = runtime.block_on(async { MockWorker::start(&socket_path, Script::default()) }).unwrap();
+    let (healthy, line, _) = check(dir.path(), port, &["--bridge-only"]);
+    assert!(healthy, "{}", line);
+    assert_eq!(line, format!("healthy: PHP worker at {} answered ping", socket_path.display()));
+    assert!(socket_path.exists(), "check removed the worker's socket");
+    drop(worker);
+
+    let script = Script {
+        rules: vec![Rule::any(Reply::Hang)],
+        ..Script::default()
+    };
+    let _worker = runtime.block_on(async { MockWorker::start(&socket_path, script) }).unwrap();
+    let (healthy, line, elapsed) = check(dir.path(), port, &["--bridge-only", "--timeout", "300ms"]);
+    assert!(!healthy);
+    assert_eq!(line, "unhealthy: no answer within 300ms");
+    assert!(elapsed < Duration::from_secs(2), "took {:?}", elapsed);
+}
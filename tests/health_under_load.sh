@@ -0,0 +1,94 @@
+#!/usr/bin/env bash
+# Health checks stay up while the bridge sheds load.
+#
+#   cargo build --release --features chaos
+#   tests/health_under_load.sh ./target/release/laravel-rust-server
+#
+# Starts the server with a stand-in worker socket, a bridge limit of two
+# requests and chaos latency on requests carrying X-Load, fills the limit
+# with such requests and checks that further requests are shed while
+# /healthz, /readyz and the PRIORITY_PATHS entry /up are still served
+# without waiting. The stand-in worker closes every connection, so /up
+# fails at the worker; only that it was not shed and did not queue is
+# checked. HTTP_PORT can be overridden from the environment.
+
+set -euo pipefail
+
+BINARY=${1:?usage: $0 path/to/laravel-rust-server}
+BINARY=$(cd "$(dirname "$BINARY")" && pwd)/$(basename "$BINARY")
+HTTP_PORT=${HTTP_PORT:-18080}
+URL=http://127.0.0.1:$HTTP_PORT
+
+WORK=$(mktemp -d)
+SERVER_PID=
+WORKER_PID=
+LOAD_PIDS=
+FAILED=0
+cleanup() {
+    for pid in $LOAD_PIDS $SERVER_PID $WORKER_PID; do
+        kill "$pid" 2>/dev/null || true
+        wait "$pid" 2>/dev/null || true
+    done
+    rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+python3 -c '
+import socket, sys
+s = socket.socket(socket.AF_UNIX)
+s.bind(sys.argv[1])
+s.listen(128)
+while True:
+    s.accept()[0].close()
+' "$WORK/worker.sock" &
+WORKER_PID=$!
+(
+    cd "$WORK"
+    export HTTP_HOST=127.0.0.1 HTTP_PORT SOCKET_PATH="$WORK/worker.sock" LARAVEL_PATH="$WORK"
+    export LOG_DIR="$WORK/logs" PHP_WORKER_AUTO_RESTART=false
+    export ADAPTIVE_CONCURRENCY=true ADAPTIVE_CONCURRENCY_INITIAL=2 ADAPTIVE_CONCURRENCY_MIN=2 ADAPTIVE_CONCURRENCY_MAX=2
+    export SOCKET_MAX_CONCURRENT_FRAMES=2 PRIORITY_PATHS=/up
+    export CHAOS_ENABLED=true CHAOS_HEADER=x-load CHAOS_LATENCY_RATE=1 CHAOS_LATENCY_MS=3000
+    exec "$BINARY"
+) >"$WORK/server.out" 2>&1 &
+SERVER_PID=$!
+for _ in $(seq 50); do
+    [ "$(curl -s -o /dev/null -w '%{http_code}' "$URL/readyz")" = 200 ] && break
+    sleep 0.2
+done
+
+# Two slow requests fill the limit
+for _ in 1 2; do
+    curl -s -o /dev/null -H 'X-Load: 1' "$URL/slow" &
+    LOAD_PIDS="$LOAD_PIDS $!"
+done
+sleep 0.5
+
+check() {
+    local name=$1 ok=$2
+    if [ "$ok" = true ]; then
+        echo "ok - $name"
+    else
+        echo "FAIL: $name"
+        FAILED=1
+    fi
+}
+
+body=$(curl -s -H 'X-Load: 1' "$URL/other")
+check "requests over the limit are shed" "$([[ $body == *overloaded* ]] && echo true || echo false)"
+for path in /healthz /readyz; do
+    code=$(curl -s -o /dev/null -w '%{http_code}' --max-time 1 "$URL$path")
+    check "$path answers 200 under load (got $code)" "$([ "$code" = 200 ] && echo true || echo false)"
+done
+result=$(curl -s -w ' %{time_total}' --max-time 2 "$URL/up" || true)
+check "/up is not shed" "$([[ $result != *overloaded* ]] && echo true || echo false)"
+check "/up does not wait for the limit (${result##* }s)" \
+    "$(awk -v t="${result##* }" 'BEGIN { print (t != "" && t < 1) ? "true" : "false" }')"
+body=$(curl -s "$URL/up/status")
+check "other paths are shed like any request" "$([[ $body == *overloaded* ]] && echo true || echo false)"
+
+if [ "$FAILED" -ne 0 ]; then
+    tail -n 20 "$WORK/server.out"
+    exit 1
+fi
+echo "ok - health checks under load"
@@ -0,0 +1,138 @@
+#!/usr/bin/env bash
+# IP allow/deny lists: which client gets 403 on which path.
+#
+#   cargo build --release
+#   tests/ip_filter.sh ./target/release/laravel-rust-server
+#
+# Starts the server with a stand-in worker socket under a few IP_ALLOW,
+# IP_DENY, IP_RULES and IP_PRECEDENCE configurations and checks which
+# requests are denied. Clients are simulated with X-Forwarded-For from a
+# trusted loopback proxy. Requests that pass end at the stand-in worker,
+# so only 403 versus anything else is checked. HTTP_PORT can be overridden
+# from the environment.
+
+set -euo pipefail
+
+BINARY=${1:?usage: $0 path/to/laravel-rust-server}
+BINARY=$(cd "$(dirname "$BINARY")" && pwd)/$(basename "$BINARY")
+HTTP_PORT=${HTTP_PORT:-18080}
+
+WORK=$(mktemp -d)
+SERVER_PID=
+WORKER_PID=
+FAILED=0
+stop_server() {
+    for pid in $SERVER_PID $WORKER_PID; do
+        kill "$pid" 2>/dev/null || true
+        wait "$pid" 2>/dev/null || true
+    done
+    SERVER_PID=
+    WORKER_PID=
+}
+cleanup() {
+    stop_server
+    rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+# start_server VAR=value... - run the server with only these IP settings
+start_server() {
+    stop_server
+    # Readiness only needs the worker socket to accept connections; the
+    # server removes the socket file when it stops, so start a fresh one
+    rm -f "$WORK/worker.sock"
+    python3 -c '
+import socket, sys
+s = socket.socket(socket.AF_UNIX)
+s.bind(sys.argv[1])
+s.listen(128)
+while True:
+    s.accept()[0].close()
+' "$WORK/worker.sock" &
+    WORKER_PID=$!
+    (
+        cd "$WORK"
+        export HTTP_HOST=127.0.0.1 HTTP_PORT SOCKET_PATH="$WORK/worker.sock" LARAVEL_PATH="$WORK"
+        export LOG_DIR="$WORK/logs" PHP_WORKER_AUTO_RESTART=false
+        for setting in "$@"; do
+            export "$setting"
+        done
+        exec "$BINARY"
+    ) >"$WORK/server.out" 2>&1 &
+    SERVER_PID=$!
+    for _ in $(seq 50); do
+        [ "$(curl -s -o /dev/null -w '%{http_code}' "http://127.0.0.1:$HTTP_PORT/readyz")" = 200 ] && return
+        sleep 0.2
+    done
+    echo "FAIL: server did not start with $*"
+    tail -n 20 "$WORK/server.out"
+    exit 1
+}
+
+# expect denied|allowed PATH [X-Forwarded-For]
+expect() {
+    local want=$1 path=$2 forwarded=${3:-}
+    local code got
+    if [ -n "$forwarded" ]; then
+        code=$(curl -s -o /dev/null -w '%{http_code}' -H "X-Forwarded-For: $forwarded" "http://127.0.0.1:$HTTP_PORT$path")
+    else
+        code=$(curl -s -o /dev/null -w '%{http_code}' "http://127.0.0.1:$HTTP_PORT$path")
+    fi
+    got=allowed
+    [ "$code" = 403 ] && got=denied
+    if [ "$got" = "$want" ]; then
+        echo "ok - $want: $path from ${forwarded:-peer}"
+    else
+        echo "FAIL: $path from ${forwarded:-peer} was $got ($code), expected $want"
+        FAILED=1
+    fi
+}
+
+# An empty config changes nothing
+start_server
+expect allowed / 192.0.2.1
+expect allowed /admin 10.0.5.7
+
+# Global lists, path rules, IPv6 and the default deny precedence
+start_server \
+    TRUSTED_PROXIES=127.0.0.0/8,::1 \
+    IP_ALLOW=10.0.0.0/8,2001:db8::/32,10.9.9.0/24 \
+    IP_DENY=10.0.5.0/24,2001:db8:bad::/48,10.9.9.0/24 \
+    'IP_RULES=[{"prefix":"/admin","allow":["10.1.0.0/16"],"deny":["10.1.2.0/24"]},{"prefix":"/admin/public","deny":["10.200.0.0/16"]}]' \
+    'IP_DENIED_BODY={"error":"office network only"}'
+expect allowed / 10.0.6.7
+expect denied / 10.0.5.7          # the narrower deny network wins
+expect denied / 192.0.2.1         # not in the allow list
+expect allowed / 2001:db8::1
+expect denied / 2001:db8:bad::1
+expect denied / 2001:db9::1
+expect denied / 10.9.9.1          # same network in both lists, deny first
+expect allowed /admin/users 10.1.1.1
+expect denied /admin 10.1.2.3
+expect denied /admin 10.0.6.7     # the rule's allow list replaces the global one
+expect allowed /administrator 10.0.6.7
+expect denied /admin/public 10.200.1.1
+expect allowed /admin/public 10.0.6.7  # deny-only rule falls through to the global lists
+expect denied /app.css 192.0.2.1  # static files are covered too
+expect allowed /healthz 192.0.2.1
+expect allowed / "192.0.2.1, 10.0.6.7"      # rightmost untrusted hop is the client
+expect allowed / "10.0.6.7, 127.0.0.5"      # trusted hops are skipped
+expect denied / "10.0.6.7, 192.0.2.1"
+expect denied / "[2001:db8:bad::1]:443"
+body=$(curl -s -D "$WORK/headers" -H "X-Forwarded-For: 192.0.2.1" "http://127.0.0.1:$HTTP_PORT/")
+if [ "$body" = '{"error":"office network only"}' ] && grep -qi '^content-type: application/json' "$WORK/headers"; then
+    echo "ok - IP_DENIED_BODY sent as JSON"
+else
+    echo "FAIL: unexpected denial body: $body"
+    FAILED=1
+fi
+
+# Allow precedence; X-Forwarded-For from an untrusted peer is ignored
+start_server IP_ALLOW=127.0.0.0/8 IP_DENY=127.0.0.0/8 IP_PRECEDENCE=allow
+expect allowed /
+expect allowed / 192.0.2.1
+
+if [ "$FAILED" -ne 0 ]; then
+    exit 1
+fi
+echo "ok - IP filter"
@@ -0,0 +1,124 @@
+//! Rotation and retention of `server.log`
+//!
+//! `LOG_ROTATION` values must parse to their policy. Several threads then
+//! write through `tracing_appender::non_blocking` into a file rotating at
+//! 1 KB, as the binary does: rollovers must happen while they write, no
+//! file may grow past the limit or hold a split line, only `LOG_MAX_FILES`
+//! rotated files may be left, older ones included, and the last line must
+//! reach the disk once the writer guard is dropped. A daily log left over
+//! from an earlier day must be rotated when it is opened.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use laravel_rust_server::log_rotation::{RollingFile, RotationPolicy};
+
+const LIMIT: u64 = 1024;
+
+/// Names of the files in `dir`, sorted
+fn files(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn rotation_values_parse_to_their_policy() {
+    let policies = [
+        ("daily", Some(RotationPolicy::Daily)),
+        (" Hourly ", Some(RotationPolicy::Hourly)),
+        ("never", Some(RotationPolicy::Never)),
+        ("size:100MB", Some(RotationPolicy::Size(100 * 1024 * 1024))),
+        ("size:512k", Some(RotationPolicy::Size(512 * 1024))),
+        ("size:4096", Some(RotationPolicy::Size(4096))),
+        ("size:0", None),
+        ("size:", None),
+        ("weekly", None),
+    ];
+    for (value, policy) in policies {
+        assert_eq!(RotationPolicy::parse(value), policy, "{:?}", value);
+    }
+}
+
+#[test]
+fn concurrent_writers_roll_over_without_losing_or_splitting_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    // Left over from a run with a larger LOG_MAX_FILES
+    for n in 4..=6 {
+        fs::write(dir.path().join(format!("server.log.{}", n)), "stale\n").unwrap();
+    }
+
+    let file = RollingFile::open(dir.path(), "server.log", RotationPolicy::Size(LIMIT), 3).unwrap();
+    let (writer, guard) = tracing_appender::non_blocking(file);
+    let threads: Vec<_> = (0..4)
+        .map(|thread| {
+            let mut writer = writer.clone();
+            std::thread::spawn(move || {
+                for line in 0..200 {
+                    // One write per line, as the fmt layer writes each event
+                    let line = format!("thread {} line {:03} {}\n", thread, line, "x".repeat(40));
+                    writer.write_all(line.as_bytes()).unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    writer.clone().write_all(b"last line\n").unwrap();
+    drop(guard);
+
+    assert_eq!(files(dir.path()), ["server.log", "server.log.1", "server.log.2", "server.log.3"]);
+    for name in files(dir.path()) {
+        let contents = fs::read_to_string(dir.path().join(&name)).unwrap();
+        assert!(contents.len() as u64 <= LIMIT, "{} has {} bytes", name, contents.len());
+        assert!(contents.ends_with('\n'), "{} ends mid-line", name);
+        for line in contents.lines().filter(|line| *line != "last line") {
+            let fields: Vec<&str> = line.split(' ').collect();
+            assert_eq!(fields.len(), 5, "split line in {}: {:?}", name, line);
+            assert_eq!((fields[0], fields[2], fields[4].len()), ("thread", "line", 40), "{}: {:?}", name, line);
+        }
+    }
+    let current = fs::read_to_string(dir.path().join("server.log")).unwrap();
+    assert!(current.ends_with("last line\n"), "last line lost: {:?}", current);
+}
+
+#[test]
+fn a_log_from_an_earlier_day_is_rotated_on_open() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("server.log");
+    fs::write(&path, "yesterday\n").unwrap();
+    let two_days_ago = SystemTime::now() - Duration::from_secs(2 * 86_400);
+    File::options().write(true).open(&path).unwrap().set_modified(two_days_ago).unwrap();
+
+    let mut file = RollingFile::open(dir.path(), "server.log", RotationPolicy::Daily, 7).unwrap();
+    writeln!(file, "today").unwrap();
+    file.flush().unwrap();
+    assert_eq!(fs::read_to_string(dir.path().join("server.log.1")).unwrap(), "yesterday\n");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "today\n");
+
+    // Today's log is appended to on the next start
+    drop(file);
+    let mut file = RollingFile::open(dir.path(), "server.log", RotationPolicy::Daily, 7).unwrap();
+    writeln!(file, "restarted").unwrap();
+    file.flush().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "today\nrestarted\n");
+    assert_eq!(files(dir.path()), ["server.log", "server.log.1"]);
+}
+
+#[test]
+fn no_rotated_files_are_kept_with_a_limit_of_zero() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut file = RollingFile::open(dir.path(), "server.log", RotationPolicy::Size(16), 0).unwrap();
+    for line in ["first line\n", "second line\n", "third line\n"] {
+        file.write_all(line.as_bytes()).unwrap();
+    }
+    file.flush().unwrap();
+    assert_eq!(files(dir.path()), ["server.log"]);
+    assert_eq!(fs::read_to_string(dir.path().join("server.log")).unwrap(), "third line\n");
+}
@@ -0,0 +1,272 @@
+//! What the server logs, as a log pipeline sees it
+//!
+//! Each test installs a subscriber with the `LOG_FORMAT=json` layer writing
+//! to memory, runs the code that logs, and parses every captured line as
+//! JSON. A failed request must be logged as one object with the level,
+//! target and message, its fields at the top level, the request fields
+//! under `span` and the error chain as a list. Events of requests handled
+//! concurrently must each carry the id of their own request, and the last
+//! one its status and duration. Requests to `QUIET_PATHS` must log nothing
+//! above trace level and be counted apart from the other requests.
+//!
+//! The binary is also started with `LOG_FORMAT=json` and stopped with
+//! SIGTERM: its startup, worker and shutdown steps must reach `server.log`
+//! as events with their fields, and nothing may bypass the subscriber by
+//! printing to stdout.
+
+mod common;
+
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use common::{command, free_port, Server};
+use hyper::{Body, Request, StatusCode};
+use laravel_rust_server::errors::{handle_error_response, ErrorDetail, JsonErrorRenderer, ServerError};
+use laravel_rust_server::log_format::json_layer;
+use laravel_rust_server::metrics::metrics;
+use laravel_rust_server::request_context::{QuietPaths, RequestContext};
+use serde_json::{json, Value};
+use tracing::subscriber::DefaultGuard;
+use tracing::Instrument;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Layer;
+
+/// Log lines written to memory
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Captured {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+impl Captured {
+    /// Every line written so far, each of which must be a JSON object
+    fn events(&self) -> Vec<Value> {
+        let output = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+        output
+            .lines()
+            .map(|line| {
+                let event: Value = serde_json::from_str(line).unwrap_or_else(|e| panic!("not JSON ({}): {}", e, line));
+                assert!(event.is_object(), "not an object: {}", line);
+                event
+            })
+            .collect()
+    }
+
+    /// The only event logged with `message`
+    fn event(&self, message: &str) -> Value {
+        let mut events: Vec<Value> = self.events().into_iter().filter(|event| event["message"] == message).collect();
+        assert_eq!(events.len(), 1, "{:?} logged {} times: {:?}", message, events.len(), self.events());
+        events.remove(0)
+    }
+}
+
+/// Send everything logged on this thread, at every level, to memory until the guard is dropped
+fn capture() -> (Captured, DefaultGuard) {
+    capture_at(LevelFilter::TRACE)
+}
+
+/// Send what is logged on this thread at `level` or above to memory until the guard is dropped
+fn capture_at(level: LevelFilter) -> (Captured, DefaultGuard) {
+    let captured = Captured::default();
+    let subscriber = tracing_subscriber::registry().with(json_layer(captured.clone()).with_filter(level));
+    (captured, tracing::subscriber::set_default(subscriber))
+}
+
+/// Value of the counter series with exactly `labels`, 0 before it is first counted
+fn counter(name: &str, labels: Value) -> f64 {
+    let snapshot = metrics().snapshot();
+    let series = snapshot[name]["series"].as_array().cloned().unwrap_or_default();
+    series.iter().find(|series| series["labels"] == labels).and_then(|series| series["value"].as_f64()).unwrap_or(0.0)
+}
+
+fn context(path: &str, request_id: &str) -> RequestContext {
+    let request = Request::builder().uri(path).header("x-request-id", request_id).body(Body::empty()).unwrap();
+    RequestContext::new(&request, IpAddr::V4(Ipv4Addr::LOCALHOST))
+}
+
+#[test]
+fn json_lines_carry_the_event_the_request_and_the_error_chain() {
+    let (captured, _guard) = capture();
+    let context = context("/api/users", "req-json");
+    let error = anyhow::Error::from(io::Error::new(io::ErrorKind::TimedOut, "read timed out"))
+        .context(ServerError::BridgeTimeout("PHP worker timed out".to_string()));
+    let message = error.to_string();
+    context.span().in_scope(|| handle_error_response(error, &context, ErrorDetail::Production, &JsonErrorRenderer));
+
+    let event = captured.event("Request failed");
+    assert!(event["timestamp"].as_str().is_some_and(|t| t.ends_with('Z')), "{}", event);
+    assert_eq!(event["level"], "ERROR");
+    assert_eq!(event["target"], "laravel_rust_server::errors");
+    assert_eq!(event["kind"], "bridge_timeout");
+    assert_eq!(event["component"], "bridge");
+    assert_eq!(event["status"], 504);
+    assert!(event["elapsed_ms"].is_u64(), "{}", event);
+    assert_eq!(event["error"], json!({"message": message, "sources": ["read timed out"]}));
+    assert_eq!(
+        event["span"],
+        json!({"request_id": "req-json", "method": "GET", "path": "/api/users", "client_ip": "127.0.0.1"})
+    );
+}
+
+#[tokio::test]
+async fn events_of_interleaved_requests_carry_their_own_request() {
+    let (captured, _guard) = capture();
+    let handle = |path: &'static str, request_id: &'static str, error: ServerError| {
+        let context = context(path, request_id);
+        let span = context.span();
+        async move {
+            tracing::debug!("Received request");
+            tokio::task::yield_now().await;
+            let response = handle_error_response(error.into(), &context, ErrorDetail::Production, &JsonErrorRenderer);
+            tokio::task::yield_now().await;
+            context.finish(response.status());
+        }
+        .instrument(span)
+    };
+    tokio::join!(
+        handle("/orders", "req-a", ServerError::UpstreamMalformed("status 999".to_string())),
+        handle("/users", "req-b", ServerError::Application("Division by zero".to_string())),
+    );
+
+    let events = captured.events();
+    assert_eq!(events.len(), 6, "{:?}", events);
+    // Interleaved, so grouping by request id is what tells them apart
+    assert_eq!(events[0]["span"]["request_id"], "req-a");
+    assert_eq!(events[1]["span"]["request_id"], "req-b");
+    let requests = [
+        ("req-a", "/orders", StatusCode::BAD_GATEWAY),
+        ("req-b", "/users", StatusCode::INTERNAL_SERVER_ERROR),
+    ];
+    for (request_id, path, status) in requests {
+        let request: Vec<&Value> = events.iter().filter(|event| event["span"]["request_id"] == request_id).collect();
+        let messages: Vec<&Value> = request.iter().map(|event| &event["message"]).collect();
+        assert_eq!(messages, ["Received request", "Request failed", "Request completed"], "{}", request_id);
+        assert!(request.iter().all(|event| event["span"]["path"] == path), "{:?}", request);
+
+        let completed = &request[2]["span"];
+        assert_eq!(completed["status"], status.as_u16(), "{}", completed);
+        assert!(completed["duration_ms"].is_u64(), "{}", completed);
+        assert!(request[0]["span"].get("status").is_none(), "status before the response: {}", request[0]);
+    }
+}
+
+#[test]
+fn quiet_paths_log_only_at_trace_level_and_are_counted_apart() {
+    let quiet_paths = QuietPaths::parse("/healthz,/internal/probe/*").unwrap();
+    let finish = |path: &str, request_id: &str| {
+        let mut context = context(path, request_id);
+        context.quiet = quiet_paths.matching(path).map(str::to_string);
+        context.span().in_scope(|| context.finish(StatusCode::NO_CONTENT));
+    };
+    let quiet = json!({"path": "/internal/probe/*", "status": "204"});
+    let quiet_before = counter("http_quiet_requests_total", quiet.clone());
+    let requests_before = counter("http_requests_total", json!({"status": "204"}));
+
+    // At debug level, as with LOG_LEVEL=debug, only the normal request shows
+    let (captured, guard) = capture_at(LevelFilter::DEBUG);
+    finish("/internal/probe/db", "req-quiet");
+    finish("/api/users", "req-normal");
+    drop(guard);
+    let event = captured.event("Request completed");
+    assert_eq!(event["span"]["request_id"], "req-normal");
+    assert_eq!(event["span"]["status"], 204);
+    assert_eq!(captured.events().len(), 1, "{:?}", captured.events());
+
+    // At trace level the quiet request is there, with its span
+    let (captured, guard) = capture();
+    finish("/internal/probe/db", "req-quiet");
+    drop(guard);
+    let event = captured.event("Request completed");
+    assert_eq!(event["level"], "TRACE");
+    assert_eq!(event["span"]["request_id"], "req-quiet");
+    assert_eq!(event["span"]["status"], 204);
+
+    assert_eq!(counter("http_quiet_requests_total", quiet), quiet_before + 2.0);
+    assert_eq!(counter("http_requests_total", json!({"status": "204"})), requests_before + 1.0);
+}
+
+/// JSON events of a log file, each of which must be an object
+fn log_events(path: &Path) -> Vec<Value> {
+    let output = std::fs::read_to_string(path).unwrap_or_default();
+    output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("not JSON ({}): {}", e, line)))
+        .collect()
+}
+
+#[test]
+fn startup_and_shutdown_steps_reach_the_log_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let port = free_port();
+    let socket_path = dir.path().join("worker.sock");
+    let stderr = dir.path().join("stderr.log");
+    let stdout = dir.path().join("stdout.log");
+    // No artisan in LARAVEL_PATH and no worker on the socket
+    let mut command = command(dir.path(), port);
+    command
+        .env("LOG_FORMAT", "json")
+        .env("SOCKET_WAIT_MAX_ATTEMPTS", "2")
+        .stdout(std::fs::File::create(&stdout).unwrap());
+    let mut server = Server::spawn_with_stdout(command, port, stderr.clone());
+
+    let until = Instant::now() + Duration::from_secs(10);
+    while !log_events(&stderr).iter().any(|event| event["message"] == "⚠️ PHP worker socket not ready in time") {
+        assert!(Instant::now() < until, "no wait timeout logged:\n{}", server.output());
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    unsafe { libc::kill(server.child.id() as libc::pid_t, libc::SIGTERM) };
+    let until = Instant::now() + Duration::from_secs(10);
+    while server.child.try_wait().unwrap().is_none() {
+        assert!(Instant::now() < until, "still running after SIGTERM");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    assert_eq!(std::fs::read_to_string(&stdout).unwrap(), "", "printed around the subscriber");
+    let events = log_events(&dir.path().join("logs/server.log"));
+    let event = |message: &str| {
+        events
+            .iter()
+            .find(|event| event["message"].as_str().is_some_and(|m| m.ends_with(message)))
+            .unwrap_or_else(|| panic!("{:?} not logged: {:?}", message, events))
+    };
+    let socket_path = socket_path.to_str().unwrap();
+
+    let started = event("Starting Laravel Rust Bridge");
+    assert_eq!(started["level"], "INFO");
+    assert_eq!(started["bind"], format!("127.0.0.1:{}", port));
+    assert_eq!(started["socket_path"], socket_path);
+    let failed = event("Failed to start PHP worker");
+    assert_eq!(failed["level"], "ERROR");
+    assert!(failed["error"].as_str().is_some_and(|e| e.contains("artisan")), "{}", failed);
+    assert_eq!(event("Waiting for the PHP worker socket")["socket_path"], socket_path);
+    let timed_out = event("PHP worker socket not ready in time");
+    assert_eq!(timed_out["level"], "WARN");
+    assert_eq!(timed_out["waited_ms"], 100);
+
+    let signal = event("Shutdown signal received");
+    assert_eq!(signal["signal"], "SIGTERM");
+    assert_eq!(signal["mode"], "graceful");
+    event("Stopping Rust HTTP server");
+    event("Stopping PHP worker");
+}
@@ -0,0 +1,423 @@
+//! The full HTTP → bridge → response path against the mock worker
+//!
+//! Starts the `laravel-rust-server` binary against a `MockWorker` on a Unix
+//! socket in a temporary directory and sends it real HTTP requests. Covers
+//! responses and request frames passing through unchanged, how worker
+//! failures, broken response frames and a panicking handler map to status
+//! codes, that every 503 carries a reason and `Retry-After` and is counted,
+//! that coalesced requests share no response with `Vary` or `no-cache`, and
+//! how the pool reuses connections. The pool is also run on its
+//! own against the mock, to check that every connection it retires reaches
+//! the worker as a clean end of file rather than a reset.
+//! Needs the `test-worker` feature:
+//! `cargo test --features test-worker --test mock_worker`.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{free_port, Server};
+use laravel_rust_server::bridge::connection_pool::{ConnectionPool, ConnectionPoolConfig, Framing};
+use laravel_rust_server::mock_worker::{MockWorker, Reply, Rule, Script, WorkerStats};
+use serde_json::{json, Value};
+
+/// A temporary directory with a mock worker answering by `rules`
+fn worker(rules: Vec<Rule>) -> (tempfile::TempDir, MockWorker) {
+    let dir = tempfile::tempdir().unwrap();
+    let worker = MockWorker::start(dir.path().join("worker.sock"), Script { rules, ..Script::default() }).unwrap();
+    (dir, worker)
+}
+
+#[tokio::test]
+async fn worker_responses_reach_the_client() {
+    let mut reply = Reply::respond(201, "created by the worker");
+    if let Reply::Respond { headers, .. } = &mut reply {
+        headers.insert("x-from-worker".to_string(), vec!["yes".to_string()]);
+    }
+    let (dir, _worker) = worker(vec![Rule::path("/hello", reply)]);
+    let server = Server::start(dir.path(), &[]).await;
+
+    let response = server.get("/hello?name=x").await;
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.headers()["x-from-worker"], "yes");
+    assert_eq!(response.text().await.unwrap(), "created by the worker");
+}
+
+#[tokio::test]
+async fn request_frames_carry_the_request() {
+    let (dir, _worker) = worker(Vec::new());
+    let server = Server::start(dir.path(), &[]).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/submit?page=2", server.url))
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body("name=Jane")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let frame: Value = response.json().await.unwrap();
+    assert_eq!(frame["method"], "POST");
+    assert_eq!(frame["uri"].as_str().unwrap().split('?').next(), Some("/submit"));
+    assert_eq!(frame["parameters"]["page"], "2");
+    assert_eq!(frame["parameters"]["name"], "Jane");
+    assert_eq!(frame["content"], "name=Jane");
+    assert!(frame["server"]["REQUEST_ID"].is_string(), "{}", frame);
+}
+
+#[tokio::test]
+async fn request_frames_carry_the_remaining_budget() {
+    let (dir, _worker) = worker(Vec::new());
+    let budget = |frame: &Value| -> u64 {
+        let server = frame["server"]["REQUEST_DEADLINE_MS"].as_str().expect("no budget in the frame");
+        assert_eq!(frame["headers"]["x-request-deadline-ms"], server, "{}", frame);
+        server.parse().unwrap()
+    };
+    let ask = |url: String, caller: Option<&'static str>| async move {
+        let mut request = reqwest::Client::new().get(url);
+        if let Some(caller) = caller {
+            request = request.header("x-request-deadline-ms", caller);
+        }
+        request.send().await.unwrap().json::<Value>().await.unwrap()
+    };
+
+    // From a peer that is not a trusted proxy the caller's budget is replaced
+    let server = Server::start(dir.path(), &[("SOCKET_READ_TIMEOUT_MS", "4000")]).await;
+    let url = format!("{}/budget", server.url);
+    let plain = budget(&ask(url.clone(), None).await);
+    assert!((3500..=4000).contains(&plain), "{} ms of a 4 s read timeout", plain);
+    let ignored = budget(&ask(url, Some("1000")).await);
+    assert!((3500..=4000).contains(&ignored), "untrusted caller's budget used: {} ms", ignored);
+    drop(server);
+
+    // A trusted caller may shorten it, not lengthen it
+    let settings = [("SOCKET_READ_TIMEOUT_MS", "4000"), ("TRUSTED_PROXIES", "127.0.0.1")];
+    let server = Server::start(dir.path(), &settings).await;
+    let url = format!("{}/budget", server.url);
+    let shorter = budget(&ask(url.clone(), Some("1000")).await);
+    assert!((500..=1000).contains(&shorter), "{} ms of a 1 s caller budget", shorter);
+    let longer = budget(&ask(url, Some("99999")).await);
+    assert!((3500..=4000).contains(&longer), "{} ms, capped at the read timeout", longer);
+}
+
+#[tokio::test]
+async fn octane_workers_are_answered_with_every_header_and_chunk() {
+    let (dir, _worker) = worker(vec![
+        Rule::path(
+            "/cookies",
+            Reply::Data { data: json!({"status": 200, "headers": {"Set-Cookie": ["a=1", "b=2"]}, "content": "two cookies"}) },
+        ),
+        Rule::path(
+            "/events",
+            Reply::Data {
+                data: json!({"status": 200, "headers": {"Content-Type": ["text/event-stream"]}, "chunks": ["data: 1\n\n", "data: 2\n\n"]}),
+            },
+        ),
+    ]);
+    let server = Server::start(dir.path(), &[("WORKER_PROTOCOL", "octane")]).await;
+
+    // The rules match on the `request_uri` of the Swoole-shaped frame
+    let response = server.get("/cookies?page=2").await;
+    assert_eq!(response.status(), 200);
+    let cookies: Vec<_> = response.headers().get_all("set-cookie").iter().map(|value| value.to_str().unwrap()).collect();
+    assert_eq!(cookies, ["a=1", "b=2"]);
+    assert_eq!(response.text().await.unwrap(), "two cookies");
+
+    let response = server.get("/events").await;
+    assert!(response.headers().get("content-length").is_none(), "a streamed response has no length");
+    assert_eq!(response.text().await.unwrap(), "data: 1\n\ndata: 2\n\n");
+}
+
+#[tokio::test]
+async fn worker_failures_map_to_status_codes() {
+    let (dir, _worker) = worker(vec![
+        Rule::path("/exception", Reply::Fail { message: "Division by zero".to_string() }),
+        Rule::path("/slow", Reply::Hang),
+    ]);
+    let server = Server::start(dir.path(), &[("SOCKET_READ_TIMEOUT_MS", "500")]).await;
+
+    assert_eq!(server.get("/exception").await.status(), 500);
+    let started = Instant::now();
+    assert_eq!(server.get("/slow").await.status(), 504);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(server.get("/fine").await.status(), 200, "the server still answers");
+}
+
+#[tokio::test]
+async fn a_panicking_handler_gives_500_and_the_server_keeps_serving() {
+    let (dir, _worker) = worker(Vec::new());
+    let server = Server::start(dir.path(), &[]).await;
+
+    for _ in 0..3 {
+        let response = server.get("/__test/panic").await;
+        assert_eq!(response.status(), 500);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "internal_error", "{}", body);
+        let frame: Value = server.get("/fine").await.json().await.unwrap();
+        assert_eq!(frame["uri"], "/fine");
+    }
+    let log = std::fs::read_to_string(&server.log).unwrap();
+    assert!(log.contains("Request handler panicked"), "panic not logged:\n{}", log);
+    assert!(log.contains("deliberate panic for /__test/panic"), "payload not logged:\n{}", log);
+}
+
+#[tokio::test]
+async fn broken_response_frames_give_502_and_the_connection_is_dropped() {
+    let (dir, _worker) = worker(vec![
+        Rule::path("/garbage", Reply::Garbage),
+        Rule::path("/empty", Reply::Empty),
+        Rule::path("/truncate", Reply::Truncate),
+        Rule::path("/disconnect", Reply::Disconnect),
+    ]);
+    let server = Server::start(dir.path(), &[("SOCKET_READ_TIMEOUT_MS", "2000")]).await;
+
+    for path in ["/garbage", "/empty", "/truncate", "/disconnect"] {
+        let started = Instant::now();
+        let response = server.get(path).await;
+        assert_eq!(response.status(), 502, "{}", path);
+        assert!(started.elapsed() < Duration::from_secs(1), "{} waited for the timeout", path);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "upstream_malformed", "{}: {}", path, body);
+
+        // What is left of the frame must not be read as the next response
+        let response = server.get("/fine").await;
+        assert_eq!(response.status(), 200, "after {}", path);
+        let frame: Value = response.json().await.unwrap();
+        assert_eq!(frame["uri"], "/fine", "after {}", path);
+    }
+}
+
+#[tokio::test]
+async fn an_unreachable_worker_gives_503() {
+    let (dir, worker) = worker(Vec::new());
+    let server = Server::start(dir.path(), &[("SOCKET_RETRY_IDEMPOTENT", "false")]).await;
+    drop(worker);
+
+    let response = server.get("/anything").await;
+    assert_eq!(response.status(), 503);
+    assert!(response.headers().contains_key("retry-after"));
+}
+
+/// Status, `Retry-After` and body of a response that must be a 503
+async fn unavailable(response: reqwest::Response) -> (String, Value) {
+    assert_eq!(response.status(), 503);
+    let retry_after = response.headers()["retry-after"].to_str().unwrap().to_string();
+    (retry_after, response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn every_503_has_a_reason_a_retry_after_and_a_counter() {
+    let slow = Rule::path("/slow", Reply::respond(200, "slow")).delayed(Duration::from_millis(800));
+    let (dir, worker) = worker(vec![slow]);
+    let admin_port = free_port().to_string();
+    let settings = [
+        ("ADAPTIVE_CONCURRENCY", "true"),
+        ("ADAPTIVE_CONCURRENCY_INITIAL", "1"),
+        ("ADAPTIVE_CONCURRENCY_MAX", "1"),
+        ("UNAVAILABLE_RETRY_AFTER_SECS", "7"),
+        ("SOCKET_RETRY_IDEMPOTENT", "false"),
+        ("ADMIN_ENABLED", "true"),
+        ("ADMIN_PORT", admin_port.as_str()),
+    ];
+    let server = Server::start(dir.path(), &settings).await;
+
+    // Shed above the adaptive limit
+    let slow = tokio::spawn(reqwest::get(format!("{}/slow", server.url)));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let (retry_after, body) = unavailable(server.get("/shed").await).await;
+    assert_eq!((retry_after.as_str(), &body["reason"]), ("7", &json!("overloaded")));
+    assert_eq!(body["error"], "overloaded");
+    assert_eq!(slow.await.unwrap().unwrap().status(), 200);
+
+    // The worker went away
+    drop(worker);
+    let (retry_after, body) = unavailable(server.get("/down").await).await;
+    assert_eq!((retry_after.as_str(), &body["reason"]), ("7", &json!("bridge_down")));
+    assert_eq!(body["error"], "bridge_unavailable");
+
+    // Drained with SIGUSR2, new connections are turned away
+    assert_eq!(unsafe { libc::kill(server.child.id() as i32, libc::SIGUSR2) }, 0);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let (retry_after, body) = unavailable(server.get("/drained").await).await;
+    assert_eq!((retry_after.as_str(), &body["reason"]), ("7", &json!("maintenance")));
+
+    let keys = ["error", "message", "reason", "request_id", "retry_after", "status"];
+    assert_eq!(body.as_object().unwrap().keys().collect::<Vec<_>>(), keys);
+    assert_eq!(body["retry_after"], 7);
+
+    let metrics = reqwest::get(format!("http://127.0.0.1:{}/metrics", admin_port)).await.unwrap();
+    let metrics = metrics.text().await.unwrap();
+    for reason in ["overloaded", "bridge_down", "maintenance"] {
+        let line = format!("http_unavailable_responses_total{{reason=\"{}\"}} 1", reason);
+        assert!(metrics.lines().any(|l| l == line), "no {:?} in:\n{}", line, metrics);
+    }
+}
+
+#[tokio::test]
+async fn connections_are_pooled_and_reused() {
+    let (dir, worker) = worker(vec![Rule::any(Reply::respond(200, "ok")).delayed(Duration::from_millis(20))]);
+    let server = Server::start(dir.path(), &[("SOCKET_POOL_MIN", "1"), ("SOCKET_POOL_MAX", "3")]).await;
+    let accepted = worker.stats().accepted();
+    let frames = worker.stats().frames();
+
+    let client = reqwest::Client::new();
+    let requests: Vec<_> = (0..40)
+        .map(|i| {
+            let request = client.get(format!("{}/page/{}", server.url, i)).send();
+            tokio::spawn(async move { request.await.unwrap().status() })
+        })
+        .collect();
+    for request in requests {
+        assert_eq!(request.await.unwrap(), 200);
+    }
+
+    assert_eq!(worker.stats().frames() - frames, 40);
+    let opened = worker.stats().accepted() - accepted;
+    assert!(opened <= 3, "{} connections opened for 40 requests with SOCKET_POOL_MAX=3", opened);
+
+    // Sequential requests keep using the same connections
+    let accepted = worker.stats().accepted();
+    for _ in 0..10 {
+        assert_eq!(server.get("/again").await.status(), 200);
+    }
+    assert_eq!(worker.stats().accepted(), accepted);
+}
+
+/// Reply with `headers`, slow enough for concurrent requests to coalesce
+fn slow_reply(path: &str, headers: &[(&str, &str)]) -> Rule {
+    let mut reply = Reply::respond(200, "page");
+    if let Reply::Respond { headers: reply_headers, .. } = &mut reply {
+        for (name, value) in headers {
+            reply_headers.insert(name.to_string(), vec![value.to_string()]);
+        }
+    }
+    Rule::path(path, reply).delayed(Duration::from_millis(300))
+}
+
+/// Frames `worker` got for two concurrent requests to `path` differing only in `Accept`
+async fn concurrent_frames(server: &Server, worker: &MockWorker, path: &str) -> usize {
+    let before = worker.stats().frames();
+    let client = reqwest::Client::new();
+    let url = format!("{}{}", server.url, path);
+    let get = |accept: &'static str| client.get(&url).header("accept", accept).send();
+    let (html, json) = tokio::join!(get("text/html"), get("application/json"));
+    assert_eq!(html.unwrap().status(), 200);
+    assert_eq!(json.unwrap().status(), 200);
+    worker.stats().frames() - before
+}
+
+#[tokio::test]
+async fn coalesced_requests_share_only_responses_that_suit_them() {
+    let (dir, worker) = worker(vec![
+        slow_reply("/plain", &[]),
+        slow_reply("/negotiated", &[("Vary", "Accept")]),
+        slow_reply("/revalidated", &[("Cache-Control", "no-cache")]),
+    ]);
+    let server = Server::start(dir.path(), &[("COALESCE_REQUESTS", "true")]).await;
+    assert_eq!(concurrent_frames(&server, &worker, "/plain").await, 1, "a plain response is shared");
+    assert_eq!(concurrent_frames(&server, &worker, "/negotiated").await, 2, "a response with Vary is not shared");
+    assert_eq!(concurrent_frames(&server, &worker, "/revalidated").await, 2, "a no-cache response is not shared");
+}
+
+fn pool_config(worker: &MockWorker) -> ConnectionPoolConfig {
+    ConnectionPoolConfig {
+        socket_path: worker.socket_path().to_string_lossy().into_owned(),
+        min_connections: 0,
+        max_connections: 4,
+        connection_timeout: Duration::from_secs(1),
+        read_timeout: Duration::from_secs(1),
+        health_check_interval: Duration::from_secs(30),
+        shards: 1,
+        max_uses: None,
+        goodbye_frame: false,
+        framing: Framing::LengthPrefixed,
+    }
+}
+
+/// Wait until the worker has seen `clean` clean closes, then check there were no abrupt ones
+async fn assert_closed_cleanly(stats: &WorkerStats, clean: usize) {
+    let until = Instant::now() + Duration::from_secs(2);
+    while stats.clean_closes() < clean && Instant::now() < until {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!((stats.clean_closes(), stats.abrupt_closes()), (clean, 0), "(clean, abrupt) closes");
+}
+
+#[tokio::test]
+async fn closing_the_pool_closes_its_connections_cleanly() {
+    let (_dir, worker) = worker(Vec::new());
+    let pool = ConnectionPool::new(ConnectionPoolConfig {
+        min_connections: 3,
+        ..pool_config(&worker)
+    });
+    pool.initialize().await.unwrap();
+
+    pool.close_all().await;
+    assert_eq!(pool.idle_connections(), 0);
+    assert_closed_cleanly(worker.stats(), 3).await;
+    assert_eq!(worker.stats().goodbyes(), 0);
+}
+
+#[tokio::test]
+async fn connections_are_recycled_after_max_uses() {
+    let (_dir, worker) = worker(Vec::new());
+    let pool = ConnectionPool::new(ConnectionPoolConfig {
+        max_uses: Some(3),
+        ..pool_config(&worker)
+    });
+
+    for _ in 0..10 {
+        pool.send_http_request(json!({"uri": "/"})).await.unwrap();
+    }
+    assert_eq!(worker.stats().accepted(), 4);
+    assert_closed_cleanly(worker.stats(), 3).await;
+}
+
+#[tokio::test]
+async fn idle_connections_past_the_health_check_interval_are_closed_cleanly() {
+    let (_dir, worker) = worker(Vec::new());
+    let pool = ConnectionPool::new(ConnectionPoolConfig {
+        health_check_interval: Duration::from_millis(50),
+        ..pool_config(&worker)
+    });
+
+    pool.send_http_request(json!({"uri": "/"})).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    pool.send_http_request(json!({"uri": "/"})).await.unwrap();
+    assert_eq!(worker.stats().accepted(), 2);
+    assert_closed_cleanly(worker.stats(), 1).await;
+}
+
+#[tokio::test]
+async fn unsolicited_bytes_are_drained_before_closing() {
+    // Closing a Unix socket with unread data would reset the worker's end
+    let (_dir, worker) = worker(vec![Rule::path("/twice", Reply::Duplicate)]);
+    let pool = ConnectionPool::new(pool_config(&worker));
+
+    pool.send_http_request(json!({"uri": "/twice"})).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    pool.send_http_request(json!({"uri": "/"})).await.unwrap();
+    assert_eq!(worker.stats().accepted(), 2);
+    assert_closed_cleanly(worker.stats(), 1).await;
+}
+
+#[tokio::test]
+async fn goodbye_frames_are_sent_only_between_frames() {
+    let (_dir, worker) = worker(vec![Rule::path("/garbage", Reply::Garbage)]);
+    let pool = ConnectionPool::new(ConnectionPoolConfig {
+        min_connections: 2,
+        goodbye_frame: true,
+        framing: Framing::LengthPrefixed,
+        ..pool_config(&worker)
+    });
+    pool.initialize().await.unwrap();
+
+    // A failed exchange is closed without one
+    pool.send_http_request(json!({"uri": "/garbage"})).await.unwrap_err();
+    assert_closed_cleanly(worker.stats(), 1).await;
+    assert_eq!(worker.stats().goodbyes(), 0);
+
+    pool.close_all().await;
+    assert_closed_cleanly(worker.stats(), 2).await;
+    assert_eq!(worker.stats().goodbyes(), 1);
+}
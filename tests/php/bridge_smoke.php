@@ -0,0 +1,46 @@
+<?php
+// Smoke test for the native extension (php-ext feature).
+//
+//   cargo build --release --features php-ext
+//   php -d extension=$PWD/target/release/liblaravel_rust_server.so tests/php/bridge_smoke.php
+//
+// Without a worker it checks that the classes load and that an unreachable
+// socket raises BridgeException with the BRIDGE_UNAVAILABLE code (-3). With
+// LARAVEL_RUST_SOCKET pointing at a running worker it also sends `ping`.
+// Exits non-zero on the first failure.
+
+const BRIDGE_UNAVAILABLE = -3;
+
+function check(bool $condition, string $message): void
+{
+    if (!$condition) {
+        fwrite(STDERR, "FAIL: {$message}\n");
+        exit(1);
+    }
+    echo "ok - {$message}\n";
+}
+
+check(class_exists(LaravelRust\Bridge::class), 'LaravelRust\Bridge is registered');
+check(is_subclass_of(LaravelRust\BridgeException::class, Exception::class), 'BridgeException extends Exception');
+
+$bridge = new LaravelRust\Bridge('/nonexistent/laravel-rust-smoke.sock');
+try {
+    $bridge->sendCommand('ping', ['from' => 'smoke']);
+    check(false, 'sendCommand to a missing socket throws');
+} catch (LaravelRust\BridgeException $e) {
+    check($e->getCode() === BRIDGE_UNAVAILABLE, "missing socket is reported as unavailable ({$e->getCode()}: {$e->getMessage()})");
+}
+
+$stats = $bridge->stats();
+check(is_array($stats) && array_key_exists('metrics', $stats), 'stats() returns metrics');
+check($stats['socket_path'] === '/nonexistent/laravel-rust-smoke.sock', 'stats() reports the socket path');
+check($stats['commands']['total'] === 1 && $stats['commands']['in_flight'] === 0, 'stats() counts the command sent');
+check($stats['commands']['failed']['bridge_unavailable'] === 1, 'stats() counts the failure by class');
+
+$socket = getenv('LARAVEL_RUST_SOCKET');
+if ($socket !== false && $socket !== '') {
+    $response = (new LaravelRust\Bridge($socket))->sendCommand('ping');
+    check(is_array($response) && array_key_exists('success', $response), 'ping returns a response array');
+}
+
+echo "all checks passed\n";
@@ -0,0 +1,112 @@
+//! Switching to `RUN_AS_USER` after binding the listeners
+//!
+//! Only runs when the tests run as root, and is skipped otherwise. Starts
+//! the binary as root with `RUN_AS_USER=nobody` and a `PHP_PATH` script
+//! that records its own identity: the server must have bound its port, yet
+//! run as `nobody` along with the worker it spawned, and the log file it
+//! opened before the switch must still be written to. A user that does not
+//! exist must abort startup with an error naming it.
+
+mod common;
+
+use std::net::TcpStream;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use common::{free_port, Server};
+
+fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// `uid gid` of `nobody`, as `id` prints them
+fn nobody() -> String {
+    let id = |flag| {
+        let output = Command::new("id").args([flag, "nobody"]).output().unwrap();
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    };
+    format!("{} {}", id("-u"), id("-g"))
+}
+
+/// Spawn the binary as `user`, with a `PHP_PATH` that writes `uid gid` to `worker.id`
+fn spawn(dir: &Path, port: u16, user: &str) -> Server {
+    let php = dir.join("php");
+    std::fs::write(&php, format!("#!/bin/sh\necho \"$(id -u) $(id -g)\" >{}/worker.id\nexec sleep 10\n", dir.display()))
+        .unwrap();
+    std::fs::set_permissions(&php, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::fs::write(dir.join("artisan"), "").unwrap();
+    // The worker writes here as nobody
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+
+    let mut command = common::command(dir, port);
+    command.env("PHP_PATH", &php).env("RUN_AS_USER", user);
+    Server::spawn(command, port, dir.join("server.log"))
+}
+
+/// Real and effective `uid gid` of a running process
+fn identity(pid: u32) -> String {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).unwrap();
+    let ids = |key: &str| -> Vec<String> {
+        let line = status.lines().find(|line| line.starts_with(key)).unwrap();
+        line.split_whitespace().skip(1).map(str::to_string).collect()
+    };
+    let (uids, gids) = (ids("Uid:"), ids("Gid:"));
+    assert_eq!(uids[0], uids[1], "real and effective uid differ");
+    assert_eq!(gids[0], gids[1], "real and effective gid differ");
+    format!("{} {}", uids[0], gids[0])
+}
+
+#[test]
+fn server_and_worker_run_as_the_configured_user() {
+    if !is_root() {
+        eprintln!("skipped: needs root");
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let port = free_port();
+    let server = spawn(dir.path(), port, "nobody");
+
+    let worker_id = dir.path().join("worker.id");
+    let until = Instant::now() + Duration::from_secs(10);
+    while !worker_id.exists() {
+        assert!(Instant::now() < until, "the PHP worker was never spawned");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    std::thread::sleep(Duration::from_millis(50));
+
+    let nobody = nobody();
+    assert_eq!(std::fs::read_to_string(&worker_id).unwrap().trim(), nobody, "identity of the worker");
+    assert_eq!(identity(server.child.id()), nobody, "identity of the server");
+    assert!(TcpStream::connect(("127.0.0.1", port)).is_ok(), "the port was not bound");
+
+    let logs = std::fs::read_dir(dir.path().join("logs")).unwrap();
+    let written: String = logs.map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap()).collect();
+    assert!(written.contains("Dropped root privileges"), "log file: {}", written);
+}
+
+#[test]
+fn an_unknown_user_aborts_startup() {
+    if !is_root() {
+        eprintln!("skipped: needs root");
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let port = free_port();
+    let mut server = spawn(dir.path(), port, "no-such-user");
+
+    let until = Instant::now() + Duration::from_secs(10);
+    let status = loop {
+        if let Some(status) = server.child.try_wait().unwrap() {
+            break status;
+        }
+        assert!(Instant::now() < until, "startup was not aborted");
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    let stderr = server.output();
+    assert!(!status.success());
+    assert!(stderr.contains("User 'no-such-user' does not exist (RUN_AS_USER)"), "stderr: {}", stderr);
+    assert!(!dir.path().join("worker.id").exists(), "the PHP worker was spawned");
+}
@@ -0,0 +1,191 @@
+//! `WORKER_PROTOCOL=psr7` against a RoadRunner-style worker
+//!
+//! Starts `tests/psr7/echo_worker.py`, a minimal PSR-7 worker speaking
+//! goridge, and lets it connect to a pool listening the way the server's
+//! does. Requests put through the psr7 codec must reach it with their
+//! headers, cookies, parsed form and uploads; its answers, streamed or
+//! not, must come back with every header value; and the `ping` and
+//! `goodbye` commands must reach it as RoadRunner's `pid` and `stop`
+//! control frames. Needs `python3`.
+
+use std::process::{Child, Command};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hyper::body::Bytes;
+use hyper::StatusCode;
+use laravel_rust_server::bridge::connection_pool::{ConnectionPool, ConnectionPoolConfig, Framing};
+use laravel_rust_server::response_validation::ResponseValidation;
+use laravel_rust_server::server::{request_frame, HttpRequestPayload};
+use laravel_rust_server::worker_protocol::{WorkerCodec, WorkerProtocol};
+use serde_json::{json, Value};
+
+/// The echo worker, killed when dropped
+struct EchoWorker(Child);
+
+impl EchoWorker {
+    fn start(socket_path: &str) -> Self {
+        let script = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/psr7/echo_worker.py");
+        let child = Command::new("python3")
+            .arg(script)
+            .env("RR_RELAY", format!("unix://{}", socket_path))
+            .spawn()
+            .expect("python3 to start the echo worker");
+        Self(child)
+    }
+
+    /// Whether the worker exits cleanly within `timeout`
+    fn exits_within(&mut self, timeout: Duration) -> bool {
+        let started = Instant::now();
+        while started.elapsed() < timeout {
+            if let Some(status) = self.0.try_wait().unwrap() {
+                return status.success();
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+}
+
+impl Drop for EchoWorker {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn psr7() -> &'static dyn WorkerCodec {
+    WorkerProtocol::Psr7.codec()
+}
+
+/// A pool for psr7 workers on a socket in `dir`, with one worker connected to it
+async fn pool_with_worker(dir: &tempfile::TempDir, config: impl FnOnce(&mut ConnectionPoolConfig)) -> (Arc<ConnectionPool>, EchoWorker) {
+    let socket_path = dir.path().join("relay.sock").to_string_lossy().into_owned();
+    let mut pool_config = ConnectionPoolConfig {
+        socket_path: socket_path.clone(),
+        min_connections: 1,
+        max_connections: 1,
+        connection_timeout: Duration::from_secs(5),
+        read_timeout: Duration::from_secs(5),
+        health_check_interval: Duration::from_millis(1),
+        shards: 1,
+        max_uses: None,
+        goodbye_frame: false,
+        framing: Framing::Goridge,
+    };
+    config(&mut pool_config);
+    let pool = Arc::new(ConnectionPool::new(pool_config));
+    let initialize = tokio::spawn({
+        let pool = pool.clone();
+        async move { pool.initialize().await }
+    });
+    let worker = EchoWorker::start(&socket_path);
+    initialize.await.unwrap().expect("the worker to connect");
+    (pool, worker)
+}
+
+/// Send the native `frame` through the psr7 codec and the pool; the response status, headers and echo
+async fn exchange(pool: &ConnectionPool, frame: Value) -> (StatusCode, hyper::HeaderMap, Value) {
+    let request = psr7().encode_request(frame);
+    let response = pool.send_http_request(request.clone()).await.unwrap();
+    if let Some(dir) = psr7().scratch_dir(&request) {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    assert!(response.success, "{:?}", response.error);
+    let response = psr7().decode_response(response.data.unwrap(), ResponseValidation::Strict).unwrap();
+    let (parts, body) = response.into_parts();
+    let body = hyper::body::to_bytes(body).await.unwrap();
+    (parts.status, parts.headers, serde_json::from_slice(&body).unwrap())
+}
+
+fn login_frame() -> Value {
+    let mut payload = HttpRequestPayload::synthetic(
+        "POST",
+        "/login?next=%2Fhome",
+        Some(Bytes::from("email=jane%40example.com&tags[]=a&tags[]=b")),
+    );
+    payload.headers.insert("content-type".to_string(), "application/x-www-form-urlencoded".to_string());
+    payload.headers.insert("cookie".to_string(), "theme=dark".to_string());
+    request_frame(payload, "psr7-test")
+}
+
+#[tokio::test]
+async fn requests_reach_the_worker_and_answers_keep_every_header_value() {
+    let dir = tempfile::tempdir().unwrap();
+    let (pool, _worker) = pool_with_worker(&dir, |_| {}).await;
+
+    // Idle worker connections are kept, however long they are idle
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    for _ in 0..3 {
+        let (status, headers, echo) = exchange(&pool, login_frame()).await;
+        assert_eq!(status, StatusCode::OK);
+        let cookies: Vec<_> = headers.get_all("set-cookie").iter().map(|v| v.to_str().unwrap()).collect();
+        assert_eq!(cookies, ["a=1", "b=2"]);
+        assert_eq!(headers["x-echo-method"], "POST");
+
+        assert_eq!(echo["method"], "POST");
+        assert_eq!(echo["uri"], "http://localhost/login?next=%2Fhome");
+        assert_eq!(echo["rawQuery"], "next=%2Fhome");
+        assert_eq!(echo["header"]["Cookie"], json!(["theme=dark"]));
+        assert_eq!(echo["cookies"], json!({"theme": "dark"}));
+        assert_eq!(echo["parsed"], true);
+        let fields: Value = serde_json::from_str(echo["body"].as_str().unwrap()).unwrap();
+        assert_eq!(fields, json!({"email": "jane@example.com", "tags": ["a", "b"]}));
+        assert_eq!(echo["attributes"]["REQUEST_ID"], "psr7-test");
+    }
+}
+
+#[tokio::test]
+async fn streamed_answers_are_joined() {
+    let dir = tempfile::tempdir().unwrap();
+    let (pool, _worker) = pool_with_worker(&dir, |_| {}).await;
+
+    let mut payload = HttpRequestPayload::synthetic("PUT", "/stream", Some(Bytes::from("x".repeat(10_000))));
+    payload.headers.insert("x-stream".to_string(), "1".to_string());
+    let (status, _, echo) = exchange(&pool, request_frame(payload, "stream")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(echo["header"]["X-Stream"], json!(["1"]));
+    assert_eq!(echo["body"].as_str().unwrap().len(), 10_000);
+}
+
+#[tokio::test]
+async fn uploaded_files_reach_the_worker() {
+    let dir = tempfile::tempdir().unwrap();
+    let (pool, _worker) = pool_with_worker(&dir, |_| {}).await;
+
+    let body = concat!(
+        "--b\r\n",
+        "Content-Disposition: form-data; name=\"note\"\r\n\r\n",
+        "hello\r\n",
+        "--b\r\n",
+        "Content-Disposition: form-data; name=\"doc\"; filename=\"a.txt\"\r\n",
+        "Content-Type: text/plain\r\n\r\n",
+        "file body\r\n",
+        "--b--\r\n",
+    );
+    let mut payload = HttpRequestPayload::synthetic("POST", "/upload", Some(Bytes::from(body)));
+    payload.headers.insert("content-type".to_string(), "multipart/form-data; boundary=b".to_string());
+    let (_, _, echo) = exchange(&pool, request_frame(payload, "upload")).await;
+    assert_eq!(echo["body"], r#"{"note":"hello"}"#);
+    assert_eq!(
+        echo["uploads"]["doc"],
+        json!({"name": "a.txt", "mime": "text/plain", "size": 9, "error": 0, "content": "file body"})
+    );
+}
+
+#[tokio::test]
+async fn ping_and_goodbye_are_control_frames() {
+    let dir = tempfile::tempdir().unwrap();
+    let (pool, mut worker) = pool_with_worker(&dir, |config| {
+        config.max_uses = Some(2);
+        config.goodbye_frame = true;
+    })
+    .await;
+
+    let pong = pool.send_http_request(json!({"command": "ping"})).await.unwrap();
+    assert_eq!(pong.data.unwrap()["pid"], worker.0.id());
+
+    // The second frame recycles the connection, and the worker is told to stop
+    exchange(&pool, login_frame()).await;
+    assert!(worker.exits_within(Duration::from_secs(5)), "the worker did not stop");
+}
@@ -0,0 +1,124 @@
+#!/usr/bin/env python3
+"""Minimal RoadRunner PSR-7 worker for the psr7 compatibility test.
+
+Connects to the relay in RR_RELAY (unix://PATH) the way a
+spiral/roadrunner-http worker does and speaks goridge frames:
+
+* the `{"pid": true}` control frame is answered with the process id
+* `{"stop": true}` ends the worker
+* an HTTP request is answered with 200 and a JSON body echoing the
+  request context and body, with the content of each upload in place of
+  its tmpName; `Set-Cookie: a=1` and `b=2`, and `X-Echo-Method`
+* a request with `X-Stream: 1` is answered in two frames, the first one
+  flagged as followed by more
+
+Retries connecting for a few seconds, and exits when the server closes
+the connection.
+"""
+
+import json
+import os
+import socket
+import struct
+import sys
+import time
+import zlib
+
+CONTROL = 0x01
+CODEC_JSON = 0x08
+STREAM = 0x01
+
+
+def read_exactly(conn, n):
+    data = b""
+    while len(data) < n:
+        chunk = conn.recv(n - len(data))
+        if not chunk:
+            sys.exit(0)
+        data += chunk
+    return data
+
+
+def read_frame(conn):
+    header = read_exactly(conn, 12)
+    if header[0] >> 4 != 1:
+        sys.exit("bad goridge version")
+    if struct.unpack("<I", header[6:10])[0] != zlib.crc32(header[:6]):
+        sys.exit("bad goridge checksum")
+    words = header[0] & 0x0F
+    payload_len = struct.unpack("<I", header[2:6])[0]
+    options = read_exactly(conn, (words - 3) * 4)
+    options = list(struct.unpack("<%dI" % (words - 3), options))
+    return header[1], options, read_exactly(conn, payload_len)
+
+
+def write_frame(conn, flags, options, payload, stream=0):
+    head = bytes([0x10 | (3 + len(options)), flags]) + struct.pack("<I", len(payload))
+    head += struct.pack("<I", zlib.crc32(head)) + bytes([stream, 0])
+    conn.sendall(head + b"".join(struct.pack("<I", o) for o in options) + payload)
+
+
+def uploads_with_content(uploads):
+    if isinstance(uploads, dict):
+        if "tmpName" in uploads:
+            upload = dict(uploads)
+            if upload["tmpName"]:
+                with open(upload.pop("tmpName"), "rb") as f:
+                    upload["content"] = f.read().decode()
+            return upload
+        return {name: uploads_with_content(value) for name, value in uploads.items()}
+    if isinstance(uploads, list):
+        return [uploads_with_content(value) for value in uploads]
+    return uploads
+
+
+def answer(conn, context, body):
+    echo = dict(context)
+    echo["uploads"] = uploads_with_content(context.get("uploads"))
+    echo["body"] = body.decode()
+    response = json.dumps({
+        "status": 200,
+        "headers": {
+            "Content-Type": ["application/json"],
+            "Set-Cookie": ["a=1", "b=2"],
+            "X-Echo-Method": [context["method"]],
+        },
+    }).encode()
+    payload = json.dumps(echo).encode()
+    if context["header"].get("X-Stream") == ["1"]:
+        half = len(payload) // 2
+        write_frame(conn, CODEC_JSON, [len(response)], response + payload[:half], STREAM)
+        write_frame(conn, CODEC_JSON, [0], payload[half:])
+    else:
+        write_frame(conn, CODEC_JSON, [len(response)], response + payload)
+
+
+def main():
+    relay = os.environ["RR_RELAY"]
+    if not relay.startswith("unix://"):
+        sys.exit("RR_RELAY must be unix://PATH")
+    # The server binds the relay socket once its pool starts
+    for _ in range(100):
+        conn = socket.socket(socket.AF_UNIX)
+        try:
+            conn.connect(relay[len("unix://"):])
+            break
+        except OSError:
+            conn.close()
+            time.sleep(0.05)
+    else:
+        sys.exit("relay socket not listening")
+    while True:
+        flags, options, payload = read_frame(conn)
+        if flags & CONTROL:
+            command = json.loads(payload)
+            if command.get("stop"):
+                return
+            if command.get("pid"):
+                write_frame(conn, CONTROL | CODEC_JSON, [], json.dumps({"pid": os.getpid()}).encode())
+            continue
+        context_len = options[0]
+        answer(conn, json.loads(payload[:context_len]), payload[context_len:])
+
+
+main()
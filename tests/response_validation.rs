@@ -0,0 +1,244 @@
+//! Every fallback of the worker response parser, in both validation modes
+//!
+//! Runs each malformed response through `worker_response` with
+//! `SOCKET_RESPONSE_VALIDATION=lenient`, where it must be served as before
+//! and counted, and with `strict`, where it must become `502
+//! upstream_malformed` and be counted as rejected. A well-formed response
+//! must pass both without being counted.
+
+use std::sync::Mutex;
+
+use hyper::body::Bytes;
+use hyper::{Body, Response, StatusCode};
+use laravel_rust_server::errors::ServerError;
+use laravel_rust_server::metrics::metrics;
+use laravel_rust_server::response_validation::ResponseValidation;
+use laravel_rust_server::server::worker_response;
+use serde_json::{json, Value};
+
+/// Held by each test, as they all read the same global counters
+static METRICS: Mutex<()> = Mutex::new(());
+
+/// What the lenient parser must make of a response
+enum Served {
+    /// Status and body
+    Response(u16, &'static str),
+    /// Status and a JSON body, compared as JSON
+    Json(u16, Value),
+    /// Status, one header and body
+    WithHeader(u16, &'static str, &'static str, &'static str),
+    /// A 502 even in lenient mode: the status cannot be served at all
+    Malformed,
+}
+
+struct Case {
+    name: &'static str,
+    response: Value,
+    fallbacks: &'static [&'static str],
+    lenient: Served,
+}
+
+fn cases() -> Vec<Case> {
+    vec![
+        Case {
+            name: "bare string",
+            response: json!("plain text"),
+            fallbacks: &["not_an_object"],
+            lenient: Served::Response(200, "plain text"),
+        },
+        Case {
+            name: "bare number",
+            response: json!(42),
+            fallbacks: &["not_an_object"],
+            lenient: Served::Response(200, "42"),
+        },
+        Case {
+            name: "array",
+            response: json!([1, 2]),
+            fallbacks: &["not_an_object"],
+            lenient: Served::Response(200, "[1,2]"),
+        },
+        Case {
+            name: "object without status",
+            response: json!({"message": "done"}),
+            fallbacks: &["missing_status"],
+            lenient: Served::Json(200, json!({"message": "done"})),
+        },
+        Case {
+            name: "originalContent without status",
+            response: json!({"originalContent": {"id": 7}}),
+            fallbacks: &["missing_status"],
+            lenient: Served::Json(200, json!({"id": 7})),
+        },
+        Case {
+            name: "status that is not a number",
+            response: json!({"status": "ok", "headers": {}, "body": "body"}),
+            fallbacks: &["invalid_status"],
+            lenient: Served::Response(200, "body"),
+        },
+        Case {
+            name: "status above 599",
+            response: json!({"status": 700, "headers": {}, "body": "body"}),
+            fallbacks: &["invalid_status"],
+            lenient: Served::Response(700, "body"),
+        },
+        Case {
+            name: "status below 100",
+            response: json!({"status": 42, "headers": {}, "body": "body"}),
+            fallbacks: &["invalid_status"],
+            lenient: Served::Malformed,
+        },
+        Case {
+            name: "headers that are not an object",
+            response: json!({"status": 201, "headers": "x-a: 1", "body": "body"}),
+            fallbacks: &["headers_not_object"],
+            lenient: Served::Response(201, "body"),
+        },
+        Case {
+            name: "header value that is a number",
+            response: json!({"status": 200, "headers": {"x-count": 5}, "body": "body"}),
+            fallbacks: &["invalid_header_value"],
+            lenient: Served::WithHeader(200, "x-count", "5", "body"),
+        },
+        Case {
+            name: "header array holding a number",
+            response: json!({"status": 200, "headers": {"x-count": [5]}, "body": "body"}),
+            fallbacks: &["invalid_header_value"],
+            lenient: Served::WithHeader(200, "x-count", "5", "body"),
+        },
+        Case {
+            name: "body that is not a string, next to headers",
+            response: json!({"status": 200, "headers": {}, "body": {"id": 7}}),
+            fallbacks: &["body_not_string"],
+            lenient: Served::Response(200, ""),
+        },
+        Case {
+            name: "body that is not a string, without headers",
+            response: json!({"status": 200, "body": {"id": 7}}),
+            fallbacks: &["body_not_string"],
+            lenient: Served::Json(200, json!({"id": 7})),
+        },
+        Case {
+            name: "status without body",
+            response: json!({"status": 200, "headers": {"x-a": ["1"]}}),
+            fallbacks: &["missing_body"],
+            lenient: Served::Json(200, json!({"status": 200, "headers": {"x-a": ["1"]}})),
+        },
+        Case {
+            name: "several fallbacks at once",
+            response: json!({"status": "ok", "headers": [], "body": 1}),
+            fallbacks: &["invalid_status", "headers_not_object", "body_not_string"],
+            lenient: Served::Response(200, ""),
+        },
+    ]
+}
+
+/// Current value of `name{fallback="fallback"}`
+fn counter(name: &str, fallback: &str) -> u64 {
+    let prefix = format!("{}{{fallback=\"{}\"}} ", name, fallback);
+    metrics()
+        .render_prometheus()
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .and_then(|value| value.trim().parse::<f64>().ok())
+        .map_or(0, |value| value as u64)
+}
+
+/// Status, headers and body of a response
+fn collect(response: Response<Body>) -> (StatusCode, hyper::HeaderMap, Bytes) {
+    let (parts, body) = response.into_parts();
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let body = runtime.block_on(hyper::body::to_bytes(body)).unwrap();
+    (parts.status, parts.headers, body)
+}
+
+fn is_upstream_malformed(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<ServerError>()
+        .is_some_and(|e| matches!(e, ServerError::UpstreamMalformed(_)) && e.status() == StatusCode::BAD_GATEWAY)
+}
+
+fn lenient(case: &Case) {
+    let before: Vec<u64> = case.fallbacks.iter().map(|f| counter("bridge_response_fallbacks_total", f)).collect();
+    let result = worker_response(case.response.clone(), ResponseValidation::Lenient);
+    let served = match (&case.lenient, result) {
+        (Served::Malformed, Err(e)) => is_upstream_malformed(&e),
+        (Served::Malformed, Ok(_)) => false,
+        (_, Err(_)) => false,
+        (expected, Ok(response)) => {
+            let (status, headers, body) = collect(response);
+            match expected {
+                Served::Response(want_status, want_body) => status.as_u16() == *want_status && body == want_body.as_bytes(),
+                Served::Json(want_status, want_json) => {
+                    status.as_u16() == *want_status && serde_json::from_slice::<Value>(&body).ok().as_ref() == Some(want_json)
+                }
+                Served::WithHeader(want_status, name, value, want_body) => {
+                    status.as_u16() == *want_status
+                        && headers.get(*name).is_some_and(|v| v == *value)
+                        && body == want_body.as_bytes()
+                }
+                Served::Malformed => unreachable!(),
+            }
+        }
+    };
+    assert!(served, "lenient: {} is not served as before", case.name);
+    for (fallback, before) in case.fallbacks.iter().zip(before) {
+        assert_eq!(
+            counter("bridge_response_fallbacks_total", fallback),
+            before + 1,
+            "lenient: {} does not count the {} fallback",
+            case.name,
+            fallback
+        );
+    }
+}
+
+fn strict(case: &Case) {
+    let first = case.fallbacks[0];
+    let before = counter("bridge_responses_rejected_total", first);
+    let result = worker_response(case.response.clone(), ResponseValidation::Strict);
+    assert!(
+        result.as_ref().err().is_some_and(is_upstream_malformed),
+        "strict: {} is not rejected with 502 upstream_malformed",
+        case.name
+    );
+    assert_eq!(
+        counter("bridge_responses_rejected_total", first),
+        before + 1,
+        "strict: {} is not counted as rejected for {}",
+        case.name,
+        first
+    );
+}
+
+#[test]
+fn lenient_mode_serves_every_fallback_and_counts_it() {
+    let _metrics = METRICS.lock().unwrap_or_else(|e| e.into_inner());
+    for case in cases() {
+        lenient(&case);
+    }
+}
+
+#[test]
+fn strict_mode_rejects_every_fallback_and_counts_it() {
+    let _metrics = METRICS.lock().unwrap_or_else(|e| e.into_inner());
+    for case in cases() {
+        strict(&case);
+    }
+}
+
+#[test]
+fn well_formed_responses_pass_both_modes_uncounted() {
+    let _metrics = METRICS.lock().unwrap_or_else(|e| e.into_inner());
+    let well_formed = || {
+        json!({"status": 404, "headers": {"content-type": ["text/plain"], "x-a": "1", "x-empty": []}, "body": "missing"})
+    };
+    let before = metrics().render_prometheus();
+    for mode in [ResponseValidation::Lenient, ResponseValidation::Strict] {
+        let (status, headers, body) = collect(worker_response(well_formed(), mode).unwrap());
+        assert_eq!(status, StatusCode::NOT_FOUND, "{:?}", mode);
+        assert_eq!(headers.get("x-a").unwrap(), "1", "{:?}", mode);
+        assert_eq!(body, "missing", "{:?}", mode);
+    }
+    assert_eq!(metrics().render_prometheus(), before, "a well-formed response is counted");
+}
@@ -0,0 +1,135 @@
+//! Requests parked while the PHP worker restarts
+//!
+//! Parks requests on a `RestartPark` the way the socket bridge does and
+//! checks that they are let through in the order they arrived, that
+//! requests beyond the count or byte bound and requests whose deadline is
+//! nearly spent are not parked, and that a backend that does not come back
+//! fails the parked requests with a 503.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use laravel_rust_server::bridge::restart_park::{RestartPark, RestartParkConfig};
+use laravel_rust_server::errors::ServerError;
+use tokio::sync::mpsc;
+
+fn park(max_requests: usize, max_body_bytes: usize) -> Arc<RestartPark> {
+    Arc::new(RestartPark::new(RestartParkConfig {
+        timeout: Duration::from_secs(5),
+        max_requests,
+        max_body_bytes,
+        min_remaining: Duration::from_millis(100),
+    }))
+}
+
+/// Park a request of `bytes` that reports `id` on `released` once let through
+fn spawn_parked(park: &Arc<RestartPark>, id: usize, bytes: usize, released: &mpsc::UnboundedSender<usize>) {
+    let park = park.clone();
+    let released = released.clone();
+    tokio::spawn(async move {
+        let waited = park.wait(|| bytes, Duration::from_secs(10)).await.expect("released");
+        assert!(!waited.is_zero(), "request {} was not parked", id);
+        released.send(id).unwrap();
+    });
+}
+
+/// Let the spawned requests reach the queue
+async fn settle() {
+    tokio::time::sleep(Duration::from_millis(20)).await;
+}
+
+#[tokio::test]
+async fn requests_are_not_parked_without_a_restart() {
+    let park = park(8, 1024);
+    let waited = park.wait(|| 10, Duration::from_secs(10)).await.unwrap();
+    assert_eq!(waited, Duration::ZERO);
+}
+
+#[tokio::test]
+async fn parked_requests_are_released_in_arrival_order() {
+    let park = park(8, 1024);
+    let generation = park.begin("test");
+    let (released, mut order) = mpsc::unbounded_channel();
+    for id in 0..5 {
+        spawn_parked(&park, id, 10, &released);
+        settle().await;
+    }
+    drop(released);
+
+    park.end(generation, true);
+    let mut seen = Vec::new();
+    while let Some(id) = order.recv().await {
+        seen.push(id);
+    }
+    assert_eq!(seen, vec![0, 1, 2, 3, 4]);
+}
+
+#[tokio::test]
+async fn requests_beyond_the_count_bound_are_not_parked() {
+    let park = park(2, 1024);
+    let generation = park.begin("test");
+    let (released, mut order) = mpsc::unbounded_channel();
+    spawn_parked(&park, 0, 10, &released);
+    spawn_parked(&park, 1, 10, &released);
+    settle().await;
+
+    let waited = park.wait(|| 10, Duration::from_secs(10)).await.unwrap();
+    assert_eq!(waited, Duration::ZERO, "a third request must go on at once");
+
+    park.end(generation, true);
+    assert_eq!(order.recv().await, Some(0));
+    assert_eq!(order.recv().await, Some(1));
+}
+
+#[tokio::test]
+async fn requests_beyond_the_byte_bound_are_not_parked() {
+    let park = park(8, 100);
+    let generation = park.begin("test");
+    let (released, mut order) = mpsc::unbounded_channel();
+    spawn_parked(&park, 0, 60, &released);
+    settle().await;
+
+    let waited = park.wait(|| 60, Duration::from_secs(10)).await.unwrap();
+    assert_eq!(waited, Duration::ZERO, "a frame that would pass the byte bound must go on at once");
+    // A smaller one still fits
+    spawn_parked(&park, 1, 40, &released);
+    settle().await;
+
+    park.end(generation, true);
+    assert_eq!(order.recv().await, Some(0));
+    assert_eq!(order.recv().await, Some(1));
+}
+
+#[tokio::test]
+async fn requests_with_their_deadline_nearly_spent_are_not_parked() {
+    let park = park(8, 1024);
+    park.begin("test");
+    for remaining in [Duration::ZERO, Duration::from_millis(50), Duration::from_millis(100)] {
+        let waited = park.wait(|| 10, remaining).await.unwrap();
+        assert_eq!(waited, Duration::ZERO, "parked with {:?} left", remaining);
+    }
+}
+
+#[tokio::test]
+async fn parked_requests_fail_with_503_when_the_backend_is_not_back() {
+    let park = park(8, 1024);
+    let generation = park.begin("test");
+    let waiting = {
+        let park = park.clone();
+        tokio::spawn(async move { park.wait(|| 10, Duration::from_secs(10)).await })
+    };
+    settle().await;
+
+    park.end(generation, false);
+    let error = waiting.await.unwrap().expect_err("the backend did not come back");
+    assert!(matches!(error, ServerError::Unavailable { .. }), "{:?}", error);
+    assert_eq!(error.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn parked_requests_fail_when_their_deadline_runs_out() {
+    let park = park(8, 1024);
+    park.begin("test");
+    let error = park.wait(|| 10, Duration::from_millis(150)).await.expect_err("deadline passed while parked");
+    assert_eq!(error.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+}
@@ -0,0 +1,94 @@
+//! Secret settings given as `<NAME>_FILE`
+//!
+//! The file must be read and trimmed, and a missing, unreadable or empty
+//! file, or a secret given both ways, must be refused with an error naming
+//! the variable. Every registered secret gets this through the generic
+//! helper, at startup and when the layers are resolved again on reload,
+//! which must pick up a rotated file.
+
+use std::path::Path;
+
+use laravel_rust_server::config_loader::{is_secret, resolve_secret_files, secret_from_file, ConfigLayers};
+
+fn write(dir: &Path, name: &str, contents: &str) -> String {
+    let path = dir.join(name);
+    std::fs::write(&path, contents).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn secrets_are_recognized_by_name() {
+    for env in ["ADMIN_TOKEN", "ADMIN_METRICS_TOKEN", "ADMIN_PASSWORD", "RECORD_HEADER_SECRET", "SENTRY_DSN"] {
+        assert!(is_secret(env), "{}", env);
+    }
+    for env in ["HTTP_PORT", "TLS_KEY_PATH", "ADMIN_USER", "SOCKET_PATH"] {
+        assert!(!is_secret(env), "{}", env);
+    }
+}
+
+#[test]
+fn a_secret_file_is_read_and_trimmed() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write(dir.path(), "token", "  s3cr3t\n\n");
+    assert_eq!(secret_from_file("ADMIN_TOKEN", None, Some(&path)).unwrap().as_deref(), Some("s3cr3t"));
+
+    // Without <NAME>_FILE the value is used as it is
+    assert_eq!(secret_from_file("ADMIN_TOKEN", Some("inline"), None).unwrap(), None);
+    assert_eq!(secret_from_file("ADMIN_TOKEN", Some("inline"), Some("")).unwrap(), None);
+    assert_eq!(secret_from_file("ADMIN_TOKEN", None, None).unwrap(), None);
+}
+
+#[test]
+fn unusable_secret_files_are_refused_naming_the_variable() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write(dir.path(), "token", "s3cr3t");
+    let error = secret_from_file("ADMIN_TOKEN", Some("inline"), Some(&path)).unwrap_err().to_string();
+    assert!(error.contains("ADMIN_TOKEN and ADMIN_TOKEN_FILE are both set"), "{}", error);
+
+    let missing = dir.path().join("missing").to_str().unwrap().to_string();
+    let error = secret_from_file("ADMIN_TOKEN", None, Some(&missing)).unwrap_err().to_string();
+    assert!(error.contains("ADMIN_TOKEN_FILE") && error.contains(&missing), "{}", error);
+
+    let directory = dir.path().to_str().unwrap();
+    let error = secret_from_file("ADMIN_TOKEN", None, Some(directory)).unwrap_err().to_string();
+    assert!(error.contains("Cannot read ADMIN_TOKEN_FILE"), "{}", error);
+
+    let blank = write(dir.path(), "blank", " \n");
+    let error = secret_from_file("ADMIN_TOKEN", None, Some(&blank)).unwrap_err().to_string();
+    assert!(error.contains("ADMIN_TOKEN_FILE") && error.contains("is empty"), "{}", error);
+}
+
+/// The only test that changes the environment, so none of them race on it
+#[test]
+fn every_registered_secret_is_loaded_from_its_file() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("ADMIN_TOKEN_FILE", write(dir.path(), "token", "admin-token\n"));
+    std::env::set_var("ADMIN_METRICS_TOKEN_FILE", write(dir.path(), "metrics", "metrics-token\n"));
+    let layers = ConfigLayers::capture_process_env();
+
+    let mut loaded = resolve_secret_files().unwrap();
+    loaded.sort();
+    assert_eq!(loaded, ["admin.metrics_token", "admin.token"]);
+    assert_eq!(std::env::var("ADMIN_TOKEN").unwrap(), "admin-token");
+    assert_eq!(std::env::var("ADMIN_METRICS_TOKEN").unwrap(), "metrics-token");
+
+    // Reloading reads the file again, as after a secret rotation
+    write(dir.path(), "metrics", "rotated-token\n");
+    let resolved = layers.resolve_fresh().unwrap();
+    assert_eq!(resolved["ADMIN_METRICS_TOKEN"].as_deref(), Some("rotated-token"));
+    assert_eq!(resolved["ADMIN_TOKEN"].as_deref(), Some("admin-token"));
+
+    // Now that the tokens are set too, every problem is reported at once
+    std::env::set_var("ADMIN_PASSWORD_FILE", dir.path().join("missing"));
+    let error = resolve_secret_files().unwrap_err().to_string();
+    assert!(error.contains("ADMIN_TOKEN and ADMIN_TOKEN_FILE are both set"), "{}", error);
+    assert!(error.contains("ADMIN_METRICS_TOKEN and ADMIN_METRICS_TOKEN_FILE are both set"), "{}", error);
+    assert!(error.contains("Cannot read ADMIN_PASSWORD_FILE"), "{}", error);
+    assert_eq!(error.lines().count(), 3, "{}", error);
+
+    for env in ["ADMIN_TOKEN", "ADMIN_METRICS_TOKEN"] {
+        std::env::remove_var(env);
+        std::env::remove_var(format!("{}_FILE", env));
+    }
+    std::env::remove_var("ADMIN_PASSWORD_FILE");
+}
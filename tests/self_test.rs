@@ -0,0 +1,119 @@
+//! The startup self-test against the mock worker
+//!
+//! Starts the `laravel-rust-server` binary with `SELF_TEST=true` against a
+//! `MockWorker` and `LOG_FORMAT=json`. A worker answering the self-test
+//! path with a 2xx must make the server ready once the request reached it.
+//! One answering with a 500, or slower than `SELF_TEST_TIMEOUT_MS`, must be
+//! logged with the problem, the frame sent and the response received, then
+//! stop the server with a non-zero status under `SELF_TEST_ON_FAILURE=abort`,
+//! or keep it serving with `/readyz` failing under `degraded` until a retry
+//! passes against a worker that answers.
+//! Needs the `test-worker` feature:
+//! `cargo test --features test-worker --test self_test`.
+
+mod common;
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use common::{command, free_port, Server};
+use laravel_rust_server::mock_worker::{MockWorker, Reply, Rule, Script};
+use serde_json::Value;
+
+/// Start the server in `dir` with the self-test on and JSON logs
+///
+/// Unlike [`Server::start`] it does not wait for `/readyz`, which the
+/// self-test itself decides.
+fn start(dir: &Path, settings: &[(&str, &str)]) -> Server {
+    let port = free_port();
+    let mut command = command(dir, port);
+    command
+        .env("LOG_FORMAT", "json")
+        .env("SELF_TEST", "true")
+        .envs(settings.iter().copied());
+    Server::spawn(command, port, dir.join("server.log"))
+}
+
+/// The first event `server` logged with a message ending in `message`
+fn event(server: &Server, message: &str) -> Value {
+    server
+        .output()
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .find(|event| event["message"].as_str().is_some_and(|m| m.ends_with(message)))
+        .unwrap_or_else(|| panic!("{:?} not logged:\n{}", message, server.output()))
+}
+
+/// A temporary directory with a mock worker answering by `rules`
+fn worker(rules: Vec<Rule>) -> (tempfile::TempDir, MockWorker) {
+    let dir = tempfile::tempdir().unwrap();
+    let worker = MockWorker::start(dir.path().join("worker.sock"), Script { rules, ..Script::default() }).unwrap();
+    (dir, worker)
+}
+
+/// Check the diagnostic of a failed self-test of `/up`
+fn assert_diagnosed(server: &Server, problem: &str) {
+    let failed = event(server, "Startup self-test request failed");
+    assert_eq!(failed["level"], "ERROR");
+    assert_eq!(failed["path"], "/up");
+    assert!(failed["problem"].as_str().is_some_and(|p| p.contains(problem)), "{}", failed);
+    let sent: Value = serde_json::from_str(failed["frame_sent"].as_str().unwrap()).unwrap();
+    assert_eq!(sent["method"], "GET");
+    assert_eq!(sent["uri"].as_str().unwrap().split('?').next(), Some("/up"));
+    assert_eq!(sent["server"]["REQUEST_ID"], "startup-self-test", "{}", sent);
+    assert!(failed["response_received"].is_string(), "{}", failed);
+}
+
+#[tokio::test]
+async fn a_passing_self_test_makes_the_server_ready() {
+    let (dir, worker) = worker(vec![Rule::path("/health", Reply::respond(204, ""))]);
+    let server = start(dir.path(), &[("SELF_TEST_PATH", "/health?probe=1")]);
+
+    server.wait_readyz(200, Duration::from_secs(10)).await;
+    assert!(worker.stats().frames() >= 1, "the self-test never reached the worker");
+    let passed = event(&server, "Startup self-test passed");
+    assert_eq!(passed["path"], "/health?probe=1");
+    assert_eq!(passed["status"], 204);
+}
+
+#[tokio::test]
+async fn a_failing_self_test_aborts_startup() {
+    let (dir, _worker) = worker(vec![Rule::path("/up", Reply::respond(500, "Class \"App\\Kernel\" not found"))]);
+    let mut server = start(dir.path(), &[]);
+
+    let status = server.wait_exit().await;
+    assert!(!status.success(), "exited with {}", status);
+    assert_diagnosed(&server, "expected a 2xx or 3xx status, got 500");
+    let received: Value = serde_json::from_str(event(&server, "request failed")["response_received"].as_str().unwrap())
+        .expect("the raw response in the diagnostic");
+    assert_eq!(received["data"]["status"], 500, "{}", received);
+    assert_eq!(event(&server, "Startup self-test failed, shutting down (SELF_TEST_ON_FAILURE=abort)")["level"], "ERROR");
+}
+
+#[tokio::test]
+async fn a_slow_self_test_degrades_until_a_retry_passes() {
+    let (dir, slow) = worker(vec![Rule::path("/up", Reply::respond(200, "ok")).delayed(Duration::from_secs(2))]);
+    let socket_path = slow.socket_path().to_path_buf();
+    let server = start(
+        dir.path(),
+        &[("SELF_TEST_ON_FAILURE", "degraded"), ("SELF_TEST_TIMEOUT_MS", "300")],
+    );
+
+    // Serving, but not ready
+    let degraded = "serving with /readyz failing until it passes (SELF_TEST_ON_FAILURE=degraded)";
+    let until = Instant::now() + Duration::from_secs(10);
+    while !server.output().contains(degraded) {
+        assert!(Instant::now() < until, "never degraded:\n{}", server.output());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(event(&server, degraded)["level"], "WARN");
+    assert_diagnosed(&server, "timed out");
+    assert_eq!(event(&server, "request failed")["response_received"], "none");
+    assert_eq!(server.status("/healthz").await, Some(200));
+    assert_eq!(server.status("/readyz").await, Some(503));
+
+    // A worker that answers in time passes the next attempt
+    drop(slow);
+    let _worker = MockWorker::start(&socket_path, Script::default()).unwrap();
+    server.wait_readyz(200, Duration::from_secs(15)).await;
+}
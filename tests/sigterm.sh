@@ -0,0 +1,113 @@
+#!/usr/bin/env bash
+# SIGTERM finishes in-flight requests; SIGQUIT cuts them short.
+#
+#   cargo build --release --features test-worker
+#   tests/sigterm.sh ./target/release/laravel-rust-server
+#
+# Starts the server against the `mock-worker` built next to it, which
+# answers /slow after 1.5 seconds, and sends SIGTERM while a request for
+# /slow is in flight. The request must complete with the worker's answer,
+# the server must exit with status 0 once it has, refuse connections from
+# then on, and log the signal with the graceful path. Then SIGQUIT with
+# SHUTDOWN_FAST_DRAIN_TIMEOUT_MS=300 must exit well before the request
+# could complete, logging the fast path. HTTP_PORT can be overridden from
+# the environment, MOCK_WORKER to use another worker binary.
+
+set -euo pipefail
+
+BINARY=${1:?usage: $0 path/to/laravel-rust-server}
+BINARY=$(cd "$(dirname "$BINARY")" && pwd)/$(basename "$BINARY")
+MOCK_WORKER=${MOCK_WORKER:-$(dirname "$BINARY")/mock-worker}
+HTTP_PORT=${HTTP_PORT:-18080}
+URL=http://127.0.0.1:$HTTP_PORT
+
+WORK=$(mktemp -d)
+SERVER_PID=
+WORKER_PID=
+FAILED=0
+cleanup() {
+    for pid in $SERVER_PID $WORKER_PID; do
+        kill "$pid" 2>/dev/null || true
+        wait "$pid" 2>/dev/null || true
+    done
+    rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+if [ ! -x "$MOCK_WORKER" ]; then
+    echo "FAIL: $MOCK_WORKER not found; build with --features test-worker"
+    exit 1
+fi
+cat >"$WORK/script.json" <<'JSON'
+{"rules": [{"path": "/slow", "delay_ms": 1500, "action": "respond", "body": "finished"}]}
+JSON
+
+# start [VAR=value...] - start a worker and the server in the background with extra settings
+start() {
+    # The server removes the socket file when it exits, so every run gets a new worker
+    kill "$WORKER_PID" 2>/dev/null || true
+    wait "$WORKER_PID" 2>/dev/null || true
+    rm -f "$WORK/worker.sock"
+    "$MOCK_WORKER" "$WORK/worker.sock" "$WORK/script.json" 2>"$WORK/worker.out" &
+    WORKER_PID=$!
+    (
+        cd "$WORK"
+        export HTTP_HOST=127.0.0.1 HTTP_PORT SOCKET_PATH="$WORK/worker.sock" LARAVEL_PATH="$WORK"
+        export LOG_DIR="$WORK/logs" PHP_WORKER_AUTO_RESTART=false
+        export "$@"
+        exec "$BINARY"
+    ) >"$WORK/server.out" 2>&1 &
+    SERVER_PID=$!
+    for _ in $(seq 50); do
+        [ "$(curl -s -o /dev/null -w '%{http_code}' "$URL/readyz")" = 200 ] && return
+        sleep 0.2
+    done
+    echo "FAIL: server not ready"
+    tail -n 20 "$WORK/server.out"
+    exit 1
+}
+check() {
+    local name=$1 want=$2 got=$3
+    if [ "$got" = "$want" ]; then
+        echo "ok - $name"
+    else
+        echo "FAIL: $name: got $got, expected $want"
+        FAILED=1
+    fi
+}
+# signal SIGNAL - send it while /slow is in flight; leaves the response and exit status in $WORK
+signal() {
+    curl -s -w ' %{http_code}' "$URL/slow" >"$WORK/inflight" || true &
+    local inflight=$!
+    sleep 0.3
+    local started=$SECONDS
+    kill -"$1" "$SERVER_PID"
+    local status=0
+    wait "$SERVER_PID" || status=$?
+    echo "$status" >"$WORK/status"
+    echo $((SECONDS - started)) >"$WORK/took"
+    SERVER_PID=
+    wait "$inflight"
+}
+logged() {
+    grep "Shutdown signal received" "$WORK/server.out" | grep "signal.*$1" | grep -c "mode.*$2" || true
+}
+
+start
+signal TERM
+check "in-flight request completed" "finished 200" "$(cat "$WORK/inflight")"
+check "exit status after SIGTERM" 0 "$(cat "$WORK/status")"
+check "connections refused after exit" 000 "$(curl -s -o /dev/null -w '%{http_code}' "$URL/healthz" || true)"
+check "SIGTERM logged as graceful" 1 "$(logged SIGTERM graceful)"
+
+start SHUTDOWN_FAST_DRAIN_TIMEOUT_MS=300
+signal QUIT
+check "in-flight request cut short" no "$([ "$(cat "$WORK/inflight")" = "finished 200" ] && echo yes || echo no)"
+check "SIGQUIT exits before the request could finish" yes "$([ "$(cat "$WORK/took")" -lt 2 ] && echo yes || echo no)"
+check "SIGQUIT logged as fast" 1 "$(logged SIGQUIT fast)"
+
+if [ "$FAILED" -ne 0 ]; then
+    tail -n 20 "$WORK/server.out"
+    exit 1
+fi
+echo "ok - sigterm"
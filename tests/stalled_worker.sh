@@ -0,0 +1,117 @@
+#!/usr/bin/env bash
+# A hung PHP worker is detected, its requests fail fast and it is replaced.
+#
+#   cargo build --release --features chaos
+#   tests/stalled_worker.sh ./target/release/laravel-rust-server
+#
+# Starts the server with a fake PHP_PATH whose worker only accepts and
+# closes connections, so it never answers the stall ping, and chaos latency
+# of 20 seconds on requests carrying X-Stall. Such a request looks like one
+# stuck in the worker: with PHP_WORKER_STALL_TIMEOUT=1 it must get a 503
+# within a few seconds, the worker must be restarted with reason `stall`
+# and php_worker_stalls_total must count it. A second stalled request then
+# gets SIGTERM before the timeout; shutdown must not restart the worker
+# again. HTTP_PORT and ADMIN_PORT can be overridden from the environment.
+
+set -euo pipefail
+
+BINARY=${1:?usage: $0 path/to/laravel-rust-server}
+BINARY=$(cd "$(dirname "$BINARY")" && pwd)/$(basename "$BINARY")
+HTTP_PORT=${HTTP_PORT:-18080}
+ADMIN_PORT=${ADMIN_PORT:-18081}
+URL=http://127.0.0.1:$HTTP_PORT
+ADMIN_URL=http://127.0.0.1:$ADMIN_PORT
+
+WORK=$(mktemp -d)
+SERVER_PID=
+LOAD_PID=
+FAILED=0
+cleanup() {
+    for pid in $LOAD_PID $SERVER_PID; do
+        kill "$pid" 2>/dev/null || true
+        wait "$pid" 2>/dev/null || true
+    done
+    pkill -f "$WORK/worker.py" 2>/dev/null || true
+    rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+# Worker that is reachable but never answers
+cat >"$WORK/worker.py" <<'EOF'
+import os, socket, sys
+path = os.environ["SOCKET_PATH"]
+if os.path.exists(path):
+    os.unlink(path)
+s = socket.socket(socket.AF_UNIX)
+s.bind(path)
+s.listen(128)
+while True:
+    s.accept()[0].close()
+EOF
+cat >"$WORK/php" <<EOF
+#!/bin/sh
+exec python3 "$WORK/worker.py"
+EOF
+chmod +x "$WORK/php"
+touch "$WORK/artisan"
+
+(
+    cd "$WORK"
+    export HTTP_HOST=127.0.0.1 HTTP_PORT SOCKET_PATH="$WORK/worker.sock" LARAVEL_PATH="$WORK" PHP_PATH="$WORK/php"
+    export LOG_DIR="$WORK/logs" SOCKET_READ_TIMEOUT_MS=30000 SHUTDOWN_DRAIN_TIMEOUT_MS=3000
+    export ADMIN_ENABLED=true ADMIN_HOST=127.0.0.1 ADMIN_PORT
+    export PHP_WORKER_STALL_TIMEOUT=1 PHP_WORKER_STALL_KILL_GRACE_MS=500 PHP_WORKER_POLL_INTERVAL_MS=100
+    export CHAOS_ENABLED=true CHAOS_HEADER=x-stall CHAOS_LATENCY_RATE=1 CHAOS_LATENCY_MS=20000
+    exec "$BINARY"
+) >"$WORK/server.out" 2>&1 &
+SERVER_PID=$!
+for _ in $(seq 50); do
+    [ "$(curl -s -o /dev/null -w '%{http_code}' "$URL/readyz")" = 200 ] && break
+    sleep 0.2
+done
+
+check() {
+    local name=$1 ok=$2
+    if [ "$ok" = true ]; then
+        echo "ok - $name"
+    else
+        echo "FAIL: $name"
+        FAILED=1
+    fi
+}
+worker_stat() {
+    curl -s "$ADMIN_URL/admin/stats" | python3 -c "import json, sys; print(json.load(sys.stdin)['php_worker'][sys.argv[1]])" "$1"
+}
+
+first_pid=$(worker_stat pid)
+result=$(curl -s -o /dev/null -w '%{http_code} %{time_total}' --max-time 15 -H 'X-Stall: 1' "$URL/stuck" || true)
+check "a stalled request gets 503 (got ${result% *})" "$([ "${result% *}" = 503 ] && echo true || echo false)"
+check "it fails long before the chaos latency (${result#* }s)" \
+    "$(awk -v t="${result#* }" 'BEGIN { print (t != "" && t < 8) ? "true" : "false" }')"
+
+sleep 1
+second_pid=$(worker_stat pid)
+check "the worker was replaced ($first_pid -> $second_pid)" \
+    "$([ "$second_pid" != None ] && [ "$second_pid" != "$first_pid" ] && echo true || echo false)"
+check "the restart reason is stall" "$([ "$(worker_stat last_restart_reason)" = stall ] && echo true || echo false)"
+check "the first worker is gone" "$(kill -0 "$first_pid" 2>/dev/null && echo false || echo true)"
+stalls=$(curl -s "$ADMIN_URL/metrics" | awk '$1 == "php_worker_stalls_total" { print $2 }')
+check "php_worker_stalls_total is 1 (got ${stalls:-nothing})" "$([ "${stalls:-}" = 1 ] && echo true || echo false)"
+code=$(curl -s -o /dev/null -w '%{http_code}' --max-time 1 "$URL/readyz")
+check "/readyz answers 200 after the restart (got $code)" "$([ "$code" = 200 ] && echo true || echo false)"
+
+# Shutdown while a request hangs: no stall restart during the drain
+curl -s -o /dev/null --max-time 15 -H 'X-Stall: 1' "$URL/stuck-again" &
+LOAD_PID=$!
+sleep 0.3
+kill -TERM "$SERVER_PID"
+wait "$SERVER_PID" 2>/dev/null || true
+SERVER_PID=
+detected=$(grep -c 'PHP worker stalled, failing' "$WORK/server.out" || true)
+check "shutdown did not count a second stall (got $detected)" "$([ "$detected" = 1 ] && echo true || echo false)"
+
+if [ "$FAILED" -ne 0 ]; then
+    tail -n 30 "$WORK/server.out"
+    exit 1
+fi
+echo "ok - stalled worker"
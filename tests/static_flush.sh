@@ -0,0 +1,112 @@
+#!/usr/bin/env bash
+# Static files in memory are evicted by the admin flush and by the rescan.
+#
+#   cargo build --release
+#   tests/static_flush.sh ./target/release/laravel-rust-server
+#
+# Starts the server with STATIC_MEMORY_CACHE_BYTES and the admin listener,
+# first without the rescan. A file overwritten in place must still be
+# served from memory, with its ETag answering If-None-Match with 304,
+# until `POST /admin/cache/static/flush?prefix=/build/` evicts it; a file
+# outside the prefix must stay in memory until a flush without one, and
+# the flushes must be logged with their counts. Then with
+# STATIC_MEMORY_CACHE_RESCAN_MS an overwritten file must be served anew
+# within a few rescans. HTTP_PORT and ADMIN_PORT can be overridden from
+# the environment.
+
+set -euo pipefail
+
+BINARY=${1:?usage: $0 path/to/laravel-rust-server}
+BINARY=$(cd "$(dirname "$BINARY")" && pwd)/$(basename "$BINARY")
+HTTP_PORT=${HTTP_PORT:-18080}
+ADMIN_PORT=${ADMIN_PORT:-19090}
+URL=http://127.0.0.1:$HTTP_PORT
+ADMIN=http://127.0.0.1:$ADMIN_PORT
+
+WORK=$(mktemp -d)
+SERVER_PID=
+FAILED=0
+cleanup() {
+    kill "$SERVER_PID" 2>/dev/null || true
+    wait "$SERVER_PID" 2>/dev/null || true
+    rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+mkdir -p "$WORK/public/build" "$WORK/public/assets"
+echo "app v1" >"$WORK/public/build/app.js"
+echo "logo v1" >"$WORK/public/assets/logo.svg"
+
+# start [VAR=value...] - start the server in the background with extra settings
+start() {
+    (
+        cd "$WORK"
+        export HTTP_HOST=127.0.0.1 HTTP_PORT LARAVEL_PATH="$WORK" LOG_DIR="$WORK/logs" PHP_WORKER_AUTO_RESTART=false
+        export SOCKET_PATH="$WORK/worker.sock" ADMIN_ENABLED=true ADMIN_PORT STATIC_MEMORY_CACHE_BYTES=1048576
+        export "$@"
+        exec "$BINARY"
+    ) >"$WORK/server.out" 2>&1 &
+    SERVER_PID=$!
+    for _ in $(seq 50); do
+        curl -s -o /dev/null "$URL/healthz" && curl -s -o /dev/null "$ADMIN/admin/stats" && return
+        sleep 0.2
+    done
+}
+stop() {
+    kill "$SERVER_PID" 2>/dev/null || true
+    wait "$SERVER_PID" 2>/dev/null || true
+    SERVER_PID=
+}
+check() {
+    local name=$1 want=$2 got=$3
+    if [ "$got" = "$want" ]; then
+        echo "ok - $name"
+    else
+        echo "FAIL: $name: got $got, expected $want"
+        FAILED=1
+    fi
+}
+flush() {
+    curl -s -X POST "$ADMIN/admin/cache/static/flush$1" | python3 -c 'import json, sys; print(json.load(sys.stdin)["evicted"])'
+}
+logged() {
+    grep -h "Flushed static files from memory" "$WORK"/server.out "$WORK"/logs/* 2>/dev/null | grep -c "evicted=$1" || true
+}
+
+start STATIC_MEMORY_CACHE_RESCAN_MS=0
+check "file read from disk" "app v1" "$(curl -s "$URL/build/app.js")"
+curl -s -o /dev/null "$URL/assets/logo.svg"
+ETAG=$(curl -s -o /dev/null -w '%header{etag}' "$URL/build/app.js")
+check "ETag sent" yes "$([ -n "$ETAG" ] && echo yes || echo no)"
+check "matching If-None-Match gets 304" 304 "$(curl -s -o /dev/null -w '%{http_code}' -H "If-None-Match: $ETAG" "$URL/build/app.js")"
+
+echo "app v2, longer" >"$WORK/public/build/app.js"
+echo "logo v2, longer" >"$WORK/public/assets/logo.svg"
+check "overwritten file served from memory" "app v1" "$(curl -s "$URL/build/app.js")"
+check "flush under /build/ evicts one file" 1 "$(flush '?prefix=/build/')"
+check "flushed file read anew" "app v2, longer" "$(curl -s "$URL/build/app.js")"
+check "old ETag no longer matches" 200 "$(curl -s -o /dev/null -w '%{http_code}' -H "If-None-Match: $ETAG" "$URL/build/app.js")"
+check "file outside the prefix kept" "logo v1" "$(curl -s "$URL/assets/logo.svg")"
+check "flush without a prefix evicts both" 2 "$(flush '')"
+check "file outside the prefix read anew" "logo v2, longer" "$(curl -s "$URL/assets/logo.svg")"
+check "prefix not starting with / refused" 400 "$(curl -s -o /dev/null -w '%{http_code}' -X POST "$ADMIN/admin/cache/static/flush?prefix=build")"
+check "flushes logged with their counts" "1 1" "$(logged 1) $(logged 2)"
+stop
+
+start STATIC_MEMORY_CACHE_RESCAN_MS=200
+curl -s -o /dev/null "$URL/build/app.js"
+echo "app v3" >"$WORK/public/build/app.js"
+got=
+for _ in $(seq 20); do
+    got=$(curl -s "$URL/build/app.js")
+    [ "$got" = "app v3" ] && break
+    sleep 0.2
+done
+check "rescan evicts an overwritten file" "app v3" "$got"
+stop
+
+if [ "$FAILED" -ne 0 ]; then
+    tail -n 20 "$WORK/server.out"
+    exit 1
+fi
+echo "ok - static flush"
@@ -0,0 +1,99 @@
+#!/usr/bin/env bash
+# Static files are found by their decoded names, and only inside public/.
+#
+#   cargo build --release
+#   tests/static_paths.sh ./target/release/laravel-rust-server
+#
+# Serves a public/ directory holding files with spaces and Cyrillic in
+# their names, and a secret file next to it. The files must be served
+# from their percent-encoded paths. Encoded or raw `..` segments, an
+# encoded NUL and bytes that are not UTF-8 once decoded must get 404
+# without reaching the secret file. So must symlinks in public/ pointing
+# to the secret file or to a directory holding it, while a link into
+# storage/app/public, as made by `php artisan storage:link`, is followed.
+# HTTP_PORT can be overridden from the environment.
+
+set -euo pipefail
+
+BINARY=${1:?usage: $0 path/to/laravel-rust-server}
+BINARY=$(cd "$(dirname "$BINARY")" && pwd)/$(basename "$BINARY")
+HTTP_PORT=${HTTP_PORT:-18080}
+URL=http://127.0.0.1:$HTTP_PORT
+
+WORK=$(mktemp -d)
+SERVER_PID=
+WORKER_PID=
+FAILED=0
+cleanup() {
+    for pid in $SERVER_PID $WORKER_PID; do
+        kill "$pid" 2>/dev/null || true
+        wait "$pid" 2>/dev/null || true
+    done
+    rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+# The server runs away from the application, as from /usr/local/bin, and
+# finds public/ under LARAVEL_PATH
+mkdir -p "$WORK/opt/bin" "$WORK/public"
+echo brochure >"$WORK/public/брошюра 2024.pdf"
+echo kit >"$WORK/public/press kit.txt"
+echo secret >"$WORK/secret.txt"
+mkdir -p "$WORK/storage/app/public"
+echo upload >"$WORK/storage/app/public/avatar.txt"
+ln -s ../storage/app/public "$WORK/public/storage"
+ln -s ../secret.txt "$WORK/public/leak.txt"
+ln -s "$WORK" "$WORK/public/root"
+
+python3 -c '
+import socket, sys
+s = socket.socket(socket.AF_UNIX)
+s.bind(sys.argv[1])
+s.listen(128)
+while True:
+    s.accept()[0].close()
+' "$WORK/worker.sock" &
+WORKER_PID=$!
+(
+    cd "$WORK/opt/bin"
+    export HTTP_HOST=127.0.0.1 HTTP_PORT SOCKET_PATH="$WORK/worker.sock" LARAVEL_PATH="$WORK"
+    export LOG_DIR="$WORK/logs" PHP_WORKER_AUTO_RESTART=false
+    exec "$BINARY"
+) >"$WORK/server.out" 2>&1 &
+SERVER_PID=$!
+for _ in $(seq 50); do
+    [ "$(curl -s -o /dev/null -w '%{http_code}' "$URL/healthz")" = 200 ] && break
+    sleep 0.2
+done
+
+check() {
+    local name=$1 ok=$2
+    if [ "$ok" = true ]; then
+        echo "ok - $name"
+    else
+        echo "FAIL: $name"
+        FAILED=1
+    fi
+}
+# Status and body of a GET of the path as given, without normalization
+fetch() {
+    curl -s --path-as-is -w ' %{http_code}' "$URL$1" | tr -d '\n'
+}
+
+got=$(fetch '/press%20kit.txt')
+check "a name with a space is served (got $got)" "$([ "$got" = 'kit 200' ] && echo true || echo false)"
+got=$(fetch '/%D0%B1%D1%80%D0%BE%D1%88%D1%8E%D1%80%D0%B0%202024.pdf')
+check "a Cyrillic name is served (got $got)" "$([ "$got" = 'brochure 200' ] && echo true || echo false)"
+got=$(fetch '/storage/avatar.txt')
+check "a file behind the storage link is served (got $got)" "$([ "$got" = 'upload 200' ] && echo true || echo false)"
+for path in '/%2e%2e/secret.txt' '/%2E%2E/secret.txt' '/..%2fsecret.txt' '/../secret.txt' '/press%00kit.txt' '/%ff.txt' \
+    '/leak.txt' '/root/secret.txt' '/storage/..%2f..%2fsecret.txt'; do
+    got=$(fetch "$path")
+    check "$path gets 404 (got ${got##* })" "$([ "${got##* }" = 404 ] && [[ $got != *secret* ]] && echo true || echo false)"
+done
+
+if [ "$FAILED" -ne 0 ]; then
+    tail -n 20 "$WORK/server.out"
+    exit 1
+fi
+echo "ok - static file paths"
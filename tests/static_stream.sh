@@ -0,0 +1,95 @@
+#!/usr/bin/env bash
+# Large static files are streamed from disk, not loaded into memory.
+#
+#   cargo build --release
+#   tests/static_stream.sh ./target/release/laravel-rust-server
+#
+# Serves a 256 MiB file above STATIC_STREAM_THRESHOLD to ten clients at
+# once. Each must get the whole file with a Content-Length from its size,
+# the first byte must arrive quickly, and the server's peak RSS (VmHWM)
+# must stay far below the size of a single copy of the file. A 3 MiB file
+# of random bytes, also above the threshold, must arrive intact, and one
+# below it must still be served whole. Needs Linux for /proc. HTTP_PORT
+# can be overridden from the environment.
+
+set -euo pipefail
+
+BINARY=${1:?usage: $0 path/to/laravel-rust-server}
+BINARY=$(cd "$(dirname "$BINARY")" && pwd)/$(basename "$BINARY")
+HTTP_PORT=${HTTP_PORT:-18080}
+URL=http://127.0.0.1:$HTTP_PORT
+BIG_BYTES=$((256 * 1024 * 1024))
+MAX_PEAK_KB=$((64 * 1024))
+
+WORK=$(mktemp -d)
+SERVER_PID=
+FAILED=0
+cleanup() {
+    kill "$SERVER_PID" 2>/dev/null || true
+    wait "$SERVER_PID" 2>/dev/null || true
+    rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+mkdir -p "$WORK/public/assets"
+# Sparse: no disk is used, but every byte is still read and sent
+truncate -s "$BIG_BYTES" "$WORK/public/assets/installer.bin"
+head -c $((3 * 1024 * 1024)) /dev/urandom >"$WORK/public/assets/random.bin"
+head -c 1000 /dev/urandom >"$WORK/public/assets/small.bin"
+
+(
+    cd "$WORK"
+    export HTTP_HOST=127.0.0.1 HTTP_PORT LARAVEL_PATH="$WORK" LOG_DIR="$WORK/logs" PHP_WORKER_AUTO_RESTART=false
+    export SOCKET_PATH="$WORK/worker.sock" STATIC_STREAM_THRESHOLD=1048576
+    exec "$BINARY"
+) >"$WORK/server.out" 2>&1 &
+SERVER_PID=$!
+for _ in $(seq 50); do
+    curl -s -o /dev/null "$URL/healthz" && break
+    sleep 0.2
+done
+
+check() {
+    local name=$1 want=$2 got=$3
+    if [ "$got" = "$want" ]; then
+        echo "ok - $name"
+    else
+        echo "FAIL: $name: got $got, expected $want"
+        FAILED=1
+    fi
+}
+peak_kb() {
+    awk '/^VmHWM:/ { print $2 }' "/proc/$SERVER_PID/status"
+}
+BASELINE_KB=$(peak_kb)
+
+for client in $(seq 10); do
+    curl -s -o /dev/null -w '%{http_code} %{size_download} %{time_starttransfer}\n' \
+        "$URL/assets/installer.bin" >"$WORK/client.$client" &
+done
+wait $(jobs -p | grep -v "^$SERVER_PID$")
+
+for client in $(seq 10); do
+    read -r status size first_byte <"$WORK/client.$client"
+    check "client $client got the whole file" "200 $BIG_BYTES" "$status $size"
+    check "client $client got the first byte within a second" yes \
+        "$(awk -v t="$first_byte" 'BEGIN { print (t < 1 ? "yes" : "no") }')"
+done
+check "Content-Length from the file size" "$BIG_BYTES" \
+    "$(curl -s -o /dev/null -w '%header{content-length}' "$URL/assets/installer.bin")"
+
+PEAK_KB=$(peak_kb)
+echo "# peak RSS ${BASELINE_KB} KiB before, ${PEAK_KB} KiB after ten downloads"
+check "peak RSS grew by less than $((MAX_PEAK_KB / 1024)) MiB" yes \
+    "$([ $((PEAK_KB - BASELINE_KB)) -lt "$MAX_PEAK_KB" ] && echo yes || echo no)"
+
+check "streamed file arrives intact" "$(sha256sum <"$WORK/public/assets/random.bin")" \
+    "$(curl -s "$URL/assets/random.bin" | sha256sum)"
+check "file below the threshold arrives intact" "$(sha256sum <"$WORK/public/assets/small.bin")" \
+    "$(curl -s "$URL/assets/small.bin" | sha256sum)"
+
+if [ "$FAILED" -ne 0 ]; then
+    tail -n 20 "$WORK/server.out"
+    exit 1
+fi
+echo "ok - static stream"
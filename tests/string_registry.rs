@@ -0,0 +1,63 @@
+//! Strings handed across the FFI with the `string-registry` feature
+//!
+//! Strings from `laravel_rust_get_option` and `laravel_rust_last_error`
+//! must be counted by `laravel_rust_live_strings` until they are released
+//! with `laravel_rust_free_string`, leaving none behind. Freeing one twice,
+//! or freeing a pointer the library never returned, must be refused and
+//! logged rather than touch the heap.
+//!
+//! `cargo test --features string-registry --test string_registry`
+
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use laravel_rust_server::laravel_integration::{
+    laravel_rust_destroy, laravel_rust_free_string, laravel_rust_get_option, laravel_rust_init,
+    laravel_rust_last_error, laravel_rust_live_strings, laravel_rust_set_option, LaravelRustServer, LaravelRustStatus,
+};
+
+#[test]
+fn strings_are_released_once_and_only_by_the_library() {
+    unsafe {
+        let mut server: *mut LaravelRustServer = ptr::null_mut();
+        assert_eq!(laravel_rust_init(&mut server), LaravelRustStatus::Ok);
+        let key = CString::new("socket_path").unwrap();
+        let value = CString::new("/run/app.sock").unwrap();
+        assert_eq!(laravel_rust_set_option(server, key.as_ptr(), value.as_ptr()), LaravelRustStatus::Ok);
+        let baseline = laravel_rust_live_strings();
+
+        // Every string handed out is live until freed
+        let mut strings = Vec::new();
+        for _ in 0..10 {
+            let mut out = ptr::null_mut();
+            assert_eq!(laravel_rust_get_option(server, key.as_ptr(), &mut out), LaravelRustStatus::Ok);
+            assert_eq!(CStr::from_ptr(out).to_str().unwrap(), "/run/app.sock");
+            strings.push(out);
+        }
+        let unknown = CString::new("no_such_option").unwrap();
+        let mut out = ptr::null_mut();
+        assert_eq!(laravel_rust_get_option(server, unknown.as_ptr(), &mut out), LaravelRustStatus::InvalidArgument);
+        assert!(out.is_null());
+        let error = laravel_rust_last_error(server);
+        assert!(CStr::from_ptr(error).to_str().unwrap().contains("no_such_option"));
+        strings.push(error);
+        assert_eq!(laravel_rust_live_strings(), baseline + 11);
+
+        for &string in &strings {
+            laravel_rust_free_string(string);
+        }
+        assert_eq!(laravel_rust_live_strings(), baseline, "strings leaked");
+
+        // Freeing again, or freeing what the library did not hand out, is refused
+        laravel_rust_free_string(strings[0]);
+        laravel_rust_free_string(error);
+        let foreign = CString::new("not from the library").unwrap().into_raw();
+        laravel_rust_free_string(foreign);
+        assert_eq!(CStr::from_ptr(foreign).to_str().unwrap(), "not from the library");
+        drop(CString::from_raw(foreign));
+        laravel_rust_free_string(ptr::null_mut());
+        assert_eq!(laravel_rust_live_strings(), baseline);
+
+        laravel_rust_destroy(server);
+    }
+}
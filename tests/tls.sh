@@ -0,0 +1,232 @@
+#!/usr/bin/env bash
+# TLS termination on the HTTP listener.
+#
+#   cargo build --release
+#   tests/tls.sh ./target/release/laravel-rust-server
+#
+# Makes two self-signed certificates with openssl and starts the server
+# with TLS_ENABLED=true and BACKEND=fastcgi, with a stand-in php-fpm
+# socket that saves what it is sent. Static files and /healthz must be
+# served over HTTPS, plain HTTP on the same port must get no answer,
+# `check` must pass, and a request passed to php-fpm must carry the
+# HTTPS=on parameter. Without TLS_ENABLED a request must reach php-fpm
+# over plain HTTP without it. With TLS_CLIENT_CA, a client certificate
+# signed by that CA must be accepted and its subject, serial and
+# SSL_CLIENT_VERIFY=SUCCESS sent to php-fpm; TLS_CLIENT_AUTH=require
+# must refuse clients without one or with one from another CA, logging
+# the refusal, and optional must let a client without one in with
+# SSL_CLIENT_VERIFY=NONE. A key that does not belong to the
+# certificate, a file that is not PEM, a missing file and a missing
+# setting must each stop the start with an error naming the setting. HTTP_PORT can be overridden from the
+# environment.
+
+set -euo pipefail
+
+BINARY=${1:?usage: $0 path/to/laravel-rust-server}
+BINARY=$(cd "$(dirname "$BINARY")" && pwd)/$(basename "$BINARY")
+HTTP_PORT=${HTTP_PORT:-18080}
+
+WORK=$(mktemp -d)
+SERVER_PID=
+FPM_PID=
+FAILED=0
+cleanup() {
+    for pid in $SERVER_PID $FPM_PID; do
+        kill "$pid" 2>/dev/null || true
+        wait "$pid" 2>/dev/null || true
+    done
+    rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+for name in server other; do
+    openssl req -x509 -newkey rsa:2048 -nodes -days 1 -subj "/CN=$name.test" \
+        -keyout "$WORK/$name.key" -out "$WORK/$name.crt" 2>/dev/null
+done
+# A CA and a client certificate it signed, serial 1A2B
+openssl req -x509 -newkey rsa:2048 -nodes -days 1 -subj "/CN=Test CA" \
+    -keyout "$WORK/ca.key" -out "$WORK/ca.crt" 2>/dev/null
+openssl req -new -newkey rsa:2048 -nodes -subj "/O=Example/CN=billing" \
+    -keyout "$WORK/client.key" -out "$WORK/client.csr" 2>/dev/null
+printf 'basicConstraints=CA:FALSE\nextendedKeyUsage=clientAuth\n' >"$WORK/client.ext"
+openssl x509 -req -in "$WORK/client.csr" -CA "$WORK/ca.crt" -CAkey "$WORK/ca.key" -set_serial 0x1A2B \
+    -days 1 -extfile "$WORK/client.ext" -out "$WORK/client.crt" 2>/dev/null
+mkdir -p "$WORK/public"
+echo "static over tls" >"$WORK/public/app.txt"
+echo "not a certificate" >"$WORK/garbage.pem"
+
+# Saves what each connection sends within a moment, then closes it
+python3 -c '
+import socket, sys, threading
+def save(conn):
+    conn.settimeout(0.3)
+    data = b""
+    try:
+        while chunk := conn.recv(65536):
+            data += chunk
+    except OSError:
+        pass
+    conn.close()
+    with open(sys.argv[2], "ab") as records:
+        records.write(data)
+s = socket.socket(socket.AF_UNIX)
+s.bind(sys.argv[1])
+s.listen(128)
+while True:
+    threading.Thread(target=save, args=(s.accept()[0],), daemon=True).start()
+' "$WORK/fpm.sock" "$WORK/records" &
+FPM_PID=$!
+
+# start [VAR=value...] - start the server in the background with extra settings
+start() {
+    (
+        cd "$WORK"
+        export HTTP_HOST=127.0.0.1 HTTP_PORT LARAVEL_PATH="$WORK" LOG_DIR="$WORK/logs" PHP_WORKER_AUTO_RESTART=false
+        export BACKEND=fastcgi FASTCGI_ADDRESS="$WORK/fpm.sock" FASTCGI_READ_TIMEOUT_MS=1000
+        export "$@"
+        exec "$BINARY"
+    ) >"$WORK/server.out" 2>&1 &
+    SERVER_PID=$!
+}
+stop() {
+    kill "$SERVER_PID" 2>/dev/null || true
+    wait "$SERVER_PID" 2>/dev/null || true
+    SERVER_PID=
+}
+# wait_ready URL [CURL_ARGS...]
+wait_ready() {
+    local url=$1
+    shift
+    for _ in $(seq 50); do
+        [ "$(curl -sk "$@" -o /dev/null -w '%{http_code}' "$url/readyz")" = 200 ] && return
+        sleep 0.2
+    done
+}
+check() {
+    local name=$1 want=$2 got=$3
+    if [ "$got" = "$want" ]; then
+        echo "ok - $name"
+    else
+        echo "FAIL: $name: got $got, expected $want"
+        FAILED=1
+    fi
+}
+# Whether the FastCGI parameters sent for PATH include HTTPS=on: "on", "absent" or "not sent"
+https_param() {
+    sleep 0.5
+    python3 -c '
+import sys
+records = open(sys.argv[1], "rb").read()
+path = sys.argv[2].encode()
+# A parameter is its name length, value length, name and value
+uri = bytes([len(b"REQUEST_URI"), len(path)]) + b"REQUEST_URI" + path
+if uri not in records:
+    print("not sent")
+else:
+    print("on" if b"\x05\x02HTTPSon" in records else "absent")
+' "$WORK/records" "$1"
+    : >"$WORK/records"
+}
+# The FastCGI parameter NAME sent for PATH, or "absent" / "not sent"
+fcgi_param() {
+    sleep 0.5
+    python3 -c '
+import sys
+records = open(sys.argv[1], "rb").read()
+path, name = sys.argv[2].encode(), sys.argv[3].encode()
+uri = bytes([len(b"REQUEST_URI"), len(path)]) + b"REQUEST_URI" + path
+if uri not in records:
+    print("not sent")
+else:
+    # A parameter is its name length, value length, name and value
+    for at in range(len(records) - len(name)):
+        if records[at] == len(name) and records[at + 2:at + 2 + len(name)] == name:
+            start = at + 2 + len(name)
+            print(records[start:start + records[at + 1]].decode())
+            break
+    else:
+        print("absent")
+' "$WORK/records" "$1" "$2"
+    : >"$WORK/records"
+}
+
+TLS=(TLS_ENABLED=true TLS_CERT_PATH="$WORK/server.crt" TLS_KEY_PATH="$WORK/server.key")
+URL=https://127.0.0.1:$HTTP_PORT
+start "${TLS[@]}"
+wait_ready "$URL"
+
+check "/healthz over HTTPS" "ok 200" "$(curl -sk -w ' %{http_code}' "$URL/healthz")"
+check "static file over HTTPS" "static over tls" "$(curl -sk "$URL/app.txt")"
+check "certificate presented" server.test \
+    "$(openssl s_client -connect "127.0.0.1:$HTTP_PORT" </dev/null 2>/dev/null | openssl x509 -noout -subject | sed 's/.*CN *= *//')"
+check "plain HTTP on the HTTPS port gets no answer" 000 "$(curl -s -m 3 -o /dev/null -w '%{http_code}' "http://127.0.0.1:$HTTP_PORT/healthz" || true)"
+curl -sk -o /dev/null "$URL/over-tls"
+check "HTTPS=on sent to php-fpm" on "$(https_param /over-tls)"
+check "check subcommand over HTTPS" 0 "$(cd "$WORK" && env HTTP_HOST=127.0.0.1 HTTP_PORT="$HTTP_PORT" \
+    LARAVEL_PATH="$WORK" LOG_DIR="$WORK/logs" "${TLS[@]}" "$BINARY" check >/dev/null 2>&1; echo $?)"
+stop
+
+URL=https://127.0.0.1:$HTTP_PORT
+CLIENT=(--cert "$WORK/client.crt" --key "$WORK/client.key")
+start "${TLS[@]}" TLS_CLIENT_CA="$WORK/ca.crt"
+wait_ready "$URL" "${CLIENT[@]}"
+check "client certificate accepted" 200 "$(curl -sk "${CLIENT[@]}" -o /dev/null -w '%{http_code}' "$URL/healthz")"
+curl -sk "${CLIENT[@]}" -o /dev/null "$URL/with-cert"
+check "subject sent to php-fpm" "CN=billing,O=Example" "$(fcgi_param /with-cert SSL_CLIENT_S_DN)"
+curl -sk "${CLIENT[@]}" -o /dev/null "$URL/with-cert"
+check "serial sent to php-fpm" 1A2B "$(fcgi_param /with-cert SSL_CLIENT_M_SERIAL)"
+curl -sk "${CLIENT[@]}" -o /dev/null "$URL/with-cert"
+check "verification sent to php-fpm" SUCCESS "$(fcgi_param /with-cert SSL_CLIENT_VERIFY)"
+check "no client certificate refused" 000 "$(curl -sk -m 3 -o /dev/null -w '%{http_code}' "$URL/healthz" || true)"
+check "certificate of another CA refused" 000 "$(curl -sk -m 3 --cert "$WORK/other.crt" --key "$WORK/other.key" \
+    -o /dev/null -w '%{http_code}' "$URL/healthz" || true)"
+check "refusal logged" yes "$(grep -q 'TLS client certificate rejected' "$WORK"/server.out "$WORK"/logs/* 2>/dev/null && echo yes || echo no)"
+stop
+
+start "${TLS[@]}" TLS_CLIENT_CA="$WORK/ca.crt" TLS_CLIENT_AUTH=optional
+wait_ready "$URL"
+curl -sk -o /dev/null "$URL/without-cert"
+check "optional certificate not presented" NONE "$(fcgi_param /without-cert SSL_CLIENT_VERIFY)"
+curl -sk -o /dev/null "$URL/without-cert"
+check "no subject without a certificate" absent "$(fcgi_param /without-cert SSL_CLIENT_S_DN)"
+stop
+
+URL=http://127.0.0.1:$HTTP_PORT
+start
+wait_ready "$URL"
+check "/healthz over plain HTTP without TLS_ENABLED" 200 "$(curl -s -o /dev/null -w '%{http_code}' "$URL/healthz")"
+curl -s -o /dev/null "$URL/over-plain"
+check "no HTTPS sent over plain HTTP" absent "$(https_param /over-plain)"
+stop
+
+# refused NAME SETTINGS... - the start must fail with an error naming SETTING
+refused() {
+    local name=$1 setting=$2
+    shift 2
+    start TLS_ENABLED=true "$@"
+    for _ in $(seq 50); do
+        kill -0 "$SERVER_PID" 2>/dev/null || break
+        sleep 0.2
+    done
+    if kill -0 "$SERVER_PID" 2>/dev/null; then
+        check "$name" "start refused" "server running"
+        stop
+    elif grep -q "$setting" "$WORK/server.out"; then
+        check "$name" "start refused" "start refused"
+    else
+        check "$name" "error naming $setting" "$(tail -n 1 "$WORK/server.out")"
+    fi
+    SERVER_PID=
+}
+refused "key of another certificate" TLS_KEY_PATH TLS_CERT_PATH="$WORK/server.crt" TLS_KEY_PATH="$WORK/other.key"
+refused "certificate that is not PEM" TLS_CERT_PATH TLS_CERT_PATH="$WORK/garbage.pem" TLS_KEY_PATH="$WORK/server.key"
+refused "missing key file" TLS_KEY_PATH TLS_CERT_PATH="$WORK/server.crt" TLS_KEY_PATH="$WORK/missing.key"
+refused "key path not set" TLS_KEY_PATH TLS_CERT_PATH="$WORK/server.crt"
+refused "client CA that is not PEM" TLS_CLIENT_CA "${TLS[@]:1}" TLS_CLIENT_CA="$WORK/garbage.pem"
+refused "unknown client auth mode" TLS_CLIENT_AUTH "${TLS[@]:1}" TLS_CLIENT_CA="$WORK/ca.crt" TLS_CLIENT_AUTH=sometimes
+
+if [ "$FAILED" -ne 0 ]; then
+    tail -n 20 "$WORK/server.out"
+    exit 1
+fi
+echo "ok - tls"
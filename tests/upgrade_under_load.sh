@@ -0,0 +1,98 @@
+#!/usr/bin/env bash
+# Binary upgrade under load: no request may fail while the server replaces itself.
+#
+#   cargo build --release
+#   tests/upgrade_under_load.sh ./target/release/laravel-rust-server
+#
+# Starts the server with a stand-in worker socket, keeps CONCURRENCY clients
+# requesting /healthz over fresh connections, upgrades through
+# POST /admin/upgrade (the same path SIGUSR1 takes) and keeps the load going
+# until the old process has drained and exited. Fails if any request was
+# refused, reset or answered with anything but 200, or if the upgrade did
+# not happen. HTTP_PORT, ADMIN_PORT, CONCURRENCY and UPGRADE_WORKER can be
+# overridden from the environment.
+
+set -euo pipefail
+
+BINARY=${1:?usage: $0 path/to/laravel-rust-server}
+BINARY=$(cd "$(dirname "$BINARY")" && pwd)/$(basename "$BINARY")
+HTTP_PORT=${HTTP_PORT:-18080}
+ADMIN_PORT=${ADMIN_PORT:-19090}
+CONCURRENCY=${CONCURRENCY:-8}
+
+WORK=$(mktemp -d)
+SERVER_PID=
+WORKER_PID=
+cleanup() {
+    touch "$WORK/stop"
+    [ -n "$SERVER_PID" ] && kill "$SERVER_PID" 2>/dev/null || true
+    [ -n "$WORKER_PID" ] && kill "$WORKER_PID" 2>/dev/null || true
+    wait 2>/dev/null || true
+    rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+# Readiness only needs the worker socket to accept connections
+python3 -c '
+import socket, sys
+s = socket.socket(socket.AF_UNIX)
+s.bind(sys.argv[1])
+s.listen(128)
+while True:
+    s.accept()[0].close()
+' "$WORK/worker.sock" &
+WORKER_PID=$!
+
+export HTTP_HOST=127.0.0.1 HTTP_PORT ADMIN_ENABLED=true ADMIN_PORT
+export SOCKET_PATH="$WORK/worker.sock" LARAVEL_PATH="$WORK" LOG_DIR="$WORK/logs"
+export PHP_WORKER_AUTO_RESTART=false UPGRADE_WORKER=${UPGRADE_WORKER:-handover}
+(cd "$WORK" && exec "$BINARY") >"$WORK/server.out" 2>&1 &
+SERVER_PID=$!
+
+for _ in $(seq 50); do
+    [ "$(curl -s -o /dev/null -w '%{http_code}' "http://127.0.0.1:$HTTP_PORT/readyz")" = 200 ] && break
+    sleep 0.2
+done
+
+load() {
+    while [ ! -e "$WORK/stop" ]; do
+        code=$(curl -s -o /dev/null -w '%{http_code}' --max-time 5 "http://127.0.0.1:$HTTP_PORT/healthz") || code="curl exit $?"
+        echo "$code"
+    done >"$WORK/load.$1"
+}
+for i in $(seq "$CONCURRENCY"); do
+    load "$i" &
+done
+sleep 1
+
+OLD_PID=$SERVER_PID
+response=$(curl -s -X POST "http://127.0.0.1:$ADMIN_PORT/admin/upgrade")
+NEW_PID=$(echo "$response" | grep -o '"pid":[0-9]*' | cut -d: -f2 || true)
+if [ -z "$NEW_PID" ]; then
+    echo "FAIL: upgrade did not happen: $response"
+    tail -n 20 "$WORK/server.out"
+    exit 1
+fi
+SERVER_PID=$NEW_PID
+echo "upgraded: $OLD_PID -> $NEW_PID"
+
+# The old process is not our child once it exits, so poll for it
+while kill -0 "$OLD_PID" 2>/dev/null; do
+    sleep 0.1
+done
+sleep 1
+touch "$WORK/stop"
+wait $(jobs -p | grep -v "^$WORKER_PID\$") 2>/dev/null || true
+
+total=$(cat "$WORK"/load.* | wc -l)
+failed=$(cat "$WORK"/load.* | grep -cv '^200$' || true)
+echo "requests: $total, failed: $failed"
+if [ "$failed" -gt 0 ]; then
+    cat "$WORK"/load.* | grep -v '^200$' | sort | uniq -c
+    exit 1
+fi
+[ "$(curl -s -o /dev/null -w '%{http_code}' "http://127.0.0.1:$HTTP_PORT/healthz")" = 200 ] || {
+    echo "FAIL: the new process is not serving"
+    exit 1
+}
+echo "ok - upgrade under load without failed requests"
@@ -0,0 +1,282 @@
+//! Wire shapes of each `WORKER_PROTOCOL`, pinned by fixtures
+//!
+//! Builds the frame for a form posted over HTTPS with cookies and a query
+//! string the way the HTTP listener does, puts it through each protocol's
+//! codec and compares the result with `tests/fixtures/worker_protocol`.
+//! The recorded worker answers of each protocol must become the HTTP
+//! responses they describe: every header value, chunked streaming for
+//! Octane, and a 502 for answers that cannot be served. For `psr7` the
+//! recordings are goridge frames as RoadRunner writes them.
+
+use hyper::body::{Bytes, HttpBody};
+use hyper::{Body, Response, StatusCode};
+use laravel_rust_server::bridge::connection_pool::FrameError;
+use laravel_rust_server::bridge::goridge;
+use laravel_rust_server::errors::ServerError;
+use laravel_rust_server::response_validation::ResponseValidation;
+use laravel_rust_server::server::{request_frame, HttpRequestPayload};
+use laravel_rust_server::worker_protocol::{WorkerCodec, WorkerProtocol};
+use serde_json::{json, Value};
+
+fn fixture(name: &str) -> Value {
+    let path = format!("{}/tests/fixtures/worker_protocol/{}", env!("CARGO_MANIFEST_DIR"), name);
+    serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap()
+}
+
+fn recorded_frames(name: &str) -> Vec<u8> {
+    std::fs::read(format!("{}/tests/fixtures/worker_protocol/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+/// The `data` of the answer in recorded goridge frames, read as the pool reads them
+fn read_goridge_answer(mut frames: &[u8]) -> Value {
+    let mut first = None;
+    let mut payload = Vec::new();
+    loop {
+        let header = goridge::parse_header(frames[..goridge::HEADER_LEN].try_into().unwrap()).unwrap();
+        frames = &frames[goridge::HEADER_LEN..];
+        let options = goridge::parse_options(&frames[..header.options_len]);
+        frames = &frames[header.options_len..];
+        let skip = if first.is_some() { options[0] as usize } else { 0 };
+        payload.extend_from_slice(&frames[skip..header.payload_len]);
+        frames = &frames[header.payload_len..];
+        first.get_or_insert((header.flags, options));
+        if header.stream & goridge::STREAM == 0 {
+            break;
+        }
+    }
+    assert!(frames.is_empty(), "frames left after the answer");
+    let (flags, options) = first.unwrap();
+    goridge::decode_response(flags, &options, payload).unwrap().data.unwrap()
+}
+
+fn codec(name: &str) -> &'static dyn WorkerCodec {
+    let codec = WorkerProtocol::parse(name).expect("a known protocol").codec();
+    assert_eq!(codec.name(), name);
+    codec
+}
+
+/// The native frame of a form login over HTTPS, as `forward_to_laravel` builds it
+fn login_frame() -> Value {
+    let body = "email=jane%40example.com&remember=on";
+    let mut payload = HttpRequestPayload::synthetic("POST", "/login?next=%2Fhome", Some(Bytes::from(body)));
+    payload.headers = [
+        ("host", "example.com"),
+        ("content-type", "application/x-www-form-urlencoded"),
+        ("cookie", "theme=dark; session=eyJpdiI6%3D%3D"),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect();
+    let mut frame = request_frame(payload, "fixture");
+    frame["server"]["HTTPS"] = "on".into();
+    frame
+}
+
+/// Status, headers and each body chunk of `response`
+async fn collect(response: Response<Body>) -> (StatusCode, hyper::HeaderMap, Vec<Bytes>) {
+    let (parts, mut body) = response.into_parts();
+    let mut chunks = Vec::new();
+    while let Some(chunk) = body.data().await {
+        chunks.push(chunk.unwrap());
+    }
+    (parts.status, parts.headers, chunks)
+}
+
+fn header_values<'a>(headers: &'a hyper::HeaderMap, name: &str) -> Vec<&'a str> {
+    headers.get_all(name).iter().map(|value| value.to_str().unwrap()).collect()
+}
+
+fn is_malformed(result: anyhow::Result<Response<Body>>) -> bool {
+    matches!(result.unwrap_err().downcast_ref::<ServerError>(), Some(ServerError::UpstreamMalformed(_)))
+}
+
+#[test]
+fn the_native_request_frame_is_sent_as_built() {
+    assert_eq!(codec("laravel-rust").encode_request(login_frame()), fixture("native_request.json"));
+}
+
+#[test]
+fn octane_requests_have_the_swoole_shape() {
+    assert_eq!(codec("octane").encode_request(login_frame()), fixture("octane_request.json"));
+}
+
+#[test]
+fn octane_requests_keep_binary_bodies_encoded() {
+    let payload = HttpRequestPayload::synthetic("PUT", "/avatar", Some(Bytes::from_static(&[0xff, 0xd8, 0xff])));
+    let frame = codec("octane").encode_request(request_frame(payload, "binary"));
+    assert_eq!(frame["content"], "/9j/");
+    assert_eq!(frame["content_encoding"], "base64");
+    assert_eq!(frame["post"], json!({}));
+    assert_eq!(frame["server"]["query_string"], "");
+}
+
+#[tokio::test]
+async fn native_responses_are_read_as_before() {
+    let response = codec("laravel-rust").decode_response(fixture("native_response.json"), ResponseValidation::Strict);
+    let (status, headers, body) = collect(response.unwrap()).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(header_values(&headers, "set-cookie"), ["a=1"]);
+    assert_eq!(body.concat(), b"created");
+}
+
+#[tokio::test]
+async fn octane_responses_keep_every_header_value() {
+    let response = codec("octane").decode_response(fixture("octane_response.json"), ResponseValidation::Strict);
+    let (status, headers, body) = collect(response.unwrap()).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(header_values(&headers, "content-type"), ["text/plain"]);
+    assert_eq!(header_values(&headers, "set-cookie"), ["a=1", "b=2"]);
+    assert_eq!(body.concat(), b"created");
+}
+
+#[tokio::test]
+async fn streamed_octane_responses_are_sent_chunk_by_chunk() {
+    let response = codec("octane").decode_response(fixture("octane_streamed_response.json"), ResponseValidation::Strict);
+    let response = response.unwrap();
+    assert_eq!(response.body().size_hint().exact(), None, "a streamed body has no length");
+    let (status, _, chunks) = collect(response).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(chunks, [Bytes::from("data: 1\n\n"), Bytes::from("data: 2\n\n")]);
+}
+
+#[tokio::test]
+async fn octane_binary_content_is_decoded() {
+    let data = json!({"status": 200, "headers": {"Content-Type": "image/png"}, "content": "iVBO", "content_encoding": "base64"});
+    let (_, headers, body) = collect(codec("octane").decode_response(data, ResponseValidation::Strict).unwrap()).await;
+    assert_eq!(header_values(&headers, "content-type"), ["image/png"]);
+    assert_eq!(body.concat(), [0x89, 0x50, 0x4e]);
+}
+
+#[tokio::test]
+async fn octane_responses_that_cannot_be_served_are_malformed() {
+    let octane = codec("octane");
+    for data in [
+        json!("just a string"),
+        json!({"status": "ok"}),
+        json!({"status": 1000}),
+        json!({"status": 200, "headers": ["Content-Type: text/plain"]}),
+        json!({"status": 200, "chunks": "data"}),
+        json!({"status": 200, "content": "%%%", "content_encoding": "base64"}),
+        json!({"status": 200, "content": "x", "content_encoding": "gzip"}),
+    ] {
+        for validation in [ResponseValidation::Lenient, ResponseValidation::Strict] {
+            assert!(is_malformed(octane.decode_response(data.clone(), validation)), "{} ({:?})", data, validation);
+        }
+    }
+}
+
+#[tokio::test]
+async fn strict_validation_refuses_what_lenient_octane_decoding_fills_in() {
+    let octane = codec("octane");
+    for data in [
+        json!({"content": "no status"}),
+        json!({"status": 200, "headers": {"X-Count": [1]}}),
+        json!({"status": 200, "content": {"not": "a string"}}),
+    ] {
+        assert!(is_malformed(octane.decode_response(data.clone(), ResponseValidation::Strict)), "{}", data);
+        let response = octane.decode_response(data.clone(), ResponseValidation::Lenient).unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{}", data);
+        assert!(response.headers().get("x-count").is_none());
+    }
+}
+
+#[test]
+fn psr7_requests_have_the_roadrunner_shape() {
+    assert_eq!(codec("psr7").encode_request(login_frame()), fixture("psr7_request.json"));
+}
+
+#[test]
+fn psr7_request_frames_match_the_recording() {
+    let mut frame = Vec::new();
+    goridge::encode_request(&mut frame, &codec("psr7").encode_request(login_frame())).unwrap();
+    assert_eq!(frame, recorded_frames("psr7_request.frame"));
+}
+
+#[test]
+fn psr7_commands_become_control_frames() {
+    let mut frame = Vec::new();
+    goridge::encode_request(&mut frame, &json!({"command": "ping"})).unwrap();
+    let header = goridge::parse_header(frame[..goridge::HEADER_LEN].try_into().unwrap()).unwrap();
+    assert_eq!(header.flags & goridge::CONTROL, goridge::CONTROL);
+    assert_eq!(&frame[goridge::HEADER_LEN..], br#"{"pid":true}"#);
+
+    goridge::encode_request(&mut frame, &json!({"command": "goodbye"})).unwrap();
+    assert_eq!(&frame[goridge::HEADER_LEN..], br#"{"stop":true}"#);
+    assert!(goridge::encode_request(&mut frame, &json!({"command": "health"})).is_err());
+}
+
+#[test]
+fn corrupt_goridge_headers_are_refused() {
+    let mut header: [u8; goridge::HEADER_LEN] = recorded_frames("psr7_response.frame")[..goridge::HEADER_LEN].try_into().unwrap();
+    header[2] ^= 1;
+    assert!(matches!(goridge::parse_header(&header), Err(FrameError::Corrupt(_))));
+    header[0] = 0x23;
+    assert!(matches!(goridge::parse_header(&header), Err(FrameError::Corrupt(_))));
+}
+
+#[tokio::test]
+async fn recorded_psr7_responses_keep_every_header_value() {
+    let data = read_goridge_answer(&recorded_frames("psr7_response.frame"));
+    let response = codec("psr7").decode_response(data, ResponseValidation::Strict);
+    let (status, headers, body) = collect(response.unwrap()).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(header_values(&headers, "content-type"), ["text/plain"]);
+    assert_eq!(header_values(&headers, "set-cookie"), ["a=1", "b=2"]);
+    assert_eq!(body.concat(), b"created");
+}
+
+#[tokio::test]
+async fn streamed_psr7_responses_are_joined() {
+    let data = read_goridge_answer(&recorded_frames("psr7_streamed_response.frame"));
+    let response = codec("psr7").decode_response(data, ResponseValidation::Strict);
+    let (status, headers, body) = collect(response.unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(header_values(&headers, "content-type"), ["text/event-stream"]);
+    assert_eq!(body.concat(), b"data: 1\n\ndata: 2\n\n");
+}
+
+#[test]
+fn psr7_multipart_uploads_are_written_and_described() {
+    let body = concat!(
+        "--XyZ\r\n",
+        "Content-Disposition: form-data; name=\"title\"\r\n\r\n",
+        "Holiday\r\n",
+        "--XyZ\r\n",
+        "Content-Disposition: form-data; name=\"photos[]\"; filename=\"beach.txt\"\r\n",
+        "Content-Type: text/plain\r\n\r\n",
+        "sand and sea\r\n",
+        "--XyZ\r\n",
+        "Content-Disposition: form-data; name=\"photos[]\"; filename=\"\"\r\n",
+        "Content-Type: application/octet-stream\r\n\r\n",
+        "\r\n",
+        "--XyZ--\r\n",
+    );
+    let mut payload = HttpRequestPayload::synthetic("POST", "/albums", Some(Bytes::from(body)));
+    payload.headers.insert("content-type".to_string(), "multipart/form-data; boundary=XyZ".to_string());
+    let psr7 = codec("psr7");
+    let request = psr7.encode_request(request_frame(payload, "upload"));
+
+    assert_eq!(request["context"]["parsed"], true);
+    assert_eq!(request["body"], r#"{"title":"Holiday"}"#);
+    let photos = &request["context"]["uploads"]["photos"];
+    assert_eq!(photos[0]["name"], "beach.txt");
+    assert_eq!(photos[0]["mime"], "text/plain");
+    assert_eq!(photos[0]["size"], 12);
+    assert_eq!(photos[0]["error"], 0);
+    let tmp_name = photos[0]["tmpName"].as_str().unwrap();
+    assert_eq!(std::fs::read_to_string(tmp_name).unwrap(), "sand and sea");
+    assert_eq!(photos[1], json!({"name": "", "mime": "", "size": 0, "error": 4, "tmpName": ""}));
+
+    let dir = psr7.scratch_dir(&request).expect("the uploads are in a directory of their own");
+    assert!(std::path::Path::new(tmp_name).starts_with(&dir));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn protocol_names() {
+    assert_eq!(WorkerProtocol::default(), WorkerProtocol::LaravelRust);
+    for name in WorkerProtocol::NAMES {
+        assert_eq!(WorkerProtocol::parse(name).unwrap().codec().name(), *name);
+    }
+    assert_eq!(WorkerProtocol::parse("swoole"), None);
+}